@@ -1,4 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use so::stackexchange::preprocess::{preprocess_with, DEFAULT_PASSES};
 use so::tui::markdown::parse;
 
 const MD: &str = r####"
@@ -23,9 +24,11 @@ After I hit **[Esc]** to exit insert mode I can't figure out how to save and qui
 
 "####;
 
-// TODO bench preprocess as well, separately
 pub fn md_benchmark(c: &mut Criterion) {
     c.bench_function("markdown::parse", |b| b.iter(|| parse(black_box(MD))));
+    c.bench_function("markdown::preprocess", |b| {
+        b.iter(|| preprocess_with(black_box(MD), DEFAULT_PASSES).len())
+    });
 }
 
 criterion_group!(benches, md_benchmark);
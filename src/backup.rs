@@ -0,0 +1,459 @@
+//! Export and import of user data, for moving `so` to another machine.
+//!
+//! An archive is a gzipped tarball with a `manifest.json` listing the components inside, each
+//! with the version of its own format. Caches are left out since they're rebuilt on demand, and
+//! so are credentials: the API key and team tokens are stripped on export and kept as they are
+//! on import.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, Team};
+use crate::error::{BackupError, Error, Result};
+use crate::notes::Notes;
+use crate::stackexchange::lucky_memory::{LuckyMemory, Remembered};
+use crate::utils;
+
+/// Version of the archive layout, bumped on changes older versions of `so` can't read
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+/// The config file, as a component of the archive
+const CONFIG: &str = "config";
+const CONFIG_VERSION: u32 = 1;
+const CONFIG_FILE: &str = "config.yml";
+
+/// Notes on questions, as a component of the archive
+const NOTES: &str = "notes";
+const NOTES_VERSION: u32 = 1;
+const NOTES_FILE: &str = "notes.json";
+
+/// Answers picked for lucky queries, as a component of the archive
+const LUCKY_MEMORY: &str = "lucky_memory";
+const LUCKY_MEMORY_VERSION: u32 = 1;
+const LUCKY_MEMORY_FILE: &str = "lucky_memory.json";
+
+/// Describes the contents of an archive
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub format_version: u32,
+    /// Version of `so` that wrote the archive
+    pub so_version: String,
+    /// Components in the archive, with the version of their format
+    pub components: BTreeMap<String, u32>,
+}
+
+/// How imported data is combined with what's already there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    /// Keep existing data, adding whatever it's missing from the archive
+    Merge,
+    /// Overwrite existing data with the archive's
+    Replace,
+}
+
+/// Where user data lives
+#[derive(Debug, Clone)]
+pub struct Locations {
+    pub config_file: PathBuf,
+    pub notes_file: PathBuf,
+    pub lucky_memory_file: PathBuf,
+}
+
+impl Locations {
+    pub fn new() -> Result<Self> {
+        Ok(Locations {
+            config_file: Config::config_file_path()?,
+            notes_file: Notes::path()?,
+            lucky_memory_file: LuckyMemory::path()?,
+        })
+    }
+}
+
+/// Bundle all user data into a gzipped tarball at `archive`
+pub fn export(locations: &Locations, archive: &Path) -> Result<Manifest> {
+    let mut manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        so_version: clap::crate_version!().to_string(),
+        components: BTreeMap::new(),
+    };
+    let mut files: Vec<(&str, Vec<u8>)> = Vec::new();
+    if let Some(config) = read_config(&locations.config_file)? {
+        files.push((CONFIG_FILE, serde_yaml::to_vec(&strip_credentials(config))?));
+        manifest
+            .components
+            .insert(CONFIG.to_string(), CONFIG_VERSION);
+    }
+    let notes = Notes::load(&locations.notes_file)?;
+    if notes != Notes::default() {
+        files.push((NOTES_FILE, serde_json::to_vec_pretty(&notes)?));
+        manifest.components.insert(NOTES.to_string(), NOTES_VERSION);
+    }
+    let answers = LuckyMemory::load(locations.lucky_memory_file.clone())?.entries();
+    if !answers.is_empty() {
+        files.push((LUCKY_MEMORY_FILE, serde_json::to_vec_pretty(&answers)?));
+        manifest
+            .components
+            .insert(LUCKY_MEMORY.to_string(), LUCKY_MEMORY_VERSION);
+    }
+
+    let file = utils::create_file(&archive.to_path_buf())?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    for (name, contents) in std::iter::once((MANIFEST, manifest_json)).chain(files) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        tar.append_data(&mut header, name, contents.as_slice())?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(manifest)
+}
+
+/// Restore user data from `archive`, combining it with existing data according to `mode`
+pub fn import(locations: &Locations, archive: &Path, mode: ImportMode) -> Result<Manifest> {
+    let file = utils::open_file(&archive.to_path_buf())?
+        .ok_or_else(|| Error::Backup(BackupError::NotFound(archive.to_path_buf())))?;
+    let mut files = HashMap::new();
+    for entry in tar::Archive::new(GzDecoder::new(file)).entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.insert(name, contents);
+    }
+    let manifest: Manifest = files
+        .get(MANIFEST)
+        .ok_or(Error::Backup(BackupError::Missing(MANIFEST)))
+        .and_then(|m| Ok(serde_json::from_slice(m)?))?;
+    check_versions(&manifest)?;
+
+    if manifest.components.contains_key(CONFIG) {
+        let imported: Config = files
+            .get(CONFIG_FILE)
+            .ok_or(Error::Backup(BackupError::Missing(CONFIG_FILE)))
+            .and_then(|c| Ok(serde_yaml::from_slice(c)?))?;
+        let local = read_config(&locations.config_file)?.unwrap_or_default();
+        write_config(&locations.config_file, &merge_config(local, imported, mode))?;
+    }
+    if manifest.components.contains_key(NOTES) {
+        let imported: Notes = files
+            .get(NOTES_FILE)
+            .ok_or(Error::Backup(BackupError::Missing(NOTES_FILE)))
+            .and_then(|n| Ok(serde_json::from_slice(n)?))?;
+        let mut notes = Notes::load(&locations.notes_file)?;
+        notes.merge(imported, mode);
+        notes.save(&locations.notes_file)?;
+    }
+    if manifest.components.contains_key(LUCKY_MEMORY) {
+        let imported: BTreeMap<String, Remembered> = files
+            .get(LUCKY_MEMORY_FILE)
+            .ok_or(Error::Backup(BackupError::Missing(LUCKY_MEMORY_FILE)))
+            .and_then(|m| Ok(serde_json::from_slice(m)?))?;
+        LuckyMemory::load(locations.lucky_memory_file.clone())?.merge(imported, mode)?;
+    }
+    Ok(manifest)
+}
+
+/// Refuse archives written by a newer version of `so`, rather than silently dropping data
+fn check_versions(manifest: &Manifest) -> Result<()> {
+    let newer = |part: &'static str, version: u32, supported: u32| {
+        Err(Error::Backup(BackupError::Newer {
+            part,
+            version,
+            supported,
+            so_version: manifest.so_version.clone(),
+        }))
+    };
+    if manifest.format_version > FORMAT_VERSION {
+        return newer(
+            "the archive format",
+            manifest.format_version,
+            FORMAT_VERSION,
+        );
+    }
+    match manifest.components.get(CONFIG) {
+        Some(&version) if version > CONFIG_VERSION => {
+            return newer("the config", version, CONFIG_VERSION)
+        }
+        _ => (),
+    }
+    match manifest.components.get(NOTES) {
+        Some(&version) if version > NOTES_VERSION => {
+            return newer("the notes", version, NOTES_VERSION)
+        }
+        _ => (),
+    }
+    match manifest.components.get(LUCKY_MEMORY) {
+        Some(&version) if version > LUCKY_MEMORY_VERSION => newer(
+            "the remembered lucky answers",
+            version,
+            LUCKY_MEMORY_VERSION,
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// `config` without the API key and team tokens
+fn strip_credentials(config: Config) -> Config {
+    let teams = config
+        .teams
+        .into_iter()
+        .map(|(name, team)| {
+            let token = String::new();
+            (name, Team { token, ..team })
+        })
+        .collect();
+    Config {
+        api_key: None,
+        teams,
+        ..config
+    }
+}
+
+/// Combine `imported` config with the `local` one.
+///
+/// Credentials always stay as they are locally. Merging keeps every local setting and only adds
+/// teams (by name) that don't exist locally yet; replacing takes the imported settings, keeping
+/// the local token of each team that exists in both.
+fn merge_config(local: Config, imported: Config, mode: ImportMode) -> Config {
+    match mode {
+        ImportMode::Merge => {
+            let mut teams = imported.teams;
+            teams.extend(local.teams);
+            Config { teams, ..local }
+        }
+        ImportMode::Replace => {
+            let teams = imported
+                .teams
+                .into_iter()
+                .map(|(name, team)| {
+                    let token = local
+                        .teams
+                        .get(&name)
+                        .map(|t| t.token.clone())
+                        .unwrap_or(team.token);
+                    (name, Team { token, ..team })
+                })
+                .collect();
+            Config {
+                api_key: local.api_key,
+                teams,
+                ..imported
+            }
+        }
+    }
+}
+
+fn read_config(filename: &PathBuf) -> Result<Option<Config>> {
+    match utils::open_file(filename)? {
+        Some(file) => serde_yaml::from_reader(file)
+            .map(Some)
+            .map_err(|_| Error::MalformedFile(filename.clone())),
+        None => Ok(None),
+    }
+}
+
+fn write_config(filename: &PathBuf, config: &Config) -> Result<()> {
+    if let Some(dir) = filename.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = utils::create_file(filename)?;
+    Ok(serde_yaml::to_writer(file, config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh temporary directory holding a machine's user data
+    fn machine(name: &str) -> (PathBuf, Locations) {
+        let dir = std::env::temp_dir().join(format!("so-backup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let locations = Locations {
+            config_file: dir.join("config").join("config.yml"),
+            notes_file: dir.join("data").join("notes.json"),
+            lucky_memory_file: dir.join("data").join("lucky_memory.json"),
+        };
+        (dir, locations)
+    }
+
+    fn team(slug: &str, token: &str) -> Team {
+        Team {
+            api_url: String::from("https://api.stackoverflowteams.com/2.3"),
+            slug: slug.to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    fn config() -> Config {
+        let mut config = Config {
+            api_key: Some(String::from("old machine key")),
+            limit: 5,
+            sites: vec![String::from("unix"), String::from("superuser")],
+            ..Config::default()
+        };
+        config
+            .teams
+            .insert(String::from("work"), team("acme", "secret"));
+        config
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (old_dir, old) = machine("roundtrip-old");
+        write_config(&old.config_file, &config()).unwrap();
+        let archive = old_dir.join("backup.tar.gz");
+        let manifest = export(&old, &archive).unwrap();
+        assert_eq!(manifest.format_version, FORMAT_VERSION);
+        assert_eq!(manifest.components.get(CONFIG), Some(&CONFIG_VERSION));
+
+        let (_, new) = machine("roundtrip-new");
+        assert_eq!(
+            import(&new, &archive, ImportMode::Replace).unwrap(),
+            manifest
+        );
+        let restored = read_config(&new.config_file).unwrap().unwrap();
+        // Everything but credentials made it over
+        assert_eq!(restored.limit, 5);
+        assert_eq!(restored.sites, config().sites);
+        assert_eq!(restored.api_key, Config::default().api_key);
+        assert_eq!(restored.teams["work"].slug, "acme");
+        assert_eq!(restored.teams["work"].token, "");
+    }
+
+    #[test]
+    fn test_notes_roundtrip() {
+        let (old_dir, old) = machine("notes-old");
+        let q = crate::stackexchange::Question {
+            id: 1,
+            score: 1,
+            title: String::from("How do I exit Vim?"),
+            body: Some(String::new()),
+            site: String::from("stackoverflow"),
+            ..crate::stackexchange::Question::default()
+        };
+        let mut notes = Notes::default();
+        notes.set(&q, "use :wq");
+        notes.save(&old.notes_file).unwrap();
+        let archive = old_dir.join("backup.tar.gz");
+        let manifest = export(&old, &archive).unwrap();
+        assert_eq!(manifest.components.get(NOTES), Some(&NOTES_VERSION));
+
+        let (_, new) = machine("notes-new");
+        import(&new, &archive, ImportMode::Merge).unwrap();
+        assert_eq!(Notes::load(&new.notes_file).unwrap(), notes);
+    }
+
+    #[test]
+    fn test_lucky_memory_roundtrip() {
+        let (old_dir, old) = machine("lucky-memory-old");
+        let picked = |answer_id| Remembered {
+            site: String::from("unix"),
+            question_id: 1,
+            answer_id,
+        };
+        LuckyMemory::load(old.lucky_memory_file.clone())
+            .unwrap()
+            .remember("tar extract flags", picked(10))
+            .unwrap();
+        let archive = old_dir.join("backup.tar.gz");
+        let manifest = export(&old, &archive).unwrap();
+        assert_eq!(
+            manifest.components.get(LUCKY_MEMORY),
+            Some(&LUCKY_MEMORY_VERSION)
+        );
+
+        let (_, new) = machine("lucky-memory-new");
+        let local = LuckyMemory::load(new.lucky_memory_file.clone()).unwrap();
+        local.remember("tar extract flags", picked(11)).unwrap();
+        import(&new, &archive, ImportMode::Merge).unwrap();
+        let memory = LuckyMemory::load(new.lucky_memory_file.clone()).unwrap();
+        assert_eq!(memory.get("tar extract flags"), Some(picked(11)));
+        import(&new, &archive, ImportMode::Replace).unwrap();
+        let memory = LuckyMemory::load(new.lucky_memory_file).unwrap();
+        assert_eq!(memory.get("tar extract flags"), Some(picked(10)));
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let local = Config {
+            api_key: Some(String::from("new machine key")),
+            limit: 20,
+            teams: vec![(String::from("work"), team("acme-renamed", "local secret"))]
+                .into_iter()
+                .collect(),
+            ..Config::default()
+        };
+        let mut imported = strip_credentials(config());
+        imported
+            .teams
+            .insert(String::from("oss"), team("oss-org", ""));
+
+        let merged = merge_config(local.clone(), imported.clone(), ImportMode::Merge);
+        assert_eq!(merged.limit, 20);
+        assert_eq!(merged.api_key, local.api_key);
+        assert_eq!(merged.teams["work"], local.teams["work"]);
+        assert_eq!(merged.teams["oss"].slug, "oss-org");
+
+        let replaced = merge_config(local.clone(), imported, ImportMode::Replace);
+        assert_eq!(replaced.limit, 5);
+        assert_eq!(replaced.api_key, local.api_key);
+        assert_eq!(replaced.teams["work"].slug, "acme");
+        assert_eq!(replaced.teams["work"].token, "local secret");
+        assert_eq!(replaced.teams["oss"].token, "");
+    }
+
+    #[test]
+    fn test_import_merge_keeps_local_config() {
+        let (old_dir, old) = machine("merge-old");
+        write_config(&old.config_file, &config()).unwrap();
+        let archive = old_dir.join("backup.tar.gz");
+        export(&old, &archive).unwrap();
+
+        let (_, new) = machine("merge-new");
+        let local = Config {
+            limit: 42,
+            ..Config::default()
+        };
+        write_config(&new.config_file, &local).unwrap();
+        import(&new, &archive, ImportMode::Merge).unwrap();
+        let merged = read_config(&new.config_file).unwrap().unwrap();
+        assert_eq!(merged.limit, 42);
+        assert!(merged.teams.contains_key("work"));
+    }
+
+    #[test]
+    fn test_refuses_newer_versions() {
+        let manifest = Manifest {
+            format_version: FORMAT_VERSION + 1,
+            so_version: String::from("9.9.9"),
+            components: BTreeMap::new(),
+        };
+        match check_versions(&manifest) {
+            Err(Error::Backup(BackupError::Newer { so_version, .. })) => {
+                assert_eq!(so_version, "9.9.9")
+            }
+            _ => panic!("expected newer format to be refused"),
+        }
+
+        let mut manifest = Manifest {
+            format_version: FORMAT_VERSION,
+            ..manifest
+        };
+        assert!(check_versions(&manifest).is_ok());
+        manifest
+            .components
+            .insert(CONFIG.to_string(), CONFIG_VERSION + 1);
+        assert!(check_versions(&manifest).is_err());
+    }
+}
@@ -1,27 +1,301 @@
-use clap::{App, AppSettings, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use crate::config::Config;
-use crate::error::Result;
+use crate::backup::ImportMode;
+use crate::config::{Config, TlDr};
+use crate::error::{Error, Result};
+use crate::output;
+use crate::stackexchange::preprocess;
+use crate::utils;
+
+/// Why the command line couldn't be turned into `Opts`
+#[derive(thiserror::Error, Debug)]
+pub enum OptsError {
+    #[error(transparent)]
+    Other(#[from] Error),
+    #[error("`--{0}` is for {1} and `--{2}` is for {3}; pick one of them")]
+    ConflictingFlags(&'static str, Mode, &'static str, Mode),
+}
 
 // TODO --add-site (in addition to defaults)
 // TODO set_api_key should probably just be a bool, since we have config
 pub struct Opts {
-    pub list_sites: bool,
+    /// Filter of the sites to list, empty to list them all
+    pub list_sites: Option<String>,
     pub print_config_path: bool,
+    /// Forget the results of past searches
+    pub clear_cache: bool,
+    /// Report how much of the StackExchange API's daily quota is left
+    pub quota: bool,
     pub update_sites: bool,
+    /// Search the site a typo of a site code most likely meant without asking
+    pub fix_sites: bool,
     pub set_api_key: Option<String>,
+    pub timings: bool,
+    /// Print details of problems that were worked around
+    pub verbose: bool,
+    /// Leave out the tag `context_detection` would add
+    pub no_context: bool,
+    /// Whether the search engine was picked on the command line, rather than by the config
+    pub search_engine_given: bool,
+    /// Whether a site was picked on the command line, so that a bare question id is looked up there
+    pub site_given: bool,
+    pub refresh: bool,
+    /// Search the questions of every cached search by what they say, with `--search`
+    pub full_text: bool,
+    pub max_time: Option<Duration>,
+    /// Print results with this formatter instead of opening the TUI
+    pub output: Option<String>,
+    /// Wrap printed results at this many columns rather than the terminal's width
+    pub width: Option<usize>,
+    /// Ask which of the command lines to print, with `--commands`
+    pub pick: bool,
+    /// Print results as JSON, with `--json` or `--output json`; in lucky mode just the lucky
+    /// answer
+    pub json: bool,
+    /// Open the lucky answer in a browser instead of printing it
+    pub open: bool,
+    /// Print the spoilers of the lucky answer rather than leaving them out
+    pub show_spoilers: bool,
+    /// Which answer of the lucky question to print, counting from 1, rather than the top one
+    pub answer: Option<usize>,
+    /// Forget the answer `lucky_memory` remembered for the query
+    pub forget: bool,
     pub query: Option<String>,
+    pub backup: Option<BackupCommand>,
+    pub notes: Option<NotesCommand>,
+    pub cache: Option<CacheCommand>,
+    /// Check whether the hosts searches depend on can be reached
+    pub doctor: bool,
+    pub url: Option<UrlCommand>,
+    /// Flags that were given but don't apply to the mode `so` runs in
+    pub warnings: Vec<String>,
     pub config: Config,
 }
 
+/// `so backup ...` subcommands
+#[derive(Debug, PartialEq)]
+pub enum BackupCommand {
+    Export(PathBuf),
+    Import(PathBuf, ImportMode),
+}
+
+/// `so notes ...` subcommands
+#[derive(Debug, PartialEq)]
+pub enum NotesCommand {
+    List,
+    /// Delete the note on a question, by site and question id
+    Delete(String, u32),
+    /// Delete notes on questions that haven't come up in this many days
+    Prune(u64),
+}
+
+/// `so cache ...` subcommands
+#[derive(Debug, PartialEq)]
+pub enum CacheCommand {
+    /// Rebuild the full-text index of the cached searches
+    Reindex,
+}
+
+/// `so url ...` subcommands, each taking the URL to inspect
+#[derive(Debug, PartialEq)]
+pub enum UrlCommand {
+    /// Print the site and question id
+    Id(String),
+    /// Print the canonical link to the question
+    Canonical(String),
+    /// Print the site's code
+    Site(String),
+}
+
+/// What a run of `so` does, as far as which flags matter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Browsing results in the TUI
+    Tui,
+    /// Printing the top answer, then opening the TUI on request
+    Lucky,
+    /// Printing results with a formatter instead of opening the TUI
+    Output,
+    /// Running a subcommand, e.g. `so doctor`
+    Command,
+}
+
+use Mode::*;
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Tui => "the TUI",
+            Lucky => "lucky mode",
+            Output => "output mode",
+            Command => "subcommands",
+        })
+    }
+}
+
+/// Modes that run a search
+const SEARCH: &[Mode] = &[Tui, Lucky, Output];
+const ALL: &[Mode] = &[Tui, Lucky, Output, Command];
+
+/// Which modes a flag applies to
+#[derive(Debug)]
+pub struct Flag {
+    pub name: &'static str,
+    pub modes: &'static [Mode],
+    /// Whether giving the flag picks its (only) mode, making flags that pick another one
+    /// contradictory rather than just irrelevant
+    pub selects: bool,
+}
+
+impl Flag {
+    const fn new(name: &'static str, modes: &'static [Mode]) -> Self {
+        Flag {
+            name,
+            modes,
+            selects: false,
+        }
+    }
+
+    const fn selecting(self) -> Self {
+        Flag {
+            selects: true,
+            ..self
+        }
+    }
+}
+
+/// Every top level flag and option with the modes it applies to; adding one to the CLI without
+/// declaring it here fails `test_flag_modes_exhaustive`
+pub const FLAGS: &[Flag] = &[
+    Flag::new("list-sites", SEARCH),
+    Flag::new("update-sites", ALL),
+    Flag::new("set-api-key", SEARCH),
+    Flag::new("print-config-path", SEARCH),
+    Flag::new("clear-cache", SEARCH),
+    Flag::new("quota", SEARCH),
+    Flag::new("site", SEARCH),
+    Flag::new("fix-sites", SEARCH),
+    Flag::new("limit", SEARCH),
+    Flag::new("lucky", &[Lucky]).selecting(),
+    Flag::new("no-lucky", &[Tui, Lucky]),
+    Flag::new("strict-lucky", &[Lucky]),
+    Flag::new("require-accepted", &[Lucky]),
+    Flag::new("tl-dr", &[Tui, Lucky]),
+    Flag::new("show-spoilers", &[Lucky]),
+    Flag::new("no-lucky-header", &[Lucky]),
+    Flag::new("open", &[Lucky]).selecting(),
+    Flag::new("answer", &[Lucky]).selecting(),
+    Flag::new("forget", &[Lucky]),
+    Flag::new("team", SEARCH),
+    Flag::new("code-search", SEARCH),
+    Flag::new("include-unanswered", SEARCH),
+    Flag::new("answer-sort", SEARCH),
+    Flag::new("max-time", SEARCH),
+    Flag::new("output", &[Output]).selecting(),
+    Flag::new("digest", &[Output]).selecting(),
+    Flag::new("commands", &[Output]).selecting(),
+    Flag::new("width", &[Lucky, Output]),
+    Flag::new("json", &[Lucky, Output]),
+    Flag::new("pick", &[Output]),
+    Flag::new("raw-markdown", &[Output]),
+    Flag::new("offline", ALL),
+    Flag::new("search", SEARCH),
+    Flag::new("refresh", SEARCH),
+    Flag::new("no-cache", SEARCH),
+    Flag::new("timings", SEARCH),
+    Flag::new("verbose", SEARCH),
+    Flag::new("no-context", SEARCH),
+    Flag::new("search-engine", SEARCH),
+];
+
+/// Help section listing the flags that only apply to some of the search modes
+fn modes_help() -> String {
+    let mut help = String::from("MODE-SPECIFIC FLAGS:");
+    for mode in SEARCH {
+        let flags = FLAGS
+            .iter()
+            .filter(|flag| {
+                flag.modes.contains(mode) && !SEARCH.iter().all(|m| flag.modes.contains(m))
+            })
+            .map(|flag| format!("--{}", flag.name))
+            .collect::<Vec<_>>();
+        help.push_str(&format!(
+            "\n    {:<16}{}",
+            format!("{}:", mode),
+            flags.join(", ")
+        ));
+    }
+    help
+}
+
+/// Work out the mode from the flags given explicitly, failing on flags that pick different
+/// modes, and warn about flags that don't apply to it
+fn check_modes(matches: &ArgMatches, lucky: bool) -> std::result::Result<Vec<String>, OptsError> {
+    let given = FLAGS
+        .iter()
+        .filter(|flag| matches.occurrences_of(flag.name) > 0)
+        .collect::<Vec<_>>();
+    let mut selectors = given.iter().filter(|flag| flag.selects);
+    if let Some(first) = selectors.next() {
+        if let Some(second) = selectors.find(|flag| flag.modes != first.modes) {
+            return Err(OptsError::ConflictingFlags(
+                first.name,
+                first.modes[0],
+                second.name,
+                second.modes[0],
+            ));
+        }
+    }
+    let mode = if matches.subcommand_name().is_some() {
+        Command
+    } else if ["output", "digest", "commands"]
+        .iter()
+        .any(|flag| matches.is_present(flag))
+        || json_output(matches)
+    {
+        Output
+    } else if lucky {
+        Lucky
+    } else {
+        Tui
+    };
+    Ok(given
+        .iter()
+        .filter(|flag| !flag.modes.contains(&mode))
+        .map(|flag| {
+            let modes = flag.modes.iter().map(Mode::to_string).collect::<Vec<_>>();
+            let modes = match modes.split_last() {
+                Some((last, rest)) if !rest.is_empty() => {
+                    format!("{} and {}", rest.join(", "), last)
+                }
+                _ => modes.join(""),
+            };
+            format!(
+                "`--{}` is ignored by {}; it's for {}",
+                flag.name, mode, modes
+            )
+        })
+        .collect())
+}
+
+/// Whether `--json` prints all results, rather than the lucky answer as with `--lucky` or
+/// `--open`. Lucky mode
+/// from the config gives way, as it does to `--output`.
+fn json_output(matches: &ArgMatches) -> bool {
+    matches.is_present("json") && !matches.is_present("lucky") && !matches.is_present("open")
+}
+
 /// Get CLI opts and args, with defaults pulled from user configuration
-pub fn get_opts() -> Result<Opts> {
+pub fn get_opts() -> std::result::Result<Opts, OptsError> {
     get_opts_with(Config::new, |a| a.get_matches())
 }
 
 /// Get CLI opts, starting with defaults produced from `mk_config` and matching args with
 /// `get_matches`.
-fn get_opts_with<F, G>(mk_config: F, get_matches: G) -> Result<Opts>
+fn get_opts_with<F, G>(mk_config: F, get_matches: G) -> std::result::Result<Opts, OptsError>
 where
     F: FnOnce() -> Result<Config>,
     G: for<'a> FnOnce(App<'a, '_>) -> ArgMatches<'a>,
@@ -30,115 +304,543 @@ where
     let limit = &config.limit.to_string();
     let sites = &config.sites.join(";");
     let engine = &config.search_engine.to_string();
-    let clapp = App::new("so")
-        .setting(AppSettings::ColoredHelp)
-        .version(clap::crate_version!())
-        .author(clap::crate_authors!())
-        .about(clap::crate_description!())
-        .arg(
-            Arg::with_name("list-sites")
-                .long("list-sites")
-                .help("Print available StackExchange sites"),
-        )
-        .arg(
-            Arg::with_name("update-sites")
-                .long("update-sites")
-                .help("Update cache of StackExchange sites"),
-        )
-        .arg(
-            Arg::with_name("set-api-key")
-                .long("set-api-key")
-                .number_of_values(1)
-                .takes_value(true)
-                .value_name("key")
-                .help("Set StackExchange API key"),
-        )
-        .arg(
-            Arg::with_name("print-config-path")
-                .long("print-config-path")
-                .help("Print path to config file")
-                .hidden(true),
-        )
-        .arg(
-            Arg::with_name("site")
-                .long("site")
-                .short("s")
-                .multiple(true)
-                .number_of_values(1)
-                .takes_value(true)
-                .default_value(sites)
-                .value_name("site-code")
-                .help("StackExchange site to search"),
-        )
-        .arg(
-            Arg::with_name("limit")
-                .long("limit")
-                .short("l")
-                .number_of_values(1)
-                .takes_value(true)
-                .default_value(limit)
-                .value_name("int")
-                .validator(|s| s.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
-                .help("Question limit"),
-        )
-        .arg(
-            Arg::with_name("lucky")
-                .long("lucky")
-                .help("Print the top-voted answer of the most relevant question"),
-        )
-        .arg(
-            Arg::with_name("no-lucky")
-                .long("no-lucky")
-                .help("Disable lucky")
-                .conflicts_with("lucky")
-                .hidden(!config.lucky),
-        )
-        .arg(
-            Arg::with_name("query")
-                .multiple(true)
-                .index(1)
-                .required_unless_one(&[
-                    "list-sites",
-                    "update-sites",
-                    "set-api-key",
-                    "print-config-path",
-                ]),
-        )
-        .arg(
-            Arg::with_name("search-engine")
-                .long("search-engine")
-                .short("e")
-                .number_of_values(1)
-                .takes_value(true)
-                .default_value(engine)
-                .value_name("engine")
-                .possible_values(&["duckduckgo", "google", "stackexchange"])
-                .help("Use specified search engine")
-                .next_line_help(true),
-        );
+    let modes_help = &modes_help();
+    let clapp =
+        App::new("so")
+            .setting(AppSettings::ColoredHelp)
+            .setting(AppSettings::SubcommandsNegateReqs)
+            .version(clap::crate_version!())
+            .author(clap::crate_authors!())
+            .about(clap::crate_description!())
+            .after_help(modes_help.as_str())
+            .arg(
+                Arg::with_name("list-sites")
+                    .long("list-sites")
+                    .takes_value(true)
+                    .min_values(0)
+                    .max_values(1)
+                    .value_name("FILTER")
+                    .help(
+                        "Print available StackExchange sites, those with FILTER in their code, \
+                        URL, name or audience if given; as JSON with --json",
+                    ),
+            )
+            .arg(
+                Arg::with_name("update-sites")
+                    .long("update-sites")
+                    .help("Update cache of StackExchange sites"),
+            )
+            .arg(
+                Arg::with_name("set-api-key")
+                    .long("set-api-key")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("key")
+                    .help("Set StackExchange API key"),
+            )
+            .arg(
+                Arg::with_name("print-config-path")
+                    .long("print-config-path")
+                    .help("Print path to config file")
+                    .hidden(true),
+            )
+            .arg(
+                Arg::with_name("clear-cache").long("clear-cache").help(
+                    "Forget the results of past searches, including those kept for --offline",
+                ),
+            )
+            .arg(
+                Arg::with_name("quota")
+                    .long("quota")
+                    .help("Report how many StackExchange API requests are left for today"),
+            )
+            .arg(
+                Arg::with_name("site")
+                    .long("site")
+                    .short("s")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .default_value(sites)
+                    .value_name("site-code")
+                    .help(
+                        "StackExchange site to search; repeat it or separate codes with commas \
+                        to search several",
+                    ),
+            )
+            .arg(Arg::with_name("fix-sites").long("fix-sites").help(
+                "Search the site an unknown site code is most likely a typo of, without asking",
+            ))
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .short("l")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .default_value(limit)
+                    .value_name("int")
+                    .validator(|s| s.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Question limit"),
+            )
+            .arg(
+                Arg::with_name("lucky")
+                    .long("lucky")
+                    .help("Print the top-voted answer of the most relevant question"),
+            )
+            .arg(
+                Arg::with_name("no-lucky")
+                    .long("no-lucky")
+                    .help("Disable lucky")
+                    .conflicts_with("lucky")
+                    .hidden(!config.lucky),
+            )
+            .arg(
+                Arg::with_name("strict-lucky")
+                    .long("strict-lucky")
+                    .help("Treat a lucky answer to a poorly matching question as no results"),
+            )
+            .arg(
+                Arg::with_name("require-accepted")
+                    .long("require-accepted")
+                    .help("Skip to the first question with an accepted answer in lucky mode"),
+            )
+            .arg(
+                Arg::with_name("tl-dr")
+                    .long("tl-dr")
+                    .help("Sum up long answers in a few of their own sentences, on top of them"),
+            )
+            .arg(
+                Arg::with_name("show-spoilers")
+                    .long("show-spoilers")
+                    .help("Print the spoilers of the lucky answer, which are left out otherwise"),
+            )
+            .arg(
+                Arg::with_name("no-lucky-header")
+                    .long("no-lucky-header")
+                    .help("Print only the lucky answer, without its question's title and link")
+                    .hidden(!config.lucky_header),
+            )
+            .arg(
+                Arg::with_name("open")
+                    .long("open")
+                    .help("Open the top-voted answer of the most relevant question in a browser")
+                    .conflicts_with("no-lucky"),
+            )
+            .arg(
+                Arg::with_name("answer")
+                    .long("answer")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("n")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(0) => Err(String::from("answers are counted from 1")),
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e.to_string()),
+                    })
+                    .conflicts_with("no-lucky")
+                    .help(
+                        "Print the nth answer of the most relevant question instead of the top one",
+                    ),
+            )
+            .arg(
+                Arg::with_name("forget")
+                    .long("forget")
+                    .help("Forget the answer remembered for the query, with lucky_memory on"),
+            )
+            .arg(
+                Arg::with_name("team")
+                    .long("team")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("name")
+                    .help("Search a Stack Overflow for Teams instance from your config"),
+            )
+            .arg(Arg::with_name("code-search").long("code-search").help(
+                "Only show questions with the query (or its quoted parts) in an answer's code",
+            ))
+            .arg(
+                Arg::with_name("include-unanswered")
+                    .long("include-unanswered")
+                    .help("Also show questions that have no answers yet"),
+            )
+            .arg(
+                Arg::with_name("answer-sort")
+                    .long("answer-sort")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("order")
+                    .possible_values(&["votes", "accepted", "newest"])
+                    .help("Order answers by votes, the accepted one first, or newest first"),
+            )
+            .arg(
+                Arg::with_name("max-time")
+                    .long("max-time")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("duration")
+                    .validator(|s| utils::parse_duration(&s).map(|_| ()))
+                    .help("Show whatever results are in after this long, e.g. 3s or 500ms"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .long("output")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("name")
+                    .validator(|s| match s.as_str() {
+                        "list" => Ok(()),
+                        name => output::find(name)
+                            .map(|_| ())
+                            .ok_or_else(|| format!("unknown output {}, see --output list", name)),
+                    })
+                    .help("Print results in this format instead of opening the TUI"),
+            )
+            .arg(
+                Arg::with_name("width")
+                    .long("width")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .value_name("columns")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(0) => Err(String::from("the width has to be at least 1")),
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e.to_string()),
+                    })
+                    .help("Wrap printed results at this many columns, e.g. 72 for --output text"),
+            )
+            .arg(
+                Arg::with_name("digest")
+                    .long("digest")
+                    .conflicts_with("output")
+                    .help("Print the gist of each question, same as --output digest"),
+            )
+            .arg(
+                Arg::with_name("commands")
+                    .long("commands")
+                    .conflicts_with_all(&["output", "digest"])
+                    .help("Print the command lines of the top answer, same as --output commands"),
+            )
+            .arg(
+                Arg::with_name("json")
+                    .long("json")
+                    .conflicts_with_all(&["output", "digest", "commands"])
+                    .help(
+                        "Print results as JSON, same as --output json; with --lucky, print just \
+                        the lucky answer",
+                    ),
+            )
+            .arg(
+                Arg::with_name("pick")
+                    .long("pick")
+                    .requires("commands")
+                    .help("Pick which of the command lines to print"),
+            )
+            .arg(
+                Arg::with_name("raw-markdown")
+                    .long("raw-markdown")
+                    .help("Print posts exactly as StackExchange has them, without preprocessing"),
+            )
+            .arg(
+                Arg::with_name("offline")
+                    .long("offline")
+                    .help("Serve results from the local cache, without network access"),
+            )
+            .arg(
+                Arg::with_name("search")
+                    .long("search")
+                    .takes_value(true)
+                    .value_name("QUERY")
+                    .conflicts_with("query")
+                    .help(
+                        "Search the questions of every cached search for QUERY by what they say, \
+                        offline",
+                    ),
+            )
+            .arg(
+                Arg::with_name("refresh")
+                    .long("refresh")
+                    .conflicts_with_all(&["offline", "search"])
+                    .help("Fetch everything fresh instead of reusing cached questions"),
+            )
+            .arg(
+                Arg::with_name("no-cache")
+                    .long("no-cache")
+                    .conflicts_with_all(&["offline", "search"])
+                    .help("Search again even if the same search ran within cache_ttl_minutes"),
+            )
+            .arg(Arg::with_name("timings").long("timings").help(
+                "Print a breakdown of where the search spent its time; with --json, also \
+                add it to the output as a timings field",
+            ))
+            .arg(Arg::with_name("verbose").long("verbose").short("v").help(
+                "Print details of problems worked around, such as answers that failed to render",
+            ))
+            .arg(
+                Arg::with_name("no-context")
+                    .long("no-context")
+                    .help("Don't narrow down the search to the language of the current project"),
+            )
+            .arg(
+                Arg::with_name("query")
+                    .multiple(true)
+                    .index(1)
+                    .required_unless_one(&[
+                        "list-sites",
+                        "update-sites",
+                        "set-api-key",
+                        "print-config-path",
+                        "clear-cache",
+                        "quota",
+                        "output",
+                        "search",
+                    ]),
+            )
+            .arg(
+                Arg::with_name("search-engine")
+                    .long("search-engine")
+                    .short("e")
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .default_value(engine)
+                    .value_name("engine")
+                    .possible_values(&["duckduckgo", "google", "stackexchange"])
+                    .help("Use specified search engine")
+                    .next_line_help(true),
+            )
+            .subcommand(
+                SubCommand::with_name("backup")
+                    .about("Export or import your config and other user data")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("export")
+                            .about("Bundle user data into an archive")
+                            .arg(Arg::with_name("archive").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("import")
+                            .about("Restore user data from an archive")
+                            .arg(Arg::with_name("archive").required(true))
+                            .arg(
+                                Arg::with_name("merge")
+                                    .long("merge")
+                                    .help("Keep existing data, adding what's missing (default)"),
+                            )
+                            .arg(
+                                Arg::with_name("replace")
+                                    .long("replace")
+                                    .conflicts_with("merge")
+                                    .help("Overwrite existing data"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("notes")
+                    .about("Manage your notes on questions")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("list").about("List all notes"))
+                    .subcommand(
+                        SubCommand::with_name("delete")
+                            .about("Delete the note on a question")
+                            .arg(Arg::with_name("site").required(true))
+                            .arg(Arg::with_name("id").required(true).validator(|s| {
+                                s.parse::<u32>().map(|_| ()).map_err(|e| e.to_string())
+                            })),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("prune")
+                            .about("Delete notes on questions that haven't come up in a while")
+                            .arg(Arg::with_name("days").required(true).validator(|s| {
+                                s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())
+                            })),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("cache")
+                    .about("Maintain the cache of past searches")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("reindex")
+                            .about("Rebuild the index --search looks through"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("doctor")
+                    .about("Check whether StackExchange and the search engines can be reached"),
+            )
+            .subcommand(
+                SubCommand::with_name("url")
+                    .about("Make sense of links to questions and answers, for use in scripts")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("id")
+                            .about("Print the site code and question id")
+                            .arg(Arg::with_name("url").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("canonical")
+                            .about("Print the canonical link to the question")
+                            .arg(Arg::with_name("url").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("site")
+                            .about("Print the site code")
+                            .arg(Arg::with_name("url").required(true)),
+                    ),
+            );
     let matches = get_matches(clapp);
-    let lucky = match (matches.is_present("lucky"), matches.is_present("no-lucky")) {
+    let backup =
+        matches
+            .subcommand_matches("backup")
+            .and_then(|backup| match backup.subcommand() {
+                // these unwraps are safe via clap required args
+                ("export", Some(m)) => {
+                    Some(BackupCommand::Export(m.value_of("archive").unwrap().into()))
+                }
+                ("import", Some(m)) => {
+                    let mode = if m.is_present("replace") {
+                        ImportMode::Replace
+                    } else {
+                        ImportMode::Merge
+                    };
+                    Some(BackupCommand::Import(
+                        m.value_of("archive").unwrap().into(),
+                        mode,
+                    ))
+                }
+                _ => None,
+            });
+    let notes = matches
+        .subcommand_matches("notes")
+        .and_then(|notes| match notes.subcommand() {
+            ("list", Some(_)) => Some(NotesCommand::List),
+            // these unwraps are safe via clap required args & validators
+            ("delete", Some(m)) => Some(NotesCommand::Delete(
+                m.value_of("site").unwrap().to_string(),
+                m.value_of("id").unwrap().parse().unwrap(),
+            )),
+            ("prune", Some(m)) => Some(NotesCommand::Prune(
+                m.value_of("days").unwrap().parse().unwrap(),
+            )),
+            _ => None,
+        });
+    let cache = matches
+        .subcommand_matches("cache")
+        .and_then(|cache| match cache.subcommand() {
+            ("reindex", Some(_)) => Some(CacheCommand::Reindex),
+            _ => None,
+        });
+    let url = matches.subcommand_matches("url").and_then(|url| {
+        // this unwrap is safe via clap required args
+        let arg = |m: &ArgMatches| m.value_of("url").unwrap().to_string();
+        match url.subcommand() {
+            ("id", Some(m)) => Some(UrlCommand::Id(arg(m))),
+            ("canonical", Some(m)) => Some(UrlCommand::Canonical(arg(m))),
+            ("site", Some(m)) => Some(UrlCommand::Site(arg(m))),
+            _ => None,
+        }
+    });
+    let lucky = match (
+        matches.is_present("lucky") || matches.is_present("open") || matches.is_present("answer"),
+        matches.is_present("no-lucky"),
+    ) {
         (true, _) => true,
         (_, true) => false,
         _ => config.lucky,
     };
+    let warnings = check_modes(&matches, lucky)?;
+    let strict_lucky = matches.is_present("strict-lucky") || config.strict_lucky;
+    let require_accepted = matches.is_present("require-accepted") || config.require_accepted;
+    let lucky_header = config.lucky_header && !matches.is_present("no-lucky-header");
+    let code_search = matches.is_present("code-search") || config.code_search;
+    let include_unanswered = matches.is_present("include-unanswered") || config.include_unanswered;
+    let answer_sort = match matches.value_of("answer-sort") {
+        Some(order) => serde_yaml::from_str(order).map_err(Error::from)?,
+        None => config.answer_sort,
+    };
+    // Only cached questions are searched by their text
+    let full_text = matches.is_present("search");
+    let offline = matches.is_present("offline") || config.offline || full_text;
+    // Fetching everything fresh is no use with results served whole from the cache
+    let cache_ttl_minutes = match matches.is_present("no-cache") || matches.is_present("refresh") {
+        true => 0,
+        false => config.cache_ttl_minutes,
+    };
+    let tl_dr = match matches.is_present("tl-dr") {
+        true => TlDr {
+            lucky: true,
+            tui: true,
+            ..config.tl_dr
+        },
+        false => config.tl_dr,
+    };
+    let team = matches.value_of("team").map(String::from).or(config.team);
+    let preprocess_passes = match matches.is_present("raw-markdown") {
+        true => vec![String::from(preprocess::NO_PASSES)],
+        false => config.preprocess_passes,
+    };
+    let config = Config {
+        team,
+        preprocess_passes,
+        ..config
+    };
+    // Fail early on typos
+    config.selected_team()?;
     Ok(Opts {
-        list_sites: matches.is_present("list-sites"),
+        list_sites: matches
+            .is_present("list-sites")
+            .then(|| matches.value_of("list-sites").unwrap_or("").to_string()),
         print_config_path: matches.is_present("print-config-path"),
+        clear_cache: matches.is_present("clear-cache"),
+        quota: matches.is_present("quota"),
         update_sites: matches.is_present("update-sites"),
+        fix_sites: matches.is_present("fix-sites"),
         set_api_key: matches.value_of("set-api-key").map(String::from),
+        timings: matches.is_present("timings"),
+        verbose: matches.is_present("verbose"),
+        no_context: matches.is_present("no-context"),
+        search_engine_given: matches.occurrences_of("search-engine") > 0,
+        site_given: matches.occurrences_of("site") > 0,
+        refresh: matches.is_present("refresh"),
+        // this unwrap is safe via clap validator
+        max_time: matches
+            .value_of("max-time")
+            .map(|s| utils::parse_duration(s).unwrap()),
+        output: match (matches.is_present("digest"), matches.is_present("commands")) {
+            (true, _) => Some(String::from("digest")),
+            (_, true) => Some(String::from("commands")),
+            _ if json_output(&matches) => Some(String::from("json")),
+            _ => matches.value_of("output").map(String::from),
+        },
+        // this unwrap is safe via clap validator
+        width: matches.value_of("width").map(|s| s.parse().unwrap()),
+        pick: matches.is_present("pick"),
+        open: matches.is_present("open"),
+        show_spoilers: matches.is_present("show-spoilers"),
+        // this unwrap is safe via clap validator
+        answer: matches.value_of("answer").map(|s| s.parse().unwrap()),
+        forget: matches.is_present("forget"),
+        json: matches.is_present("json") || matches.value_of("output") == Some("json"),
         query: matches
             .values_of("query")
-            .map(|q| q.collect::<Vec<_>>().join(" ")),
+            .map(|q| q.collect::<Vec<_>>().join(" "))
+            .or_else(|| matches.value_of("search").map(String::from)),
+        full_text,
+        backup,
+        notes,
+        cache,
+        doctor: matches.subcommand_matches("doctor").is_some(),
+        url,
+        warnings,
         config: Config {
             // these unwraps are safe via clap default values & validators
             limit: matches.value_of("limit").unwrap().parse::<u16>().unwrap(),
-            search_engine: serde_yaml::from_str(matches.value_of("search-engine").unwrap())?,
+            search_engine: serde_yaml::from_str(matches.value_of("search-engine").unwrap())
+                .map_err(Error::from)?,
             sites: matches
                 .values_of("site")
                 .unwrap()
-                .map(|s| s.split(';'))
-                .flatten()
+                .flat_map(|s| s.split(&[';', ','][..]))
+                .filter(|s| !s.is_empty())
                 .map(String::from)
                 .collect(),
             api_key: matches
@@ -146,6 +848,16 @@ where
                 .map(String::from)
                 .or(config.api_key),
             lucky,
+            strict_lucky,
+            require_accepted,
+            lucky_header,
+            code_search,
+            include_unanswered,
+            answer_sort,
+            offline,
+            cache_ttl_minutes,
+            tl_dr,
+            ..config
         },
     })
 }
@@ -153,7 +865,9 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::SearchEngine;
+    use crate::config::{SearchEngine, Team};
+    use crate::error::Error;
+    use crate::stackexchange::answer_sort::AnswerSort;
 
     fn defaults() -> Config {
         Config {
@@ -166,6 +880,7 @@ mod tests {
                 String::from("yeah"),
             ],
             search_engine: SearchEngine::DuckDuckGo,
+            ..Config::default()
         }
     }
 
@@ -196,6 +911,23 @@ mod tests {
             }
         );
 
+        // Repeated as well as comma separated
+        let opts = get_opts_with(mk_config, |a| {
+            let args = vec![
+                "so",
+                "-s",
+                "unix,askubuntu",
+                "--site",
+                "superuser",
+                "--fix-sites",
+                "q",
+            ];
+            a.get_matches_from(args)
+        })
+        .unwrap();
+        assert_eq!(opts.config.sites, vec!["unix", "askubuntu", "superuser"]);
+        assert!(opts.fix_sites);
+
         let opts = get_opts_with(mk_config, |a| {
             a.get_matches_from(vec!["so", "-l", "5", "--lucky", "how do I exit Vim"])
         });
@@ -230,6 +962,698 @@ mod tests {
         assert_eq!(opts.set_api_key, Some(String::from("new key")));
     }
 
+    #[test]
+    fn test_strict_lucky() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--strict-lucky", "how do I exit Vim"])
+        });
+
+        assert_eq!(
+            opts.unwrap().config,
+            Config {
+                strict_lucky: true,
+                ..defaults()
+            }
+        );
+    }
+
+    #[test]
+    fn test_require_accepted() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec![
+                "so",
+                "--lucky",
+                "--require-accepted",
+                "how do I exit Vim",
+            ])
+        })
+        .unwrap();
+        assert!(opts.warnings.is_empty());
+        assert_eq!(
+            opts.config,
+            Config {
+                lucky: true,
+                require_accepted: true,
+                ..defaults()
+            }
+        );
+    }
+
+    #[test]
+    fn test_tl_dr() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--tl-dr", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert!(opts.warnings.is_empty());
+        assert_eq!(
+            opts.config.tl_dr,
+            TlDr {
+                lucky: true,
+                tui: true,
+                sentences: 3,
+            }
+        );
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--tl-dr", "--digest", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert_eq!(opts.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_open() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--open", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert!(opts.warnings.is_empty());
+        assert!(opts.open);
+        assert!(opts.config.lucky);
+        assert!(!opts.json);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--open", "--json", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert_eq!(opts.output, None);
+        assert!(matches!(
+            get_opts_with(mk_config, |a| {
+                a.get_matches_from(vec!["so", "--open", "--digest", "how do I exit Vim"])
+            }),
+            Err(OptsError::ConflictingFlags("open", Lucky, "digest", Output))
+        ));
+    }
+
+    #[test]
+    fn test_raw_markdown() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--output", "markdown", "--raw-markdown", "q"])
+        })
+        .unwrap();
+        assert!(opts.warnings.is_empty());
+        assert_eq!(opts.config.preprocess_passes, vec![String::from("none")]);
+        assert!(opts.config.passes().is_empty());
+    }
+
+    #[test]
+    fn test_offline() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--offline", "how do I exit Vim"])
+        });
+        assert_eq!(
+            opts.unwrap().config,
+            Config {
+                offline: true,
+                ..defaults()
+            }
+        );
+    }
+
+    #[test]
+    fn test_include_unanswered() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "how do I exit Vim"])
+        });
+        assert!(!opts.unwrap().config.include_unanswered);
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--include-unanswered", "how do I exit Vim"])
+        });
+        assert_eq!(
+            opts.unwrap().config,
+            Config {
+                include_unanswered: true,
+                ..defaults()
+            }
+        );
+    }
+
+    #[test]
+    fn test_refresh() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--refresh", "how do I exit Vim"])
+        });
+        let opts = opts.unwrap();
+        assert!(opts.refresh);
+        assert_eq!(opts.config.cache_ttl_minutes, 0);
+    }
+
+    #[test]
+    fn test_no_cache() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "how do I exit Vim"])
+        });
+        assert_eq!(opts.unwrap().config.cache_ttl_minutes, 10);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--no-cache", "how do I exit Vim"])
+        });
+        let opts = opts.unwrap();
+        assert_eq!(opts.config.cache_ttl_minutes, 0);
+        // Questions are still reused
+        assert!(!opts.refresh);
+    }
+
+    #[test]
+    fn test_clear_cache() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--clear-cache"])
+        });
+        let opts = opts.unwrap();
+        assert!(opts.clear_cache);
+        assert_eq!(opts.query, None);
+    }
+
+    #[test]
+    fn test_quota() {
+        let opts = get_opts_with(mk_config, |a| a.get_matches_from(vec!["so", "--quota"]));
+        let opts = opts.unwrap();
+        assert!(opts.quota);
+        assert_eq!(opts.query, None);
+    }
+
+    #[test]
+    fn test_list_sites() {
+        let list_sites = |args: Vec<&str>| {
+            get_opts_with(mk_config, |a| a.get_matches_from(args))
+                .unwrap()
+                .list_sites
+        };
+        assert_eq!(list_sites(vec!["so", "--list-sites"]), Some(String::new()));
+        assert_eq!(
+            list_sites(vec!["so", "--list-sites", "unix"]),
+            Some(String::from("unix"))
+        );
+        assert_eq!(
+            list_sites(vec!["so", "--list-sites", "unix", "--json"]),
+            Some(String::from("unix"))
+        );
+        assert_eq!(list_sites(vec!["so", "how do I exit Vim"]), None);
+    }
+
+    #[test]
+    fn test_verbose() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "-v", "how do I exit Vim"])
+        });
+        assert!(opts.unwrap().verbose);
+    }
+
+    #[test]
+    fn test_search_engine_given() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert!(!opts.search_engine_given);
+        assert_eq!(opts.config.search_engine, SearchEngine::DuckDuckGo);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "-e", "duckduckgo", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert!(opts.search_engine_given);
+    }
+
+    #[test]
+    fn test_site_given() {
+        let opts =
+            get_opts_with(mk_config, |a| a.get_matches_from(vec!["so", "11828270"])).unwrap();
+        assert!(!opts.site_given);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "-s", "superuser", "11828270"])
+        })
+        .unwrap();
+        assert!(opts.site_given);
+    }
+
+    #[test]
+    fn test_no_context() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "how do I exit Vim"])
+        });
+        assert!(!opts.unwrap().no_context);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--no-context", "--lucky", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert!(opts.no_context);
+        assert!(opts.warnings.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_refresh_conflicts_with_offline() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--refresh", "--offline", "how do I exit Vim"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_backup() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "backup", "export", "backup.tar.gz"])
+        })
+        .unwrap();
+        assert_eq!(
+            opts.backup,
+            Some(BackupCommand::Export(PathBuf::from("backup.tar.gz")))
+        );
+        assert_eq!(opts.query, None);
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "backup", "import", "b.tar.gz", "--replace"])
+        })
+        .unwrap();
+        assert_eq!(
+            opts.backup,
+            Some(BackupCommand::Import(
+                PathBuf::from("b.tar.gz"),
+                ImportMode::Replace
+            ))
+        );
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "backup", "import", "b.tar.gz"])
+        })
+        .unwrap();
+        assert_eq!(
+            opts.backup,
+            Some(BackupCommand::Import(
+                PathBuf::from("b.tar.gz"),
+                ImportMode::Merge
+            ))
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "notes", "list"])
+        });
+        assert_eq!(opts.unwrap().notes, Some(NotesCommand::List));
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "notes", "delete", "askubuntu", "42"])
+        });
+        assert_eq!(
+            opts.unwrap().notes,
+            Some(NotesCommand::Delete(String::from("askubuntu"), 42))
+        );
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "notes", "prune", "90"])
+        });
+        assert_eq!(opts.unwrap().notes, Some(NotesCommand::Prune(90)));
+    }
+
+    #[test]
+    fn test_search() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--offline", "--search", "tokio timeout"])
+        })
+        .unwrap();
+        assert!(opts.full_text && opts.config.offline);
+        assert_eq!(opts.query, Some(String::from("tokio timeout")));
+        // Offline either way
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--search", "tokio timeout"])
+        })
+        .unwrap();
+        assert!(opts.full_text && opts.config.offline);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--offline", "tokio timeout"])
+        })
+        .unwrap();
+        assert!(!opts.full_text);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_search_conflicts_with_refresh() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--search", "tokio timeout", "--refresh"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cache_reindex() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "cache", "reindex"])
+        });
+        assert_eq!(opts.unwrap().cache, Some(CacheCommand::Reindex));
+    }
+
+    #[test]
+    fn test_doctor() {
+        let opts = get_opts_with(mk_config, |a| a.get_matches_from(vec!["so", "doctor"]));
+        assert!(opts.unwrap().doctor);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "how do I exit Vim"])
+        });
+        assert!(!opts.unwrap().doctor);
+    }
+
+    #[test]
+    fn test_url() {
+        let link = "https://stackoverflow.com/q/11828270";
+        for (command, expected) in [
+            ("id", UrlCommand::Id(link.to_string())),
+            ("canonical", UrlCommand::Canonical(link.to_string())),
+            ("site", UrlCommand::Site(link.to_string())),
+        ] {
+            let opts = get_opts_with(mk_config, |a| {
+                a.get_matches_from(vec!["so", "url", command, link])
+            });
+            assert_eq!(opts.unwrap().url, Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_output() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--output", "markdown", "how do I exit Vim"])
+        });
+        assert_eq!(opts.unwrap().output, Some(String::from("markdown")));
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--output", "list"])
+        });
+        let opts = opts.unwrap();
+        assert_eq!(opts.output, Some(String::from("list")));
+        assert_eq!(opts.query, None);
+    }
+
+    #[test]
+    fn test_digest() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--digest", "--limit", "5", "how do I exit Vim"])
+        })
+        .unwrap();
+        assert_eq!(opts.output, Some(String::from("digest")));
+        assert_eq!(opts.config.limit, 5);
+    }
+
+    #[test]
+    fn test_commands() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--commands", "--pick", "untar a file"])
+        })
+        .unwrap();
+        assert_eq!(opts.output, Some(String::from("commands")));
+        assert!(opts.pick);
+        assert!(opts.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_json() {
+        let opts = |args: Vec<&str>, lucky: bool| {
+            let mk_config = || {
+                Ok(Config {
+                    lucky,
+                    ..defaults()
+                })
+            };
+            get_opts_with(mk_config, |a| a.get_matches_from(args)).unwrap()
+        };
+        let all = opts(vec!["so", "--json", "how do I exit Vim"], false);
+        assert_eq!(all.output, Some(String::from("json")));
+        assert!(all.json);
+        assert!(all.warnings.is_empty());
+
+        let lucky = opts(vec!["so", "--lucky", "--json", "how do I exit Vim"], false);
+        assert_eq!(lucky.output, None);
+        assert!(lucky.config.lucky && lucky.json);
+        assert!(lucky.warnings.is_empty());
+
+        // Lucky mode from the config gives way
+        let from_config = opts(vec!["so", "--json", "how do I exit Vim"], true);
+        assert_eq!(from_config.output, Some(String::from("json")));
+        assert!(opts(vec!["so", "--output", "json", "q"], false).json);
+        assert!(!opts(vec!["so", "--digest", "q"], false).json);
+
+        let warnings = opts(vec!["so", "--json", "--strict-lucky", "q"], false).warnings;
+        assert_eq!(
+            warnings,
+            vec!["`--strict-lucky` is ignored by output mode; it's for lucky mode"]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_pick_requires_commands() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--digest", "--pick", "q"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unknown_output() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--output", "csv", "how do I exit Vim"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_no_lucky_header() {
+        let opts = get_opts_with(mk_config, |a| a.get_matches_from(vec!["so", "exit Vim"]));
+        assert!(opts.unwrap().config.lucky_header);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--lucky", "--no-lucky-header", "exit Vim"])
+        });
+        assert!(!opts.unwrap().config.lucky_header);
+    }
+
+    #[test]
+    fn test_answer() {
+        let opts = get_opts_with(mk_config, |a| a.get_matches_from(vec!["so", "exit Vim"]));
+        let opts = opts.unwrap();
+        assert_eq!(opts.answer, None);
+        assert!(!opts.forget);
+        // Picking an answer is for lucky mode
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--answer", "2", "--forget", "exit Vim"])
+        });
+        let opts = opts.unwrap();
+        assert_eq!(opts.answer, Some(2));
+        assert!(opts.forget);
+        assert!(opts.config.lucky);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zeroth_answer() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--answer", "0", "exit Vim"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_answer_sort() {
+        let opts = get_opts_with(mk_config, |a| a.get_matches_from(vec!["so", "exit Vim"]));
+        assert_eq!(opts.unwrap().config.answer_sort, AnswerSort::Votes);
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--answer-sort", "newest", "exit Vim"])
+        });
+        assert_eq!(opts.unwrap().config.answer_sort, AnswerSort::Newest);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unknown_answer_sort() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--answer-sort", "oldest", "exit Vim"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_width() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--output", "text", "--width", "72", "exit Vim"])
+        });
+        assert_eq!(opts.unwrap().width, Some(72));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_width() {
+        get_opts_with(mk_config, |a| {
+            a.get_matches_from_safe(vec!["so", "--output", "text", "--width", "0", "exit Vim"])
+                .unwrap()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_max_time() {
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--max-time", "1.5s", "how do I exit Vim"])
+        });
+        assert_eq!(opts.unwrap().max_time, Some(Duration::from_millis(1500)));
+
+        // Far too long to wait, but not an error
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--max-time", "99999999999999999999999s", "vim"])
+        });
+        assert_eq!(
+            opts.unwrap().max_time,
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn test_team() {
+        let mk_config = || {
+            let mut config = defaults();
+            config.teams.insert(
+                String::from("work"),
+                Team {
+                    api_url: String::from("https://api.stackoverflowteams.com/2.3"),
+                    slug: String::from("acme"),
+                    token: String::from("token"),
+                },
+            );
+            Ok(config)
+        };
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--team", "work", "how do I deploy"])
+        })
+        .unwrap();
+        assert_eq!(opts.config.team, Some(String::from("work")));
+        assert_eq!(opts.config.selected_team().unwrap().unwrap().slug, "acme");
+
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--team", "wrok", "how do I deploy"])
+        });
+        match opts {
+            Err(OptsError::Other(Error::UnknownTeam(name))) => assert_eq!(name, "wrok"),
+            _ => panic!("expected unknown team error"),
+        }
+    }
+
+    #[test]
+    fn test_flag_modes_exhaustive() {
+        let mut args = Vec::new();
+        get_opts_with(mk_config, |a| {
+            // clap 2 has no API for listing args, but its parser's fields are public
+            args.extend(a.p.flags.iter().map(|f| f.b.name.to_string()));
+            args.extend(a.p.opts.iter().map(|o| o.b.name.to_string()));
+            args.extend(a.p.positionals.values().map(|p| p.b.name.to_string()));
+            a.get_matches_from(vec!["so", "how do I exit Vim"])
+        })
+        .unwrap();
+        args.retain(|name| !["help", "version", "query"].contains(&name.as_str()));
+        for name in &args {
+            assert!(
+                FLAGS.iter().any(|flag| flag.name == name),
+                "`--{}` doesn't declare which modes it applies to in FLAGS",
+                name
+            );
+        }
+        for flag in FLAGS {
+            assert!(
+                args.iter().any(|name| name == flag.name),
+                "FLAGS declares `--{}`, which doesn't exist",
+                flag.name
+            );
+            assert!(!flag.modes.is_empty(), "`--{}` has no modes", flag.name);
+            assert!(
+                !flag.selects || flag.modes.len() == 1,
+                "`--{}` selects more than one mode",
+                flag.name
+            );
+        }
+        assert_eq!(args.len(), FLAGS.len(), "FLAGS has duplicates");
+    }
+
+    #[test]
+    fn test_conflicting_modes() {
+        for (args, second) in [
+            (vec!["so", "--lucky", "--output", "markdown", "q"], "output"),
+            (vec!["so", "--digest", "--lucky", "q"], "digest"),
+        ] {
+            match get_opts_with(mk_config, |a| a.get_matches_from(args)) {
+                Err(OptsError::ConflictingFlags("lucky", Lucky, flag, Output)) => {
+                    assert_eq!(flag, second)
+                }
+                _ => panic!("expected conflicting flags error"),
+            }
+        }
+        // Lucky mode from the config isn't contradicted by --output
+        let mk_config = || {
+            Ok(Config {
+                lucky: true,
+                ..defaults()
+            })
+        };
+        let opts = get_opts_with(mk_config, |a| {
+            a.get_matches_from(vec!["so", "--output", "markdown", "q"])
+        });
+        assert!(opts.unwrap().warnings.is_empty());
+        assert_eq!(
+            OptsError::ConflictingFlags("lucky", Lucky, "output", Output).to_string(),
+            "`--lucky` is for lucky mode and `--output` is for output mode; pick one of them"
+        );
+    }
+
+    #[test]
+    fn test_mode_warnings() {
+        let warnings = |args: Vec<&str>| {
+            get_opts_with(mk_config, |a| a.get_matches_from(args))
+                .unwrap()
+                .warnings
+        };
+        assert_eq!(
+            warnings(vec!["so", "--strict-lucky", "--output", "markdown", "q"]),
+            vec!["`--strict-lucky` is ignored by output mode; it's for lucky mode"]
+        );
+        assert_eq!(
+            warnings(vec!["so", "--no-lucky", "--digest", "q"]),
+            vec!["`--no-lucky` is ignored by output mode; it's for the TUI and lucky mode"]
+        );
+        assert_eq!(
+            warnings(vec!["so", "--limit", "5", "doctor"]),
+            vec![
+                "`--limit` is ignored by subcommands; it's for the TUI, lucky mode and output mode"
+            ]
+        );
+        // Default values don't count as given
+        assert!(warnings(vec!["so", "doctor"]).is_empty());
+        assert!(warnings(vec![
+            "so",
+            "--offline",
+            "url",
+            "id",
+            "stackoverflow.com/q/1"
+        ])
+        .is_empty());
+        assert!(warnings(vec!["so", "--strict-lucky", "--lucky", "q"]).is_empty());
+        assert!(warnings(vec!["so", "-l", "5", "--code-search", "q"]).is_empty());
+    }
+
+    #[test]
+    fn test_modes_help() {
+        assert_eq!(
+            modes_help(),
+            "MODE-SPECIFIC FLAGS:\n    \
+             the TUI:        --no-lucky, --tl-dr\n    \
+             lucky mode:     --lucky, --no-lucky, --strict-lucky, --require-accepted, --tl-dr, \
+             --show-spoilers, --no-lucky-header, --open, --answer, --forget, --width, --json\n    \
+             output mode:    --output, --digest, --commands, --width, --json, --pick, --raw-markdown"
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_conflicts() {
@@ -1,29 +1,294 @@
 use directories::ProjectDirs;
+use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
-use std::io::Write;
 use std::path::PathBuf;
 
+use crate::credentials::{self, CredentialStore};
 use crate::error::{Error, Result};
+use crate::format::{self, Locale, ScoreThresholds};
+use crate::stackexchange::answer_sort::AnswerSort;
+use crate::stackexchange::link_rewrites::LinkRewrites;
+use crate::stackexchange::preprocess::{self, Pass};
+use crate::stackexchange::urls::LinkStyle;
+use crate::stackexchange::{self, analysis::Language, Destination, ExtraHeaders};
+use crate::template::{self, Template};
+#[cfg(feature = "tui")]
+use crate::tui::syntax;
 use crate::utils;
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum SearchEngine {
+    #[default]
     DuckDuckGo,
     Google,
     StackExchange,
 }
 
+/// Which of the configured sites lucky mode searches, when searching via the StackExchange API
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LuckySiteStrategy {
+    /// The first one
+    #[default]
+    First,
+    /// The one whose searches have been quickest lately
+    Fastest,
+    /// The first two at once, taking the answer of whichever comes back with one first
+    Race,
+}
+
+/// How to let the user know a slow search is done
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOnComplete {
+    /// Ring the terminal bell
+    Bell,
+    /// Put the number of results in the terminal title until `so` exits
+    Title,
+    /// Both of the above
+    Both,
+    #[default]
+    Off,
+}
+
+/// Where the TUI copies to
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Clipboard {
+    /// The terminal's clipboard over SSH, the system's otherwise
+    #[default]
+    Auto,
+    /// The system clipboard, via `pbcopy`, `clip`, `wl-copy` or `xclip`
+    System,
+    /// Whichever clipboard the terminal puts OSC 52 sequences on, which works over SSH
+    Osc52,
+}
+
+/// Colors of the TUI
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// `colors.toml` if it was edited, or else the built-in theme matching the terminal
+    #[default]
+    Auto,
+    Dark,
+    Light,
+    /// Always `colors.toml`
+    Custom,
+}
+
+/// How to show the images of an answer
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImagePreview {
+    /// Whichever protocol the terminal looks like it speaks
+    Auto,
+    Kitty,
+    Iterm,
+    /// Not spoken yet, since it takes decoding images to pixels; links are listed instead
+    Sixel,
+    /// Only list the links
+    #[default]
+    Off,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct Config {
     pub api_key: Option<String>,
+    /// Base of the StackExchange API, without the version, e.g. for a mirror
+    pub api_url: String,
+    /// Version of the StackExchange API, to pin back to an older one if need be
+    pub api_version: String,
+    /// Seconds a request may take before it's given up on
+    pub timeout_secs: u64,
+    /// Seconds connecting for a request may take before it's given up on
+    pub connect_timeout_secs: u64,
     pub limit: u16,
     pub lucky: bool,
     pub sites: Vec<String>,
     pub search_engine: SearchEngine,
+    /// Minimum relevance (0 to 1) between the query and the lucky question's title before the
+    /// lucky answer is flagged as a low confidence match
+    pub lucky_confidence: f64,
+    /// Language queries are written in, for telling how well results match them: `english`,
+    /// `french`, `german`, `portuguese`, `russian`, `spanish`, or `none` to compare words as
+    /// they are
+    pub query_language: Language,
+    /// Fold typographic punctuation to ASCII, collapse whitespace and correct common typos in
+    /// queries before searching, leaving quoted phrases as they are
+    pub normalize_query: bool,
+    /// Typos for `normalize_query` to correct, and what to, on top of the built-in ones; a word
+    /// mapped to itself is never corrected
+    pub typos: BTreeMap<String, String>,
+    /// Treat low confidence lucky matches as no results at all
+    pub strict_lucky: bool,
+    /// Only print accepted answers in lucky mode, looking past questions without one
+    pub require_accepted: bool,
+    /// How to print the lucky answer; see `template` for the placeholders
+    pub lucky_template: String,
+    /// Print the title of the lucky answer's question, and a link to it, above the answer
+    pub lucky_header: bool,
+    /// Print who posted the lucky answer, and where, below it
+    pub lucky_attribution: bool,
+    /// Remember the answer picked for a lucky query, with `--answer` or at the prompt, and print
+    /// it first when the query comes up again; `--forget` drops it
+    pub lucky_memory: bool,
+    /// Swap the characters outside of ASCII that `--output text` prints for look-alikes
+    pub ascii_only: bool,
+    /// Where the API key is stored
+    pub credential_store: CredentialStore,
+    /// Collapse answers that were posted verbatim on more than one of the resulting questions
+    pub collapse_duplicate_answers: bool,
+    /// StackExchange API filter to use instead of the built-in one
+    pub filter: Option<String>,
+    /// Only keep questions with an answer containing the query verbatim in a code block
+    pub code_search: bool,
+    /// Also show questions that have no answers yet (never in lucky mode)
+    pub include_unanswered: bool,
+    /// Stack Overflow for Teams instances, by name
+    pub teams: BTreeMap<String, Team>,
+    /// Search this team (one of `teams`) instead of the public sites
+    pub team: Option<String>,
+    /// How numbers and dates are written
+    pub locale: Locale,
+    /// Colors of the TUI: `auto`, `dark`, `light`, or `custom` for `colors.toml`
+    pub theme: Theme,
+    /// Draw answer images in the terminal: `auto`, `kitty` or `iterm` for the graphics protocol
+    /// to use, or `off` to list their links instead
+    pub image_preview: ImagePreview,
+    /// Lowest scores shown as plain, good, great and gold, strictly increasing
+    pub score_thresholds: ScoreThresholds,
+    /// Never touch the network; serve sites and searches from the local cache only
+    pub offline: bool,
+    /// Most requests (one per site, or per batch of question ids) to have in flight at once, at
+    /// least 1; lower it on flaky connections or behind strict proxies. Also read as
+    /// `concurrency`.
+    #[serde(alias = "concurrency")]
+    pub max_concurrent_requests: usize,
+    /// Warn once fewer than this many requests are left of the StackExchange API's daily quota
+    pub quota_threshold: u32,
+    /// Searching several sites via the StackExchange API, stop waiting for the rest once those
+    /// in have come back with several times `limit` questions between them
+    pub early_stop: bool,
+    /// Selector for DuckDuckGo result links, tried before the built-in ones; a stopgap for when
+    /// DuckDuckGo changes its markup
+    pub ddg_result_selector: Option<String>,
+    /// Retry DuckDuckGo searches with the query quoted when the results look off-topic
+    pub auto_requote: bool,
+    /// Names of the markdown preprocessing passes to run, in order, or `none`
+    pub preprocess_passes: Vec<String>,
+    /// Narrow down searches to the language of the project `so` is run in, going by manifest
+    /// files like `Cargo.toml`
+    pub context_detection: bool,
+    /// When to search via the StackExchange API for a while after DuckDuckGo blocked requests
+    pub ddg_fallback: DdgFallback,
+    /// Where to put extractive summaries on top of long answers, and how long they get
+    pub tl_dr: TlDr,
+    /// Headers to send with every request, e.g. a token for a proxy, and under `duckduckgo`,
+    /// `google` or `stackexchange`, headers just for requests there, which take precedence
+    pub extra_headers: ExtraHeaders,
+    /// Form of the links printed, `full` or `short`; links opened in a browser are always full
+    pub link_style: LinkStyle,
+    /// Hosts to link to in place of others wherever `so` shows or opens a link, e.g.
+    /// `stackoverflow.com: so-mirror.corp`
+    pub link_rewrites: LinkRewrites,
+    /// Rewrite links in code blocks and inline code too, see `link_rewrites`
+    pub rewrite_links_in_code: bool,
+    /// Which site lucky mode searches via the StackExchange API: `first`, `fastest`, or `race`
+    pub lucky_site_strategy: LuckySiteStrategy,
+    /// Order answers are listed in, and lucky mode picks from: `votes`, `accepted` (the accepted
+    /// one first, then by votes) or `newest`
+    pub answer_sort: AnswerSort,
+    /// Serve a search repeated within this many minutes from the results cached the first time,
+    /// without touching the network; 0 always searches again
+    pub cache_ttl_minutes: u64,
+    /// Longest the summary `S` shares from the TUI gets, in characters; questions that don't fit
+    /// are left out
+    pub share_max_chars: usize,
+    /// Color code blocks in the TUI by the syntax of their language
+    pub syntax_highlighting: bool,
+    /// Theme to color code in: `auto` for the one going with the TUI's theme, or one of syntect's
+    /// built-in themes
+    pub syntax_theme: String,
+    /// Print the code edited with `E` in the TUI once it quits, e.g. to pipe it on
+    pub print_edited_code: bool,
+    /// Let the user know when results are ready, if the search took long enough for them to have
+    /// switched windows: `bell`, `title`, `both` or `off`
+    pub notify_on_complete: NotifyOnComplete,
+    /// Seconds a search has to take for `notify_on_complete` to kick in
+    pub notify_after_secs: u64,
+    /// Clipboard the TUI copies code and summaries to: `system`, `osc52` (the terminal's, which
+    /// works over SSH) or `auto` (the terminal's over SSH, the system's otherwise)
+    pub clipboard: Clipboard,
+    /// Fetch the comments on every listed answer while the TUI sits idle, so that `c` shows them
+    /// right away; each answer takes a request of the daily API quota
+    pub prefetch_comments: bool,
+}
+
+/// Switch from DuckDuckGo to the StackExchange API for `cooldown_mins` once DuckDuckGo blocked
+/// `threshold` requests in a row within `window_mins`
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct DdgFallback {
+    /// Blocks in a row that switch to the API; 0 never switches
+    pub threshold: u32,
+    pub window_mins: u64,
+    pub cooldown_mins: u64,
+}
+
+impl Default for DdgFallback {
+    fn default() -> Self {
+        DdgFallback {
+            threshold: 3,
+            window_mins: 10,
+            cooldown_mins: 60,
+        }
+    }
+}
+
+/// Summaries of long answers, picked from their own sentences; `--tl-dr` turns on both places
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct TlDr {
+    /// Above the lucky answer
+    pub lucky: bool,
+    /// Above each answer in the TUI
+    pub tui: bool,
+    /// Most sentences in a summary
+    pub sentences: usize,
+}
+
+impl Default for TlDr {
+    fn default() -> Self {
+        TlDr {
+            lucky: false,
+            tui: false,
+            sentences: 3,
+        }
+    }
+}
+
+/// A Stack Overflow for Teams instance
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Team {
+    /// Base URL of the API, including the version
+    #[serde(default = "Team::default_api_url")]
+    pub api_url: String,
+    /// Team slug, as in `stackoverflowteams.com/c/<slug>`
+    pub slug: String,
+    /// Personal access token
+    pub token: String,
+}
+
+impl Team {
+    fn default_api_url() -> String {
+        String::from("https://api.stackoverflowteams.com/2.3")
+    }
 }
 
 impl fmt::Display for SearchEngine {
@@ -37,31 +302,88 @@ impl fmt::Display for SearchEngine {
     }
 }
 
-impl Default for SearchEngine {
-    fn default() -> Self {
-        SearchEngine::DuckDuckGo
-    }
-}
-
 // TODO make a friender config file, like the colors.toml below
 impl Default for Config {
     fn default() -> Self {
         Config {
             api_key: Some(String::from("8o9g7WcfwnwbB*Qp4VsGsw((")),
+            api_url: String::from(stackexchange::SE_API_URL),
+            api_version: String::from(stackexchange::SE_API_VERSION),
+            timeout_secs: 10,
+            connect_timeout_secs: 5,
             limit: 20,
             lucky: true,
             sites: vec![String::from("stackoverflow")],
             search_engine: SearchEngine::default(),
+            lucky_confidence: 0.3,
+            query_language: Language::default(),
+            normalize_query: true,
+            typos: BTreeMap::new(),
+            strict_lucky: false,
+            require_accepted: false,
+            lucky_template: String::from(template::DEFAULT),
+            lucky_header: true,
+            lucky_attribution: false,
+            lucky_memory: false,
+            ascii_only: false,
+            credential_store: CredentialStore::default(),
+            collapse_duplicate_answers: false,
+            filter: None,
+            code_search: false,
+            include_unanswered: false,
+            teams: BTreeMap::new(),
+            team: None,
+            locale: Locale::default(),
+            theme: Theme::default(),
+            image_preview: ImagePreview::default(),
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            offline: false,
+            max_concurrent_requests: 8,
+            quota_threshold: 50,
+            early_stop: false,
+            ddg_result_selector: None,
+            auto_requote: false,
+            preprocess_passes: preprocess::DEFAULT_PASSES
+                .iter()
+                .map(|pass| pass.name().to_string())
+                .collect(),
+            context_detection: false,
+            ddg_fallback: DdgFallback::default(),
+            tl_dr: TlDr::default(),
+            extra_headers: ExtraHeaders::default(),
+            link_style: LinkStyle::default(),
+            link_rewrites: LinkRewrites::default(),
+            rewrite_links_in_code: false,
+            lucky_site_strategy: LuckySiteStrategy::default(),
+            answer_sort: AnswerSort::default(),
+            cache_ttl_minutes: 10,
+            share_max_chars: 2000,
+            syntax_highlighting: true,
+            syntax_theme: String::from("auto"),
+            print_edited_code: false,
+            notify_on_complete: NotifyOnComplete::default(),
+            notify_after_secs: 3,
+            clipboard: Clipboard::default(),
+            prefetch_comments: false,
         }
     }
 }
 
 impl Config {
-    /// Get user config (writes default if none found)
+    /// Get user config (writes default if none found), with credentials resolved from the
+    /// configured credential store
     pub fn new() -> Result<Self> {
+        let cfg = Self::read()?;
+        let source = cfg.credential_store.source()?;
+        let api_key = credentials::resolve(source.as_deref(), credentials::API_KEY, cfg.api_key)?;
+        Ok(Config { api_key, ..cfg })
+    }
+
+    /// Get user config exactly as it is in the config file (writes default if none found)
+    fn read() -> Result<Self> {
         let project = Self::project_dir()?;
         let dir = project.config_dir();
-        fs::create_dir_all(&dir)?;
+        fs::create_dir_all(dir)?;
         let filename = Self::config_file_path()?;
 
         match utils::open_file(&filename)? {
@@ -76,18 +398,79 @@ impl Config {
                     if cfg.sites.is_empty() {
                         Err(Error::MalformedFile(filename))
                     } else {
+                        cfg.validate()?;
                         Ok(cfg)
                     }
                 }),
         }
     }
 
+    /// Check the settings that parse but might not make sense
+    fn validate(&self) -> Result<()> {
+        stackexchange::parse_api_url(&self.api_url)
+            .map_err(|reason| Error::ApiUrl(self.api_url.clone(), reason))?;
+        if !stackexchange::valid_api_version(&self.api_version) {
+            return Err(Error::ApiVersion(self.api_version.clone()));
+        }
+        Template::parse(&self.lucky_template)?;
+        Pass::parse_list(&self.preprocess_passes).map_err(Error::PreprocessPass)?;
+        if !format::valid_score_thresholds(&self.score_thresholds) {
+            return Err(Error::ScoreThresholds(self.score_thresholds));
+        }
+        if self.max_concurrent_requests == 0 {
+            return Err(Error::MaxConcurrentRequests);
+        }
+        // Only the TUI highlights code, so only it knows which themes there are
+        #[cfg(feature = "tui")]
+        if !syntax::is_theme(&self.syntax_theme) {
+            return Err(Error::SyntaxTheme(
+                self.syntax_theme.clone(),
+                syntax::themes().join(", "),
+            ));
+        }
+        self.extra_headers.validate()?;
+        self.link_rewrites.validate()?;
+        Ok(())
+    }
+
+    /// The preprocessing passes to run; the default ones if `preprocess_passes` is invalid, which
+    /// `validate` rules out for the config file
+    pub fn passes(&self) -> Vec<Pass> {
+        Pass::parse_list(&self.preprocess_passes)
+            .unwrap_or_else(|_| preprocess::DEFAULT_PASSES.to_vec())
+    }
+
+    /// The extra headers to send to `destination`; none if `extra_headers` is invalid, which
+    /// `validate` rules out for the config file
+    pub fn headers_for(&self, destination: Destination) -> HeaderMap {
+        self.extra_headers
+            .for_destination(destination)
+            .unwrap_or_default()
+    }
+
     // TODO This looks odd when refactoring to associate functions under Config; perhaps this
     // shouldn't be a CLI opt? Maybe a generic --save-config based on current opts?
     pub fn set_api_key(key: String) -> Result<()> {
-        let mut cfg = Self::new()?;
-        cfg.api_key = Some(key);
-        cfg.write()
+        let mut cfg = Self::read()?;
+        match cfg.credential_store.source()? {
+            Some(source) => source.set(credentials::API_KEY, &key),
+            None => {
+                cfg.api_key = Some(key);
+                cfg.write()
+            }
+        }
+    }
+
+    /// The team selected by `team`, if any
+    pub fn selected_team(&self) -> Result<Option<&Team>> {
+        match &self.team {
+            Some(name) => self
+                .teams
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| Error::UnknownTeam(name.clone())),
+            None => Ok(None),
+        }
     }
 
     /// Get project directory
@@ -100,19 +483,129 @@ impl Config {
         Ok(Self::project_dir()?.config_dir().join("config.yml"))
     }
 
-    /// Get theme file path; if it doesn't exist yet, create it with defaults.
-    pub fn theme_file_path() -> Result<PathBuf> {
-        let name = Self::project_dir()?.config_dir().join("colors.toml");
-        if !name.as_path().exists() {
-            let mut file = utils::create_file(&name)?;
-            file.write_all(include_bytes!("../themes/default.toml"))?;
-        }
-        Ok(name)
-    }
-
     fn write(&self) -> Result<()> {
         let filename = Self::config_file_path()?;
         let file = utils::create_file(&filename)?;
         Ok(serde_yaml::to_writer(file, &self)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_lucky_template() {
+        assert!(Config::default().validate().is_ok());
+        let config = Config {
+            lucky_template: String::from("{title}\n{answer}"),
+            ..Config::default()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(
+            err.starts_with(
+                "Invalid `lucky_template` in your config: unknown placeholder `{answer}`"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_validate_api_url() {
+        let config: Config =
+            serde_yaml::from_str("api_url: https://mirror.example.com/se").unwrap();
+        assert!(config.validate().is_ok());
+        let config = Config {
+            api_url: String::from("api.stackexchange.com"),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Invalid `api_url` in your config, `api.stackexchange.com`: \
+             relative URL without a base"
+        );
+    }
+
+    #[test]
+    fn test_validate_api_version() {
+        let config: Config = serde_yaml::from_str("api_version: '2.2'").unwrap();
+        assert!(config.validate().is_ok());
+        for version in ["", "v2.3", "2.3/", "2..3"] {
+            let config = Config {
+                api_version: String::from(version),
+                ..Config::default()
+            };
+            assert_eq!(
+                config.validate().unwrap_err().to_string(),
+                format!(
+                    "Invalid `api_version` in your config, `{}`: expected a version like 2.3",
+                    version
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_score_thresholds() {
+        let config = Config {
+            score_thresholds: [0, 10, 5, 1000],
+            ..Config::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "`score_thresholds` in your config must be strictly increasing, \
+             but are [0, 10, 5, 1000]"
+        );
+        let config: Config = serde_yaml::from_str("score_thresholds: [-1, 2, 20, 200]").unwrap();
+        assert!(config.validate().is_ok());
+        assert!(serde_yaml::from_str::<Config>("score_thresholds: [0, 10]").is_err());
+    }
+
+    #[test]
+    fn test_validate_max_concurrent_requests() {
+        let config: Config = serde_yaml::from_str("max_concurrent_requests: 0").unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "`max_concurrent_requests` in your config must be at least 1"
+        );
+        let config: Config = serde_yaml::from_str("max_concurrent_requests: 1").unwrap();
+        assert!(config.validate().is_ok());
+        let config: Config = serde_yaml::from_str("concurrency: 3").unwrap();
+        assert_eq!(config.max_concurrent_requests, 3);
+    }
+
+    #[test]
+    fn test_validate_preprocess_passes() {
+        let passes = |names: &[&str]| Config {
+            preprocess_passes: names.iter().map(|name| name.to_string()).collect(),
+            ..Config::default()
+        };
+        assert_eq!(Config::default().passes(), preprocess::DEFAULT_PASSES);
+        assert!(passes(&["none"]).validate().is_ok());
+        assert!(passes(&["none"]).passes().is_empty());
+        assert!(passes(&["kbd"]).validate().is_ok());
+        let err = passes(&["trim", "fence-normalize"]).validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown preprocessing pass `fence-normalize` in `preprocess_passes`; \
+             available are trim, kbd, comments and entities, \
+             or none on its own to turn them all off"
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_validate_syntax_theme() {
+        let theme = |name: &str| Config {
+            syntax_theme: String::from(name),
+            ..Config::default()
+        };
+        assert!(theme("auto").validate().is_ok());
+        assert!(theme("Solarized (dark)").validate().is_ok());
+        let err = theme("monokai").validate().unwrap_err().to_string();
+        assert!(err
+            .starts_with("Unknown `syntax_theme` `monokai` in your config; available are auto, "));
+        assert!(err.contains("InspiredGitHub"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Storage backends for secrets such as the StackExchange API key.
+//!
+//! By default secrets live in plain text in `config.yml`. When built with the `secure-store`
+//! feature and configured with `credential_store: keyring`, they are kept in the OS keychain
+//! instead, and the config file value only serves as a fallback.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Name under which the StackExchange API key is stored
+pub const API_KEY: &str = "api_key";
+
+/// Service name used for keychain entries
+#[cfg(feature = "secure-store")]
+const KEYRING_SERVICE: &str = "so";
+
+/// Where secrets are read from and written to
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialStore {
+    /// Plain text in the config file
+    #[default]
+    File,
+    /// The OS keychain (requires the `secure-store` feature)
+    Keyring,
+}
+
+/// A place secrets can be kept outside of the config file
+pub trait CredentialSource {
+    /// Look up the secret stored under `name`. `Ok(None)` means the source is
+    /// reachable but holds no such entry.
+    fn get(&self, name: &str) -> Result<Option<String>>;
+
+    /// Store `secret` under `name`, replacing any existing entry
+    fn set(&self, name: &str, secret: &str) -> Result<()>;
+}
+
+impl CredentialStore {
+    /// The source backing this store, or `None` if secrets belong in the config file
+    pub fn source(self) -> Result<Option<Box<dyn CredentialSource>>> {
+        match self {
+            CredentialStore::File => Ok(None),
+            #[cfg(feature = "secure-store")]
+            CredentialStore::Keyring => Ok(Some(Box::new(Keyring))),
+            #[cfg(not(feature = "secure-store"))]
+            CredentialStore::Keyring => Err(Error::CredentialStore(
+                "this build of so does not include the `secure-store` feature".into(),
+            )),
+        }
+    }
+}
+
+/// Resolve a secret, preferring `source` and falling back to the value found in the config file
+pub fn resolve(
+    source: Option<&dyn CredentialSource>,
+    name: &str,
+    file_value: Option<String>,
+) -> Result<Option<String>> {
+    match source {
+        Some(source) => Ok(source.get(name)?.or(file_value)),
+        None => Ok(file_value),
+    }
+}
+
+/// The OS keychain: macOS Keychain, Windows Credential Manager, or the kernel's session keyring
+/// on Linux. The latter doesn't outlive the login session, so a key stored there is gone after
+/// logging out or rebooting, at which point the config file value takes over again.
+#[cfg(feature = "secure-store")]
+pub struct Keyring;
+
+#[cfg(feature = "secure-store")]
+impl Keyring {
+    fn entry(name: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(KEYRING_SERVICE, name).map_err(keyring_error)
+    }
+}
+
+#[cfg(feature = "secure-store")]
+impl CredentialSource for Keyring {
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        match Self::entry(name)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+
+    fn set(&self, name: &str, secret: &str) -> Result<()> {
+        Self::entry(name)?
+            .set_password(secret)
+            .map_err(keyring_error)
+    }
+}
+
+#[cfg(feature = "secure-store")]
+fn keyring_error(e: keyring::Error) -> Error {
+    Error::CredentialStore(Box::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    /// In-memory credential source, optionally simulating an unreachable keychain
+    struct MockSource {
+        entries: RefCell<HashMap<String, String>>,
+        available: bool,
+    }
+
+    impl MockSource {
+        fn new(entries: &[(&str, &str)]) -> Self {
+            MockSource {
+                entries: RefCell::new(
+                    entries
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                ),
+                available: true,
+            }
+        }
+
+        fn unavailable() -> Self {
+            MockSource {
+                available: false,
+                ..Self::new(&[])
+            }
+        }
+    }
+
+    impl CredentialSource for MockSource {
+        fn get(&self, name: &str) -> Result<Option<String>> {
+            if !self.available {
+                return Err(Error::CredentialStore("no keychain service".into()));
+            }
+            Ok(self.entries.borrow().get(name).cloned())
+        }
+
+        fn set(&self, name: &str, secret: &str) -> Result<()> {
+            if !self.available {
+                return Err(Error::CredentialStore("no keychain service".into()));
+            }
+            self.entries
+                .borrow_mut()
+                .insert(name.to_string(), secret.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_source_takes_precedence() {
+        let source = MockSource::new(&[(API_KEY, "from keychain")]);
+        assert_eq!(
+            resolve(Some(&source), API_KEY, Some(String::from("from file"))).unwrap(),
+            Some(String::from("from keychain"))
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_file() {
+        let source = MockSource::new(&[]);
+        assert_eq!(
+            resolve(Some(&source), API_KEY, Some(String::from("from file"))).unwrap(),
+            Some(String::from("from file"))
+        );
+        assert_eq!(resolve(Some(&source), API_KEY, None).unwrap(), None);
+        assert_eq!(
+            resolve(None, API_KEY, Some(String::from("from file"))).unwrap(),
+            Some(String::from("from file"))
+        );
+    }
+
+    #[test]
+    fn test_unavailable_source_errors() {
+        let source = MockSource::unavailable();
+        match resolve(Some(&source), API_KEY, Some(String::from("from file"))) {
+            Err(Error::CredentialStore(_)) => (),
+            r => panic!("expected credential store error, got {:?}", r),
+        }
+        assert!(source.set(API_KEY, "key").is_err());
+    }
+
+    #[test]
+    fn test_store_source() {
+        assert!(CredentialStore::File.source().unwrap().is_none());
+        #[cfg(not(feature = "secure-store"))]
+        assert!(CredentialStore::Keyring.source().is_err());
+    }
+}
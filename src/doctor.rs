@@ -0,0 +1,589 @@
+//! `so doctor`: checks whether the hosts `so` talks to can be reached, one stage of the connection
+//! at a time, so that a blocked network shows up as "DNS fails for api.stackexchange.com" rather
+//! than an opaque error from the HTTP client.
+//!
+//! The stages themselves go through the `Probe` trait, so the diagnosis can be tested without a
+//! network.
+
+use futures::future::{self, BoxFuture, FutureExt};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time;
+
+/// Hosts searches depend on
+pub const HOSTS: &[&str] = &["api.stackexchange.com", "duckduckgo.com"];
+
+const PORT: u16 = 443;
+
+/// Longest to wait on any one stage
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Environment variables reqwest takes a proxy from
+const PROXY_VARS: &[&str] = &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Dns,
+    Tcp,
+    Tls,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Dns => "DNS",
+            Stage::Tcp => "TCP",
+            Stage::Tls => "TLS",
+        }
+    }
+}
+
+/// The steps of connecting to a host
+pub trait Probe: Sync {
+    /// Addresses `host` resolves to
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Vec<SocketAddr>, String>>;
+
+    /// Open a TCP connection to `addr`
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<(), String>>;
+
+    /// Connect to `addr` and complete a TLS handshake, verifying the certificate for `host`
+    fn handshake<'a>(
+        &'a self,
+        host: &'a str,
+        addr: SocketAddr,
+    ) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// Probe of the actual network
+pub struct NetProbe {
+    pub port: u16,
+    pub timeout: Duration,
+}
+
+impl Default for NetProbe {
+    fn default() -> Self {
+        NetProbe {
+            port: PORT,
+            timeout: TIMEOUT,
+        }
+    }
+}
+
+impl NetProbe {
+    async fn tcp(&self, addr: SocketAddr) -> Result<TcpStream, String> {
+        time::timeout(self.timeout, TcpStream::connect(addr))
+            .await
+            .map_err(|_| format!("timed out after {}s", self.timeout.as_secs()))?
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Probe for NetProbe {
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Vec<SocketAddr>, String>> {
+        async move {
+            let addrs = time::timeout(self.timeout, tokio::net::lookup_host((host, self.port)))
+                .await
+                .map_err(|_| format!("timed out after {}s", self.timeout.as_secs()))?
+                .map_err(|e| e.to_string())?;
+            Ok(addrs.collect())
+        }
+        .boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<(), String>> {
+        async move { self.tcp(addr).await.map(|_| ()) }.boxed()
+    }
+
+    fn handshake<'a>(
+        &'a self,
+        host: &'a str,
+        addr: SocketAddr,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            let stream = self.tcp(addr).await?;
+            let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+            let connector = tokio_native_tls::TlsConnector::from(connector);
+            time::timeout(self.timeout, connector.connect(host, stream))
+                .await
+                .map_err(|_| format!("timed out after {}s", self.timeout.as_secs()))?
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        .boxed()
+    }
+}
+
+/// Outcome of one stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub latency: Duration,
+    /// Some detail on success, or the error
+    pub result: Result<String, String>,
+}
+
+/// Outcome of connecting to one host, up to the first stage that failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub host: String,
+    pub stages: Vec<StageReport>,
+}
+
+impl Check {
+    fn new(host: &str, stages: Vec<StageReport>) -> Self {
+        Check {
+            host: host.to_string(),
+            stages,
+        }
+    }
+
+    /// Whether every stage succeeded
+    pub fn ok(&self) -> bool {
+        self.stages.len() == 3 && self.stages.iter().all(|s| s.result.is_ok())
+    }
+
+    /// Advice on what's likely wrong, going by how the check failed
+    pub fn hints(&self) -> Vec<&'static str> {
+        let mut hints = Vec::new();
+        for report in &self.stages {
+            match &report.result {
+                Ok(detail) if report.stage == Stage::Dns && is_sinkhole(detail) => {
+                    hints.push(SINKHOLE_HINT)
+                }
+                Err(message) => hints.extend(hint(report.stage, message)),
+                _ => (),
+            }
+        }
+        hints
+    }
+}
+
+/// Run each stage of connecting to `host` in turn, stopping at the first failure
+pub async fn check<P: Probe>(probe: &P, host: &str) -> Check {
+    let mut stages = Vec::new();
+    let start = Instant::now();
+    let resolved = probe.resolve(host).await.and_then(|addrs| {
+        // Prefer IPv4, which is less likely to be broken somewhere along the way
+        addrs
+            .iter()
+            .find(|a| a.is_ipv4())
+            .or_else(|| addrs.first())
+            .copied()
+            .ok_or_else(|| String::from("no addresses found"))
+    });
+    stages.push(StageReport {
+        stage: Stage::Dns,
+        latency: start.elapsed(),
+        result: resolved
+            .as_ref()
+            .map(|a| a.ip().to_string())
+            .map_err(Clone::clone),
+    });
+    let addr = match resolved {
+        Ok(addr) => addr,
+        Err(_) => return Check::new(host, stages),
+    };
+
+    let start = Instant::now();
+    let connected = probe.connect(addr).await;
+    let failed = connected.is_err();
+    stages.push(StageReport {
+        stage: Stage::Tcp,
+        latency: start.elapsed(),
+        result: connected.map(|_| addr.to_string()),
+    });
+    if failed {
+        return Check::new(host, stages);
+    }
+
+    let start = Instant::now();
+    let result = probe.handshake(host, addr).await;
+    stages.push(StageReport {
+        stage: Stage::Tls,
+        latency: start.elapsed(),
+        result: result.map(|_| String::from("certificate verified")),
+    });
+    Check::new(host, stages)
+}
+
+/// Check all of `hosts` at once
+pub async fn check_all<P: Probe>(probe: &P, hosts: &[&str]) -> Vec<Check> {
+    future::join_all(hosts.iter().map(|host| check(probe, host))).await
+}
+
+const SINKHOLE_HINT: &str = "The host resolves to a local address, so DNS is being blocked, e.g. \
+    by a corporate DNS filter, an ad blocker or an entry in your hosts file.";
+
+/// Known failure signatures of each stage, by lowercase substrings of the error, and what they
+/// usually mean
+const SIGNATURES: &[(Stage, &[&str], &str)] = &[
+    (
+        Stage::Dns,
+        &[
+            "failed to lookup address",
+            "name or service not known",
+            "nodename nor servname",
+            "no such host",
+            "temporary failure in name resolution",
+            "no addresses",
+        ],
+        "DNS can't resolve the host. Check your network connection and DNS settings; on a \
+         corporate network, you may need to be on the VPN.",
+    ),
+    (
+        Stage::Dns,
+        &["timed out"],
+        "DNS lookups time out, so no DNS server is reachable. Check your network connection.",
+    ),
+    (
+        Stage::Tcp,
+        &["timed out"],
+        "Connections are silently dropped, which usually means a firewall only lets traffic \
+         through a proxy. If your network has one, set HTTPS_PROXY to its URL.",
+    ),
+    (
+        Stage::Tcp,
+        &["refused", "reset"],
+        "The connection was refused, so something on the way is blocking the host. If your \
+         network has a proxy, set HTTPS_PROXY to its URL.",
+    ),
+    (
+        Stage::Tcp,
+        &["unreachable"],
+        "The network is unreachable. Check your network connection.",
+    ),
+    (
+        Stage::Tls,
+        &[
+            "certificate verify failed",
+            "self signed certificate",
+            "self-signed certificate",
+            "unable to get local issuer certificate",
+            "not trusted",
+            "unknownissuer",
+            "invalid peer certificate",
+        ],
+        "The host's certificate isn't trusted, which usually means a corporate proxy is \
+         intercepting TLS traffic. Ask your IT department for its root certificate and add it \
+         to your system's trust store.",
+    ),
+    (
+        Stage::Tls,
+        &[
+            "hostname mismatch",
+            "certificate is not valid for",
+            "does not match",
+        ],
+        "The certificate is for another host, so the connection is being redirected, e.g. by a \
+         captive portal or filtering proxy. Try logging in to the network through a browser.",
+    ),
+    (
+        Stage::Tls,
+        &["timed out", "reset", "unexpected eof", "handshake"],
+        "The TLS handshake is being cut off, likely by a firewall inspecting traffic. If your \
+         network has a proxy, set HTTPS_PROXY to its URL.",
+    ),
+];
+
+/// What an error `message` at `stage` most likely means
+pub fn hint(stage: Stage, message: &str) -> Option<&'static str> {
+    let message = message.to_lowercase();
+    SIGNATURES
+        .iter()
+        .find(|(s, patterns, _)| *s == stage && patterns.iter().any(|p| message.contains(p)))
+        .map(|(_, _, hint)| *hint)
+}
+
+fn is_sinkhole(ip: &str) -> bool {
+    ip.parse::<std::net::IpAddr>()
+        .map(|ip| ip.is_loopback() || ip.is_unspecified())
+        .unwrap_or(false)
+}
+
+/// Proxy reqwest sends requests through, per the environment
+pub fn proxy() -> Option<String> {
+    PROXY_VARS
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+}
+
+/// Markdown report of `checks`: a table of each stage's outcome and latency, then details on
+/// whatever failed
+pub fn report(checks: &[Check], proxy: Option<&str>) -> String {
+    let mut md = String::new();
+    md.push_str("|:-:|:-:|:-:|:-:|\n");
+    md.push_str("|Host|DNS|TCP|TLS|\n");
+    md.push_str("|:-|:-|:-|:-|\n");
+    for check in checks {
+        let mut cells: Vec<String> = check
+            .stages
+            .iter()
+            .map(|s| {
+                let outcome = if s.result.is_ok() { "ok" } else { "FAILED" };
+                format!("{} ({}ms)", outcome, s.latency.as_millis())
+            })
+            .collect();
+        cells.resize(3, String::from("-"));
+        md.push_str(&format!("|{}|{}\n", check.host, cells.join("|")));
+    }
+    md.push_str("|-\n");
+    for check in checks {
+        let failure = check.stages.iter().find_map(|s| match &s.result {
+            Err(message) => Some((s.stage, message)),
+            Ok(_) => None,
+        });
+        if let Some((stage, message)) = failure {
+            md.push_str(&format!(
+                "\n**{}**: {} failed: {}\n",
+                check.host,
+                stage.name(),
+                message
+            ));
+        }
+        for hint in check.hints() {
+            md.push_str(&format!("* {}\n", hint));
+        }
+    }
+    if let Some(proxy) = proxy {
+        md.push_str(&format!(
+            "\nSearches go through the proxy at `{}`, but these checks connect directly, so \
+             failures here may not affect them.\n",
+            proxy
+        ));
+    }
+    if checks.iter().all(Check::ok) {
+        md.push_str("\nEverything looks fine.\n");
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::net::TcpListener;
+
+    /// Probe whose every stage has a canned outcome, by host
+    #[derive(Default)]
+    struct MockProbe {
+        addrs: HashMap<&'static str, Result<Vec<SocketAddr>, String>>,
+        connect: HashMap<SocketAddr, Result<(), String>>,
+        handshake: HashMap<&'static str, Result<(), String>>,
+    }
+
+    impl Probe for MockProbe {
+        fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Vec<SocketAddr>, String>> {
+            future::ready(self.addrs[host].clone()).boxed()
+        }
+
+        fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<(), String>> {
+            future::ready(self.connect.get(&addr).cloned().unwrap_or(Ok(()))).boxed()
+        }
+
+        fn handshake<'a>(
+            &'a self,
+            host: &'a str,
+            _: SocketAddr,
+        ) -> BoxFuture<'a, Result<(), String>> {
+            future::ready(self.handshake.get(host).cloned().unwrap_or(Ok(()))).boxed()
+        }
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn mock() -> MockProbe {
+        let mut probe = MockProbe::default();
+        probe.addrs.insert(
+            "fine.com",
+            Ok(vec![addr("[2001:db8::1]:443"), addr("192.0.2.1:443")]),
+        );
+        probe.addrs.insert(
+            "unknown.com",
+            Err(String::from(
+                "failed to lookup address information: Name or service not known",
+            )),
+        );
+        probe
+            .addrs
+            .insert("blocked.com", Ok(vec![addr("192.0.2.2:443")]));
+        probe.connect.insert(
+            addr("192.0.2.2:443"),
+            Err(String::from("timed out after 5s")),
+        );
+        probe
+            .addrs
+            .insert("intercepted.com", Ok(vec![addr("192.0.2.3:443")]));
+        probe.handshake.insert(
+            "intercepted.com",
+            Err(String::from(
+                "error:1416F086:SSL routines:tls_process_server_certificate:certificate verify \
+                 failed:ssl/statem/statem_clnt.c:1915: (self signed certificate in certificate \
+                 chain)",
+            )),
+        );
+        probe
+            .addrs
+            .insert("sinkholed.com", Ok(vec![addr("0.0.0.0:443")]));
+        probe
+    }
+
+    fn outcomes(check: &Check) -> Vec<(Stage, Result<String, String>)> {
+        check
+            .stages
+            .iter()
+            .map(|s| (s.stage, s.result.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_check_stages() {
+        let probe = mock();
+        let fine = check(&probe, "fine.com").await;
+        assert!(fine.ok());
+        assert_eq!(
+            outcomes(&fine),
+            vec![
+                (Stage::Dns, Ok(String::from("192.0.2.1"))),
+                (Stage::Tcp, Ok(String::from("192.0.2.1:443"))),
+                (Stage::Tls, Ok(String::from("certificate verified"))),
+            ]
+        );
+        assert!(fine.hints().is_empty());
+
+        // Stages after a failure aren't attempted
+        let unknown = check(&probe, "unknown.com").await;
+        assert!(!unknown.ok());
+        assert_eq!(unknown.stages.len(), 1);
+        let blocked = check(&probe, "blocked.com").await;
+        assert_eq!(blocked.stages.len(), 2);
+        assert_eq!(
+            blocked.stages[1].result,
+            Err(String::from("timed out after 5s"))
+        );
+        let intercepted = check(&probe, "intercepted.com").await;
+        assert_eq!(intercepted.stages.len(), 3);
+        assert!(!intercepted.ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_hints() {
+        let probe = mock();
+        let hints = |host| {
+            let probe = &probe;
+            async move { check(probe, host).await.hints() }
+        };
+        assert!(hints("unknown.com").await[0].starts_with("DNS can't resolve"));
+        assert!(hints("blocked.com").await[0].contains("HTTPS_PROXY"));
+        assert!(hints("intercepted.com").await[0].contains("corporate proxy"));
+        // Resolving to 0.0.0.0 works, but isn't the real host
+        let sinkholed = check(&probe, "sinkholed.com").await;
+        assert!(sinkholed.ok());
+        assert_eq!(sinkholed.hints(), vec![SINKHOLE_HINT]);
+    }
+
+    #[test]
+    fn test_hint_signatures() {
+        let cases = [
+            (
+                Stage::Dns,
+                "No such host is known. (os error 11001)",
+                "DNS can't",
+            ),
+            (Stage::Dns, "timed out after 5s", "DNS lookups time out"),
+            (Stage::Tcp, "Connection refused (os error 111)", "refused"),
+            (
+                Stage::Tcp,
+                "Network is unreachable (os error 101)",
+                "unreachable",
+            ),
+            (
+                Stage::Tls,
+                "The certificate was not trusted. (UnknownIssuer)",
+                "corporate proxy",
+            ),
+            (
+                Stage::Tls,
+                "certificate verify failed: Hostname mismatch",
+                "corporate proxy",
+            ),
+            (Stage::Tls, "hostname mismatch", "captive portal"),
+            (Stage::Tls, "unexpected EOF", "cut off"),
+        ];
+        for (stage, message, expected) in cases.iter() {
+            let hint = hint(*stage, message).unwrap_or_default();
+            assert!(hint.contains(expected), "{:?} {}: {}", stage, message, hint);
+        }
+        assert_eq!(hint(Stage::Tcp, "something else entirely"), None);
+        // Signatures are per stage
+        assert_eq!(hint(Stage::Dns, "Connection refused"), None);
+    }
+
+    #[tokio::test]
+    async fn test_report() {
+        let probe = mock();
+        let checks = check_all(&probe, &["fine.com", "blocked.com"]).await;
+        let checks: Vec<Check> = checks
+            .into_iter()
+            .map(|mut c| {
+                for s in &mut c.stages {
+                    s.latency = Duration::from_millis(12);
+                }
+                c
+            })
+            .collect();
+        assert_eq!(
+            report(&checks, None),
+            "|:-:|:-:|:-:|:-:|\n\
+             |Host|DNS|TCP|TLS|\n\
+             |:-|:-|:-|:-|\n\
+             |fine.com|ok (12ms)|ok (12ms)|ok (12ms)\n\
+             |blocked.com|ok (12ms)|FAILED (12ms)|-\n\
+             |-\n\
+             \n**blocked.com**: TCP failed: timed out after 5s\n\
+             * Connections are silently dropped, which usually means a firewall only lets \
+             traffic through a proxy. If your network has one, set HTTPS_PROXY to its URL.\n"
+        );
+        let fine = report(&checks[..1], Some("http://proxy:3128"));
+        assert!(fine.contains("proxy at `http://proxy:3128`"));
+        assert!(fine.ends_with("Everything looks fine.\n"));
+    }
+
+    #[tokio::test]
+    async fn test_net_probe() {
+        let probe = NetProbe {
+            port: 443,
+            timeout: Duration::from_secs(5),
+        };
+        let addrs = probe.resolve("localhost").await.unwrap();
+        assert!(addrs
+            .iter()
+            .all(|a| a.ip().is_loopback() && a.port() == 443));
+
+        // A plain TCP server accepts the connection, but not the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                drop(socket);
+            }
+        });
+        assert_eq!(probe.connect(open).await, Ok(()));
+        let handshake = probe.handshake("localhost", open).await.unwrap_err();
+        assert!(hint(Stage::Tls, &handshake).is_some(), "{}", handshake);
+
+        // Nothing listens on a port that was just freed
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let refused = probe.connect(closed).await.unwrap_err();
+        assert_eq!(
+            hint(Stage::Tcp, &refused),
+            hint(Stage::Tcp, "Connection refused")
+        );
+    }
+}
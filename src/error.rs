@@ -1,13 +1,15 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Everything that can go wrong. Failures with a cause of their own, such as a request or a site
+/// listing that failed, chain to it via `source`; those of the StackExchange API and of search
+/// engines carry what they're about as fields, for scripts and for telling them apart.
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+#[non_exhaustive]
 pub enum Error {
-    #[error("Termimad error: {0}")]
-    Termimad(#[from] termimad::Error),
-    #[error("Crossterm error: {0}")]
-    Crossterm(#[from] crossterm::ErrorKind),
     #[error("Reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
     #[error("SerdeJson error: {0}")]
@@ -18,18 +20,325 @@ pub enum Error {
     IO(#[from] std::io::Error),
     #[error("Futures Join error : {0}")]
     JoinError(#[from] tokio::task::JoinError),
+    /// Failure of the requests for a single site, among those searched concurrently
+    #[error("{site}: {source}")]
+    Site {
+        site: String,
+        #[source]
+        source: Box<Error>,
+    },
+    /// Failure of every site searched concurrently, each with its own error
+    #[error("Every site failed: {}", join(.0, "; "))]
+    AllSites(Vec<Error>),
+    #[error("Panicked: {0}")]
+    Panic(String),
+    #[error("Search cancelled")]
+    Cancelled,
+    /// Ctrl-C while waiting on a search
+    #[error("Interrupted")]
+    Interrupted,
     #[error("File `{}` is malformed; try removing it", .0.display())]
     MalformedFile(PathBuf),
     #[error("Lacking {0:?} permissions on `{}`", .1.display())]
     Permissions(PermissionType, PathBuf),
-    #[error("{0}")]
-    StackExchange(String),
-    #[error("{0}")]
-    ScrapingError(String),
+    /// Error object the StackExchange API responded with in place of items
+    #[error(
+        "StackExchange API error {status}{}: {message}",
+        .name.as_ref().map(|name| format!(" ({})", name)).unwrap_or_default()
+    )]
+    Api {
+        /// Site the request was for, if it was for one
+        site: Option<String>,
+        /// Endpoint requested, e.g. `search/advanced`
+        endpoint: Option<String>,
+        /// The `error_id`, which follows HTTP status codes, e.g. 502 for throttling
+        status: u32,
+        /// The `error_name`, e.g. `bad_parameter`, unless the response left it out
+        name: Option<String>,
+        message: String,
+    },
+    /// A response that has the right shape but not what it should
+    #[error("Unexpected response from `{endpoint}`: {reason}")]
+    UnexpectedResponse {
+        endpoint: String,
+        reason: &'static str,
+    },
+    #[error("Invalid API URL `{url}`: {reason}")]
+    InvalidApiUrl { url: String, reason: String },
+    #[error("{engine} blocked this request")]
+    Blocked { engine: &'static str },
+    #[error("{engine} asked for cookie consent instead of searching; try another search engine")]
+    Consent { engine: &'static str },
+    #[error(
+        "{engine} suspects unusual traffic and asked for a captcha instead of searching; \
+        try another search engine"
+    )]
+    Captcha { engine: &'static str },
+    #[error("Invalid {name} selector `{selector}`")]
+    ResultSelector { name: String, selector: String },
+    /// None of the selectors tried, each as its name and the selector, found any results
+    #[error(
+        "{engine}'s result layout seems to have changed; none of these selectors found any \
+        questions: {}. Set `ddg_result_selector` in your config to a selector matching result \
+        links, or try another search engine",
+        .tried.join(", ")
+    )]
+    ResultLayout {
+        engine: &'static str,
+        tried: Vec<String>,
+    },
+    #[error(
+        "Couldn't use the system keychain: {0}; \
+        set `credential_store: file` in your config to store credentials in plain text"
+    )]
+    CredentialStore(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Site codes missing from the site listing, e.g. from `--site`
+    #[error("{}", join(.0, "\n"))]
+    InvalidSites(Vec<crate::stackexchange::InvalidSite>),
+    /// A site the API no longer knows, having been shut down or merged into another
+    #[error(
+        "site '{site}' no longer exists \u{2014} {}",
+        match .successor {
+            Some(successor) => format!("it moved to '{}'; use that in your config instead", successor),
+            None => String::from("remove it from your config"),
+        }
+    )]
+    SiteGone {
+        site: String,
+        /// The site it was merged into or renamed to, if any
+        successor: Option<String>,
+    },
+    #[error("No team named `{0}` in the `teams` section of your config")]
+    UnknownTeam(String),
     #[error("Couldn't find a suitable project directory; is your OS supported?")]
     ProjectDir,
     #[error("Sorry, couldn't find any answers to your question")]
     NoResults,
+    #[error("--answer {wanted} is out of range: the lucky question only has {found}")]
+    NoSuchAnswer { wanted: usize, found: usize },
+    #[error("Search timed out after {0:?} before any results came in")]
+    Timeout(Duration),
+    /// A single request that took longer than the config allows
+    #[error("request to {to} timed out after {after:?}; raise `{setting}` in your config")]
+    RequestTimeout {
+        /// Site or search engine the request was for
+        to: String,
+        after: Duration,
+        /// The config option that set the timeout
+        setting: &'static str,
+    },
+    #[error("This needs network access, which isn't allowed in offline mode")]
+    Offline,
+    #[error(
+        "No cached results for `{0}`; search for it without --offline first{}",
+        .1.as_ref().map(|q| format!(" (closest cached query: `{}`)", q)).unwrap_or_default()
+    )]
+    NotCached(String, Option<String>),
+    #[error("Couldn't import backup: {0}")]
+    Backup(#[source] BackupError),
+    #[error("Not a link to a question or answer on a known StackExchange site: {0}")]
+    UnrecognizedUrl(String),
+    #[error("No known StackExchange site is at {0}; if it's new, run `so --update-sites` first")]
+    UnknownHost(String),
+    #[error(
+        "Unknown preprocessing pass `{0}` in `preprocess_passes`; available are {}, \
+        or none on its own to turn them all off",
+        pass_names()
+    )]
+    PreprocessPass(String),
+    #[error("`score_thresholds` in your config must be strictly increasing, but are {0:?}")]
+    ScoreThresholds(crate::format::ScoreThresholds),
+    #[error("`max_concurrent_requests` in your config must be at least 1")]
+    MaxConcurrentRequests,
+    #[error("Invalid `api_url` in your config, `{0}`: {1}")]
+    ApiUrl(String, String),
+    #[error("Invalid `api_version` in your config, `{0}`: expected a version like 2.3")]
+    ApiVersion(String),
+    #[error("Unknown `syntax_theme` `{0}` in your config; available are auto, {1}")]
+    SyntaxTheme(String, String),
+    #[error("Invalid header `{0}` in `extra_headers` in your config: {1}")]
+    ExtraHeader(String, String),
+    #[error("Invalid entry `{0}` in `link_rewrites` in your config: {1}")]
+    LinkRewrite(String, String),
+    #[error("Invalid `lucky_template` in your config: {0}")]
+    LuckyTemplate(#[from] crate::template::ParseError),
+    #[error("No such file: `{}`", .0.display())]
+    FileNotFound(PathBuf),
+    #[error("`{}` is not a file", .0.display())]
+    NotAFile(PathBuf),
+    #[error("`{}` looks like a binary file; only text files can be compared", .0.display())]
+    BinaryFile(PathBuf),
+    #[error("`{0}` is over the {} MiB images are capped at", .1 / (1024 * 1024))]
+    ImageTooLarge(String, u64),
+    #[error("Couldn't open `{url}` in a browser: {source}")]
+    Browser {
+        url: String,
+        #[source]
+        source: CommandError,
+    },
+    /// Failure of every clipboard command tried, each with its own error
+    #[error("Couldn't copy to the clipboard: {}", join(.0, "; "))]
+    Clipboard(Vec<CommandError>),
+    #[error("Couldn't copy to the clipboard via OSC 52: {0}")]
+    Osc52(#[source] std::io::Error),
+    #[error("Set $EDITOR to edit code, e.g. to `vim` or `code --wait`")]
+    NoEditor,
+    #[error("Couldn't edit the code: {0}")]
+    Editor(#[source] CommandError),
+}
+
+/// Why an external command, such as a browser opener or `$EDITOR`, didn't do its job
+#[derive(thiserror::Error, Debug)]
+pub enum CommandError {
+    #[error("`{command}` couldn't run: {source}")]
+    Io {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("`{command}` exited with {status}")]
+    Exited {
+        command: String,
+        status: std::process::ExitStatus,
+    },
+}
+
+/// What's wrong with a backup archive
+#[derive(thiserror::Error, Debug)]
+pub enum BackupError {
+    #[error("`{}` not found", .0.display())]
+    NotFound(PathBuf),
+    /// A file the manifest calls for, or the manifest itself
+    #[error("archive is missing `{0}`")]
+    Missing(&'static str),
+    /// Part of the archive written in a format only a newer version of `so` can read
+    #[error(
+        "{part} is version {version}, but this version of `so` only supports up to {supported}; \
+        please upgrade to at least {so_version}, which wrote the archive"
+    )]
+    Newer {
+        part: &'static str,
+        version: u32,
+        supported: u32,
+        /// Version of `so` that wrote the archive
+        so_version: String,
+    },
+}
+
+/// How the API's error message for a site it doesn't know starts, as in
+/// ``No site found for name `windowsphone` ``
+const UNKNOWN_SITE_MESSAGE: &str = "No site found for name";
+
+/// Exit code after Ctrl-C, as shells report a process killed by SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Exit code of lucky mode when the answer it printed isn't accepted, so that scripts can tell a
+/// best effort apart from an accepted answer
+pub const NOT_ACCEPTED_EXIT_CODE: i32 = 10;
+
+impl Error {
+    /// `e`, the failure of the requests for `site`
+    pub fn at_site(site: impl Into<String>, e: Error) -> Self {
+        Error::Site {
+            site: site.into(),
+            source: Box::new(e),
+        }
+    }
+
+    /// `endpoint` responded with something it shouldn't, as `reason` explains
+    pub fn unexpected(endpoint: impl Into<String>, reason: &'static str) -> Self {
+        Error::UnexpectedResponse {
+            endpoint: endpoint.into(),
+            reason,
+        }
+    }
+
+    /// Process exit code for this error, so that scripts can tell "no hits" apart from failures
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoResults => 2,
+            Error::Interrupted => INTERRUPTED_EXIT_CODE,
+            Error::Site { source, .. } => source.exit_code(),
+            _ => 1,
+        }
+    }
+
+    /// Site this error happened on, if it was on just one
+    pub fn site(&self) -> Option<&str> {
+        match self {
+            Error::Site { site, .. } => Some(site),
+            Error::Api { site, .. } => site.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the API didn't know the site a request was for, e.g. as it was shut down
+    pub fn is_unknown_site(&self) -> bool {
+        match self {
+            Error::Site { source, .. } => source.is_unknown_site(),
+            Error::Api {
+                status: 400,
+                message,
+                ..
+            } => message.starts_with(UNKNOWN_SITE_MESSAGE),
+            _ => false,
+        }
+    }
+
+    /// API endpoint that failed, if this error came from one
+    pub fn endpoint(&self) -> Option<&str> {
+        match self {
+            Error::Site { source, .. } => source.endpoint(),
+            Error::Api { endpoint, .. } => endpoint.as_deref(),
+            Error::UnexpectedResponse { endpoint, .. } => Some(endpoint),
+            _ => None,
+        }
+    }
+
+    /// File this error is about, if it's about one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Site { source, .. } => source.path(),
+            Error::MalformedFile(path)
+            | Error::Permissions(_, path)
+            | Error::FileNotFound(path)
+            | Error::NotAFile(path)
+            | Error::BinaryFile(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// HTTP status, or the API's equivalent, that came with this error
+    pub fn status(&self) -> Option<u32> {
+        match self {
+            Error::Site { source, .. } => source.status(),
+            Error::Api { status, .. } => Some(*status),
+            Error::Reqwest(e) => e.status().map(|status| u32::from(status.as_u16())),
+            _ => None,
+        }
+    }
+}
+
+/// Names of all preprocessing passes, as in "a, b and c"
+fn pass_names() -> String {
+    let names: Vec<_> = crate::stackexchange::preprocess::Pass::ALL
+        .iter()
+        .map(|pass| pass.name())
+        .collect();
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// `items`, one after the other
+fn join<T: ToString>(items: &[T], separator: &str) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(separator)
 }
 
 #[derive(Debug)]
@@ -37,3 +346,325 @@ pub enum PermissionType {
     Read,
     Write,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::InvalidSite;
+    use crate::template::ParseError;
+    use std::error::Error as _;
+
+    fn api(site: Option<&str>, name: Option<&str>) -> Error {
+        Error::Api {
+            site: site.map(String::from),
+            endpoint: Some(String::from("search/advanced")),
+            status: 400,
+            name: name.map(String::from),
+            message: String::from("site is required"),
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let path = || PathBuf::from("/tmp/so/sites.json");
+        let io = || std::io::Error::other("disk full");
+        let json = || serde_json::from_str::<u32>("x").unwrap_err();
+        let cases: Vec<(Error, &str)> =
+            vec![
+            (Error::from(json()), "SerdeJson error: expected value at line 1 column 1"),
+            (Error::from(io()), "IO error: disk full"),
+            (
+                Error::at_site("superuser", Error::Offline),
+                "superuser: This needs network access, which isn't allowed in offline mode",
+            ),
+            (
+                Error::AllSites(vec![Error::Cancelled, Error::NoResults]),
+                "Every site failed: Search cancelled; \
+                 Sorry, couldn't find any answers to your question",
+            ),
+            (Error::Panic(String::from("bad item")), "Panicked: bad item"),
+            (Error::Cancelled, "Search cancelled"),
+            (Error::Interrupted, "Interrupted"),
+            (
+                Error::MalformedFile(path()),
+                "File `/tmp/so/sites.json` is malformed; try removing it",
+            ),
+            (
+                Error::Permissions(PermissionType::Write, path()),
+                "Lacking Write permissions on `/tmp/so/sites.json`",
+            ),
+            (
+                api(None, Some("bad_parameter")),
+                "StackExchange API error 400 (bad_parameter): site is required",
+            ),
+            (api(None, None), "StackExchange API error 400: site is required"),
+            (
+                Error::unexpected("filters/create", "no filter created"),
+                "Unexpected response from `filters/create`: no filter created",
+            ),
+            (
+                Error::InvalidApiUrl {
+                    url: String::from("mailto:se@example.com"),
+                    reason: String::from("not a base URL"),
+                },
+                "Invalid API URL `mailto:se@example.com`: not a base URL",
+            ),
+            (
+                Error::Blocked { engine: "DuckDuckGo" },
+                "DuckDuckGo blocked this request",
+            ),
+            (
+                Error::Consent { engine: "Google" },
+                "Google asked for cookie consent instead of searching; \
+                 try another search engine",
+            ),
+            (
+                Error::Captcha { engine: "Google" },
+                "Google suspects unusual traffic and asked for a captcha instead of searching; \
+                 try another search engine",
+            ),
+            (
+                Error::ResultSelector {
+                    name: String::from("ddg_result_selector"),
+                    selector: String::from("a["),
+                },
+                "Invalid ddg_result_selector selector `a[`",
+            ),
+            (
+                Error::ResultLayout {
+                    engine: "DuckDuckGo",
+                    tried: vec![String::from("current (`a.result__a`)")],
+                },
+                "DuckDuckGo's result layout seems to have changed; none of these selectors found \
+                 any questions: current (`a.result__a`). Set `ddg_result_selector` in your config \
+                 to a selector matching result links, or try another search engine",
+            ),
+            (
+                Error::CredentialStore("no keyring".into()),
+                "Couldn't use the system keychain: no keyring; \
+                 set `credential_store: file` in your config to store credentials in plain text",
+            ),
+            (
+                Error::InvalidSites(vec![
+                    InvalidSite {
+                        code: String::from("supperuser"),
+                        suggestions: vec![String::from("superuser")],
+                        correction: Some(String::from("superuser")),
+                    },
+                    InvalidSite {
+                        code: String::from("xyzzy"),
+                        suggestions: vec![],
+                        correction: None,
+                    },
+                ]),
+                "supperuser is not a valid StackExchange site; did you mean `superuser`?\n\
+                 xyzzy is not a valid StackExchange site",
+            ),
+            (
+                Error::UnknownTeam(String::from("work")),
+                "No team named `work` in the `teams` section of your config",
+            ),
+            (
+                Error::ProjectDir,
+                "Couldn't find a suitable project directory; is your OS supported?",
+            ),
+            (
+                Error::NoResults,
+                "Sorry, couldn't find any answers to your question",
+            ),
+            (
+                Error::NoSuchAnswer {
+                    wanted: 3,
+                    found: 2,
+                },
+                "--answer 3 is out of range: the lucky question only has 2",
+            ),
+            (
+                Error::Timeout(Duration::from_secs(3)),
+                "Search timed out after 3s before any results came in",
+            ),
+            (
+                Error::SiteGone {
+                    site: String::from("windowsphone"),
+                    successor: None,
+                },
+                "site 'windowsphone' no longer exists \u{2014} remove it from your config",
+            ),
+            (
+                Error::SiteGone {
+                    site: String::from("programmers"),
+                    successor: Some(String::from("softwareengineering")),
+                },
+                "site 'programmers' no longer exists \u{2014} it moved to 'softwareengineering'; \
+                 use that in your config instead",
+            ),
+            (
+                Error::RequestTimeout {
+                    to: String::from("stackoverflow"),
+                    after: Duration::from_secs(10),
+                    setting: "timeout_secs",
+                },
+                "request to stackoverflow timed out after 10s; raise `timeout_secs` in your config",
+            ),
+            (
+                Error::Offline,
+                "This needs network access, which isn't allowed in offline mode",
+            ),
+            (
+                Error::NotCached(String::from("exit vim"), None),
+                "No cached results for `exit vim`; search for it without --offline first",
+            ),
+            (
+                Error::NotCached(
+                    String::from("exit vim"),
+                    Some(String::from("exiting vim")),
+                ),
+                "No cached results for `exit vim`; search for it without --offline first \
+                 (closest cached query: `exiting vim`)",
+            ),
+            (
+                Error::Backup(BackupError::Missing("manifest.json")),
+                "Couldn't import backup: archive is missing `manifest.json`",
+            ),
+            (
+                Error::UnrecognizedUrl(String::from("https://example.com")),
+                "Not a link to a question or answer on a known StackExchange site: \
+                 https://example.com",
+            ),
+            (
+                Error::UnknownHost(String::from("example.com")),
+                "No known StackExchange site is at example.com; \
+                 if it's new, run `so --update-sites` first",
+            ),
+            (
+                Error::PreprocessPass(String::from("trim-all")),
+                "Unknown preprocessing pass `trim-all` in `preprocess_passes`; \
+                 available are trim, kbd, comments and entities, \
+                 or none on its own to turn them all off",
+            ),
+            (
+                Error::ScoreThresholds([0, 10, 10, 1000]),
+                "`score_thresholds` in your config must be strictly increasing, \
+                 but are [0, 10, 10, 1000]",
+            ),
+            (
+                Error::MaxConcurrentRequests,
+                "`max_concurrent_requests` in your config must be at least 1",
+            ),
+            (
+                Error::ApiUrl(String::from("ftp://x"), String::from("not http")),
+                "Invalid `api_url` in your config, `ftp://x`: not http",
+            ),
+            (
+                Error::ApiVersion(String::from("two")),
+                "Invalid `api_version` in your config, `two`: expected a version like 2.3",
+            ),
+            (
+                Error::SyntaxTheme(String::from("neon"), String::from("InspiredGitHub")),
+                "Unknown `syntax_theme` `neon` in your config; available are auto, InspiredGitHub",
+            ),
+            (
+                Error::ExtraHeader(String::from("X Y"), String::from("invalid name")),
+                "Invalid header `X Y` in `extra_headers` in your config: invalid name",
+            ),
+            (
+                Error::LinkRewrite(String::from("so"), String::from("expected a bare host")),
+                "Invalid entry `so` in `link_rewrites` in your config: expected a bare host",
+            ),
+            (
+                Error::from(ParseError::Unmatched),
+                "Invalid `lucky_template` in your config: unmatched `}`; write `}}` for a literal brace",
+            ),
+            (
+                Error::FileNotFound(path()),
+                "No such file: `/tmp/so/sites.json`",
+            ),
+            (
+                Error::NotAFile(path()),
+                "`/tmp/so/sites.json` is not a file",
+            ),
+            (
+                Error::BinaryFile(path()),
+                "`/tmp/so/sites.json` looks like a binary file; only text files can be compared",
+            ),
+            (
+                Error::ImageTooLarge(String::from("https://i.sstatic.net/a.png"), 8 << 20),
+                "`https://i.sstatic.net/a.png` is over the 8 MiB images are capped at",
+            ),
+            (
+                Error::Browser {
+                    url: String::from("https://so.com"),
+                    source: not_found("xdg-open"),
+                },
+                "Couldn't open `https://so.com` in a browser: `xdg-open` couldn't run: not found",
+            ),
+            (
+                Error::Clipboard(vec![not_found("wl-copy"), not_found("xclip")]),
+                "Couldn't copy to the clipboard: `wl-copy` couldn't run: not found; \
+                 `xclip` couldn't run: not found",
+            ),
+            (
+                Error::Osc52(std::io::Error::other("broken pipe")),
+                "Couldn't copy to the clipboard via OSC 52: broken pipe",
+            ),
+            (
+                Error::NoEditor,
+                "Set $EDITOR to edit code, e.g. to `vim` or `code --wait`",
+            ),
+            (
+                Error::Editor(not_found("vim")),
+                "Couldn't edit the code: `vim` couldn't run: not found",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    fn not_found(command: &str) -> CommandError {
+        CommandError::Io {
+            command: command.to_string(),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "not found"),
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        let io = Error::from(std::io::Error::other("disk full"));
+        assert_eq!(io.source().unwrap().to_string(), "disk full");
+        let json = Error::from(serde_json::from_str::<u32>("x").unwrap_err());
+        assert!(json.source().unwrap().is::<serde_json::Error>());
+        // All the way down to the cause
+        let site = Error::at_site("superuser", io);
+        let inner = site.source().unwrap();
+        assert_eq!(inner.to_string(), "IO error: disk full");
+        assert_eq!(inner.source().unwrap().to_string(), "disk full");
+        assert!(Error::NoResults.source().is_none());
+        // Down to why a command failed
+        let editor = Error::Editor(not_found("vim"));
+        let command = editor.source().unwrap();
+        assert!(command.is::<CommandError>());
+        assert!(command.source().unwrap().is::<std::io::Error>());
+    }
+
+    #[test]
+    fn test_fields() {
+        let e = Error::at_site("work", api(Some("work"), Some("bad_parameter")));
+        assert_eq!(e.site(), Some("work"));
+        assert_eq!(e.endpoint(), Some("search/advanced"));
+        assert_eq!(e.status(), Some(400));
+        assert_eq!(e.path(), None);
+        let e = Error::at_site("superuser", Error::MalformedFile(PathBuf::from("a.json")));
+        assert_eq!(e.path(), Some(Path::new("a.json")));
+        assert_eq!(e.status(), None);
+        assert_eq!(Error::NoResults.site(), None);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(Error::NoResults.exit_code(), 2);
+        assert_eq!(Error::at_site("superuser", Error::NoResults).exit_code(), 2);
+        assert_eq!(api(None, None).exit_code(), 1);
+        assert_eq!(Error::Interrupted.exit_code(), 130);
+    }
+}
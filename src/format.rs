@@ -0,0 +1,274 @@
+//! Human readable formatting of numbers and dates, in one place so that every part of the
+//! output follows the configured `locale`.
+
+use serde::{Deserialize, Serialize};
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const MONTH: i64 = 30 * DAY;
+const YEAR: i64 = 365 * DAY;
+
+/// How numbers and dates are written
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// `1,234.5`, `10/16/2026`
+    #[default]
+    En,
+    /// `1.234,5`, `16.10.2026`
+    Eu,
+    /// Plain numbers and absolute ISO-8601 dates everywhere: `1234`, `2026-10-16`
+    Iso,
+}
+
+impl Locale {
+    /// Integer with thousands separators
+    pub fn number(self, n: i64) -> String {
+        let digits = n.unsigned_abs().to_string();
+        let separator = match self {
+            Locale::En => ",",
+            Locale::Eu => ".",
+            Locale::Iso => "",
+        };
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push_str(separator);
+            }
+            grouped.push(digit);
+        }
+        if n < 0 {
+            grouped.insert(0, '-');
+        }
+        grouped
+    }
+
+    /// Large counts such as reputation shortened to `12.3k` or `1.5m`; exact in `Iso`
+    pub fn abbreviated(self, n: u64) -> String {
+        let (value, suffix) = match n {
+            _ if self == Locale::Iso => return n.to_string(),
+            0..=9_999 => return self.number(n as i64),
+            10_000..=999_999 => (n as f64 / 1e3, "k"),
+            _ => (n as f64 / 1e6, "m"),
+        };
+        let value = format!("{:.1}", (value * 10.0).floor() / 10.0);
+        let value = value.trim_end_matches(".0");
+        match self {
+            Locale::Eu => format!("{}{}", value.replace('.', ","), suffix),
+            _ => format!("{}{}", value, suffix),
+        }
+    }
+
+    /// Absolute date of a unix `timestamp`
+    pub fn date(self, timestamp: i64) -> String {
+        let (year, month, day) = civil_from_days(timestamp.div_euclid(DAY));
+        match self {
+            Locale::En => format!("{:02}/{:02}/{}", month, day, year),
+            Locale::Eu => format!("{:02}.{:02}.{}", day, month, year),
+            Locale::Iso => format!("{}-{:02}-{:02}", year, month, day),
+        }
+    }
+
+    /// How long before `now` a unix `timestamp` was, e.g. "3 years ago"; an absolute date in
+    /// `Iso`. Timestamps slightly in the future (clock skew) count as "just now".
+    pub fn relative_date(self, timestamp: i64, now: i64) -> String {
+        if self == Locale::Iso {
+            return self.date(timestamp);
+        }
+        let elapsed = now - timestamp;
+        let (n, unit) = match elapsed {
+            _ if elapsed < MINUTE => return String::from("just now"),
+            _ if elapsed < HOUR => (elapsed / MINUTE, "minute"),
+            _ if elapsed < DAY => (elapsed / HOUR, "hour"),
+            _ if elapsed < MONTH => (elapsed / DAY, "day"),
+            _ if elapsed < YEAR => (elapsed / MONTH, "month"),
+            _ => (elapsed / YEAR, "year"),
+        };
+        let plural = if n == 1 { "" } else { "s" };
+        format!("{} {}{} ago", self.number(n), unit, plural)
+    }
+}
+
+/// RFC 3339 date and time in UTC of a unix `timestamp`, for machine readable output
+pub fn rfc3339(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(DAY));
+    let seconds = timestamp.rem_euclid(DAY);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / HOUR,
+        seconds % HOUR / MINUTE,
+        seconds % MINUTE
+    )
+}
+
+/// Lowest scores shown as plain, good, great and gold, in that order; anything below the first is
+/// negative. Set with `score_thresholds` in the config.
+pub type ScoreThresholds = [i32; 4];
+
+pub const DEFAULT_SCORE_THRESHOLDS: ScoreThresholds = [0, 10, 100, 1000];
+
+/// How prominently a score is shown, from red to gold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreStyle {
+    Negative,
+    Plain,
+    Good,
+    Great,
+    Gold,
+}
+
+/// Style of `score` according to `thresholds`
+pub fn score_style(score: i32, thresholds: &ScoreThresholds) -> ScoreStyle {
+    const STYLES: [ScoreStyle; 4] = [
+        ScoreStyle::Plain,
+        ScoreStyle::Good,
+        ScoreStyle::Great,
+        ScoreStyle::Gold,
+    ];
+    match thresholds.iter().rposition(|&threshold| score >= threshold) {
+        Some(ix) => STYLES[ix],
+        None => ScoreStyle::Negative,
+    }
+}
+
+/// Whether `thresholds` are strictly increasing, as they must be
+pub fn valid_score_thresholds(thresholds: &ScoreThresholds) -> bool {
+    thresholds.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+/// (year, month, day) of the given number of days since the unix epoch, per Howard Hinnant's
+/// [algorithm](http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-16T12:00:00Z
+    const NOW: i64 = 1_792_152_000;
+
+    #[test]
+    fn test_score_style() {
+        use ScoreStyle::*;
+        let thresholds = DEFAULT_SCORE_THRESHOLDS;
+        for (score, style) in [
+            (i32::MIN, Negative),
+            (-1, Negative),
+            (0, Plain),
+            (9, Plain),
+            (10, Good),
+            (99, Good),
+            (100, Great),
+            (999, Great),
+            (1000, Gold),
+            (i32::MAX, Gold),
+        ] {
+            assert_eq!(score_style(score, &thresholds), style, "{}", score);
+        }
+        // e.g. for a small site
+        let thresholds = [-2, 1, 5, 25];
+        assert_eq!(score_style(-1, &thresholds), Plain);
+        assert_eq!(score_style(-3, &thresholds), Negative);
+        assert_eq!(score_style(5, &thresholds), Great);
+        assert_eq!(score_style(25, &thresholds), Gold);
+    }
+
+    #[test]
+    fn test_valid_score_thresholds() {
+        assert!(valid_score_thresholds(&DEFAULT_SCORE_THRESHOLDS));
+        assert!(valid_score_thresholds(&[-5, -4, 0, 1]));
+        assert!(!valid_score_thresholds(&[0, 10, 10, 1000]));
+        assert!(!valid_score_thresholds(&[0, 100, 10, 1000]));
+    }
+
+    #[test]
+    fn test_number() {
+        assert_eq!(Locale::En.number(0), "0");
+        assert_eq!(Locale::En.number(999), "999");
+        assert_eq!(Locale::En.number(1000), "1,000");
+        assert_eq!(Locale::En.number(2_612_233), "2,612,233");
+        assert_eq!(Locale::En.number(-4123), "-4,123");
+        assert_eq!(Locale::Eu.number(2_612_233), "2.612.233");
+        assert_eq!(Locale::Eu.number(-4123), "-4.123");
+        assert_eq!(Locale::Iso.number(2_612_233), "2612233");
+    }
+
+    #[test]
+    fn test_abbreviated() {
+        assert_eq!(Locale::En.abbreviated(1), "1");
+        assert_eq!(Locale::En.abbreviated(9_999), "9,999");
+        assert_eq!(Locale::En.abbreviated(10_000), "10k");
+        assert_eq!(Locale::En.abbreviated(12_345), "12.3k");
+        assert_eq!(Locale::En.abbreviated(999_999), "999.9k");
+        assert_eq!(Locale::En.abbreviated(1_500_000), "1.5m");
+        assert_eq!(Locale::Eu.abbreviated(12_345), "12,3k");
+        assert_eq!(Locale::Eu.abbreviated(9_999), "9.999");
+        assert_eq!(Locale::Iso.abbreviated(1_500_000), "1500000");
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(Locale::Iso.date(0), "1970-01-01");
+        assert_eq!(Locale::Iso.date(NOW), "2026-10-16");
+        assert_eq!(Locale::Iso.date(951_782_400), "2000-02-29");
+        assert_eq!(Locale::Iso.date(-DAY), "1969-12-31");
+        assert_eq!(Locale::En.date(NOW), "10/16/2026");
+        assert_eq!(Locale::Eu.date(NOW), "16.10.2026");
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(NOW), "2026-10-16T12:00:00Z");
+        assert_eq!(
+            rfc3339(NOW + 3 * HOUR + 25 * MINUTE + 7),
+            "2026-10-16T15:25:07Z"
+        );
+        assert_eq!(rfc3339(-1), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_relative_date() {
+        let en = Locale::En;
+        assert_eq!(en.relative_date(NOW, NOW), "just now");
+        assert_eq!(en.relative_date(NOW - 59, NOW), "just now");
+        assert_eq!(en.relative_date(NOW - MINUTE, NOW), "1 minute ago");
+        assert_eq!(en.relative_date(NOW - 59 * MINUTE, NOW), "59 minutes ago");
+        assert_eq!(en.relative_date(NOW - HOUR, NOW), "1 hour ago");
+        assert_eq!(en.relative_date(NOW - 2 * DAY, NOW), "2 days ago");
+        assert_eq!(en.relative_date(NOW - MONTH, NOW), "1 month ago");
+        assert_eq!(en.relative_date(NOW - 3 * YEAR, NOW), "3 years ago");
+        assert_eq!(en.relative_date(NOW - 1500 * YEAR, NOW), "1,500 years ago");
+        assert_eq!(
+            Locale::Eu.relative_date(NOW - 1500 * YEAR, NOW),
+            "1.500 years ago"
+        );
+    }
+
+    #[test]
+    fn test_relative_date_clock_skew() {
+        assert_eq!(Locale::En.relative_date(NOW + 30, NOW), "just now");
+        assert_eq!(Locale::En.relative_date(NOW + 2 * HOUR, NOW), "just now");
+    }
+
+    #[test]
+    fn test_relative_date_iso() {
+        assert_eq!(Locale::Iso.relative_date(NOW - 3 * YEAR, NOW), "2023-10-17");
+        assert_eq!(Locale::Iso.relative_date(NOW + 30, NOW), "2026-10-16");
+    }
+}
@@ -1,7 +1,71 @@
+//! Search StackExchange sites from Rust, the way the `so` binary does.
+//!
+//! The public API is [`stackexchange::Search`], which runs queries against the configured sites
+//! with the configured search engine, [`stackexchange::LocalStorage`], the listing of sites it
+//! searches, the [`stackexchange::Question`]s and [`stackexchange::Answer`]s it returns,
+//! [`config::Config`], and [`error::Error`]. One `Search` runs as many queries as needed, each
+//! reporting on itself through its accessors until the next starts. Neither `Config::default()` nor
+//! `LocalStorage::from_sites` touch the filesystem, so a library user needs neither a config file
+//! nor the project directories the binary keeps its caches in.
+//!
+//! ```no_run
+//! use so::config::Config;
+//! use so::stackexchange::{LocalStorage, Search, Site};
+//!
+//! #[tokio::main]
+//! async fn main() -> so::error::Result<()> {
+//!     let sites = LocalStorage::from_sites(vec![Site {
+//!         api_site_parameter: String::from("stackoverflow"),
+//!         site_url: String::from("stackoverflow.com"),
+//!         ..Site::default()
+//!     }]);
+//!     let search = Search::new(Config::default(), sites);
+//!     for question in search.search("how to exit vim").await? {
+//!         println!("{} ({} answers)", question.title, question.answers.len());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The other modules make up the binary and aren't meant to be depended on. Its CLI, TUI and
+//! printed output are behind the `tui` feature, which is on by default; without it, as with
+//! `so = { version = "…", default-features = false }`, the library leaves out cursive and the
+//! other terminal crates.
+
+#[doc(hidden)]
+pub mod backup;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
 pub mod cli;
 pub mod config;
+#[doc(hidden)]
+pub mod credentials;
+#[doc(hidden)]
+pub mod doctor;
 pub mod error;
+#[doc(hidden)]
+pub mod format;
+pub mod messages;
+#[doc(hidden)]
+pub mod notes;
+#[doc(hidden)]
+pub mod notify;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod output;
+#[doc(hidden)]
+pub mod plain;
 pub mod stackexchange;
+#[doc(hidden)]
+pub mod template;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
 pub mod term;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
 pub mod tui;
+#[doc(hidden)]
 pub mod utils;
+
+#[cfg(test)]
+mod alloc_count;
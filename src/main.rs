@@ -1,48 +1,154 @@
-mod cli;
-mod config;
-mod error;
-mod stackexchange;
-mod term;
-mod tui;
-mod utils;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use tokio::runtime::Runtime;
 use tokio::task;
+use tokio_util::sync::CancellationToken;
 
-use config::Config;
-use error::{Error, Result};
-use stackexchange::{LocalStorage, Question, Search};
-use term::Term;
-use tui::markdown::Markdown;
+use so::{backup, cli, doctor, notify, output, stackexchange, term, tui, utils};
+
+use so::cli::{BackupCommand, CacheCommand, NotesCommand, UrlCommand};
+use so::config::Config;
+use so::error::{Error, Result};
+use so::format::Locale;
+use so::notes::Notes;
+use so::notify::Notifier;
+use so::output::{FormatOpts, OutputFormatter, SearchResults};
+use so::stackexchange::aliases::Aliases;
+use so::stackexchange::backoff::Backoff;
+use so::stackexchange::blocks::BlockLog;
+use so::stackexchange::filtered::Filtered;
+use so::stackexchange::filters::Filters;
+use so::stackexchange::latency::Latency;
+use so::stackexchange::lucky_memory::{LuckyMemory, Remembered};
+use so::stackexchange::query_cache::{self, QueryCache};
+use so::stackexchange::question_cache::QuestionCache;
+use so::stackexchange::quota::{Quota, Reading};
+use so::stackexchange::timings::Timings;
+use so::stackexchange::urls::LinkStyle;
+use so::stackexchange::{
+    context, relevance, scraper, urls, Api, Destination, LocalStorage, OfflinePolicy, Question,
+    Search, Timeouts,
+};
+use so::template::Template;
+use so::term::Term;
+use so::tui::markdown::Markdown;
+
+/// Set once `notify_on_complete` replaced the terminal title, which exiting restores
+static TITLE_SET: AtomicBool = AtomicBool::new(false);
 
 fn main() -> Result<()> {
     // Tokio runtime
     Runtime::new()?
         .block_on(run())
-        .and_then(|qs| {
+        .map(|next| match next {
             // Run TUI
-            qs.map(tui::run);
-            Ok(())
+            Next::Tui(qs, query, config, search) => {
+                let _ = tui::run(qs, &query, *config, *search);
+                restore_title();
+            }
+            Next::Exit(0) => restore_title(),
+            Next::Exit(code) => {
+                restore_title();
+                std::process::exit(code)
+            }
         })
         .or_else(|e: Error| {
             // Handle errors
-            term::print_error(&e.to_string())
+            restore_title();
+            term::print_error(&e.to_string())?;
+            std::process::exit(e.exit_code())
         })
 }
 
-/// Runs the CLI and, if the user wishes to enter the TUI, returns
-/// question/answer data
-async fn run() -> Result<Option<Vec<Question<Markdown>>>> {
+/// Let the user know `results` results are ready, if the search since `start` took long enough
+fn notify_complete(notifier: &Notifier, start: Instant, results: usize) -> Result<()> {
+    if notifier.complete(&mut std::io::stderr(), start.elapsed(), results)? {
+        TITLE_SET.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Bring back the terminal title `notify_complete` replaced, if it did
+fn restore_title() {
+    if TITLE_SET.load(Ordering::Relaxed) {
+        let _ = notify::restore_title(&mut std::io::stderr());
+    }
+}
+
+/// What's left to do once the CLI has run
+enum Next {
+    /// Exit with this code
+    Exit(i32),
+    /// Enter the TUI with the question/answer data, including questions filters left out, and the
+    /// query that found it, along with the search that did, to search again from the TUI
+    Tui(Filtered<Markdown>, String, Box<Config>, Box<Search>),
+}
+
+/// Runs the CLI and, if the user wishes to enter the TUI, returns the data for it
+async fn run() -> Result<Next> {
     // Get CLI opts
-    let opts = cli::get_opts()?;
-    let config = opts.config;
-    let sites = &config.sites;
+    let opts = match cli::get_opts() {
+        Ok(opts) => opts,
+        Err(cli::OptsError::Other(e)) => return Err(e),
+        Err(e) => {
+            term::print_error(&e.to_string())?;
+            return Ok(Next::Exit(1));
+        }
+    };
+    let mut config = opts.config;
     let lucky = config.lucky;
 
     // Term tools and markdown styles (outside of TUI)
     let mut term = Term::new();
 
-    let ls = LocalStorage::new(opts.update_sites).await?;
+    for warning in &opts.warnings {
+        term.print_notice(&format!("{}\n\n", warning))?;
+    }
+
+    if let Some(backup) = opts.backup {
+        run_backup(&mut term, backup)?;
+        return Ok(Next::Exit(0));
+    }
+
+    if let Some(notes) = opts.notes {
+        run_notes(&mut term, notes, config.locale)?;
+        return Ok(Next::Exit(0));
+    }
+
+    if opts.doctor {
+        run_doctor(&mut term).await?;
+        return Ok(Next::Exit(0));
+    }
+
+    if opts.clear_cache {
+        let cleared = QueryCache::open()?.clear()?;
+        term.print(&format!("Forgot {} cached searches", cleared));
+        return Ok(Next::Exit(0));
+    }
+
+    if opts.quota {
+        run_quota(&mut term, &config).await?;
+        return Ok(Next::Exit(0));
+    }
+
+    if let Some(CacheCommand::Reindex) = opts.cache {
+        let index = QueryCache::open()?.reindex(config.query_language)?;
+        term.print(&format!(
+            "Indexed {} questions from {} cached searches",
+            index.questions(),
+            index.searches()
+        ));
+        return Ok(Next::Exit(0));
+    }
+
+    let ls = LocalStorage::new(opts.update_sites, &config).await?;
+
+    if let Some(url) = opts.url {
+        run_url(url, &ls, &config).await?;
+        return Ok(Next::Exit(0));
+    }
 
     if let Some(key) = opts.set_api_key {
         Config::set_api_key(key)?;
@@ -52,51 +158,729 @@ async fn run() -> Result<Option<Vec<Question<Markdown>>>> {
         println!("{}", Config::config_file_path()?.display());
     }
 
-    if opts.list_sites {
-        let mut md = String::new();
-        md.push_str("|:-:|:-:|\n");
-        md.push_str("|Site Code|Site URL|\n");
-        md.push_str("|-:|:-|\n");
-        for s in ls.sites.iter() {
-            md.push_str(&format!("|{}|{}\n", s.api_site_parameter, s.site_url));
+    if opts.output.as_deref() == Some("list") {
+        term.print(&output::list());
+        return Ok(Next::Exit(0));
+    }
+
+    if let Some(filter) = &opts.list_sites {
+        let sites = ls.matching(filter);
+        let mut stdout = std::io::stdout();
+        if opts.json {
+            output::Json::sites(&sites, &mut stdout)?;
+        } else {
+            output::sites(&sites, &mut stdout)?;
         }
-        md.push_str("|-\n");
-        term.print(&md);
-        return Ok(None);
+        return Ok(Next::Exit(0));
     }
 
-    if let Some(site) = ls.find_invalid_site(sites).await {
-        term.print_error(&format!("{} is not a valid StackExchange site.\n\n", site))?;
-        term.print_notice(
-            "If you think this is incorrect, try running\n\
-                ```\n\
-                so --update-sites\n\
-                ```\n\
-                to update the cached site listing. \
-                You can also run `so --list-sites` to list all available sites.",
-        )?;
-        return Ok(None);
+    // Teams aren't in the public site listing
+    let invalid_sites = match config.team {
+        Some(_) => Vec::new(),
+        None => ls.find_invalid_sites(&config.sites),
+    };
+    if !invalid_sites.is_empty() {
+        // Only ask someone who can answer
+        let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+        let fix_sites = opts.fix_sites;
+        let corrected =
+            stackexchange::correct_sites(&config.sites, &invalid_sites, |site, fix| {
+                match (fix_sites, interactive) {
+                    (true, _) => {
+                        term.print_notice(&format!(
+                            "Searching `{}` instead of `{}`\n\n",
+                            fix, site.code
+                        ))?;
+                        Ok(true)
+                    }
+                    (false, true) => Term::confirm(&format!(
+                        "{} is not a valid StackExchange site; search `{}` instead?",
+                        site.code, fix
+                    )),
+                    (false, false) => Ok(false),
+                }
+            })?;
+        match corrected {
+            Some(sites) => config.sites = sites,
+            None => {
+                let e = Error::InvalidSites(invalid_sites);
+                term.print_error(&format!("{}\n\n", e))?;
+                term.print_notice(
+                    "If you think this is incorrect, try running\n\
+                        ```\n\
+                        so --update-sites\n\
+                        ```\n\
+                        to update the cached site listing. \
+                        You can also run `so --list-sites` to list all available sites.",
+                )?;
+                return Ok(Next::Exit(e.exit_code()));
+            }
+        }
     }
 
     if let Some(q) = opts.query {
-        let mut search = Search::new(config, ls, q);
+        let blocks = BlockLog::open(config.ddg_fallback)?;
+        // Teams are always searched via the API, and the cache remembers which engine it was
+        if config.team.is_none() && !config.offline {
+            let (engine, notify) = blocks.engine(
+                &config.search_engine,
+                opts.search_engine_given,
+                stackexchange::blocks::now(),
+            );
+            if let Some(until) = notify {
+                term.print_notice(&format!("{}\n\n", blocks.notice(until)))?;
+            }
+            config.search_engine = engine;
+        }
+        let limit = config.limit;
+        let lucky_confidence = config.lucky_confidence;
+        let query_language = config.query_language;
+        let strict_lucky = config.strict_lucky;
+        let locale = config.locale;
+        let context = match (config.context_detection, opts.no_context) {
+            (true, false) => std::env::current_dir()
+                .ok()
+                .and_then(|dir| context::detect(&dir)),
+            _ => None,
+        };
+        // Nobody to notify when the output goes to a script or a file
+        let notifier = Notifier::from_config(
+            &config,
+            std::io::stdout().is_terminal() && std::io::stderr().is_terminal(),
+        );
+        let search = Search::new(config.clone(), ls)
+            .with_context(context)
+            .with_block_log(blocks)
+            .with_backoff(Backoff::open()?)
+            .with_quota(Quota::open()?)
+            .with_latency(Latency::open()?)
+            .with_filters(Filters::open()?)
+            .with_max_time(opts.max_time)
+            .with_cache(QueryCache::open()?)
+            .with_full_text(opts.full_text)
+            .with_direct_ids(opts.site_given)
+            .with_question_cache(QuestionCache::open()?.refresh(opts.refresh))
+            .with_aliases(Aliases::open()?);
+        let start = Instant::now();
+        let format_opts = FormatOpts {
+            width: Some(
+                opts.width
+                    .unwrap_or_else(|| termimad::terminal_size().0 as usize),
+            ),
+            locale,
+            color: FormatOpts::color_enabled(std::io::stdout().is_terminal()),
+            lucky_template: Template::parse(&config.lucky_template)?,
+            lucky_header: config.lucky_header,
+            lucky_attribution: config.lucky_attribution,
+            score_thresholds: config.score_thresholds,
+            link_style: config.link_style,
+            show_spoilers: opts.show_spoilers,
+            ascii_only: config.ascii_only,
+        };
+        // this unwrap is safe via clap validator
+        if let Some(formatter) = opts.output.as_deref().map(|o| output::find(o).unwrap()) {
+            let search = search.with_question_bodies(formatter.question_bodies());
+            let questions = match Term::wrap_spinner(search.search(&q)).await? {
+                Err(e) if opts.json => return print_json_error(&e),
+                questions => questions?,
+            };
+            notify_complete(&notifier, start, questions.len())?;
+            warn_all(&mut term, &search, locale)?;
+            if opts.verbose {
+                print_verbose(&search, &config, &q, &questions);
+            }
+            let timings = opts.timings.then(|| print_timings(&search, start));
+            let results = SearchResults {
+                query: q.clone(),
+                questions,
+                site_urls: search.site_urls(),
+                timings,
+            };
+            if opts.pick {
+                let commands = output::top_commands(&results);
+                if commands.is_empty() {
+                    term.print_notice("The top answer has no command lines to pick from\n")?;
+                } else {
+                    for i in Term::pick(&commands)? {
+                        println!("{}", commands[i]);
+                    }
+                }
+            } else {
+                formatter.format(&results, &format_opts, &mut std::io::stdout())?;
+            }
+            return Ok(Next::Exit(0));
+        }
         if lucky {
             // Show top answer
-            let md = Term::wrap_spinner(search.search_lucky()).await??;
-            term.print(&md);
-            term.print("\nPress **[SPACE]** to see more results, or any other key to exit");
+            // The lucky answer is shown without its question, unlike in the TUI behind it
+            let mut lucky_search = search
+                .clone()
+                .with_question_bodies(output::Lucky.question_bodies())
+                .with_lucky_answer(opts.answer.unwrap_or(1));
+            let memory = LuckyMemory::open()?;
+            if opts.forget && memory.forget(&q)? {
+                term.print_notice("Forgot the answer remembered for this query\n\n")?;
+            }
+            // An answer picked with --answer wins over the remembered one
+            let remembered = match (config.lucky_memory, opts.answer) {
+                (true, None) => memory.get(&q),
+                _ => None,
+            };
+            let remembered = match remembered {
+                Some(remembered) => {
+                    match Term::wrap_spinner(lucky_search.remembered(&remembered)).await? {
+                        Ok(Some(question)) => Some(question),
+                        // Gone since, so back to searching
+                        Ok(None) => {
+                            memory.forget(&q)?;
+                            None
+                        }
+                        // Might be back later
+                        Err(_) => None,
+                    }
+                }
+                None => None,
+            };
+            let from_memory = remembered.is_some();
+            let mut lucky_answer = match remembered {
+                Some(question) => question,
+                None => match Term::wrap_spinner(lucky_search.search_lucky(&q)).await? {
+                    Err(e) if opts.json => return print_json_error(&e),
+                    answer => answer?,
+                },
+            };
+            notify_complete(&notifier, start, 1)?;
+            let picked = Remembered::of(&lucky_answer);
+            if let (true, Some(_), Some(picked)) = (config.lucky_memory, opts.answer, &picked) {
+                memory.remember(&q, picked.clone())?;
+            }
+            warn_all(&mut term, &search, locale)?;
+            if opts.verbose {
+                print_verbose(&search, &config, &q, std::slice::from_ref(&lucky_answer));
+            }
+            let timings = opts.timings.then(|| print_timings(&search, start));
+            // Titles match the query as it was searched for, typos corrected
+            let searched = search.normalized().map_or_else(|| q.clone(), |n| n.query);
+            // The remembered answer was picked for this query, however well its title matches
+            if from_memory {
+                term.print_notice("(remembered) — run with --forget to search again\n\n")?;
+            } else if !search.is_direct(&q)
+                && relevance::score(&searched, &lucky_answer.title, query_language)
+                    < lucky_confidence
+            {
+                if strict_lucky && opts.json {
+                    return print_json_error(&Error::NoResults);
+                }
+                if strict_lucky {
+                    return Err(Error::NoResults);
+                }
+                term.print_notice(&format!(
+                    "low confidence match: '{}' — run without --lucky to review {} other results\n\n",
+                    lucky_answer.title,
+                    locale.number(limit.saturating_sub(1).into())
+                ))?;
+            }
+            let exit_code = stackexchange::lucky_exit_code(&lucky_answer);
+            if opts.open {
+                let results = SearchResults {
+                    query: q.clone(),
+                    questions: vec![lucky_answer],
+                    site_urls: search.site_urls(),
+                    timings: None,
+                };
+                utils::open_browser(&lucky_link(&results).ok_or(Error::NoResults)?)?;
+                return Ok(Next::Exit(exit_code));
+            }
+            if opts.json {
+                let results = SearchResults {
+                    query: q.clone(),
+                    questions: vec![lucky_answer],
+                    site_urls: search.site_urls(),
+                    timings: None,
+                };
+                let questions = results.questions_linked(config.link_style);
+                if let Some(answer) = questions.first().and_then(|q| q.answers.first()) {
+                    output::Json::answer(answer, timings.as_ref(), &mut std::io::stdout())?;
+                }
+                return Ok(Next::Exit(exit_code));
+            }
+            if config.tl_dr.lucky {
+                search.add_tl_drs(&q, std::slice::from_mut(&mut lucky_answer));
+            }
+            let results = SearchResults {
+                query: q.clone(),
+                questions: vec![lucky_answer],
+                site_urls: search.site_urls(),
+                timings: None,
+            };
+            output::Lucky.format(&results, &format_opts, &mut std::io::stdout())?;
+            match config.lucky_memory {
+                true => term.print(
+                    "\nPress **[SPACE]** to see more results, **[r]** to remember this answer \
+                     for the query, or any other key to exit",
+                ),
+                false => {
+                    term.print("\nPress **[SPACE]** to see more results, or any other key to exit")
+                }
+            }
 
             // Kick off the rest of the search in the background
-            let qs = task::spawn(async move { search.search_md().await });
-            if !Term::wait_for_char(' ')? {
-                return Ok(None);
+            let cancel = CancellationToken::new();
+            let background = search.clone().with_cancellation(cancel.clone());
+            let query = q.clone();
+            let mut qs = task::spawn(async move { background.search_md(&query).await });
+            match Term::read_char()? {
+                Some(' ') => (),
+                key => {
+                    // No need for the rest after all
+                    cancel.cancel();
+                    if let (true, Some('r'), Some(picked)) = (config.lucky_memory, key, picked) {
+                        memory.remember(&q, picked)?;
+                        term.print_notice("Remembered this answer for the query\n")?;
+                    }
+                    return Ok(Next::Exit(exit_code));
+                }
+            }
+
+            // Get the rest of the questions, aborting the task if that's interrupted. Only the
+            // wait counts towards notifying, not the time spent reading the lucky answer.
+            let waiting = Instant::now();
+            let qs = match Term::wrap_spinner(&mut qs).await {
+                Err(e) => {
+                    qs.abort();
+                    return Err(e);
+                }
+                Ok(qs) => qs.unwrap()?,
+            };
+            notify_complete(&notifier, waiting, qs.shown.len())?;
+            return Ok(Next::Tui(qs, q.clone(), Box::new(config), Box::new(search)));
+        } else {
+            let qs = Term::wrap_spinner(search.search_md(&q)).await??;
+            notify_complete(&notifier, start, qs.shown.len())?;
+            warn_all(&mut term, &search, locale)?;
+            if opts.verbose {
+                print_verbose(&search, &config, &q, &qs.shown);
+            }
+            if opts.timings {
+                print_timings(&search, start);
+            }
+            return Ok(Next::Tui(qs, q.clone(), Box::new(config), Box::new(search)));
+        }
+    }
+    Ok(Next::Exit(0))
+}
+
+/// Report `error` as a JSON object on stdout for `--json`, exiting with its code all the same
+fn print_json_error(error: &Error) -> Result<Next> {
+    output::Json::error(error, &mut std::io::stdout())?;
+    Ok(Next::Exit(error.exit_code()))
+}
+
+/// Export or import user data
+fn run_backup(term: &mut Term, command: BackupCommand) -> Result<()> {
+    let locations = backup::Locations::new()?;
+    let (verb, archive, manifest) = match command {
+        BackupCommand::Export(archive) => {
+            let manifest = backup::export(&locations, &archive)?;
+            ("Exported", archive, manifest)
+        }
+        BackupCommand::Import(archive, mode) => {
+            let manifest = backup::import(&locations, &archive, mode)?;
+            ("Imported", archive, manifest)
+        }
+    };
+    let components: Vec<&str> = manifest.components.keys().map(String::as_str).collect();
+    term.print_notice(&format!(
+        "{} {} ({})\n",
+        verb,
+        archive.display(),
+        components.join(", ")
+    ))
+}
+
+/// Print a single line about the link, without any styling, for `$(so url ...)`
+async fn run_url(command: UrlCommand, ls: &LocalStorage, config: &Config) -> Result<()> {
+    let input = match &command {
+        UrlCommand::Id(input) | UrlCommand::Canonical(input) | UrlCommand::Site(input) => input,
+    };
+    let unrecognized = || Error::UnrecognizedUrl(input.clone());
+    let link = urls::classify(input).ok_or_else(unrecognized)?;
+    let site = ls.site_by_host(&link.host).ok_or_else(unrecognized)?;
+    let site_code = &site.api_site_parameter;
+    if let UrlCommand::Site(_) = command {
+        println!("{}", site_code);
+        return Ok(());
+    }
+    let id = match link.post {
+        urls::Post::Question(id) => id,
+        // Share links to answers don't say which question they're on
+        urls::Post::Answer(id) => api(config)
+            .answer_question_id(site_code, id)
+            .await?
+            .ok_or_else(unrecognized)?,
+    };
+    match command {
+        UrlCommand::Canonical(_) => {
+            let canonical = urls::canonical(&site.site_url, id);
+            println!("{}", config.link_rewrites.url(&canonical))
+        }
+        _ => println!("{} {}", site_code, id),
+    }
+    Ok(())
+}
+
+/// Report how much of the API quota is left, going by a request to the first configured site;
+/// offline, by the last reading kept
+async fn run_quota(term: &mut Term, config: &Config) -> Result<()> {
+    let quota = Quota::open()?;
+    let reading = if config.offline {
+        quota.last()
+    } else {
+        let api = api(config).with_quota(quota);
+        let site = config.sites.first().map_or("stackoverflow", String::as_str);
+        Term::wrap_spinner(api.check_quota(site)).await??
+    };
+    match reading {
+        Some(reading) => {
+            let mut report = quota_report(&reading, config.locale);
+            if config.offline {
+                let now = stackexchange::backoff::now() / 1000;
+                report.push_str(&format!(
+                    ", as of {}",
+                    config.locale.relative_date(reading.at / 1000, now)
+                ));
+            }
+            term.print(&report);
+        }
+        None => term.print("StackExchange didn't say how much of the quota is left"),
+    }
+    Ok(())
+}
+
+/// Client for one-off API requests outside of a search
+fn api(config: &Config) -> Api {
+    Api::new(
+        config.api_key.clone(),
+        config.filter.clone(),
+        OfflinePolicy::from(config),
+    )
+    .with_api_url(&config.api_url)
+    .with_api_version(&config.api_version)
+    .with_extra_headers(config.headers_for(Destination::StackExchange))
+    .with_timeouts(Timeouts::from(config))
+}
+
+/// E.g. "42 of 300 StackExchange API requests left today"
+fn quota_report(reading: &Reading, locale: Locale) -> String {
+    format!(
+        "{} of {} StackExchange API requests left today",
+        locale.number(reading.remaining.into()),
+        locale.number(reading.max.into())
+    )
+}
+
+/// Check each stage of connecting to the hosts searches depend on
+async fn run_doctor(term: &mut Term) -> Result<()> {
+    let checks = Term::wrap_spinner(doctor::check_all(
+        &doctor::NetProbe::default(),
+        doctor::HOSTS,
+    ))
+    .await?;
+    term.print(&doctor::report(&checks, doctor::proxy().as_deref()));
+    Ok(())
+}
+
+/// List, delete or prune notes on questions
+fn run_notes(term: &mut Term, command: NotesCommand, locale: Locale) -> Result<()> {
+    let path = Notes::path()?;
+    let mut notes = Notes::load(&path)?;
+    if notes.migrate(&Aliases::open()?) {
+        notes.save(&path)?;
+    }
+    match command {
+        NotesCommand::List => {
+            let mut md = String::new();
+            md.push_str("|:-:|:-:|:-:|:-:|\n");
+            md.push_str("|Site|Id|Question|Note|\n");
+            md.push_str("|-:|-:|:-|:-|\n");
+            for n in notes.list() {
+                md.push_str(&format!(
+                    "|{}|{}|{}|{} ({})\n",
+                    n.site,
+                    n.question_id,
+                    n.title,
+                    n.text,
+                    locale.date(n.updated_at)
+                ));
             }
+            md.push_str("|-\n");
+            term.print(&md);
+            Ok(())
+        }
+        NotesCommand::Delete(site, id) => {
+            if !notes.delete(&site, id) {
+                return term.print_error(&format!("No note on question {} on {}\n", id, site));
+            }
+            notes.save(&path)?;
+            term.print_notice(&format!("Deleted note on question {} on {}\n", id, site))
+        }
+        NotesCommand::Prune(days) => {
+            let pruned = notes.prune(Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+            notes.save(&path)?;
+            term.print_notice(&format!("Pruned {} notes\n", pruned.len()))
+        }
+    }
+}
+
+/// Tell the user everything worth knowing about how `search` went, before its results
+fn warn_all(term: &mut Term, search: &Search, locale: Locale) -> Result<()> {
+    print_offline_banner(term, search, locale)?;
+    warn_filter_degraded(term, search)?;
+    print_code_search_stats(term, search, locale)?;
+    warn_partial(term, search)?;
+    warn_site_failures(term, search)?;
+    warn_skipped_items(term, search)?;
+    warn_backoff(term, search)?;
+    warn_quota(term, search, locale)?;
+    warn_scraper_fallback(term, search)
+}
+
+/// Make it clear that offline results may be out of date
+fn print_offline_banner(term: &mut Term, search: &Search, locale: Locale) -> Result<()> {
+    if let Some(fetched_at) = search.offline_since() {
+        term.print_notice(&format!(
+            "{}\n\n",
+            query_cache::offline_banner(fetched_at, locale)
+        ))?;
+    }
+    Ok(())
+}
+
+/// Let the user know if SE rejected our API filter, since only a new release can fix that
+fn warn_filter_degraded(term: &mut Term, search: &Search) -> Result<()> {
+    if search.filter_degraded() {
+        term.print_error(
+            "The StackExchange API no longer accepts the filter used by this version of `so`, \
+            so results may be missing formatting. Please upgrade `so`.\n\n",
+        )?;
+    }
+    Ok(())
+}
+
+/// Let the user know if the StackExchange API throttled requests, which otherwise only shows as
+/// slow or missing results
+fn warn_backoff(term: &mut Term, search: &Search) -> Result<()> {
+    for notice in search.backoff_notices() {
+        term.print_notice(&format!("{}\n\n", notice))?;
+    }
+    Ok(())
+}
+
+/// Let the user know when the API quota is running low, before requests start failing
+fn warn_quota(term: &mut Term, search: &Search, locale: Locale) -> Result<()> {
+    if let Some(reading) = search.low_quota() {
+        term.print_notice(&format!(
+            "Only {}; set an API key of your own with `so --set-api-key` for a quota of your own\n\n",
+            quota_report(&reading, locale)
+        ))?;
+    }
+    Ok(())
+}
+
+/// Let the user know if `--max-time` cut the search short
+fn warn_partial(term: &mut Term, search: &Search) -> Result<()> {
+    if search.partial() {
+        term.print_notice(
+            "--max-time reached before all sites responded; results are partial\n\n",
+        )?;
+    }
+    Ok(())
+}
+
+/// Let the user know which sites failed, or no longer exist, and are missing from the results
+fn warn_site_failures(term: &mut Term, search: &Search) -> Result<()> {
+    for failure in search.site_failures() {
+        term.print_notice(&format!(
+            "Couldn't search {}; showing the other sites' results\n\n",
+            failure
+        ))?;
+    }
+    for gone in search.gone_sites() {
+        term.print_notice(&format!("{}\n\n", gone))?;
+    }
+    Ok(())
+}
+
+/// Let the user know if questions were left out for coming back malformed from the API
+fn warn_skipped_items(term: &mut Term, search: &Search) -> Result<()> {
+    let skipped = search.skipped_items().len();
+    if skipped > 0 {
+        term.print_notice(&format!(
+            "Left out {} malformed {} the StackExchange API returned; run with -v to see which\n\n",
+            skipped,
+            if skipped == 1 { "item" } else { "items" }
+        ))?;
+    }
+    Ok(())
+}
+
+/// Let the user know if DuckDuckGo results were only found by a fallback selector, since that
+/// means DuckDuckGo changed its markup and the results may be incomplete
+fn warn_scraper_fallback(term: &mut Term, search: &Search) -> Result<()> {
+    match search.scraper_strategy() {
+        Some(strategy) if scraper::is_fallback(&strategy) => term.print_notice(&format!(
+            "DuckDuckGo results were found with the {} fallback selector; its layout may have \
+            changed, so consider upgrading `so`\n\n",
+            strategy
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Let the user know how much the `--code-search` filter narrowed down the results
+fn print_code_search_stats(term: &mut Term, search: &Search, locale: Locale) -> Result<()> {
+    if let Some(stats) = search.code_search_stats() {
+        term.print_notice(&format!(
+            "code search: {} of {} questions have the query in an answer's code\n\n",
+            locale.number(stats.matched as i64),
+            locale.number(stats.questions as i64)
+        ))?;
+    }
+    Ok(())
+}
+
+/// Print everything `--verbose` adds on how `search` for `q` went to stderr, down to where each
+/// of `questions` came from
+fn print_verbose<S>(search: &Search, config: &Config, q: &str, questions: &[Question<S>]) {
+    print_render_failures(search);
+    print_skipped_items(search);
+    print_backfill(search);
+    print_early_stop(search);
+    print_normalized(search);
+    print_requote(search);
+    print_context(search, q);
+    print_extra_headers(config);
+    print_provenance(questions);
+}
+
+/// List the questions and answers that failed to render and are shown as plain text instead
+fn print_render_failures(search: &Search) {
+    for failure in search.render_failures() {
+        eprintln!("{}", failure);
+    }
+}
+
+/// List the malformed items left out of API responses, with their question ids where known
+fn print_skipped_items(search: &Search) {
+    for skip in search.skipped_items() {
+        eprintln!("{}", skip);
+    }
+}
 
-            // Get the rest of the questions
-            return Ok(Some(Term::wrap_spinner(qs).await?.unwrap()?));
+/// Say which sites were asked for another page since filters left too few questions, and how
+/// many that added
+fn print_backfill(search: &Search) {
+    if let Some(backfill) = search.backfilled() {
+        eprintln!(
+            "backfill: fetched page 2 of {}, which added {} questions",
+            backfill.sites.join(", "),
+            backfill.added
+        );
+    }
+}
+
+/// Name the sites `early_stop` stopped waiting for, if any
+fn print_early_stop(search: &Search) {
+    let skipped = search.early_stopped();
+    if !skipped.is_empty() {
+        eprintln!(
+            "early stop: skipped {}, with plenty of results from the others",
+            skipped.join(", ")
+        );
+    }
+}
+
+/// Say what `normalize_query` searched for instead of the query as typed, and which typos it
+/// corrected
+fn print_normalized(search: &Search) {
+    if let Some(normalized) = search.normalized() {
+        let corrections: Vec<String> = normalized
+            .corrections
+            .iter()
+            .map(|(typo, fix)| format!("{} \u{2192} {}", typo, fix))
+            .collect();
+        let corrected = if corrections.is_empty() {
+            String::new()
         } else {
-            return Ok(Some(Term::wrap_spinner(search.search_md()).await??));
+            format!(", corrected {}", corrections.join(", "))
+        };
+        eprintln!(
+            "normalized query: searched for `{}`{}",
+            normalized.query, corrected
+        );
+    }
+}
+
+/// Say whether `auto_requote` retried the search with the query quoted, and which results it kept
+fn print_requote(search: &Search) {
+    if let Some(requote) = search.requote() {
+        let quoted = match requote.quoted_relevance {
+            Some(r) => format!("{:.2}", r),
+            None => String::from("failed"),
+        };
+        eprintln!(
+            "requote: results scored {:.2}, quoted query scored {}; kept the {} results",
+            requote.relevance,
+            quoted,
+            if requote.kept_quoted() {
+                "quoted"
+            } else {
+                "original"
+            }
+        );
+    }
+}
+
+/// Link to the lucky answer, or else to its question
+fn lucky_link(results: &SearchResults) -> Option<String> {
+    let q = results.questions.first()?;
+    q.answers
+        .first()
+        .and_then(|a| a.link.clone())
+        .or_else(|| q.link.clone())
+        .or_else(|| results.question_url(q, LinkStyle::Full).map(String::from))
+}
+
+/// Say what the project the search for `query` was run in added to it
+fn print_context(search: &Search, query: &str) {
+    if let Some(added) = search.context_added(query) {
+        eprintln!("context: {}", added);
+    }
+}
+
+/// Say which extra headers were sent along, without their values
+fn print_extra_headers(config: &Config) {
+    if !config.extra_headers.is_empty() {
+        eprintln!("extra headers: {}", config.extra_headers);
+    }
+}
+
+/// Print which engine found each question, and where, to stderr
+fn print_provenance<S>(qs: &[Question<S>]) {
+    for q in qs {
+        if let Some(provenance) = &q.provenance {
+            eprintln!("provenance: question {}: {}", q.id, provenance);
         }
     }
-    Ok(None)
+}
+
+/// Print the timing breakdown of the search so far to stderr, returning it for `--json` to include
+fn print_timings(search: &Search, start: Instant) -> Timings {
+    let mut timings = search.timings();
+    timings.total = Some(start.elapsed());
+    eprint!("{}", timings);
+    if let Some(strategy) = search.scraper_strategy() {
+        eprintln!("scraper strategy: {}", strategy);
+    }
+    timings
 }
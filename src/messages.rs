@@ -0,0 +1,137 @@
+//! Warnings and errors collected over a session, for the TUI message console.
+//!
+//! The search pipeline pushes whatever it worked around (partial results, fallback selectors,
+//! posts that failed to render) onto a shared `Messages` handle, and the TUI lists them on
+//! demand. Messages stay unread until the console has shown them, and a message identical to
+//! one already collected is dropped, so that retries don't flood the console.
+
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How bad a message is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A message collected during the session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Unix timestamp of when it was collected
+    pub at: i64,
+    pub severity: Severity,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    messages: Vec<Message>,
+    /// Number of messages already shown
+    read: usize,
+}
+
+/// Shared handle to the messages of a session; clones push to and read from the same messages
+#[derive(Debug, Clone, Default)]
+pub struct Messages(Arc<Mutex<Inner>>);
+
+impl Messages {
+    pub fn warn(&self, text: impl Into<String>) {
+        self.push(Severity::Warning, text.into(), now());
+    }
+
+    pub fn error(&self, text: impl Into<String>) {
+        self.push(Severity::Error, text.into(), now());
+    }
+
+    fn push(&self, severity: Severity, text: String, at: i64) {
+        let mut inner = self.lock();
+        if inner
+            .messages
+            .iter()
+            .any(|m| m.severity == severity && m.text == text)
+        {
+            return;
+        }
+        inner.messages.push(Message { at, severity, text });
+    }
+
+    /// Number of messages not shown yet, and the worst severity among them
+    pub fn unread(&self) -> Option<(usize, Severity)> {
+        let inner = self.lock();
+        let unread = &inner.messages[inner.read..];
+        let worst = unread.iter().map(|m| m.severity).max()?;
+        Some((unread.len(), worst))
+    }
+
+    /// Everything collected so far, oldest first, marking it as read
+    pub fn read(&self) -> Vec<Message> {
+        let mut inner = self.lock();
+        inner.read = inner.messages.len();
+        inner.messages.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unread() {
+        let messages = Messages::default();
+        assert_eq!(messages.unread(), None);
+
+        messages.warn("results are partial");
+        assert_eq!(messages.unread(), Some((1, Severity::Warning)));
+        messages.error("parsing failed for question 1");
+        assert_eq!(messages.unread(), Some((2, Severity::Error)));
+
+        let read = messages.read();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].text, "results are partial");
+        assert_eq!(read[1].severity, Severity::Error);
+        assert_eq!(messages.unread(), None);
+
+        // Only what comes in afterwards is unread
+        messages.warn("fallback selector");
+        assert_eq!(messages.unread(), Some((1, Severity::Warning)));
+        assert_eq!(messages.read().len(), 3);
+    }
+
+    #[test]
+    fn test_duplicates_dropped() {
+        let messages = Messages::default();
+        messages.warn("results are partial");
+        messages.warn("results are partial");
+        assert_eq!(messages.unread(), Some((1, Severity::Warning)));
+        // Unless it's a different severity
+        messages.error("results are partial");
+        assert_eq!(messages.unread(), Some((2, Severity::Error)));
+        // Having been read doesn't make a repeat new either
+        messages.read();
+        messages.warn("results are partial");
+        assert_eq!(messages.unread(), None);
+    }
+
+    #[test]
+    fn test_shared_between_clones() {
+        let messages = Messages::default();
+        let pipeline = messages.clone();
+        let handle = std::thread::spawn(move || pipeline.warn("from another thread"));
+        handle.join().unwrap();
+        let list = messages.read();
+        assert_eq!(list.len(), 1);
+        assert!(list[0].at > 0);
+    }
+}
@@ -0,0 +1,332 @@
+//! One-line notes on questions, jotted down from the TUI and shown whenever the question comes up
+//! again in a later search.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::backup::ImportMode;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::stackexchange::aliases::Aliases;
+use crate::stackexchange::Question;
+use crate::utils;
+
+/// A note on a question
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Note {
+    pub site: String,
+    pub question_id: u32,
+    /// Title of the question, so that `so notes list` is readable
+    pub title: String,
+    pub text: String,
+    /// Unix timestamp of the last edit
+    pub updated_at: i64,
+    /// Unix timestamp of the last time the question came up in a search
+    pub last_seen: i64,
+}
+
+/// All notes, keyed by site and question id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Notes {
+    notes: BTreeMap<(String, u32), Note>,
+}
+
+impl Notes {
+    /// Where notes are stored
+    pub fn path() -> Result<PathBuf> {
+        Ok(Config::project_dir()?.data_dir().join("notes.json"))
+    }
+
+    /// Load notes from `filename`, which is fine not to exist yet
+    pub fn load(filename: &PathBuf) -> Result<Self> {
+        match utils::open_file(filename)? {
+            Some(file) => serde_json::from_reader(file)
+                .map(Self::from_list)
+                .map_err(|_| Error::MalformedFile(filename.clone())),
+            None => Ok(Notes::default()),
+        }
+    }
+
+    pub fn save(&self, filename: &PathBuf) -> Result<()> {
+        if let Some(dir) = filename.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        utils::write_atomic(filename, &serde_json::to_vec_pretty(&self.list())?)
+    }
+
+    fn from_list(notes: Vec<Note>) -> Self {
+        Notes {
+            notes: notes
+                .into_iter()
+                .map(|n| ((n.site.clone(), n.question_id), n))
+                .collect(),
+        }
+    }
+
+    /// All notes, by site and question id
+    pub fn list(&self) -> Vec<Note> {
+        self.notes.values().cloned().collect()
+    }
+
+    pub fn get(&self, site: &str, question_id: u32) -> Option<&Note> {
+        self.notes.get(&(site.to_string(), question_id))
+    }
+
+    /// Set the note on `q`; an empty `text` deletes it
+    pub fn set<S>(&mut self, q: &Question<S>, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            self.delete(&q.site, q.id);
+            return;
+        }
+        let now = now();
+        self.notes.insert(
+            (q.site.clone(), q.id),
+            Note {
+                site: q.site.clone(),
+                question_id: q.id,
+                title: q.title.clone(),
+                text: text.to_string(),
+                updated_at: now,
+                last_seen: now,
+            },
+        );
+    }
+
+    /// Delete the note on a question, returning whether there was one
+    pub fn delete(&mut self, site: &str, question_id: u32) -> bool {
+        self.notes
+            .remove(&(site.to_string(), question_id))
+            .is_some()
+    }
+
+    /// Mark the notes on any of `qs` as seen just now. Returns whether any were.
+    pub fn touch<S>(&mut self, qs: &[Question<S>]) -> bool {
+        let now = now();
+        let mut touched = false;
+        for q in qs {
+            if let Some(note) = self.notes.get_mut(&(q.site.clone(), q.id)) {
+                note.last_seen = now;
+                touched = true;
+            }
+        }
+        touched
+    }
+
+    /// Move notes on merged questions over to the questions they were merged into. Returns
+    /// whether any moved.
+    pub fn migrate(&mut self, aliases: &Aliases) -> bool {
+        let mut migrated = false;
+        for (site, old_id, _) in aliases.list() {
+            let new_id = aliases.resolve(&site, old_id);
+            if let Some(note) = self.notes.remove(&(site.clone(), old_id)) {
+                let note = Note {
+                    question_id: new_id,
+                    ..note
+                };
+                // Both questions may have had notes; keep the most recently edited
+                let key = (site, new_id);
+                match self.notes.get(&key) {
+                    Some(existing) if existing.updated_at >= note.updated_at => (),
+                    _ => {
+                        self.notes.insert(key, note);
+                    }
+                }
+                migrated = true;
+            }
+        }
+        migrated
+    }
+
+    /// Delete notes on questions that haven't come up in a search for `age`, returning them
+    pub fn prune(&mut self, age: Duration) -> Vec<Note> {
+        let cutoff = now().saturating_sub(i64::try_from(age.as_secs()).unwrap_or(i64::MAX));
+        let (stale, fresh) = std::mem::take(&mut self.notes)
+            .into_iter()
+            .partition(|(_, n)| n.last_seen < cutoff);
+        self.notes = fresh;
+        stale.into_values().collect()
+    }
+
+    /// Combine `imported` notes with these. When merging, the most recently edited of two notes
+    /// on the same question wins.
+    pub fn merge(&mut self, imported: Notes, mode: ImportMode) {
+        if mode == ImportMode::Replace {
+            self.notes.clear();
+        }
+        for (key, note) in imported.notes {
+            match self.notes.get(&key) {
+                Some(local) if local.updated_at >= note.updated_at => (),
+                _ => {
+                    self.notes.insert(key, note);
+                }
+            }
+        }
+    }
+}
+
+impl Serialize for Notes {
+    fn serialize<Se: serde::Serializer>(&self, s: Se) -> std::result::Result<Se::Ok, Se::Error> {
+        self.list().serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Notes {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        Vec::<Note>::deserialize(d).map(Self::from_list)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn question(site: &str, id: u32) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            title: format!("Question {}", id),
+            body: Some(String::new()),
+            site: site.to_string(),
+            ..Question::default()
+        }
+    }
+
+    fn note(site: &str, id: u32, text: &str, updated_at: i64) -> Note {
+        Note {
+            site: site.to_string(),
+            question_id: id,
+            title: format!("Question {}", id),
+            text: text.to_string(),
+            updated_at,
+            last_seen: updated_at,
+        }
+    }
+
+    #[test]
+    fn test_set_get_delete() {
+        let mut notes = Notes::default();
+        notes.set(&question("stackoverflow", 1), "  worked for nginx 1.25 ");
+        assert_eq!(
+            notes.get("stackoverflow", 1).unwrap().text,
+            "worked for nginx 1.25"
+        );
+        // Same id on another site is another question
+        assert!(notes.get("superuser", 1).is_none());
+
+        notes.set(&question("stackoverflow", 1), "");
+        assert!(notes.get("stackoverflow", 1).is_none());
+
+        notes.set(&question("superuser", 2), "meh");
+        assert!(notes.delete("superuser", 2));
+        assert!(!notes.delete("superuser", 2));
+    }
+
+    #[test]
+    fn test_load_save() {
+        let dir = std::env::temp_dir().join(format!("so-notes-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let filename = dir.join("data").join("notes.json");
+        assert_eq!(Notes::load(&filename).unwrap(), Notes::default());
+
+        let mut notes = Notes::default();
+        notes.set(&question("stackoverflow", 1), "first");
+        notes.set(&question("askubuntu", 7), "second");
+        notes.save(&filename).unwrap();
+        assert_eq!(Notes::load(&filename).unwrap(), notes);
+        assert!(!dir.join("data").join("notes.json.tmp").exists());
+
+        fs::write(&filename, "not json").unwrap();
+        assert!(matches!(
+            Notes::load(&filename),
+            Err(Error::MalformedFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_touch_and_prune() {
+        let long_ago = now() - 100 * DAY.as_secs() as i64;
+        let mut notes = Notes::from_list(vec![
+            note("stackoverflow", 1, "seen again", long_ago),
+            note("stackoverflow", 2, "never seen again", long_ago),
+            note("stackoverflow", 3, "recent", now()),
+        ]);
+        assert!(notes.touch(&[question("stackoverflow", 1), question("unix", 2)]));
+        assert!(!notes.touch(&[question("unix", 2)]));
+
+        let pruned = notes.prune(30 * DAY);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].question_id, 2);
+        assert!(notes.get("stackoverflow", 1).is_some());
+        assert!(notes.get("stackoverflow", 3).is_some());
+
+        // Ages too long to subtract from now keep everything
+        assert!(notes.prune(Duration::from_secs(u64::MAX)).is_empty());
+        assert!(notes
+            .prune(Duration::from_secs(106_751_991_167_301 * 24 * 60 * 60))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = std::env::temp_dir().join(format!("so-notes-aliases-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let aliases = Aliases::load(dir.join("aliases.json")).unwrap();
+        let mut notes = Notes::from_list(vec![
+            note("stackoverflow", 1, "merged away", 100),
+            note("superuser", 1, "same id, other site", 100),
+        ]);
+        assert!(!notes.migrate(&aliases));
+
+        aliases.record("stackoverflow", 1, 5);
+        assert!(notes.migrate(&aliases));
+        assert!(notes.get("stackoverflow", 1).is_none());
+        assert_eq!(notes.get("stackoverflow", 5).unwrap().text, "merged away");
+        assert_eq!(notes.get("superuser", 1).unwrap().question_id, 1);
+        assert!(!notes.migrate(&aliases));
+    }
+
+    #[test]
+    fn test_merge() {
+        let local = Notes::from_list(vec![
+            note("stackoverflow", 1, "local, newer", 200),
+            note("stackoverflow", 2, "local, older", 100),
+            note("stackoverflow", 3, "local only", 100),
+        ]);
+        let imported = Notes::from_list(vec![
+            note("stackoverflow", 1, "imported, older", 100),
+            note("stackoverflow", 2, "imported, newer", 200),
+            note("stackoverflow", 4, "imported only", 100),
+        ]);
+
+        let mut merged = local.clone();
+        merged.merge(imported.clone(), ImportMode::Merge);
+        let texts: Vec<String> = merged.list().into_iter().map(|n| n.text).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "local, newer",
+                "imported, newer",
+                "local only",
+                "imported only"
+            ]
+        );
+
+        let mut replaced = local;
+        replaced.merge(imported.clone(), ImportMode::Replace);
+        assert_eq!(replaced, imported);
+    }
+}
@@ -0,0 +1,159 @@
+//! Letting the user know a slow search is done, for when they switched windows while it ran.
+//!
+//! The bell is a plain BEL. The title is pushed onto xterm's title stack before it's set, via
+//! OSC 2, so that popping it once `so` exits brings back whatever the title was. Terminals
+//! without a title stack ignore the push and pop, and keep the title `so` set.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::config::{Config, NotifyOnComplete};
+
+const BELL: &[u8] = b"\x07";
+/// XTWINOPS: save the window and icon titles on the stack
+const PUSH_TITLE: &[u8] = b"\x1b[22;0t";
+/// XTWINOPS: restore the window and icon titles from the stack
+const POP_TITLE: &[u8] = b"\x1b[23;0t";
+
+/// Lets the user know when results are ready, as configured
+#[derive(Debug, Clone, Copy)]
+pub struct Notifier {
+    how: NotifyOnComplete,
+    after: Duration,
+    /// Whether the output goes to a terminal; there's nothing to notify otherwise
+    terminal: bool,
+}
+
+impl Notifier {
+    pub fn new(how: NotifyOnComplete, after: Duration, terminal: bool) -> Self {
+        Notifier {
+            how,
+            after,
+            terminal,
+        }
+    }
+
+    /// As `config` has it, for output to a terminal or not
+    pub fn from_config(config: &Config, terminal: bool) -> Self {
+        Self::new(
+            config.notify_on_complete,
+            Duration::from_secs(config.notify_after_secs),
+            terminal,
+        )
+    }
+
+    /// Write the notification that `results` results are ready, `elapsed` after the search
+    /// started, to `out`, if the search took long enough. Returns whether it set the title, which
+    /// `restore_title` undoes.
+    pub fn complete<W: Write>(
+        &self,
+        out: &mut W,
+        elapsed: Duration,
+        results: usize,
+    ) -> io::Result<bool> {
+        if !self.terminal || elapsed < self.after {
+            return Ok(false);
+        }
+        let (bell, title) = match self.how {
+            NotifyOnComplete::Bell => (true, false),
+            NotifyOnComplete::Title => (false, true),
+            NotifyOnComplete::Both => (true, true),
+            NotifyOnComplete::Off => (false, false),
+        };
+        if title {
+            out.write_all(PUSH_TITLE)?;
+            let noun = if results == 1 { "result" } else { "results" };
+            write!(out, "\x1b]2;so: {} {}\x07", results, noun)?;
+        }
+        if bell {
+            out.write_all(BELL)?;
+        }
+        out.flush()?;
+        Ok(title)
+    }
+}
+
+/// Bring back the title `Notifier::complete` replaced
+pub fn restore_title<W: Write>(out: &mut W) -> io::Result<()> {
+    out.write_all(POP_TITLE)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLOW: Duration = Duration::from_secs(5);
+
+    fn emitted(how: NotifyOnComplete, terminal: bool, elapsed: Duration) -> (Vec<u8>, bool) {
+        let notifier = Notifier::new(how, Duration::from_secs(3), terminal);
+        let mut out = vec![];
+        let title = notifier.complete(&mut out, elapsed, 12).unwrap();
+        (out, title)
+    }
+
+    #[test]
+    fn test_bell() {
+        assert_eq!(
+            emitted(NotifyOnComplete::Bell, true, SLOW),
+            (b"\x07".to_vec(), false)
+        );
+    }
+
+    #[test]
+    fn test_title() {
+        let (out, title) = emitted(NotifyOnComplete::Title, true, SLOW);
+        // Saved before it's replaced
+        assert_eq!(out, b"\x1b[22;0t\x1b]2;so: 12 results\x07".to_vec());
+        assert!(title);
+        let mut out = vec![];
+        restore_title(&mut out).unwrap();
+        assert_eq!(out, b"\x1b[23;0t".to_vec());
+
+        let notifier = Notifier::new(NotifyOnComplete::Title, Duration::ZERO, true);
+        let mut out = vec![];
+        notifier.complete(&mut out, SLOW, 1).unwrap();
+        assert_eq!(out, b"\x1b[22;0t\x1b]2;so: 1 result\x07".to_vec());
+    }
+
+    #[test]
+    fn test_both() {
+        let (out, title) = emitted(NotifyOnComplete::Both, true, SLOW);
+        assert_eq!(out, b"\x1b[22;0t\x1b]2;so: 12 results\x07\x07".to_vec());
+        assert!(title);
+    }
+
+    #[test]
+    fn test_only_after_threshold() {
+        let quick = Duration::from_millis(2999);
+        for how in &[NotifyOnComplete::Bell, NotifyOnComplete::Both] {
+            assert_eq!(emitted(*how, true, quick), (vec![], false));
+            // The threshold itself is slow enough
+            assert!(!emitted(*how, true, Duration::from_secs(3)).0.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_suppressed() {
+        let hows = [
+            NotifyOnComplete::Bell,
+            NotifyOnComplete::Title,
+            NotifyOnComplete::Both,
+            NotifyOnComplete::Off,
+        ];
+        for how in &hows {
+            for &terminal in &[true, false] {
+                let (out, title) = emitted(*how, terminal, SLOW);
+                let expected = terminal && *how != NotifyOnComplete::Off;
+                assert_eq!(!out.is_empty(), expected, "{:?} terminal={}", how, terminal);
+                assert_eq!(
+                    title,
+                    expected && *how != NotifyOnComplete::Bell,
+                    "{:?} terminal={}",
+                    how,
+                    terminal
+                );
+            }
+        }
+    }
+}
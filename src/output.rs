@@ -0,0 +1,1406 @@
+//! Rendering of search results outside of the TUI.
+//!
+//! Each way of printing results is an `OutputFormatter`, registered in `FORMATTERS` and picked by
+//! name with `--output`. Adding a format is a matter of implementing the trait and adding it to
+//! the list.
+
+use crossterm::style::{style, Attribute, Color};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::format::{self, Locale, ScoreStyle, ScoreThresholds};
+use crate::plain::{self, Footnotes};
+use crate::stackexchange::timings::Timings;
+use crate::stackexchange::urls::{self, LinkKind, LinkStyle, PostLink};
+use crate::stackexchange::{commands, snippets};
+use crate::stackexchange::{Answer, Question, Site};
+use crate::template::{Template, Values};
+use crate::term;
+use crate::tui::spoilers;
+
+/// What a formatter gets to render
+#[derive(Debug, Clone)]
+pub struct SearchResults {
+    pub query: String,
+    pub questions: Vec<Question<String>>,
+    /// Base URL of each site the questions are on, by site code
+    pub site_urls: HashMap<String, String>,
+    /// Where the search spent its time, with `--timings`
+    pub timings: Option<Timings>,
+}
+
+impl SearchResults {
+    /// Link to `q` in `style`, if its site is known
+    pub fn question_url<S>(&self, q: &Question<S>, style: LinkStyle) -> Option<PostLink> {
+        let site_url = self.site_urls.get(&q.site)?;
+        Some(urls::format_link(site_url, LinkKind::Question, q.id, style))
+    }
+
+    /// Link to `a`, an answer to `q`, in `style`, if its site is known
+    pub fn answer_url<S>(
+        &self,
+        q: &Question<S>,
+        a: &Answer<S>,
+        style: LinkStyle,
+    ) -> Option<PostLink> {
+        let site_url = self.site_urls.get(&q.site)?;
+        let kind = LinkKind::Answer { question_id: q.id };
+        Some(urls::format_link(site_url, kind, a.id, style))
+    }
+
+    /// The questions, with the links the API gave them and their answers in `style`. Those are
+    /// full links already, so only short ones are built here.
+    pub fn questions_linked(&self, style: LinkStyle) -> Vec<Question<String>> {
+        let mut questions = self.questions.clone();
+        if style == LinkStyle::Full {
+            return questions;
+        }
+        for q in &mut questions {
+            let question_url = self.question_url(q, style);
+            let answer_urls: Vec<_> = q
+                .answers
+                .iter()
+                .map(|a| self.answer_url(q, a, style))
+                .collect();
+            restyle(&mut q.link, question_url);
+            for (a, url) in q.answers.iter_mut().zip(answer_urls) {
+                restyle(&mut a.link, url);
+            }
+        }
+        questions
+    }
+}
+
+/// Replace `link`, if there is one, with `styled`, if there is one
+fn restyle(link: &mut Option<String>, styled: Option<PostLink>) {
+    if let (Some(link), Some(styled)) = (link, styled) {
+        *link = styled.into();
+    }
+}
+
+/// Color of `score` according to `thresholds`, the same ones the TUI uses
+fn score_color(score: i32, thresholds: &ScoreThresholds) -> Option<Color> {
+    match format::score_style(score, thresholds) {
+        ScoreStyle::Negative => Some(Color::Red),
+        ScoreStyle::Plain => None,
+        ScoreStyle::Good => Some(Color::DarkGreen),
+        ScoreStyle::Great => Some(Color::Green),
+        ScoreStyle::Gold => Some(Color::DarkYellow),
+    }
+}
+
+/// The accepted answer of `q`, or else its top answer
+pub fn best_answer<S>(q: &Question<S>) -> Option<&Answer<S>> {
+    q.answers
+        .iter()
+        .find(|a| a.is_accepted)
+        .or_else(|| q.answers.first())
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatOpts {
+    /// Width to wrap text at, if any
+    pub width: Option<usize>,
+    pub locale: Locale,
+    /// Whether to style output with ANSI colors
+    pub color: bool,
+    /// How `Lucky` lays out the answer
+    pub lucky_template: Template,
+    /// Whether `Lucky` names the question the answer is to, and links to it, above it
+    pub lucky_header: bool,
+    /// Whether `Lucky` names the author of the answer, and links to it, below it
+    pub lucky_attribution: bool,
+    pub score_thresholds: ScoreThresholds,
+    pub link_style: LinkStyle,
+    /// Whether `Lucky` prints spoilers, as block quotes, rather than leaving them out
+    pub show_spoilers: bool,
+    /// Whether `Text` swaps characters outside of ASCII for look-alikes
+    pub ascii_only: bool,
+}
+
+impl FormatOpts {
+    /// Whether output to a terminal should be colored, going by the `NO_COLOR` convention
+    pub fn color_enabled(is_terminal: bool) -> bool {
+        is_terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+    }
+
+    fn paint(&self, text: String, color: Option<Color>, bold: bool) -> String {
+        if !self.color {
+            return text;
+        }
+        let mut styled = style(text);
+        if let Some(color) = color {
+            styled = styled.with(color);
+        }
+        if bold {
+            styled = styled.attribute(Attribute::Bold);
+        }
+        styled.to_string()
+    }
+}
+
+pub trait OutputFormatter: Sync {
+    /// Name to select the formatter by
+    fn name(&self) -> &'static str;
+
+    /// One line description for `--output list`
+    fn description(&self) -> &'static str;
+
+    /// Whether the question bodies are printed, so that searches can leave them out otherwise
+    fn question_bodies(&self) -> bool {
+        true
+    }
+
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()>;
+}
+
+/// Every available formatter
+pub const FORMATTERS: &[&dyn OutputFormatter] = &[
+    &Lucky,
+    &MarkdownDump,
+    &Text,
+    &Digest,
+    &Atom,
+    &Commands,
+    &Json,
+];
+
+/// Formatter called `name`
+pub fn find(name: &str) -> Option<&'static dyn OutputFormatter> {
+    FORMATTERS.iter().copied().find(|f| f.name() == name)
+}
+
+/// Table of available formatters, in markdown
+pub fn list() -> String {
+    let mut md = String::new();
+    md.push_str("|:-:|:-:|\n");
+    md.push_str("|Output|Description|\n");
+    md.push_str("|-:|:-|\n");
+    for f in FORMATTERS {
+        md.push_str(&format!("|{}|{}\n", f.name(), f.description()));
+    }
+    md.push_str("|-\n");
+    md
+}
+
+/// The top answer of the first question, styled for the terminal and laid out by the
+/// `lucky_template`; what `--lucky` prints
+pub struct Lucky;
+
+impl OutputFormatter for Lucky {
+    fn name(&self) -> &'static str {
+        "lucky"
+    }
+
+    fn description(&self) -> &'static str {
+        "Top answer, styled for the terminal"
+    }
+
+    fn question_bodies(&self) -> bool {
+        false
+    }
+
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        let q = match results.questions.first() {
+            Some(q) => q,
+            None => return Ok(()),
+        };
+        if let Some(answer) = q.answers.first() {
+            let raw_body = if opts.show_spoilers {
+                spoilers::reveal(&answer.body)
+            } else {
+                spoilers::omit(&answer.body)
+            };
+            let body = term::skin().text(&raw_body, opts.width).to_string();
+            let link = results.question_url(q, opts.link_style);
+            if opts.lucky_header {
+                writeln!(
+                    w,
+                    "{}",
+                    opts.paint(
+                        header(q, answer, link.as_ref(), opts.locale),
+                        Some(Color::DarkGrey),
+                        false
+                    )
+                )?;
+            }
+            let values = Values {
+                title: &q.title,
+                site: &q.site,
+                score: opts.locale.number(answer.score.into()),
+                accepted: answer.is_accepted,
+                body: &body,
+                raw_body: &raw_body,
+                link: link.as_ref().map(PostLink::as_str),
+                tags: &q.tags,
+            };
+            write!(w, "{}", opts.lucky_template.render(&values))?;
+            if opts.lucky_attribution {
+                if let Some(attribution) = attribution(answer, link.as_ref(), opts.locale) {
+                    writeln!(
+                        w,
+                        "\n{}",
+                        opts.paint(attribution, Some(Color::DarkGrey), false)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The question `answer` is to, with its score and whether it's accepted, and `link` to it
+fn header(
+    q: &Question<String>,
+    answer: &Answer<String>,
+    link: Option<&PostLink>,
+    locale: Locale,
+) -> String {
+    let accepted = if answer.is_accepted { ", accepted" } else { "" };
+    let score = locale.number(answer.score.into());
+    let header = format!("\u{25b8} {} (score {}{})", q.title, score, accepted);
+    match link {
+        Some(link) => format!("{} \u{2014} {}", header, link.as_str()),
+        None => header,
+    }
+}
+
+/// Who posted `answer`, and where: its own link if it has one, else `link` to its question
+fn attribution(answer: &Answer<String>, link: Option<&PostLink>, locale: Locale) -> Option<String> {
+    let byline = answer.owner.as_ref()?.byline(locale)?;
+    let link = answer.link.as_deref().or(link.map(PostLink::as_str));
+    Some(match link {
+        Some(link) => format!("\u{2014} answered by {}, {}", byline, link),
+        None => format!("\u{2014} answered by {}", byline),
+    })
+}
+
+/// All questions and answers as a single markdown document
+pub struct MarkdownDump;
+
+impl OutputFormatter for MarkdownDump {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn description(&self) -> &'static str {
+        "All questions and answers as markdown"
+    }
+
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        for q in &results.questions {
+            writeln!(w, "# {}\n", q.title)?;
+            writeln!(w, "*Score: {}*\n", opts.locale.number(q.score.into()))?;
+            if let Some(body) = &q.body {
+                writeln!(w, "{}\n", body.trim_end())?;
+            }
+            for a in &q.answers {
+                let accepted = if a.is_accepted { ", accepted" } else { "" };
+                writeln!(
+                    w,
+                    "## Answer (score: {}{})\n",
+                    opts.locale.number(a.score.into()),
+                    accepted
+                )?;
+                writeln!(w, "{}\n", a.body.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// All questions and answers as plain text wrapped at the width, for tickets and email: links
+/// as footnotes below each question, code indented and never wrapped, and never any color
+pub struct Text;
+
+impl OutputFormatter for Text {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn description(&self) -> &'static str {
+        "All questions and answers as plain text, wrapped at --width"
+    }
+
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        let width = opts.width.unwrap_or(plain::DEFAULT_WIDTH);
+        let mut footnotes = Footnotes::default();
+        let mut text = String::new();
+        for (i, q) in results.questions_linked(opts.link_style).iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            text.push_str(&plain::heading(&q.title, '=', width));
+            let mut about = format!("Score {} on {}", opts.locale.number(q.score.into()), q.site);
+            if let Some(link) = &q.link {
+                about.push_str(&format!(" [{}]", footnotes.add(link)));
+            }
+            text.push_str(&format!("{}\n\n", about));
+            if let Some(body) = &q.body {
+                text.push_str(&plain::render(&spoilers::omit(body), width, &mut footnotes));
+                text.push('\n');
+            }
+            for a in &q.answers {
+                let mut title = format!("Answer, score {}", opts.locale.number(a.score.into()));
+                if a.is_accepted {
+                    title.push_str(", accepted");
+                }
+                if let Some(link) = &a.link {
+                    title.push_str(&format!(" [{}]", footnotes.add(link)));
+                }
+                text.push_str(&plain::heading(&title, '-', width));
+                text.push('\n');
+                text.push_str(&plain::render(
+                    &spoilers::omit(&a.body),
+                    width,
+                    &mut footnotes,
+                ));
+                text.push('\n');
+            }
+            for footnote in footnotes.take() {
+                text.push_str(&footnote);
+                text.push('\n');
+            }
+        }
+        let text = match opts.ascii_only {
+            true => plain::ascii(&text),
+            false => text.into(),
+        };
+        write!(w, "{}", text)?;
+        Ok(())
+    }
+}
+
+/// Three lines per question: its title, a sentence from its best answer, and a link
+pub struct Digest;
+
+impl OutputFormatter for Digest {
+    fn name(&self) -> &'static str {
+        "digest"
+    }
+
+    fn description(&self) -> &'static str {
+        "Title, gist of the best answer, and link of each question"
+    }
+
+    fn question_bodies(&self) -> bool {
+        false
+    }
+
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        for q in &results.questions {
+            writeln!(
+                w,
+                "{} [{}] {}",
+                opts.paint(q.title.clone(), None, true),
+                q.site,
+                opts.paint(
+                    format!("({})", opts.locale.number(q.score.into())),
+                    score_color(q.score, &opts.score_thresholds),
+                    true
+                )
+            )?;
+            let gist = best_answer(q).map(|a| snippets::first_sentence(&a.body));
+            writeln!(w, "{}", gist.unwrap_or_default())?;
+            let url = results
+                .question_url(q, opts.link_style)
+                .map(String::from)
+                .unwrap_or_default();
+            writeln!(w, "{}", opts.paint(url, Some(Color::Cyan), false))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sites as `--list-sites` lists them, one per line, in columns of their code, URL, name and
+/// audience, so that they can be paged through or cut into fields
+pub fn sites(sites: &[&Site], w: &mut dyn Write) -> Result<()> {
+    let width = |field: fn(&Site) -> &str| {
+        sites
+            .iter()
+            .map(|s| field(s).chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let code = width(|s| &s.api_site_parameter);
+    let url = width(|s| &s.site_url);
+    let name = width(|s| &s.name);
+    for s in sites {
+        let line = format!(
+            "{:code$}  {:url$}  {:name$}  {}",
+            s.api_site_parameter,
+            s.site_url,
+            s.name,
+            s.audience,
+            code = code,
+            url = url,
+            name = name
+        );
+        writeln!(w, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Command lines in the code blocks of the top answer of the first question, the one `--lucky`
+/// would print
+pub fn top_commands(results: &SearchResults) -> Vec<String> {
+    results
+        .questions
+        .first()
+        .and_then(|q| q.answers.first())
+        .map(|a| commands::commands(&snippets::code_blocks(&a.body)))
+        .unwrap_or_default()
+}
+
+/// Just the command lines of the top answer, one per line, ready to paste into a shell
+pub struct Commands;
+
+impl OutputFormatter for Commands {
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
+    fn description(&self) -> &'static str {
+        "Command lines from the code blocks of the top answer"
+    }
+
+    fn question_bodies(&self) -> bool {
+        false
+    }
+
+    fn format(&self, results: &SearchResults, _: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        for command in top_commands(results) {
+            writeln!(w, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+/// The questions and their answers as a JSON array, for scripts and editor plugins. Fields keep
+/// the names the StackExchange API gives them, e.g. `question_id` and `body_markdown`.
+pub struct Json;
+
+/// An answer with the timings of the search that found it, if any, as fields of one object
+#[derive(Serialize)]
+struct Timed<'a> {
+    #[serde(flatten)]
+    answer: &'a Answer<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<&'a Timings>,
+}
+
+impl Json {
+    /// Just the lucky answer, as a JSON object, along with a `timings` field if there are any
+    pub fn answer(
+        answer: &Answer<String>,
+        timings: Option<&Timings>,
+        w: &mut dyn Write,
+    ) -> Result<()> {
+        serde_json::to_writer(&mut *w, &Timed { answer, timings })?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// Sites, as `--list-sites --json` lists them, as a JSON array
+    pub fn sites(sites: &[&Site], w: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer(&mut *w, sites)?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// A JSON object in place of results that failed with `error`, so that scripts can tell no
+    /// results apart from other failures by its `error` field as well as the exit code. The site,
+    /// endpoint, status and path the error came with, if any, are fields of their own.
+    pub fn error(error: &Error, w: &mut dyn Write) -> Result<()> {
+        let kind = match error {
+            Error::NoResults => "no_results",
+            _ => "failure",
+        };
+        let mut object = serde_json::json!({
+            "error": kind,
+            "message": error.to_string(),
+        });
+        if let Some(site) = error.site() {
+            object["site"] = site.into();
+        }
+        if let Some(endpoint) = error.endpoint() {
+            object["endpoint"] = endpoint.into();
+        }
+        if let Some(status) = error.status() {
+            object["status"] = status.into();
+        }
+        if let Some(path) = error.path() {
+            object["path"] = path.to_string_lossy().into();
+        }
+        serde_json::to_writer(&mut *w, &object)?;
+        writeln!(w)?;
+        Ok(())
+    }
+}
+
+impl OutputFormatter for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn description(&self) -> &'static str {
+        "Questions and answers as JSON, with the API's field names"
+    }
+
+    /// The questions as a JSON array, or with `--timings` as the `questions` field of an object
+    /// whose `timings` field has those
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        let questions = results.questions_linked(opts.link_style);
+        match &results.timings {
+            Some(timings) => serde_json::to_writer(
+                &mut *w,
+                &serde_json::json!({ "questions": questions, "timings": timings }),
+            )?,
+            None => serde_json::to_writer(&mut *w, &questions)?,
+        }
+        writeln!(w)?;
+        Ok(())
+    }
+}
+
+/// An Atom feed with an entry per question, for following a query in a feed reader
+pub struct Atom;
+
+impl OutputFormatter for Atom {
+    fn name(&self) -> &'static str {
+        "atom"
+    }
+
+    fn description(&self) -> &'static str {
+        "Atom feed with an entry per question"
+    }
+
+    fn format(&self, results: &SearchResults, opts: &FormatOpts, w: &mut dyn Write) -> Result<()> {
+        let updated = |q: &Question<String>| q.last_activity_date.or(q.creation_date);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let feed_updated = results
+            .questions
+            .iter()
+            .filter_map(updated)
+            .max()
+            .unwrap_or(now);
+        writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(w, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+        writeln!(w, "  <title>so: {}</title>", xml_escape(&results.query))?;
+        writeln!(
+            w,
+            "  <id>urn:so:search:{}</id>",
+            utf8_percent_encode(&results.query, NON_ALPHANUMERIC)
+        )?;
+        writeln!(w, "  <updated>{}</updated>", format::rfc3339(feed_updated))?;
+        writeln!(w, "  <author><name>so</name></author>")?;
+        writeln!(w, "  <generator>so</generator>")?;
+        for q in &results.questions {
+            writeln!(w, "  <entry>")?;
+            writeln!(w, "    <title>{}</title>", xml_escape(&q.title))?;
+            if let Some(url) = results.question_url(q, opts.link_style) {
+                writeln!(w, r#"    <link href="{}"/>"#, xml_escape(url.as_str()))?;
+            }
+            // Stays the same across searches, so readers recognize questions they've seen
+            writeln!(
+                w,
+                "    <id>urn:so:question:{}:{}</id>",
+                utf8_percent_encode(&q.site, NON_ALPHANUMERIC),
+                q.id
+            )?;
+            if let Some(created) = q.creation_date {
+                writeln!(w, "    <published>{}</published>", format::rfc3339(created))?;
+            }
+            writeln!(
+                w,
+                "    <updated>{}</updated>",
+                format::rfc3339(updated(q).unwrap_or(feed_updated))
+            )?;
+            let summary = match best_answer(q) {
+                Some(a) => snippets::first_sentence(&a.body),
+                None => q
+                    .body
+                    .as_deref()
+                    .map(snippets::first_sentence)
+                    .unwrap_or_default(),
+            };
+            writeln!(w, "    <summary>{}</summary>", xml_escape(&summary))?;
+            writeln!(w, "  </entry>")?;
+        }
+        writeln!(w, "</feed>")?;
+        Ok(())
+    }
+}
+
+/// `text` escaped for XML text and attribute values, leaving out characters XML can't contain
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::{Answer, Owner};
+    use crate::tui::testing;
+
+    fn results() -> SearchResults {
+        let answer = |id, score, is_accepted, body: &str| Answer {
+            id,
+            score,
+            body: body.to_string(),
+            is_accepted,
+            ..Answer::default()
+        };
+        SearchResults {
+            query: String::from("exit vim"),
+            questions: vec![
+                Question {
+                    id: 1,
+                    score: 1234,
+                    answers: vec![
+                        answer(10, 2048, true, "Hit `Esc`, then type `:q`\n"),
+                        answer(11, -1, false, "Pull the plug"),
+                    ],
+                    title: String::from("How do I exit Vim?"),
+                    body: Some(String::from("I am stuck")),
+                    site: String::from("stackoverflow"),
+                    ..Question::default()
+                },
+                Question {
+                    id: 2,
+                    score: 3,
+                    answers: vec![answer(20, 1, false, "`ZZ`")],
+                    title: String::from("Quit vi"),
+                    body: Some(String::from("Same, but vi")),
+                    site: String::from("unix"),
+                    ..Question::default()
+                },
+            ],
+            site_urls: vec![
+                ("stackoverflow", "https://stackoverflow.com"),
+                ("unix", "unix.stackexchange.com"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            timings: None,
+        }
+    }
+
+    fn render(formatter: &dyn OutputFormatter, results: &SearchResults) -> String {
+        render_with(formatter, results, Template::default())
+    }
+
+    fn render_with(
+        formatter: &dyn OutputFormatter,
+        results: &SearchResults,
+        lucky_template: Template,
+    ) -> String {
+        let opts = FormatOpts {
+            width: Some(40),
+            locale: Locale::En,
+            color: false,
+            lucky_template,
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let mut out = Vec::new();
+        formatter.format(results, &opts, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("markdown").unwrap().name(), "markdown");
+        assert!(find("list").is_none());
+        assert!(list().contains("|lucky|"));
+    }
+
+    #[test]
+    fn test_question_bodies() {
+        let skipped: Vec<_> = FORMATTERS
+            .iter()
+            .filter(|f| !f.question_bodies())
+            .map(|f| f.name())
+            .collect();
+        assert_eq!(skipped, vec!["lucky", "digest", "commands"]);
+
+        // Questions fetched without their body still print
+        let mut results = results();
+        results.questions[0].body = None;
+        assert!(render(&MarkdownDump, &results)
+            .starts_with("# How do I exit Vim?\n\n*Score: 1,234*\n\n## Answer"));
+    }
+
+    #[test]
+    fn test_markdown_snapshot() {
+        assert_eq!(
+            render(&MarkdownDump, &results()),
+            "# How do I exit Vim?\n\n\
+             *Score: 1,234*\n\n\
+             I am stuck\n\n\
+             ## Answer (score: 2,048, accepted)\n\n\
+             Hit `Esc`, then type `:q`\n\n\
+             ## Answer (score: -1)\n\n\
+             Pull the plug\n\n\
+             # Quit vi\n\n\
+             *Score: 3*\n\n\
+             Same, but vi\n\n\
+             ## Answer (score: 1)\n\n\
+             `ZZ`\n\n"
+        );
+    }
+
+    #[test]
+    fn test_lucky_snapshot() {
+        let lucky = render(&Lucky, &results());
+        // Same rendering `--lucky` has always printed
+        assert_eq!(
+            lucky,
+            term::skin()
+                .text("Hit `Esc`, then type `:q`\n", Some(40))
+                .to_string()
+        );
+        assert!(lucky.contains("Esc"));
+        assert!(!lucky.contains("Pull the plug"));
+        let empty = SearchResults {
+            query: String::new(),
+            questions: vec![],
+            site_urls: HashMap::new(),
+            timings: None,
+        };
+        assert_eq!(render(&Lucky, &empty), "");
+    }
+
+    #[test]
+    fn test_lucky_template() {
+        let template =
+            Template::parse("{title} [{score}{accepted}]\n{body:raw}\n-- {link}").unwrap();
+        assert_eq!(
+            render_with(&Lucky, &results(), template),
+            "How do I exit Vim? [2,048\u{2713}]\n\
+             Hit `Esc`, then type `:q`\n\n\
+             -- https://stackoverflow.com/questions/1"
+        );
+        let template = Template::parse("{site}: {body}").unwrap();
+        assert_eq!(
+            render_with(&Lucky, &results(), template),
+            format!(
+                "stackoverflow: {}",
+                term::skin().text("Hit `Esc`, then type `:q`\n", Some(40))
+            )
+        );
+        let mut results = results();
+        results.questions[0].tags = vec![String::from("vim"), String::from("exit")];
+        let template = Template::parse("{title} ({tags})").unwrap();
+        assert_eq!(
+            render_with(&Lucky, &results, template),
+            "How do I exit Vim? (vim exit)"
+        );
+    }
+
+    #[test]
+    fn test_lucky_spoilers() {
+        let mut results = results();
+        results.questions[0].answers[0].body =
+            String::from("Hit `Esc`, then type\n\n>! `:q`\n\nand you're out");
+        let template = || Template::parse("{body:raw}").unwrap();
+        assert_eq!(
+            render_with(&Lucky, &results, template()),
+            "Hit `Esc`, then type\n\n\nand you're out"
+        );
+        let opts = FormatOpts {
+            width: Some(40),
+            locale: Locale::En,
+            color: false,
+            lucky_template: template(),
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: true,
+            ascii_only: false,
+        };
+        let mut out = Vec::new();
+        Lucky.format(&results, &opts, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Hit `Esc`, then type\n\n> `:q`\n\nand you're out"
+        );
+    }
+
+    #[test]
+    fn test_lucky_attribution() {
+        let mut results = results();
+        results.questions[0].answers[0].owner = Some(Owner {
+            display_name: Some(String::from("jane")),
+            reputation: Some(512_345),
+        });
+        let opts = |lucky_attribution| FormatOpts {
+            width: Some(40),
+            locale: Locale::En,
+            color: false,
+            lucky_template: Template::parse("{body:raw}").unwrap(),
+            lucky_header: false,
+            lucky_attribution,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let lucky = |results: &SearchResults, attribution| {
+            let mut out = Vec::new();
+            Lucky.format(results, &opts(attribution), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(lucky(&results, false), "Hit `Esc`, then type `:q`\n");
+        assert_eq!(
+            lucky(&results, true),
+            "Hit `Esc`, then type `:q`\n\n\
+             \u{2014} answered by jane (512.3k), https://stackoverflow.com/questions/1\n"
+        );
+        // The answer's own link, if it has one
+        results.questions[0].answers[0].link = Some(String::from("https://stackoverflow.com/a/10"));
+        assert!(lucky(&results, true).ends_with("(512.3k), https://stackoverflow.com/a/10\n"));
+        // Nobody to name
+        results.questions[0].answers[0].owner = Some(Owner::default());
+        assert_eq!(lucky(&results, true), "Hit `Esc`, then type `:q`\n");
+    }
+
+    #[test]
+    fn test_lucky_header() {
+        let mut results = results();
+        let opts = |lucky_header, color| FormatOpts {
+            width: Some(40),
+            locale: Locale::En,
+            color,
+            lucky_template: Template::parse("{body:raw}").unwrap(),
+            lucky_header,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Short,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let lucky = |results: &SearchResults, header, color| {
+            let mut out = Vec::new();
+            Lucky
+                .format(results, &opts(header, color), &mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            lucky(&results, true, false),
+            "\u{25b8} How do I exit Vim? (score 2,048, accepted) \u{2014} \
+             https://stackoverflow.com/q/1\n\
+             Hit `Esc`, then type `:q`\n"
+        );
+        // Dimmed, leaving the answer as is
+        let colored = lucky(&results, true, true);
+        assert!(colored.starts_with("\u{1b}["));
+        assert!(colored.ends_with("\nHit `Esc`, then type `:q`\n"));
+        // Just the body for scripts
+        assert_eq!(lucky(&results, false, false), "Hit `Esc`, then type `:q`\n");
+        // Without a link or an accepted answer
+        results.site_urls.clear();
+        results.questions[0].answers[0].is_accepted = false;
+        assert!(
+            lucky(&results, true, false).starts_with("\u{25b8} How do I exit Vim? (score 2,048)\n")
+        );
+    }
+
+    /// Results with everything `Text` has to lay out: headings, lists, quotes, links, images,
+    /// code short and long, and unicode punctuation
+    fn text_results() -> SearchResults {
+        let mut results = results();
+        let q = &mut results.questions[0];
+        q.title = String::from("How do I exit Vim \u{2014} without losing my work?");
+        q.link = Some(String::from("https://stackoverflow.com/questions/1"));
+        q.body = Some(String::from(
+            "I opened a file with `vim notes.txt` and now I\u{2019}m stuck\u{2026} \
+             Typing \u{201c}exit\u{201d} does nothing, and the manual at \
+             [vimhelp.org](https://vimhelp.org) didn\u{2019}t help either.",
+        ));
+        q.answers[0].link = Some(String::from("https://stackoverflow.com/a/10"));
+        q.answers[0].body = String::from(
+            "## Short version\n\n\
+             Press `Esc`, then one of:\n\n\
+             - `:q` \u{2192} quit, if nothing changed\n\
+             - `:wq` \u{2192} save and quit\n\
+             - `:q!` \u{2192} quit and throw away your changes, which is what you want \
+             when the file is a mess\n\n\
+             1. Esc\n2. `:wq`\n\n\
+             ```\n\
+             :wq\n\
+             ```\n\n\
+             To do it from a script \u{2013} say, a git hook \u{2013} run\n\n\
+             ```\n\
+             vim -es -c 'normal! ggdG' -c 'wq' /path/to/some/very/long/file/name/that/goes/on.txt\n\
+             ```\n\n\
+             > Vim is a text editor, not a prison.\n> \u{2014} someone on the internet\n\n\
+             See ![the cheat sheet](https://i.imgur.com/vim.png) and \
+             <https://vim.rtorr.com>; **caf\u{e9}** optional.\n",
+        );
+        results
+    }
+
+    fn render_text(width: usize, ascii_only: bool) -> String {
+        let opts = FormatOpts {
+            width: Some(width),
+            locale: Locale::En,
+            color: true,
+            lucky_template: Template::default(),
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: false,
+            ascii_only,
+        };
+        let mut out = Vec::new();
+        Text.format(&text_results(), &opts, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_text_snapshots() {
+        for width in [72, 100] {
+            let text = render_text(width, false);
+            // Only code goes past the width
+            for line in text.lines().filter(|l| !l.starts_with("    ")) {
+                assert!(line.chars().count() <= width, "too wide: {}", line);
+            }
+            assert!(!text.contains('\u{1b}'));
+            testing::assert_snapshot_in("src/snapshots", &format!("text_{}", width), &text);
+        }
+    }
+
+    #[test]
+    fn test_text_ascii_only() {
+        let text = render_text(72, true);
+        assert!(text.is_ascii());
+        assert!(text.contains("How do I exit Vim -- without losing my work?"));
+        assert!(text.contains("`:wq` -> save and quit"));
+        assert!(text.contains("cafe"));
+        // Preserved otherwise
+        let text = render_text(72, false);
+        assert!(text.contains("\u{2192} save and quit"));
+    }
+
+    /// Questions whose best answers lead with prose, code, and little else
+    #[test]
+    fn test_commands() {
+        let mut results = results();
+        results.questions[0].answers[0].body = String::from(
+            "Save first:\n\n```\n$ vim -c 'wq' file.txt\n\"file.txt\" written\n```\n\n\
+             or from inside:\n\n    :wq\n",
+        );
+        assert_eq!(render(&Commands, &results), "vim -c 'wq' file.txt\n");
+        // Only the top answer of the first question counts
+        assert_eq!(top_commands(&results), vec!["vim -c 'wq' file.txt"]);
+        results.questions.clear();
+        assert_eq!(render(&Commands, &results), "");
+    }
+
+    fn digest_results() -> SearchResults {
+        let mut results = results();
+        results.questions[0].answers[1].body = String::from(
+            "Pull the plug. Your computer will turn off, and Vim along with it, which is more \
+             than can be said for most other ways out.",
+        );
+        results.questions[0].answers[0].is_accepted = false;
+        results.questions[0].answers[1].is_accepted = true;
+        results.questions[1].answers[0].body =
+            String::from("```\n:wq\n:q!\n```\n\nThe first saves, the second doesn't.");
+        results.questions.push(Question {
+            id: 3,
+            score: -2,
+            title: String::from("Vim on Mars"),
+            body: Some(String::new()),
+            site: String::from("space"),
+            ..Question::default()
+        });
+        results
+    }
+
+    #[test]
+    fn test_digest_snapshot() {
+        assert_eq!(
+            render(&Digest, &digest_results()),
+            "How do I exit Vim? [stackoverflow] (1,234)\n\
+             Pull the plug.\n\
+             https://stackoverflow.com/questions/1\n\
+             Quit vi [unix] (3)\n\
+             :wq\n\
+             https://unix.stackexchange.com/questions/2\n\
+             Vim on Mars [space] (-2)\n\
+             \n\
+             \n"
+        );
+        // Short answers are printed whole, and without an accepted answer the top one is used
+        let mut results = digest_results();
+        results.questions.truncate(1);
+        results.questions[0].answers[1].is_accepted = false;
+        assert_eq!(
+            render(&Digest, &results).lines().nth(1),
+            Some("Hit Esc, then type :q")
+        );
+    }
+
+    #[test]
+    fn test_digest_colors() {
+        let opts = FormatOpts {
+            width: None,
+            locale: Locale::En,
+            color: true,
+            lucky_template: Template::default(),
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let mut out = Vec::new();
+        Digest.format(&digest_results(), &opts, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        // Scores are colored by how high they are
+        let score = |text: &str, color| opts.paint(text.to_string(), color, true);
+        assert!(out.contains(&score("(1,234)", Some(Color::DarkYellow))));
+        assert!(out.contains(&score("(3)", None)));
+        assert!(out.contains(&score("(-2)", Some(Color::Red))));
+        let opts = FormatOpts {
+            score_thresholds: [-10, 0, 2, 5000],
+            ..opts.clone()
+        };
+        let mut out = Vec::new();
+        Digest.format(&digest_results(), &opts, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&score("(1,234)", Some(Color::Green))));
+        assert!(out.contains(&score("(-2)", None)));
+
+        let mut results = digest_results();
+        results.questions.truncate(1);
+        let opts = FormatOpts {
+            width: None,
+            locale: Locale::En,
+            color: true,
+            lucky_template: Template::default(),
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let mut out = Vec::new();
+        Digest.format(&results, &opts, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\u{1b}["));
+        assert!(out.contains("How do I exit Vim?"));
+        assert!(!FormatOpts::color_enabled(false));
+    }
+
+    fn atom(results: &SearchResults) -> roxmltree::Document<'static> {
+        let xml = render(&Atom, results);
+        // Leaked so that the document can borrow it; it's a test
+        roxmltree::Document::parse(Box::leak(xml.into_boxed_str())).unwrap()
+    }
+
+    fn child_text<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<&'a str> {
+        node.children()
+            .find(|n| n.has_tag_name(name))
+            .and_then(|n| n.text())
+    }
+
+    #[test]
+    fn test_json() {
+        let json: serde_json::Value = serde_json::from_str(&render(&Json, &results())).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "question_id": 1,
+                "score": 1234,
+                "answers": [
+                    {
+                        "answer_id": 10,
+                        "score": 2048,
+                        "body_markdown": "Hit `Esc`, then type `:q`\n",
+                        "is_accepted": true,
+                    },
+                    {
+                        "answer_id": 11,
+                        "score": -1,
+                        "body_markdown": "Pull the plug",
+                        "is_accepted": false,
+                    },
+                ],
+                "title": "How do I exit Vim?",
+                "tags": [],
+                "body_markdown": "I am stuck",
+                "site": "stackoverflow",
+                "creation_date": null,
+                "last_activity_date": null,
+            })
+        );
+        assert_eq!(json[1]["question_id"], 2);
+        assert_eq!(json.as_array().unwrap().len(), 2);
+
+        let mut out = Vec::new();
+        Json::answer(&results().questions[0].answers[0], None, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"answer_id\":10,\"score\":2048,\"body_markdown\":\"Hit `Esc`, then type `:q`\\n\",\
+             \"is_accepted\":true}\n"
+        );
+    }
+
+    #[test]
+    fn test_json_timings() {
+        let timings = Timings {
+            total: Some(std::time::Duration::from_millis(1622)),
+            ..Timings::default()
+        };
+        let timed = SearchResults {
+            timings: Some(timings.clone()),
+            ..results()
+        };
+        let json: serde_json::Value = serde_json::from_str(&render(&Json, &timed)).unwrap();
+        assert_eq!(json["questions"].as_array().unwrap().len(), 2);
+        assert_eq!(json["timings"]["total_ms"], 1622);
+
+        let mut out = Vec::new();
+        Json::answer(&results().questions[0].answers[0], Some(&timings), &mut out).unwrap();
+        let answer: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(answer["answer_id"], 10);
+        assert_eq!(answer["timings"]["total_ms"], 1622);
+    }
+
+    #[test]
+    fn test_sites() {
+        let site = |code: &str, url: &str, name: &str, audience: &str| Site {
+            api_site_parameter: code.to_string(),
+            site_url: url.to_string(),
+            name: name.to_string(),
+            audience: audience.to_string(),
+            ..Site::default()
+        };
+        let unix = site(
+            "unix",
+            "https://unix.stackexchange.com",
+            "Unix & Linux",
+            "users of Linux, FreeBSD and other Un*x-like operating systems",
+        );
+        let meta = site(
+            "unix.meta",
+            "https://unix.meta.stackexchange.com",
+            "Unix & Linux Meta",
+            "",
+        );
+        let mut out = Vec::new();
+        sites(&[&unix, &meta], &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "unix       https://unix.stackexchange.com       Unix & Linux       \
+             users of Linux, FreeBSD and other Un*x-like operating systems\n\
+             unix.meta  https://unix.meta.stackexchange.com  Unix & Linux Meta\n"
+        );
+
+        let mut out = Vec::new();
+        Json::sites(&[&unix], &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[0]["api_site_parameter"], "unix");
+        assert_eq!(json[0]["name"], "Unix & Linux");
+        assert_eq!(
+            json[0]["audience"],
+            "users of Linux, FreeBSD and other Un*x-like operating systems"
+        );
+    }
+
+    #[test]
+    fn test_short_links() {
+        let mut results = digest_results();
+        results.questions[0].link = Some(String::from(
+            "https://stackoverflow.com/questions/1/how-do-i-exit-vim",
+        ));
+        results.questions[0].answers[0].link = Some(String::from(
+            "https://stackoverflow.com/questions/1/how-do-i-exit-vim/10#10",
+        ));
+        let opts = FormatOpts {
+            width: Some(40),
+            locale: Locale::En,
+            color: false,
+            lucky_template: Template::parse("-- {link}").unwrap(),
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Short,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let render = |formatter: &dyn OutputFormatter| {
+            let mut out = Vec::new();
+            formatter.format(&results, &opts, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render(&Lucky), "-- https://stackoverflow.com/q/1");
+        let digest = render(&Digest);
+        assert!(digest.contains("\nhttps://stackoverflow.com/q/1\n"));
+        assert!(digest.contains("\nhttps://unix.stackexchange.com/q/2\n"));
+        assert!(render(&Atom).contains(r#"<link href="https://stackoverflow.com/q/1"/>"#));
+
+        // JSON restyles the links the API gave, and makes up none
+        let json: serde_json::Value = serde_json::from_str(&render(&Json)).unwrap();
+        assert_eq!(json[0]["link"], "https://stackoverflow.com/q/1");
+        assert_eq!(
+            json[0]["answers"][0]["link"],
+            "https://stackoverflow.com/a/10"
+        );
+        assert!(json[0]["answers"][1].get("link").is_none());
+        assert!(json[1].get("link").is_none());
+        // Full links are left as the API gave them, slug and all
+        let full = results.questions_linked(LinkStyle::Full);
+        assert_eq!(full[0].link, results.questions[0].link);
+    }
+
+    #[test]
+    fn test_json_error() {
+        let mut out = Vec::new();
+        Json::error(&Error::NoResults, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"error\":\"no_results\",\
+             \"message\":\"Sorry, couldn't find any answers to your question\"}\n"
+        );
+
+        let mut out = Vec::new();
+        let throttled = Error::Api {
+            site: Some(String::from("superuser")),
+            endpoint: Some(String::from("search/advanced")),
+            status: 502,
+            name: Some(String::from("throttle_violation")),
+            message: String::from("too many requests from this IP"),
+        };
+        Json::error(&Error::at_site("superuser", throttled), &mut out).unwrap();
+        let object: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            object,
+            serde_json::json!({
+                "error": "failure",
+                "message": "superuser: StackExchange API error 502 (throttle_violation): \
+                            too many requests from this IP",
+                "site": "superuser",
+                "endpoint": "search/advanced",
+                "status": 502,
+            })
+        );
+    }
+
+    #[test]
+    fn test_atom() {
+        let mut results = digest_results();
+        results.questions[0].creation_date = Some(1_250_000_000);
+        results.questions[0].last_activity_date = Some(1_792_152_000);
+        results.questions[1].creation_date = Some(1_300_000_000);
+        let doc = atom(&results);
+        let feed = doc.root_element();
+        assert!(feed.has_tag_name(("http://www.w3.org/2005/Atom", "feed")));
+        assert_eq!(child_text(feed, "title"), Some("so: exit vim"));
+        assert_eq!(child_text(feed, "id"), Some("urn:so:search:exit%20vim"));
+        assert_eq!(child_text(feed, "updated"), Some("2026-10-16T12:00:00Z"));
+
+        let entries: Vec<_> = feed
+            .children()
+            .filter(|n| n.has_tag_name("entry"))
+            .collect();
+        assert_eq!(entries.len(), 3);
+        let first = entries[0];
+        assert_eq!(child_text(first, "title"), Some("How do I exit Vim?"));
+        assert_eq!(
+            child_text(first, "id"),
+            Some("urn:so:question:stackoverflow:1")
+        );
+        assert_eq!(child_text(first, "published"), Some("2009-08-11T14:13:20Z"));
+        assert_eq!(child_text(first, "updated"), Some("2026-10-16T12:00:00Z"));
+        assert_eq!(child_text(first, "summary"), Some("Pull the plug."));
+        let link = first.children().find(|n| n.has_tag_name("link")).unwrap();
+        assert_eq!(
+            link.attribute("href"),
+            Some("https://stackoverflow.com/questions/1")
+        );
+        // Without activity, the creation date stands in
+        assert_eq!(
+            child_text(entries[1], "updated"),
+            Some("2011-03-13T07:06:40Z")
+        );
+        // Without dates or answers, the feed's date and the question body do
+        assert_eq!(
+            child_text(entries[2], "updated"),
+            Some("2026-10-16T12:00:00Z")
+        );
+        assert!(entries[2].children().all(|n| !n.has_tag_name("published")));
+        assert!(entries[2].children().all(|n| !n.has_tag_name("link")));
+    }
+
+    #[test]
+    fn test_atom_escaping() {
+        let mut results = results();
+        results.query = String::from("<b> & \"friends\"");
+        results.questions.truncate(1);
+        let title = "Why does <div class=\"a\"> & 'b' break? ]]> \u{1b}[0m";
+        results.questions[0].title = String::from(title);
+        results.questions[0].answers[0].body =
+            String::from("Use `&amp;` instead of `&`, or `<br/>`");
+        results.questions[0].site = String::from("my team/ü");
+        results.site_urls.insert(
+            String::from("my team/ü"),
+            String::from("https://x.com/?a=1&b=2"),
+        );
+        let doc = atom(&results);
+        let feed = doc.root_element();
+        assert_eq!(child_text(feed, "title"), Some("so: <b> & \"friends\""));
+        let entry = feed.children().find(|n| n.has_tag_name("entry")).unwrap();
+        // Control characters can't appear in XML at all
+        assert_eq!(
+            child_text(entry, "title"),
+            Some("Why does <div class=\"a\"> & 'b' break? ]]> [0m")
+        );
+        assert_eq!(
+            child_text(entry, "summary"),
+            Some("Use &amp; instead of &, or <br/>")
+        );
+        assert_eq!(
+            child_text(entry, "id"),
+            Some("urn:so:question:my%20team%2F%C3%BC:1")
+        );
+        let link = entry.children().find(|n| n.has_tag_name("link")).unwrap();
+        assert_eq!(
+            link.attribute("href"),
+            Some("https://x.com/?a=1&b=2/questions/1")
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! Plain text rendering of markdown, for pasting where formatting doesn't survive, e.g. tickets
+//! and email.
+//!
+//! Prose is wrapped at a fixed width, headings are underlined, list items and block quotes keep
+//! their markers, and links become numbered footnotes. Code is indented four spaces and never
+//! wrapped, since wrapping would break it; a block too wide for the width is marked `(code)`
+//! so that whoever reads it knows the long lines are meant to be that way. With `ascii_only`, the
+//! characters outside of ASCII are swapped for the look-alikes in `GLYPHS`.
+
+use pulldown_cmark::{Event, Parser, Tag};
+use std::borrow::Cow;
+
+/// Width to wrap at when none is given
+pub const DEFAULT_WIDTH: usize = 72;
+
+/// Narrowest width text is wrapped at, however little room nesting leaves it
+const MIN_WIDTH: usize = 20;
+
+/// Indent of code blocks
+const CODE_INDENT: &str = "    ";
+
+/// Line above a code block with lines too long for the width
+const CODE_MARKER: &str = "(code)";
+
+/// ASCII stand-ins for the characters outside of ASCII that posts tend to have
+const GLYPHS: &[(char, &str)] = &[
+    ('\u{a0}', " "),
+    ('\u{200b}', ""),
+    ('\u{feff}', ""),
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201a}', "'"),
+    ('\u{2032}', "'"),
+    ('\u{201c}', "\""),
+    ('\u{201d}', "\""),
+    ('\u{201e}', "\""),
+    ('\u{2033}', "\""),
+    ('\u{ab}', "<<"),
+    ('\u{bb}', ">>"),
+    ('\u{2039}', "<"),
+    ('\u{203a}', ">"),
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', "-"),
+    ('\u{2212}', "-"),
+    ('\u{2014}', "--"),
+    ('\u{2015}', "--"),
+    ('\u{2026}', "..."),
+    ('\u{2022}', "*"),
+    ('\u{b7}', "*"),
+    ('\u{2190}', "<-"),
+    ('\u{2192}', "->"),
+    ('\u{2194}', "<->"),
+    ('\u{21d0}', "<="),
+    ('\u{21d2}', "=>"),
+    ('\u{2264}', "<="),
+    ('\u{2265}', ">="),
+    ('\u{2260}', "!="),
+    ('\u{2248}', "~="),
+    ('\u{d7}', "x"),
+    ('\u{f7}', "/"),
+    ('\u{b0}', " deg"),
+    ('\u{2713}', "[x]"),
+    ('\u{2714}', "[x]"),
+    ('\u{2717}', "[ ]"),
+    ('\u{2718}', "[ ]"),
+    ('\u{a9}', "(c)"),
+    ('\u{ae}', "(R)"),
+    ('\u{2122}', "(TM)"),
+    ('\u{20ac}', "EUR"),
+    ('\u{a3}', "GBP"),
+    ('\u{df}', "ss"),
+    ('\u{e6}', "ae"),
+    ('\u{c6}', "AE"),
+    ('\u{153}', "oe"),
+    ('\u{152}', "OE"),
+];
+
+/// Letters with diacritics, by the letter without them
+const LETTERS: &[(&str, char)] = &[
+    ("àáâãäåā", 'a'),
+    ("ÀÁÂÃÄÅĀ", 'A'),
+    ("çćč", 'c'),
+    ("ÇĆČ", 'C'),
+    ("èéêëēę", 'e'),
+    ("ÈÉÊËĒĘ", 'E'),
+    ("ìíîïī", 'i'),
+    ("ÌÍÎÏĪ", 'I'),
+    ("łľ", 'l'),
+    ("ŁĽ", 'L'),
+    ("ñńň", 'n'),
+    ("ÑŃŇ", 'N'),
+    ("òóôõöøō", 'o'),
+    ("ÒÓÔÕÖØŌ", 'O'),
+    ("śšş", 's'),
+    ("ŚŠŞ", 'S'),
+    ("ùúûüūů", 'u'),
+    ("ÙÚÛÜŪŮ", 'U'),
+    ("ýÿ", 'y'),
+    ("Ý", 'Y'),
+    ("źżž", 'z'),
+    ("ŹŻŽ", 'Z'),
+];
+
+/// `text` with each character outside of ASCII swapped for its look-alike in `GLYPHS`, or its
+/// letter without diacritics, or else `?`
+pub fn ascii(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let mut ascii = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii.push(c);
+        } else if let Some((_, glyph)) = GLYPHS.iter().find(|(g, _)| *g == c) {
+            ascii.push_str(glyph);
+        } else if let Some((_, letter)) = LETTERS.iter().find(|(ls, _)| ls.contains(c)) {
+            ascii.push(*letter);
+        } else {
+            ascii.push('?');
+        }
+    }
+    Cow::Owned(ascii)
+}
+
+/// `text` wrapped at `width` characters, breaking between words; a word longer than that gets a
+/// line of its own. Line breaks in `text` are kept.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut length = 0;
+        for word in paragraph.split_whitespace() {
+            let word_length = word.chars().count();
+            if length > 0 && length + 1 + word_length > width {
+                lines.push(std::mem::take(&mut line));
+                length = 0;
+            }
+            if length > 0 {
+                line.push(' ');
+                length += 1;
+            }
+            line.push_str(word);
+            length += word_length;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// `text` wrapped at `width`, underlined with `underline`
+pub fn heading(text: &str, underline: char, width: usize) -> String {
+    let lines = wrap(text, width);
+    let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let mut heading = lines.join("\n");
+    heading.push('\n');
+    heading.extend(std::iter::repeat_n(underline, longest));
+    heading.push('\n');
+    heading
+}
+
+/// Links to list below the text referring to them, numbered in the order they came up
+#[derive(Debug, Default)]
+pub struct Footnotes {
+    links: Vec<String>,
+    /// How many of `links` were listed already
+    listed: usize,
+}
+
+impl Footnotes {
+    /// Number of the footnote linking to `url`, added unless there's one already
+    pub fn add(&mut self, url: &str) -> usize {
+        match self.links.iter().position(|link| link == url) {
+            Some(i) => i + 1,
+            None => {
+                self.links.push(url.to_string());
+                self.links.len()
+            }
+        }
+    }
+
+    /// Footnotes added since the last call, one per line, e.g. `[1] https://stackoverflow.com`
+    pub fn take(&mut self) -> Vec<String> {
+        let listed = self.listed;
+        self.listed = self.links.len();
+        self.links[listed..]
+            .iter()
+            .enumerate()
+            .map(|(i, link)| format!("[{}] {}", listed + i + 1, link))
+            .collect()
+    }
+}
+
+/// `md` as plain text wrapped at `width`, with its links added to `footnotes`
+pub fn render(md: &str, width: usize, footnotes: &mut Footnotes) -> String {
+    let mut renderer = Renderer {
+        width,
+        footnotes,
+        lines: Vec::new(),
+        blocks: Vec::new(),
+        lists: Vec::new(),
+        inline: String::new(),
+        links: Vec::new(),
+        code: None,
+    };
+    for event in Parser::new(md) {
+        renderer.event(event);
+    }
+    renderer.flush();
+    while renderer.lines.last().is_some_and(|l| l.is_empty()) {
+        renderer.lines.pop();
+    }
+    let mut text = renderer.lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Block that prefixes the lines inside it
+enum Block {
+    Quote,
+    /// List item with its marker, e.g. `- ` or `2. `, which only its first line shows
+    Item {
+        marker: String,
+        started: bool,
+    },
+}
+
+struct Renderer<'a> {
+    width: usize,
+    footnotes: &'a mut Footnotes,
+    lines: Vec<String>,
+    /// Blocks the text is in, outermost first
+    blocks: Vec<Block>,
+    /// Number of the next item of each list the text is in, if it's numbered
+    lists: Vec<Option<u64>>,
+    /// Text of the paragraph or heading so far
+    inline: String,
+    /// Links the text is in, and where their text starts in `inline`
+    links: Vec<(String, usize)>,
+    /// Code of the code block so far, if the text is in one
+    code: Option<String>,
+}
+
+impl Renderer<'_> {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some(code) => code.push_str(&text),
+                None => self.inline.push_str(&text),
+            },
+            Event::Code(code) => {
+                self.inline.push('`');
+                self.inline.push_str(&code);
+                self.inline.push('`');
+            }
+            Event::SoftBreak => self.inline.push(' '),
+            Event::HardBreak => self.inline.push('\n'),
+            Event::Rule => {
+                self.flush();
+                let rule = "-".repeat(self.room());
+                self.line(&rule);
+                self.blank();
+            }
+            Event::TaskListMarker(done) => self.inline.push_str(if done { "[x] " } else { "[ ] " }),
+            // Markup plain text has no use for
+            Event::Html(_) | Event::FootnoteReference(_) => (),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::BlockQuote => {
+                self.flush();
+                self.blocks.push(Block::Quote);
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.code = Some(String::new());
+            }
+            Tag::List(first) => {
+                // The text of a tight item comes before the list nested in it
+                self.flush();
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => String::from("- "),
+                };
+                self.blocks.push(Block::Item {
+                    marker,
+                    started: false,
+                });
+            }
+            Tag::Link(_, url, _) => self.links.push((url.to_string(), self.inline.len())),
+            Tag::Image(_, url, _) => {
+                self.inline.push_str("[image: ");
+                self.links.push((url.to_string(), self.inline.len()));
+            }
+            _ => (),
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.flush();
+                self.blank();
+            }
+            Tag::Heading(level) => {
+                let text = std::mem::take(&mut self.inline);
+                let underline = if level == 1 { '=' } else { '-' };
+                for line in heading(text.trim(), underline, self.room()).lines() {
+                    self.line(line);
+                }
+                self.blank();
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                // The quote's own separator after its last paragraph goes, for the one outside it
+                let quotes = self.quotes();
+                if self.lines.last().is_some_and(|l| *l == ">".repeat(quotes)) {
+                    self.lines.pop();
+                }
+                self.blocks.pop();
+                self.blank();
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code.take().unwrap_or_default();
+                let room = self.room();
+                let lines: Vec<&str> = code.trim_end_matches('\n').lines().collect();
+                if lines
+                    .iter()
+                    .any(|l| CODE_INDENT.len() + l.chars().count() > room)
+                {
+                    self.line(CODE_MARKER);
+                }
+                for line in lines {
+                    self.line(&format!("{}{}", CODE_INDENT, line));
+                }
+                self.blank();
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            Tag::Item => {
+                self.flush();
+                self.blocks.pop();
+            }
+            Tag::Link(..) => {
+                if let Some((url, start)) = self.links.pop() {
+                    // Autolinks show their link already
+                    if self.inline[start..].trim() != url {
+                        let n = self.footnotes.add(&url);
+                        self.inline.push_str(&format!("[{}]", n));
+                    }
+                }
+            }
+            Tag::Image(..) => {
+                if let Some((url, _)) = self.links.pop() {
+                    let n = self.footnotes.add(&url);
+                    self.inline.push_str(&format!("][{}]", n));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Characters left for text on a line, after the prefixes of the blocks it's in
+    fn room(&self) -> usize {
+        self.width
+            .saturating_sub(self.prefix().chars().count())
+            .max(MIN_WIDTH)
+    }
+
+    /// What lines start with in the blocks the text is in
+    fn prefix(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| match block {
+                Block::Quote => String::from("> "),
+                Block::Item { marker, started } if !started => marker.clone(),
+                Block::Item { marker, .. } => " ".repeat(marker.len()),
+            })
+            .collect()
+    }
+
+    /// Add `text` as a line of the blocks it's in
+    fn line(&mut self, text: &str) {
+        let line = format!("{}{}", self.prefix(), text);
+        self.lines.push(line.trim_end().to_string());
+        for block in &mut self.blocks {
+            if let Block::Item { started, .. } = block {
+                *started = true;
+            }
+        }
+    }
+
+    /// Separate the previous block from the next one
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|l| !l.is_empty()) {
+            let separator = ">".repeat(self.quotes());
+            self.lines.push(separator);
+        }
+    }
+
+    /// How many quotes the text is in
+    fn quotes(&self) -> usize {
+        self.blocks
+            .iter()
+            .filter(|b| matches!(b, Block::Quote))
+            .count()
+    }
+
+    /// Wrap the text of the paragraph so far into lines
+    fn flush(&mut self) {
+        let text = std::mem::take(&mut self.inline);
+        if text.trim().is_empty() {
+            return;
+        }
+        for line in wrap(&text, self.room()) {
+            self.line(&line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(md: &str, width: usize) -> String {
+        render(md, width, &mut Footnotes::default())
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("the quick brown fox jumps", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+        // Too long to break, so on a line of its own
+        assert_eq!(
+            wrap("see https://example.com/a/long/path now", 10),
+            vec!["see", "https://example.com/a/long/path", "now"]
+        );
+        assert_eq!(wrap("one\ntwo", 10), vec!["one", "two"]);
+        assert_eq!(wrap("", 10), vec![""]);
+    }
+
+    #[test]
+    fn test_heading() {
+        assert_eq!(heading("Exit Vim", '=', 72), "Exit Vim\n========\n");
+        assert_eq!(heading("How to exit", '-', 8), "How to\nexit\n------\n");
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let mut footnotes = Footnotes::default();
+        assert_eq!(footnotes.add("https://a.com"), 1);
+        assert_eq!(footnotes.add("https://b.com"), 2);
+        assert_eq!(footnotes.add("https://a.com"), 1);
+        assert_eq!(
+            footnotes.take(),
+            vec!["[1] https://a.com", "[2] https://b.com"]
+        );
+        assert!(footnotes.take().is_empty());
+        // Numbers carry on
+        assert_eq!(footnotes.add("https://c.com"), 3);
+        assert_eq!(footnotes.take(), vec!["[3] https://c.com"]);
+    }
+
+    #[test]
+    fn test_ascii() {
+        assert!(matches!(ascii("plain"), Cow::Borrowed("plain")));
+        assert_eq!(
+            ascii("\u{201c}Don\u{2019}t\u{201d} \u{2014} it\u{2026} caf\u{e9} \u{2192} \u{2713}"),
+            "\"Don't\" -- it... cafe -> [x]"
+        );
+        assert_eq!(ascii("\u{1f980} \u{4e2d}"), "? ?");
+    }
+
+    #[test]
+    fn test_links() {
+        let mut footnotes = Footnotes::default();
+        let text = render(
+            "See [the docs](https://vim.org) and <https://neovim.io>, \
+             or ![a screenshot](https://i.imgur.com/x.png).",
+            72,
+            &mut footnotes,
+        );
+        assert_eq!(
+            text,
+            "See the docs[1] and https://neovim.io, or [image: a screenshot][2].\n"
+        );
+        assert_eq!(
+            footnotes.take(),
+            vec!["[1] https://vim.org", "[2] https://i.imgur.com/x.png"]
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let md = "- one\n- two is a longer item here\n  - nested\n\n3. three\n4. four";
+        assert_eq!(
+            plain(md, 24),
+            "- one\n- two is a longer item\n  here\n  - nested\n\n3. three\n4. four\n"
+        );
+    }
+
+    #[test]
+    fn test_code() {
+        let md = "Run\n\n```\nshort\n```\n\nor\n\n    a line far too long for the width\n";
+        assert_eq!(
+            plain(md, 24),
+            "Run\n\n    short\n\nor\n\n(code)\n    a line far too long for the width\n"
+        );
+    }
+
+    #[test]
+    fn test_quotes() {
+        assert_eq!(
+            plain("> quoted words here and there\n>\n> more", 22),
+            "> quoted words here\n> and there\n>\n> more\n"
+        );
+    }
+}
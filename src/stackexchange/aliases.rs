@@ -0,0 +1,200 @@
+//! Question ids that changed, because the question was merged into another one.
+//!
+//! Asking SE for a merged question gets back the question it was merged into, under that
+//! question's id. When a batch of requested ids comes back with exactly one id missing and exactly
+//! one id nobody asked for, the two are recorded as an alias, so that stores keyed by question id
+//! (the question cache, notes) can follow the question to its new id. When several are missing,
+//! each is asked for on its own to tell which went where, rather than guessed at.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::utils;
+
+/// Merges more than this deep are assumed to be a cycle
+const MAX_CHAIN: usize = 16;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+struct Alias {
+    site: String,
+    old_id: u32,
+    new_id: u32,
+}
+
+/// Old question ids and what they became, by site
+#[derive(Debug, Clone)]
+pub struct Aliases {
+    path: PathBuf,
+    map: Arc<Mutex<BTreeMap<(String, u32), u32>>>,
+}
+
+impl Aliases {
+    /// Aliases stored at `path`, which is fine not to exist yet
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let aliases: Vec<Alias> = match utils::open_file(&path)? {
+            Some(file) => {
+                serde_json::from_reader(file).map_err(|_| Error::MalformedFile(path.clone()))?
+            }
+            None => Vec::new(),
+        };
+        let map = aliases
+            .into_iter()
+            .map(|a| ((a.site, a.old_id), a.new_id))
+            .collect();
+        Ok(Aliases {
+            path,
+            map: Arc::new(Mutex::new(map)),
+        })
+    }
+
+    /// Aliases in the project's data directory
+    pub fn open() -> Result<Self> {
+        Self::load(Config::project_dir()?.data_dir().join("aliases.json"))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        // Holding the lock throughout keeps concurrent saves from clobbering each other's file
+        let map = self.lock();
+        let aliases: Vec<Alias> = map
+            .iter()
+            .map(|((site, old_id), new_id)| Alias {
+                site: site.clone(),
+                old_id: *old_id,
+                new_id: *new_id,
+            })
+            .collect();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        utils::write_atomic(&self.path, &serde_json::to_vec_pretty(&aliases)?)
+    }
+
+    /// Current id of question `id` on `site`, following merges of merges
+    pub fn resolve(&self, site: &str, id: u32) -> u32 {
+        let map = self.lock();
+        let mut current = id;
+        for _ in 0..MAX_CHAIN {
+            match map.get(&(site.to_string(), current)) {
+                Some(&next) if next != id => current = next,
+                _ => break,
+            }
+        }
+        current
+    }
+
+    /// Record that question `old_id` on `site` is now `new_id`. Returns whether this is news.
+    pub fn record(&self, site: &str, old_id: u32, new_id: u32) -> bool {
+        if old_id == new_id {
+            return false;
+        }
+        self.lock().insert((site.to_string(), old_id), new_id) != Some(new_id)
+    }
+
+    /// All aliases, as (site, old id, new id)
+    pub fn list(&self) -> Vec<(String, u32, u32)> {
+        self.lock()
+            .iter()
+            .map(|((site, old_id), new_id)| (site.clone(), *old_id, *new_id))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<(String, u32), u32>> {
+        self.map.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Spot a merged question in the response to a request for `requested` ids, returning its old
+/// and new id. Questions can also be missing because they were deleted, so only a single missing
+/// id alongside a single unrequested one is taken as a merge.
+pub fn detect(requested: &[u32], returned: &[u32]) -> Option<(u32, u32)> {
+    let missing: Vec<u32> = requested
+        .iter()
+        .filter(|id| !returned.contains(id))
+        .copied()
+        .collect();
+    let unrequested: Vec<u32> = returned
+        .iter()
+        .filter(|id| !requested.contains(id))
+        .copied()
+        .collect();
+    match (missing.as_slice(), unrequested.as_slice()) {
+        ([old], [new]) => Some((*old, *new)),
+        _ => None,
+    }
+}
+
+/// Requested ids that may have been merged when `detect` can't pair them up with the unrequested
+/// ones returned, along with how many merges there can be at most: none unless something nobody
+/// asked for came back.
+pub fn ambiguous(requested: &[u32], returned: &[u32]) -> (Vec<u32>, usize) {
+    let unrequested = returned.iter().filter(|id| !requested.contains(id)).count();
+    if unrequested == 0 || detect(requested, returned).is_some() {
+        return (Vec::new(), 0);
+    }
+    let missing = requested
+        .iter()
+        .filter(|id| !returned.contains(id))
+        .copied()
+        .collect();
+    (missing, unrequested)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_aliases(name: &str) -> Aliases {
+        let dir = std::env::temp_dir().join(format!("so-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Aliases::load(dir.join("aliases.json")).unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(&[1, 2, 3], &[3, 1, 7]), Some((2, 7)));
+        assert_eq!(detect(&[1, 2, 3], &[1, 2, 3]), None);
+        // Deleted, not merged
+        assert_eq!(detect(&[1, 2, 3], &[1, 3]), None);
+        // Can't tell which went where
+        assert_eq!(detect(&[1, 2, 3], &[1, 7, 8]), None);
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        assert_eq!(ambiguous(&[1, 2, 3], &[1, 7, 8]), (vec![2, 3], 2));
+        assert_eq!(ambiguous(&[1, 2, 3, 4], &[1, 7]), (vec![2, 3, 4], 1));
+        // Nothing merged, or plain enough for `detect`
+        assert_eq!(ambiguous(&[1, 2, 3], &[1, 3]), (vec![], 0));
+        assert_eq!(ambiguous(&[1, 2, 3], &[3, 1, 7]), (vec![], 0));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let aliases = temp_aliases("aliases-resolve");
+        assert_eq!(aliases.resolve("stackoverflow", 1), 1);
+        assert!(aliases.record("stackoverflow", 1, 2));
+        assert!(!aliases.record("stackoverflow", 1, 2));
+        assert!(aliases.record("stackoverflow", 2, 3));
+        assert_eq!(aliases.resolve("stackoverflow", 1), 3);
+        assert_eq!(aliases.resolve("superuser", 1), 1);
+
+        // A cycle doesn't hang
+        aliases.record("unix", 4, 5);
+        aliases.record("unix", 5, 4);
+        aliases.resolve("unix", 4);
+    }
+
+    #[test]
+    fn test_load_save() {
+        let aliases = temp_aliases("aliases-save");
+        aliases.record("stackoverflow", 1, 2);
+        aliases.save().unwrap();
+        let loaded = Aliases::load(aliases.path.clone()).unwrap();
+        assert_eq!(loaded.resolve("stackoverflow", 1), 2);
+    }
+}
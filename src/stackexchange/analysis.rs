@@ -0,0 +1,278 @@
+//! Breaking query text down into the terms that get compared with titles, for everything that
+//! judges how well results match a query: the lucky confidence check, `auto_requote` and finding
+//! the closest cached query.
+//!
+//! Which words are stop words, and how the rest are stemmed, depends on the language queries are
+//! written in; see `query_language` in the config.
+
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Language of the queries, for localized sites like es.stackoverflow.com
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    English,
+    French,
+    German,
+    Portuguese,
+    Russian,
+    Spanish,
+    /// Neither drop stop words nor stem
+    None,
+}
+
+const ENGLISH: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "do", "does", "for", "from",
+    "get", "how", "i", "if", "in", "into", "is", "it", "its", "my", "no", "not", "of", "on", "or",
+    "so", "such", "that", "the", "their", "then", "there", "these", "this", "to", "use", "using",
+    "was", "way", "what", "when", "where", "which", "who", "why", "will", "with", "you", "your",
+];
+
+const FRENCH: &[&str] = &[
+    "à", "au", "aux", "avec", "ce", "ces", "comment", "dans", "de", "des", "du", "en", "est", "et",
+    "faire", "il", "je", "la", "le", "les", "mon", "ne", "ou", "où", "par", "pas", "peut", "pour",
+    "pourquoi", "quand", "que", "quel", "qui", "sur", "un", "une", "utiliser",
+];
+
+const GERMAN: &[&str] = &[
+    "als", "am", "an", "auf", "aus", "bei", "das", "dass", "dem", "den", "der", "des", "die",
+    "ein", "eine", "einem", "einen", "einer", "es", "für", "ich", "im", "in", "ist", "kann", "man",
+    "mein", "meine", "mit", "nicht", "oder", "so", "um", "und", "von", "warum", "was", "wie", "wo",
+    "zu", "zum", "zur",
+];
+
+const PORTUGUESE: &[&str] = &[
+    "a", "ao", "com", "como", "da", "das", "de", "do", "dos", "e", "é", "em", "eu", "fazer", "meu",
+    "minha", "na", "no", "não", "o", "os", "ou", "para", "por", "posso", "qual", "que", "se",
+    "sem", "um", "uma", "usar",
+];
+
+const RUSSIAN: &[&str] = &[
+    "а",
+    "в",
+    "во",
+    "где",
+    "для",
+    "и",
+    "из",
+    "к",
+    "как",
+    "какой",
+    "ли",
+    "мне",
+    "можно",
+    "на",
+    "не",
+    "но",
+    "о",
+    "от",
+    "по",
+    "почему",
+    "при",
+    "с",
+    "что",
+    "это",
+    "я",
+];
+
+const SPANISH: &[&str] = &[
+    "a", "al", "como", "cómo", "con", "cual", "cuál", "de", "del", "el", "en", "es", "hacer",
+    "hay", "la", "las", "lo", "los", "mi", "no", "o", "para", "por", "puedo", "que", "qué", "se",
+    "sin", "su", "un", "una", "usar", "y",
+];
+
+impl Language {
+    /// Words that carry no meaning for the purposes of matching a query to a title
+    fn stop_words(self) -> &'static [&'static str] {
+        match self {
+            Language::English => ENGLISH,
+            Language::French => FRENCH,
+            Language::German => GERMAN,
+            Language::Portuguese => PORTUGUESE,
+            Language::Russian => RUSSIAN,
+            Language::Spanish => SPANISH,
+            Language::None => &[],
+        }
+    }
+
+    fn stemmer(self) -> Option<Stemmer> {
+        let algorithm = match self {
+            // Has its own stemmer, see `stem_english`
+            Language::English | Language::None => return None,
+            Language::French => Algorithm::French,
+            Language::German => Algorithm::German,
+            Language::Portuguese => Algorithm::Portuguese,
+            Language::Russian => Algorithm::Russian,
+            Language::Spanish => Algorithm::Spanish,
+        };
+        Some(Stemmer::create(algorithm))
+    }
+}
+
+/// Lowercased words of `text`, keeping `+` and `#` as in `c++` and `c#`
+pub fn tokens(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '+' || c == '#'))
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Normalized set of meaningful terms in `text`: its tokens in `language`, stripped of stop words
+/// and stemmed
+pub fn terms(text: &str, language: Language) -> HashSet<String> {
+    all_terms(text, language).into_iter().collect()
+}
+
+/// Meaningful terms of `text` as `terms` has them, but in order and repeats included, for counting
+pub fn all_terms(text: &str, language: Language) -> Vec<String> {
+    let stop_words = language.stop_words();
+    let stemmer = language.stemmer();
+    tokens(text)
+        .filter(|w| !stop_words.contains(&w.as_str()))
+        .map(|w| match (&stemmer, language) {
+            (Some(stemmer), _) => stemmer.stem(&w).into_owned(),
+            (None, Language::English) => stem_english(&w),
+            (None, _) => w,
+        })
+        .collect()
+}
+
+/// A deliberately light English stemmer: strip the handful of suffixes that most often separate
+/// a query term from the same word in a title ("exiting" vs "exit", "lists" vs "list"). Snowball
+/// would stem more, and differently from what the relevance thresholds were tuned with.
+fn stem_english(word: &str) -> String {
+    if !word.is_ascii() || word.len() < 4 {
+        return word.to_string();
+    }
+    if let Some(base) = word.strip_suffix("ies") {
+        return format!("{}y", base);
+    }
+    for suffix in &["sses", "shes", "ches", "xes"] {
+        if word.ends_with(suffix) {
+            return word[..word.len() - 2].to_string();
+        }
+    }
+    if word.ends_with('s') && !word.ends_with("ss") {
+        return word[..word.len() - 1].to_string();
+    }
+    for suffix in &["ing", "ed"] {
+        if let Some(base) = word.strip_suffix(suffix) {
+            if base.len() >= 3 {
+                return base.to_string();
+            }
+        }
+    }
+    word.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(text: &str, language: Language) -> Vec<String> {
+        let mut terms: Vec<String> = terms(text, language).into_iter().collect();
+        terms.sort();
+        terms
+    }
+
+    #[test]
+    fn test_tokens() {
+        let words: Vec<String> =
+            tokens("How do I use C++ and C#? (git --force-with-lease)").collect();
+        assert_eq!(
+            words,
+            vec!["how", "do", "i", "use", "c++", "and", "c#", "git", "force", "with", "lease"]
+        );
+        assert_eq!(tokens("  ").count(), 0);
+    }
+
+    #[test]
+    fn test_all_terms() {
+        assert_eq!(
+            all_terms("Exiting vim, then exit vim again", Language::English),
+            vec!["exit", "vim", "exit", "vim", "again"]
+        );
+    }
+
+    #[test]
+    fn test_english() {
+        assert_eq!(
+            sorted("How do I exit Vim?", Language::English),
+            vec!["exit", "vim"]
+        );
+        assert_eq!(
+            sorted("c++ queries classes", Language::English),
+            vec!["c++", "class", "query"]
+        );
+    }
+
+    #[test]
+    fn test_stem_english() {
+        assert_eq!(stem_english("lists"), "list");
+        assert_eq!(stem_english("exiting"), "exit");
+        assert_eq!(stem_english("class"), "class");
+        assert_eq!(stem_english("classes"), "class");
+        assert_eq!(stem_english("queries"), "query");
+        assert_eq!(stem_english("lifetimes"), "lifetime");
+        assert_eq!(stem_english("used"), "used");
+        assert_eq!(stem_english("is"), "is");
+        assert_eq!(stem_english("c++"), "c++");
+    }
+
+    #[test]
+    fn test_german() {
+        assert_eq!(
+            sorted(
+                "Wie kann ich Dateien in einem Ordner zählen?",
+                Language::German
+            ),
+            vec!["datei", "ordn", "zahl"]
+        );
+        assert_eq!(
+            sorted("Datei im Ordner zählen", Language::German),
+            sorted("Dateien in Ordnern zählen", Language::German)
+        );
+    }
+
+    #[test]
+    fn test_spanish() {
+        assert_eq!(
+            sorted(
+                "¿Cómo puedo ordenar una lista en Python?",
+                Language::Spanish
+            ),
+            vec!["list", "orden", "python"]
+        );
+        assert_eq!(
+            sorted("ordenando listas", Language::Spanish),
+            sorted("ordenar lista", Language::Spanish)
+        );
+    }
+
+    #[test]
+    fn test_other_languages() {
+        assert_eq!(
+            sorted("Comment trier une liste en Python", Language::French),
+            vec!["list", "python", "tri"]
+        );
+        assert_eq!(
+            sorted("Como ordenar uma lista em Python", Language::Portuguese),
+            vec!["list", "orden", "python"]
+        );
+        assert_eq!(
+            sorted("Как отсортировать список в Python", Language::Russian),
+            vec!["python", "отсортирова", "список"]
+        );
+    }
+
+    #[test]
+    fn test_none() {
+        assert_eq!(
+            sorted("How do I exit Vim", Language::None),
+            vec!["do", "exit", "how", "i", "vim"]
+        );
+        assert_eq!(sorted("exiting", Language::None), vec!["exiting"]);
+    }
+}
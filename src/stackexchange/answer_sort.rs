@@ -0,0 +1,115 @@
+//! Order the answers of a question are listed in, and lucky mode picks from.
+//!
+//! The API hands answers over sorted by votes. `accepted` pins the accepted answer, if there is
+//! one, above the others, and `newest` lists the latest first, for when the top answers have aged
+//! badly. Ties, and answers without a creation date, e.g. cached by older versions, go by votes.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+use super::api::Answer;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AnswerSort {
+    /// Highest score first
+    #[default]
+    Votes,
+    /// The accepted answer first, then by votes
+    Accepted,
+    /// Latest first, then by votes
+    Newest,
+}
+
+impl AnswerSort {
+    /// Every order, in the order the TUI cycles through them
+    pub const ALL: [AnswerSort; 3] = [AnswerSort::Votes, AnswerSort::Accepted, AnswerSort::Newest];
+
+    /// Sort `answers` in this order
+    pub fn sort<S>(self, answers: &mut [Answer<S>]) {
+        match self {
+            AnswerSort::Votes => answers.sort_by_key(|a| Reverse(a.score)),
+            AnswerSort::Accepted => answers.sort_by_key(|a| (!a.is_accepted, Reverse(a.score))),
+            // `None` is less than any date, so answers without one go last
+            AnswerSort::Newest => {
+                answers.sort_by_key(|a| (Reverse(a.creation_date), Reverse(a.score)))
+            }
+        }
+    }
+
+    /// The order after this one, wrapping around
+    pub fn next(self) -> Self {
+        let i = AnswerSort::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        AnswerSort::ALL[(i + 1) % AnswerSort::ALL.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(id: u32, score: i32, is_accepted: bool, creation_date: Option<i64>) -> Answer<()> {
+        Answer {
+            id,
+            score,
+            is_accepted,
+            creation_date,
+            ..Answer::default()
+        }
+    }
+
+    /// An old accepted answer, outvoted by a newer one, and one cached without its date
+    fn answers() -> Vec<Answer<()>> {
+        vec![
+            answer(1, 40, true, Some(1_300_000_000)),
+            answer(2, 5, false, Some(1_600_000_000)),
+            answer(3, 90, false, Some(1_400_000_000)),
+            answer(4, 12, false, None),
+        ]
+    }
+
+    fn sorted(sort: AnswerSort, mut answers: Vec<Answer<()>>) -> Vec<u32> {
+        sort.sort(&mut answers);
+        answers.iter().map(|a| a.id).collect()
+    }
+
+    #[test]
+    fn test_votes() {
+        assert_eq!(sorted(AnswerSort::Votes, answers()), vec![3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn test_accepted() {
+        assert_eq!(sorted(AnswerSort::Accepted, answers()), vec![1, 3, 4, 2]);
+        // Nothing to pin, so by votes
+        let unaccepted = answers()
+            .into_iter()
+            .map(|a| Answer {
+                is_accepted: false,
+                ..a
+            })
+            .collect();
+        assert_eq!(sorted(AnswerSort::Accepted, unaccepted), vec![3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn test_newest() {
+        assert_eq!(sorted(AnswerSort::Newest, answers()), vec![2, 3, 1, 4]);
+        // Nothing to go by, so by votes
+        let undated = answers()
+            .into_iter()
+            .map(|a| Answer {
+                creation_date: None,
+                ..a
+            })
+            .collect();
+        assert_eq!(sorted(AnswerSort::Newest, undated), vec![3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn test_next() {
+        assert_eq!(AnswerSort::Votes.next(), AnswerSort::Accepted);
+        assert_eq!(AnswerSort::Accepted.next(), AnswerSort::Newest);
+        assert_eq!(AnswerSort::Newest.next(), AnswerSort::Votes);
+    }
+}
@@ -1,42 +1,188 @@
+use futures::stream::{StreamExt, TryStreamExt};
 use rayon::prelude::*;
 use reqwest::header;
-use reqwest::Client;
 use reqwest::Url;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
-use crate::error::Result;
-use crate::tui::markdown;
+use super::preprocess::{self, Pass};
+use crate::config::{SearchEngine, Team};
+use crate::error::{Error, Result};
+use crate::format::Locale;
 
-/// StackExchange API v2.2 URL
-// TODO why not https?
-const SE_API_URL: &str = "http://api.stackexchange.com";
-const SE_API_VERSION: &str = "2.2";
+use super::aliases::{self, Aliases};
+use super::backoff::{self, Backoff};
+use super::filters::{Derived, Filters};
+use super::isolation::{self, Failure, Failures, Stage};
+use super::lossy::{self, Skipped};
+use super::network::{Network, OfflinePolicy, Timeouts};
+use super::question_cache::QuestionCache;
+use super::quota::{Quota, Reading};
+use super::sanitize::{self, sanitize};
+use super::timings::Recorder;
+
+/// Base of the public API, unless `api_url` in the config points elsewhere
+pub const SE_API_URL: &str = "https://api.stackexchange.com";
+/// Version of the public API, unless `api_version` in the config pins another
+pub const SE_API_VERSION: &str = "2.3";
 
 /// Filter generated to include only the fields needed to populate
 /// the structs below. Go here to make new filters:
 /// [create filter](https://api.stackexchange.com/docs/create-filter).
 const SE_FILTER: &str = ".DND5X2VHHUH8HyJzpjo)5NvdHI3w6auG";
 
+/// Fields added to the configured filter, which `SE_FILTER` predates. Filters are opaque ids, so
+/// the filter requests use is created from the configured one the first time it's needed, see
+/// `Filters`.
+const SE_FILTER_INCLUDE: &str = "question.tags;question.link;answer.link;question.owner;\
+    answer.owner;answer.creation_date;shallow_user.display_name;shallow_user.reputation";
+
+/// Field the slim filter leaves out of the configured filter, for modes that never show question
+/// bodies
+const SE_SLIM_FILTER_EXCLUDE: &str = "question.body_markdown";
+
+/// Built-in filter used when SE rejects `SE_FILTER`. It returns HTML `body` instead of
+/// `body_markdown` and doesn't include answers, so those are fetched separately.
+const SE_FALLBACK_FILTER: &str = "withbody";
+
+/// Built-in filter comments are derived from, which has everything but their markdown
+const SE_COMMENTS_FILTER_BASE: &str = "default";
+
+/// Field added to `SE_COMMENTS_FILTER_BASE`; without it, comments come with an HTML `body`
+const SE_COMMENTS_FILTER_INCLUDE: &str = "comment.body_markdown";
+
+/// Most comments fetched for an answer, which is as many as one request returns
+const SE_COMMENTS_PAGESIZE: &str = "100";
+
+/// Most ids the /questions/{ids} endpoint accepts in one request
+const SE_IDS_PER_REQUEST: usize = 100;
+
+/// Limit on concurrent requests for batches of ids on a single site, which is kept below the
+/// overall `max_concurrent_requests` so that one site can't take up every connection
+const SITE_CONCURRENT_REQUESTS_LIMIT: usize = 2;
+
 /// Pagesize when fetching all SE sites. Should be good for many years...
 const SE_SITES_PAGESIZE: u16 = 10000;
 
+/// Header carrying the personal access token for Stack Overflow for Teams
+const TEAMS_TOKEN_HEADER: &str = "X-API-Access-Token";
+
 /// Represents a StackExchange answer with a custom selection of fields from
 /// the [StackExchange docs](https://api.stackexchange.com/docs/types/answer)
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 pub struct Answer<S> {
     #[serde(rename = "answer_id")]
     pub id: u32,
     pub score: i32,
-    #[serde(rename = "body_markdown")]
+    #[serde(rename = "body_markdown", alias = "body")]
     pub body: S,
     pub is_accepted: bool,
+    /// Link to the answer on its site; missing from answers cached by older versions, or fetched
+    /// with a filter leaving it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Who posted it; missing from answers cached by older versions, or fetched with a filter
+    /// leaving it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    /// Unix timestamp of when it was posted; missing from answers cached by older versions, or
+    /// fetched with a filter leaving it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_date: Option<i64>,
+    /// Set when this answer is a copy of one posted on a higher ranked question
+    #[serde(skip)]
+    pub duplicate_of: Option<DuplicateOf>,
+    /// Code block lines matching the query in `--code-search` mode
+    #[serde(skip)]
+    pub code_matches: Vec<CodeMatch>,
+    /// Contents of the code blocks in the body, kept for when the body is no longer markdown
+    #[serde(skip)]
+    pub code_blocks: Vec<String>,
+}
+
+/// Points at the original of an answer that was posted on multiple questions
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuplicateOf {
+    pub question_id: u32,
+    pub question_title: String,
+    pub answer_id: u32,
+}
+
+/// A line within one of an answer's code blocks, both counted from zero
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CodeMatch {
+    pub block: usize,
+    pub line: usize,
+}
+
+/// Comment on an answer, from the [StackExchange docs](https://api.stackexchange.com/docs/types/comment)
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[serde(from = "RawComment")]
+pub struct Comment {
+    pub score: i32,
+    pub body_markdown: String,
+    pub owner_display_name: String,
+}
+
+/// Comment as the API has it, with its author nested
+#[derive(Deserialize)]
+struct RawComment {
+    score: i32,
+    #[serde(alias = "body")]
+    body_markdown: String,
+    #[serde(default)]
+    owner: Option<Owner>,
+}
+
+/// Author of a post or comment, from the [StackExchange docs](https://api.stackexchange.com/docs/types/shallow-user).
+/// Deleted users may come without either field.
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+pub struct Owner {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reputation: Option<u64>,
+}
+
+impl Owner {
+    /// Name, with the reputation abbreviated if known, e.g. `jane (12.3k)`; `None` without a
+    /// name to give
+    pub fn byline(&self, locale: Locale) -> Option<String> {
+        let name = self
+            .display_name
+            .as_deref()
+            .filter(|name| !name.trim().is_empty())?;
+        Some(match self.reputation {
+            Some(reputation) => format!("{} ({})", name, locale.abbreviated(reputation)),
+            None => name.to_string(),
+        })
+    }
+}
+
+impl From<RawComment> for Comment {
+    fn from(c: RawComment) -> Self {
+        Comment {
+            score: c.score,
+            body_markdown: c.body_markdown,
+            owner_display_name: c
+                .owner
+                .and_then(|owner| owner.display_name)
+                .unwrap_or_else(|| String::from("anonymous")),
+        }
+    }
 }
 
 /// Represents a StackExchange question with a custom selection of fields from
 /// the [StackExchange docs](https://api.stackexchange.com/docs/types/question)
 // TODO container over answers should be generic iterator
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 pub struct Question<S> {
     #[serde(rename = "question_id")]
     pub id: u32,
@@ -46,136 +192,1069 @@ pub struct Question<S> {
     // answers >= 1
     pub answers: Vec<Answer<S>>,
     pub title: String,
-    #[serde(rename = "body_markdown")]
-    pub body: S,
+    /// Missing from questions cached by older versions, or fetched with a filter leaving them out
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Left out when fetched with the slim filter, for modes that don't show it
+    #[serde(rename = "body_markdown", alias = "body", default)]
+    pub body: Option<S>,
+    /// Site the question is on (or the team name, for Teams)
+    #[serde(default)]
+    pub site: String,
+    /// Unix timestamps; missing from questions cached by older versions, or fetched with a filter
+    /// leaving them out
+    pub creation_date: Option<i64>,
+    pub last_activity_date: Option<i64>,
+    /// Link to the question's page; missing from questions cached by older versions, or fetched
+    /// with a filter leaving it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Who asked it; missing from questions cached by older versions, or fetched with a filter
+    /// leaving it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    /// Where the question came from; never part of API responses, and only set on questions
+    /// found by a search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Which engine found a question, where it ranked, and which request fetched it
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+pub struct Provenance {
+    pub engine: SearchEngine,
+    pub site: String,
+    /// Place in the results of `engine`, counted from one: in the search engine results for
+    /// DuckDuckGo and Google, and in the API results for StackExchange
+    pub rank: usize,
+    pub request: Request,
+}
+
+/// The API request a question came with
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Request {
+    /// A page of `/search/advanced` results, counted from one
+    Search { page: u32 },
+    /// One of the batches of ids a `/questions/{ids}` lookup was split into, counted from zero
+    Batch { chunk: usize },
+    /// None; the question cache had it
+    Cached,
+}
+
+impl Provenance {
+    /// Provenance of a question the API returned for `request`, to be ranked later
+    fn api(site: &str, request: Request) -> Self {
+        Provenance {
+            engine: SearchEngine::StackExchange,
+            site: site.to_string(),
+            rank: 0,
+            request,
+        }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} via {}, ranked {}, from {}",
+            self.site, self.engine, self.rank, self.request
+        )
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Search { page } => write!(f, "page {} of the API search", page),
+            Request::Batch { chunk } => write!(f, "batch {} of ids", chunk + 1),
+            Request::Cached => write!(f, "the question cache"),
+        }
+    }
+}
+
+/// Answer as returned by the /questions/{ids}/answers endpoint, which is only
+/// used when answers can't be embedded in their questions
+#[derive(Deserialize, Debug)]
+struct StandaloneAnswer {
+    question_id: u32,
+    answer_id: u32,
+    score: i32,
+    body: String,
+    is_accepted: bool,
+    #[serde(default)]
+    owner: Option<Owner>,
+    #[serde(default)]
+    creation_date: Option<i64>,
+}
+
+impl From<StandaloneAnswer> for Answer<String> {
+    fn from(a: StandaloneAnswer) -> Self {
+        Answer {
+            id: a.answer_id,
+            score: a.score,
+            body: a.body,
+            is_accepted: a.is_accepted,
+            link: None,
+            owner: a.owner,
+            creation_date: a.creation_date,
+            duplicate_of: None,
+            code_matches: Vec::new(),
+            code_blocks: Vec::new(),
+        }
+    }
+}
+
+/// Just enough of a question to tell which it is
+#[derive(Deserialize, Debug)]
+struct QuestionId {
+    question_id: u32,
+}
+
+/// Just enough of an answer to tell which question it's on
+#[derive(Deserialize, Debug)]
+struct AnswerParent {
+    answer_id: u32,
+    question_id: u32,
 }
 
 /// Internal struct that represents the boilerplate response wrapper from SE API.
 #[derive(Deserialize, Debug)]
 struct ResponseWrapper<T> {
     items: Vec<T>,
+    /// Whether there are more items past this page
+    #[serde(default)]
+    has_more: bool,
+    /// Seconds to leave the site alone for before sending more requests
+    #[serde(default)]
+    backoff: Option<u64>,
+    /// Requests left of the daily quota, and how many it allows
+    #[serde(default)]
+    quota_remaining: Option<u32>,
+    #[serde(default)]
+    quota_max: Option<u32>,
+    /// Errors normally come as an `ApiError`, but a wrapper can carry one too
+    #[serde(default)]
+    error_id: Option<u32>,
+    #[serde(default)]
+    error_message: Option<String>,
+    #[serde(skip)]
+    origin: Origin,
+}
+
+/// Site and endpoint a response came from, so that its error can tell
+#[derive(Debug, Default, Clone)]
+struct Origin {
+    site: Option<String>,
+    endpoint: Option<String>,
+}
+
+/// Filter created by the /filters/create endpoint
+#[derive(Deserialize, Debug)]
+struct CreatedFilter {
+    filter: String,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Error object the SE API responds with instead of a `ResponseWrapper`
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    error_id: u32,
+    error_name: String,
+    error_message: String,
+    #[serde(skip)]
+    origin: Origin,
+}
+
+impl ApiError {
+    fn is_invalid_filter(&self) -> bool {
+        self.error_name == "bad_parameter" && self.error_message.contains("filter")
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(e: ApiError) -> Self {
+        Error::Api {
+            site: e.origin.site,
+            endpoint: e.origin.endpoint,
+            status: e.error_id,
+            name: Some(e.error_name),
+            message: e.error_message,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ApiResponse<T> {
+    Error(ApiError),
+    Items(ResponseWrapper<T>),
+}
+
+impl<T> ApiResponse<T> {
+    /// The response, as one from `endpoint` of `site`
+    fn at(mut self, site: Option<&str>, endpoint: &str) -> Self {
+        let origin = match &mut self {
+            ApiResponse::Error(e) => &mut e.origin,
+            ApiResponse::Items(wrapper) => &mut wrapper.origin,
+        };
+        *origin = Origin {
+            site: site.map(str::to_string),
+            endpoint: Some(endpoint.to_string()),
+        };
+        self
+    }
+
+    fn into_items(self) -> Result<Vec<T>> {
+        self.into_page().map(|(items, _)| items)
+    }
+
+    /// Items of the response, and whether there are more past them
+    fn into_page(self) -> Result<(Vec<T>, bool)> {
+        match self {
+            ApiResponse::Items(ResponseWrapper {
+                error_id: Some(error_id),
+                error_message,
+                origin,
+                ..
+            }) => Err(Error::Api {
+                site: origin.site,
+                endpoint: origin.endpoint,
+                status: error_id,
+                name: None,
+                message: error_message.unwrap_or_default(),
+            }),
+            ApiResponse::Items(wrapper) => Ok((wrapper.items, wrapper.has_more)),
+            ApiResponse::Error(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A page of `/search/advanced` results
+#[derive(Debug)]
+pub struct SearchPage {
+    pub questions: Vec<Question<String>>,
+    /// Whether the site has more results past this page
+    pub has_more: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Site {
     pub api_site_parameter: String,
     pub site_url: String,
+    /// Its name as people know it, e.g. `Super User`; missing from listings cached by older
+    /// versions
+    #[serde(default)]
+    pub name: String,
+    /// Who it's for, e.g. `professional and enthusiast programmers`; meta sites have none, and
+    /// listings cached by older versions lack it
+    #[serde(default)]
+    pub audience: String,
+    /// `normal`, `open_beta`, `closed_beta` or `linked_meta`; like the rest below, missing from
+    /// listings cached by older versions
+    #[serde(default)]
+    pub site_state: Option<String>,
+    /// Former urls of the site, of sites merged into it or from before it was renamed
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// When it was shut down, or is to be, if it is
+    #[serde(default)]
+    pub closing_date: Option<i64>,
+}
+
+impl Site {
+    /// Whether it's a site of its own that isn't shut down, as opposed to a meta site
+    pub fn is_open(&self) -> bool {
+        self.closing_date.is_none() && self.site_state.as_deref() != Some("linked_meta")
+    }
 }
 
+/// Cheap to clone, so that every concurrent request can own one; read-only state is shared
+/// rather than copied
 #[derive(Clone)]
 pub struct Api {
-    client: Client,
-    api_key: Option<String>,
+    network: Network,
+    api_key: Option<Arc<str>>,
+    filter: Arc<str>,
+    /// Set once SE has rejected `filter` and requests fell back to `SE_FALLBACK_FILTER`
+    filter_degraded: Arc<AtomicBool>,
+    /// Fetch question bodies; without them, requests use the slim filter
+    question_bodies: bool,
+    /// `filter` with `SE_FILTER_INCLUDE`, and without question bodies for the slim filter, once
+    /// created, or `filter` itself if that failed
+    derived_filter: Arc<tokio::sync::Mutex<Option<Arc<str>>>>,
+    /// Filters created in earlier sessions
+    filters: Filters,
+    /// Base of the public API, without the version
+    api_url: Arc<str>,
+    api_version: Arc<str>,
+    /// Send requests to this Teams instance rather than the public API
+    team: Option<Arc<Team>>,
+    question_cache: Option<QuestionCache>,
+    /// Where to record and look up the new ids of merged questions
+    aliases: Option<Aliases>,
+    timings: Recorder,
+    /// Posts that failed to render, across this client and all of its clones
+    failures: Failures,
+    /// Malformed items left out of responses, across this client and all of its clones
+    skipped: Skipped,
+    /// Keep questions without answers, rather than filtering them out
+    include_unanswered: bool,
+    /// Only search questions with this tag
+    tagged: Option<Arc<str>>,
+    /// Markdown preprocessing passes to run on fetched posts
+    passes: Arc<[Pass]>,
+    /// Backoffs SE asked for, which requests to each site wait out
+    backoff: Backoff,
+    /// Lowest quota responses said is left
+    quota: Quota,
 }
 
 impl Api {
-    pub fn new(api_key: Option<String>) -> Self {
+    /// Create a client; `filter` overrides the built-in `SE_FILTER`
+    pub fn new(api_key: Option<String>, filter: Option<String>, policy: OfflinePolicy) -> Self {
         // TODO can lazy_static this above
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::ACCEPT,
             header::HeaderValue::from_static("application/json"),
         );
-        let client = Client::builder().default_headers(headers).build().unwrap();
-        Api { client, api_key }
+        Api {
+            network: Network::new(policy, headers),
+            api_key: api_key.map(Arc::from),
+            filter: filter.map_or_else(|| Arc::from(SE_FILTER), Arc::from),
+            filter_degraded: Arc::new(AtomicBool::new(false)),
+            question_bodies: true,
+            derived_filter: Arc::default(),
+            filters: Filters::default(),
+            api_url: Arc::from(SE_API_URL),
+            api_version: Arc::from(SE_API_VERSION),
+            team: None,
+            question_cache: None,
+            aliases: None,
+            timings: Recorder::default(),
+            failures: Failures::default(),
+            skipped: Skipped::default(),
+            include_unanswered: false,
+            tagged: None,
+            passes: Arc::from(preprocess::DEFAULT_PASSES),
+            backoff: Backoff::default(),
+            quota: Quota::default(),
+        }
+    }
+
+    /// Send requests for the public sites to a mirror of the API rather than `SE_API_URL`
+    pub fn with_api_url(self, api_url: &str) -> Self {
+        Api {
+            api_url: Arc::from(api_url),
+            ..self
+        }
+    }
+
+    /// Use version `api_version` of the public API
+    pub fn with_api_version(self, api_version: &str) -> Self {
+        Api {
+            api_version: Arc::from(api_version),
+            ..self
+        }
+    }
+
+    /// Also send `headers` with every request, e.g. for a proxy
+    pub fn with_extra_headers(self, headers: header::HeaderMap) -> Self {
+        Api {
+            network: self.network.with_extra_headers(headers),
+            ..self
+        }
+    }
+
+    /// Give up on requests after `timeouts`
+    pub fn with_timeouts(self, timeouts: Timeouts) -> Self {
+        Api {
+            network: self.network.with_timeouts(timeouts),
+            ..self
+        }
+    }
+
+    /// Route site searches to a Stack Overflow for Teams instance
+    pub fn with_team(self, team: Option<Team>) -> Self {
+        Api {
+            team: team.map(Arc::new),
+            ..self
+        }
+    }
+
+    /// Reuse questions fetched by earlier searches from `cache`, fetching only the rest
+    pub fn with_question_cache(self, question_cache: Option<QuestionCache>) -> Self {
+        Api {
+            question_cache,
+            ..self
+        }
+    }
+
+    /// Also return questions that have no answers yet
+    pub fn with_unanswered(self, include_unanswered: bool) -> Self {
+        Api {
+            include_unanswered,
+            ..self
+        }
+    }
+
+    /// Only search questions tagged with `tagged`, if any
+    pub fn with_tagged(self, tagged: Option<&str>) -> Self {
+        Api {
+            tagged: tagged.map(Arc::from),
+            ..self
+        }
+    }
+
+    /// Preprocess fetched posts with `passes` rather than the default ones
+    pub fn with_preprocess_passes(self, passes: Vec<Pass>) -> Self {
+        Api {
+            passes: Arc::from(passes),
+            ..self
+        }
+    }
+
+    /// Leave out question bodies, for modes that don't show them; answers are still fetched in
+    /// full
+    pub fn with_question_bodies(self, question_bodies: bool) -> Self {
+        Api {
+            question_bodies,
+            ..self
+        }
+    }
+
+    /// Whether question bodies are fetched
+    pub fn question_bodies(&self) -> bool {
+        self.question_bodies
+    }
+
+    /// Follow merged questions to their new ids, recording newly spotted merges in `aliases`
+    pub fn with_aliases(self, aliases: Option<Aliases>) -> Self {
+        Api { aliases, ..self }
+    }
+
+    /// Reuse the filters created in earlier sessions from `filters`, and keep new ones there
+    pub fn with_filters(self, filters: Filters) -> Self {
+        Api { filters, ..self }
     }
 
-    /// Search against the SE site's /questions/{ids} endpoint.
-    /// Filters out questions with no answers.
+    /// Keep the backoffs SE asks for in `backoff`, rather than only for this client and its clones
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Api { backoff, ..self }
+    }
+
+    /// Handle to the backoffs SE asked this client (and all of its clones) for
+    pub fn backoff(&self) -> &Backoff {
+        &self.backoff
+    }
+
+    /// Keep the lowest quota left in `quota`, rather than only for this client and its clones
+    pub fn with_quota(self, quota: Quota) -> Self {
+        Api { quota, ..self }
+    }
+
+    /// Handle to the lowest quota responses to this client (and all of its clones) said is left
+    pub fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    /// Ask `site` for its info, about the cheapest request there is, to read the quota off the
+    /// response
+    pub async fn check_quota(&self, site: &str) -> Result<Option<Reading>> {
+        let params = [self.site_param(site)];
+        self.get::<IgnoredAny>(site, "info", "default", &params, "info")
+            .await?
+            .into_items()?;
+        Ok(self.quota.lowest())
+    }
+
+    /// Whether SE rejected the configured filter, meaning results are in a degraded format and
+    /// `so` (or the `filter` in the config) needs updating
+    pub fn filter_degraded(&self) -> bool {
+        self.filter_degraded.load(Ordering::Relaxed)
+    }
+
+    /// Handle to the timings recorded by this client (and all of its clones)
+    pub fn timings(&self) -> &Recorder {
+        &self.timings
+    }
+
+    /// Handle to the rendering failures recorded by this client (and all of its clones)
+    pub fn failures(&self) -> &Failures {
+        &self.failures
+    }
+
+    /// Handle to the malformed items this client (and all of its clones) left out of responses
+    pub fn skipped(&self) -> &Skipped {
+        &self.skipped
+    }
+
+    /// Questions by id, in the order of `ids`. Those in the question cache are taken from there;
+    /// the rest are fetched and then added to the cache. Filters out questions with no answers,
+    /// unless including unanswered questions. Ids of merged questions are replaced by the ids of
+    /// the questions they were merged into.
     pub async fn questions(&self, site: &str, ids: Vec<String>) -> Result<Vec<Question<String>>> {
+        let cache_site = {
+            let (kind, name) = self.site_param(site);
+            // Cached posts have already been preprocessed, so other passes need their own entries
+            if *self.passes == *preprocess::DEFAULT_PASSES {
+                format!("{}-{}", kind, name)
+            } else {
+                let passes: Vec<&str> = self.passes.iter().map(|pass| pass.name()).collect();
+                format!("{}-{}-{}", kind, name, passes.join("+"))
+            }
+        };
+        let ids = self.resolve_aliases(site, ids);
+        let mut cached = HashMap::new();
+        let mut missing = Vec::new();
+        for id in &ids {
+            if missing.contains(id) {
+                continue;
+            }
+            match self
+                .question_cache
+                .as_ref()
+                .and_then(|cache| cache.get(&cache_site, id))
+            {
+                Some(q) => {
+                    // Cached by versions that didn't sanitize yet, perhaps
+                    let q = sanitize::question(q);
+                    let provenance = Provenance::api(site, Request::Cached);
+                    cached.insert(
+                        q.id,
+                        Question {
+                            provenance: Some(provenance),
+                            ..q
+                        },
+                    );
+                }
+                None => missing.push(id.clone()),
+            }
+        }
+        let fetched = if missing.is_empty() {
+            Vec::new()
+        } else {
+            self.fetch_questions(site, missing).await?
+        };
+        // Questions without bodies would pass for complete ones once cached. The questions are
+        // here either way, so a cache that can't be written to only costs requests later.
+        if let (Some(cache), true) = (&self.question_cache, self.question_bodies) {
+            let _ = cache.put(&cache_site, &fetched);
+        }
+        // Merges spotted while fetching replace the ids they were requested by
+        let ids = self.resolve_aliases(site, ids);
+        let mut qs = merge_in_order(&ids, cached, fetched);
+        rank(&mut qs);
+        Ok(qs
+            .into_iter()
+            .filter(|q| self.include_unanswered || !q.answers.is_empty())
+            .collect())
+    }
+
+    /// Current ids of questions `ids` on `site`, following the merges recorded so far
+    pub fn resolve_aliases(&self, site: &str, ids: Vec<String>) -> Vec<String> {
+        match &self.aliases {
+            Some(aliases) => ids
+                .into_iter()
+                .map(|id| match id.parse() {
+                    Ok(id) => aliases.resolve(site, id).to_string(),
+                    Err(_) => id,
+                })
+                .collect(),
+            None => ids,
+        }
+    }
+
+    /// Search against the SE site's /questions/{ids} endpoint, in batches of up to
+    /// `SE_IDS_PER_REQUEST` ids.
+    async fn fetch_questions(&self, site: &str, ids: Vec<String>) -> Result<Vec<Question<String>>> {
+        let batches: Vec<Vec<String>> = ids.chunks(SE_IDS_PER_REQUEST).map(<[_]>::to_vec).collect();
+        let limit = batches.len().clamp(1, SITE_CONCURRENT_REQUESTS_LIMIT);
+        let qs: Vec<Vec<Question<String>>> = futures::stream::iter(batches)
+            .enumerate()
+            .map(|(chunk, ids)| self.questions_batch(site, ids, chunk))
+            .buffered(limit)
+            .try_collect()
+            .await?;
+        Ok(self.timed_preprocess(qs.into_iter().flatten().collect()))
+    }
+
+    async fn questions_batch(
+        &self,
+        site: &str,
+        ids: Vec<String>,
+        chunk: usize,
+    ) -> Result<Vec<Question<String>>> {
         let total = ids.len().to_string();
         let endpoint = format!("questions/{ids}", ids = ids.join(";"));
-        let qs = self
-            .client
-            .get(stackexchange_url(&endpoint))
-            .query(&self.get_default_se_opts())
-            .query(&[("site", site), ("pagesize", &total)])
-            .send()
+        let params = [self.site_param(site), ("pagesize", total.as_str())];
+        let label = format!("{} questions", site);
+        let filter = self.filter().await;
+        let items = self
+            .with_filter_fallback(&filter, |filter| {
+                self.get(site, &endpoint, filter, &params, &label)
+            })
             .await?
-            .json::<ResponseWrapper<Question<String>>>()
+            .into_items()?;
+        let qs: Vec<Question<String>> = lossy::items(items, &label, &self.skipped)?;
+        if let Some(aliases) = &self.aliases {
+            let requested: Vec<u32> = ids.iter().filter_map(|id| id.parse().ok()).collect();
+            let returned: Vec<u32> = qs.iter().map(|q| q.id).collect();
+            let merges = match aliases::detect(&requested, &returned) {
+                Some(merge) => vec![merge],
+                None => {
+                    let (missing, most) = aliases::ambiguous(&requested, &returned);
+                    self.merges(site, missing, most).await
+                }
+            };
+            let mut recorded = false;
+            for (old_id, new_id) in merges {
+                recorded |= aliases.record(site, old_id, new_id);
+            }
+            // Unsaved, merges are still followed for the rest of this run
+            if recorded {
+                let _ = aliases.save();
+            }
+        }
+        let qs = self.with_answers(site, qs).await?;
+        Ok(tag_site(site, Request::Batch { chunk }, qs))
+    }
+
+    /// Which of the questions `ids` on `site` were merged, and into what, by asking for each on
+    /// its own until `most` merges are found. Questions that can't be looked up are skipped, since
+    /// they're only missing from the results either way.
+    async fn merges(&self, site: &str, ids: Vec<u32>, most: usize) -> Vec<(u32, u32)> {
+        let mut merges = Vec::new();
+        for old_id in ids {
+            if merges.len() == most {
+                break;
+            }
+            let endpoint = format!("questions/{}", old_id);
+            let params = [self.site_param(site)];
+            let label = format!("{} questions", site);
+            let found = self
+                .get(site, &endpoint, SE_FALLBACK_FILTER, &params, &label)
+                .await
+                .and_then(|response| response.into_items());
+            if let Ok([QuestionId { question_id }]) = found.as_deref() {
+                if *question_id != old_id {
+                    merges.push((old_id, *question_id));
+                }
+            }
+        }
+        merges
+    }
+
+    /// Id of the question `answer_id` was posted on, if the answer exists
+    pub async fn answer_question_id(&self, site: &str, answer_id: u32) -> Result<Option<u32>> {
+        let endpoint = format!("answers/{}", answer_id);
+        let params = [self.site_param(site)];
+        let label = format!("{} answers", site);
+        let answers: Vec<AnswerParent> = self
+            .get(site, &endpoint, SE_FALLBACK_FILTER, &params, &label)
             .await?
-            .items
+            .into_items()?;
+        Ok(answers.first().map(|a| a.question_id))
+    }
+
+    /// Ids of the questions `answer_ids` were posted on, as `(answer_id, question_id)` pairs, in no
+    /// particular order. Answers that don't exist are left out.
+    pub async fn answer_question_ids(
+        &self,
+        site: &str,
+        answer_ids: &[String],
+    ) -> Result<Vec<(u32, u32)>> {
+        let endpoint = format!("answers/{}", answer_ids.join(";"));
+        let params = [self.site_param(site), ("pagesize", "100")];
+        let label = format!("{} answers", site);
+        let answers: Vec<AnswerParent> = self
+            .get(site, &endpoint, SE_FALLBACK_FILTER, &params, &label)
+            .await?
+            .into_items()?;
+        Ok(answers
             .into_iter()
-            .filter(|q| !q.answers.is_empty())
-            .collect();
-        Ok(Self::preprocess(qs))
+            .map(|a| (a.answer_id, a.question_id))
+            .collect())
     }
 
-    /// Search against the SE site's /search/advanced endpoint with a given query.
-    /// Only fetches questions that have at least one answer.
+    /// Comments on `answer_id`, oldest first, with their bodies sanitized and preprocessed like
+    /// those of posts
+    pub async fn answer_comments(&self, site: &str, answer_id: u32) -> Result<Vec<Comment>> {
+        let endpoint = format!("answers/{}/comments", answer_id);
+        let params = [
+            self.site_param(site),
+            ("order", "asc"),
+            ("sort", "creation"),
+            ("pagesize", SE_COMMENTS_PAGESIZE),
+        ];
+        let label = format!("{} comments", site);
+        let filter = self.comments_filter().await;
+        let comments: Vec<Comment> = self
+            .get(site, &endpoint, &filter, &params, &label)
+            .await?
+            .into_items()?;
+        let passes = &self.passes;
+        Ok(comments
+            .into_iter()
+            .map(|c| Comment {
+                body_markdown: preprocess::preprocess_with(&sanitize(&c.body_markdown), passes)
+                    .into_owned(),
+                owner_display_name: sanitize(&c.owner_display_name).into_owned(),
+                ..c
+            })
+            .collect())
+    }
+
+    /// Filter to fetch comments with, created on first use like the one for questions, or
+    /// `SE_COMMENTS_FILTER_BASE` if that fails
+    async fn comments_filter(&self) -> String {
+        let url = match self.url("filters/create") {
+            Ok(url) => url,
+            Err(_) => return String::from(SE_COMMENTS_FILTER_BASE),
+        };
+        let derived = Derived {
+            endpoint: url.as_str(),
+            base: SE_COMMENTS_FILTER_BASE,
+            include: SE_COMMENTS_FILTER_INCLUDE,
+            exclude: "",
+        };
+        if let Some(filter) = self.filters.get(&derived) {
+            return filter;
+        }
+        match self.create_filter(&derived).await {
+            Ok(filter) => {
+                self.filters.insert(&derived, &filter);
+                filter
+            }
+            Err(_) => String::from(SE_COMMENTS_FILTER_BASE),
+        }
+    }
+
+    /// Search against the SE site's /search/advanced endpoint with a given query, for page
+    /// `page` of `limit` questions, counted from one. Only fetches questions that have at least
+    /// one answer, unless including unanswered questions, and only those with the tag given to
+    /// `with_tagged`, if any.
     pub async fn search_advanced(
         &self,
         query: &str,
         site: &str,
         limit: u16,
-    ) -> Result<Vec<Question<String>>> {
-        let qs = self
-            .client
-            .get(stackexchange_url("search/advanced"))
-            .query(&self.get_default_se_opts())
-            .query(&[
-                ("q", query),
-                ("pagesize", &limit.to_string()),
-                ("site", site),
-                ("answers", "1"),
-                ("order", "desc"),
-                ("sort", "relevance"),
-            ])
-            .send()
+        page: u32,
+    ) -> Result<SearchPage> {
+        let limit = limit.to_string();
+        let number = page.to_string();
+        let mut params = vec![
+            ("q", query),
+            ("pagesize", limit.as_str()),
+            ("page", number.as_str()),
+            self.site_param(site),
+            ("order", "desc"),
+            ("sort", "relevance"),
+        ];
+        if !self.include_unanswered {
+            params.push(("answers", "1"));
+        }
+        if let Some(tagged) = &self.tagged {
+            params.push(("tagged", tagged));
+        }
+        let label = format!("{} search", site);
+        let filter = self.filter().await;
+        let (items, has_more) = self
+            .with_filter_fallback(&filter, |filter| {
+                self.get(site, "search/advanced", filter, &params, &label)
+            })
             .await?
-            .json::<ResponseWrapper<Question<String>>>()
+            .into_page()?;
+        let qs = lossy::items(items, &label, &self.skipped)?;
+        let qs = self.with_answers(site, qs).await?;
+        let mut qs = tag_site(site, Request::Search { page }, qs);
+        rank(&mut qs);
+        Ok(SearchPage {
+            questions: self.timed_preprocess(qs),
+            has_more,
+        })
+    }
+
+    /// Filter to fetch questions with: the configured one plus `SE_FILTER_INCLUDE`, and minus
+    /// `SE_SLIM_FILTER_EXCLUDE` when question bodies are left out. It's created on first use, or
+    /// taken from `filters`, and if that fails, the configured filter is used instead for the rest
+    /// of the session.
+    async fn filter(&self) -> Arc<str> {
+        let mut derived = self.derived_filter.lock().await;
+        if derived.is_none() {
+            let created = self.derive_filter().await;
+            *derived = Some(created.map_or_else(|_| self.filter.clone(), Arc::from));
+        }
+        derived.clone().unwrap_or_else(|| self.filter.clone())
+    }
+
+    /// Create the filter `filter` uses, unless an earlier session did
+    async fn derive_filter(&self) -> Result<String> {
+        let url = self.url("filters/create")?;
+        let derived = Derived {
+            endpoint: url.as_str(),
+            base: &self.filter,
+            include: SE_FILTER_INCLUDE,
+            exclude: if self.question_bodies {
+                ""
+            } else {
+                SE_SLIM_FILTER_EXCLUDE
+            },
+        };
+        if let Some(filter) = self.filters.get(&derived) {
+            return Ok(filter);
+        }
+        let filter = self.create_filter(&derived).await?;
+        self.filters.insert(&derived, &filter);
+        Ok(filter)
+    }
+
+    /// Create a filter with /filters/create
+    async fn create_filter(&self, derived: &Derived<'_>) -> Result<String> {
+        let mut params = vec![
+            ("base", derived.base),
+            ("include", derived.include),
+            ("unsafe", "false"),
+        ];
+        if !derived.exclude.is_empty() {
+            params.push(("exclude", derived.exclude));
+        }
+        let start = Instant::now();
+        let response = self
+            .network
+            .client()?
+            .get(derived.endpoint)
+            .query(&params)
+            .send()
+            .await
+            .map_err(|e| self.network.failed("filters/create", e))?;
+        let ttfb = start.elapsed();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| self.network.failed("filters/create", e))?;
+        self.timings
+            .request(String::from("filters/create"), ttfb, start.elapsed());
+        serde_json::from_slice::<ApiResponse<CreatedFilter>>(&body)?
+            .at(None, "filters/create")
+            .into_items()?
+            .pop()
+            .map(|created| created.filter)
+            .ok_or_else(|| Error::unexpected("filters/create", "no filter created"))
+    }
+
+    /// Run `fetch` with `filter`. If SE reports that filter as invalid, warn via
+    /// `filter_degraded` and run it again (as well as all subsequent requests) with
+    /// `SE_FALLBACK_FILTER`.
+    async fn with_filter_fallback<'a, T, F, Fut>(
+        &'a self,
+        filter: &'a str,
+        fetch: F,
+    ) -> Result<ApiResponse<T>>
+    where
+        F: Fn(&'a str) -> Fut,
+        Fut: Future<Output = Result<ApiResponse<T>>>,
+    {
+        if !self.filter_degraded() {
+            match fetch(filter).await? {
+                ApiResponse::Error(e) if e.is_invalid_filter() => {
+                    self.filter_degraded.store(true, Ordering::Relaxed);
+                }
+                response => return Ok(response),
+            }
+        }
+        fetch(SE_FALLBACK_FILTER).await
+    }
+
+    /// When running with `SE_FALLBACK_FILTER`, answers aren't embedded in questions; fetch them
+    /// from the /questions/{ids}/answers endpoint instead.
+    async fn with_answers(
+        &self,
+        site: &str,
+        qs: Vec<Question<String>>,
+    ) -> Result<Vec<Question<String>>> {
+        if !self.filter_degraded() || qs.is_empty() {
+            return Ok(qs);
+        }
+        let ids = qs
+            .iter()
+            .map(|q| q.id.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+        let endpoint = format!("questions/{ids}/answers", ids = ids);
+        let params = [self.site_param(site), ("pagesize", "100")];
+        let label = format!("{} answers", site);
+        let items = self
+            .get(site, &endpoint, SE_FALLBACK_FILTER, &params, &label)
             .await?
-            .items;
-        Ok(Self::preprocess(qs))
+            .into_items()?;
+        let answers = lossy::items(items, &label, &self.skipped)?;
+        Ok(attach_answers(qs, answers))
+    }
+
+    /// GET an endpoint of `site` with the given filter, recording its timing under `label`. Waits
+    /// out any backoff on the site first, and records the one the response asks for.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        site: &str,
+        endpoint: &str,
+        filter: &str,
+        params: &[(&str, &str)],
+        label: &str,
+    ) -> Result<ApiResponse<T>> {
+        let mut defaults = self.get_default_se_opts();
+        // Paged requests ask for a page of their own
+        if params.iter().any(|(name, _)| *name == "page") {
+            defaults.remove("page");
+        }
+        let mut request = self
+            .network
+            .client()?
+            .get(self.url(endpoint)?)
+            .query(&defaults)
+            .query(&[("filter", filter)])
+            .query(params);
+        if let Some(team) = &self.team {
+            request = request.header(TEAMS_TOKEN_HEADER, &team.token);
+        }
+        self.backoff.wait(site).await;
+        let start = Instant::now();
+        let failed = |e| self.network.failed(site, e);
+        let response = request.send().await.map_err(failed)?;
+        let ttfb = start.elapsed();
+        let body = response.bytes().await.map_err(failed)?;
+        self.timings
+            .request(label.to_string(), ttfb, start.elapsed());
+        let response = serde_json::from_slice::<ApiResponse<T>>(&body)?.at(Some(site), endpoint);
+        if let ApiResponse::Items(wrapper) = &response {
+            if let Some(secs) = wrapper.backoff {
+                self.backoff.record(site, secs, backoff::now());
+            }
+            if let (Some(remaining), Some(max)) = (wrapper.quota_remaining, wrapper.quota_max) {
+                self.quota.record(remaining, max, backoff::now());
+            }
+        }
+        Ok(response)
     }
 
     pub async fn sites(&self) -> Result<Vec<Site>> {
         let sites = self
-            .client
-            .get(stackexchange_url("sites"))
+            .network
+            .client()?
+            .get(self.url("sites")?)
             .query(&[("pagesize", SE_SITES_PAGESIZE.to_string())])
             .send()
-            .await?
-            .json::<ResponseWrapper<Site>>()
-            .await?
-            .items;
+            .await
+            .map_err(|e| self.network.failed("sites", e))?
+            .json::<ApiResponse<Site>>()
+            .await
+            .map_err(|e| self.network.failed("sites", e))?
+            .at(None, "sites")
+            .into_items()?;
         Ok(sites
             .into_par_iter()
             .map(|site| {
-                let site_url = site.site_url.trim_start_matches("https://").to_string();
-                Site { site_url, ..site }
+                let site_url = sanitize(site.site_url.trim_start_matches("https://")).into_owned();
+                let api_site_parameter = sanitize(&site.api_site_parameter).into_owned();
+                let name = sanitize(&site.name).into_owned();
+                let audience = sanitize(&site.audience).into_owned();
+                let aliases = site
+                    .aliases
+                    .iter()
+                    .map(|alias| sanitize(alias).into_owned())
+                    .collect();
+                Site {
+                    api_site_parameter,
+                    site_url,
+                    name,
+                    audience,
+                    aliases,
+                    ..site
+                }
             })
             .collect())
     }
 
     fn get_default_se_opts(&self) -> HashMap<&str, &str> {
         let mut params = HashMap::new();
-        params.insert("filter", SE_FILTER);
         params.insert("page", "1");
-        if let Some(key) = &self.api_key {
-            params.insert("key", &key);
+        // Teams authenticate with a token instead
+        if let (Some(key), None) = (&self.api_key, &self.team) {
+            params.insert("key", key);
         }
         params
     }
 
+    /// Query param identifying the site; Teams instances are identified by their slug instead
+    fn site_param<'a>(&'a self, site: &'a str) -> (&'static str, &'a str) {
+        match &self.team {
+            Some(team) => ("team", &team.slug),
+            None => ("site", site),
+        }
+    }
+
+    /// URL of `path` on either the public API or the configured Teams instance
+    fn url(&self, path: &str) -> Result<Url> {
+        match &self.team {
+            Some(team) => stackexchange_url(&team.api_url, path),
+            None => stackexchange_url(
+                &format!(
+                    "{}/{}",
+                    self.api_url.trim_end_matches('/'),
+                    self.api_version
+                ),
+                path,
+            ),
+        }
+    }
+
+    fn timed_preprocess(&self, qs: Vec<Question<String>>) -> Vec<Question<String>> {
+        let start = Instant::now();
+        let passes = &self.passes;
+        let qs = Self::preprocess(
+            qs,
+            &|body| preprocess::preprocess_with(body, passes),
+            &self.failures,
+        );
+        self.timings.preprocess(start.elapsed());
+        qs
+    }
+
     /// Sorts answers by score
     /// Preprocess SE markdown to "cmark" markdown (or something closer to it)
-    /// This markdown preprocess _always_ happens.
-    fn preprocess(qs: Vec<Question<String>>) -> Vec<Question<String>> {
+    /// This markdown preprocess _always_ happens, after stripping terminal control sequences.
+    /// Bodies `preprocessor` panics on are shown as plain text instead, with the failure recorded
+    /// in `failures`.
+    fn preprocess(
+        qs: Vec<Question<String>>,
+        preprocessor: &Preprocessor<'_>,
+        failures: &Failures,
+    ) -> Vec<Question<String>> {
         qs.into_par_iter()
             .map(|q| {
+                let q = sanitize::question(q);
+                let question_id = q.id;
                 let mut answers = q.answers;
                 answers.par_sort_unstable_by_key(|a| -a.score);
                 let answers = answers
                     .into_par_iter()
                     .map(|a| Answer {
-                        body: markdown::preprocess(a.body.clone()),
+                        body: preprocess_post(
+                            preprocessor,
+                            a.body,
+                            question_id,
+                            Some(a.id),
+                            failures,
+                        ),
                         ..a
                     })
                     .collect();
                 Question {
                     answers,
-                    body: markdown::preprocess(q.body),
+                    body: q.body.map(|body| {
+                        preprocess_post(preprocessor, body, question_id, None, failures)
+                    }),
                     ..q
                 }
             })
@@ -183,25 +1262,1544 @@ impl Api {
     }
 }
 
-/// Creates stackexchange API url given endpoint
-// TODO lazy static this url parse
-fn stackexchange_url(path: &str) -> Url {
-    let mut url = Url::parse(SE_API_URL).unwrap();
+/// Converts the body of a post, borrowing it when there's nothing to convert
+type Preprocessor<'a> = dyn Fn(&str) -> Cow<'_, str> + Sync + 'a;
+
+/// Preprocess the body of a single question or answer, isolated from all the others
+/// Takes ownership of `body` so that it can be reused as is when there's nothing to convert.
+fn preprocess_post(
+    preprocessor: &Preprocessor<'_>,
+    body: String,
+    question_id: u32,
+    answer_id: Option<u32>,
+    failures: &Failures,
+) -> String {
+    match isolation::catch(|| preprocessor(&body)) {
+        // Borrowing all of `body` means there was nothing to convert
+        Ok(Cow::Borrowed(processed)) if processed.len() == body.len() => {}
+        Ok(processed) => return processed.into_owned(),
+        Err(message) => {
+            failures.record(Failure {
+                question_id,
+                answer_id,
+                stage: Stage::Preprocess,
+                message,
+            });
+            return isolation::fallback_markdown(&body, answer_id.is_some());
+        }
+    }
+    body
+}
+
+/// Record the `site` fetched questions are on, since SE doesn't include it, and the `request`
+/// they came with
+fn tag_site(site: &str, request: Request, qs: Vec<Question<String>>) -> Vec<Question<String>> {
+    qs.into_iter()
+        .map(|q| Question {
+            site: site.to_string(),
+            provenance: Some(Provenance::api(site, request)),
+            ..q
+        })
+        .collect()
+}
+
+/// Rank questions the API returned in the order they're in
+fn rank<S>(qs: &mut [Question<S>]) {
+    for (i, q) in qs.iter_mut().enumerate() {
+        if let Some(provenance) = q.provenance.as_mut() {
+            provenance.rank = i + 1;
+        }
+    }
+}
+
+/// Combine `cached` and `fetched` questions, ordered as in `ids`
+fn merge_in_order(
+    ids: &[String],
+    mut cached: HashMap<u32, Question<String>>,
+    fetched: Vec<Question<String>>,
+) -> Vec<Question<String>> {
+    cached.extend(fetched.into_iter().map(|q| (q.id, q)));
+    ids.iter()
+        .filter_map(|id| cached.remove(&id.parse().ok()?))
+        .collect()
+}
+
+/// Distribute answers fetched separately among their questions, keeping the order they came in
+fn attach_answers(
+    qs: Vec<Question<String>>,
+    answers: Vec<StandaloneAnswer>,
+) -> Vec<Question<String>> {
+    let mut by_question: HashMap<u32, Vec<Answer<String>>> = HashMap::new();
+    for a in answers {
+        by_question.entry(a.question_id).or_default().push(a.into());
+    }
+    qs.into_iter()
+        .map(|q| Question {
+            answers: by_question.remove(&q.id).unwrap_or_default(),
+            ..q
+        })
+        .collect()
+}
+
+/// Creates stackexchange API url given the API base (including version) and endpoint
+fn stackexchange_url(base: &str, path: &str) -> Result<Url> {
+    let mut url = parse_api_url(base).map_err(|reason| Error::InvalidApiUrl {
+        url: base.to_string(),
+        reason,
+    })?;
+    // Checked to be a base by `parse_api_url`
     url.path_segments_mut()
         .unwrap()
-        .push(SE_API_VERSION)
+        .pop_if_empty()
         .extend(path.split('/'));
-    url
+    Ok(url)
+}
+
+/// Parse the base of an API, which endpoints get appended to, or say what's wrong with it
+pub fn parse_api_url(base: &str) -> std::result::Result<Url, String> {
+    let url = Url::parse(base).map_err(|e| e.to_string())?;
+    if url.cannot_be_a_base() {
+        return Err(String::from("not a base URL"));
+    }
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme `{}`", url.scheme()));
+    }
+    Ok(url)
+}
+
+/// Whether `version` looks like an API version, e.g. `2.3`
+pub fn valid_api_version(version: &str) -> bool {
+    version
+        .split('.')
+        .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use super::super::network::{Destination, ExtraHeaders};
+
+    const INVALID_FILTER: &str = r#"{
+        "error_id": 400,
+        "error_message": "filter",
+        "error_name": "bad_parameter"
+    }"#;
+
+    const WITHBODY_QUESTIONS: &str = r#"{
+        "items": [{
+            "tags": ["vim"],
+            "is_answered": true,
+            "view_count": 2612233,
+            "answer_count": 13,
+            "score": 4123,
+            "question_id": 11828270,
+            "link": "https://stackoverflow.com/questions/11828270/how-do-i-exit-vim",
+            "title": "How do I exit Vim?",
+            "body": "<p>I am stuck and cannot escape.</p>"
+        }],
+        "has_more": false,
+        "quota_max": 10000,
+        "quota_remaining": 9999
+    }"#;
+
+    const WITHBODY_ANSWERS: &str = r#"{
+        "items": [
+            {"is_accepted": true, "score": 5000, "answer_id": 1, "question_id": 11828270,
+             "body": "<p>Hit <kbd>Esc</kbd>, then type <code>:q</code></p>"},
+            {"is_accepted": false, "score": 10, "answer_id": 2, "question_id": 42,
+             "body": "<p>Unrelated</p>"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_detect_invalid_filter() {
+        match serde_json::from_str::<ApiResponse<Question<String>>>(INVALID_FILTER).unwrap() {
+            ApiResponse::Error(e) => assert!(e.is_invalid_filter()),
+            r => panic!("expected api error, got {:?}", r),
+        }
+        let throttled = r#"{"error_id": 502, "error_name": "throttle_violation",
+            "error_message": "too many requests from this IP"}"#;
+        match serde_json::from_str::<ApiResponse<Question<String>>>(throttled).unwrap() {
+            ApiResponse::Error(e) => assert!(!e.is_invalid_filter()),
+            r => panic!("expected api error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_owner() {
+        let answers: Vec<Answer<String>> = serde_json::from_str(
+            r#"[
+                {"answer_id": 1, "score": 5, "is_accepted": true, "body_markdown": "a",
+                 "owner": {"display_name": "jane", "reputation": 12345, "user_type": "registered"}},
+                {"answer_id": 2, "score": 1, "is_accepted": false, "body_markdown": "b",
+                 "owner": {"user_type": "does_not_exist"}},
+                {"answer_id": 3, "score": 0, "is_accepted": false, "body_markdown": "c"}
+            ]"#,
+        )
+        .unwrap();
+        let byline = |a: &Answer<String>, locale| a.owner.as_ref().and_then(|o| o.byline(locale));
+        assert_eq!(
+            byline(&answers[0], Locale::En),
+            Some(String::from("jane (12.3k)"))
+        );
+        assert_eq!(
+            byline(&answers[0], Locale::Iso),
+            Some(String::from("jane (12345)"))
+        );
+        // A deleted user, and an answer cached before owners were fetched
+        assert_eq!(answers[1].owner, Some(Owner::default()));
+        assert_eq!(byline(&answers[1], Locale::En), None);
+        assert_eq!(answers[2].owner, None);
+        let owner = Owner {
+            display_name: Some(String::from("jane")),
+            reputation: None,
+        };
+        assert_eq!(owner.byline(Locale::En), Some(String::from("jane")));
+        // Left out of JSON output when unknown
+        let json = serde_json::to_value(&answers[1]).unwrap();
+        assert_eq!(json["owner"], serde_json::json!({}));
+        assert!(serde_json::to_value(&answers[2])
+            .unwrap()
+            .get("owner")
+            .is_none());
+    }
+
+    #[test]
+    fn test_withbody_shape() {
+        let qs = serde_json::from_str::<ApiResponse<Question<String>>>(WITHBODY_QUESTIONS)
+            .unwrap()
+            .into_items()
+            .unwrap();
+        assert_eq!(qs.len(), 1);
+        assert_eq!(
+            qs[0].body.as_deref(),
+            Some("<p>I am stuck and cannot escape.</p>")
+        );
+        assert_eq!(
+            qs[0].link.as_deref(),
+            Some("https://stackoverflow.com/questions/11828270/how-do-i-exit-vim")
+        );
+        assert!(qs[0].answers.is_empty());
+
+        let answers = serde_json::from_str::<ApiResponse<StandaloneAnswer>>(WITHBODY_ANSWERS)
+            .unwrap()
+            .into_items()
+            .unwrap();
+        let qs = attach_answers(qs, answers);
+        assert_eq!(qs[0].answers.len(), 1);
+        assert_eq!(qs[0].answers[0].id, 1);
+        assert!(qs[0].answers[0].is_accepted);
+    }
+
+    /// Question as returned by the filter derived from `SE_FILTER`, or without its body by the
+    /// slim filter
+    fn filtered_questions(body: bool) -> String {
+        let body = if body {
+            r#""body_markdown": "I am stuck and cannot escape.","#
+        } else {
+            ""
+        };
+        format!(
+            r#"{{
+                "items": [{{
+                    "question_id": 11828270,
+                    "score": 4123,
+                    "title": "How do I exit Vim?",
+                    "tags": ["vim", "exit"],
+                    {}
+                    "answers": [{{"answer_id": 1, "score": 5000, "is_accepted": true,
+                                  "body_markdown": "Hit `Esc`, then type `:q`"}}]
+                }}]
+            }}"#,
+            body
+        )
+    }
+
+    #[test]
+    fn test_filter_shapes() {
+        let parse = |payload: &str| {
+            serde_json::from_str::<ApiResponse<Question<String>>>(payload)
+                .unwrap()
+                .into_items()
+                .unwrap()
+        };
+        let full = parse(&filtered_questions(true));
+        assert_eq!(
+            full[0].body.as_deref(),
+            Some("I am stuck and cannot escape.")
+        );
+        assert_eq!(full[0].answers[0].body, "Hit `Esc`, then type `:q`");
+        assert_eq!(full[0].tags, vec!["vim", "exit"]);
+
+        let slim = parse(&filtered_questions(false));
+        assert_eq!(slim[0].body, None);
+        assert_eq!(slim[0].title, "How do I exit Vim?");
+        assert_eq!(slim[0].answers[0].body, "Hit `Esc`, then type `:q`");
+
+        // Bodies that are left out stay out through preprocessing
+        let slim = Api::preprocess(slim, &default_preprocess, &Failures::default());
+        assert_eq!(slim[0].body, None);
+    }
+
+    #[tokio::test]
+    async fn test_derived_filter() {
+        let (api_url, request) =
+            mock_server(r#"{"items": [{"filter": "tagged", "filter_type": "safe"}]}"#).await;
+        let filters = Filters::default();
+        let api = Api::new(None, Some(String::from("custom")), OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(filters.clone());
+        assert_eq!(&*api.filter().await, "tagged");
+        let request = request.await.unwrap();
+        assert!(
+            request.starts_with("GET /2.3/filters/create?"),
+            "{}",
+            request
+        );
+        assert!(request.contains("base=custom"));
+        assert!(request.contains("include=question.tags"));
+        assert!(!request.contains("exclude"));
+        // Created once per session; the mock server is gone by now
+        assert_eq!(&*api.clone().filter().await, "tagged");
+
+        // And once at all
+        let again = Api::new(None, Some(String::from("custom")), OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(filters);
+        assert_eq!(&*again.filter().await, "tagged");
+    }
+
+    #[tokio::test]
+    async fn test_slim_filter() {
+        let (api_url, request) =
+            mock_server(r#"{"items": [{"filter": "slim", "filter_type": "safe"}]}"#).await;
+        let slim = Api::new(None, Some(String::from("custom")), OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_question_bodies(false);
+        assert_eq!(&*slim.filter().await, "slim");
+        let request = request.await.unwrap();
+        assert!(request.contains("base=custom"));
+        assert!(request.contains("include=question.tags"));
+        assert!(request.contains("exclude=question.body_markdown"));
+    }
+
+    #[tokio::test]
+    async fn test_derived_filter_unavailable() {
+        // Without a derived filter, tags are left out and bodies are fetched after all rather
+        // than failing the search
+        let api = Api::new(None, None, OfflinePolicy::Offline).with_question_bodies(false);
+        assert_eq!(&*api.filter().await, SE_FILTER);
+    }
+
+    #[tokio::test]
+    async fn test_filter_fallback() {
+        let api = Api::new(None, None, OfflinePolicy::Online);
+        let filters = RefCell::new(Vec::new());
+        let fetch = |filter: &str| {
+            filters.borrow_mut().push(filter.to_string());
+            let payload = if filter == SE_FALLBACK_FILTER {
+                WITHBODY_QUESTIONS
+            } else {
+                INVALID_FILTER
+            };
+            async move {
+                Ok(serde_json::from_str::<ApiResponse<Question<String>>>(
+                    payload,
+                )?)
+            }
+        };
+
+        let filter = SE_FILTER;
+        let qs = api
+            .with_filter_fallback(filter, fetch)
+            .await
+            .and_then(ApiResponse::into_items)
+            .unwrap();
+        assert_eq!(qs.len(), 1);
+        assert!(api.filter_degraded());
+        assert_eq!(*filters.borrow(), vec![SE_FILTER, SE_FALLBACK_FILTER]);
+
+        // Once degraded, don't bother with the broken filter anymore
+        api.with_filter_fallback(filter, fetch).await.unwrap();
+        assert_eq!(filters.borrow().len(), 3);
+        assert_eq!(filters.borrow()[2], SE_FALLBACK_FILTER);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_not_retried() {
+        let api = Api::new(None, Some(String::from("custom")), OfflinePolicy::Online);
+        let filters = RefCell::new(Vec::new());
+        let fetch = |filter: &str| {
+            filters.borrow_mut().push(filter.to_string());
+            async {
+                Ok(serde_json::from_str::<ApiResponse<Question<String>>>(
+                    r#"{"error_id": 403, "error_name": "access_denied", "error_message": "no"}"#,
+                )?)
+            }
+        };
+        match api
+            .with_filter_fallback("custom", fetch)
+            .await
+            .and_then(ApiResponse::into_items)
+        {
+            Err(Error::Api { name, status, .. }) => {
+                assert_eq!((name.as_deref(), status), (Some("access_denied"), 403))
+            }
+            r => panic!("expected stackexchange error, got {:?}", r),
+        }
+        assert!(!api.filter_degraded());
+        assert_eq!(*filters.borrow(), vec!["custom"]);
+    }
+
+    /// Filters as if created in an earlier session, so that requests go straight to the endpoint
+    /// under test
+    fn created_filters(api_url: &str) -> Filters {
+        let filters = Filters::default();
+        let endpoint = format!("{}/filters/create", api_url);
+        for exclude in &["", SE_SLIM_FILTER_EXCLUDE] {
+            let derived = Derived {
+                endpoint: &endpoint,
+                base: SE_FILTER,
+                include: SE_FILTER_INCLUDE,
+                exclude,
+            };
+            filters.insert(&derived, SE_FILTER);
+        }
+        filters
+    }
+
+    fn team(api_url: &str) -> Team {
+        Team {
+            api_url: api_url.to_string(),
+            slug: String::from("acme"),
+            token: String::from("secret-token"),
+        }
+    }
+
+    /// Serve `body` as the response to a single request, handing back the raw request received
+    async fn mock_server(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (format!("http://{}/2.3", addr), handle)
+    }
+
+    /// Serve any number of requests with the body of the first of `routes` whose request line
+    /// starts with its prefix, or empty results, handing back the request lines received
+    async fn routed_server(
+        routes: &'static [(&'static str, &'static str)],
+    ) -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = received.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    let request = String::from_utf8(request).unwrap();
+                    let line = request.lines().next().unwrap_or_default().to_string();
+                    let body = routes
+                        .iter()
+                        .find(|(prefix, _)| line.starts_with(prefix))
+                        .map_or(r#"{"items": []}"#, |(_, body)| body);
+                    log.lock().unwrap().push(line);
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (format!("http://{}/2.3", addr), received)
+    }
+
+    /// Serve empty results to any number of requests, each taking a while, and report how many
+    /// requests came in in total and at most at once
+    async fn slow_server() -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let (total, max) = (requests.clone(), peak.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (total, max, in_flight) = (total.clone(), max.clone(), in_flight.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    total.fetch_add(1, Ordering::SeqCst);
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let body = r#"{"items": []}"#;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        (format!("http://{}/2.3", addr), requests, peak)
+    }
+
+    #[tokio::test]
+    async fn test_questions_batches_ids() {
+        let (api_url, requests, peak) = slow_server().await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+
+        let ids = (0..350).map(|id| id.to_string()).collect();
+        assert!(api.questions("work", ids).await.unwrap().is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        assert_eq!(peak.load(Ordering::SeqCst), SITE_CONCURRENT_REQUESTS_LIMIT);
+
+        requests.store(0, Ordering::SeqCst);
+        peak.store(0, Ordering::SeqCst);
+        let ids = (0..20).map(|id| id.to_string()).collect();
+        api.questions("work", ids).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let (api_url, requests, _) = slow_server().await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_timeouts(Timeouts {
+                request: Duration::from_millis(20),
+                ..Timeouts::default()
+            });
+        let ids = vec![String::from("1")];
+        let e = api.questions("stackoverflow", ids).await.unwrap_err();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(
+            matches!(
+                &e,
+                Error::RequestTimeout { to, after, setting: "timeout_secs" }
+                    if to == "stackoverflow" && *after == Duration::from_millis(20)
+            ),
+            "{:?}",
+            e
+        );
+        assert_eq!(
+            e.to_string(),
+            "request to stackoverflow timed out after 20ms; raise `timeout_secs` in your config"
+        );
+    }
+
+    fn question(id: u32) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            answers: vec![Answer {
+                id: id * 10,
+                score: 1,
+                body: format!("Answer {}", id),
+                ..Answer::default()
+            }],
+            title: format!("Question {}", id),
+            body: Some(String::new()),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    /// Preprocessor with a bug triggered by certain bodies
+    fn fragile_preprocess(body: &str) -> Cow<'_, str> {
+        if body.contains("BOOM") {
+            panic!("preprocessor bug");
+        }
+        preprocess::preprocess_with(body, preprocess::DEFAULT_PASSES)
+    }
+
+    #[test]
+    fn test_preprocess_isolates_failures() {
+        let mut qs = vec![question(1), question(2), question(3)];
+        qs[1].answers[0].body = String::from("BOOM <kbd>x</kbd>");
+        qs[2].body = Some(String::from("BOOM"));
+        qs[2].answers[0].body = String::from("Press <kbd>q</kbd>");
+        let failures = Failures::default();
+        let qs = Api::preprocess(qs, &fragile_preprocess, &failures);
+
+        assert_eq!(qs.len(), 3);
+        assert_eq!(qs[0].answers[0].body, "Answer 1");
+        // The failing body is shown verbatim, under a notice
+        assert_eq!(
+            qs[1].answers[0].body,
+            isolation::fallback_markdown("BOOM <kbd>x</kbd>", true)
+        );
+        assert!(qs[2]
+            .body
+            .as_ref()
+            .unwrap()
+            .starts_with("*Rendering failed for this question"));
+        // Answers of a question whose body failed still render
+        assert_eq!(qs[2].answers[0].body, "Press **[q]**");
+
+        let mut failures = failures.list();
+        failures.sort_by_key(|f| f.question_id);
+        assert_eq!(
+            failures,
+            vec![
+                Failure {
+                    question_id: 2,
+                    answer_id: Some(20),
+                    stage: Stage::Preprocess,
+                    message: String::from("preprocessor bug"),
+                },
+                Failure {
+                    question_id: 3,
+                    answer_id: None,
+                    stage: Stage::Preprocess,
+                    message: String::from("preprocessor bug"),
+                },
+            ]
+        );
+    }
+
+    /// 20 questions with 3 answers each; one body in four has something to convert
+    fn synthetic_workload() -> Vec<Question<String>> {
+        (1..=20)
+            .map(|id| {
+                let mut q = question(id);
+                q.body = Some(format!("How do I quit program {}?\n\n    code block", id));
+                q.answers = (0..3)
+                    .map(|n| Answer {
+                        id: id * 10 + n,
+                        score: n as i32,
+                        body: if (id + n) % 4 == 0 {
+                            format!("Press <kbd>Ctrl</kbd>+<kbd>{}</kbd>", n)
+                        } else {
+                            format!("Run `quit {}` and then **restart**", n)
+                        },
+                        ..q.answers[0].clone()
+                    })
+                    .collect();
+                q
+            })
+            .collect()
+    }
+
+    fn default_preprocess(body: &str) -> Cow<'_, str> {
+        preprocess::preprocess_with(body, preprocess::DEFAULT_PASSES)
+    }
+
+    /// `Api::preprocess` as it was before bodies were taken by value
+    fn preprocess_reference(qs: Vec<Question<String>>) -> Vec<Question<String>> {
+        fn preprocess(input: String) -> String {
+            input
+                .as_str()
+                .trim()
+                .replace("<kbd>", "**[")
+                .replace("</kbd>", "]**")
+        }
+        fn preprocess_post(body: &str) -> String {
+            isolation::catch(|| preprocess(body.to_string()))
+                .unwrap_or_else(|_| isolation::fallback_markdown(body, false))
+        }
+        qs.into_par_iter()
+            .map(|q| {
+                let mut answers = q.answers;
+                answers.par_sort_unstable_by_key(|a| -a.score);
+                let answers = answers
+                    .into_par_iter()
+                    .map(|a| Answer {
+                        body: preprocess_post(&a.body),
+                        ..a
+                    })
+                    .collect();
+                Question {
+                    answers,
+                    body: q.body.as_deref().map(preprocess_post),
+                    ..q
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_preprocess_equivalence() {
+        let failures = Failures::default();
+        let qs = Api::preprocess(synthetic_workload(), &default_preprocess, &failures);
+        let expected = preprocess_reference(synthetic_workload());
+        assert!(failures.list().is_empty());
+        for (q, expected) in qs.iter().zip(&expected) {
+            assert_eq!(q.body, expected.body);
+            let answers: Vec<_> = q.answers.iter().map(|a| (a.id, &a.body)).collect();
+            let expected: Vec<_> = expected.answers.iter().map(|a| (a.id, &a.body)).collect();
+            assert_eq!(answers, expected);
+        }
+        assert_eq!(qs[1].answers[0].body, "Press **[Ctrl]**+**[2]**");
+    }
+
+    #[test]
+    fn test_preprocess_allocations() {
+        let failures = Failures::default();
+        let (_, allocations) = crate::alloc_count::count(|| {
+            Api::preprocess(synthetic_workload(), &default_preprocess, &failures)
+        });
+        let (_, reference) =
+            crate::alloc_count::count(|| preprocess_reference(synthetic_workload()));
+        let (_, workload) = crate::alloc_count::count(synthetic_workload);
+        let (allocations, reference) = (allocations - workload, reference - workload);
+        // Only the 15 bodies with something to convert need new strings, where every one of the
+        // 80 bodies used to be copied at least once
+        assert!(
+            allocations * 2 < reference,
+            "{} allocations, {} before",
+            allocations,
+            reference
+        );
+    }
+
+    fn temp_question_cache(name: &str) -> QuestionCache {
+        let dir = std::env::temp_dir().join(format!("so-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        QuestionCache::new(dir)
+    }
+
+    fn ids(ids: &[u32]) -> Vec<String> {
+        ids.iter().map(u32::to_string).collect()
+    }
+
+    #[test]
+    fn test_merge_in_order() {
+        let cached = vec![(2, question(2)), (5, question(5))]
+            .into_iter()
+            .collect();
+        let fetched = vec![question(3), question(1)];
+        let merged = merge_in_order(&ids(&[1, 2, 3, 4, 5]), cached, fetched);
+        let merged: Vec<u32> = merged.iter().map(|q| q.id).collect();
+        assert_eq!(merged, vec![1, 2, 3, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_questions_fetches_only_uncached() {
+        let body = r#"{"items": [
+            {"question_id": 3, "score": 1, "title": "Question 3", "body_markdown": "",
+             "answers": [{"answer_id": 30, "score": 1, "is_accepted": false,
+                          "body_markdown": "Answer 3"}]},
+            {"question_id": 1, "score": 1, "title": "Question 1", "body_markdown": "",
+             "answers": [{"answer_id": 10, "score": 1, "is_accepted": false,
+                          "body_markdown": "Answer 1"}]}
+        ]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let cache = temp_question_cache("api-partial-hit");
+        cache.put("team-acme", &[question(2)]).unwrap();
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(cache.clone()));
+
+        let qs = api.questions("work", ids(&[1, 2, 3])).await.unwrap();
+        let titles: Vec<&str> = qs.iter().map(|q| q.title.as_str()).collect();
+        assert_eq!(titles, vec!["Question 1", "Question 2", "Question 3"]);
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /2.3/questions/1;3?"));
+
+        // Everything is cached now, so even an offline client can answer
+        let api = Api::new(None, None, OfflinePolicy::Offline)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(cache.clone()));
+        let qs = api.questions("work", ids(&[3, 1, 2])).await.unwrap();
+        assert_eq!(qs.iter().map(|q| q.id).collect::<Vec<_>>(), vec![3, 1, 2]);
+
+        // Unless refreshing
+        let api = api.with_question_cache(Some(cache.refresh(true)));
+        let qs = api.questions("work", ids(&[3, 1, 2])).await;
+        assert!(matches!(qs, Err(Error::Offline)));
+    }
+
+    #[tokio::test]
+    async fn test_questions_follows_merges() {
+        // 2 was merged into 5
+        let body = r#"{"items": [
+            {"question_id": 5, "score": 1, "title": "Question 5", "body_markdown": "",
+             "answers": [{"answer_id": 50, "score": 1, "is_accepted": false,
+                          "body_markdown": "Answer 5"}]},
+            {"question_id": 1, "score": 1, "title": "Question 1", "body_markdown": "",
+             "answers": [{"answer_id": 10, "score": 1, "is_accepted": false,
+                          "body_markdown": "Answer 1"}]}
+        ]}"#;
+        let (api_url, _) = mock_server(body).await;
+        let cache = temp_question_cache("api-merged");
+        let dir = std::env::temp_dir().join(format!("so-api-aliases-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let aliases = Aliases::load(dir.join("aliases.json")).unwrap();
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(cache.clone()))
+            .with_aliases(Some(aliases.clone()));
+
+        let qs = api.questions("work", ids(&[2, 1])).await.unwrap();
+        assert_eq!(qs.iter().map(|q| q.id).collect::<Vec<_>>(), vec![5, 1]);
+        assert_eq!(aliases.resolve("work", 2), 5);
+        // The alias is persisted for later runs
+        let reloaded = Aliases::load(dir.join("aliases.json")).unwrap();
+        assert_eq!(reloaded.resolve("work", 2), 5);
+
+        // Looking up the old id later finds the cached question under its new id
+        let api = Api::new(None, None, OfflinePolicy::Offline)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(cache))
+            .with_aliases(Some(reloaded));
+        let qs = api.questions("work", ids(&[2, 5, 1])).await.unwrap();
+        assert_eq!(qs.iter().map(|q| q.id).collect::<Vec<_>>(), vec![5, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_questions_follows_several_merges() {
+        // 2 was merged into 5 and 3 into 6, while 4 was deleted
+        const ROUTES: &[(&str, &str)] = &[
+            (
+                "GET /2.3/questions/2;3;4;1?",
+                r#"{"items": [
+                    {"question_id": 6, "score": 1, "title": "Question 6", "body_markdown": "",
+                     "answers": []},
+                    {"question_id": 5, "score": 1, "title": "Question 5", "body_markdown": "",
+                     "answers": []},
+                    {"question_id": 1, "score": 1, "title": "Question 1", "body_markdown": "",
+                     "answers": []}
+                ]}"#,
+            ),
+            (
+                "GET /2.3/questions/2?",
+                r#"{"items": [{"question_id": 5}]}"#,
+            ),
+            (
+                "GET /2.3/questions/3?",
+                r#"{"items": [{"question_id": 6}]}"#,
+            ),
+        ];
+        let (api_url, received) = routed_server(ROUTES).await;
+        let dir = std::env::temp_dir().join(format!("so-api-merges-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let aliases = Aliases::load(dir.join("aliases.json")).unwrap();
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_unanswered(true)
+            .with_aliases(Some(aliases.clone()));
+
+        let qs = api.questions("work", ids(&[2, 3, 4, 1])).await.unwrap();
+        assert_eq!(qs.iter().map(|q| q.id).collect::<Vec<_>>(), vec![5, 6, 1]);
+        assert_eq!(aliases.resolve("work", 2), 5);
+        assert_eq!(aliases.resolve("work", 3), 6);
+        assert_eq!(aliases.resolve("work", 4), 4);
+        // Both merges were found before getting to the deleted question
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+        let reloaded = Aliases::load(dir.join("aliases.json")).unwrap();
+        assert_eq!(reloaded.resolve("work", 3), 6);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_questions_despite_unwritable_state() {
+        // 2 was merged into 5
+        let body = r#"{"items": [
+            {"question_id": 5, "score": 1, "title": "Question 5", "body_markdown": "",
+             "answers": [{"answer_id": 50, "score": 1, "is_accepted": false,
+                          "body_markdown": "Answer 5"}]}
+        ]}"#;
+        let (api_url, _) = mock_server(body).await;
+        let dir = std::env::temp_dir().join(format!("so-api-unwritable-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let aliases = Aliases::load(dir.join("state").join("aliases.json")).unwrap();
+        // A file where the directories should go
+        std::fs::write(dir.join("state"), "").unwrap();
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(QuestionCache::new(
+                dir.join("state").join("questions"),
+            )))
+            .with_aliases(Some(aliases.clone()));
+
+        let qs = api.questions("work", ids(&[2])).await.unwrap();
+        assert_eq!(qs.iter().map(|q| q.id).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(aliases.resolve("work", 2), 5);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn test_stackexchange_url() {
         assert_eq!(
-            stackexchange_url("some/endpoint").as_str(),
-            "http://api.stackexchange.com/2.2/some/endpoint"
+            Api::new(None, None, OfflinePolicy::Online)
+                .url("some/endpoint")
+                .unwrap()
+                .as_str(),
+            "https://api.stackexchange.com/2.3/some/endpoint"
+        );
+        assert_eq!(
+            Api::new(None, None, OfflinePolicy::Online)
+                .with_api_url("https://mirror.example.com/se/api/")
+                .url("some/endpoint")
+                .unwrap()
+                .as_str(),
+            "https://mirror.example.com/se/api/2.3/some/endpoint"
+        );
+        assert_eq!(
+            Api::new(None, None, OfflinePolicy::Online)
+                .with_api_version("2.2")
+                .url("some/endpoint")
+                .unwrap()
+                .as_str(),
+            "https://api.stackexchange.com/2.2/some/endpoint"
+        );
+        assert_eq!(
+            stackexchange_url("https://api.stackoverflowteams.com/2.3/", "search/advanced")
+                .unwrap()
+                .as_str(),
+            "https://api.stackoverflowteams.com/2.3/search/advanced"
+        );
+        assert!(stackexchange_url("not a url", "sites").is_err());
+        assert_eq!(
+            stackexchange_url("mailto:se@example.com", "sites")
+                .unwrap_err()
+                .to_string(),
+            "Invalid API URL `mailto:se@example.com`: not a base URL"
+        );
+        assert!(stackexchange_url("ftp://mirror.example.com", "sites").is_err());
+    }
+
+    #[test]
+    fn test_team_url_and_params() {
+        let api = Api::new(
+            Some(String::from("public key")),
+            None,
+            OfflinePolicy::Online,
+        )
+        .with_team(Some(team("https://api.stackoverflowteams.com/2.3")));
+        assert_eq!(
+            api.url("questions/1;2").unwrap().as_str(),
+            "https://api.stackoverflowteams.com/2.3/questions/1;2"
+        );
+        assert_eq!(api.site_param("stackoverflow"), ("team", "acme"));
+        assert!(!api.get_default_se_opts().contains_key("key"));
+
+        let api = Api::new(
+            Some(String::from("public key")),
+            None,
+            OfflinePolicy::Online,
+        );
+        assert_eq!(api.site_param("stackoverflow"), ("site", "stackoverflow"));
+        assert_eq!(api.get_default_se_opts().get("key"), Some(&"public key"));
+    }
+
+    #[tokio::test]
+    async fn test_team_request() {
+        let body = r#"{"items": [{"question_id": 1, "score": 3, "title": "Deploying",
+            "body_markdown": "How?", "answers": [{"answer_id": 2, "score": 1,
+            "is_accepted": true, "body_markdown": "Like this"}]}]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(
+            Some(String::from("public key")),
+            None,
+            OfflinePolicy::Online,
         )
+        .with_team(Some(team(&api_url)))
+        .with_filters(created_filters(&api_url));
+
+        let qs = api
+            .search_advanced("deploy", "work", 5, 1)
+            .await
+            .unwrap()
+            .questions;
+        assert_eq!(qs.len(), 1);
+        assert_eq!(qs[0].answers[0].body, "Like this");
+        assert_eq!(qs[0].site, "work");
+
+        let request = server.await.unwrap();
+        let request_line = request.lines().next().unwrap();
+        assert!(request_line.starts_with("GET /2.3/search/advanced?"));
+        assert!(request_line.contains("team=acme"));
+        assert!(request_line.contains("q=deploy"));
+        assert!(!request_line.contains("site="));
+        assert!(!request_line.contains("key="));
+        assert!(request
+            .lines()
+            .any(|l| l.eq_ignore_ascii_case("x-api-access-token: secret-token")));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_question_skipped() {
+        // The second question's answer lost its body, and the third has a null score
+        let body = r#"{"items": [
+            {"question_id": 1, "score": 3, "title": "Deploying", "body_markdown": "How?",
+                "answers": [{"answer_id": 2, "score": 1, "is_accepted": true,
+                "body_markdown": "Like this"}]},
+            {"question_id": 3, "score": 1, "title": "Deleted", "body_markdown": "Why?",
+                "answers": [{"answer_id": 4, "score": 1, "is_accepted": false}]},
+            {"question_id": 5, "score": null, "title": "Null", "body_markdown": "What?",
+                "answers": []},
+            {"question_id": 6, "score": 2, "title": "Releasing", "body_markdown": "When?",
+                "answers": [{"answer_id": 7, "score": 5, "is_accepted": false,
+                "body_markdown": "Now"}]}
+        ]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+        let qs = api
+            .search_advanced("deploy", "work", 5, 1)
+            .await
+            .unwrap()
+            .questions;
+        assert_eq!(qs.iter().map(|q| q.id).collect::<Vec<_>>(), vec![1, 6]);
+        server.await.unwrap();
+        let skipped = api.skipped().list();
+        assert_eq!(
+            skipped.iter().map(|s| s.question_id).collect::<Vec<_>>(),
+            vec![Some(3), Some(5)]
+        );
+        assert!(skipped[0].message.contains("body_markdown"));
+        assert_eq!(skipped[0].label, "work search");
+    }
+
+    #[tokio::test]
+    async fn test_every_question_malformed() {
+        let body = r#"{"items": [{"question_id": 1, "score": null, "title": "Null"}]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+        let error = api
+            .search_advanced("deploy", "work", 5, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::SerdeJson(_)));
+        server.await.unwrap();
+        assert_eq!(api.skipped().list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_answer_question_id() {
+        let body = r#"{"items": [{"answer_id": 2, "question_id": 1, "score": 1}]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+        assert_eq!(api.answer_question_id("work", 2).await.unwrap(), Some(1));
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /2.3/answers/2?"));
+
+        let (api_url, server) = mock_server(r#"{"items": []}"#).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+        assert_eq!(api.answer_question_id("work", 2).await.unwrap(), None);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_answer_question_ids() {
+        let body = r#"{"items": [
+            {"answer_id": 24410, "question_id": 24406, "score": 3},
+            {"answer_id": 11828573, "question_id": 11828270, "score": 9}
+        ]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_api_url(api_url.trim_end_matches("/2.3"))
+            .with_api_version("2.3")
+            .with_filters(created_filters(&api_url));
+        let ids = vec![
+            String::from("11828573"),
+            String::from("24410"),
+            String::from("404"),
+        ];
+        assert_eq!(
+            api.answer_question_ids("askubuntu", &ids).await.unwrap(),
+            vec![(24410, 24406), (11828573, 11828270)]
+        );
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /2.3/answers/11828573;24410;404?"));
+        assert!(request.contains("site=askubuntu"));
+    }
+
+    #[tokio::test]
+    async fn test_extra_headers() {
+        let mut extra = ExtraHeaders::default();
+        extra
+            .all
+            .insert("X-Proxy-Token".into(), "everywhere".into());
+        extra.all.insert("X-Department".into(), "physics".into());
+        extra
+            .stackexchange
+            .insert("X-Proxy-Token".into(), "s3cret".into());
+        let (api_url, server) = mock_server(r#"{"items": []}"#).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_extra_headers(extra.for_destination(Destination::StackExchange).unwrap());
+        assert_eq!(api.answer_question_id("work", 2).await.unwrap(), None);
+        let request = server.await.unwrap();
+        let has = |header: &str| request.lines().any(|l| l.eq_ignore_ascii_case(header));
+        assert!(has("x-proxy-token: s3cret"));
+        assert!(!has("x-proxy-token: everywhere"));
+        assert!(has("x-department: physics"));
+        // The API's own headers are still sent
+        assert!(has("x-api-access-token: secret-token"));
+    }
+
+    #[tokio::test]
+    async fn test_answer_comments() {
+        let body = r#"{"items": [
+            {"score": 12, "body_markdown": "This no longer works on v3, use <kbd>X</kbd>",
+             "owner": {"display_name": "Jane\u001b]0;pwned\u0007"}},
+            {"score": 0, "body": "Thanks!"}
+        ]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let filters = Filters::default();
+        let endpoint = format!("{}/filters/create", api_url);
+        let derived = Derived {
+            endpoint: &endpoint,
+            base: SE_COMMENTS_FILTER_BASE,
+            include: SE_COMMENTS_FILTER_INCLUDE,
+            exclude: "",
+        };
+        filters.insert(&derived, "comments-filter");
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(filters);
+        let comments = api.answer_comments("work", 7).await.unwrap();
+        assert_eq!(
+            comments,
+            vec![
+                Comment {
+                    score: 12,
+                    body_markdown: String::from("This no longer works on v3, use **[X]**"),
+                    owner_display_name: String::from("Jane"),
+                },
+                Comment {
+                    score: 0,
+                    body_markdown: String::from("Thanks!"),
+                    owner_display_name: String::from("anonymous"),
+                },
+            ]
+        );
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /2.3/answers/7/comments?"));
+        assert!(request.contains("filter=comments-filter"));
+    }
+
+    #[tokio::test]
+    async fn test_backoff() {
+        let body = r#"{"items": [], "backoff": 12, "quota_remaining": 9000}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+        assert_eq!(api.answer_question_id("work", 2).await.unwrap(), None);
+        server.await.unwrap();
+        assert_eq!(
+            api.backoff().notices(),
+            vec!["StackExchange asked us to back off from work for 12s"]
+        );
+        // Errors in the wrapper are errors all the same
+        let body = r#"{"items": [], "error_id": 502, "error_message": "slow down"}"#;
+        let response: ApiResponse<AnswerParent> = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            response.into_items().unwrap_err().to_string(),
+            "StackExchange API error 502: slow down"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_quota() {
+        let body =
+            r#"{"items": [{"total_questions": 1}], "quota_remaining": 42, "quota_max": 300}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_api_url(api_url.trim_end_matches("/2.3"))
+            .with_api_version("2.3");
+        let reading = api.check_quota("superuser").await.unwrap().unwrap();
+        assert_eq!((reading.remaining, reading.max), (42, 300));
+        // Shared with clones, which is what searches use
+        assert_eq!(api.clone().quota().lowest(), Some(reading));
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /2.3/info?"));
+        assert!(request.contains("site=superuser"));
+    }
+
+    #[tokio::test]
+    async fn test_sites() {
+        let (api_url, server) =
+            mock_server(include_str!("../../test/stackexchange/sites.json")).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_api_url(api_url.trim_end_matches("/2.3"))
+            .with_api_version("2.3");
+        let sites = api.sites().await.unwrap();
+        server.await.unwrap();
+        let site = |code| sites.iter().find(|s| s.api_site_parameter == code).unwrap();
+        assert_eq!(site("unix").site_url, "unix.stackexchange.com");
+        assert_eq!(
+            site("stackoverflow").audience,
+            "professional and enthusiast programmers"
+        );
+        assert_eq!(site("meta.stackoverflow").audience, "");
+        assert_eq!(
+            site("softwareengineering").aliases,
+            vec!["https://programmers.stackexchange.com"]
+        );
+        assert!(site("stackoverflow").is_open());
+        // Meta sites, and those shut down, aren't
+        assert!(!site("meta.stackoverflow").is_open());
+        assert_eq!(site("windowsphone").closing_date, Some(1513209600));
+        assert!(!site("windowsphone").is_open());
+        // As cached by older versions
+        let cached: Site = serde_json::from_str(
+            r#"{"api_site_parameter": "unix", "site_url": "unix.stackexchange.com"}"#,
+        )
+        .unwrap();
+        assert!(cached.aliases.is_empty());
+        assert!(cached.name.is_empty() && cached.audience.is_empty());
+        assert!(cached.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_site() {
+        let (api_url, server) = mock_server(
+            r#"{
+                "error_id": 400,
+                "error_message": "No site found for name `windowsphone`",
+                "error_name": "bad_parameter"
+            }"#,
+        )
+        .await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_api_url(api_url.trim_end_matches("/2.3"))
+            .with_api_version("2.3")
+            .with_filters(created_filters(&api_url));
+        let e = api
+            .search_advanced("deploy", "windowsphone", 5, 1)
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert!(e.is_unknown_site(), "{:?}", e);
+        assert!(Error::at_site("windowsphone", e).is_unknown_site());
+        // Other bad parameters are something else
+        let e = Error::Api {
+            site: Some(String::from("stackoverflow")),
+            endpoint: Some(String::from("search/advanced")),
+            status: 400,
+            name: Some(String::from("bad_parameter")),
+            message: String::from("site is required"),
+        };
+        assert!(!e.is_unknown_site());
+    }
+
+    #[tokio::test]
+    async fn test_api_error_message() {
+        const BAD_PARAMETER: &str = r#"{
+            "error_id": 400,
+            "error_message": "site is required",
+            "error_name": "bad_parameter"
+        }"#;
+        let message = "StackExchange API error 400 (bad_parameter): site is required";
+
+        let (api_url, server) = mock_server(BAD_PARAMETER).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_api_url(api_url.trim_end_matches("/2.3"))
+            .with_api_version("2.3");
+        match api.sites().await {
+            Err(e @ Error::Api { .. }) => {
+                assert_eq!(e.to_string(), message);
+                assert_eq!(
+                    (e.site(), e.endpoint(), e.status()),
+                    (None, Some("sites"), Some(400))
+                );
+            }
+            r => panic!("expected the API's error, got {:?}", r),
+        }
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /2.3/sites?"));
+
+        // Not an invalid filter, so there's nothing to fall back on
+        let (api_url, server) = mock_server(BAD_PARAMETER).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url));
+        match api.search_advanced("deploy", "work", 5, 1).await {
+            Err(e @ Error::Api { .. }) => {
+                assert_eq!(e.to_string(), message);
+                assert_eq!(e.site(), Some("work"));
+                assert_eq!(e.endpoint(), Some("search/advanced"));
+            }
+            r => panic!("expected the API's error, got {:?}", r),
+        }
+        server.await.unwrap();
+        assert!(!api.filter_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_search_advanced_unanswered() {
+        let body = r#"{"items": [{"question_id": 1, "score": 3, "title": "Deploying",
+            "body_markdown": "How?"}]}"#;
+        for include_unanswered in [false, true] {
+            let (api_url, server) = mock_server(body).await;
+            let api = Api::new(None, None, OfflinePolicy::Online)
+                .with_team(Some(team(&api_url)))
+                .with_filters(created_filters(&api_url))
+                .with_unanswered(include_unanswered);
+            let qs = api
+                .search_advanced("deploy", "work", 5, 1)
+                .await
+                .unwrap()
+                .questions;
+            assert_eq!(qs.len(), 1);
+            assert!(qs[0].answers.is_empty());
+
+            let request = server.await.unwrap();
+            let request_line = request.lines().next().unwrap();
+            assert_eq!(request_line.contains("answers=1"), !include_unanswered);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_advanced_tagged() {
+        for tagged in [None, Some("c++")] {
+            let (api_url, server) = mock_server(r#"{"items": []}"#).await;
+            let api = Api::new(None, None, OfflinePolicy::Online)
+                .with_team(Some(team(&api_url)))
+                .with_filters(created_filters(&api_url))
+                .with_tagged(tagged);
+            api.search_advanced("sort", "work", 5, 1).await.unwrap();
+
+            let request = server.await.unwrap();
+            let request_line = request.lines().next().unwrap();
+            assert_eq!(request_line.contains("tagged=c%2B%2B"), tagged.is_some());
+            assert_eq!(request_line.contains("tagged="), tagged.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provenance() {
+        let body = r#"{"items": [
+            {"question_id": 4, "score": 3, "title": "Deploying", "body_markdown": "How?"},
+            {"question_id": 2, "score": 1, "title": "Building", "body_markdown": "How?"}
+        ]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_unanswered(true);
+        let qs = api
+            .search_advanced("deploy", "work", 5, 1)
+            .await
+            .unwrap()
+            .questions;
+        server.await.unwrap();
+        let provenance: Vec<_> = qs.iter().map(|q| q.provenance.clone().unwrap()).collect();
+        assert_eq!(
+            provenance,
+            vec![
+                Provenance {
+                    engine: SearchEngine::StackExchange,
+                    site: String::from("work"),
+                    rank: 1,
+                    request: Request::Search { page: 1 },
+                },
+                Provenance {
+                    engine: SearchEngine::StackExchange,
+                    site: String::from("work"),
+                    rank: 2,
+                    request: Request::Search { page: 1 },
+                },
+            ]
+        );
+
+        // By id, ranked in the order asked for, whether fetched or cached
+        let body = r#"{"items": [{"question_id": 3, "score": 1, "title": "Question 3",
+            "body_markdown": ""}]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let cache = temp_question_cache("api-provenance");
+        cache.put("team-acme", &[question(2)]).unwrap();
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(cache))
+            .with_unanswered(true);
+        let qs = api.questions("work", ids(&[2, 3])).await.unwrap();
+        server.await.unwrap();
+        let provenance: Vec<_> = qs
+            .iter()
+            .map(|q| {
+                let p = q.provenance.as_ref().unwrap();
+                (q.id, p.rank, p.request)
+            })
+            .collect();
+        assert_eq!(
+            provenance,
+            vec![(2, 1, Request::Cached), (3, 2, Request::Batch { chunk: 0 })]
+        );
+        assert_eq!(
+            qs[1].provenance.as_ref().unwrap().to_string(),
+            "work via stackexchange, ranked 2, from batch 1 of ids"
+        );
+    }
+
+    #[test]
+    fn test_provenance_serialization() {
+        let provenance = Provenance {
+            engine: SearchEngine::Google,
+            site: String::from("superuser"),
+            rank: 2,
+            request: Request::Batch { chunk: 1 },
+        };
+        let q = Question {
+            provenance: Some(provenance.clone()),
+            ..question(1)
+        };
+        let json: serde_json::Value = serde_json::to_value(&q).unwrap();
+        assert_eq!(
+            json["provenance"],
+            serde_json::json!({
+                "engine": "google",
+                "site": "superuser",
+                "rank": 2,
+                "request": {"batch": {"chunk": 1}}
+            })
+        );
+        let back: Question<String> = serde_json::from_value(json).unwrap();
+        assert_eq!(back.provenance, Some(provenance));
+
+        // Never in API responses, and left out of cached questions without it
+        let json = serde_json::to_value(question(1)).unwrap();
+        assert!(json.get("provenance").is_none());
+        let q: Question<String> = serde_json::from_str(
+            r#"{"question_id": 1, "score": 1, "title": "Question 1", "body_markdown": ""}"#,
+        )
+        .unwrap();
+        assert_eq!(q.provenance, None);
+        let cached: Request = serde_json::from_str(r#""cached""#).unwrap();
+        assert_eq!(cached, Request::Cached);
+    }
+
+    #[tokio::test]
+    async fn test_questions_unanswered() {
+        let body = r#"{"items": [{"question_id": 1, "score": 3, "title": "Deploying",
+            "body_markdown": "How?"}, {"question_id": 2, "score": 1, "title": "Building",
+            "body_markdown": "How?", "answers": [{"answer_id": 3, "score": 1,
+            "is_accepted": false, "body_markdown": "Like this"}]}]}"#;
+        for include_unanswered in [false, true] {
+            let (api_url, server) = mock_server(body).await;
+            let api = Api::new(None, None, OfflinePolicy::Online)
+                .with_team(Some(team(&api_url)))
+                .with_filters(created_filters(&api_url))
+                .with_unanswered(include_unanswered);
+            let ids = vec![String::from("1"), String::from("2")];
+            let qs = api.questions("work", ids).await.unwrap();
+            let ids: Vec<u32> = qs.iter().map(|q| q.id).collect();
+            if include_unanswered {
+                assert_eq!(ids, vec![1, 2]);
+            } else {
+                assert_eq!(ids, vec![2]);
+            }
+            server.await.unwrap();
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[tokio::test]
+    async fn test_hostile_post_renders_inertly() {
+        use crate::format::{self, Locale};
+        use crate::output::{FormatOpts, Lucky, OutputFormatter, SearchResults};
+        use crate::stackexchange::urls::LinkStyle;
+        use crate::template::Template;
+
+        let body = r#"{"items": [{"question_id": 1, "score": 1,
+            "title": "Exit\u001b]0;pwned\u0007 Vim",
+            "body_markdown": "Stuck\u001bP+q\u001b\\",
+            "answers": [{"answer_id": 10, "score": 5, "is_accepted": true,
+                "body_markdown": "Run\u001b[2J\u001b[H `:q`\u0007 \u009b31mnow\u001b"}]}]}"#;
+        let (api_url, server) = mock_server(body).await;
+        let cache = temp_question_cache("api-hostile");
+        let mut cached = question(2);
+        cached.title = String::from("Cached \u{1b}]2;pwned\u{1b}\\before sanitizing");
+        cache.put("team-acme", &[cached]).unwrap();
+        let api = Api::new(None, None, OfflinePolicy::Online)
+            .with_team(Some(team(&api_url)))
+            .with_filters(created_filters(&api_url))
+            .with_question_cache(Some(cache));
+        let qs = api.questions("work", ids(&[1, 2])).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(qs[0].body.as_deref(), Some("Stuck"));
+        assert_eq!(qs[1].title, "Cached before sanitizing");
+
+        let results = SearchResults {
+            query: String::from("exit vim"),
+            questions: qs,
+            site_urls: HashMap::new(),
+            timings: None,
+        };
+        let opts = FormatOpts {
+            width: Some(40),
+            locale: Locale::En,
+            color: false,
+            lucky_template: Template::parse("{title}\n{body:raw}").unwrap(),
+            lucky_header: false,
+            lucky_attribution: false,
+            score_thresholds: format::DEFAULT_SCORE_THRESHOLDS,
+            link_style: LinkStyle::Full,
+            show_spoilers: false,
+            ascii_only: false,
+        };
+        let mut out = Vec::new();
+        Lucky.format(&results, &opts, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "Exit Vim\nRun `:q` now");
     }
 }
@@ -0,0 +1,221 @@
+//! Backoffs the StackExchange API asks for when it's throttling requests, per site.
+//!
+//! A response carrying `backoff` means no more requests to that site for that many seconds, and
+//! ignoring it gets the IP banned for the day. Requests to the site wait the backoff out, and it's
+//! kept on disk so that the next invocation waits too. As with the DuckDuckGo block log, the state
+//! file is only a hint: if it can't be read it's as good as empty.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::utils;
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+struct State {
+    /// Until when to leave each site alone, in unix milliseconds
+    until: HashMap<String, i64>,
+}
+
+impl State {
+    /// Drop backoffs that are over by `now`
+    fn prune(&mut self, now: i64) {
+        self.until.retain(|_, &mut until| until > now);
+    }
+}
+
+/// Backoffs per site, shared by every clone
+#[derive(Debug, Clone, Default)]
+pub struct Backoff {
+    /// State file, if backoffs outlive the invocation
+    path: Option<PathBuf>,
+    state: Arc<Mutex<State>>,
+    /// What the user is told about backoffs asked for or waited out so far
+    notices: Arc<Mutex<Vec<String>>>,
+}
+
+impl Backoff {
+    /// Backoffs kept in `path`, picking up those still in effect
+    pub fn new(path: PathBuf) -> Self {
+        let mut state: State = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        state.prune(now());
+        Backoff {
+            path: Some(path),
+            state: Arc::new(Mutex::new(state)),
+            notices: Arc::default(),
+        }
+    }
+
+    /// Backoffs kept in the project's cache directory
+    pub fn open() -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("backoff.json")))
+    }
+
+    /// Wait out the backoff on `site`, if there is one
+    pub async fn wait(&self, site: &str) {
+        if let Some(remaining) = self.remaining(site, now()) {
+            self.notify(format!(
+                "Waiting {}s before sending more requests to {}, as StackExchange asked",
+                round_up_secs(remaining),
+                site
+            ));
+            tokio::time::sleep(remaining).await;
+        }
+    }
+
+    /// Record that a response from `site` at `now` asked to back off for `secs` seconds
+    pub fn record(&self, site: &str, secs: u64, now: i64) {
+        self.notify(format!(
+            "StackExchange asked us to back off from {} for {}s",
+            site, secs
+        ));
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let until = now.saturating_add(secs.saturating_mul(1000) as i64);
+        let entry = state.until.entry(site.to_string()).or_insert(until);
+        *entry = (*entry).max(until);
+        state.prune(now);
+        if let Some(path) = &self.path {
+            // Better to not know about it next time than to fail the request over it
+            let _ = save(path, &state);
+        }
+    }
+
+    /// What the user should know about backoffs so far
+    pub fn notices(&self) -> Vec<String> {
+        self.notices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// How much longer `site` is to be left alone at `now`
+    fn remaining(&self, site: &str, now: i64) -> Option<Duration> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let until = *state.until.get(site)?;
+        (until > now).then(|| Duration::from_millis((until - now) as u64))
+    }
+
+    fn notify(&self, notice: String) {
+        let mut notices = self.notices.lock().unwrap_or_else(|e| e.into_inner());
+        if !notices.contains(&notice) {
+            notices.push(notice);
+        }
+    }
+}
+
+fn save(path: &PathBuf, state: &State) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    utils::write_atomic(path, &serde_json::to_vec(state)?)
+}
+
+fn round_up_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// Current unix time in milliseconds
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-16 14:00 UTC, in milliseconds
+    const T: i64 = 1_792_159_200_000;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("so-backoff-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("backoff.json")
+    }
+
+    #[test]
+    fn test_per_site() {
+        let backoff = Backoff::default();
+        assert_eq!(backoff.remaining("stackoverflow", T), None);
+        backoff.record("stackoverflow", 12, T);
+        assert_eq!(
+            backoff.remaining("stackoverflow", T + 2000),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(backoff.remaining("stackoverflow", T + 12_000), None);
+        assert_eq!(backoff.remaining("superuser", T + 2000), None);
+        assert_eq!(
+            backoff.notices(),
+            vec!["StackExchange asked us to back off from stackoverflow for 12s"]
+        );
+    }
+
+    #[test]
+    fn test_longest_wins() {
+        let backoff = Backoff::default();
+        backoff.record("stackoverflow", 30, T);
+        backoff.record("stackoverflow", 5, T + 1000);
+        assert_eq!(
+            backoff.remaining("stackoverflow", T + 1000),
+            Some(Duration::from_secs(29))
+        );
+        // Shared with clones, e.g. concurrent requests
+        let clone = backoff.clone();
+        clone.record("superuser", 1, T);
+        assert!(backoff.remaining("superuser", T).is_some());
+        assert_eq!(backoff.notices().len(), 3);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = temp_path("persistence");
+        let backoff = Backoff::new(path.clone());
+        let now = now();
+        backoff.record("stackoverflow", 60, now);
+        backoff.record("superuser", 0, now);
+        // As in the next invocation
+        let next = Backoff::new(path.clone());
+        assert!(next.remaining("stackoverflow", now).is_some());
+        assert!(next.notices().is_empty());
+        let state = next.state.lock().unwrap();
+        assert_eq!(
+            state.until.keys().collect::<Vec<_>>(),
+            vec!["stackoverflow"]
+        );
+        drop(state);
+        // Garbage is as good as nothing
+        fs::write(&path, b"{\"until\": 5").unwrap();
+        assert_eq!(
+            Backoff::new(path.clone()).remaining("stackoverflow", now),
+            None
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait() {
+        let backoff = Backoff::default();
+        backoff.wait("stackoverflow").await;
+        assert!(backoff.notices().is_empty());
+        backoff.record("stackoverflow", 1, now() - 900);
+        let start = std::time::Instant::now();
+        backoff.wait("stackoverflow").await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(
+            backoff.notices()[1],
+            "Waiting 1s before sending more requests to stackoverflow, as StackExchange asked"
+        );
+    }
+}
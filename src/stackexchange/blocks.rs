@@ -0,0 +1,317 @@
+//! DuckDuckGo blocks in a row, kept on disk across invocations, so that once DuckDuckGo keeps
+//! blocking requests `so` searches via the StackExchange API for a while instead of trying again
+//! and again.
+//!
+//! See `ddg_fallback` in the config for how many blocks it takes, and for how long. The state file
+//! is only a hint: if it can't be read it's as good as empty, and if it can't be written the next
+//! invocation simply doesn't know about this one.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, DdgFallback, SearchEngine};
+use crate::error::Result;
+use crate::utils;
+
+const MINUTE: i64 = 60;
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+struct State {
+    /// When each block since the last successful search happened
+    blocks: Vec<i64>,
+    /// Search via the API until then
+    fallback_until: Option<i64>,
+    /// Whether the user has been told about the fallback
+    notified: bool,
+}
+
+impl State {
+    /// Record a block at `now`, returning whether that starts a fallback
+    fn block(&mut self, now: i64, policy: &DdgFallback) -> bool {
+        let window = policy.window_mins as i64 * MINUTE;
+        // Anything "later" than now is from a clock that has since been set back
+        self.blocks.retain(|&at| at <= now && now - at < window);
+        self.blocks.push(now);
+        if policy.threshold == 0 || self.blocks.len() < policy.threshold as usize {
+            return false;
+        }
+        self.blocks.clear();
+        self.fallback_until = Some(now + policy.cooldown_mins as i64 * MINUTE);
+        self.notified = false;
+        true
+    }
+
+    /// Record a search that went through, which breaks a run of blocks
+    fn success(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Until when to search via the API, if still falling back at `now`
+    fn fallback(&self, now: i64) -> Option<i64> {
+        self.fallback_until.filter(|&until| now < until)
+    }
+}
+
+/// Blocks recorded in a state file
+#[derive(Debug, Clone)]
+pub struct BlockLog {
+    path: PathBuf,
+    policy: DdgFallback,
+}
+
+impl BlockLog {
+    pub fn new(path: PathBuf, policy: DdgFallback) -> Self {
+        BlockLog { path, policy }
+    }
+
+    /// Log in the project's cache directory
+    pub fn open(policy: DdgFallback) -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(
+            project.cache_dir().join("ddg_blocks.json"),
+            policy,
+        ))
+    }
+
+    /// Record whether a DuckDuckGo search at `now` was blocked
+    pub fn record(&self, blocked: bool, now: i64) -> Result<()> {
+        let mut state = self.load();
+        if blocked {
+            state.block(now, &self.policy);
+        } else if state.blocks.is_empty() {
+            // Nothing to write
+            return Ok(());
+        } else {
+            state.success();
+        }
+        self.save(&state)
+    }
+
+    /// Engine to search with at `now`: the API while falling back from DuckDuckGo, unless the
+    /// `configured` engine was given `explicitly` on the command line. Also returns until when
+    /// the fallback lasts if the user hasn't been told about it yet, counting them as told.
+    pub fn engine(
+        &self,
+        configured: &SearchEngine,
+        explicitly: bool,
+        now: i64,
+    ) -> (SearchEngine, Option<i64>) {
+        if explicitly || *configured != SearchEngine::DuckDuckGo {
+            return (configured.clone(), None);
+        }
+        let mut state = self.load();
+        let until = match state.fallback(now) {
+            Some(until) => until,
+            None => return (configured.clone(), None),
+        };
+        if state.notified {
+            return (SearchEngine::StackExchange, None);
+        }
+        state.notified = true;
+        // Better to tell them twice than to fail the search over it
+        let _ = self.save(&state);
+        (SearchEngine::StackExchange, Some(until))
+    }
+
+    /// What the user is told about a fallback lasting `until`
+    pub fn notice(&self, until: i64) -> String {
+        format!(
+            "DuckDuckGo blocked {} requests within {} min, so searching via the StackExchange \
+            API until {}; override with --search-engine duckduckgo",
+            self.policy.threshold,
+            self.policy.window_mins,
+            time_of_day(until)
+        )
+    }
+
+    /// The state on disk, or a fresh one if there's none or it's unreadable
+    fn load(&self) -> State {
+        fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, state: &State) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        utils::write_atomic(&self.path, &serde_json::to_vec(state)?)
+    }
+}
+
+/// Hours and minutes of a unix `timestamp`, in UTC
+fn time_of_day(timestamp: i64) -> String {
+    let minutes = timestamp.rem_euclid(24 * 60 * MINUTE) / MINUTE;
+    format!("{:02}:{:02} UTC", minutes / 60, minutes % 60)
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-16 14:00 UTC
+    const T: i64 = 1_792_159_200;
+
+    fn policy() -> DdgFallback {
+        DdgFallback {
+            threshold: 3,
+            window_mins: 10,
+            cooldown_mins: 30,
+        }
+    }
+
+    fn temp_log(name: &str) -> BlockLog {
+        let dir = std::env::temp_dir().join(format!("so-blocks-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        BlockLog::new(dir.join("ddg_blocks.json"), policy())
+    }
+
+    #[test]
+    fn test_threshold_within_window() {
+        let mut state = State::default();
+        assert!(!state.block(T, &policy()));
+        assert!(!state.block(T + 4 * MINUTE, &policy()));
+        assert!(state.block(T + 9 * MINUTE, &policy()));
+        assert_eq!(state.fallback(T + 9 * MINUTE), Some(T + 39 * MINUTE));
+        assert_eq!(state.fallback(T + 38 * MINUTE), Some(T + 39 * MINUTE));
+        assert_eq!(state.fallback(T + 39 * MINUTE), None);
+        // Counting starts over
+        assert!(state.blocks.is_empty());
+    }
+
+    #[test]
+    fn test_blocks_outside_window() {
+        let mut state = State::default();
+        state.block(T, &policy());
+        state.block(T + 5 * MINUTE, &policy());
+        // The first block is too old by now
+        assert!(!state.block(T + 10 * MINUTE, &policy()));
+        assert_eq!(state.blocks, vec![T + 5 * MINUTE, T + 10 * MINUTE]);
+        assert!(state.block(T + 11 * MINUTE, &policy()));
+    }
+
+    #[test]
+    fn test_success_breaks_run() {
+        let mut state = State::default();
+        state.block(T, &policy());
+        state.block(T + MINUTE, &policy());
+        state.success();
+        assert!(!state.block(T + 2 * MINUTE, &policy()));
+        assert_eq!(state.fallback(T + 2 * MINUTE), None);
+    }
+
+    #[test]
+    fn test_clock_set_back() {
+        let mut state = State::default();
+        state.block(T + 60 * MINUTE, &policy());
+        state.block(T + 61 * MINUTE, &policy());
+        assert!(!state.block(T, &policy()));
+        assert_eq!(state.blocks, vec![T]);
+    }
+
+    #[test]
+    fn test_never_falls_back() {
+        let never = DdgFallback {
+            threshold: 0,
+            ..policy()
+        };
+        let mut state = State::default();
+        for i in 0..10 {
+            assert!(!state.block(T + i, &never));
+        }
+        assert_eq!(state.fallback(T + 10), None);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let log = temp_log("persistence");
+        let ddg = SearchEngine::DuckDuckGo;
+        log.record(true, T).unwrap();
+        log.record(true, T + MINUTE).unwrap();
+        // A fresh handle, as in the next invocation
+        let log = BlockLog::new(log.path.clone(), policy());
+        assert_eq!(log.engine(&ddg, false, T + MINUTE), (ddg.clone(), None));
+        log.record(true, T + 2 * MINUTE).unwrap();
+
+        let until = T + 32 * MINUTE;
+        assert_eq!(
+            log.engine(&ddg, false, T + 3 * MINUTE),
+            (SearchEngine::StackExchange, Some(until))
+        );
+        // Only told once
+        assert_eq!(
+            log.engine(&ddg, false, T + 4 * MINUTE),
+            (SearchEngine::StackExchange, None)
+        );
+        assert_eq!(log.engine(&ddg, false, until), (ddg, None));
+        fs::remove_dir_all(log.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_explicit_engine_wins() {
+        let log = temp_log("explicit");
+        for i in 0..3 {
+            log.record(true, T + i).unwrap();
+        }
+        let ddg = SearchEngine::DuckDuckGo;
+        assert_eq!(log.engine(&ddg, true, T + 5), (ddg.clone(), None));
+        // Other engines weren't blocked in the first place
+        assert_eq!(
+            log.engine(&SearchEngine::Google, false, T + 5),
+            (SearchEngine::Google, None)
+        );
+        // Neither of which counts as telling the user
+        assert_eq!(
+            log.engine(&ddg, false, T + 5),
+            (SearchEngine::StackExchange, Some(T + 2 + 30 * MINUTE))
+        );
+        fs::remove_dir_all(log.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_state() {
+        let log = temp_log("corrupt");
+        fs::create_dir_all(log.path.parent().unwrap()).unwrap();
+        let ddg = SearchEngine::DuckDuckGo;
+        for garbage in [
+            &b"{\"blocks\": [1, 2"[..],
+            b"\x00\xff",
+            b"[]",
+            b"{\"blocks\": \"x\"}",
+        ] {
+            fs::write(&log.path, garbage).unwrap();
+            assert_eq!(log.engine(&ddg, false, T), (ddg.clone(), None));
+        }
+        // And overwritten by the next record
+        log.record(true, T).unwrap();
+        assert_eq!(log.load().blocks, vec![T]);
+        // Fields missing from older or hand edited files default
+        fs::write(&log.path, format!("{{\"fallback_until\": {}}}", T + 60)).unwrap();
+        assert_eq!(
+            log.engine(&ddg, false, T),
+            (SearchEngine::StackExchange, Some(T + 60))
+        );
+        fs::remove_dir_all(log.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_notice() {
+        let log = temp_log("notice");
+        assert_eq!(
+            log.notice(T + 32 * MINUTE),
+            "DuckDuckGo blocked 3 requests within 10 min, so searching via the StackExchange API \
+            until 14:32 UTC; override with --search-engine duckduckgo"
+        );
+    }
+}
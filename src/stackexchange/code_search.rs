@@ -0,0 +1,191 @@
+//! Post-filtering of results down to questions whose answers contain the query verbatim in a
+//! code block, for when the query is an exact error message or identifier.
+
+use std::ops::AddAssign;
+
+use super::api::CodeMatch;
+use super::filtered::{Filtered, Reason};
+use super::snippets;
+
+/// How many questions survived the code search filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub questions: usize,
+    pub matched: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.questions += other.questions;
+        self.matched += other.matched;
+    }
+}
+
+/// Strings to look for in code blocks: the quoted parts of `query` if it has any, otherwise the
+/// whole query
+pub fn needles(query: &str) -> Vec<String> {
+    let quoted: Vec<String> = query
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+    if quoted.is_empty() {
+        vec![query.trim().to_string()]
+    } else {
+        quoted
+    }
+}
+
+/// Wrap `query` in quotes to bias the SE search towards exact matches, unless the user already
+/// quoted what they're after
+pub fn quote(query: &str) -> String {
+    if query.contains('"') {
+        query.to_string()
+    } else {
+        format!("\"{}\"", query)
+    }
+}
+
+/// Lines of the code blocks in markdown `body` containing any of `needles`
+pub fn find_matches(body: &str, needles: &[String]) -> Vec<CodeMatch> {
+    snippets::code_blocks(body)
+        .iter()
+        .enumerate()
+        .flat_map(|(block, code)| {
+            code.lines()
+                .enumerate()
+                .filter(|(_, line)| needles.iter().any(|n| line.contains(n.as_str())))
+                .map(move |(line, _)| CodeMatch { block, line })
+        })
+        .collect()
+}
+
+/// Record code matches on every answer of the shown questions and hide those without any
+pub fn filter(mut qs: Filtered<String>, needles: &[String]) -> (Filtered<String>, Stats) {
+    let questions = qs.shown.len();
+    for q in qs.shown.iter_mut() {
+        for a in q.answers.iter_mut() {
+            a.code_matches = find_matches(&a.body, needles);
+        }
+    }
+    let qs = qs.filter(Reason::NoCodeMatch, |q| {
+        q.answers.iter().any(|a| !a.code_matches.is_empty())
+    });
+    let stats = Stats {
+        questions,
+        matched: qs.shown.len(),
+    };
+    (qs, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::{Answer, Question};
+
+    const MULTI_BLOCK: &str = "First try\n\n\
+        ```\n\
+        $ cargo build\n\
+        error[E0382]: borrow of moved value: `v`\n\
+        ```\n\n\
+        then\n\n\
+        ```rust\n\
+        let w = v.clone();\n\
+        println!(\"{:?}\", v);\n\
+        ```\n\n\
+        Prose mentioning borrow of moved value doesn't count.";
+
+    fn answer(id: u32, body: &str) -> Answer<String> {
+        Answer {
+            id,
+            score: 1,
+            body: body.to_string(),
+            ..Answer::default()
+        }
+    }
+
+    fn question(id: u32, answers: Vec<Answer<String>>) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            answers,
+            title: format!("Question {}", id),
+            body: Some(String::new()),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    #[test]
+    fn test_needles() {
+        assert_eq!(
+            needles(" borrow of moved value "),
+            vec!["borrow of moved value"]
+        );
+        assert_eq!(
+            needles(r#"rust "borrow of moved value" "E0382""#),
+            vec!["borrow of moved value", "E0382"]
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("E0382"), "\"E0382\"");
+        assert_eq!(quote(r#"rust "E0382""#), r#"rust "E0382""#);
+    }
+
+    #[test]
+    fn test_find_matches_multi_block() {
+        let needles = vec![String::from("borrow of moved value")];
+        assert_eq!(
+            find_matches(MULTI_BLOCK, &needles),
+            vec![CodeMatch { block: 0, line: 1 }]
+        );
+        let needles = vec![String::from("v.clone()"), String::from("cargo")];
+        assert_eq!(
+            find_matches(MULTI_BLOCK, &needles),
+            vec![
+                CodeMatch { block: 0, line: 0 },
+                CodeMatch { block: 1, line: 0 }
+            ]
+        );
+        assert!(find_matches(MULTI_BLOCK, &[String::from("First try")]).is_empty());
+    }
+
+    #[test]
+    fn test_filter() {
+        let qs = vec![
+            question(1, vec![answer(10, "no code here, just E0382")]),
+            question(2, vec![answer(20, "nope"), answer(21, MULTI_BLOCK)]),
+            question(3, vec![]),
+        ];
+        let (filtered, stats) = filter(Filtered::new(qs), &[String::from("E0382")]);
+        assert_eq!(
+            stats,
+            Stats {
+                questions: 3,
+                matched: 1
+            }
+        );
+        let hidden: Vec<_> = filtered
+            .hidden
+            .iter()
+            .map(|h| (h.question.id, h.reason))
+            .collect();
+        assert_eq!(
+            hidden,
+            vec![(1, Reason::NoCodeMatch), (3, Reason::NoCodeMatch)]
+        );
+        let qs = filtered.shown;
+        assert_eq!(qs.len(), 1);
+        assert_eq!(qs[0].id, 2);
+        assert!(qs[0].answers[0].code_matches.is_empty());
+        assert_eq!(
+            qs[0].answers[1].code_matches,
+            vec![CodeMatch { block: 0, line: 1 }]
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Extraction of shell command lines from code blocks, for `--commands`.
+//!
+//! Blocks with prompts (`$ `, `% `, `user@host:~$ `, `PS C:\> ` and the like) give the lines
+//! after a prompt, with the prompt stripped; anything else in them is taken as output and skipped.
+//! `# ` counts as a root prompt in such blocks, or when every line of the block starts with it, and
+//! as a comment otherwise. Blocks without prompts only count if every line looks like an
+//! invocation, so that source code isn't mistaken for commands.
+//!
+//! Lines continued with a trailing backslash, or ending in a pipe, `&&` or `||`, are joined into
+//! one command, and heredocs are kept whole, body and terminator included.
+
+use std::str::Lines;
+
+/// First words of source code lines that are unlikely to start a shell command
+const CODE_KEYWORDS: &[&str] = &[
+    "import",
+    "from",
+    "def",
+    "class",
+    "fn",
+    "let",
+    "const",
+    "var",
+    "function",
+    "return",
+    "pub",
+    "use",
+    "mod",
+    "impl",
+    "struct",
+    "package",
+    "public",
+    "private",
+    "protected",
+    "static",
+    "int",
+    "void",
+    "try",
+    "catch",
+    "else",
+    "elif",
+    "end",
+    "SELECT",
+    "INSERT",
+    "UPDATE",
+    "DELETE",
+];
+
+/// Command lines in `blocks`, in order of appearance
+pub fn commands(blocks: &[String]) -> Vec<String> {
+    blocks.iter().flat_map(|b| block_commands(b)).collect()
+}
+
+/// Which commands to take from a list of `count` for a selection such as `1,3-4` (1-based);
+/// blank picks all of them. Fails on anything out of range or not a number.
+pub fn parse_picks(input: &str, count: usize) -> Option<Vec<usize>> {
+    if input.trim().is_empty() {
+        return Some((0..count).collect());
+    }
+    let mut picks = Vec::new();
+    for part in input.split(|c: char| c == ',' || c.is_whitespace()) {
+        if part.is_empty() {
+            continue;
+        }
+        let (from, to) = match part.split_once('-') {
+            Some((from, to)) => (from.parse::<usize>().ok()?, to.parse::<usize>().ok()?),
+            None => {
+                let n = part.parse::<usize>().ok()?;
+                (n, n)
+            }
+        };
+        if from == 0 || from > to || to > count {
+            return None;
+        }
+        for n in from - 1..to {
+            if !picks.contains(&n) {
+                picks.push(n);
+            }
+        }
+    }
+    Some(picks)
+}
+
+/// A prompt at the start of a line
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Prompt {
+    /// `# `, which may as well start a comment
+    Hash,
+    Other,
+}
+
+fn block_commands(block: &str) -> Vec<String> {
+    let prompted = block
+        .lines()
+        .any(|l| matches!(strip_prompt(l), Some((Prompt::Other, _))));
+    let all_hash = block.lines().any(|l| !l.trim().is_empty())
+        && block
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .all(|l| matches!(strip_prompt(l), Some((Prompt::Hash, _))));
+    let mut commands = Vec::new();
+    let mut lines = block.lines();
+    while let Some(line) = lines.next() {
+        let first = match strip_prompt(line) {
+            Some((Prompt::Hash, cmd)) if prompted || all_hash => cmd,
+            Some((Prompt::Other, cmd)) => cmd,
+            // Output, when there are prompts
+            _ if prompted || all_hash => continue,
+            _ if line.trim().is_empty() || line.trim_start().starts_with('#') => continue,
+            _ if looks_like_invocation(line) => line.trim(),
+            // Source code, not commands
+            _ => return Vec::new(),
+        };
+        let command = continued(first.trim_end(), &mut lines, prompted);
+        if !command.is_empty() {
+            commands.push(command);
+        }
+    }
+    commands
+}
+
+/// `first` with its continuation lines and heredoc bodies taken from `lines`. In prompted blocks,
+/// continuation lines may carry a `> ` secondary prompt.
+fn continued(first: &str, lines: &mut Lines, prompted: bool) -> String {
+    let mut command = first.to_string();
+    loop {
+        if let Some(terminator) = heredoc_terminator(&command) {
+            for line in lines.by_ref() {
+                let line = if prompted {
+                    line.strip_prefix("> ").unwrap_or(line)
+                } else {
+                    line
+                };
+                command.push('\n');
+                command.push_str(line);
+                if line.trim() == terminator {
+                    break;
+                }
+            }
+            return command;
+        }
+        let joiner = if let Some(stripped) = command.strip_suffix('\\') {
+            command = stripped.trim_end().to_string();
+            " "
+        } else if ["|", "&&", "||"].iter().any(|op| command.ends_with(op)) {
+            " "
+        } else {
+            return command;
+        };
+        let next = match lines.next() {
+            Some(next) => next,
+            None => return command,
+        };
+        let next = if prompted {
+            next.trim_start().strip_prefix("> ").unwrap_or(next)
+        } else {
+            next
+        };
+        command.push_str(joiner);
+        command.push_str(next.trim());
+    }
+}
+
+/// Terminator of a heredoc started in `command`, e.g. `EOF` for `cat <<'EOF' > file`
+fn heredoc_terminator(command: &str) -> Option<String> {
+    let start = command.find("<<")?;
+    let rest = &command[start + 2..];
+    // Here strings (`<<<`) have no body
+    if rest.starts_with('<') {
+        return None;
+    }
+    let rest = rest.strip_prefix('-').unwrap_or(rest).trim_start();
+    let word = rest.split_whitespace().next()?;
+    let word = word.trim_matches(['\'', '"']);
+    Some(word.to_string()).filter(|w| !w.is_empty())
+}
+
+/// The command after the prompt `line` starts with, if it starts with one
+fn strip_prompt(line: &str) -> Option<(Prompt, &str)> {
+    let line = line.trim_start();
+    if let Some(cmd) = line.strip_prefix("$ ").or_else(|| line.strip_prefix("% ")) {
+        return Some((Prompt::Other, cmd));
+    }
+    if let Some(cmd) = line.strip_prefix("# ") {
+        return Some((Prompt::Hash, cmd));
+    }
+    // PowerShell, e.g. `PS C:\Users\me> ` or `PS> `
+    if let Some(rest) = line.strip_prefix("PS") {
+        if rest.starts_with(' ') || rest.starts_with('>') {
+            let end = rest.find("> ")?;
+            return Some((Prompt::Other, &rest[end + 2..]));
+        }
+    }
+    // cmd.exe, e.g. `C:\> `
+    let mut chars = line.chars();
+    if let (Some(drive), Some(':'), Some('\\')) = (chars.next(), chars.next(), chars.next()) {
+        if drive.is_ascii_alphabetic() {
+            let end = line.find("> ")?;
+            return Some((Prompt::Other, &line[end + 2..]));
+        }
+    }
+    // `user@host:~/dir$ `, `[user@host dir]# ` and the like
+    let end = line.find(['$', '#'])?;
+    let (prompt, rest) = (&line[..end], &line[end + 1..]);
+    let cmd = rest.strip_prefix(' ')?;
+    let bracketed = prompt.starts_with('[') && prompt.ends_with(']') && prompt.contains('@');
+    let user_host = prompt
+        .split_once('@')
+        .map(|(user, host)| {
+            !user.is_empty()
+                && user
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+                && !host.is_empty()
+                && !host.contains(char::is_whitespace)
+        })
+        .unwrap_or(false);
+    if bracketed || user_host {
+        Some((Prompt::Other, cmd))
+    } else {
+        None
+    }
+}
+
+/// Whether an unprompted `line` reads like running a program, rather than like source code
+fn looks_like_invocation(line: &str) -> bool {
+    let line = line.trim();
+    if line.ends_with(['{', '}', ';', ':', ',', '(']) {
+        return false;
+    }
+    // Leading variable assignments, e.g. `RUST_LOG=debug cargo run`
+    let mut words = line
+        .split_whitespace()
+        .skip_while(|w| w.split_once('=').is_some_and(|(name, _)| is_variable(name)));
+    let program = match words.next() {
+        Some(program) => program,
+        // Nothing but assignments, e.g. `export`-less `PATH=...`
+        None => return true,
+    };
+    if CODE_KEYWORDS.contains(&program) || words.next() == Some("=") {
+        return false;
+    }
+    program
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '/' | '~' | '_'))
+        && program
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '/' | '~' | '_' | '-' | '+'))
+}
+
+fn is_variable(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(block: &str) -> Vec<String> {
+        commands(&[block.to_string()])
+    }
+
+    #[test]
+    fn test_prompts() {
+        let block = "$ git status\nOn branch master\nnothing to commit\n\
+                     $ git log --oneline -1\nd996cd2 Track questions\n";
+        assert_eq!(extract(block), vec!["git status", "git log --oneline -1"]);
+        assert_eq!(
+            extract("user@host:~/src$ make\nmake: Nothing to be done.\n[root@box etc]# ls -l\n"),
+            vec!["make", "ls -l"]
+        );
+        assert_eq!(extract("% brew install so\n"), vec!["brew install so"]);
+        // Root prompts next to other prompts, or on their own
+        assert_eq!(
+            extract("$ sudo -i\n# apt update\nHit:1 http://deb.debian.org\n"),
+            vec!["sudo -i", "apt update"]
+        );
+        assert_eq!(
+            extract("# modprobe fuse\n# lsmod\n"),
+            vec!["modprobe fuse", "lsmod"]
+        );
+    }
+
+    #[test]
+    fn test_powershell_prompts() {
+        let block = "PS C:\\Users\\me> Get-ChildItem -Recurse\n\n    Directory: C:\\Users\\me\n\
+                     PS> Set-ExecutionPolicy RemoteSigned\nC:\\> dir /s\n";
+        assert_eq!(
+            extract(block),
+            vec![
+                "Get-ChildItem -Recurse",
+                "Set-ExecutionPolicy RemoteSigned",
+                "dir /s"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unprompted() {
+        let block = "# install the toolchain first\nrustup update\n\
+                     RUST_LOG=debug cargo run -- --lucky\n./configure --prefix=/usr\n";
+        assert_eq!(
+            extract(block),
+            vec![
+                "rustup update",
+                "RUST_LOG=debug cargo run -- --lucky",
+                "./configure --prefix=/usr"
+            ]
+        );
+        // Source code isn't commands
+        assert!(extract("import os\nos.getcwd()\n").is_empty());
+        assert!(extract("fn main() {\n    println!(\"hi\");\n}\n").is_empty());
+        assert!(extract("x = 5\n").is_empty());
+    }
+
+    #[test]
+    fn test_continuations() {
+        let block = "$ docker run \\\n>   --rm \\\n    -it ubuntu\nroot@abc:/#\n";
+        assert_eq!(extract(block), vec!["docker run --rm -it ubuntu"]);
+        let block = "find . -name '*.log' |\n  xargs rm &&\n  echo done\n";
+        assert_eq!(
+            extract(block),
+            vec!["find . -name '*.log' | xargs rm && echo done"]
+        );
+        // A pipeline split before the pipe is two lines of output, after a prompt
+        assert_eq!(extract("$ ps aux\n| grep so\n"), vec!["ps aux"]);
+    }
+
+    #[test]
+    fn test_heredocs() {
+        let block = "$ cat <<'EOF' > hello.sh\n> #!/bin/sh\n> echo hi\n> EOF\n$ sh hello.sh\nhi\n";
+        assert_eq!(
+            extract(block),
+            vec![
+                "cat <<'EOF' > hello.sh\n#!/bin/sh\necho hi\nEOF",
+                "sh hello.sh"
+            ]
+        );
+        let block = "cat <<-END\n\tindented\n\tEND\nls\n";
+        assert_eq!(extract(block), vec!["cat <<-END\n\tindented\n\tEND", "ls"]);
+        // Here strings have no body
+        assert_eq!(
+            extract("$ grep so <<< \"so much\"\nso much\n"),
+            vec!["grep so <<< \"so much\""]
+        );
+    }
+
+    #[test]
+    fn test_across_blocks() {
+        let blocks = vec![
+            "$ cargo install so\n".to_string(),
+            "fn main() {}\n".to_string(),
+            "so --lucky how do I exit vim\n".to_string(),
+        ];
+        assert_eq!(
+            commands(&blocks),
+            vec!["cargo install so", "so --lucky how do I exit vim"]
+        );
+    }
+
+    #[test]
+    fn test_parse_picks() {
+        assert_eq!(parse_picks("", 3), Some(vec![0, 1, 2]));
+        assert_eq!(parse_picks("2", 3), Some(vec![1]));
+        assert_eq!(parse_picks("3, 1-2", 3), Some(vec![2, 0, 1]));
+        assert_eq!(parse_picks("1 1,1-2", 3), Some(vec![0, 1]));
+        assert_eq!(parse_picks("0", 3), None);
+        assert_eq!(parse_picks("4", 3), None);
+        assert_eq!(parse_picks("2-1", 3), None);
+        assert_eq!(parse_picks("all", 3), None);
+    }
+}
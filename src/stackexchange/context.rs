@@ -0,0 +1,207 @@
+//! Guesses what a search is about from the project it's run in, with `context_detection`.
+//!
+//! Searching from within a Rust project almost always means Rust questions, so the current
+//! directory and its parents are checked for well known manifest files, up to the repository
+//! root or `MAX_DEPTH` directories up. The tag of the nearest manifest is appended to scraped
+//! search engine queries, or passed as `tagged` to the API.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Manifest file names and the tag each one hints at. Where a directory has several, the first
+/// one listed wins, so more specific manifests come before those they're usually found with,
+/// e.g. `tsconfig.json` before `package.json`.
+pub const MANIFESTS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("go.mod", "go"),
+    ("tsconfig.json", "typescript"),
+    ("package.json", "javascript"),
+    ("pyproject.toml", "python"),
+    ("setup.py", "python"),
+    ("Pipfile", "python"),
+    ("requirements.txt", "python"),
+    ("Gemfile", "ruby"),
+    ("build.gradle.kts", "kotlin"),
+    ("pom.xml", "java"),
+    ("build.gradle", "java"),
+    ("composer.json", "php"),
+    ("mix.exs", "elixir"),
+    ("Package.swift", "swift"),
+    ("pubspec.yaml", "dart"),
+    ("stack.yaml", "haskell"),
+    ("CMakeLists.txt", "c++"),
+];
+
+/// How many directories to look in, starting with the current one, when there's no repository
+/// root to stop at
+pub const MAX_DEPTH: usize = 8;
+
+/// The tag a project hints at, and the manifest it was derived from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context {
+    pub tag: &'static str,
+    pub manifest: PathBuf,
+}
+
+/// Tag hinted at by a directory with these `files`, if any of them is a manifest
+pub fn tag<S: AsRef<str>>(files: &[S]) -> Option<(&'static str, &'static str)> {
+    MANIFESTS
+        .iter()
+        .find(|(manifest, _)| files.iter().any(|f| f.as_ref() == *manifest))
+        .copied()
+}
+
+/// `query` with `tag` appended, or `None` if the query already mentions it
+pub fn augment(query: &str, tag: &str) -> Option<String> {
+    let mentioned = query
+        .split(|c: char| c.is_whitespace() || c == '[' || c == ']' || c == '"')
+        .any(|word| word.eq_ignore_ascii_case(tag));
+    if mentioned {
+        None
+    } else {
+        Some(format!("{} {}", query.trim_end(), tag))
+    }
+}
+
+/// Context of the nearest manifest in `dir` or its parents, looking no further than the
+/// repository root (the directory with `.git`) or `MAX_DEPTH` directories
+pub fn detect(dir: &Path) -> Option<Context> {
+    for dir in dir.ancestors().take(MAX_DEPTH) {
+        let files = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+            Err(_) => return None,
+        };
+        if let Some((manifest, tag)) = tag(&files) {
+            return Some(Context {
+                tag,
+                manifest: dir.join(manifest),
+            });
+        }
+        if files.iter().any(|f| f == ".git") {
+            return None;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh directory under the temp dir, named after the test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("so-context-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, file: &str) {
+        fs::write(dir.join(file), "").unwrap();
+    }
+
+    #[test]
+    fn test_tag() {
+        assert_eq!(
+            tag(&["README.md", "Cargo.toml"]),
+            Some(("Cargo.toml", "rust"))
+        );
+        assert_eq!(tag(&["go.mod"]), Some(("go.mod", "go")));
+        assert_eq!(tag(&["README.md", "Makefile"]), None);
+        assert_eq!(tag::<&str>(&[]), None);
+        // Case matters, as it does to the tools reading them
+        assert_eq!(tag(&["cargo.toml"]), None);
+    }
+
+    #[test]
+    fn test_tag_precedence() {
+        assert_eq!(
+            tag(&["package.json", "tsconfig.json"]),
+            Some(("tsconfig.json", "typescript"))
+        );
+        assert_eq!(
+            tag(&["package.json", "Cargo.toml"]),
+            Some(("Cargo.toml", "rust"))
+        );
+        assert_eq!(
+            tag(&["build.gradle", "build.gradle.kts"]),
+            Some(("build.gradle.kts", "kotlin"))
+        );
+        assert_eq!(
+            tag(&["requirements.txt", "package.json"]),
+            Some(("package.json", "javascript"))
+        );
+    }
+
+    #[test]
+    fn test_augment() {
+        assert_eq!(
+            augment("how to sort a vec", "rust"),
+            Some(String::from("how to sort a vec rust"))
+        );
+        assert_eq!(
+            augment("how to sort a vec  ", "rust"),
+            Some(String::from("how to sort a vec rust"))
+        );
+        assert_eq!(augment("sort a Vec in Rust", "rust"), None);
+        assert_eq!(augment("[rust] sort a vec", "rust"), None);
+        assert_eq!(augment("\"exit vim\" rust", "rust"), None);
+        // Only whole words count
+        assert_eq!(
+            augment("rusty hinges", "rust"),
+            Some(String::from("rusty hinges rust"))
+        );
+        assert_eq!(augment("std::sort in C++", "c++"), None);
+    }
+
+    #[test]
+    fn test_detect_nearest() {
+        let root = scratch("nearest");
+        touch(&root, "package.json");
+        let crate_dir = root.join("native");
+        let src = crate_dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        touch(&crate_dir, "Cargo.toml");
+
+        let context = detect(&src).unwrap();
+        assert_eq!(context.tag, "rust");
+        assert_eq!(context.manifest, crate_dir.join("Cargo.toml"));
+        assert_eq!(detect(&root).unwrap().tag, "javascript");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_detect_stops_at_repo_root() {
+        let outer = scratch("repo-root");
+        touch(&outer, "Cargo.toml");
+        let repo = outer.join("repo");
+        let docs = repo.join("docs");
+        fs::create_dir_all(&docs).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+        assert_eq!(detect(&docs), None);
+
+        // A manifest at the root itself still counts
+        touch(&repo, "go.mod");
+        assert_eq!(detect(&docs).unwrap().manifest, repo.join("go.mod"));
+        fs::remove_dir_all(&outer).unwrap();
+    }
+
+    #[test]
+    fn test_detect_depth_cap() {
+        let root = scratch("depth");
+        touch(&root, "Gemfile");
+        let mut deep = root.clone();
+        for i in 1..MAX_DEPTH {
+            deep.push(i.to_string());
+        }
+        fs::create_dir_all(&deep).unwrap();
+        assert_eq!(detect(&deep).unwrap().tag, "ruby");
+        let deeper = deep.join("too-far");
+        fs::create_dir(&deeper).unwrap();
+        assert_eq!(detect(&deeper), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
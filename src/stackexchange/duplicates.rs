@@ -0,0 +1,127 @@
+//! Detection of the same answer posted on several of the questions in one result set.
+//!
+//! Some users paste identical answers across near-duplicate questions; rather than
+//! showing the same wall of text several times, repeats are collapsed into a stub
+//! pointing at the copy on the highest ranked question.
+
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use super::api::{DuplicateOf, Question};
+
+/// Fingerprint an answer body so that copies differing only in whitespace or letter case
+/// collide
+pub fn fingerprint(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in body.split_whitespace() {
+        word.to_lowercase().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Collapse answers that also appear on a higher ranked question (i.e. earlier in `qs`).
+///
+/// The first occurrence keeps its body; later occurrences on *other* questions get
+/// `duplicate_of` set and their body replaced by a short stub. Identical answers within a single
+/// question are left alone.
+pub fn collapse(mut qs: Vec<Question<String>>) -> Vec<Question<String>> {
+    let mut seen: HashMap<u64, DuplicateOf> = HashMap::new();
+    for q in qs.iter_mut() {
+        for a in q.answers.iter_mut() {
+            match seen.entry(fingerprint(&a.body)) {
+                Entry::Vacant(v) => {
+                    v.insert(DuplicateOf {
+                        question_id: q.id,
+                        question_title: q.title.clone(),
+                        answer_id: a.id,
+                    });
+                }
+                Entry::Occupied(o) if o.get().question_id != q.id => {
+                    let original = o.get().clone();
+                    a.body = stub(&original);
+                    a.duplicate_of = Some(original);
+                }
+                Entry::Occupied(_) => (),
+            }
+        }
+    }
+    qs
+}
+
+/// Markdown shown in place of a collapsed answer
+fn stub(original: &DuplicateOf) -> String {
+    format!(
+        "*Same as the answer on* **{}**\n\nPress **[J]** to jump to it.",
+        original.question_title
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::Answer;
+
+    fn answer(id: u32, body: &str) -> Answer<String> {
+        Answer {
+            id,
+            score: 1,
+            body: body.to_string(),
+            ..Answer::default()
+        }
+    }
+
+    fn question(id: u32, answers: Vec<Answer<String>>) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            answers,
+            title: format!("Question {}", id),
+            body: Some(String::new()),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_normalizes_whitespace() {
+        assert_eq!(
+            fingerprint("Use `:wq`\r\n\r\nto   save"),
+            fingerprint("  use `:wq`\n\nto save\n")
+        );
+        assert_ne!(fingerprint("Use `:wq`"), fingerprint("Use `:q!`"));
+    }
+
+    #[test]
+    fn test_collapse_keeps_highest_ranked() {
+        let qs = vec![
+            question(1, vec![answer(10, "the same answer"), answer(11, "unique")]),
+            question(
+                2,
+                vec![answer(20, "The  same\nanswer"), answer(21, "other")],
+            ),
+            question(3, vec![answer(30, "the same answer")]),
+        ];
+        let qs = collapse(qs);
+
+        assert_eq!(qs[0].answers[0].body, "the same answer");
+        assert!(qs[0].answers[0].duplicate_of.is_none());
+        assert!(qs[0].answers[1].duplicate_of.is_none());
+
+        for a in &[&qs[1].answers[0], &qs[2].answers[0]] {
+            let dup = a.duplicate_of.as_ref().unwrap();
+            assert_eq!(dup.question_id, 1);
+            assert_eq!(dup.answer_id, 10);
+            assert!(a.body.contains("Question 1"));
+        }
+        assert!(qs[1].answers[1].duplicate_of.is_none());
+    }
+
+    #[test]
+    fn test_collapse_ignores_same_question() {
+        let qs = vec![question(1, vec![answer(10, "dup"), answer(11, "dup")])];
+        let qs = collapse(qs);
+        assert!(qs[0].answers.iter().all(|a| a.duplicate_of.is_none()));
+        assert!(qs[0].answers.iter().all(|a| a.body == "dup"));
+    }
+}
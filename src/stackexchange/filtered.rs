@@ -0,0 +1,190 @@
+//! Results split into the questions to show and those filters left out, along with why, so that
+//! the ones left out can still be counted and brought back.
+
+use std::fmt;
+
+use super::api::Question;
+
+/// Why a filter left a question out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// Nobody answered it, and unanswered questions aren't included
+    Unanswered,
+    /// None of its answers have the query in a code block, in `--code-search` mode
+    NoCodeMatch,
+    /// The TUI listed it earlier in the session, and is hiding questions it listed before
+    ListedBefore,
+    /// A transformer registered by a library user left it out, for the reason given
+    Other(&'static str),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Unanswered => write!(f, "no answers"),
+            Reason::NoCodeMatch => write!(f, "no code matching the query"),
+            Reason::ListedBefore => write!(f, "listed earlier"),
+            Reason::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// A question a filter left out
+#[derive(Debug, Clone)]
+pub struct Hidden<S> {
+    pub question: Question<S>,
+    pub reason: Reason,
+}
+
+/// Questions that made it through every filter, in order, and those that didn't, in the order
+/// they were left out
+#[derive(Debug, Clone)]
+pub struct Filtered<S> {
+    pub shown: Vec<Question<S>>,
+    pub hidden: Vec<Hidden<S>>,
+}
+
+impl<S> Filtered<S> {
+    /// Results nothing has filtered yet
+    pub fn new(qs: Vec<Question<S>>) -> Self {
+        Filtered {
+            shown: qs,
+            hidden: vec![],
+        }
+    }
+
+    /// Hide the shown questions that `keep` rejects, for `reason`
+    pub fn filter<F>(self, reason: Reason, mut keep: F) -> Self
+    where
+        F: FnMut(&Question<S>) -> bool,
+    {
+        let mut hidden = self.hidden;
+        let mut shown = Vec::with_capacity(self.shown.len());
+        for q in self.shown {
+            if keep(&q) {
+                shown.push(q);
+            } else {
+                hidden.push(Hidden {
+                    question: q,
+                    reason,
+                });
+            }
+        }
+        Filtered { shown, hidden }
+    }
+
+    /// Add `more` results after these
+    pub fn extend(&mut self, more: Filtered<S>) {
+        self.shown.extend(more.shown);
+        self.hidden.extend(more.hidden);
+    }
+
+    /// Convert every question, shown or hidden, with `convert`, which has to return as many
+    /// questions as it's given, in the same order
+    pub fn convert<T, F>(self, convert: F) -> Filtered<T>
+    where
+        F: FnOnce(Vec<Question<S>>) -> Vec<Question<T>>,
+    {
+        let shown = self.shown.len();
+        let reasons: Vec<Reason> = self.hidden.iter().map(|h| h.reason).collect();
+        let mut qs = self.shown;
+        qs.extend(self.hidden.into_iter().map(|h| h.question));
+        let mut converted = convert(qs);
+        let hidden = converted.split_off(shown);
+        assert_eq!(hidden.len(), reasons.len(), "conversion lost questions");
+        Filtered {
+            shown: converted,
+            hidden: hidden
+                .into_iter()
+                .zip(reasons)
+                .map(|(question, reason)| Hidden { question, reason })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::testing;
+
+    fn question(id: u32, answers: usize) -> Question<String> {
+        let answers = (0..answers)
+            .map(|i| testing::answer(id * 10 + i as u32, 1, false, String::new()))
+            .collect();
+        testing::question(
+            id,
+            id as i32,
+            &format!("Question {}", id),
+            String::new(),
+            answers,
+        )
+    }
+
+    fn ids<S>(qs: &[Question<S>]) -> Vec<u32> {
+        qs.iter().map(|q| q.id).collect()
+    }
+
+    fn hidden<S>(filtered: &Filtered<S>) -> Vec<(u32, Reason)> {
+        filtered
+            .hidden
+            .iter()
+            .map(|h| (h.question.id, h.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_filters_keep_what_they_hide() {
+        let qs = (1..=6).map(|id| question(id, id as usize % 3)).collect();
+        let filtered = Filtered::new(qs)
+            .filter(Reason::Unanswered, |q| !q.answers.is_empty())
+            .filter(Reason::NoCodeMatch, |q| q.score > 2);
+        assert_eq!(ids(&filtered.shown), vec![4, 5]);
+        assert_eq!(
+            hidden(&filtered),
+            vec![
+                (3, Reason::Unanswered),
+                (6, Reason::Unanswered),
+                (1, Reason::NoCodeMatch),
+                (2, Reason::NoCodeMatch)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let qs = (1..=4).map(|id| question(id, 1)).collect();
+        let filtered = Filtered::new(qs)
+            .filter(Reason::NoCodeMatch, |q| q.id % 2 == 0)
+            .convert(|qs| {
+                qs.into_iter()
+                    .map(|q| Question {
+                        body: q.body.as_ref().map(|_| q.id),
+                        title: q.title,
+                        tags: q.tags,
+                        id: q.id,
+                        score: q.score,
+                        site: q.site,
+                        ..Question::default()
+                    })
+                    .collect()
+            });
+        assert_eq!(ids(&filtered.shown), vec![2, 4]);
+        assert_eq!(
+            hidden(&filtered),
+            vec![(1, Reason::NoCodeMatch), (3, Reason::NoCodeMatch)]
+        );
+        assert_eq!(filtered.hidden[1].question.body, Some(3));
+    }
+
+    #[test]
+    fn test_reasons() {
+        assert_eq!(Reason::Unanswered.to_string(), "no answers");
+        assert_eq!(
+            Reason::NoCodeMatch.to_string(),
+            "no code matching the query"
+        );
+        assert_eq!(Reason::ListedBefore.to_string(), "listed earlier");
+        assert_eq!(Reason::Other("off-topic").to_string(), "off-topic");
+    }
+}
@@ -0,0 +1,144 @@
+//! Filters created from the configured one, by what they add to it and leave out of it.
+//!
+//! Filters are opaque ids, so adding or leaving out a field takes a request to /filters/create.
+//! The ids it hands out never change, so they're kept on disk and only the first search pays for
+//! the request. As with the backoffs, the file is only a hint: if it can't be read, filters are
+//! created again.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::utils;
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+struct State {
+    /// Created filters by `Derived::key`
+    created: HashMap<String, String>,
+}
+
+/// How to derive a filter from `base`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Derived<'a> {
+    /// URL of the /filters/create endpoint, since Teams has filters of its own
+    pub endpoint: &'a str,
+    pub base: &'a str,
+    pub include: &'a str,
+    pub exclude: &'a str,
+}
+
+impl Derived<'_> {
+    fn key(&self) -> String {
+        format!(
+            "{} {} +{} -{}",
+            self.endpoint, self.base, self.include, self.exclude
+        )
+    }
+}
+
+/// Filters created so far, shared by every clone
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    /// State file, if filters outlive the invocation
+    path: Option<PathBuf>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Filters {
+    /// Filters kept in `path`
+    pub fn new(path: PathBuf) -> Self {
+        let state = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Filters {
+            path: Some(path),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Filters kept in the project's cache directory
+    pub fn open() -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("filters.json")))
+    }
+
+    /// The filter created for `derived` before, if any
+    pub fn get(&self, derived: &Derived) -> Option<String> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.created.get(&derived.key()).cloned()
+    }
+
+    /// Remember that `filter` was created for `derived`
+    pub fn insert(&self, derived: &Derived, filter: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.created.insert(derived.key(), filter.to_string());
+        if let Some(path) = &self.path {
+            // Creating it again next time beats failing the search over it
+            let _ = save(path, &state);
+        }
+    }
+}
+
+fn save(path: &PathBuf, state: &State) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    utils::write_atomic(path, &serde_json::to_vec(state)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAGGED: Derived = Derived {
+        endpoint: "https://api.stackexchange.com/2.3/filters/create",
+        base: "custom",
+        include: "question.tags",
+        exclude: "",
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("so-filters-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("filters.json")
+    }
+
+    #[test]
+    fn test_derived() {
+        let filters = Filters::default();
+        assert_eq!(filters.get(&TAGGED), None);
+        filters.insert(&TAGGED, "tagged");
+        assert_eq!(filters.clone().get(&TAGGED).as_deref(), Some("tagged"));
+        let slim = Derived {
+            exclude: "question.body_markdown",
+            ..TAGGED
+        };
+        assert_eq!(filters.get(&slim), None);
+        let teams = Derived {
+            endpoint: "https://api.stackoverflowteams.com/2.3/filters/create",
+            ..TAGGED
+        };
+        assert_eq!(filters.get(&teams), None);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = temp_path("persistence");
+        Filters::new(path.clone()).insert(&TAGGED, "tagged");
+        // As in the next invocation
+        assert_eq!(
+            Filters::new(path.clone()).get(&TAGGED).as_deref(),
+            Some("tagged")
+        );
+        // Garbage is as good as nothing
+        fs::write(&path, b"{\"created\": ").unwrap();
+        assert_eq!(Filters::new(path.clone()).get(&TAGGED), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
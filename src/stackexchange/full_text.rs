@@ -0,0 +1,459 @@
+//! Full-text index of the questions in the query cache, for `--search`, which finds cached
+//! questions by what they say rather than by the query that found them.
+//!
+//! The index maps each term, as `analysis` breaks titles and bodies down, to the questions it's in
+//! along with how often, a term in a title counting `TITLE_WEIGHT` times. Questions are ranked by
+//! TF-IDF over those counts, and served from the cached searches they're in, which the index
+//! doesn't duplicate.
+//!
+//! It's kept next to the cached searches and updated as each one is cached. Searching it first
+//! checks it against the cached searches themselves, by modification time: those it lacks or has
+//! an older version of are indexed then, and those gone are dropped. So an index that missed an
+//! update, e.g. to a concurrent run of `so`, or that can't be read at all, catches up on its own;
+//! `so cache reindex` rebuilds it from scratch.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::error::Result;
+use crate::utils;
+
+use super::analysis::{self, Language};
+use super::api::Question;
+use super::query_cache::{self, Entry};
+
+/// File name of the index, among the cached searches
+pub const FILE: &str = "index.json";
+
+/// Version of the index layout; an index of any other version is rebuilt
+const VERSION: u32 = 1;
+
+/// How many times a term in a title counts, over one in a body
+const TITLE_WEIGHT: u32 = 3;
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct Index {
+    version: u32,
+    /// Language terms were broken down in; searching in another rebuilds the index
+    language: Language,
+    /// Modification time, in milliseconds, of each cached search indexed, by file name
+    searches: BTreeMap<String, u64>,
+    /// Indexed questions, by `site/id`
+    questions: BTreeMap<String, Indexed>,
+    /// Weighted count of each term in each question it's in, by term, then question
+    postings: BTreeMap<String, BTreeMap<String, u32>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct Indexed {
+    /// File names of the cached searches it's in
+    searches: BTreeSet<String>,
+    /// Its distinct terms, to take it out of `postings` when it's indexed again
+    terms: Vec<String>,
+}
+
+/// A question that matched, as cached
+#[derive(Debug)]
+pub struct Hit {
+    /// Unix timestamp of when the search it's served from was fetched
+    pub fetched_at: i64,
+    pub question: Question<String>,
+}
+
+impl Index {
+    fn new(language: Language) -> Self {
+        Index {
+            version: VERSION,
+            language,
+            ..Index::default()
+        }
+    }
+
+    /// The index in `dir`, if there's one this version can read
+    fn read(dir: &Path) -> Option<Self> {
+        let file = fs::File::open(dir.join(FILE)).ok()?;
+        let index: Index = serde_json::from_reader(file).ok()?;
+        Some(index).filter(|index| index.version == VERSION)
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        utils::write_atomic(&dir.join(FILE), &serde_json::to_vec(self)?)
+    }
+
+    /// How many cached searches are indexed
+    pub fn searches(&self) -> usize {
+        self.searches.len()
+    }
+
+    /// How many distinct questions are indexed
+    pub fn questions(&self) -> usize {
+        self.questions.len()
+    }
+
+    /// Index `questions` as those of the cached search `file`, modified at `modified`, in place
+    /// of whatever it had before
+    fn add(&mut self, file: &str, modified: u64, questions: &[Question<String>]) {
+        self.remove(file);
+        for q in questions {
+            let key = format!("{}/{}", q.site, q.id);
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for term in analysis::all_terms(&q.title, self.language) {
+                *counts.entry(term).or_default() += TITLE_WEIGHT;
+            }
+            let bodies = q.body.iter().chain(q.answers.iter().map(|a| &a.body));
+            for term in bodies.flat_map(|body| analysis::all_terms(body, self.language)) {
+                *counts.entry(term).or_default() += 1;
+            }
+            // The latest version of a question is the one indexed
+            let mut indexed = self.questions.remove(&key).unwrap_or_default();
+            self.unpost(&key, &indexed.terms);
+            indexed.terms = counts.keys().cloned().collect();
+            indexed.searches.insert(file.to_string());
+            for (term, count) in counts {
+                self.postings
+                    .entry(term)
+                    .or_default()
+                    .insert(key.clone(), count);
+            }
+            self.questions.insert(key, indexed);
+        }
+        self.searches.insert(file.to_string(), modified);
+    }
+
+    /// Forget the cached search `file`, and the questions in no other
+    fn remove(&mut self, file: &str) {
+        if self.searches.remove(file).is_none() {
+            return;
+        }
+        let mut orphans = Vec::new();
+        for (key, indexed) in self.questions.iter_mut() {
+            if indexed.searches.remove(file) && indexed.searches.is_empty() {
+                orphans.push(key.clone());
+            }
+        }
+        for key in orphans {
+            if let Some(indexed) = self.questions.remove(&key) {
+                self.unpost(&key, &indexed.terms);
+            }
+        }
+    }
+
+    fn unpost(&mut self, key: &str, terms: &[String]) {
+        for term in terms {
+            if let Some(posting) = self.postings.get_mut(term) {
+                posting.remove(key);
+                if posting.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+    }
+
+    /// Index what changed among the cached searches in `dir` since the index last saw them.
+    /// Returns whether anything did.
+    fn catch_up(&mut self, dir: &Path) -> Result<bool> {
+        let mut current = BTreeMap::new();
+        for path in query_cache::cached_searches(dir)? {
+            if let (Some(file), Some(modified)) = (file_name(&path), modified(&path)) {
+                current.insert(file, (path, modified));
+            }
+        }
+        let gone: Vec<String> = self
+            .searches
+            .keys()
+            .filter(|file| !current.contains_key(*file))
+            .cloned()
+            .collect();
+        let mut changed = !gone.is_empty();
+        for file in gone {
+            self.remove(&file);
+        }
+        for (file, (path, modified)) in current {
+            if self.searches.get(&file) == Some(&modified) {
+                continue;
+            }
+            changed = true;
+            match read_entry(&path) {
+                Some(entry) => self.add(&file, modified, &entry.questions),
+                // Can't be served either, so there's nothing to find in it
+                None => self.remove(&file),
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Keys of the questions best matching `query`, best first, at most `limit` of them
+    fn rank(&self, query: &str, limit: usize) -> Vec<&str> {
+        let total = self.questions.len() as f64;
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for term in analysis::terms(query, self.language) {
+            if let Some(posting) = self.postings.get(&term) {
+                let idf = (1.0 + total / posting.len() as f64).ln();
+                for (key, count) in posting {
+                    *scores.entry(key).or_default() += (1.0 + f64::from(*count).ln()) * idf;
+                }
+            }
+        }
+        let mut ranked: Vec<(&str, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.partial_cmp(a_score).unwrap().then_with(|| a.cmp(b))
+        });
+        ranked.into_iter().take(limit).map(|(key, _)| key).collect()
+    }
+
+    /// The cached search `key` is best served from: the most recently modified one it's in
+    fn source(&self, key: &str) -> Option<&str> {
+        self.questions
+            .get(key)?
+            .searches
+            .iter()
+            .max_by_key(|file| self.searches.get(*file))
+            .map(String::as_str)
+    }
+}
+
+/// Index `questions` as those of the cached search `file` in `dir`, just written. An index that
+/// can't be read is left for searching it to rebuild.
+pub fn update(dir: &Path, file: &str, questions: &[Question<String>]) -> Result<()> {
+    let mut index = match Index::read(dir) {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+    if let Some(modified) = modified(&dir.join(file)) {
+        index.add(file, modified, questions);
+        index.save(dir)?;
+    }
+    Ok(())
+}
+
+/// The cached questions in `dir` best matching `query`, best first, at most `limit` of them
+pub fn search(dir: &Path, query: &str, language: Language, limit: usize) -> Result<Vec<Hit>> {
+    let mut index = Index::read(dir)
+        .filter(|index| index.language == language)
+        .unwrap_or_else(|| Index::new(language));
+    if index.catch_up(dir)? {
+        index.save(dir)?;
+    }
+    let mut entries: HashMap<&str, Option<Entry>> = HashMap::new();
+    let mut hits = Vec::new();
+    for key in index.rank(query, limit) {
+        let file = match index.source(key) {
+            Some(file) => file,
+            None => continue,
+        };
+        let entry = entries
+            .entry(file)
+            .or_insert_with(|| read_entry(&dir.join(file)));
+        let found = entry.as_ref().and_then(|entry| {
+            entry
+                .questions
+                .iter()
+                .find(|q| format!("{}/{}", q.site, q.id) == key)
+                .map(|q| (entry.fetched_at, q.clone()))
+        });
+        if let Some((fetched_at, question)) = found {
+            hits.push(Hit {
+                fetched_at,
+                question,
+            });
+        }
+    }
+    Ok(hits)
+}
+
+/// Build the index of the cached searches in `dir` from scratch
+pub fn rebuild(dir: &Path, language: Language) -> Result<Index> {
+    let mut index = Index::new(language);
+    index.catch_up(dir)?;
+    index.save(dir)?;
+    Ok(index)
+}
+
+fn read_entry(path: &Path) -> Option<Entry> {
+    serde_json::from_reader(fs::File::open(path).ok()?).ok()
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    Some(path.file_name()?.to_str()?.to_string())
+}
+
+/// Modification time of `path` in milliseconds, fine enough to tell two writes apart
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::stackexchange::query_cache::{Key, QueryCache};
+    use crate::stackexchange::testing;
+    use std::path::PathBuf;
+
+    /// Empty cache in a fresh temporary directory
+    fn temp_cache(name: &str) -> (PathBuf, QueryCache) {
+        let dir = std::env::temp_dir().join(format!("so-index-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        (dir.clone(), QueryCache::new(dir))
+    }
+
+    fn question(id: u32, title: &str, body: &str, answer: &str) -> Question<String> {
+        let answers = vec![testing::answer(id * 10, 1, true, answer.to_string())];
+        testing::question(id, 1, title, body.to_string(), answers)
+    }
+
+    fn tokio_task() -> Question<String> {
+        question(
+            1,
+            "How do I put a timeout on a tokio task?",
+            "My task hangs forever",
+            "Wrap it in `tokio::time::timeout`",
+        )
+    }
+
+    fn reqwest_timeout() -> Question<String> {
+        question(
+            2,
+            "Setting a timeout for reqwest requests",
+            "I'm on the tokio runtime",
+            "Use `ClientBuilder::timeout`",
+        )
+    }
+
+    fn exit_vim() -> Question<String> {
+        question(
+            3,
+            "How do I exit Vim?",
+            "I am stuck",
+            "Hit `Esc`, then type `:q`",
+        )
+    }
+
+    /// The archived corpus: three searches, one question turning up in two of them
+    fn archive(cache: &QueryCache) {
+        let put = |query: &str, qs: &[Question<String>]| {
+            cache.put(&Key::new(&Config::default(), query), qs).unwrap();
+        };
+        put("tokio timeout", &[tokio_task(), reqwest_timeout()]);
+        put("reqwest timeout", &[reqwest_timeout()]);
+        put("exit vim", &[exit_vim()]);
+    }
+
+    fn ids(dir: &Path, query: &str, limit: usize) -> Vec<u32> {
+        search(dir, query, Language::English, limit)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.question.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_ranking() {
+        let (dir, cache) = temp_cache("ranking");
+        archive(&cache);
+        // Both terms in the title beat one in the title and one in the body
+        assert_eq!(ids(&dir, "tokio timeout", 10), vec![1, 2]);
+        assert_eq!(ids(&dir, "tokio timeout", 1), vec![1]);
+        // Stemmed, whatever the case
+        assert_eq!(ids(&dir, "Timeouts for Requests", 10), vec![2, 1]);
+        assert_eq!(ids(&dir, "ESC", 10), vec![3]);
+        assert!(ids(&dir, "haskell monads", 10).is_empty());
+        assert!(ids(&dir, "how do I", 10).is_empty());
+
+        let hits = search(&dir, "vim", Language::English, 10).unwrap();
+        assert_eq!(
+            hits[0].question.answers[0].body,
+            "Hit `Esc`, then type `:q`"
+        );
+        assert!(hits[0].fetched_at > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_incremental() {
+        let (dir, cache) = temp_cache("incremental");
+        cache
+            .put(&Key::new(&Config::default(), "exit vim"), &[exit_vim()])
+            .unwrap();
+        // Nothing to update before the first search builds the index
+        assert!(Index::read(&dir).is_none());
+        assert_eq!(ids(&dir, "vim", 10), vec![3]);
+
+        // Searches cached since are indexed as they are
+        cache
+            .put(
+                &Key::new(&Config::default(), "tokio timeout"),
+                &[tokio_task()],
+            )
+            .unwrap();
+        let index = Index::read(&dir).unwrap();
+        assert_eq!((index.searches(), index.questions()), (2, 2));
+        assert_eq!(index.rank("tokio", 10), vec!["stackoverflow/1"]);
+
+        // A search cached again replaces what it had
+        cache
+            .put(
+                &Key::new(&Config::default(), "tokio timeout"),
+                &[reqwest_timeout()],
+            )
+            .unwrap();
+        let index = Index::read(&dir).unwrap();
+        assert_eq!((index.searches(), index.questions()), (2, 2));
+        assert_eq!(index.rank("tokio", 10), vec!["stackoverflow/2"]);
+        assert!(!index.postings.contains_key("hang"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_catch_up() {
+        let (dir, cache) = temp_cache("catch-up");
+        archive(&cache);
+        assert_eq!(ids(&dir, "tokio timeout", 10), vec![1, 2]);
+        let searches = query_cache::cached_searches(&dir).unwrap();
+        let mut index = Index::read(&dir).unwrap();
+        assert_eq!((index.searches(), index.questions()), (3, 3));
+        assert!(!index.catch_up(&dir).unwrap());
+
+        // A question stays while any search it's in does
+        for path in &searches {
+            if read_entry(path).unwrap().key.query == "tokio timeout" {
+                fs::remove_file(path).unwrap();
+            }
+        }
+        assert!(index.catch_up(&dir).unwrap());
+        assert_eq!((index.searches(), index.questions()), (2, 2));
+        assert_eq!(index.rank("tokio timeout", 10), vec!["stackoverflow/2"]);
+        // One gone behind the index's back isn't served
+        assert_eq!(ids(&dir, "tokio timeout", 10), vec![2]);
+
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert!(!dir.join(FILE).exists());
+        assert!(ids(&dir, "timeout", 10).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corruption_recovery() {
+        let (dir, cache) = temp_cache("corruption");
+        archive(&cache);
+        fs::write(dir.join(FILE), "{\"version\": 1, \"postings\": [").unwrap();
+        // Rebuilt from the cached searches, before updates would go into the void
+        assert_eq!(ids(&dir, "vim", 10), vec![3]);
+        let index = Index::read(&dir).unwrap();
+        assert_eq!((index.searches(), index.questions()), (3, 3));
+
+        // As is an index in another language
+        assert_eq!(search(&dir, "vim", Language::None, 10).unwrap().len(), 1);
+        assert_eq!(Index::read(&dir).unwrap().language, Language::None);
+
+        let index = cache.reindex(Language::English).unwrap();
+        assert_eq!((index.searches(), index.questions()), (3, 3));
+        assert_eq!(index.language, Language::English);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Images linked from answers, downloaded on demand to preview them in the terminal.
+//!
+//! Downloads are kept in the cache directory, named after a hash of their URL, so that looking at
+//! the same screenshot again doesn't download it again. Images over a size cap aren't downloaded
+//! at all, and the cache as a whole is trimmed to a size cap by evicting the least recently used
+//! images, going by file modification times, which reads bump.
+
+use reqwest::header::HeaderMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::utils;
+
+use super::network::{Network, OfflinePolicy};
+use super::query_cache::fnv1a;
+
+/// Largest image that gets downloaded
+const MAX_IMAGE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Size the cache is trimmed down to after each download
+const MAX_SIZE: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ImageCache {
+    dir: PathBuf,
+    max_image_size: u64,
+    max_size: u64,
+    network: Network,
+}
+
+impl ImageCache {
+    pub fn new(dir: PathBuf, policy: OfflinePolicy) -> Self {
+        ImageCache {
+            dir,
+            max_image_size: MAX_IMAGE_SIZE,
+            max_size: MAX_SIZE,
+            network: Network::new(policy, HeaderMap::new()),
+        }
+    }
+
+    /// Also send `headers` with every download, e.g. for a proxy
+    pub fn with_extra_headers(self, headers: HeaderMap) -> Self {
+        ImageCache {
+            network: self.network.with_extra_headers(headers),
+            ..self
+        }
+    }
+
+    /// Cache in the project's cache directory
+    pub fn open(policy: OfflinePolicy) -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("images"), policy))
+    }
+
+    /// The image at `url`, from the cache if it was downloaded before
+    pub async fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let path = self.path(url);
+        if let Some(bytes) = self.cached(&path) {
+            return Ok(bytes);
+        }
+        let mut response = self
+            .network
+            .client()?
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?;
+        let too_large = || Error::ImageTooLarge(url.to_string(), self.max_image_size);
+        // Servers don't have to say, so the download is checked as it comes in too
+        if response
+            .content_length()
+            .is_some_and(|len| len > self.max_image_size)
+        {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() as u64 > self.max_image_size {
+                return Err(too_large());
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        utils::write_atomic(&path, &bytes)?;
+        self.evict()?;
+        Ok(bytes)
+    }
+
+    fn cached(&self, path: &PathBuf) -> Option<Vec<u8>> {
+        let bytes = fs::read(path).ok()?;
+        if let Ok(file) = fs::File::open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(bytes)
+    }
+
+    /// Remove the least recently used images until the cache fits in `max_size`
+    fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_unstable();
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            size -= len;
+        }
+        Ok(())
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}", fnv1a(url.as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> ImageCache {
+        let dir = std::env::temp_dir().join(format!("so-images-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ImageCache::new(dir, OfflinePolicy::Offline)
+    }
+
+    #[tokio::test]
+    async fn test_cached() {
+        let cache = temp_cache("cached");
+        let url = "https://i.sstatic.net/abc.png";
+        fs::create_dir_all(&cache.dir).unwrap();
+        fs::write(cache.path(url), b"\x89PNG").unwrap();
+        // Served from the cache, without the network
+        assert_eq!(cache.get(url).await.unwrap(), b"\x89PNG");
+        let other = "https://i.sstatic.net/def.png";
+        assert_ne!(cache.path(url), cache.path(other));
+        assert!(matches!(cache.get(other).await, Err(Error::Offline)));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_evict() {
+        let cache = ImageCache {
+            max_size: 10,
+            ..temp_cache("evict")
+        };
+        fs::create_dir_all(&cache.dir).unwrap();
+        let old = cache.path("old");
+        fs::write(&old, [0; 8]).unwrap();
+        fs::File::open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - std::time::Duration::from_secs(60))
+            .unwrap();
+        fs::write(cache.path("new"), [0; 8]).unwrap();
+        cache.evict().unwrap();
+        assert!(!old.exists());
+        assert!(cache.path("new").exists());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
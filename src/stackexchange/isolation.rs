@@ -0,0 +1,183 @@
+//! Isolation of failures while rendering posts.
+//!
+//! Preprocessing and parsing run once per question and answer. If either panics on some
+//! pathological body, that one post is shown as plain text with a notice instead, and everything
+//! else renders as usual. Failures are collected so that `--verbose` can say which posts were
+//! affected.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Once};
+
+/// Step of rendering that failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Preprocess,
+    Parse,
+}
+
+/// A post that failed to render
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub question_id: u32,
+    /// Unset if it was the question body that failed
+    pub answer_id: Option<u32>,
+    pub stage: Stage,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self.stage {
+            Stage::Preprocess => "preprocessing",
+            Stage::Parse => "parsing",
+        };
+        write!(f, "{} failed for question {}", stage, self.question_id)?;
+        if let Some(answer_id) = self.answer_id {
+            write!(f, ", answer {}", answer_id)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Shared handle used to collect failures from concurrently rendered posts
+#[derive(Debug, Clone, Default)]
+pub struct Failures(Arc<Mutex<Vec<Failure>>>);
+
+impl Failures {
+    pub fn record(&self, failure: Failure) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(failure);
+    }
+
+    /// Everything recorded so far
+    pub fn list(&self) -> Vec<Failure> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Line shown in place of the rendering of a post that failed to render
+pub fn notice(is_answer: bool) -> &'static str {
+    if is_answer {
+        "Rendering failed for this answer; showing it as plain text."
+    } else {
+        "Rendering failed for this question; showing it as plain text."
+    }
+}
+
+/// Markdown showing `body` verbatim, under the failure notice. The body goes in a code block
+/// fenced with more backticks than it contains in a row, so nothing in it can close the block.
+pub fn fallback_markdown(body: &str, is_answer: bool) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in body.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!(
+        "*{}*\n\n{}\n{}\n{}\n",
+        notice(is_answer),
+        fence,
+        body.trim_end(),
+        fence
+    )
+}
+
+thread_local! {
+    /// Whether this thread is running a closure under `catch`
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+static QUIET_HOOK: Once = Once::new();
+
+/// Run `f`, turning a panic into an error carrying the panic message. Panics caught this way
+/// aren't printed, since they're reported through `Failures` instead.
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    QUIET_HOOK.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(Cell::get) {
+                default(info)
+            }
+        }));
+    });
+    let was_catching = CATCHING.with(|c| c.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(was_catching));
+    result.map_err(|payload| panic_message(&*payload))
+}
+
+/// Message a panic was raised with, from its payload
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("unknown panic"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch() {
+        assert_eq!(catch(|| 1 + 1), Ok(2));
+        assert_eq!(
+            catch(|| -> u32 { panic!("bad body") }),
+            Err(String::from("bad body"))
+        );
+        let id = 7;
+        assert_eq!(
+            catch(|| -> u32 { panic!("bad body {}", id) }),
+            Err(String::from("bad body 7"))
+        );
+        // Nesting doesn't leave the outer closure unprotected
+        assert!(catch(|| {
+            let _ = catch(|| panic!("inner"));
+            panic!("outer")
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_fallback_markdown() {
+        assert_eq!(
+            fallback_markdown("**not bold**\n", true),
+            "*Rendering failed for this answer; showing it as plain text.*\n\n\
+             ```\n**not bold**\n```\n"
+        );
+        let md = fallback_markdown("````\nfenced\n````", false);
+        assert!(md.starts_with("*Rendering failed for this question"));
+        assert!(md.contains("\n`````\n````\nfenced\n````\n`````\n"));
+    }
+
+    #[test]
+    fn test_failure_display() {
+        let failure = Failure {
+            question_id: 1,
+            answer_id: Some(2),
+            stage: Stage::Parse,
+            message: String::from("oops"),
+        };
+        assert_eq!(
+            failure.to_string(),
+            "parsing failed for question 1, answer 2: oops"
+        );
+        let failures = Failures::default();
+        failures.record(Failure {
+            answer_id: None,
+            stage: Stage::Preprocess,
+            ..failure
+        });
+        assert_eq!(
+            failures.list()[0].to_string(),
+            "preprocessing failed for question 1: oops"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! How long each site takes to answer searches, for lucky mode to pick the quickest.
+//!
+//! Every StackExchange search feeds the time its requests took into a rolling average per site,
+//! weighted towards recent searches so that a site that got faster (or slower) is noticed within a
+//! few searches. Averages are kept on disk across invocations. As with backoffs, the state file is
+//! only a hint: if it can't be read it's as good as empty.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::utils;
+
+/// Weight of the latest sample in the rolling average
+const WEIGHT: f64 = 0.3;
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+struct State {
+    /// Rolling average of search latency per site, in milliseconds
+    millis: HashMap<String, f64>,
+}
+
+/// Search latency per site, shared by every clone
+#[derive(Debug, Clone, Default)]
+pub struct Latency {
+    /// State file, if averages outlive the invocation
+    path: Option<PathBuf>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Latency {
+    /// Averages kept in `path`
+    pub fn new(path: PathBuf) -> Self {
+        let state = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Latency {
+            path: Some(path),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Averages kept in the project's cache directory
+    pub fn open() -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("latency.json")))
+    }
+
+    /// Record how long searches of each site took
+    pub fn record(&self, samples: &[(&str, Duration)]) {
+        if samples.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for (site, took) in samples {
+            let sample = took.as_secs_f64() * 1000.0;
+            state
+                .millis
+                .entry(site.to_string())
+                .and_modify(|average| *average += WEIGHT * (sample - *average))
+                .or_insert(sample);
+        }
+        if let Some(path) = &self.path {
+            // Better to not know about it next time than to fail the search over it
+            let _ = save(path, &state);
+        }
+    }
+
+    /// Rolling average of how long searches of `site` take, if it was ever searched
+    pub fn average(&self, site: &str) -> Option<Duration> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let millis = *state.millis.get(site)?;
+        Some(Duration::from_millis(millis.max(0.0).round() as u64))
+    }
+
+    /// `sites`, quickest first. Sites never searched come before all others, in their original
+    /// order, so that they get measured.
+    pub fn fastest_first(&self, sites: &[String]) -> Vec<String> {
+        let mut sites = sites.to_vec();
+        // Stable, so ties keep their configured order
+        sites.sort_by_key(|site| self.average(site));
+        sites
+    }
+}
+
+fn save(path: &PathBuf, state: &State) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    utils::write_atomic(path, &serde_json::to_vec(state)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("so-latency-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("latency.json")
+    }
+
+    fn sites(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_rolling_average() {
+        let latency = Latency::default();
+        assert_eq!(latency.average("stackoverflow"), None);
+        latency.record(&[("stackoverflow", Duration::from_millis(1000))]);
+        assert_eq!(
+            latency.average("stackoverflow"),
+            Some(Duration::from_millis(1000))
+        );
+        latency.record(&[("stackoverflow", Duration::from_millis(2000))]);
+        assert_eq!(
+            latency.average("stackoverflow"),
+            Some(Duration::from_millis(1300))
+        );
+        // Shared with clones, e.g. concurrent searches
+        latency
+            .clone()
+            .record(&[("superuser", Duration::from_millis(10))]);
+        assert_eq!(
+            latency.average("superuser"),
+            Some(Duration::from_millis(10))
+        );
+    }
+
+    #[test]
+    fn test_fastest_first() {
+        let latency = Latency::default();
+        latency.record(&[
+            ("stackoverflow", Duration::from_millis(900)),
+            ("superuser", Duration::from_millis(200)),
+            ("askubuntu", Duration::from_millis(500)),
+        ]);
+        assert_eq!(
+            latency.fastest_first(&sites(&[
+                "stackoverflow",
+                "unix",
+                "superuser",
+                "askubuntu",
+                "serverfault"
+            ])),
+            sites(&[
+                "unix",
+                "serverfault",
+                "superuser",
+                "askubuntu",
+                "stackoverflow"
+            ])
+        );
+        // A site that got slow loses its place after a few searches
+        for _ in 0..3 {
+            latency.record(&[("superuser", Duration::from_millis(2000))]);
+        }
+        assert_eq!(
+            latency.fastest_first(&sites(&["superuser", "askubuntu"])),
+            sites(&["askubuntu", "superuser"])
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = temp_path("persistence");
+        let latency = Latency::new(path.clone());
+        latency.record(&[("stackoverflow", Duration::from_millis(400))]);
+        // As in the next invocation
+        let next = Latency::new(path.clone());
+        assert_eq!(
+            next.average("stackoverflow"),
+            Some(Duration::from_millis(400))
+        );
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_garbage_state() {
+        let path = temp_path("garbage");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "not json").unwrap();
+        let latency = Latency::new(path.clone());
+        assert_eq!(latency.average("stackoverflow"), None);
+        latency.record(&[("stackoverflow", Duration::from_millis(5))]);
+        assert!(Latency::new(path.clone())
+            .average("stackoverflow")
+            .is_some());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,268 @@
+//! Rewriting the hosts of the links `so` shows, e.g. to a read-only mirror inside a network that
+//! can't reach the sites themselves.
+//!
+//! `link_rewrites` maps hosts to the hosts to link to instead, keeping the scheme, port, path,
+//! query and fragment, and leaving links to any other host as they are. Links are rewritten where
+//! they leave `Search`, and in comments as the TUI fetches them, never in what's cached, so that
+//! changing the map applies to cached results too. Links in code blocks and inline code are left
+//! alone, being more likely commands or config than something to click on, unless
+//! `rewrite_links_in_code` is set.
+
+use pulldown_cmark::{Event, Parser, Tag};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::error::{Error, Result};
+
+use super::api::Question;
+
+/// Hosts to link to in place of others, by the host they replace
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(transparent)]
+pub struct LinkRewrites(BTreeMap<String, String>);
+
+impl LinkRewrites {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check that every entry maps a bare host, as in `stackoverflow.com`, to another
+    pub fn validate(&self) -> Result<()> {
+        for (from, to) in &self.0 {
+            let invalid = |reason: &str| Error::LinkRewrite(from.clone(), reason.to_string());
+            if !is_host(from) {
+                return Err(invalid("expected a bare host, like stackoverflow.com"));
+            }
+            if from.chars().any(|c| c.is_ascii_uppercase()) {
+                return Err(invalid("hosts are matched in lower case"));
+            }
+            if !is_host(to) {
+                return Err(invalid(&format!(
+                    "expected a bare host to rewrite to, like so-mirror.example.com, not `{}`",
+                    to
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// `url` pointing at the host it's rewritten to, if it's an absolute link to a host that is
+    pub fn url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        match url.find("://") {
+            Some(i) => self.rewrite_at(url, i + 3),
+            None => Cow::Borrowed(url),
+        }
+    }
+
+    /// `site_url`, a site's base URL with or without a scheme, as `format_link` takes them,
+    /// pointing at the host it's rewritten to
+    pub fn site_url<'a>(&self, site_url: &'a str) -> Cow<'a, str> {
+        match site_url.find("://") {
+            Some(i) => self.rewrite_at(site_url, i + 3),
+            None => self.rewrite_at(site_url, 0),
+        }
+    }
+
+    /// Markdown `body` with its links rewritten, those in code too if `in_code` is set
+    pub fn body<'a>(&self, body: &'a str, in_code: bool) -> Cow<'a, str> {
+        if self.is_empty() || !body.contains("://") {
+            return Cow::Borrowed(body);
+        }
+        let code = if in_code { vec![] } else { code_spans(body) };
+        let mut rewritten = String::with_capacity(body.len());
+        let mut copied = 0;
+        for (i, _) in body.match_indices("://") {
+            let host_start = i + 3;
+            if code.iter().any(|span| span.contains(&host_start)) {
+                continue;
+            }
+            if let Some((host, to)) = self.lookup(body, host_start) {
+                rewritten.push_str(&body[copied..host.start]);
+                rewritten.push_str(to);
+                copied = host.end;
+            }
+        }
+        if copied == 0 {
+            return Cow::Borrowed(body);
+        }
+        rewritten.push_str(&body[copied..]);
+        Cow::Owned(rewritten)
+    }
+
+    /// `q` with the links of it and its answers rewritten, in their bodies too
+    pub fn question(&self, q: Question<String>, in_code: bool) -> Question<String> {
+        if self.is_empty() {
+            return q;
+        }
+        let link = |link: Option<String>| link.map(|l| self.url(&l).into_owned());
+        Question {
+            link: link(q.link),
+            body: q.body.map(|body| self.body(&body, in_code).into_owned()),
+            answers: q
+                .answers
+                .into_iter()
+                .map(|a| super::api::Answer {
+                    link: link(a.link),
+                    body: self.body(&a.body, in_code).into_owned(),
+                    ..a
+                })
+                .collect(),
+            ..q
+        }
+    }
+
+    /// `s` with the host starting at `host_start` rewritten, if it is
+    fn rewrite_at<'a>(&self, s: &'a str, host_start: usize) -> Cow<'a, str> {
+        match self.lookup(s, host_start) {
+            Some((host, to)) => Cow::Owned(format!("{}{}{}", &s[..host.start], to, &s[host.end..])),
+            None => Cow::Borrowed(s),
+        }
+    }
+
+    /// Where the host starting at `host_start` of `s` is, along with the host it's rewritten to,
+    /// if it is. Ports aren't part of the host.
+    fn lookup(&self, s: &str, host_start: usize) -> Option<(Range<usize>, &str)> {
+        let len = s[host_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+            .unwrap_or(s.len() - host_start);
+        let host = host_start..host_start + len;
+        // A host can't end in the dot that ends a sentence
+        let name = s[host.clone()].trim_end_matches('.');
+        let to = self.0.get(&name.to_ascii_lowercase())?;
+        Some((host.start..host.start + name.len(), to))
+    }
+}
+
+/// Whether `s` is a host and nothing else
+fn is_host(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        && Url::parse(&format!("https://{}/", s))
+            .is_ok_and(|url| url.host_str() == Some(&s.to_ascii_lowercase()))
+}
+
+/// Byte ranges of the code blocks and inline code of markdown `body`
+fn code_spans(body: &str) -> Vec<Range<usize>> {
+    Parser::new(body)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrites() -> LinkRewrites {
+        LinkRewrites(
+            vec![
+                ("stackoverflow.com", "so-mirror.corp"),
+                ("i.sstatic.net", "img-mirror.corp"),
+            ]
+            .into_iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn test_url() {
+        let rewrites = rewrites();
+        let cases = [
+            (
+                "https://stackoverflow.com/questions/1/title?tab=votes#2",
+                "https://so-mirror.corp/questions/1/title?tab=votes#2",
+            ),
+            ("http://stackoverflow.com", "http://so-mirror.corp"),
+            (
+                "https://StackOverflow.com:8443/a/2",
+                "https://so-mirror.corp:8443/a/2",
+            ),
+            // Not the same host
+            (
+                "https://meta.stackoverflow.com/q/1",
+                "https://meta.stackoverflow.com/q/1",
+            ),
+            (
+                "https://stackoverflow.com.evil/q/1",
+                "https://stackoverflow.com.evil/q/1",
+            ),
+            ("https://superuser.com/q/1", "https://superuser.com/q/1"),
+            // Not a link
+            ("stackoverflow.com/q/1", "stackoverflow.com/q/1"),
+        ];
+        for (url, expected) in &cases {
+            assert_eq!(rewrites.url(url), *expected);
+        }
+        assert_eq!(rewrites.site_url("stackoverflow.com"), "so-mirror.corp");
+        assert_eq!(rewrites.site_url("superuser.com"), "superuser.com");
+    }
+
+    const MIXED: &str = "See [the docs](https://stackoverflow.com/a/1 \"title\") and \
+        <https://superuser.com/q/2>, or https://stackoverflow.com.\n\n\
+        ![diagram](https://i.sstatic.net/abc.png)\n\n\
+        Run `curl https://stackoverflow.com/ping` first:\n\n\
+        ```\n\
+        git clone https://stackoverflow.com/repo\n\
+        ```\n\n\
+        [1]: https://stackoverflow.com/questions/3\n";
+
+    #[test]
+    fn test_body() {
+        let expected = "See [the docs](https://so-mirror.corp/a/1 \"title\") and \
+            <https://superuser.com/q/2>, or https://so-mirror.corp.\n\n\
+            ![diagram](https://img-mirror.corp/abc.png)\n\n\
+            Run `curl https://stackoverflow.com/ping` first:\n\n\
+            ```\n\
+            git clone https://stackoverflow.com/repo\n\
+            ```\n\n\
+            [1]: https://so-mirror.corp/questions/3\n";
+        assert_eq!(rewrites().body(MIXED, false), expected);
+    }
+
+    #[test]
+    fn test_body_in_code() {
+        let rewritten = rewrites().body(MIXED, true);
+        assert!(rewritten.contains("`curl https://so-mirror.corp/ping`"));
+        assert!(rewritten.contains("git clone https://so-mirror.corp/repo\n"));
+        assert!(!rewritten.contains("stackoverflow.com"));
+    }
+
+    #[test]
+    fn test_body_untouched() {
+        let body = "Nothing to rewrite at https://superuser.com/q/2";
+        assert!(matches!(rewrites().body(body, false), Cow::Borrowed(_)));
+        assert!(matches!(
+            LinkRewrites::default().body(MIXED, true),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(rewrites().validate().is_ok());
+        let invalid = |from: &str, to: &str| {
+            let map = vec![(from.to_string(), to.to_string())]
+                .into_iter()
+                .collect();
+            LinkRewrites(map).validate().unwrap_err().to_string()
+        };
+        assert_eq!(
+            invalid("https://stackoverflow.com", "so-mirror.corp"),
+            "Invalid entry `https://stackoverflow.com` in `link_rewrites` in your config: \
+             expected a bare host, like stackoverflow.com"
+        );
+        assert!(invalid("stackoverflow.com/questions", "so-mirror.corp").contains("bare host"));
+        assert!(invalid("", "so-mirror.corp").contains("bare host"));
+        assert!(invalid("StackOverflow.com", "so-mirror.corp").contains("lower case"));
+        assert!(invalid("stackoverflow.com", "https://so-mirror.corp/").contains("not `https"));
+        assert!(invalid("stackoverflow.com", "").contains("host to rewrite to"));
+    }
+}
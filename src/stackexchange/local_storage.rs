@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 
@@ -7,6 +9,87 @@ use crate::error::{Error, Result};
 use crate::utils;
 
 use super::api::{Api, Site};
+use super::network::{Destination, OfflinePolicy, Timeouts};
+
+/// Sites to offer when running offline before the site listing was ever cached
+const FALLBACK_SITES: &[(&str, &str, &str)] = &[
+    ("stackoverflow", "stackoverflow.com", "Stack Overflow"),
+    ("superuser", "superuser.com", "Super User"),
+    ("serverfault", "serverfault.com", "Server Fault"),
+    ("askubuntu", "askubuntu.com", "Ask Ubuntu"),
+    ("unix", "unix.stackexchange.com", "Unix & Linux"),
+    ("math", "math.stackexchange.com", "Mathematics"),
+    (
+        "softwareengineering",
+        "softwareengineering.stackexchange.com",
+        "Software Engineering",
+    ),
+    ("codereview", "codereview.stackexchange.com", "Code Review"),
+    ("tex", "tex.stackexchange.com", "TeX - LaTeX"),
+    ("apple", "apple.stackexchange.com", "Ask Different"),
+];
+
+/// Version of the site listing cached in `sites.json`, bumped whenever `Site` gains a field worth
+/// fetching the listing again for
+const SITES_SCHEMA: u32 = 1;
+
+/// The site listing as cached in `sites.json`
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+enum Cached {
+    Versioned {
+        schema: u32,
+        sites: Vec<Site>,
+    },
+    /// As cached by versions before `SITES_SCHEMA`, without site audiences
+    Bare(Vec<Site>),
+}
+
+impl Cached {
+    /// Whether it has every field of `Site` the current version fetches
+    fn is_current(&self) -> bool {
+        matches!(self, Cached::Versioned { schema, .. } if *schema >= SITES_SCHEMA)
+    }
+
+    fn into_sites(self) -> Vec<Site> {
+        match self {
+            Cached::Versioned { sites, .. } | Cached::Bare(sites) => sites,
+        }
+    }
+}
+
+/// Edit distance up to which a site is suggested for an unknown site code
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+/// Edit distance up to which a lone suggestion is taken to be what was meant
+const MAX_CORRECTION_DISTANCE: usize = 2;
+/// Suggestions for an unknown site code at most
+const MAX_SUGGESTIONS: usize = 3;
+
+/// A site code that isn't in the site listing, with the codes of the sites closest to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidSite {
+    pub code: String,
+    /// Closest first
+    pub suggestions: Vec<String>,
+    /// The one suggestion close enough to take for a typo of it, if there's just one
+    pub correction: Option<String>,
+}
+
+impl fmt::Display for InvalidSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not a valid StackExchange site", self.code)?;
+        let quoted: Vec<String> = self
+            .suggestions
+            .iter()
+            .map(|code| format!("`{}`", code))
+            .collect();
+        match quoted.split_last() {
+            None => Ok(()),
+            Some((last, [])) => write!(f, "; did you mean {}?", last),
+            Some((last, rest)) => write!(f, "; did you mean {} or {}?", rest.join(", "), last),
+        }
+    }
+}
 
 /// This structure allows interacting with locally cached StackExchange metadata.
 pub struct LocalStorage {
@@ -14,7 +97,7 @@ pub struct LocalStorage {
 }
 
 impl LocalStorage {
-    fn fetch_local_sites(filename: &PathBuf) -> Result<Option<Vec<Site>>> {
+    fn fetch_local_sites(filename: &PathBuf) -> Result<Option<Cached>> {
         if let Some(file) = utils::open_file(filename)? {
             return serde_json::from_reader(file)
                 .map_err(|_| Error::MalformedFile(filename.clone()));
@@ -22,44 +105,164 @@ impl LocalStorage {
         Ok(None)
     }
 
-    fn store_local_sites(filename: &PathBuf, sites: &[Site]) -> Result<()> {
+    fn store_local_sites(filename: &PathBuf, sites: Vec<Site>) -> Result<Vec<Site>> {
         let file = utils::create_file(filename)?;
-        serde_json::to_writer(file, sites)?;
-        Ok(())
+        let cached = Cached::Versioned {
+            schema: SITES_SCHEMA,
+            sites,
+        };
+        serde_json::to_writer(file, &cached)?;
+        Ok(cached.into_sites())
     }
 
-    async fn init_sites(filename: &PathBuf, update: bool) -> Result<Vec<Site>> {
+    /// The cached site listing, fetched first if it's missing or `update` is set. A listing
+    /// cached by an older version is fetched again for the fields it lacks, but still does
+    /// offline, or if fetching it fails.
+    async fn init_sites(filename: &PathBuf, update: bool, config: &Config) -> Result<Vec<Site>> {
+        let policy = OfflinePolicy::from(config);
+        let mut outdated = None;
         if !update {
-            if let Some(sites) = Self::fetch_local_sites(filename)? {
-                return Ok(sites);
+            match Self::fetch_local_sites(filename)? {
+                Some(cached) if cached.is_current() || policy == OfflinePolicy::Offline => {
+                    return Ok(cached.into_sites())
+                }
+                Some(cached) => outdated = Some(cached.into_sites()),
+                None if policy == OfflinePolicy::Offline => return Ok(fallback_sites()),
+                None => (),
             }
         }
-        let sites = Api::new(None).sites().await?;
-        Self::store_local_sites(filename, &sites)?;
-        Ok(sites)
+        let fetched = Api::new(None, None, policy)
+            .with_api_url(&config.api_url)
+            .with_api_version(&config.api_version)
+            .with_extra_headers(config.headers_for(Destination::StackExchange))
+            .with_timeouts(Timeouts::from(config))
+            .sites()
+            .await;
+        match (fetched, outdated) {
+            (Ok(sites), _) => Self::store_local_sites(filename, sites),
+            (Err(_), Some(outdated)) => Ok(outdated),
+            (Err(e), None) => Err(e),
+        }
     }
 
-    pub async fn new(update: bool) -> Result<Self> {
+    /// Load the cached site listing, fetching it first from the configured API if it's missing
+    /// or `update` is set
+    pub async fn new(update: bool, config: &Config) -> Result<Self> {
         let project = Config::project_dir()?;
         let dir = project.cache_dir();
-        fs::create_dir_all(&dir)?;
+        fs::create_dir_all(dir)?;
         let sites_filename = dir.join("sites.json");
-        let sites = Self::init_sites(&sites_filename, update).await?;
+        let sites = Self::init_sites(&sites_filename, update, config).await?;
         Ok(LocalStorage { sites })
     }
 
-    // TODO is this HM worth it? Probably only will ever have < 10 site codes to search...
-    // maybe store this as Option<HM> on self if other methods use it...
-    pub async fn find_invalid_site<'a, 'b>(
-        &'b self,
-        site_codes: &'a [String],
-    ) -> Option<&'a String> {
-        let hm: HashMap<&str, ()> = self
+    /// A site listing of `sites`, for library users who know which sites they search, without
+    /// touching the cache or the network
+    ///
+    /// ```
+    /// use so::stackexchange::{LocalStorage, Site};
+    ///
+    /// let ls = LocalStorage::from_sites(vec![Site {
+    ///     api_site_parameter: String::from("unix"),
+    ///     site_url: String::from("unix.stackexchange.com"),
+    ///     ..Site::default()
+    /// }]);
+    /// assert!(ls.find_invalid_sites(&[String::from("unix")]).is_empty());
+    /// ```
+    pub fn from_sites(sites: Vec<Site>) -> Self {
+        LocalStorage { sites }
+    }
+
+    /// Each of `site_codes` that isn't in the site listing, with the sites it may be a typo of
+    pub fn find_invalid_sites(&self, site_codes: &[String]) -> Vec<InvalidSite> {
+        let known: HashSet<&str> = self
+            .sites
+            .iter()
+            .map(|site| site.api_site_parameter.as_str())
+            .collect();
+        let mut seen = HashSet::new();
+        site_codes
+            .iter()
+            .filter(|code| !known.contains(code.as_str()) && seen.insert(code.as_str()))
+            .map(|code| self.invalid_site(code))
+            .collect()
+    }
+
+    /// `code` along with the sites whose code or name is closest to it
+    fn invalid_site(&self, code: &str) -> InvalidSite {
+        let typed = code.to_lowercase();
+        let mut close: Vec<(usize, &str)> = self
+            .sites
+            .iter()
+            .map(|site| {
+                // Names go without their spaces, as in `superuser` for `Super User`
+                let name: String = site.name.to_lowercase().split_whitespace().collect();
+                let by_code = distance(&typed, &site.api_site_parameter.to_lowercase());
+                let by_name = match name.is_empty() {
+                    true => usize::MAX,
+                    false => distance(&typed, &name),
+                };
+                (by_code.min(by_name), site.api_site_parameter.as_str())
+            })
+            .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+            .collect();
+        close.sort_unstable();
+        close.truncate(MAX_SUGGESTIONS);
+        let mut correctable = close
+            .iter()
+            .filter(|(distance, _)| *distance <= MAX_CORRECTION_DISTANCE);
+        let correction = match (correctable.next(), correctable.next()) {
+            (Some((_, code)), None) => Some(code.to_string()),
+            _ => None,
+        };
+        InvalidSite {
+            code: code.to_string(),
+            suggestions: close.iter().map(|(_, code)| code.to_string()).collect(),
+            correction,
+        }
+    }
+
+    /// Sites whose code, url, name or audience has `filter` in it, ignoring case, by code
+    pub fn matching(&self, filter: &str) -> Vec<&Site> {
+        let filter = filter.to_lowercase();
+        let mut sites: Vec<&Site> = self
             .sites
             .iter()
-            .map(|site| (site.api_site_parameter.as_str(), ()))
+            .filter(|site| {
+                [
+                    &site.api_site_parameter,
+                    &site.site_url,
+                    &site.name,
+                    &site.audience,
+                ]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&filter))
+            })
             .collect();
-        site_codes.iter().find(|s| !hm.contains_key(&s.as_str()))
+        sites.sort_by(|a, b| a.api_site_parameter.cmp(&b.api_site_parameter));
+        sites
+    }
+
+    /// Site served at `host`, e.g. `unix.stackexchange.com`
+    pub fn site_by_host(&self, host: &str) -> Option<&Site> {
+        self.sites
+            .iter()
+            .find(|site| host_of(&site.site_url).eq_ignore_ascii_case(host))
+    }
+
+    /// The open site that took over from the site `code` formerly served at `site_url`, which
+    /// was merged into it or renamed to it, as the listing tells by keeping `site_url` among its
+    /// aliases
+    pub fn successor(&self, code: &str, site_url: &str) -> Option<&Site> {
+        let host = host_of(site_url);
+        self.sites
+            .iter()
+            .filter(|site| site.api_site_parameter != code && site.is_open())
+            .find(|site| {
+                site.aliases
+                    .iter()
+                    .any(|alias| host_of(alias).eq_ignore_ascii_case(host))
+            })
     }
 
     pub fn get_urls(&self, site_codes: &[String]) -> HashMap<String, String> {
@@ -74,3 +277,275 @@ impl LocalStorage {
             .collect()
     }
 }
+
+/// `url` without its scheme or a trailing slash, e.g. `unix.stackexchange.com`
+fn host_of(url: &str) -> &str {
+    url.trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+}
+
+fn fallback_sites() -> Vec<Site> {
+    FALLBACK_SITES
+        .iter()
+        .map(|(code, url, name)| Site {
+            api_site_parameter: code.to_string(),
+            site_url: url.to_string(),
+            name: name.to_string(),
+            audience: String::new(),
+            site_state: None,
+            aliases: vec![],
+            closing_date: None,
+        })
+        .collect()
+}
+
+/// `site_codes` with each of `invalid` replaced by its correction, if each has one and `accept`
+/// agrees to it; `None` as soon as one can't be corrected
+pub fn correct_sites<F>(
+    site_codes: &[String],
+    invalid: &[InvalidSite],
+    mut accept: F,
+) -> Result<Option<Vec<String>>>
+where
+    F: FnMut(&InvalidSite, &str) -> Result<bool>,
+{
+    let mut corrected = site_codes.to_vec();
+    for site in invalid {
+        let correction = match &site.correction {
+            Some(correction) if accept(site, correction)? => correction,
+            _ => return Ok(None),
+        };
+        for code in corrected.iter_mut().filter(|code| **code == site.code) {
+            *code = correction.clone();
+        }
+    }
+    // A typo of a site that's searched anyway
+    let mut seen = HashSet::new();
+    corrected.retain(|code| seen.insert(code.clone()));
+    Ok(Some(corrected))
+}
+
+/// Levenshtein distance between `a` and `b`, in characters
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the part of `a` so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_by_host() {
+        let ls = LocalStorage {
+            sites: fallback_sites(),
+        };
+        let site = |host| ls.site_by_host(host).map(|s| s.api_site_parameter.as_str());
+        assert_eq!(site("unix.stackexchange.com"), Some("unix"));
+        assert_eq!(site("StackOverflow.com"), Some("stackoverflow"));
+        assert_eq!(site("stackexchange.com"), None);
+    }
+
+    /// The site listing of `test/stackexchange/sites.json`
+    fn listing() -> LocalStorage {
+        let listing: serde_json::Value =
+            serde_json::from_str(include_str!("../../test/stackexchange/sites.json")).unwrap();
+        LocalStorage {
+            sites: serde_json::from_value(listing["items"].clone()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_successor() {
+        let ls = listing();
+        let successor = |code, url| {
+            ls.successor(code, url)
+                .map(|s| s.api_site_parameter.as_str())
+        };
+        // Renamed
+        assert_eq!(
+            successor("programmers", "programmers.stackexchange.com"),
+            Some("softwareengineering")
+        );
+        assert_eq!(
+            successor("programmers", "https://Programmers.stackexchange.com/"),
+            Some("softwareengineering")
+        );
+        // Never a meta site, one that's shut down itself, or the site itself
+        assert_eq!(
+            successor("meta.programmers", "meta.programmers.stackexchange.com"),
+            None
+        );
+        assert_eq!(successor("wp", "wp.stackexchange.com"), None);
+        assert_eq!(successor("unix", "linux.stackexchange.com"), None);
+        // Shut down without a successor
+        assert_eq!(
+            successor("windowsphone", "windowsphone.stackexchange.com"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_matching() {
+        let ls = listing();
+        let codes = |filter| -> Vec<&str> {
+            ls.matching(filter)
+                .iter()
+                .map(|s| s.api_site_parameter.as_str())
+                .collect()
+        };
+        // By code, url, name or audience, sorted by code
+        assert_eq!(codes("UNIX"), vec!["unix"]);
+        assert_eq!(
+            codes("meta"),
+            vec!["meta.stackoverflow", "softwareengineering.meta"]
+        );
+        assert_eq!(codes("systems development"), vec!["softwareengineering"]);
+        assert_eq!(codes("Meta Stack Overflow"), vec!["meta.stackoverflow"]);
+        assert!(codes("xyzzy").is_empty());
+        let all = codes("");
+        assert_eq!(all.len(), ls.sites.len());
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[tokio::test]
+    async fn test_outdated_listing() {
+        let path = std::env::temp_dir().join(format!("so-sites-{}.json", std::process::id()));
+        // As cached by versions before the schema, without audiences
+        fs::write(
+            &path,
+            r#"[{"api_site_parameter": "unix", "site_url": "unix.stackexchange.com"}]"#,
+        )
+        .unwrap();
+        let cached = LocalStorage::fetch_local_sites(&path).unwrap().unwrap();
+        assert!(!cached.is_current());
+        let offline = Config {
+            offline: true,
+            ..Config::default()
+        };
+        let sites = LocalStorage::init_sites(&path, false, &offline).await;
+        assert_eq!(sites.unwrap()[0].api_site_parameter, "unix");
+        // Still better than nothing when it can't be fetched again
+        let unreachable = Config {
+            api_url: String::from("http://127.0.0.1:9"),
+            ..Config::default()
+        };
+        let sites = LocalStorage::init_sites(&path, false, &unreachable).await;
+        assert_eq!(sites.unwrap()[0].api_site_parameter, "unix");
+
+        LocalStorage::store_local_sites(&path, fallback_sites()).unwrap();
+        let cached = LocalStorage::fetch_local_sites(&path).unwrap().unwrap();
+        assert!(cached.is_current());
+        assert_eq!(cached.into_sites().len(), FALLBACK_SITES.len());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("superuser", "superuser"), 0);
+        assert_eq!(distance("supeuser", "superuser"), 1);
+        assert_eq!(distance("suepruser", "superuser"), 2);
+        assert_eq!(distance("", "tex"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_find_invalid_sites() {
+        let ls = LocalStorage {
+            sites: fallback_sites(),
+        };
+        let codes =
+            |codes: &[&str]| -> Vec<String> { codes.iter().map(|c| c.to_string()).collect() };
+        let invalid =
+            ls.find_invalid_sites(&codes(&["supperuser", "unix", "stackoverflw", "xyzzy"]));
+        // All of them, not just the first
+        assert_eq!(
+            invalid.iter().map(|i| i.code.as_str()).collect::<Vec<_>>(),
+            vec!["supperuser", "stackoverflw", "xyzzy"]
+        );
+        assert_eq!(invalid[0].suggestions, vec!["superuser"]);
+        assert_eq!(invalid[0].correction.as_deref(), Some("superuser"));
+        assert_eq!(
+            invalid[0].to_string(),
+            "supperuser is not a valid StackExchange site; did you mean `superuser`?"
+        );
+        assert_eq!(invalid[1].correction.as_deref(), Some("stackoverflow"));
+        assert_eq!(invalid[2].suggestions, Vec::<String>::new());
+        assert_eq!(invalid[2].correction, None);
+        assert_eq!(
+            invalid[2].to_string(),
+            "xyzzy is not a valid StackExchange site"
+        );
+        assert!(ls.find_invalid_sites(&codes(&["unix", "tex"])).is_empty());
+    }
+
+    #[test]
+    fn test_correct_sites() {
+        let ls = LocalStorage {
+            sites: fallback_sites(),
+        };
+        let codes =
+            |codes: &[&str]| -> Vec<String> { codes.iter().map(|c| c.to_string()).collect() };
+        let sites = codes(&["unix", "supperuser", "uniks", "unix"]);
+        let invalid = ls.find_invalid_sites(&sites);
+        let mut asked = Vec::new();
+        let corrected = correct_sites(&sites, &invalid, |site, correction| {
+            asked.push((site.code.clone(), correction.to_string()));
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(corrected, Some(codes(&["unix", "superuser"])));
+        assert_eq!(
+            asked,
+            vec![
+                (String::from("supperuser"), String::from("superuser")),
+                (String::from("uniks"), String::from("unix")),
+            ]
+        );
+        // Declined
+        assert_eq!(
+            correct_sites(&sites, &invalid, |_, _| Ok(false)).unwrap(),
+            None
+        );
+        // Nothing to go by
+        let sites = codes(&["supperuser", "xyzzy"]);
+        let invalid = ls.find_invalid_sites(&sites);
+        assert_eq!(
+            correct_sites(&sites, &invalid, |_, _| Ok(true)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let ls = LocalStorage {
+            sites: fallback_sites(),
+        };
+        let invalid = |code: &str| ls.invalid_site(code);
+        // By name as well as by code
+        assert_eq!(invalid("Ask Diferent").correction.as_deref(), Some("apple"));
+        // As close to one as to the other
+        let ambiguous = invalid("ux");
+        assert_eq!(ambiguous.suggestions, vec!["tex", "unix"]);
+        assert_eq!(ambiguous.correction, None);
+        assert_eq!(
+            ambiguous.to_string(),
+            "ux is not a valid StackExchange site; did you mean `tex` or `unix`?"
+        );
+        let many = invalid("tx").suggestions;
+        assert_eq!(many.len(), 3);
+        assert_eq!(many[0], "tex");
+    }
+}
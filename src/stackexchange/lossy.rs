@@ -0,0 +1,152 @@
+//! Lenient conversion of the items of API responses.
+//!
+//! Now and then the API returns an item that doesn't have the shape the filter promises, say an
+//! answer of a deleted post without its `body_markdown`, or a null where there's always a number.
+//! Rather than losing every other item of the response to it, items are taken as plain JSON first
+//! and converted one at a time; those that don't convert are left out and recorded, so that
+//! `--verbose` can say which they were. Only a response none of whose items convert is an error.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::error::Result;
+
+/// An item left out of a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skip {
+    /// The request it came with, as labeled in timings
+    pub label: String,
+    /// Id of its question, if it had one to tell
+    pub question_id: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped a malformed item of {}", self.label)?;
+        if let Some(question_id) = self.question_id {
+            write!(f, ", question {}", question_id)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Shared handle used to collect the items left out of concurrently fetched responses
+#[derive(Debug, Clone, Default)]
+pub struct Skipped(Arc<Mutex<Vec<Skip>>>);
+
+impl Skipped {
+    pub fn record(&self, skip: Skip) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).push(skip);
+    }
+
+    /// Everything recorded so far
+    pub fn list(&self) -> Vec<Skip> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Each of `items`, from the response to the request `label`, that converts to a `T`, recording
+/// those that don't in `skipped`. Fails with the first item's error if none of them convert.
+pub fn items<T: DeserializeOwned>(
+    items: Vec<Value>,
+    label: &str,
+    skipped: &Skipped,
+) -> Result<Vec<T>> {
+    let mut converted = Vec::with_capacity(items.len());
+    let mut first_error = None;
+    for item in items {
+        let question_id = question_id(&item);
+        match serde_json::from_value(item) {
+            Ok(item) => converted.push(item),
+            Err(e) => {
+                skipped.record(Skip {
+                    label: label.to_string(),
+                    question_id,
+                    message: e.to_string(),
+                });
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if converted.is_empty() => Err(e.into()),
+        _ => Ok(converted),
+    }
+}
+
+/// `question_id` of `item`, which questions as well as answers have
+fn question_id(item: &Value) -> Option<u32> {
+    let id = item.get("question_id")?.as_u64()?;
+    u32::try_from(id).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        question_id: u32,
+        body_markdown: String,
+    }
+
+    fn values(json: &str) -> Vec<Value> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_corrupt_item_skipped() {
+        let skipped = Skipped::default();
+        let items: Vec<Item> = items(
+            values(
+                r#"[
+                    {"question_id": 1, "body_markdown": "good"},
+                    {"question_id": 2, "body_markdown": null},
+                    {"question_id": 3, "body_markdown": "also good"}
+                ]"#,
+            ),
+            "stackoverflow search",
+            &skipped,
+        )
+        .unwrap();
+        assert_eq!(
+            items.iter().map(|i| i.question_id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        let skips = skipped.list();
+        assert_eq!(skips.len(), 1);
+        assert_eq!(skips[0].question_id, Some(2));
+        assert!(skips[0]
+            .to_string()
+            .starts_with("skipped a malformed item of stackoverflow search, question 2: "));
+    }
+
+    #[test]
+    fn test_all_corrupt() {
+        let skipped = Skipped::default();
+        let result: Result<Vec<Item>> = items(
+            values(r#"[{"question_id": 1}, {"body_markdown": "no id"}]"#),
+            "stackoverflow search",
+            &skipped,
+        );
+        assert!(result.unwrap_err().to_string().contains("body_markdown"));
+        let skips = skipped.list();
+        assert_eq!(skips.len(), 2);
+        // Without an id to tell
+        assert_eq!(skips[1].question_id, None);
+        assert!(!skips[1].to_string().contains(", question"));
+    }
+
+    #[test]
+    fn test_empty() {
+        let skipped = Skipped::default();
+        let items: Vec<Item> = items(vec![], "stackoverflow search", &skipped).unwrap();
+        assert!(items.is_empty());
+        assert!(skipped.list().is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+//! Answers picked for lucky queries, so that repeating a query prints the answer picked last time.
+//!
+//! With `lucky_memory` on, printing an answer picked with `--answer`, or confirming the printed
+//! one at the lucky prompt, remembers it for the query. Queries differing only in letter case,
+//! whitespace or punctuation share an entry, which is kept under a hash of the normalized query so
+//! that the file doesn't spell out past searches. `--forget` drops the entry of a query.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::backup::ImportMode;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::utils;
+
+use super::api::Question;
+use super::query_cache::fnv1a;
+
+/// An answer picked for a query
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Remembered {
+    pub site: String,
+    pub question_id: u32,
+    pub answer_id: u32,
+}
+
+impl Remembered {
+    /// The first answer of `q`, which is the one lucky mode printed
+    pub fn of<S>(q: &Question<S>) -> Option<Self> {
+        let answer = q.answers.first()?;
+        Some(Remembered {
+            site: q.site.clone(),
+            question_id: q.id,
+            answer_id: answer.id,
+        })
+    }
+}
+
+/// Answers picked for queries, by the hash of the normalized query
+#[derive(Debug, Clone)]
+pub struct LuckyMemory {
+    path: PathBuf,
+    map: Arc<Mutex<BTreeMap<String, Remembered>>>,
+}
+
+impl LuckyMemory {
+    /// Answers stored at `path`, which is fine not to exist yet
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let map = match utils::open_file(&path)? {
+            Some(file) => {
+                serde_json::from_reader(file).map_err(|_| Error::MalformedFile(path.clone()))?
+            }
+            None => BTreeMap::new(),
+        };
+        Ok(LuckyMemory {
+            path,
+            map: Arc::new(Mutex::new(map)),
+        })
+    }
+
+    /// Where answers are kept, in the project's data directory
+    pub fn path() -> Result<PathBuf> {
+        Ok(Config::project_dir()?.data_dir().join("lucky_memory.json"))
+    }
+
+    /// Answers in the project's data directory
+    pub fn open() -> Result<Self> {
+        Self::load(Self::path()?)
+    }
+
+    /// Answer picked for `query`, if any
+    pub fn get(&self, query: &str) -> Option<Remembered> {
+        self.lock().get(&key(query)).cloned()
+    }
+
+    /// Remember `answer` for `query`, in place of whatever was picked before
+    pub fn remember(&self, query: &str, answer: Remembered) -> Result<()> {
+        let mut map = self.lock();
+        map.insert(key(query), answer);
+        self.save(&map)
+    }
+
+    /// Drop the answer picked for `query`. Returns whether there was one.
+    pub fn forget(&self, query: &str) -> Result<bool> {
+        let mut map = self.lock();
+        if map.remove(&key(query)).is_none() {
+            return Ok(false);
+        }
+        self.save(&map)?;
+        Ok(true)
+    }
+
+    /// All picked answers, by the hash of their normalized query
+    pub fn entries(&self) -> BTreeMap<String, Remembered> {
+        self.lock().clone()
+    }
+
+    /// Combine answers picked elsewhere, `imported`, with these. When merging, the local pick
+    /// wins for a query picked in both places.
+    pub fn merge(&self, imported: BTreeMap<String, Remembered>, mode: ImportMode) -> Result<()> {
+        let mut map = self.lock();
+        match mode {
+            ImportMode::Merge => {
+                for (key, answer) in imported {
+                    map.entry(key).or_insert(answer);
+                }
+            }
+            ImportMode::Replace => *map = imported,
+        }
+        self.save(&map)
+    }
+
+    fn save(&self, map: &BTreeMap<String, Remembered>) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        utils::write_atomic(&self.path, &serde_json::to_vec_pretty(map)?)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Remembered>> {
+        self.map.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// `query` in lower case, with its words separated by single spaces and punctuation left out,
+/// e.g. `tar extract flags` for `Tar: extract  flags?`
+pub fn normalize(query: &str) -> String {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What answers for `query` are stored under
+fn key(query: &str) -> String {
+    format!("{:016x}", fnv1a(normalize(query).as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("so-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("lucky_memory.json")
+    }
+
+    fn remembered(answer_id: u32) -> Remembered {
+        Remembered {
+            site: String::from("unix"),
+            question_id: 1,
+            answer_id,
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("Tar: extract  flags?"), "tar extract flags");
+        assert_eq!(normalize("  tar extract flags "), "tar extract flags");
+        assert_eq!(normalize("c++ std::vector"), "c std vector");
+        assert_eq!(normalize("Überprüfen ü"), "überprüfen ü");
+        assert_eq!(normalize("?!"), "");
+        assert_eq!(key("Tar extract flags"), key("tar, extract flags"));
+        assert_ne!(key("tar extract flags"), key("tar extract"));
+    }
+
+    #[test]
+    fn test_remember_and_forget() {
+        let path = temp_path("lucky-memory");
+        let memory = LuckyMemory::load(path.clone()).unwrap();
+        assert_eq!(memory.get("tar extract flags"), None);
+        memory
+            .remember("tar extract flags", remembered(10))
+            .unwrap();
+        assert_eq!(memory.get("Tar extract flags?"), Some(remembered(10)));
+        // The latest pick wins
+        memory
+            .remember("tar extract flags", remembered(11))
+            .unwrap();
+        assert_eq!(memory.get("tar extract flags"), Some(remembered(11)));
+
+        // Kept on disk, without the query
+        let stored = fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains("tar"));
+        let memory = LuckyMemory::load(path.clone()).unwrap();
+        assert_eq!(memory.get("tar extract flags"), Some(remembered(11)));
+
+        assert!(memory.forget("TAR extract flags").unwrap());
+        assert!(!memory.forget("tar extract flags").unwrap());
+        assert_eq!(memory.get("tar extract flags"), None);
+        let memory = LuckyMemory::load(path).unwrap();
+        assert_eq!(memory.get("tar extract flags"), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let imported: BTreeMap<_, _> = vec![
+            (key("tar extract flags"), remembered(20)),
+            (key("exit vim"), remembered(21)),
+        ]
+        .into_iter()
+        .collect();
+
+        let memory = LuckyMemory::load(temp_path("lucky-memory-merge")).unwrap();
+        memory
+            .remember("tar extract flags", remembered(10))
+            .unwrap();
+        memory.merge(imported.clone(), ImportMode::Merge).unwrap();
+        assert_eq!(memory.get("tar extract flags"), Some(remembered(10)));
+        assert_eq!(memory.get("exit vim"), Some(remembered(21)));
+
+        let memory = LuckyMemory::load(temp_path("lucky-memory-replace")).unwrap();
+        memory.remember("list files", remembered(10)).unwrap();
+        memory.merge(imported.clone(), ImportMode::Replace).unwrap();
+        assert_eq!(memory.get("list files"), None);
+        assert_eq!(memory.entries(), imported);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let path = temp_path("lucky-memory-malformed");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            LuckyMemory::load(path),
+            Err(Error::MalformedFile(_))
+        ));
+    }
+}
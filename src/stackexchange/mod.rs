@@ -1,9 +1,50 @@
+pub mod aliases;
+pub mod analysis;
+pub mod answer_sort;
 mod api;
+pub mod backoff;
+pub mod blocks;
+pub mod code_search;
+pub mod commands;
+pub mod context;
+pub mod duplicates;
+pub mod filtered;
+pub mod filters;
+pub mod full_text;
+pub mod images;
+pub mod isolation;
+pub mod latency;
+pub mod link_rewrites;
 mod local_storage;
+pub mod lossy;
+pub mod lucky_memory;
+mod network;
+pub mod normalize;
+pub mod preprocess;
+pub mod query_cache;
+pub mod question_cache;
+pub mod quota;
+pub mod relevance;
+pub mod sanitize;
 mod search;
+pub mod snippets;
+pub mod summary;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod timings;
+pub mod transform;
+pub mod urls;
+mod versioned;
 // Exposed for benchmarking
 pub mod scraper;
 
-pub use api::{Answer, Question};
-pub use local_storage::LocalStorage;
-pub use search::Search;
+pub use api::{
+    parse_api_url, valid_api_version, Answer, Api, CodeMatch, Comment, Owner, Provenance, Question,
+    Request, SE_API_URL, SE_API_VERSION,
+};
+// Named by library users building a site listing of their own
+#[allow(unused_imports)]
+pub use api::Site;
+pub use local_storage::{correct_sites, InvalidSite, LocalStorage};
+pub use network::{Destination, ExtraHeaders, OfflinePolicy, Timeouts};
+pub use search::{lucky_exit_code, Search};
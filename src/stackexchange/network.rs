@@ -0,0 +1,345 @@
+//! The one place HTTP clients get built.
+//!
+//! In offline mode no client is built at all, so every code path that would touch the network
+//! gets an `Error::Offline` back instead of sending a request. Online, clients send the
+//! `extra_headers` from the config along with their own, e.g. for a proxy that wants a token, and
+//! give up on requests after `timeout_secs`, or on connecting after `connect_timeout_secs`.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Whether network access is allowed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OfflinePolicy {
+    Online,
+    /// Serve everything from the local cache, never touching the network
+    Offline,
+}
+
+impl From<&Config> for OfflinePolicy {
+    fn from(config: &Config) -> Self {
+        if config.offline {
+            OfflinePolicy::Offline
+        } else {
+            OfflinePolicy::Online
+        }
+    }
+}
+
+/// How long requests may take before they're given up on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    /// For a whole request, from connecting until the response is read
+    pub request: Duration,
+    /// For connecting
+    pub connect: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            request: Duration::from_secs(10),
+            connect: Duration::from_secs(5),
+        }
+    }
+}
+
+impl From<&Config> for Timeouts {
+    fn from(config: &Config) -> Self {
+        Timeouts {
+            request: Duration::from_secs(config.timeout_secs),
+            connect: Duration::from_secs(config.connect_timeout_secs),
+        }
+    }
+}
+
+/// Where a request goes, as far as `extra_headers` are concerned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Destination {
+    DuckDuckGo,
+    Google,
+    /// The API, for searches and the site listing alike
+    StackExchange,
+    /// Images linked from posts, which can be hosted anywhere
+    Images,
+}
+
+/// Headers sent with every request, with those under a destination taking precedence for
+/// requests to it. Values are never shown, since they tend to be tokens.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ExtraHeaders {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub duckduckgo: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub google: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub stackexchange: BTreeMap<String, String>,
+    /// Headers for every destination
+    #[serde(flatten)]
+    pub all: BTreeMap<String, String>,
+}
+
+impl ExtraHeaders {
+    /// Headers to send to `destination`, failing on names or values that aren't legal
+    pub fn for_destination(&self, destination: Destination) -> Result<HeaderMap> {
+        let overrides = match destination {
+            Destination::DuckDuckGo => Some(&self.duckduckgo),
+            Destination::Google => Some(&self.google),
+            Destination::StackExchange => Some(&self.stackexchange),
+            Destination::Images => None,
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.all.iter().chain(overrides.into_iter().flatten()) {
+            let (name, value) = header(name, value)?;
+            headers.insert(name, value);
+        }
+        Ok(headers)
+    }
+
+    /// Check that every header is legal, wherever it goes
+    pub fn validate(&self) -> Result<()> {
+        for destination in &[
+            Destination::DuckDuckGo,
+            Destination::Google,
+            Destination::StackExchange,
+        ] {
+            self.for_destination(*destination)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.all.is_empty()
+            && self.duckduckgo.is_empty()
+            && self.google.is_empty()
+            && self.stackexchange.is_empty()
+    }
+}
+
+/// Names only, with where they're sent
+impl fmt::Display for ExtraHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = [
+            ("all", &self.all),
+            ("duckduckgo", &self.duckduckgo),
+            ("google", &self.google),
+            ("stackexchange", &self.stackexchange),
+        ];
+        let mut first = true;
+        for (destination, headers) in groups.iter() {
+            for name in headers.keys() {
+                if !first {
+                    f.write_str(", ")?;
+                }
+                first = false;
+                write!(f, "{}: {} ({})", name, REDACTED, destination)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ExtraHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ExtraHeaders({})", self)
+    }
+}
+
+/// What header values are shown as
+const REDACTED: &str = "<redacted>";
+
+/// Parse a header from the config, marking its value sensitive so that it's left out of debug
+/// output
+fn header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue)> {
+    let invalid = |reason: &str| Error::ExtraHeader(name.to_string(), reason.to_string());
+    let parsed_name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid("bad name"))?;
+    let mut parsed_value = HeaderValue::from_str(value)
+        .map_err(|_| invalid("bad value; only visible ASCII, spaces and tabs are allowed"))?;
+    parsed_value.set_sensitive(true);
+    Ok((parsed_name, parsed_value))
+}
+
+/// HTTP client subject to an `OfflinePolicy`
+#[derive(Clone, Debug)]
+pub struct Network {
+    client: Option<Client>,
+    /// Headers the client sends with every request
+    headers: HeaderMap,
+    timeouts: Timeouts,
+}
+
+impl Network {
+    pub fn new(policy: OfflinePolicy, headers: HeaderMap) -> Self {
+        let timeouts = Timeouts::default();
+        let client = match policy {
+            OfflinePolicy::Online => Some(Self::build(headers.clone(), timeouts)),
+            OfflinePolicy::Offline => None,
+        };
+        Network {
+            client,
+            headers,
+            timeouts,
+        }
+    }
+
+    /// The same client, also sending `extra` headers, which replace its own of the same name
+    pub fn with_extra_headers(self, extra: HeaderMap) -> Self {
+        let mut headers = self.headers;
+        headers.extend(extra);
+        let timeouts = self.timeouts;
+        let client = self.client.map(|_| Self::build(headers.clone(), timeouts));
+        Network {
+            client,
+            headers,
+            ..self
+        }
+    }
+
+    /// The same client, giving up on requests after `timeouts`
+    pub fn with_timeouts(self, timeouts: Timeouts) -> Self {
+        let headers = &self.headers;
+        let client = self
+            .client
+            .as_ref()
+            .map(|_| Self::build(headers.clone(), timeouts));
+        Network {
+            client,
+            timeouts,
+            ..self
+        }
+    }
+
+    fn build(headers: HeaderMap, timeouts: Timeouts) -> Client {
+        Client::builder()
+            .default_headers(headers)
+            .timeout(timeouts.request)
+            .connect_timeout(timeouts.connect)
+            .build()
+            .unwrap()
+    }
+
+    /// Client to send requests with, unless running offline
+    pub fn client(&self) -> Result<&Client> {
+        self.client.as_ref().ok_or(Error::Offline)
+    }
+
+    /// `e`, which a request to `to` failed with, saying which timeout to raise if it timed out
+    pub fn failed(&self, to: &str, e: reqwest::Error) -> Error {
+        if !e.is_timeout() {
+            return e.into();
+        }
+        let (after, setting) = match e.is_connect() {
+            true => (self.timeouts.connect, "connect_timeout_secs"),
+            false => (self.timeouts.request, "timeout_secs"),
+        };
+        Error::RequestTimeout {
+            to: to.to_string(),
+            after,
+            setting,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_has_no_client() {
+        let network = Network::new(OfflinePolicy::Offline, HeaderMap::new());
+        assert!(matches!(network.client(), Err(Error::Offline)));
+        let network = Network::new(OfflinePolicy::Online, HeaderMap::new());
+        assert!(network.client().is_ok());
+    }
+
+    fn extra_headers() -> ExtraHeaders {
+        serde_yaml::from_str(
+            "X-Proxy-Token: s3cret\n\
+             Accept-Language: de\n\
+             duckduckgo:\n  \
+               Accept-Language: en-US\n\
+             stackexchange:\n  \
+               X-Proxy-Token: other\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let extra = extra_headers();
+        let ddg = extra.for_destination(Destination::DuckDuckGo).unwrap();
+        assert_eq!(ddg["accept-language"], "en-US");
+        assert_eq!(ddg["x-proxy-token"], "s3cret");
+        let se = extra.for_destination(Destination::StackExchange).unwrap();
+        assert_eq!(se["accept-language"], "de");
+        assert_eq!(se["x-proxy-token"], "other");
+        let images = extra.for_destination(Destination::Images).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images["x-proxy-token"], "s3cret");
+        assert!(ExtraHeaders::default()
+            .for_destination(Destination::Google)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(extra_headers().validate().is_ok());
+        let mut extra = ExtraHeaders::default();
+        extra
+            .all
+            .insert(String::from("Bad Name"), String::from("x"));
+        assert!(matches!(
+            extra.validate(),
+            Err(Error::ExtraHeader(name, _)) if name == "Bad Name"
+        ));
+        let mut extra = ExtraHeaders::default();
+        extra
+            .google
+            .insert(String::from("X-Token"), String::from("line\nbreak"));
+        assert!(matches!(
+            extra.validate(),
+            Err(Error::ExtraHeader(name, _)) if name == "X-Token"
+        ));
+    }
+
+    #[test]
+    fn test_redacted() {
+        let extra = extra_headers();
+        let shown = format!("{} {:?}", extra, extra);
+        assert!(!shown.contains("s3cret") && !shown.contains("other"));
+        assert_eq!(
+            extra.to_string(),
+            "Accept-Language: <redacted> (all), X-Proxy-Token: <redacted> (all), \
+             Accept-Language: <redacted> (duckduckgo), X-Proxy-Token: <redacted> (stackexchange)"
+        );
+        let headers = extra.for_destination(Destination::Images).unwrap();
+        assert!(!format!("{:?}", headers).contains("s3cret"));
+        // Nothing to write to a fresh config
+        assert_eq!(
+            serde_yaml::to_string(&ExtraHeaders::default()).unwrap(),
+            "---\n{}\n"
+        );
+    }
+
+    #[test]
+    fn test_extra_headers_replace_own() {
+        let mut own = HeaderMap::new();
+        own.insert("accept", HeaderValue::from_static("application/json"));
+        own.insert("x-own", HeaderValue::from_static("kept"));
+        let mut extra = HeaderMap::new();
+        extra.insert("accept", HeaderValue::from_static("text/html"));
+        let network = Network::new(OfflinePolicy::Offline, own).with_extra_headers(extra);
+        assert_eq!(network.headers["accept"], "text/html");
+        assert_eq!(network.headers["x-own"], "kept");
+        assert!(network.client().is_err());
+    }
+}
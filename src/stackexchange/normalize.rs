@@ -0,0 +1,282 @@
+//! Tidies up queries before they're searched for, with `normalize_query`.
+//!
+//! Text copied from web pages and chat comes with curly quotes, dashes and zero-width spaces that
+//! search engines take literally, and fast typing comes with the same few typos over and over.
+//! Punctuation is folded to its ASCII look-alike throughout the query, so that a phrase in curly
+//! quotes is a quoted phrase too. Outside of quoted phrases, runs of whitespace collapse into one
+//! space and words in `TYPOS`, or in the `typos` config option, are corrected. Quoted phrases are
+//! otherwise searched for exactly as given, typos and all.
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Common typos in developer queries, and what they're meant to be. Keys are lowercase.
+pub const TYPOS: &[(&str, &str)] = &[
+    ("arguement", "argument"),
+    ("asyncronous", "asynchronous"),
+    ("charachter", "character"),
+    ("compatable", "compatible"),
+    ("cosnt", "const"),
+    ("databse", "database"),
+    ("defualt", "default"),
+    ("dictonary", "dictionary"),
+    ("dokcer", "docker"),
+    ("enviroment", "environment"),
+    ("eror", "error"),
+    ("excpetion", "exception"),
+    ("fucntion", "function"),
+    ("functon", "function"),
+    ("funtion", "function"),
+    ("gihtub", "github"),
+    ("improt", "import"),
+    ("intger", "integer"),
+    ("javasript", "javascript"),
+    ("javscript", "javascript"),
+    ("kubernets", "kubernetes"),
+    ("lamda", "lambda"),
+    ("lenght", "length"),
+    ("libary", "library"),
+    ("occured", "occurred"),
+    ("paramater", "parameter"),
+    ("paramter", "parameter"),
+    ("pyhton", "python"),
+    ("pyton", "python"),
+    ("pytohn", "python"),
+    ("recieve", "receive"),
+    ("reponse", "response"),
+    ("reqeust", "request"),
+    ("retrun", "return"),
+    ("seperate", "separate"),
+    ("strign", "string"),
+    ("syncronous", "synchronous"),
+    ("udpate", "update"),
+    ("varaible", "variable"),
+];
+
+/// A query as it's searched for, and the typos corrected on the way
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalized {
+    pub query: String,
+    /// Each word corrected, as given, and what it was corrected to, in the order they came up
+    pub corrections: Vec<(String, String)>,
+}
+
+/// Corrects queries with the shipped typos and the user's own
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    typos: HashMap<String, String>,
+}
+
+impl Normalizer {
+    /// The shipped typos along with `extra`, which take precedence; mapping a word to itself
+    /// keeps it from being corrected
+    pub fn new(extra: &BTreeMap<String, String>) -> Self {
+        let mut typos: HashMap<String, String> = TYPOS
+            .iter()
+            .map(|(typo, word)| (typo.to_string(), word.to_string()))
+            .collect();
+        for (typo, word) in extra {
+            typos.insert(typo.to_lowercase(), word.clone());
+        }
+        Normalizer { typos }
+    }
+
+    /// `query` tidied up, see the module docs
+    pub fn normalize(&self, query: &str) -> Normalized {
+        let folded = fold(query);
+        let mut corrections = Vec::new();
+        // Every other piece between double quotes is a quoted phrase, as is whatever follows a
+        // quote that isn't closed
+        let pieces: Vec<&str> = folded.split('"').collect();
+        let mut normalized = String::with_capacity(folded.len());
+        for (i, piece) in pieces.iter().enumerate() {
+            if i > 0 {
+                normalized.push('"');
+            }
+            if i % 2 == 0 {
+                normalized.push_str(&self.correct(piece, &mut corrections));
+            } else {
+                normalized.push_str(piece);
+            }
+        }
+        let mut query = normalized.trim_start().to_string();
+        if pieces.len() % 2 == 1 {
+            query.truncate(query.trim_end().len());
+        }
+        Normalized { query, corrections }
+    }
+
+    /// `text` with its whitespace collapsed and its typos corrected, each added to `corrections`
+    fn correct(&self, text: &str, corrections: &mut Vec<(String, String)>) -> String {
+        let mut corrected = String::with_capacity(text.len());
+        let mut words = text.split_whitespace().peekable();
+        if text.starts_with(char::is_whitespace) {
+            corrected.push(' ');
+        }
+        while let Some(word) = words.next() {
+            // Punctuation around a word, e.g. a question mark, stays where it is
+            let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+            match self.typos.get(&core.to_lowercase()) {
+                Some(fix) if fix != core => {
+                    let fix = in_case_of(core, fix);
+                    let at = word.find(core).unwrap_or_default();
+                    corrected.push_str(&word[..at]);
+                    corrected.push_str(&fix);
+                    corrected.push_str(&word[at + core.len()..]);
+                    let correction = (core.to_string(), fix);
+                    if !corrections.contains(&correction) {
+                        corrections.push(correction);
+                    }
+                }
+                _ => corrected.push_str(word),
+            }
+            if words.peek().is_some() {
+                corrected.push(' ');
+            }
+        }
+        if text.ends_with(char::is_whitespace) && !text.trim().is_empty() {
+            corrected.push(' ');
+        }
+        corrected
+    }
+}
+
+/// `fix` capitalized like `word` is: all lowercase, all uppercase or just the first letter
+fn in_case_of(word: &str, fix: &str) -> String {
+    let mut chars = word.chars();
+    let first_upper = chars.next().is_some_and(char::is_uppercase);
+    let rest_upper = chars.clone().any(char::is_uppercase);
+    let rest_lower = chars.any(char::is_lowercase);
+    match (first_upper, rest_upper, rest_lower) {
+        (true, true, false) => fix.to_uppercase(),
+        (true, false, _) => {
+            let mut fix_chars = fix.chars();
+            fix_chars
+                .next()
+                .map(|c| c.to_uppercase().chain(fix_chars).collect())
+                .unwrap_or_default()
+        }
+        _ => fix.to_string(),
+    }
+}
+
+/// `text` with typographic quotes, dashes and ellipses swapped for ASCII, and zero-width
+/// characters left out
+fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => folded.push('\''),
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' | '\u{ab}'
+            | '\u{bb}' => folded.push('"'),
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => folded.push('-'),
+            '\u{2026}' => folded.push_str("..."),
+            '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => {}
+            c => folded.push(c),
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(query: &str) -> Normalized {
+        Normalizer::new(&BTreeMap::new()).normalize(query)
+    }
+
+    #[test]
+    fn test_typos() {
+        let normalized = normalize("pyhton list lenght");
+        assert_eq!(normalized.query, "python list length");
+        assert_eq!(
+            normalized.corrections,
+            vec![
+                (String::from("pyhton"), String::from("python")),
+                (String::from("lenght"), String::from("length")),
+            ]
+        );
+
+        // Punctuation around words stays, and each typo is listed once
+        let normalized = normalize("(pyhton) or pyhton?");
+        assert_eq!(normalized.query, "(python) or python?");
+        assert_eq!(normalized.corrections.len(), 1);
+
+        // Capitalized like the typo was
+        assert_eq!(normalize("Pyhton").query, "Python");
+        assert_eq!(normalize("PYHTON").query, "PYTHON");
+        assert_eq!(normalize("pyHton").query, "python");
+
+        // Words that merely contain a typo are left alone
+        assert_eq!(normalize("pyhtonic").query, "pyhtonic");
+        assert!(normalize("how to reverse a list").corrections.is_empty());
+    }
+
+    #[test]
+    fn test_user_typos() {
+        let mut extra = BTreeMap::new();
+        extra.insert(String::from("Rsut"), String::from("rust"));
+        extra.insert(String::from("lenght"), String::from("len"));
+        extra.insert(String::from("pyhton"), String::from("pyhton"));
+        let normalizer = Normalizer::new(&extra);
+
+        // Added, keys matched whatever their case
+        assert_eq!(normalizer.normalize("rsut vec").query, "rust vec");
+        // Overriding a shipped one
+        assert_eq!(normalizer.normalize("vec lenght").query, "vec len");
+        // Turning a shipped one off
+        let normalized = normalizer.normalize("pyhton recieve");
+        assert_eq!(normalized.query, "pyhton receive");
+        assert_eq!(
+            normalized.corrections,
+            vec![(String::from("recieve"), String::from("receive"))]
+        );
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(
+            normalize("don\u{2019}t \u{201c}exit vim\u{201d}").query,
+            "don't \"exit vim\""
+        );
+        assert_eq!(normalize("foo \u{2014} bar\u{2026}").query, "foo - bar...");
+        assert_eq!(normalize("git\u{200b} re\u{feff}base").query, "git rebase");
+        assert!(normalize("don\u{2019}t").corrections.is_empty());
+    }
+
+    #[test]
+    fn test_whitespace() {
+        assert_eq!(
+            normalize("  exit \t vim\u{a0}\n now  ").query,
+            "exit vim now"
+        );
+        assert_eq!(normalize("exit  \"vim\"  now").query, "exit \"vim\" now");
+        assert_eq!(normalize("").query, "");
+        assert_eq!(normalize("   ").query, "");
+    }
+
+    #[test]
+    fn test_quoted_phrases_untouched() {
+        let normalized = normalize("pyhton  \"lenght  of pyhton \" recieve");
+        assert_eq!(normalized.query, "python \"lenght  of pyhton \" receive");
+        assert_eq!(
+            normalized.corrections,
+            vec![
+                (String::from("pyhton"), String::from("python")),
+                (String::from("recieve"), String::from("receive")),
+            ]
+        );
+
+        // Curly quotes quote too
+        assert_eq!(
+            normalize("\u{201c}recieve  it\u{201d}").query,
+            "\"recieve  it\""
+        );
+
+        // So does a quote that isn't closed, up to the end
+        assert_eq!(
+            normalize("pyhton \"lenght  of ").query,
+            "python \"lenght  of "
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Preprocessing of the markdown the StackExchange API returns, bringing it closer to what
+//! CommonMark parsers expect before it's shown or printed.
+
+use pulldown_cmark::{Event, Tag};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// A step of converting SE markdown to something closer to cmark
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    /// Drop leading and trailing whitespace
+    Trim,
+    /// Render `<kbd>` keys as bold and bracketed
+    Kbd,
+    /// Drop HTML comments, but for language hints
+    Comments,
+    /// Decode HTML entities such as `&lt;`, but in fenced code blocks
+    Entities,
+}
+
+/// Passes run unless configured otherwise, in order
+pub const DEFAULT_PASSES: &[Pass] = &[Pass::Trim, Pass::Kbd, Pass::Comments, Pass::Entities];
+
+/// Name standing in for an empty list of passes, since an empty list in the config is more
+/// likely a mistake than a deliberate choice
+pub const NO_PASSES: &str = "none";
+
+impl Pass {
+    pub const ALL: &'static [Pass] = &[Pass::Trim, Pass::Kbd, Pass::Comments, Pass::Entities];
+
+    /// Name of the pass in `preprocess_passes`
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Trim => "trim",
+            Pass::Kbd => "kbd",
+            Pass::Comments => "comments",
+            Pass::Entities => "entities",
+        }
+    }
+
+    /// Passes named by `names`, or the first name that isn't one. `["none"]` is no passes.
+    pub fn parse_list<S: AsRef<str>>(names: &[S]) -> Result<Vec<Pass>, String> {
+        match names {
+            [name] if name.as_ref() == NO_PASSES => Ok(Vec::new()),
+            _ => names
+                .iter()
+                .map(|name| {
+                    Pass::ALL
+                        .iter()
+                        .copied()
+                        .find(|pass| pass.name() == name.as_ref())
+                        .ok_or_else(|| name.as_ref().to_string())
+                })
+                .collect(),
+        }
+    }
+
+    /// Run the pass, borrowing `input` if there's nothing to change
+    pub fn apply(self, input: &str) -> Cow<'_, str> {
+        match self {
+            Pass::Trim => Cow::Borrowed(input.trim()),
+            Pass::Kbd => kbd(input),
+            Pass::Comments => strip_comments(input),
+            Pass::Entities => decode_entities(input),
+        }
+    }
+}
+
+fn kbd(input: &str) -> Cow<'_, str> {
+    if input.contains("<kbd>") || input.contains("</kbd>") {
+        Cow::Owned(input.replace("<kbd>", "**[").replace("</kbd>", "]**"))
+    } else {
+        Cow::Borrowed(input)
+    }
+}
+
+/// Drop the HTML comments in `input` outside of code, e.g. editorial notes, along with their
+/// lines if they're alone on them. Language hints (`<!-- language: lang-rust -->`) stay, since
+/// they say how to highlight the code that follows rather than being meant for readers.
+fn strip_comments(input: &str) -> Cow<'_, str> {
+    if !input.contains("<!--") {
+        return Cow::Borrowed(input);
+    }
+    let code = code_ranges(input);
+    let mut stripped = String::with_capacity(input.len());
+    // End of what's been copied to `stripped` so far, and of what's been searched
+    let (mut copied, mut searched) = (0, 0);
+    while let Some(found) = input[searched..].find("<!--") {
+        let start = searched + found;
+        let end = match input[start..].find("-->") {
+            Some(end) => start + end + "-->".len(),
+            // Unterminated, so not a comment
+            None => break,
+        };
+        if code.iter().any(|range| range.contains(&start)) || is_language_hint(&input[start..end]) {
+            searched = start + "<!--".len();
+            continue;
+        }
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1).max(copied);
+        let line_end = input[end..].find('\n').map_or(input.len(), |i| end + i + 1);
+        let alone =
+            input[line_start..start].trim().is_empty() && input[end..line_end].trim().is_empty();
+        let (from, to) = if alone {
+            (line_start, line_end)
+        } else {
+            (start, end)
+        };
+        stripped.push_str(&input[copied..from]);
+        copied = to;
+        searched = to;
+    }
+    if copied == 0 {
+        return Cow::Borrowed(input);
+    }
+    stripped.push_str(&input[copied..]);
+    Cow::Owned(stripped)
+}
+
+/// Named entities decoded, beyond the numeric ones; the few that show up in posts rather than
+/// all of HTML's
+const ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("copy", '\u{a9}'),
+    ("reg", '\u{ae}'),
+    ("trade", '\u{2122}'),
+    ("deg", '\u{b0}'),
+    ("plusmn", '\u{b1}'),
+    ("times", '\u{d7}'),
+    ("divide", '\u{f7}'),
+    ("middot", '\u{b7}'),
+    ("sect", '\u{a7}'),
+    ("para", '\u{b6}'),
+    ("euro", '\u{20ac}'),
+    ("pound", '\u{a3}'),
+    ("yen", '\u{a5}'),
+    ("cent", '\u{a2}'),
+    ("hellip", '\u{2026}'),
+    ("ndash", '\u{2013}'),
+    ("mdash", '\u{2014}'),
+    ("lsquo", '\u{2018}'),
+    ("rsquo", '\u{2019}'),
+    ("ldquo", '\u{201c}'),
+    ("rdquo", '\u{201d}'),
+    ("laquo", '\u{ab}'),
+    ("raquo", '\u{bb}'),
+    ("bull", '\u{2022}'),
+    ("larr", '\u{2190}'),
+    ("rarr", '\u{2192}'),
+    ("uarr", '\u{2191}'),
+    ("darr", '\u{2193}'),
+    ("harr", '\u{2194}'),
+    ("le", '\u{2264}'),
+    ("ge", '\u{2265}'),
+    ("ne", '\u{2260}'),
+    ("infin", '\u{221e}'),
+];
+
+/// Longest entity name, `#x` and the digits of a hex one included, and its `;`
+const MAX_ENTITY: usize = 9;
+
+/// Decode the HTML entities in `input`, named, decimal (`&#60;`) and hex (`&#x3C;`), each exactly
+/// once: `&amp;lt;` is `&lt;` as written. Fenced code blocks are left as they are, since the API
+/// doesn't encode their contents. In text, an `&` that would read as the start of another entity
+/// once decoded stays encoded, as markdown renderers decode entities in text themselves.
+fn decode_entities(input: &str) -> Cow<'_, str> {
+    if !input.contains('&') {
+        return Cow::Borrowed(input);
+    }
+    let code = code_ranges(input);
+    let mut decoded = String::with_capacity(input.len());
+    // End of what's been copied to `decoded` so far, and of what's been searched
+    let (mut copied, mut searched) = (0, 0);
+    while let Some(found) = input[searched..].find('&') {
+        let start = searched + found;
+        searched = start + 1;
+        let in_code = code.iter().find(|range| range.contains(&start));
+        if let Some(range) = in_code.filter(|range| is_fenced(&input[(*range).clone()])) {
+            searched = range.end;
+            continue;
+        }
+        let (c, len) = match entity(&input[searched..]) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let end = searched + len;
+        if c == '&' && in_code.is_none() && entity(&input[end..]).is_some() {
+            searched = end;
+            continue;
+        }
+        decoded.push_str(&input[copied..start]);
+        decoded.push(c);
+        copied = end;
+        searched = end;
+    }
+    if copied == 0 {
+        return Cow::Borrowed(input);
+    }
+    decoded.push_str(&input[copied..]);
+    Cow::Owned(decoded)
+}
+
+/// Character of the entity at the start of `input`, following its `&`, and the length of the
+/// entity up to and including its `;`
+fn entity(input: &str) -> Option<(char, usize)> {
+    // Entities are short, so there's no need to look far for the end of one
+    let end = input
+        .char_indices()
+        .take(MAX_ENTITY)
+        .find(|(_, c)| *c == ';')?
+        .0;
+    let name = &input[..end];
+    let c = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        if hex.is_empty() || hex.len() > 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+    } else if let Some(decimal) = name.strip_prefix('#') {
+        if decimal.is_empty() || decimal.len() > 7 || !decimal.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        char::from_u32(decimal.parse().ok()?)?
+    } else {
+        ENTITIES
+            .iter()
+            .find(|(entity, _)| *entity == name)
+            .map(|(_, c)| *c)?
+    };
+    // NUL isn't a character anyone meant
+    (c != '\0').then(|| (c, end + 1))
+}
+
+/// Whether the code block `block` is fenced, rather than indented
+fn is_fenced(block: &str) -> bool {
+    let block = block.trim_start();
+    block.starts_with("```") || block.starts_with("~~~")
+}
+
+fn is_language_hint(comment: &str) -> bool {
+    language_hint(comment).is_some()
+}
+
+/// Language a hint such as `<!-- language: lang-rust -->` names, and whether it's for all the
+/// code blocks that follow (`language-all:`) rather than just the next one
+pub fn language_hint(comment: &str) -> Option<(&str, bool)> {
+    let hint = comment
+        .trim()
+        .trim_start_matches("<!--")
+        .trim_end_matches("-->")
+        .trim();
+    if let Some(language) = hint.strip_prefix("language:") {
+        return Some((language.trim(), false));
+    }
+    hint.strip_prefix("language-all:")
+        .map(|language| (language.trim(), true))
+}
+
+/// Byte ranges of the code blocks and code spans in `input`, which are left as they are
+pub fn code_ranges(input: &str) -> Vec<Range<usize>> {
+    pulldown_cmark::Parser::new(input)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Byte ranges of the code blocks in `input`
+pub fn code_block_ranges(input: &str) -> Vec<Range<usize>> {
+    pulldown_cmark::Parser::new(input)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Convert SE markdown with `passes`, in order. Bodies without anything to convert are borrowed
+/// rather than copied, which is most of them.
+pub fn preprocess_with<'a>(input: &'a str, passes: &[Pass]) -> Cow<'a, str> {
+    passes
+        .iter()
+        .fold(Cow::Borrowed(input), |body, pass| match body {
+            Cow::Borrowed(body) => pass.apply(body),
+            Cow::Owned(body) => match pass.apply(&body) {
+                Cow::Owned(processed) => Cow::Owned(processed),
+                // Nothing changed, so there's no need for a copy
+                Cow::Borrowed(processed) if processed.len() == body.len() => Cow::Owned(body),
+                Cow::Borrowed(processed) => Cow::Owned(processed.to_string()),
+            },
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(input: &str) -> Cow<'_, str> {
+        preprocess_with(input, DEFAULT_PASSES)
+    }
+
+    #[test]
+    fn test_trim_pass() {
+        assert_eq!(Pass::Trim.apply("\n\t body \r\n"), "body");
+        assert!(matches!(Pass::Trim.apply("body"), Cow::Borrowed("body")));
+    }
+
+    #[test]
+    fn test_kbd_pass() {
+        assert_eq!(
+            Pass::Kbd.apply("<kbd>Ctrl</kbd>+<kbd>C</kbd>"),
+            "**[Ctrl]**+**[C]**"
+        );
+        assert_eq!(Pass::Kbd.apply(" stray </kbd> "), " stray ]** ");
+        assert!(matches!(
+            Pass::Kbd.apply(" `kbd` "),
+            Cow::Borrowed(" `kbd` ")
+        ));
+    }
+
+    #[test]
+    fn test_comments_pass() {
+        assert_eq!(
+            Pass::Comments.apply(include_str!("../../test/markdown/comments.md")),
+            include_str!("../../test/markdown/comments.stripped.md")
+        );
+        assert!(matches!(
+            Pass::Comments.apply("<!-- language: lang-rust -->"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_entities_pass() {
+        let input = include_str!("../../test/markdown/entities.md");
+        let decoded = Pass::Entities.apply(input);
+        assert_eq!(
+            decoded,
+            include_str!("../../test/markdown/entities.decoded.md")
+        );
+        assert!(matches!(Pass::Entities.apply("a & b; c"), Cow::Borrowed(_)));
+        assert_eq!(Pass::Entities.apply("&#X3c;&#60;&lt;"), "<<<");
+    }
+
+    #[test]
+    fn test_preprocess_with() {
+        let input = "  <kbd>Esc</kbd>  ";
+        assert_eq!(preprocess_with(input, DEFAULT_PASSES), "**[Esc]**");
+        assert_eq!(preprocess_with(input, &[Pass::Kbd]), "  **[Esc]**  ");
+        assert_eq!(
+            preprocess_with(input, &[Pass::Kbd, Pass::Trim]),
+            "**[Esc]**"
+        );
+        assert!(matches!(preprocess_with(input, &[]), Cow::Borrowed(i) if i == input));
+    }
+
+    #[test]
+    fn test_parse_passes() {
+        assert_eq!(Pass::parse_list(&["none"]), Ok(vec![]));
+        assert_eq!(
+            Pass::parse_list(&["kbd", "trim"]),
+            Ok(vec![Pass::Kbd, Pass::Trim])
+        );
+        let names: Vec<&str> = DEFAULT_PASSES.iter().map(|p| p.name()).collect();
+        assert_eq!(Pass::parse_list(&names).unwrap(), DEFAULT_PASSES);
+        assert_eq!(
+            Pass::parse_list(&["trim", "smart-punctuation"]),
+            Err(String::from("smart-punctuation"))
+        );
+        // "none" only stands for no passes on its own
+        assert_eq!(
+            Pass::parse_list(&["trim", "none"]),
+            Err(String::from("none"))
+        );
+    }
+
+    /// The default passes, locked to their output on a corpus of bodies
+    #[test]
+    fn test_preprocess_snapshot() {
+        const SEPARATOR: &str = "\n---8<---\n";
+        let corpus = include_str!("../../test/markdown/corpus.md").split(SEPARATOR);
+        let snapshot = include_str!("../../test/markdown/corpus.preprocessed.md").split(SEPARATOR);
+        let mut count = 0;
+        for (body, expected) in corpus.zip(snapshot) {
+            assert_eq!(preprocess(body), expected, "{:?}", body);
+            count += 1;
+        }
+        assert_eq!(count, 12);
+    }
+}
@@ -0,0 +1,433 @@
+//! Results of past searches, kept on disk so that `--offline` can serve them later, and so that
+//! repeating a search within `cache_ttl_minutes` doesn't wait on the network again.
+//!
+//! Each search is stored in its own file, named after a hash of the `Key` it was made with.
+//! Entries record when they were fetched, and offline results are always shown alongside that
+//! date, so stale data is never passed off as fresh. The questions in them are indexed by
+//! `full_text`, for `--search`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::preprocess;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::format::Locale;
+use crate::utils;
+
+use super::analysis::Language;
+use super::api::Question;
+use super::full_text::{self, Hit, Index};
+use super::relevance;
+
+/// Everything that determines the results of a search
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Key {
+    pub engine: String,
+    pub team: Option<String>,
+    pub sites: Vec<String>,
+    pub limit: u16,
+    pub code_search: bool,
+    /// Left out when unset, so keys of searches predating it keep their file names
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_unanswered: bool,
+    /// Preprocessing passes, left out when they're the default ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preprocess_passes: Option<Vec<String>>,
+    /// Tag added by `context_detection`, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub query: String,
+}
+
+impl Key {
+    /// Key of the search `config` would run for `query`; queries differing only in letter case
+    /// or whitespace share a key
+    pub fn new(config: &Config, query: &str) -> Self {
+        let mut sites = config.sites.clone();
+        sites.sort();
+        Key {
+            engine: config.search_engine.to_string(),
+            team: config.team.clone(),
+            sites,
+            limit: config.limit,
+            code_search: config.code_search,
+            include_unanswered: config.include_unanswered,
+            preprocess_passes: match config.passes() {
+                passes if passes == preprocess::DEFAULT_PASSES => None,
+                passes => Some(passes.iter().map(|pass| pass.name().to_string()).collect()),
+            },
+            tag: None,
+            query: normalize(query),
+        }
+    }
+
+    /// The same key for a search with `tag` added from the project it was run in
+    pub fn with_tag(self, tag: Option<&str>) -> Self {
+        Key {
+            tag: tag.map(String::from),
+            ..self
+        }
+    }
+
+    fn file_name(&self) -> String {
+        // serializing a plain struct can't fail
+        let key = serde_json::to_string(self).unwrap();
+        format!("{:016x}.json", fnv1a(key.as_bytes()))
+    }
+}
+
+/// A cached search
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Entry {
+    pub key: Key,
+    /// Unix timestamp of when the results were fetched
+    pub fetched_at: i64,
+    #[serde(with = "super::versioned")]
+    pub questions: Vec<Question<String>>,
+}
+
+/// Just enough of an `Entry` to find the closest cached query
+#[derive(Deserialize)]
+struct EntryKey {
+    key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+    dir: PathBuf,
+}
+
+impl QueryCache {
+    pub fn new(dir: PathBuf) -> Self {
+        QueryCache { dir }
+    }
+
+    /// Cache in the project's cache directory
+    pub fn open() -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("queries")))
+    }
+
+    pub fn get(&self, key: &Key) -> Result<Option<Entry>> {
+        let filename = self.dir.join(key.file_name());
+        match utils::open_file(&filename)? {
+            Some(file) => {
+                let value: serde_json::Value = serde_json::from_reader(file)
+                    .map_err(|_| Error::MalformedFile(filename.clone()))?;
+                let entry: Entry = match serde_json::from_value(value) {
+                    Ok(entry) => entry,
+                    // Written by a version of `so` whose questions can't be read anymore, so it's
+                    // as good as missing
+                    Err(_) => {
+                        fs::remove_file(&filename)?;
+                        return Ok(None);
+                    }
+                };
+                // Guard against hash collisions
+                Ok(Some(entry).filter(|e| e.key == *key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The results of `key`, if they were fetched less than `ttl` ago
+    pub fn fresh(&self, key: &Key, ttl: Duration) -> Result<Option<Entry>> {
+        Ok(self
+            .get(key)?
+            .filter(|entry| (now().saturating_sub(entry.fetched_at).max(0) as u64) < ttl.as_secs()))
+    }
+
+    /// Forget every search, and their index. Returns how many searches there were.
+    pub fn clear(&self) -> Result<usize> {
+        let searches = cached_searches(&self.dir)?;
+        for path in &searches {
+            fs::remove_file(path)?;
+        }
+        match fs::remove_file(self.dir.join(full_text::FILE)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Ok(searches.len())
+    }
+
+    /// Store `questions` as the results of `key`, fetched just now
+    pub fn put(&self, key: &Key, questions: &[Question<String>]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let file_name = key.file_name();
+        let file = utils::create_file(&self.dir.join(&file_name))?;
+        serde_json::to_writer(
+            file,
+            &Entry {
+                key: key.clone(),
+                fetched_at: now(),
+                questions: questions.to_vec(),
+            },
+        )?;
+        // Searching the index catches up on whatever it misses here
+        let _ = full_text::update(&self.dir, &file_name, questions);
+        Ok(())
+    }
+
+    /// The cached questions best matching `query`, by what they say, whichever search they came
+    /// up in; at most `limit` of them, best first
+    pub fn search_text(&self, query: &str, language: Language, limit: usize) -> Result<Vec<Hit>> {
+        full_text::search(&self.dir, query, language, limit)
+    }
+
+    /// Index every cached search anew, in `language`
+    pub fn reindex(&self, language: Language) -> Result<Index> {
+        full_text::rebuild(&self.dir, language)
+    }
+
+    /// The cached query most similar to `query`, if any share a meaningful term with it
+    pub fn closest(&self, query: &str, language: Language) -> Option<String> {
+        cached_searches(&self.dir)
+            .ok()?
+            .into_iter()
+            .filter_map(|path| {
+                let file = fs::File::open(path).ok()?;
+                let entry: EntryKey = serde_json::from_reader(file).ok()?;
+                Some(entry.key.query)
+            })
+            .map(|cached| (relevance::score(query, &cached, language), cached))
+            .filter(|(score, _)| *score > 0.0)
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .map(|(_, cached)| cached)
+    }
+}
+
+/// Files of the searches cached in `dir`, none if there's no `dir`
+pub(super) fn cached_searches(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut searches = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json")
+            && path.file_name().is_some_and(|name| name != full_text::FILE)
+        {
+            searches.push(path);
+        }
+    }
+    Ok(searches)
+}
+
+/// Indicator shown wherever offline results are displayed
+pub fn offline_banner(fetched_at: i64, locale: Locale) -> String {
+    format!("OFFLINE (data from {})", locale.date(fetched_at))
+}
+
+fn normalize(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across Rust releases
+pub(super) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::{versioned, Answer};
+
+    /// Empty cache in a fresh temporary directory
+    fn temp_cache(name: &str) -> QueryCache {
+        let dir = std::env::temp_dir().join(format!("so-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        QueryCache::new(dir)
+    }
+
+    fn question(id: u32, title: &str) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            answers: vec![Answer {
+                id: id * 10,
+                score: 1,
+                body: String::from("Hit `Esc`, then type `:q`"),
+                is_accepted: true,
+                ..Answer::default()
+            }],
+            title: title.to_string(),
+            body: Some(String::from("I am stuck")),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let cache = temp_cache("roundtrip");
+        let key = Key::new(&Config::default(), "How do I   exit Vim");
+        assert!(cache.get(&key).unwrap().is_none());
+
+        cache
+            .put(&key, &[question(1, "How do I exit Vim?")])
+            .unwrap();
+        let entry = cache
+            .get(&Key::new(&Config::default(), "how do i exit vim"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.questions[0].title, "How do I exit Vim?");
+        assert_eq!(
+            entry.questions[0].answers[0].body,
+            "Hit `Esc`, then type `:q`"
+        );
+        assert!(entry.fetched_at > 0);
+
+        let other_sites = Config {
+            sites: vec![String::from("superuser")],
+            ..Config::default()
+        };
+        assert!(cache
+            .get(&Key::new(&other_sites, "how do i exit vim"))
+            .unwrap()
+            .is_none());
+
+        // Bodies are cached preprocessed, so other passes don't share entries
+        let raw = Config {
+            preprocess_passes: vec![String::from("none")],
+            ..Config::default()
+        };
+        assert!(cache
+            .get(&Key::new(&raw, "how do i exit vim"))
+            .unwrap()
+            .is_none());
+        let default_by_name = Config {
+            preprocess_passes: vec![
+                String::from("trim"),
+                String::from("kbd"),
+                String::from("comments"),
+                String::from("entities"),
+            ],
+            ..Config::default()
+        };
+        assert!(cache
+            .get(&Key::new(&default_by_name, "how do i exit vim"))
+            .unwrap()
+            .is_some());
+
+        // Neither are searches with a tag from the project they were run in
+        let tagged = Key::new(&Config::default(), "how do i exit vim").with_tag(Some("rust"));
+        assert!(cache.get(&tagged).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_fresh_and_expired() {
+        let cache = temp_cache("fresh");
+        let key = Key::new(&Config::default(), "how do i exit vim");
+        let ttl = Duration::from_secs(10 * 60);
+        assert!(cache.fresh(&key, ttl).unwrap().is_none());
+        cache
+            .put(&key, &[question(1, "How do I exit Vim?")])
+            .unwrap();
+        assert!(cache.fresh(&key, ttl).unwrap().is_some());
+        assert!(cache.fresh(&key, Duration::from_secs(0)).unwrap().is_none());
+
+        // Fetched an hour ago
+        let filename = cache.dir.join(key.file_name());
+        let mut entry: serde_json::Value =
+            serde_json::from_slice(&fs::read(&filename).unwrap()).unwrap();
+        entry["fetched_at"] = serde_json::json!(now() - 60 * 60);
+        fs::write(&filename, entry.to_string()).unwrap();
+        assert!(cache.fresh(&key, ttl).unwrap().is_none());
+        // Still there for offline searches
+        assert!(cache.get(&key).unwrap().is_some());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_clear() {
+        let cache = temp_cache("clear");
+        assert_eq!(cache.clear().unwrap(), 0);
+        for query in ["how do i exit vim", "how do i quit emacs"] {
+            cache
+                .put(&Key::new(&Config::default(), query), &[question(1, query)])
+                .unwrap();
+        }
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert!(cache
+            .get(&Key::new(&Config::default(), "how do i exit vim"))
+            .unwrap()
+            .is_none());
+        assert_eq!(cache.clear().unwrap(), 0);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_version() {
+        let cache = temp_cache("unreadable-version");
+        let key = Key::new(&Config::default(), "how do i exit vim");
+        cache
+            .put(&key, &[question(1, "How do I exit Vim?")])
+            .unwrap();
+        let filename = cache.dir.join(key.file_name());
+        let mut entry: serde_json::Value =
+            serde_json::from_slice(&fs::read(&filename).unwrap()).unwrap();
+        assert_eq!(entry["questions"]["v"], versioned::VERSION);
+
+        // Questions from before the envelope still load
+        entry["questions"] = entry["questions"]["data"].take();
+        fs::write(&filename, entry.to_string()).unwrap();
+        let cached = cache.get(&key).unwrap().unwrap();
+        assert_eq!(cached.questions[0].title, "How do I exit Vim?");
+
+        // Ones that can't be read anymore are a miss, and cleaned up
+        entry["questions"] = serde_json::json!({"v": 0, "data": []});
+        fs::write(&filename, entry.to_string()).unwrap();
+        assert!(cache.get(&key).unwrap().is_none());
+        assert!(!filename.exists());
+
+        // Unlike a file that isn't JSON at all
+        fs::write(&filename, "{").unwrap();
+        assert!(matches!(cache.get(&key), Err(Error::MalformedFile(_))));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_closest() {
+        let cache = temp_cache("closest");
+        assert_eq!(cache.closest("exit vim", Language::English), None);
+        for q in &["how do I exit vim", "undo git commit", "rust lifetimes"] {
+            cache.put(&Key::new(&Config::default(), q), &[]).unwrap();
+        }
+        assert_eq!(
+            cache.closest("exiting vim quickly", Language::English),
+            Some(String::from("how do i exit vim"))
+        );
+        assert_eq!(
+            cache.closest("git undo", Language::English),
+            Some(String::from("undo git commit"))
+        );
+        assert_eq!(cache.closest("haskell monads", Language::English), None);
+    }
+
+    #[test]
+    fn test_offline_banner() {
+        assert_eq!(
+            offline_banner(1_792_152_000, Locale::Iso),
+            "OFFLINE (data from 2026-10-16)"
+        );
+    }
+}
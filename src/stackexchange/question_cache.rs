@@ -0,0 +1,246 @@
+//! Questions (with their answers) cached by site and id, so that different queries leading to
+//! the same popular questions only fetch them once.
+//!
+//! Entries expire after a TTL. The cache is kept under a size cap by evicting the least recently
+//! used entries, going by file modification times, which reads bump.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::utils;
+
+use super::api::Question;
+
+/// How long cached questions are used before being fetched again
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Size the cache is trimmed down to after each write
+const MAX_SIZE: u64 = 50 * 1024 * 1024;
+
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    /// Unix timestamp of when the question was fetched
+    fetched_at: u64,
+    #[serde(with = "super::versioned")]
+    question: Question<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuestionCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_size: u64,
+    /// Skip reads (but keep writing), so that everything gets fetched fresh
+    refresh: bool,
+}
+
+impl QuestionCache {
+    pub fn new(dir: PathBuf) -> Self {
+        QuestionCache {
+            dir,
+            ttl: TTL,
+            max_size: MAX_SIZE,
+            refresh: false,
+        }
+    }
+
+    /// Cache in the project's cache directory
+    pub fn open() -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("questions")))
+    }
+
+    /// Ignore cached questions, replacing them with whatever gets fetched instead
+    pub fn refresh(self, refresh: bool) -> Self {
+        QuestionCache { refresh, ..self }
+    }
+
+    /// Question `id` on `site`, if cached within the TTL. `site` is any name unique to the site,
+    /// e.g. its API parameter.
+    pub fn get(&self, site: &str, id: &str) -> Option<Question<String>> {
+        if self.refresh {
+            return None;
+        }
+        let filename = self.path(site, id);
+        // An unreadable entry, e.g. from a version of `so` whose questions can't be read anymore,
+        // is as good as a missing one, and goes until it's refetched
+        let file = utils::open_file(&filename).ok()??;
+        let entry: Entry = match serde_json::from_reader(&file) {
+            Ok(entry) => entry,
+            Err(_) => {
+                let _ = fs::remove_file(&filename);
+                return None;
+            }
+        };
+        if now().saturating_sub(entry.fetched_at) >= self.ttl.as_secs() {
+            return None;
+        }
+        let _ = file.set_modified(SystemTime::now());
+        Some(entry.question)
+    }
+
+    /// Store `questions` fetched from `site` just now, then evict entries over the size cap
+    pub fn put(&self, site: &str, questions: &[Question<String>]) -> Result<()> {
+        if questions.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(self.dir.join(site))?;
+        let fetched_at = now();
+        for q in questions {
+            let file = utils::create_file(&self.path(site, &q.id.to_string()))?;
+            serde_json::to_writer(
+                file,
+                &Entry {
+                    fetched_at,
+                    question: q.clone(),
+                },
+            )?;
+        }
+        self.evict()
+    }
+
+    /// Remove the least recently used entries until the cache fits in `max_size`
+    fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for site in fs::read_dir(&self.dir)? {
+            for entry in fs::read_dir(site?.path())? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_unstable();
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            size -= len;
+        }
+        Ok(())
+    }
+
+    fn path(&self, site: &str, id: &str) -> PathBuf {
+        self.dir.join(site).join(format!("{}.json", id))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::Answer;
+
+    fn temp_cache(name: &str) -> QuestionCache {
+        let dir = std::env::temp_dir().join(format!("so-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        QuestionCache::new(dir)
+    }
+
+    fn question(id: u32) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            answers: vec![Answer {
+                id: id * 10,
+                score: 1,
+                body: String::from("Hit `Esc`, then type `:q`"),
+                is_accepted: true,
+                ..Answer::default()
+            }],
+            title: format!("Question {}", id),
+            body: Some(String::from("I am stuck")),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let cache = temp_cache("questions-roundtrip");
+        assert!(cache.get("stackoverflow", "1").is_none());
+        cache
+            .put("stackoverflow", &[question(1), question(2)])
+            .unwrap();
+        assert_eq!(cache.get("stackoverflow", "1").unwrap().title, "Question 1");
+        assert_eq!(cache.get("stackoverflow", "2").unwrap().answers[0].id, 20);
+        assert!(cache.get("superuser", "1").is_none());
+        assert!(cache.refresh(true).get("stackoverflow", "1").is_none());
+    }
+
+    #[test]
+    fn test_versions() {
+        let cache = temp_cache("questions-versions");
+        cache.put("stackoverflow", &[question(1)]).unwrap();
+        let path = cache.path("stackoverflow", "1");
+        let mut entry: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(entry["question"]["data"]["question_id"], 1);
+
+        // From before the envelope
+        entry["question"] = entry["question"]["data"].take();
+        fs::write(&path, entry.to_string()).unwrap();
+        assert_eq!(cache.get("stackoverflow", "1").unwrap().title, "Question 1");
+
+        // From a newer `so`
+        entry["question"] = serde_json::json!({"v": 99, "data": {}});
+        fs::write(&path, entry.to_string()).unwrap();
+        assert!(cache.get("stackoverflow", "1").is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let cache = QuestionCache {
+            ttl: Duration::from_secs(0),
+            ..temp_cache("questions-ttl")
+        };
+        cache.put("stackoverflow", &[question(1)]).unwrap();
+        assert!(cache.get("stackoverflow", "1").is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = temp_cache("questions-evict");
+        cache.put("stackoverflow", &[question(1)]).unwrap();
+        let entry_size = fs::metadata(cache.path("stackoverflow", "1"))
+            .unwrap()
+            .len();
+        // Room for three entries
+        let cache = QuestionCache {
+            max_size: entry_size * 3,
+            ..cache
+        };
+        let pause = || std::thread::sleep(Duration::from_millis(20));
+
+        pause();
+        cache.put("stackoverflow", &[question(2)]).unwrap();
+        pause();
+        cache.put("superuser", &[question(3)]).unwrap();
+        pause();
+        // Reading 1 makes 2 the least recently used
+        assert!(cache.get("stackoverflow", "1").is_some());
+        pause();
+        cache.put("stackoverflow", &[question(4)]).unwrap();
+
+        assert!(cache.get("stackoverflow", "2").is_none());
+        for (site, id) in &[
+            ("stackoverflow", "1"),
+            ("superuser", "3"),
+            ("stackoverflow", "4"),
+        ] {
+            assert!(cache.get(site, id).is_some());
+        }
+    }
+}
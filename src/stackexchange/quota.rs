@@ -0,0 +1,143 @@
+//! How many requests the StackExchange API still allows today.
+//!
+//! Every response says how much of the daily quota is left, which is 300 requests per IP without
+//! an API key. Once it runs out, requests fail in ways that look a lot like no results, so the
+//! lowest reading of an invocation is kept to warn about before that happens, and written to the
+//! cache directory with when it was taken. As with backoffs, the file is only a record: failing to
+//! write it never fails a request.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::utils;
+
+/// What a response said about the quota
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub remaining: u32,
+    pub max: u32,
+    /// When the response came, in unix milliseconds
+    pub at: i64,
+}
+
+impl Reading {
+    /// Whether fewer than `threshold` requests are left
+    pub fn is_low(&self, threshold: u32) -> bool {
+        self.remaining < threshold
+    }
+}
+
+/// Lowest reading so far, shared by every clone
+#[derive(Debug, Clone, Default)]
+pub struct Quota {
+    /// Where the lowest reading is written, if it outlives the invocation
+    path: Option<PathBuf>,
+    lowest: Arc<Mutex<Option<Reading>>>,
+}
+
+impl Quota {
+    /// Readings kept in `path`
+    pub fn new(path: PathBuf) -> Self {
+        Quota {
+            path: Some(path),
+            lowest: Arc::default(),
+        }
+    }
+
+    /// Readings kept in the project's cache directory
+    pub fn open() -> Result<Self> {
+        let project = Config::project_dir()?;
+        Ok(Self::new(project.cache_dir().join("quota.json")))
+    }
+
+    /// Record that a response at `now` said `remaining` of `max` requests are left
+    pub fn record(&self, remaining: u32, max: u32, now: i64) {
+        let mut lowest = self.lowest.lock().unwrap_or_else(|e| e.into_inner());
+        if lowest.is_some_and(|reading| reading.remaining <= remaining) {
+            return;
+        }
+        let reading = Reading {
+            remaining,
+            max,
+            at: now,
+        };
+        *lowest = Some(reading);
+        if let Some(path) = &self.path {
+            let _ = save(path, &reading);
+        }
+    }
+
+    /// Lowest reading of this invocation, if any response said
+    pub fn lowest(&self) -> Option<Reading> {
+        *self.lowest.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Lowest reading of the last invocation that made a request, if it was kept
+    pub fn last(&self) -> Option<Reading> {
+        let bytes = fs::read(self.path.as_ref()?).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+fn save(path: &PathBuf, reading: &Reading) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    utils::write_atomic(path, &serde_json::to_vec(reading)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-16 14:00 UTC, in milliseconds
+    const T: i64 = 1_792_159_200_000;
+
+    #[test]
+    fn test_lowest() {
+        let quota = Quota::default();
+        assert_eq!(quota.lowest(), None);
+        quota.record(120, 300, T);
+        quota.record(119, 300, T + 1000);
+        // Concurrent requests can come back out of order
+        quota.clone().record(121, 300, T + 2000);
+        assert_eq!(
+            quota.lowest(),
+            Some(Reading {
+                remaining: 119,
+                max: 300,
+                at: T + 1000
+            })
+        );
+        assert!(quota.lowest().unwrap().is_low(120));
+        assert!(!quota.lowest().unwrap().is_low(119));
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = std::env::temp_dir().join(format!("so-quota-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("quota.json");
+        let quota = Quota::new(path.clone());
+        assert_eq!(quota.last(), None);
+        quota.record(40, 300, T);
+        quota.record(45, 300, T + 1000);
+
+        // As in the next invocation, which hasn't made a request yet
+        let next = Quota::new(path.clone());
+        assert_eq!(next.lowest(), None);
+        assert_eq!(
+            next.last().map(|reading| (reading.remaining, reading.at)),
+            Some((40, T))
+        );
+
+        // Garbage is as good as nothing
+        fs::write(&path, b"{\"remaining\": 5").unwrap();
+        assert_eq!(next.last(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
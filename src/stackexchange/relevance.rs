@@ -0,0 +1,191 @@
+//! Cheap relevance heuristics between a search query and the questions it returned.
+//!
+//! None of this is meant to compete with the search engines' own ranking; it only
+//! needs to be good enough to notice when the top hit obviously has nothing to do
+//! with the query.
+
+use super::analysis::{self, Language};
+
+/// Score how well `title` matches `query`, from `0.0` (no overlap) to `1.0` (every meaningful
+/// query term appears in the title).
+///
+/// Both sides are broken down into terms in `language` before comparing. A query with no
+/// meaningful terms at all can't be judged, so it scores `1.0`.
+pub fn score(query: &str, title: &str, language: Language) -> f64 {
+    let query_terms = analysis::terms(query, language);
+    if query_terms.is_empty() {
+        return 1.0;
+    }
+    let title_terms = analysis::terms(title, language);
+    let hits = query_terms
+        .iter()
+        .filter(|t| title_terms.contains(*t))
+        .count();
+    hits as f64 / query_terms.len() as f64
+}
+
+/// Mean `score` of `titles` against `query`, or `0.0` without any titles
+pub fn aggregate<'a, I>(query: &str, titles: I, language: Language) -> f64
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let (sum, count) = titles.into_iter().fold((0.0, 0), |(sum, count), title| {
+        (sum + score(query, title, language), count + 1)
+    });
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+/// `query` as an exact phrase, if that's any different: it has several meaningful terms and
+/// isn't quoted already
+pub fn quoted(query: &str, language: Language) -> Option<String> {
+    if query.contains('"') || analysis::terms(query, language).len() < 2 {
+        None
+    } else {
+        Some(format!("\"{}\"", query.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Language::{English, German, Spanish};
+
+    #[test]
+    fn test_score_matches() {
+        assert_eq!(
+            score(
+                "how do I exit vim",
+                "How do I exit the Vim editor?",
+                English
+            ),
+            1.0
+        );
+        assert_eq!(
+            score("exiting vim", "How to exit Vim after editing", English),
+            1.0
+        );
+        assert!(
+            score(
+                "reverse a list in python",
+                "How do I reverse a list?",
+                English
+            ) > 0.5
+        );
+    }
+
+    #[test]
+    fn test_score_mismatches() {
+        assert_eq!(
+            score(
+                "how do I exit vim",
+                "Sorting a dictionary by value in Python",
+                English
+            ),
+            0.0
+        );
+        assert!(
+            score(
+                "rust borrow checker lifetimes",
+                "What is a lifetime in C#?",
+                English
+            ) < 0.5
+        );
+    }
+
+    /// Scores of English queries as they were before terms depended on the query language
+    #[test]
+    fn test_score_english_unchanged() {
+        let scores = [
+            ("how do I exit vim", "How do I exit the Vim editor?", 1.0),
+            ("exiting vim", "How to exit Vim after editing", 1.0),
+            (
+                "reverse a list in python",
+                "How do I reverse a list?",
+                2.0 / 3.0,
+            ),
+            (
+                "rust borrow checker lifetimes",
+                "What is a lifetime in C#?",
+                0.25,
+            ),
+            (
+                "undo git commits",
+                "How do I undo the most recent local commits in Git?",
+                1.0,
+            ),
+            (
+                "sort dictionary by values",
+                "How do I sort a dictionary by value?",
+                1.0,
+            ),
+            ("c++ queries classes", "C++ query class templates", 1.0),
+            ("using used uses", "Use of uses", 0.5),
+            (
+                "git push --force-with-lease",
+                "Force pushing a branch safely with --force-with-lease",
+                0.75,
+            ),
+            ("café résumé", "Cafe resume", 0.0),
+        ];
+        for (query, title, expected) in scores.iter() {
+            assert_eq!(score(query, title, English), *expected, "{}", query);
+        }
+        assert_eq!(Language::default(), English);
+    }
+
+    #[test]
+    fn test_score_languages() {
+        let query = "Wie kann ich Dateien in einem Ordner zählen";
+        let title = "Anzahl der Dateien im Ordner zählen";
+        assert_eq!(score(query, title, German), 1.0);
+        // English stop words and stemming leave half the German query unmatched
+        assert!(score(query, title, English) < 0.5);
+
+        let query = "cómo ordenar listas";
+        let title = "Ordenando una lista en Python";
+        assert_eq!(score(query, title, Spanish), 1.0);
+        assert_eq!(score(query, title, Language::None), 0.0);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let titles = [
+            "How do I exit the Vim editor?",
+            "Sorting a dictionary by value",
+        ];
+        assert_eq!(aggregate("exit vim", titles.iter().copied(), English), 0.5);
+        assert_eq!(
+            aggregate("exit vim", titles[..1].iter().copied(), English),
+            1.0
+        );
+        assert_eq!(aggregate("exit vim", std::iter::empty(), English), 0.0);
+    }
+
+    #[test]
+    fn test_quoted() {
+        assert_eq!(
+            quoted(" borrow checker lifetimes ", English),
+            Some(String::from("\"borrow checker lifetimes\""))
+        );
+        assert_eq!(quoted("\"borrow checker\" lifetimes", English), None);
+        // A single meaningful term has no phrase to keep together
+        assert_eq!(quoted("how do I rebase", English), None);
+        assert_eq!(quoted("", English), None);
+        // Unless nothing counts as a stop word
+        assert_eq!(
+            quoted("how do I rebase", Language::None),
+            Some(String::from("\"how do I rebase\""))
+        );
+        assert_eq!(quoted("¿cómo hacer rebase?", Spanish), None);
+    }
+
+    #[test]
+    fn test_score_stop_words_only() {
+        assert_eq!(score("how do I", "Anything at all", English), 1.0);
+        assert_eq!(score("", "Anything at all", English), 1.0);
+    }
+}
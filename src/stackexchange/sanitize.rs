@@ -0,0 +1,187 @@
+//! Strips terminal control sequences from posts before anything prints them.
+//!
+//! Titles and bodies come from whoever posted them, so a post could carry escape sequences that
+//! retitle the terminal, move the cursor or worse once printed by `--lucky` or `--output`, or
+//! written to a file. Every control character goes, along with the whole sequence it introduces,
+//! except for newlines and tabs.
+
+use std::borrow::Cow;
+
+use super::api::{Owner, Question};
+
+const ESC: char = '\u{1b}';
+const BEL: char = '\u{7}';
+/// String terminator, the 8-bit form of `ESC \`
+const ST: char = '\u{9c}';
+
+/// What a control character introduces
+enum Sequence {
+    /// Nothing, it stands alone
+    Single,
+    /// Control Sequence Introducer: parameters, intermediates, and a final byte
+    Csi,
+    /// OSC, DCS, SOS, PM or APC: anything up to a string terminator
+    String,
+}
+
+/// `input` without control characters and the sequences they introduce, other than `\n` and
+/// `\t`. Borrowed when there's nothing to strip.
+pub fn sanitize(input: &str) -> Cow<'_, str> {
+    if !input.chars().any(is_stripped) {
+        return Cow::Borrowed(input);
+    }
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_stripped(c) {
+            output.push(c);
+            continue;
+        }
+        let sequence = match c {
+            ESC => match chars.peek() {
+                Some('[') => {
+                    chars.next();
+                    Sequence::Csi
+                }
+                Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => {
+                    chars.next();
+                    Sequence::String
+                }
+                // Other escapes, e.g. `ESC c` to reset the terminal or `ESC ( B` to pick a
+                // character set: intermediates, then a final byte
+                Some(_) => {
+                    while let Some(' '..='/') = chars.peek() {
+                        chars.next();
+                    }
+                    if let Some('0'..='~') = chars.peek() {
+                        chars.next();
+                    }
+                    Sequence::Single
+                }
+                None => Sequence::Single,
+            },
+            '\u{9b}' => Sequence::Csi,
+            '\u{90}' | '\u{98}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => Sequence::String,
+            _ => Sequence::Single,
+        };
+        match sequence {
+            Sequence::Single => (),
+            Sequence::Csi => {
+                while let Some('0'..='?') | Some(' '..='/') = chars.peek() {
+                    chars.next();
+                }
+                if let Some('@'..='~') = chars.peek() {
+                    chars.next();
+                }
+            }
+            // An unterminated string runs to the end, as it would in the terminal
+            Sequence::String => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        BEL | ST => break,
+                        ESC if chars.peek() == Some(&'\\') => {
+                            chars.next();
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+    Cow::Owned(output)
+}
+
+/// Control characters (C0, DEL and C1), other than newlines and tabs
+fn is_stripped(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+/// `q` with its title, tags, all bodies and the names of their authors sanitized
+pub fn question(q: Question<String>) -> Question<String> {
+    let owned = |s: String| match sanitize(&s) {
+        Cow::Borrowed(_) => s,
+        Cow::Owned(sanitized) => sanitized,
+    };
+    let owner = |owner: Owner| Owner {
+        display_name: owner.display_name.map(owned),
+        ..owner
+    };
+    Question {
+        title: owned(q.title),
+        tags: q.tags.into_iter().map(owned).collect(),
+        body: q.body.map(owned),
+        owner: q.owner.map(owner),
+        answers: q
+            .answers
+            .into_iter()
+            .map(|mut a| {
+                a.body = owned(a.body);
+                a.owner = a.owner.map(owner);
+                a
+            })
+            .collect(),
+        ..q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_input_borrowed() {
+        let input = "Run `ls -l`\n\n\tthen **quit** \u{2014} caf\u{e9} \u{1f980}";
+        assert!(matches!(sanitize(input), Cow::Borrowed(s) if s == input));
+    }
+
+    #[test]
+    fn test_csi() {
+        assert_eq!(sanitize("\u{1b}[31mred\u{1b}[0m text"), "red text");
+        assert_eq!(sanitize("a\u{1b}[2Jb\u{1b}[10;20Hc"), "abc");
+        assert_eq!(sanitize("\u{1b}[?1049h\u{1b}[ qalt"), "alt");
+        // 8-bit CSI
+        assert_eq!(sanitize("\u{9b}1;31mred"), "red");
+    }
+
+    #[test]
+    fn test_osc() {
+        // Retitling the terminal, terminated by BEL or ST
+        assert_eq!(sanitize("\u{1b}]0;pwned\u{7}after"), "after");
+        assert_eq!(sanitize("\u{1b}]2;pwned\u{1b}\\after"), "after");
+        // Hyperlinks keep their text, not their target
+        assert_eq!(
+            sanitize("\u{1b}]8;;https://evil.example\u{1b}\\click\u{1b}]8;;\u{1b}\\"),
+            "click"
+        );
+        assert_eq!(sanitize("\u{9d}0;pwned\u{9c}after"), "after");
+        // Unterminated, it swallows the rest
+        assert_eq!(sanitize("before\u{1b}]0;pwned forever"), "before");
+    }
+
+    #[test]
+    fn test_dcs_and_other_strings() {
+        assert_eq!(sanitize("\u{1b}P+q544e\u{1b}\\after"), "after");
+        assert_eq!(sanitize("\u{90}1$r\u{9c}after"), "after");
+        assert_eq!(
+            sanitize("\u{1b}_apc\u{1b}\\a\u{1b}^pm\u{7}b\u{1b}Xsos\u{9c}c"),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn test_lone_esc() {
+        assert_eq!(sanitize("trailing\u{1b}"), "trailing");
+        assert_eq!(sanitize("\u{1b}creset"), "reset");
+        assert_eq!(sanitize("\u{1b}(Bcharset"), "charset");
+        assert_eq!(sanitize("\u{1b}\u{1b}[1mbold"), "bold");
+    }
+
+    #[test]
+    fn test_other_controls() {
+        assert_eq!(sanitize("ding\u{7}\u{8}\u{8}\u{0}!"), "ding!");
+        assert_eq!(sanitize("line\r\nover\rwritten\u{7f}"), "line\noverwritten");
+        assert_eq!(sanitize("\u{85}next\u{9b}"), "next");
+        assert_eq!(sanitize("keep\n\ttabs"), "keep\n\ttabs");
+    }
+}
@@ -2,7 +2,6 @@ use percent_encoding::percent_decode_str;
 use reqwest::Url;
 use scraper::html::Html;
 use scraper::selector::Selector;
-use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
 use crate::error::{Error, Result};
@@ -11,6 +10,28 @@ use crate::error::{Error, Result};
 const DUCKDUCKGO_URL: &str = "https://duckduckgo.com";
 const GOOGLE_URL: &str = "https://google.com/search";
 
+/// Selectors for links to DuckDuckGo results, by the name of the layout they work for. They're
+/// tried in order until one finds questions, so that a change to DDG's markup degrades to an
+/// older or more structural selector rather than breaking search altogether.
+const DUCKDUCKGO_STRATEGIES: &[(&str, &str)] = &[
+    ("current", "a.result__a"),
+    ("lite", "a.result-link"),
+    ("legacy", "a.large"),
+    // Every result links through DDG's redirect, with the target in the `uddg` parameter
+    (
+        "structural",
+        r#".result a[href*="uddg="], #links a[href*="uddg="]"#,
+    ),
+];
+
+/// Links in DDG's "More results from <site>" clusters under a result, which the strategies'
+/// selectors don't match. Clusters often repeat the result above them, but may hold questions
+/// that aren't listed anywhere else.
+const DUCKDUCKGO_CLUSTER_SELECTOR: &str = ".result__sitelinks a";
+/// Names of the search engines, as their errors give them
+const DUCKDUCKGO: &str = "DuckDuckGo";
+const GOOGLE: &str = "Google";
+
 // Is question_id unique across all sites? If not, then this edge case is
 // unaccounted for when sorting.
 //
@@ -21,8 +42,14 @@ const GOOGLE_URL: &str = "https://google.com/search";
 pub struct ScrapedData {
     /// Mapping of site code to question ids
     pub question_ids: HashMap<String, Vec<String>>,
-    /// Mapping of question_id to its ordinal place in search results
+    /// Mapping of site code to the ids of answers linked to directly, whose questions are yet to
+    /// be looked up
+    pub answer_ids: HashMap<String, Vec<String>>,
+    /// Mapping of question_id (or answer_id, for answers linked to directly) to its ordinal place
+    /// in search results
     pub ordering: HashMap<String, usize>,
+    /// Name of the selector strategy that found the results, for scrapers that have several
+    pub strategy: Option<String>,
 }
 
 // TODO add this type system limitation to blog post
@@ -37,7 +64,38 @@ pub trait Scraper {
         I: IntoIterator<Item = &'a String>;
 }
 
-pub struct DuckDuckGo;
+#[derive(Default)]
+pub struct DuckDuckGo {
+    /// Selector from the config, tried ahead of the built-in ones
+    custom_selector: Option<String>,
+}
+
+impl DuckDuckGo {
+    /// Scraper trying `custom_selector` (the `ddg_result_selector` config) before the built-in
+    /// selectors
+    pub fn new(custom_selector: Option<String>) -> Self {
+        DuckDuckGo { custom_selector }
+    }
+
+    fn strategies(&self) -> Vec<(&str, &str)> {
+        self.custom_selector
+            .iter()
+            .map(|s| ("ddg_result_selector", s.as_str()))
+            .chain(DUCKDUCKGO_STRATEGIES.iter().copied())
+            .collect()
+    }
+}
+
+/// Whether the selector `strategy` that found the results is one of the fallbacks, meaning
+/// DuckDuckGo changed its markup and the results may be incomplete
+pub fn is_fallback(strategy: &str) -> bool {
+    !matches!(strategy, "current" | "ddg_result_selector")
+}
+
+/// Whether `error` means DuckDuckGo blocked the search
+pub fn is_blocked(error: &Error) -> bool {
+    matches!(error, Error::Blocked { engine } if *engine == DUCKDUCKGO)
+}
 
 impl Scraper for DuckDuckGo {
     /// Parse (site, question_id) pairs out of duckduckgo search results html
@@ -47,16 +105,35 @@ impl Scraper for DuckDuckGo {
         sites: &HashMap<String, String>,
         limit: u16,
     ) -> Result<ScrapedData> {
-        let anchors = Selector::parse("a.result__a").unwrap();
-        parse_with_selector(anchors, html, sites, limit).and_then(|sd| {
-            // DDG seems to never have empty results, so assume this is blocked
-            if sd.question_ids.is_empty() {
-                Err(Error::ScrapingError(String::from(
-                    "DuckDuckGo blocked this request",
-                )))
-            } else {
-                Ok(sd)
+        let strategies = self.strategies();
+        for (name, selector) in &strategies {
+            // Matches come in document order, so cluster links rank right below their result
+            let anchors =
+                Selector::parse(&format!("{}, {}", selector, DUCKDUCKGO_CLUSTER_SELECTOR))
+                    .map_err(|_| Error::ResultSelector {
+                        name: name.to_string(),
+                        selector: selector.to_string(),
+                    })?;
+            let sd = parse_with_selector(anchors, html, sites, limit)?;
+            if !sd.question_ids.is_empty() {
+                return Ok(ScrapedData {
+                    strategy: Some(name.to_string()),
+                    ..sd
+                });
             }
+        }
+        // DDG seems to never have empty results, so without any result links at all, assume
+        // this is blocked
+        if !html.contains("uddg=") {
+            return Err(Error::Blocked { engine: DUCKDUCKGO });
+        }
+        let tried: Vec<String> = strategies
+            .iter()
+            .map(|(name, selector)| format!("{} (`{}`)", name, selector))
+            .collect();
+        Err(Error::ResultLayout {
+            engine: DUCKDUCKGO,
+            tried,
         })
     }
 
@@ -86,11 +163,16 @@ impl Scraper for Google {
         limit: u16,
     ) -> Result<ScrapedData> {
         let anchors = Selector::parse("div.r > a").unwrap();
-        parse_with_selector(anchors, html, sites, limit)
+        let sd = parse_with_selector(anchors, html, sites, limit)?;
+        if sd.question_ids.is_empty() {
+            if let Some(interstitial) = google_interstitial(html) {
+                return Err(interstitial);
+            }
+        }
+        Ok(sd)
     }
 
-    /// Creates duckduckgo search url given sites and query
-    /// See https://duckduckgo.com/params for more info
+    /// Creates google search url given sites and query
     fn get_url<'a, I>(&self, query: &str, sites: I) -> Url
     where
         I: IntoIterator<Item = &'a String>,
@@ -100,6 +182,24 @@ impl Scraper for Google {
     }
 }
 
+/// Error for the page Google shows in place of results, if `html` is one
+fn google_interstitial(html: &str) -> Option<Error> {
+    let fragment = Html::parse_document(html);
+    let matches = |selector: &str| {
+        fragment
+            .select(&Selector::parse(selector).unwrap())
+            .next()
+            .is_some()
+    };
+    if matches(r#"form[action*="consent.google."]"#) {
+        Some(Error::Consent { engine: GOOGLE })
+    } else if matches("#captcha-form, .g-recaptcha") {
+        Some(Error::Captcha { engine: GOOGLE })
+    } else {
+        None
+    }
+}
+
 fn make_query_arg<'a, I>(query: &str, sites: I) -> String
 where
     I: IntoIterator<Item = &'a String>,
@@ -128,6 +228,8 @@ where
     q
 }
 
+/// Question and answer links among the `anchors` of `html` to any of `sites`, up to `limit` of
+/// them. Links elsewhere, and anchors without a link, are skipped.
 fn parse_with_selector(
     anchors: Selector,
     html: &str,
@@ -136,21 +238,30 @@ fn parse_with_selector(
 ) -> Result<ScrapedData> {
     let fragment = Html::parse_document(html);
     let mut question_ids: HashMap<String, Vec<String>> = HashMap::new();
+    let mut answer_ids: HashMap<String, Vec<String>> = HashMap::new();
     let mut ordering: HashMap<String, usize> = HashMap::new();
     let mut count = 0;
     for anchor in fragment.select(&anchors) {
-        let url = anchor
-            .value()
-            .attr("href")
-            .ok_or_else(|| Error::ScrapingError("Anchor with no href".to_string()))
-            .map(|href| percent_decode_str(href).decode_utf8_lossy().into_owned())?;
+        let url = match anchor.value().attr("href") {
+            Some(href) => percent_decode_str(href).decode_utf8_lossy().into_owned(),
+            None => continue,
+        };
         sites.iter().find_map(|(site_code, site_url)| {
-            let id = question_url_to_id(site_url, &url)?;
+            let (ids, id) = match question_url_to_id(site_url, &url) {
+                Some(id) => (&mut question_ids, id),
+                None => (&mut answer_ids, answer_url_to_id(site_url, &url)?),
+            };
+            // The same result can be linked more than once, e.g. again in a cluster; the first
+            // link decides its rank, and only that one counts towards the limit
+            if ids.get(site_code).is_some_and(|ids| ids.contains(&id)) {
+                return Some(());
+            }
             ordering.insert(id.to_owned(), count);
-            match question_ids.entry(site_code.to_owned()) {
-                Entry::Occupied(mut o) => o.get_mut().push(id),
-                Entry::Vacant(o) => {
-                    o.insert(vec![id]);
+            // Only the first post on a site needs its own copy of the site code
+            match ids.get_mut(site_code) {
+                Some(ids) => ids.push(id),
+                None => {
+                    ids.insert(site_code.to_owned(), vec![id]);
                 }
             }
             count += 1;
@@ -162,26 +273,45 @@ fn parse_with_selector(
     }
     Ok(ScrapedData {
         question_ids,
+        answer_ids,
         ordering,
+        strategy: None,
     })
 }
 
-// TODO use str_prefix once its stable
+/// Id of the question `input` links to on `site_url`, as in `/questions/{id}`, with or without a
+/// slug or an anchor after it, or `/q/{id}`
 fn question_url_to_id(site_url: &str, input: &str) -> Option<String> {
-    ["/questions/", "/q/"].iter().find_map(|segment| {
-        let fragment = site_url.trim_end_matches('/').to_owned() + segment;
-        let mut ix = input.find(&fragment)?;
+    url_to_id(site_url, input, &["/questions/", "/q/"])
+}
+
+/// Id of the answer `input` links to on `site_url`, as in `/a/{id}`
+fn answer_url_to_id(site_url: &str, input: &str) -> Option<String> {
+    url_to_id(site_url, input, &["/a/"])
+}
+
+// TODO use str_prefix once its stable
+fn url_to_id(site_url: &str, input: &str, segments: &[&str]) -> Option<String> {
+    let site_url = site_url.trim_end_matches('/');
+    segments.iter().find_map(|segment| {
+        // First occurrence of `site_url` followed by `segment`, without building that string for
+        // every link and site
+        let segment_ix = input
+            .match_indices(segment)
+            .map(|(ix, _)| ix)
+            .find(|&ix| input[..ix].ends_with(site_url))?;
+        let ix = segment_ix - site_url.len();
         if ix > 0 && input.chars().nth(ix - 1) == Some('.') {
             return None;
         }
-        ix += fragment.len();
+        let ix = segment_ix + segment.len();
         let input = &input[ix..];
-        let id = if let Some(end) = input.find('/') {
-            input[0..end].to_string()
-        } else {
-            input[0..].to_string()
+        // Up to the slug, the anchor, the query, or the next parameter of a redirect link
+        let id = match input.find(['/', '#', '?', '&']) {
+            Some(end) => input[0..end].to_string(),
+            None => input[0..].to_string(),
         };
-        if id.chars().all(|c| c.is_digit(10)) {
+        if id.chars().all(|c| c.is_ascii_digit()) {
             Some(id)
         } else {
             None
@@ -189,7 +319,6 @@ fn question_url_to_id(site_url: &str, input: &str) -> Option<String> {
     })
 }
 
-// TODO  Get blocked google request html
 // TODO Get google no results html
 // note: this may only be possible at search.rs level (with non-200 code)
 #[cfg(test)]
@@ -204,7 +333,7 @@ mod tests {
             String::from("unix.stackexchange.com"),
         ];
         assert_eq!(
-            DuckDuckGo.get_url(q, &sites).as_str(),
+            DuckDuckGo::default().get_url(q, &sites).as_str(),
             String::from(
                 "https://duckduckgo.com/\
                 ?q=%28site%3Astackoverflow.com+OR+site%3Aunix.stackexchange.com%29\
@@ -223,9 +352,11 @@ mod tests {
         .into_iter()
         .map(|(k, v)| (k.to_string(), v.to_string()))
         .collect::<HashMap<String, String>>();
+        // The top result has a cluster repeating it along with 7811855, and is listed again
+        // after 24406, neither of which counts towards the limit
         let expected_scraped_data = ScrapedData {
             question_ids: vec![
-                ("stackoverflow", vec!["11828270", "9171356"]),
+                ("stackoverflow", vec!["11828270", "7811855", "9171356"]),
                 ("askubuntu", vec!["24406"]),
             ]
             .into_iter()
@@ -236,13 +367,20 @@ mod tests {
                 )
             })
             .collect(),
-            ordering: vec![("11828270", 0), ("9171356", 2), ("24406", 1)]
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v))
-                .collect(),
+            ordering: vec![
+                ("11828270", 0),
+                ("7811855", 1),
+                ("24406", 2),
+                ("9171356", 3),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+            answer_ids: HashMap::new(),
+            strategy: Some(String::from("current")),
         };
         assert_eq!(
-            DuckDuckGo.parse(html, &sites, 3).unwrap(),
+            DuckDuckGo::default().parse(html, &sites, 4).unwrap(),
             expected_scraped_data
         );
     }
@@ -274,6 +412,8 @@ mod tests {
                 .into_iter()
                 .map(|(k, v)| (k.to_string(), v))
                 .collect(),
+            answer_ids: HashMap::new(),
+            strategy: None,
         };
         assert_eq!(
             Google.parse(html, &sites, 3).unwrap(),
@@ -307,6 +447,8 @@ mod tests {
             ]
             .into_iter()
             .collect(),
+            answer_ids: HashMap::new(),
+            strategy: None,
         };
         assert_eq!(
             Google.parse(html, &sites, 3).unwrap(),
@@ -323,14 +465,107 @@ mod tests {
             String::from("stackoverflow.com"),
         );
 
-        match DuckDuckGo.parse(html, &sites, 2) {
-            Err(Error::ScrapingError(s)) if s == "DuckDuckGo blocked this request".to_string() => {
-                Ok(())
-            }
+        let result = DuckDuckGo::default().parse(html, &sites, 2);
+        assert!(matches!(&result, Err(e) if is_blocked(e)));
+        match result {
+            Err(Error::Blocked {
+                engine: "DuckDuckGo",
+            }) => Ok(()),
             _ => Err(String::from("Failed to detect DuckDuckGo blocker")),
         }
     }
 
+    #[test]
+    fn test_google_interstitials() {
+        let sites = exit_vim_sites();
+        match Google.parse(include_str!("../../test/google/consent.html"), &sites, 3) {
+            Err(Error::Consent { engine: "Google" }) => (),
+            r => panic!("expected a consent page, got {:?}", r),
+        }
+        match Google.parse(include_str!("../../test/google/captcha.html"), &sites, 3) {
+            Err(Error::Captcha { engine: "Google" }) => (),
+            r => panic!("expected a captcha, got {:?}", r),
+        }
+        // Actual results don't count as either
+        let html = include_str!("../../test/google/exit-vim.html");
+        assert!(google_interstitial(html).is_none());
+    }
+
+    fn exit_vim_sites() -> HashMap<String, String> {
+        vec![
+            ("stackoverflow", "stackoverflow.com"),
+            ("askubuntu", "askubuntu.com"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    /// Parse a fixture with only the two top exit-vim results, asserting `strategy` found them
+    fn assert_strategy(scraper: DuckDuckGo, html: &str, strategy: &str) {
+        let sd = scraper.parse(html, &exit_vim_sites(), 10).unwrap();
+        assert_eq!(sd.strategy.as_deref(), Some(strategy));
+        assert_eq!(sd.question_ids["stackoverflow"], vec!["11828270"]);
+        assert_eq!(sd.question_ids["askubuntu"], vec!["24406"]);
+        assert_eq!(sd.ordering["11828270"], 0);
+        assert_eq!(sd.ordering["24406"], 1);
+    }
+
+    #[test]
+    fn test_duckduckgo_lite_strategy() {
+        let html = include_str!("../../test/duckduckgo/lite.html");
+        assert_strategy(DuckDuckGo::default(), html, "lite");
+    }
+
+    #[test]
+    fn test_duckduckgo_legacy_strategy() {
+        let html = include_str!("../../test/duckduckgo/legacy.html");
+        assert_strategy(DuckDuckGo::default(), html, "legacy");
+    }
+
+    #[test]
+    fn test_duckduckgo_structural_strategy() {
+        // Also links each result twice, which must only count once
+        let html = include_str!("../../test/duckduckgo/structural.html");
+        assert_strategy(DuckDuckGo::default(), html, "structural");
+    }
+
+    #[test]
+    fn test_duckduckgo_custom_selector() {
+        let html = include_str!("../../test/duckduckgo/structural.html");
+        let scraper = DuckDuckGo::new(Some(String::from("a.result-v2__a")));
+        assert_strategy(scraper, html, "ddg_result_selector");
+
+        let scraper = DuckDuckGo::new(Some(String::from("a[")));
+        match scraper.parse(html, &exit_vim_sites(), 10) {
+            Err(Error::ResultSelector { name, selector }) => {
+                assert_eq!(
+                    (name.as_str(), selector.as_str()),
+                    ("ddg_result_selector", "a[")
+                )
+            }
+            _ => panic!("expected an invalid selector error"),
+        }
+    }
+
+    #[test]
+    fn test_duckduckgo_layout_changed() {
+        let html = include_str!("../../test/duckduckgo/structural.html")
+            .replace("class=\"result ", "class=\"hit ")
+            .replace("id=\"links\"", "id=\"hits\"");
+        match DuckDuckGo::default().parse(&html, &exit_vim_sites(), 10) {
+            Err(e @ Error::ResultLayout { .. }) => {
+                let s = e.to_string();
+                assert!(s.starts_with("DuckDuckGo's result layout seems to have changed"));
+                for (name, selector) in DUCKDUCKGO_STRATEGIES {
+                    assert!(s.contains(&format!("{} (`{}`)", name, selector)));
+                }
+                assert!(s.contains("ddg_result_selector"));
+            }
+            _ => panic!("expected a layout changed error"),
+        }
+    }
+
     #[test]
     fn test_question_url_to_id() {
         // Happy path
@@ -358,5 +593,171 @@ mod tests {
         let site_url = "meta.stackexchange.com";
         let input = "/l/?kh=-1&uddg=https://math.meta.stackexchange.com/q/11828270";
         assert_eq!(question_url_to_id(site_url, input), None);
+
+        // Anchored at an answer, with or without a slug, and with a query or redirect parameters
+        let site_url = "stackoverflow.com";
+        for input in &[
+            "https://stackoverflow.com/questions/11828270/how-do-i-exit#11828573",
+            "https://stackoverflow.com/questions/11828270#11828573",
+            "https://stackoverflow.com/questions/11828270?noredirect=1",
+            "/l/?uddg=https://stackoverflow.com/q/11828270&rut=6b1f",
+        ] {
+            assert_eq!(
+                question_url_to_id(site_url, input).as_deref(),
+                Some("11828270"),
+                "{}",
+                input
+            );
+        }
+
+        // Answers aren't questions
+        let input = "https://stackoverflow.com/a/11828573";
+        assert_eq!(question_url_to_id(site_url, input), None);
+    }
+
+    #[test]
+    fn test_answer_url_to_id() {
+        let site_url = "stackoverflow.com";
+        for input in &[
+            "https://stackoverflow.com/a/11828573",
+            // Share links name the user sharing them
+            "https://stackoverflow.com/a/11828573/1000",
+            "/l/?uddg=https://stackoverflow.com/a/11828573&rut=0d3a",
+        ] {
+            assert_eq!(
+                answer_url_to_id(site_url, input).as_deref(),
+                Some("11828573"),
+                "{}",
+                input
+            );
+        }
+        for input in &[
+            "https://stackoverflow.com/questions/11828270/how-do-i-exit#11828573",
+            "https://meta.stackoverflow.com/a/11828573",
+            "https://stackoverflow.com/a/best",
+        ] {
+            assert_eq!(answer_url_to_id(site_url, input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_link_shapes() {
+        let html = include_str!("../../test/duckduckgo/link-shapes.html");
+        let ids = |ids: &[(&str, &[&str])]| -> HashMap<String, Vec<String>> {
+            ids.iter()
+                .map(|(site, ids)| {
+                    (
+                        site.to_string(),
+                        ids.iter().map(|id| id.to_string()).collect(),
+                    )
+                })
+                .collect()
+        };
+        // Links to GitHub and to Super User, which isn't searched, are skipped, and so is the
+        // result without a link
+        let expected_scraped_data = ScrapedData {
+            question_ids: ids(&[
+                ("stackoverflow", &["11828270", "7811855", "9171356"]),
+                ("askubuntu", &["24406"]),
+            ]),
+            answer_ids: ids(&[("stackoverflow", &["11828573"]), ("askubuntu", &["24410"])]),
+            ordering: vec![
+                ("11828270", 0),
+                ("11828573", 1),
+                ("7811855", 2),
+                ("24406", 3),
+                ("9171356", 4),
+                ("24410", 5),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+            strategy: Some(String::from("current")),
+        };
+        assert_eq!(
+            DuckDuckGo::default()
+                .parse(html, &exit_vim_sites(), 10)
+                .unwrap(),
+            expected_scraped_data
+        );
+        // Answer links count towards the limit
+        let limited = DuckDuckGo::default()
+            .parse(html, &exit_vim_sites(), 2)
+            .unwrap();
+        assert_eq!(
+            limited.question_ids,
+            ids(&[("stackoverflow", &["11828270"])])
+        );
+        assert_eq!(limited.answer_ids, ids(&[("stackoverflow", &["11828573"])]));
+    }
+
+    /// `question_url_to_id` as it was before it stopped allocating per call
+    fn question_url_to_id_reference(site_url: &str, input: &str) -> Option<String> {
+        ["/questions/", "/q/"].iter().find_map(|segment| {
+            let fragment = site_url.trim_end_matches('/').to_owned() + segment;
+            let mut ix = input.find(&fragment)?;
+            if ix > 0 && input.chars().nth(ix - 1) == Some('.') {
+                return None;
+            }
+            ix += fragment.len();
+            let input = &input[ix..];
+            let id = input.split('/').next().unwrap().to_string();
+            if id.chars().all(|c| c.is_ascii_digit()) {
+                Some(id)
+            } else {
+                None
+            }
+        })
+    }
+
+    #[test]
+    fn test_question_url_to_id_equivalence() {
+        let site_urls = [
+            "stackoverflow.com",
+            "stackoverflow.com/",
+            "meta.stackexchange.com",
+            "unix.stackexchange.com",
+        ];
+        let inputs = [
+            "https://stackoverflow.com/questions/11828270/how-do-i-exit-the-vim-editor",
+            "https://stackoverflow.com/q/11828270",
+            "https://stackoverflow.com/questions/tagged/vim",
+            "https://stackoverflow.com/q/11828270/questions/42",
+            "https://meta.stackoverflow.com/questions/1",
+            "https://math.meta.stackexchange.com/q/11828270",
+            "https://unix.stackexchange.com/a/1/q/2",
+            "/l/?uddg=https://unix.stackexchange.com/questions/3/x&rut=stackoverflow.com/q/4",
+            "https://stackoverflow.com/questions/",
+            "https://stackoverflow.com/q/",
+            "stackoverflow.com/q/5",
+            "https://ünïcode.com/stackoverflow.com/q/6",
+            "",
+        ];
+        for site_url in &site_urls {
+            for input in &inputs {
+                assert_eq!(
+                    question_url_to_id(site_url, input),
+                    question_url_to_id_reference(site_url, input),
+                    "{} in {}",
+                    site_url,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_question_url_to_id_allocations() {
+        let input = "https://stackoverflow.com/questions/11828270/how-do-i-exit-the-vim-editor";
+        // Just the id itself
+        let (id, allocations) =
+            crate::alloc_count::count(|| question_url_to_id("stackoverflow.com", input));
+        assert_eq!(id.as_deref(), Some("11828270"));
+        assert_eq!(allocations, 1);
+        // Nothing for the links to other sites, which are most of the (link, site) pairs tried
+        let (id, allocations) =
+            crate::alloc_count::count(|| question_url_to_id("askubuntu.com", input));
+        assert_eq!(id, None);
+        assert_eq!(allocations, 0);
     }
 }
@@ -1,20 +1,60 @@
-use futures::stream::StreamExt;
+use futures::future::{self, FutureExt};
+#[cfg(feature = "tui")]
 use rayon::prelude::*;
 use reqwest::header;
-use reqwest::Client;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
 
-use crate::config::{Config, SearchEngine};
-use crate::error::{Error, Result};
-use crate::tui::markdown;
-use crate::tui::markdown::Markdown;
+#[cfg(feature = "tui")]
+use cursive::theme::{BaseColor, Color, Effect, Style};
 
+use crate::config::{Config, LuckySiteStrategy, SearchEngine};
+use crate::error::{Error, Result, NOT_ACCEPTED_EXIT_CODE};
+use crate::messages::Messages;
+#[cfg(feature = "tui")]
+use crate::tui::markdown::{self, Markdown};
+
+use super::aliases::Aliases;
+use super::analysis::{self, Language};
+#[cfg(feature = "tui")]
+use super::api::CodeMatch;
 use super::api::{Answer, Api, Question};
+use super::backoff::Backoff;
+use super::blocks::{self, BlockLog};
+use super::code_search;
+use super::context;
+#[cfg(feature = "tui")]
+use super::duplicates;
+use super::filtered::Filtered;
+use super::filters::Filters;
+use super::isolation::{self, Failure};
+#[cfg(feature = "tui")]
+use super::isolation::{Failures, Stage};
+use super::latency::Latency;
 use super::local_storage::LocalStorage;
-use super::scraper::{DuckDuckGo, Google, ScrapedData, Scraper};
-
-/// Limit on concurrent requests (gets passed to `buffer_unordered`)
-const CONCURRENT_REQUESTS_LIMIT: usize = 8;
+use super::lossy::Skip;
+use super::lucky_memory::Remembered;
+use super::network::{Destination, Network, OfflinePolicy, Timeouts};
+use super::normalize::{Normalized, Normalizer};
+use super::query_cache::{self, QueryCache};
+use super::question_cache::QuestionCache;
+use super::quota::{Quota, Reading};
+use super::relevance;
+use super::sanitize;
+use super::scraper::{self, DuckDuckGo, Google, ScrapedData, Scraper};
+#[cfg(feature = "tui")]
+use super::snippets;
+use super::summary;
+use super::timings::Timings;
+use super::transform::{self, ResultTransformer};
+use super::urls::{self, Direct, Post};
 
 /// Mock user agent to get real DuckDuckGo results
 // TODO copy other user agents and use random one each time
@@ -22,186 +62,3421 @@ const USER_AGENT: &str =
     "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.7; rv:11.0) Gecko/20100101 Firefox/11.0";
 
 /// This structure provides methods to search queries and get StackExchange
-/// questions/answers in return.
+/// questions/answers in return. The query is given to each search, so one `Search` can run any
+/// number of them, e.g. from the TUI, sharing its site list, clients and caches.
 // TODO this really needs a better name...
 #[derive(Clone)]
 pub struct Search {
     api: Api,
-    config: Config,
-    query: String,
-    sites: HashMap<String, String>,
+    /// Client for search engine requests
+    network: Network,
+    /// Read-only after construction (lucky mode swaps it out rather than editing it), and shared
+    /// with clones, e.g. the background search started after the lucky prompt
+    config: Arc<Config>,
+    sites: Arc<HashMap<String, String>>,
+    /// Every site, to tell which one a link to a post is on, see `urls::direct`
+    listing: Arc<LocalStorage>,
+    /// Look up a query that's a bare question id on the one configured site, rather than search
+    /// for it, since that site was given on the command line
+    direct_ids: bool,
+    code_search_stats: Arc<Mutex<Option<code_search::Stats>>>,
+    /// Which selector strategy found the results of a scraped search
+    scraper_strategy: Arc<Mutex<Option<String>>>,
+    /// Tidies up queries before they're searched for, with `normalize_query`
+    normalizer: Option<Arc<Normalizer>>,
+    /// Set when `normalize_query` changed the last query searched for
+    normalized: Arc<Mutex<Option<Normalized>>>,
+    /// Set when `auto_requote` retried a search with the query quoted
+    requote: Arc<Mutex<Option<Requote>>>,
+    /// Overall time budget for each search
+    max_time: Option<Duration>,
+    /// Set when a search was cut off by `max_time` before all requests completed
+    partial: Arc<AtomicBool>,
+    /// Sites that failed while others came back with results, each with its error
+    site_failures: Arc<Mutex<Vec<String>>>,
+    /// Sites `early_stop` stopped waiting for, once the others came back with plenty
+    early_stopped: Arc<Mutex<Vec<String>>>,
+    /// Sites the API no longer knows, each with the site that took over from it if any, left out
+    /// of the rest of this invocation's requests
+    gone: Arc<Mutex<HashMap<String, Option<String>>>>,
+    /// Online searches are stored here; offline searches, and online ones repeated within
+    /// `cache_ttl_minutes`, are served from here
+    cache: Option<QueryCache>,
+    /// When the results of an offline search were originally fetched
+    offline_since: Arc<Mutex<Option<i64>>>,
+    /// Offline searches look through everything cached by what it says, see `full_text`, rather
+    /// than for an earlier search for the same query
+    full_text: bool,
+    /// Whatever the search had to work around, for the TUI message console
+    messages: Messages,
+    /// Project the search was run in, whose tag narrows down the search
+    context: Option<context::Context>,
+    /// Where DuckDuckGo searches record whether they were blocked
+    blocks: Option<BlockLog>,
+    /// Cancelling this stops the requests of searches in flight, shared with clones
+    cancel: CancellationToken,
+    /// Where StackExchange searches record how long each site took
+    latency: Option<Latency>,
+    /// Fetch another page of results when filters leave fewer than `limit`; never in lucky mode
+    backfill: bool,
+    /// Set when a search fetched another page, see `backfill`
+    backfilled: Arc<Mutex<Option<Backfill>>>,
+    /// Which of the lucky question's fitting answers lucky mode picks, counting from 1
+    lucky_answer: usize,
+    /// Run on results after the built-in filters, in order, see `transform`
+    transformers: Vec<Arc<dyn ResultTransformer>>,
 }
 
 impl Search {
-    pub fn new(config: Config, local_storage: LocalStorage, query: String) -> Self {
-        let api = Api::new(config.api_key.clone());
+    pub fn new(mut config: Config, local_storage: LocalStorage) -> Self {
+        // Private content isn't indexed by search engines, so Teams are always searched via the
+        // API, and as a single "site"
+        let team = config.selected_team().ok().flatten().cloned();
+        // A site listed twice would be searched, and count against the quota, twice
+        let mut seen = HashSet::new();
+        config.sites.retain(|site| seen.insert(site.clone()));
+        if let (Some(_), Some(name)) = (&team, &config.team) {
+            config.search_engine = SearchEngine::StackExchange;
+            config.sites = vec![name.clone()];
+        }
+        let policy = OfflinePolicy::from(&config);
+        let api = Api::new(config.api_key.clone(), config.filter.clone(), policy)
+            .with_api_url(&config.api_url)
+            .with_api_version(&config.api_version)
+            .with_extra_headers(config.headers_for(Destination::StackExchange))
+            .with_timeouts(Timeouts::from(&config))
+            .with_team(team)
+            .with_preprocess_passes(config.passes());
         let sites = local_storage.get_urls(&config.sites);
+        // Only DuckDuckGo and Google are scraped, but one of them is as good as the other here
+        let scraped = match config.search_engine {
+            SearchEngine::Google => Destination::Google,
+            _ => Destination::DuckDuckGo,
+        };
+        let normalizer = config
+            .normalize_query
+            .then(|| Arc::new(Normalizer::new(&config.typos)));
         Search {
             api,
-            config,
-            query,
-            sites,
+            network: Network::new(policy, header::HeaderMap::new())
+                .with_extra_headers(config.headers_for(scraped))
+                .with_timeouts(Timeouts::from(&config)),
+            config: Arc::new(config),
+            sites: Arc::new(sites),
+            listing: Arc::new(local_storage),
+            direct_ids: false,
+            code_search_stats: Arc::default(),
+            scraper_strategy: Arc::default(),
+            normalizer,
+            normalized: Arc::default(),
+            requote: Arc::default(),
+            max_time: None,
+            partial: Arc::default(),
+            site_failures: Arc::default(),
+            early_stopped: Arc::default(),
+            gone: Arc::default(),
+            cache: None,
+            offline_since: Arc::default(),
+            full_text: false,
+            messages: Messages::default(),
+            context: None,
+            blocks: None,
+            cancel: CancellationToken::new(),
+            latency: None,
+            backfill: true,
+            backfilled: Arc::default(),
+            lucky_answer: 1,
+            transformers: vec![],
+        }
+    }
+
+    /// Stop the requests of searches in flight once `cancel` is cancelled, failing those searches
+    /// with `Error::Cancelled`
+    pub fn with_cancellation(self, cancel: CancellationToken) -> Self {
+        Search { cancel, ..self }
+    }
+
+    /// Record in `blocks` whether DuckDuckGo blocked searches, to fall back to the API once it
+    /// keeps doing so
+    pub fn with_block_log(self, blocks: BlockLog) -> Self {
+        Search {
+            blocks: Some(blocks),
+            ..self
+        }
+    }
+
+    /// Narrow down searches to the tag hinted at by the project they're run in
+    pub fn with_context(self, context: Option<context::Context>) -> Self {
+        Search { context, ..self }
+    }
+
+    /// What `context` added to a search for `query`, and where from
+    pub fn context_added(&self, query: &str) -> Option<String> {
+        let context = self.context.as_ref()?;
+        let added = match self.config.search_engine {
+            SearchEngine::StackExchange => format!("tagged [{}]", context.tag),
+            _ => match context::augment(query, context.tag) {
+                Some(query) => format!("searched for \"{}\"", query),
+                None => format!("nothing added, the query already mentions {}", context.tag),
+            },
+        };
+        Some(format!("{}, from {}", added, context.manifest.display()))
+    }
+
+    fn tag(&self) -> Option<&'static str> {
+        self.context.as_ref().map(|context| context.tag)
+    }
+
+    /// Keep the results of online searches in `cache`, and serve offline searches from it
+    pub fn with_cache(self, cache: QueryCache) -> Self {
+        Search {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Look up queries that are bare question ids, rather than search for them, if there's just
+    /// the one site to look them up on
+    pub fn with_direct_ids(self, direct_ids: bool) -> Self {
+        Search { direct_ids, ..self }
+    }
+
+    /// Search the questions of every cached search by their text when offline, as `--search` does
+    pub fn with_full_text(self, full_text: bool) -> Self {
+        Search { full_text, ..self }
+    }
+
+    /// Reuse questions fetched by earlier searches, when searching via DuckDuckGo or Google
+    pub fn with_question_cache(self, cache: QuestionCache) -> Self {
+        Search {
+            api: self.api.with_question_cache(Some(cache)),
+            ..self
+        }
+    }
+
+    /// Reuse the StackExchange API filters created by earlier invocations from `filters`
+    pub fn with_filters(self, filters: Filters) -> Self {
+        Search {
+            api: self.api.with_filters(filters),
+            ..self
+        }
+    }
+
+    /// Wait out the backoffs the StackExchange API asks for, across invocations via `backoff`
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Search {
+            api: self.api.with_backoff(backoff),
+            ..self
+        }
+    }
+
+    /// Keep the lowest API quota left in `quota`, which is written to disk
+    pub fn with_quota(self, quota: Quota) -> Self {
+        Search {
+            api: self.api.with_quota(quota),
+            ..self
+        }
+    }
+
+    /// Lowest API quota responses said is left, if it's under `quota_threshold`
+    pub fn low_quota(&self) -> Option<Reading> {
+        self.api
+            .quota()
+            .lowest()
+            .filter(|reading| reading.is_low(self.config.quota_threshold))
+    }
+
+    /// Record in `latency` how long searches of each site take, for lucky mode to pick the
+    /// quickest site with `lucky_site_strategy: fastest`
+    pub fn with_latency(self, latency: Latency) -> Self {
+        Search {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    /// What the user should know about the StackExchange API throttling requests
+    pub fn backoff_notices(&self) -> Vec<String> {
+        self.api.backoff().notices()
+    }
+
+    /// Follow merged questions to their new ids, recording merges spotted along the way
+    pub fn with_aliases(self, aliases: Aliases) -> Self {
+        Search {
+            api: self.api.with_aliases(Some(aliases)),
+            ..self
+        }
+    }
+
+    /// Fetch question bodies, or leave them out for modes that don't show them (lucky mode and
+    /// `--digest`); the TUI needs them
+    pub fn with_question_bodies(self, question_bodies: bool) -> Self {
+        Search {
+            api: self.api.with_question_bodies(question_bodies),
+            ..self
+        }
+    }
+
+    /// When the results were originally fetched, if they were served offline from the cache
+    pub fn offline_since(&self) -> Option<i64> {
+        *self.offline_since.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Pick the `n`th answer of the lucky question in lucky mode, counting from 1, rather than the
+    /// top one
+    pub fn with_lucky_answer(self, n: usize) -> Self {
+        Search {
+            lucky_answer: n.max(1),
+            ..self
         }
     }
 
-    /// Search query and get the top answer body
+    /// Run results through `transformer` after the built-in filters and those registered before it,
+    /// and before they're parsed for display
+    pub fn with_transformer(mut self, transformer: Box<dyn ResultTransformer>) -> Self {
+        self.transformers.push(Arc::from(transformer));
+        self
+    }
+
+    /// Return whatever results are in once `max_time` has passed, rather than waiting for all
+    /// requests to complete
+    pub fn with_max_time(self, max_time: Option<Duration>) -> Self {
+        Search { max_time, ..self }
+    }
+
+    /// Whether a search was cut off by `max_time`, i.e. its results are partial
+    pub fn partial(&self) -> bool {
+        self.partial.load(Ordering::Relaxed)
+    }
+
+    /// Sites left out of the results because their requests failed, each with its error, e.g.
+    /// `superuser: Service Unavailable`
+    pub fn site_failures(&self) -> Vec<String> {
+        self.site_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Sites left out of the results because `early_stop` stopped waiting for them, in the order
+    /// they were to be searched
+    pub fn early_stopped(&self) -> Vec<String> {
+        self.early_stopped
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Sites the API turned out not to know anymore, as `Error::SiteGone`, sorted by site
+    pub fn gone_sites(&self) -> Vec<Error> {
+        let gone = self.gone.lock().unwrap_or_else(|e| e.into_inner());
+        let mut sites: Vec<_> = gone.iter().collect();
+        sites.sort_unstable();
+        sites
+            .into_iter()
+            .map(|(site, successor)| Error::SiteGone {
+                site: site.clone(),
+                successor: successor.clone(),
+            })
+            .collect()
+    }
+
+    /// Search `query` and get the most relevant question, with only its top answer
     ///
-    /// For StackExchange engine, use only the first configured site,
-    /// since, parodoxically, sites with the worst results will finish
-    /// executing first, because there's less data to retrieve.
+    /// For StackExchange engine, use only one of the configured sites, as `lucky_site_strategy`
+    /// picks it, since, parodoxically, sites with the worst results will finish
+    /// executing first, because there's less data to retrieve. Racing the first two sites is the
+    /// exception: whichever has a usable answer first wins.
     ///
-    /// Needs mut because it temporarily changes self.config
-    pub async fn search_lucky(&mut self) -> Result<String> {
-        let original_config = self.config.clone();
+    /// Needs mut because it temporarily changes self.config. Offline, the top result of the full
+    /// search is used instead, since that is what gets cached. Questions without answers are
+    /// never picked, even when including unanswered questions. With `require_accepted`, the
+    /// answer is the first accepted one among the `limit` top questions, if any.
+    pub async fn search_lucky(&mut self, query: &str) -> Result<Question<String>> {
+        let original_config = Arc::clone(&self.config);
+        // A single answer is wanted, not `limit` questions
+        let backfill = std::mem::replace(&mut self.backfill, false);
         // Temp set lucky config
-        self.config.limit = 1;
-        if let SearchEngine::StackExchange = self.config.search_engine {
-            self.config.sites.truncate(1);
+        if !self.config.offline {
+            let config = Arc::make_mut(&mut self.config);
+            if !config.require_accepted {
+                config.limit = 1;
+            }
+            config.include_unanswered = false;
+            if let SearchEngine::StackExchange = config.search_engine {
+                let keep = match (config.lucky_site_strategy, &self.latency) {
+                    (LuckySiteStrategy::First, _) => 1,
+                    (LuckySiteStrategy::Fastest, Some(latency)) => {
+                        config.sites = latency.fastest_first(&config.sites);
+                        1
+                    }
+                    // Nothing measured to go by
+                    (LuckySiteStrategy::Fastest, None) => 1,
+                    (LuckySiteStrategy::Race, _) => 2,
+                };
+                config.sites.truncate(keep);
+            }
         }
         // Run search with temp config
-        let result = self.search().await;
+        let racing = !self.config.offline
+            && self.config.search_engine == SearchEngine::StackExchange
+            && self.config.sites.len() > 1
+            && !self.is_direct(query);
+        let result = if racing {
+            self.race(query).await
+        } else {
+            self.search(query).await.and_then(|qs| self.pick_lucky(qs))
+        };
         // Reset config
         self.config = original_config;
+        self.backfill = backfill;
+        result
+    }
 
-        Ok(result?
-            .into_iter()
-            .next()
-            .ok_or(Error::NoResults)?
+    /// Search each configured site on its own, all at once, and take the lucky answer of the
+    /// first one to come back with a usable one, cancelling the searches of the others
+    async fn race(&self, query: &str) -> Result<Question<String>> {
+        let tokens: Vec<_> = self
+            .config
+            .sites
+            .iter()
+            .map(|_| self.cancel.child_token())
+            .collect();
+        let racers = self.config.sites.iter().zip(&tokens).map(|(site, token)| {
+            let mut racer = self.clone().with_cancellation(token.clone());
+            Arc::make_mut(&mut racer.config).sites = vec![site.clone()];
+            Box::pin(async move { racer.pick_lucky(racer.search(query).await?) })
+        });
+        let result = future::select_ok(racers)
+            .await
+            .map(|(question, _)| question);
+        // Whoever is still searching lost
+        for token in tokens {
+            token.cancel();
+        }
+        result
+    }
+
+    /// The lucky question among the results `qs`, with only its answer
+    fn pick_lucky(&self, qs: Vec<Question<String>>) -> Result<Question<String>> {
+        // In code search mode, the top answer isn't necessarily the one with the code
+        let code_search = self.config.code_search;
+        let require_accepted = self.config.require_accepted;
+        let fits = |a: &Answer<String>| {
+            (!code_search || !a.code_matches.is_empty()) && (!require_accepted || a.is_accepted)
+        };
+        let mut questions = qs.into_iter().filter(|q| !q.answers.is_empty());
+        let question = if require_accepted {
+            questions.find(|q| q.answers.iter().any(fits))
+        } else {
+            questions.next()
+        }
+        .ok_or(Error::NoResults)?;
+        let mut answers: Vec<_> = question.answers.into_iter().filter(fits).collect();
+        let answer = match (self.lucky_answer, answers.len()) {
+            (_, 0) => {
+                return Err(Error::unexpected(
+                    "questions",
+                    "a question came without answers",
+                ))
+            }
+            (n, found) if n > found => return Err(Error::NoSuchAnswer { wanted: n, found }),
+            (n, _) => answers.swap_remove(n - 1),
+        };
+        Ok(Question {
+            answers: vec![answer],
+            ..question
+        })
+    }
+
+    /// The question of `remembered` with only its answer, as lucky mode prints it; `None` if
+    /// either is gone
+    pub async fn remembered(&self, remembered: &Remembered) -> Result<Option<Question<String>>> {
+        let ids = vec![remembered.question_id.to_string()];
+        // A merged question comes back as the one it was merged into, answers and all
+        let question = match self.api.questions(&remembered.site, ids).await?.pop() {
+            Some(question) => question,
+            None => return Ok(None),
+        };
+        let answer = question
             .answers
+            .iter()
+            .position(|a| a.id == remembered.answer_id);
+        Ok(answer.map(|i| {
+            let mut answers = question.answers;
+            let answer = answers.swap_remove(i);
+            let question = Question {
+                answers: vec![answer],
+                ..question
+            };
+            self.config
+                .link_rewrites
+                .question(question, self.config.rewrite_links_in_code)
+        }))
+    }
+
+    /// Search `query` and parse to Markdown for TUI, keeping the questions filters left out
+    #[cfg(feature = "tui")]
+    pub async fn search_md(&self, query: &str) -> Result<Filtered<Markdown>> {
+        let mut qs = self
+            .search_filtered(query)
+            .await?
+            .convert(|qs| self.rewrite_links(qs));
+        if self.config.collapse_duplicate_answers {
+            qs.shown = duplicates::collapse(qs.shown);
+        }
+        let start = Instant::now();
+        if self.config.tl_dr.tui {
+            qs = qs.convert(|mut qs| {
+                self.add_tl_drs(query, &mut qs);
+                qs
+            });
+        }
+        let failures = self.api.failures();
+        let qs = qs.convert(|qs| parse_markdown(qs, markdown::parse_with_highlights, failures));
+        self.api.timings().parse(start.elapsed());
+        for failure in self.api.failures().list() {
+            self.messages.error(failure.to_string());
+        }
+        Ok(qs)
+    }
+
+    /// Whether SE rejected the API filter and results came back in a degraded format
+    pub fn filter_degraded(&self) -> bool {
+        self.api.filter_degraded()
+    }
+
+    /// Questions and answers that failed to render and are shown as plain text
+    pub fn render_failures(&self) -> Vec<Failure> {
+        self.api.failures().list()
+    }
+
+    /// Malformed items the API returned that were left out of the results
+    pub fn skipped_items(&self) -> Vec<Skip> {
+        self.api.skipped().list()
+    }
+
+    /// Timings recorded by all searches so far
+    pub fn timings(&self) -> Timings {
+        self.api.timings().timings()
+    }
+
+    /// Put summaries on top of the answers of `qs` that are long enough to need one, going by the
+    /// terms of `query`
+    pub fn add_tl_drs(&self, query: &str, qs: &mut [Question<String>]) {
+        let language = self.config.query_language;
+        let terms = analysis::terms(query, language);
+        let max = self.config.tl_dr.sentences;
+        for answer in qs.iter_mut().flat_map(|q| q.answers.iter_mut()) {
+            if let Some(tl_dr) = summary::summarize(&answer.body, &terms, language, max) {
+                answer.body = summary::prepend(&answer.body, &tl_dr);
+            }
+        }
+    }
+
+    /// Base URL of each site searched, by site code (or team name)
+    pub fn site_urls(&self) -> HashMap<String, String> {
+        let mut urls = HashMap::clone(&self.sites);
+        if let (Ok(Some(team)), Some(name)) = (self.config.selected_team(), &self.config.team) {
+            urls.insert(
+                name.clone(),
+                format!("https://stackoverflowteams.com/c/{}", team.slug),
+            );
+        }
+        let rewrites = &self.config.link_rewrites;
+        urls.into_iter()
+            .map(|(site, url)| {
+                let url = rewrites.site_url(&url).into_owned();
+                (site, url)
+            })
+            .collect()
+    }
+
+    /// Name of the selector strategy that found the results, if they were scraped by a scraper
+    /// with several
+    pub fn scraper_strategy(&self) -> Option<String> {
+        self.scraper_strategy
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// How `normalize_query` changed the last query searched for, if it did
+    pub fn normalized(&self) -> Option<Normalized> {
+        self.normalized
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// How `auto_requote` retried the last search, if it did
+    pub fn requote(&self) -> Option<Requote> {
+        self.requote
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Warnings and errors collected by searches so far
+    pub fn messages(&self) -> Messages {
+        self.messages.clone()
+    }
+
+    /// Stats of the `--code-search` filter, once a search has run with it
+    pub fn code_search_stats(&self) -> Option<code_search::Stats> {
+        *self
+            .code_search_stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The page fetched to make up for the questions filters left out, if a search needed one
+    pub fn backfilled(&self) -> Option<Backfill> {
+        self.backfilled
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Search `query` using the configured search engine, or the cache when offline or when the
+    /// same search ran recently
+    pub async fn search(&self, query: &str) -> Result<Vec<Question<String>>> {
+        self.search_filtered(query)
+            .await
+            .map(|qs| self.rewrite_links(qs.shown))
+    }
+
+    /// `qs` with their links rewritten as `link_rewrites` has it, on their way out; what's cached
+    /// keeps the links the sites gave
+    fn rewrite_links(&self, qs: Vec<Question<String>>) -> Vec<Question<String>> {
+        let rewrites = &self.config.link_rewrites;
+        let in_code = self.config.rewrite_links_in_code;
+        qs.into_iter()
+            .map(|q| rewrites.question(q, in_code))
+            .collect()
+    }
+
+    /// Search `query`, keeping the questions filters left out apart from those to show; a query
+    /// naming a post outright just gets its question
+    pub async fn search_filtered(&self, query: &str) -> Result<Filtered<String>> {
+        self.reset();
+        if let Some((site, post)) = self.direct(query)? {
+            return self.look_up(&site, post).await;
+        }
+        let normalized = self.normalize(query);
+        let query = normalized.as_deref().unwrap_or(query);
+        // One too far off to represent is as good as none
+        let deadline = self
+            .max_time
+            .and_then(|max_time| time::Instant::now().checked_add(max_time));
+        let (qs, more, online) = if self.config.offline {
+            (self.search_cache(query)?, vec![], false)
+        } else if let Some(qs) = self.search_fresh(query) {
+            (qs, vec![], false)
+        } else {
+            let (qs, more) = self.search_online(query, deadline).await?;
+            (qs, more, true)
+        };
+        // Applied to cached results too, which are stored in whatever order they were searched in
+        let (steps, code_search) = self.pipeline(query);
+        let mut qs = transform::run(&steps, Filtered::new(qs));
+        self.backfill(query, &mut qs, &steps, &more, deadline)
+            .await?;
+        if let Some(stats) = code_search.and_then(|code_search| code_search.stats()) {
+            *self
+                .code_search_stats
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()) = Some(stats);
+        }
+        // Failing to cache results only costs requests when searching for them again
+        if online {
+            let _ = self.store(query, &qs);
+        }
+        if qs.shown.is_empty() {
+            Err(Error::NoResults)
+        } else {
+            Ok(qs)
+        }
+    }
+
+    /// Forget what the last search found out about itself, so that it isn't taken for the next
+    /// one's; the message console and the sites found gone are kept for the whole invocation.
+    /// Racing searches share these, but each resets before its first request goes out.
+    fn reset(&self) {
+        self.partial.store(false, Ordering::Relaxed);
+        for list in [&self.site_failures, &self.early_stopped] {
+            list.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        *self
+            .code_search_stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+        *self
+            .scraper_strategy
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+        *self.requote.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *self.offline_since.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *self.backfilled.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// `query` as `normalize_query` has it searched for, if that's any different, which is kept
+    /// for `normalized`
+    fn normalize(&self, query: &str) -> Option<String> {
+        let normalized = self
+            .normalizer
+            .as_ref()
+            .map(|normalizer| normalizer.normalize(query))
+            .filter(|normalized| normalized.query != query);
+        let searched = normalized.as_ref().map(|n| n.query.clone());
+        *self.normalized.lock().unwrap_or_else(|e| e.into_inner()) = normalized;
+        searched
+    }
+
+    /// Site and post `query` names outright, if it does, see `urls::direct`. Fails if it's a link
+    /// to a host that isn't a known site.
+    fn direct(&self, query: &str) -> Result<Option<(String, Post)>> {
+        // Offline, a link is searched for among cached results like any other query
+        if self.config.offline {
+            return Ok(None);
+        }
+        let direct_ids = self.direct_ids && self.config.sites.len() == 1;
+        match urls::direct(query, direct_ids) {
+            Some(Direct::Link(link)) => match self.listing.site_by_host(&link.host) {
+                Some(site) => Ok(Some((site.api_site_parameter.clone(), link.post))),
+                None => Err(Error::UnknownHost(link.host)),
+            },
+            Some(Direct::Id(id)) => Ok(Some((self.config.sites[0].clone(), Post::Question(id)))),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether `query` names a post outright, which is looked up rather than searched for
+    pub fn is_direct(&self, query: &str) -> bool {
+        matches!(self.direct(query), Ok(Some(_)))
+    }
+
+    /// The question of `post` on `site`, answered or not, with the answer `post` is, if it's one,
+    /// listed first. Filters don't apply to a question asked for by name.
+    async fn look_up(&self, site: &str, post: Post) -> Result<Filtered<String>> {
+        let id = match post {
+            Post::Question(id) => id,
+            Post::Answer(id) => self
+                .api
+                .answer_question_id(site, id)
+                .await?
+                .ok_or(Error::NoResults)?,
+        };
+        let api = self.api.clone().with_unanswered(true);
+        let mut qs = api.questions(site, vec![id.to_string()]).await?;
+        for q in &mut qs {
+            self.config.answer_sort.sort(&mut q.answers);
+            if let Post::Answer(id) = post {
+                if let Some(i) = q.answers.iter().position(|a| a.id == id) {
+                    let answer = q.answers.remove(i);
+                    q.answers.insert(0, answer);
+                }
+            }
+        }
+        if qs.is_empty() {
+            Err(Error::NoResults)
+        } else {
+            Ok(Filtered::new(qs))
+        }
+    }
+
+    /// What the results of `query` go through before they're shown: the built-in filters, in
+    /// order, then the registered transformers. Also returns the `--code-search` filter, if it's
+    /// on, to read what it kept.
+    fn pipeline(
+        &self,
+        query: &str,
+    ) -> (
+        Vec<Arc<dyn ResultTransformer>>,
+        Option<Arc<transform::CodeSearch>>,
+    ) {
+        let mut steps: Vec<Arc<dyn ResultTransformer>> = vec![Arc::new(transform::Unanswered {
+            include_unanswered: self.config.include_unanswered,
+        })];
+        let code_search = self
+            .config
+            .code_search
+            .then(|| Arc::new(transform::CodeSearch::new(query)));
+        if let Some(code_search) = &code_search {
+            steps.push(code_search.clone());
+        }
+        steps.push(Arc::new(transform::SortAnswers(self.config.answer_sort)));
+        steps.extend(self.transformers.iter().cloned());
+        (steps, code_search)
+    }
+
+    /// Make up for the questions filters left out of `qs`, if that leaves fewer than `limit`,
+    /// with the next page of the sites in `more` whose first page the filters kept most of, see
+    /// `best_yield`. That's one more page at most, which goes through `steps` on its own, after
+    /// which `qs` is what it is.
+    async fn backfill(
+        &self,
+        query: &str,
+        qs: &mut Filtered<String>,
+        steps: &[Arc<dyn ResultTransformer>],
+        more: &[More],
+        deadline: Option<time::Instant>,
+    ) -> Result<()> {
+        if !self.backfill || qs.shown.len() >= usize::from(self.config.limit) {
+            return Ok(());
+        }
+        let sites = best_yield(more, &qs.shown);
+        if sites.is_empty() {
+            return Ok(());
+        }
+        let query = self.api_query(query);
+        let tasks: Vec<_> = sites
+            .iter()
+            .map(|more| {
+                let api = self
+                    .api
+                    .clone()
+                    .with_unanswered(self.config.include_unanswered)
+                    .with_tagged(self.tag().filter(|_| more.tagged));
+                let limit = self.config.limit;
+                let query = Arc::clone(&query);
+                let site = more.site.clone();
+                let task = async move {
+                    let page = api.search_advanced(&query, &site, limit, 2).await?;
+                    Ok(page.questions)
+                };
+                (more.site.clone(), task)
+            })
+            .collect();
+        let ceiling = self.config.max_concurrent_requests;
+        let Collected {
+            results, failures, ..
+        } = collect_until(tasks, deadline, ceiling, &self.cancel, never).await?;
+        // The first page is complete either way
+        for failure in failures {
+            self.messages
+                .warn(format!("Couldn't fetch more results from {}", failure));
+        }
+        // Questions can move to the next page between requests
+        let known: HashSet<(&str, u32)> = qs
+            .shown
+            .iter()
+            .chain(qs.hidden.iter().map(|h| &h.question))
+            .map(|q| (q.site.as_str(), q.id))
+            .collect();
+        let fetched: Vec<_> = results
             .into_iter()
-            .next()
-            .ok_or_else(|| Error::StackExchange(String::from("Received question with no answers")))?
-            .body)
+            .flatten()
+            .filter(|q| !known.contains(&(q.site.as_str(), q.id)))
+            .collect();
+        let fetched = transform::run(steps, Filtered::new(fetched));
+        *self.backfilled.lock().unwrap_or_else(|e| e.into_inner()) = Some(Backfill {
+            sites: sites.iter().map(|more| more.site.clone()).collect(),
+            added: fetched.shown.len(),
+        });
+        qs.extend(fetched);
+        Ok(())
+    }
+
+    /// Keep the results of an online search for `query` in the cache, shown or not, if they're
+    /// complete
+    fn store(&self, query: &str, qs: &Filtered<String>) -> Result<()> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+        // Partial results, or questions without bodies, would pass for complete ones once served
+        // offline
+        let complete =
+            !self.partial() && self.site_failures().is_empty() && self.early_stopped().is_empty();
+        if qs.shown.is_empty() && qs.hidden.is_empty() || !complete || !self.api.question_bodies() {
+            return Ok(());
+        }
+        let all: Vec<_> = qs
+            .shown
+            .iter()
+            .chain(qs.hidden.iter().map(|h| &h.question))
+            .cloned()
+            .collect();
+        cache.put(&self.cache_key(query), &all)
+    }
+
+    /// Search `query` online, along with where the sites searched via the API have more results
+    async fn search_online(
+        &self,
+        query: &str,
+        deadline: Option<time::Instant>,
+    ) -> Result<(Vec<Question<String>>, Vec<More>)> {
+        let mut more = vec![];
+        let qs = match self.config.search_engine {
+            SearchEngine::DuckDuckGo => {
+                let scraper = DuckDuckGo::new(self.config.ddg_result_selector.clone());
+                let qs = if self.config.auto_requote {
+                    let search = |query: String| {
+                        let scraper = &scraper;
+                        async move { self.search_by_scraper(scraper, &query, deadline).await }
+                    };
+                    requoting(query, self.config.query_language, search)
+                        .await
+                        .map(|(qs, requote)| {
+                            *self.requote.lock().unwrap_or_else(|e| e.into_inner()) = requote;
+                            qs
+                        })
+                } else {
+                    self.search_by_scraper(&scraper, query, deadline).await
+                };
+                if let Some(log) = &self.blocks {
+                    match &qs {
+                        Err(e) if scraper::is_blocked(e) => log.record(true, blocks::now()),
+                        Ok(_) => log.record(false, blocks::now()),
+                        // Nothing to say about DuckDuckGo when the network is down
+                        Err(_) => Ok(()),
+                    }
+                    // The log is only a hint; the search itself went as it went
+                    .ok();
+                }
+                qs
+            }
+            SearchEngine::Google => self.search_by_scraper(&Google, query, deadline).await,
+            SearchEngine::StackExchange => {
+                let timed = self.api.timings().timings().requests.len();
+                let qs = self.parallel_search_advanced(query, deadline).await;
+                self.record_latency(timed);
+                qs.map(|(qs, left)| {
+                    more = left;
+                    qs
+                })
+            }
+        }?;
+        for notice in self.api.backoff().notices() {
+            self.messages.warn(notice);
+        }
+        if self.api.filter_degraded() {
+            self.messages.warn(
+                "The StackExchange API rejected the filter used by this version of `so`; \
+                results may be missing formatting",
+            );
+        }
+        Ok((qs, more))
     }
 
-    /// Search and parse to Markdown for TUI
-    pub async fn search_md(&self) -> Result<Vec<Question<Markdown>>> {
-        Ok(parse_markdown(self.search().await?))
+    /// Feed how long the searches of the configured sites took into `latency`, going by the
+    /// requests timed after the first `timed`
+    fn record_latency(&self, timed: usize) {
+        let latency = match &self.latency {
+            Some(latency) => latency,
+            None => return,
+        };
+        let timings = self.api.timings().timings();
+        let samples: Vec<_> = timings
+            .requests
+            .iter()
+            .skip(timed)
+            .filter_map(|request| {
+                // Requests of concurrent searches sharing the timings are someone else's
+                let site = request.label.strip_suffix(" search")?;
+                self.config
+                    .sites
+                    .iter()
+                    .any(|s| s == site)
+                    .then_some((site, request.total))
+            })
+            .collect();
+        latency.record(&samples);
+    }
+
+    /// The results of the same search for `query`, if it ran within `cache_ttl_minutes`
+    fn search_fresh(&self, query: &str) -> Option<Vec<Question<String>>> {
+        let ttl = Duration::from_secs(self.config.cache_ttl_minutes.saturating_mul(60));
+        if ttl.is_zero() {
+            return None;
+        }
+        // An unreadable entry is as good as none, there's always the network
+        let entry = self
+            .cache
+            .as_ref()?
+            .fresh(&self.cache_key(query), ttl)
+            .ok()??;
+        Some(
+            entry
+                .questions
+                .into_iter()
+                .map(sanitize::question)
+                .collect(),
+        )
     }
 
-    /// Search using the configured search engine
-    pub async fn search(&self) -> Result<Vec<Question<String>>> {
-        match self.config.search_engine {
-            SearchEngine::DuckDuckGo => self.search_by_scraper(DuckDuckGo).await,
-            SearchEngine::Google => self.search_by_scraper(Google).await,
-            SearchEngine::StackExchange => self.parallel_search_advanced().await,
+    fn search_cache(&self, query: &str) -> Result<Vec<Question<String>>> {
+        if self.full_text {
+            return self.search_text(query);
         }
-        .and_then(|qs| {
-            if qs.is_empty() {
-                Err(Error::NoResults)
-            } else {
-                Ok(qs)
+        let entry = match &self.cache {
+            Some(cache) => cache.get(&self.cache_key(query))?,
+            None => None,
+        };
+        match entry {
+            Some(entry) => {
+                *self.offline_since.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some(entry.fetched_at);
+                // Cached by versions that didn't sanitize yet, perhaps
+                Ok(entry
+                    .questions
+                    .into_iter()
+                    .map(sanitize::question)
+                    .collect())
             }
-        })
+            None => Err(Error::NotCached(
+                query.to_string(),
+                self.cache
+                    .as_ref()
+                    .and_then(|c| c.closest(query, self.config.query_language)),
+            )),
+        }
+    }
+
+    /// The cached questions best matching `query`, from whichever searches they came up in, which
+    /// are as old as the oldest of those
+    fn search_text(&self, query: &str) -> Result<Vec<Question<String>>> {
+        let hits = match &self.cache {
+            Some(cache) => {
+                cache.search_text(query, self.config.query_language, self.config.limit.into())?
+            }
+            None => vec![],
+        };
+        *self.offline_since.lock().unwrap_or_else(|e| e.into_inner()) =
+            hits.iter().map(|hit| hit.fetched_at).min();
+        Ok(hits
+            .into_iter()
+            .map(|hit| sanitize::question(hit.question))
+            .collect())
+    }
+
+    fn cache_key(&self, query: &str) -> query_cache::Key {
+        query_cache::Key::new(&self.config, query).with_tag(self.tag())
     }
 
     /// Search query at duckduckgo and then fetch the resulting questions from SE.
-    async fn search_by_scraper(&self, scraper: impl Scraper) -> Result<Vec<Question<String>>> {
-        let url = scraper.get_url(&self.query, self.sites.values());
-        let html = Client::new()
-            .get(url)
-            .header(header::USER_AGENT, USER_AGENT)
-            .send()
-            .await?
-            .text()
-            .await?;
+    async fn search_by_scraper(
+        &self,
+        scraper: &impl Scraper,
+        query: &str,
+        deadline: Option<time::Instant>,
+    ) -> Result<Vec<Question<String>>> {
+        let augmented = self.tag().and_then(|tag| context::augment(query, tag));
+        let query = augmented.as_deref().unwrap_or(query);
+        let url = scraper.get_url(query, self.sites.values());
+        let start = Instant::now();
+        let engine = self.config.search_engine.to_string();
+        let failed = |e| self.network.failed(&engine, e);
+        let request = async {
+            let response = self
+                .network
+                .client()?
+                .get(url)
+                .header(header::USER_AGENT, USER_AGENT)
+                .send()
+                .await
+                .map_err(failed)?;
+            let ttfb = start.elapsed();
+            Ok::<_, Error>((ttfb, response.text().await.map_err(failed)?))
+        };
+        let (ttfb, html) = match deadline {
+            Some(deadline) => time::timeout_at(deadline, request)
+                .await
+                .map_err(|_| self.timeout())??,
+            None => request.await?,
+        };
+        self.api.timings().request(engine, ttfb, start.elapsed());
         let data = scraper.parse(&html, &self.sites, self.config.limit)?;
-        self.parallel_questions(data).await
+        if let Some(strategy) = data.strategy.as_deref().filter(|s| scraper::is_fallback(s)) {
+            self.messages.warn(format!(
+                "DuckDuckGo results were found with the {} fallback selector",
+                strategy
+            ));
+        }
+        *self
+            .scraper_strategy
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = data.strategy.clone();
+        self.parallel_questions(data, deadline).await
     }
 
     /// Parallel requests against the SE question endpoint across all sites in data.
-    // TODO I'm sure there is a way to DRY the following two functions
-    async fn parallel_questions(&self, data: ScrapedData) -> Result<Vec<Question<String>>> {
+    async fn parallel_questions(
+        &self,
+        data: ScrapedData,
+        deadline: Option<time::Instant>,
+    ) -> Result<Vec<Question<String>>> {
         let ScrapedData {
             question_ids,
             ordering,
-        } = data;
-        futures::stream::iter(question_ids)
+            ..
+        } = self.resolve_answers(data, deadline).await;
+        let gone = self.gone.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let requested = question_ids.clone();
+        // Unanswered questions are filtered out later, so that they can be counted
+        let tasks = question_ids
+            .into_iter()
+            .filter(|(site, _)| !gone.contains_key(site))
             .map(|(site, ids)| {
-                let api = self.api.clone();
-                tokio::spawn(async move {
-                    let api = &api;
-                    api.questions(&site, ids).await
-                })
-            })
-            .buffer_unordered(CONCURRENT_REQUESTS_LIMIT)
-            .collect::<Vec<_>>()
-            .await
+                let api = self.api.clone().with_unanswered(true);
+                (site.clone(), async move { api.questions(&site, ids).await })
+            });
+        let mut qs: Vec<Question<String>> = self
+            .collect_until(tasks, deadline, never)
+            .await?
             .into_iter()
-            .map(|r| r.map_err(Error::from).and_then(|x| x))
-            .collect::<Result<Vec<Vec<_>>>>()
-            .map(|v| {
-                let mut qs: Vec<Question<String>> = v.into_iter().flatten().collect();
-                qs.sort_unstable_by_key(|q| ordering.get(&q.id.to_string()).unwrap());
-                qs
-            })
+            .flatten()
+            .collect();
+        // Merged questions come back under the ids they were merged into, and any the merges
+        // can't account for go last
+        let ordering = self.follow_aliases(&requested, ordering);
+        // Ids are unique, so a stable sort orders them the same; caching keys formats each id once
+        qs.sort_by_cached_key(|q| {
+            ordering
+                .get(&q.id.to_string())
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+        rank_by_engine(&mut qs, &self.config.search_engine, &ordering);
+        Ok(qs)
+    }
+
+    /// `ordering`, with each of `question_ids` that was merged also ranked under its current id
+    fn follow_aliases(
+        &self,
+        question_ids: &HashMap<String, Vec<String>>,
+        ordering: HashMap<String, usize>,
+    ) -> HashMap<String, usize> {
+        let mut followed = ordering.clone();
+        for (site, ids) in question_ids {
+            let current = self.api.resolve_aliases(site, ids.clone());
+            for (id, current) in ids.iter().zip(current) {
+                if let Some(&rank) = ordering.get(id) {
+                    let ranked = followed.entry(current).or_insert(rank);
+                    *ranked = (*ranked).min(rank);
+                }
+            }
+        }
+        followed
+    }
+
+    /// `data`, with the questions its answer links were posted on added where the answers ranked.
+    /// Answers that can't be looked up, in time or at all, are left out with a warning.
+    async fn resolve_answers(
+        &self,
+        mut data: ScrapedData,
+        deadline: Option<time::Instant>,
+    ) -> ScrapedData {
+        let answer_ids = std::mem::take(&mut data.answer_ids);
+        if answer_ids.is_empty() {
+            return data;
+        }
+        let gone = self.gone.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let lookups = future::join_all(
+            answer_ids
+                .into_iter()
+                .filter(|(site, _)| !gone.contains_key(site))
+                .map(|(site, ids)| async move {
+                    let resolved = self.api.answer_question_ids(&site, &ids).await;
+                    (site, resolved)
+                }),
+        );
+        let resolved = match deadline {
+            Some(deadline) => match time::timeout_at(deadline, lookups).await {
+                Ok(resolved) => resolved,
+                Err(_) => {
+                    self.messages.warn(
+                        "--max-time reached before answer links were looked up; leaving them out",
+                    );
+                    return data;
+                }
+            },
+            None => lookups.await,
+        };
+        for (site, resolved) in resolved {
+            let pairs = match resolved {
+                Ok(pairs) => pairs,
+                Err(e) => {
+                    self.messages.warn(format!(
+                        "Couldn't look up the questions of answers linked on {}; leaving them out: {}",
+                        site, e
+                    ));
+                    continue;
+                }
+            };
+            for (answer_id, question_id) in pairs {
+                let rank = match data.ordering.get(&answer_id.to_string()) {
+                    Some(&rank) => rank,
+                    None => continue,
+                };
+                // Linked to as a question too, or through another of its answers
+                let question_id = question_id.to_string();
+                let ranked = data.ordering.entry(question_id.clone()).or_insert(rank);
+                *ranked = (*ranked).min(rank);
+                let ids = data.question_ids.entry(site.clone()).or_default();
+                if !ids.contains(&question_id) {
+                    ids.push(question_id);
+                }
+            }
+        }
+        data
     }
 
-    /// Parallel requests against the SE search/advanced endpoint across all configured sites
-    async fn parallel_search_advanced(&self) -> Result<Vec<Question<String>>> {
-        futures::stream::iter(self.config.sites.clone())
+    /// `query` as sent to the API: quoted in `--code-search` mode
+    fn api_query(&self, query: &str) -> Arc<str> {
+        if self.config.code_search {
+            code_search::quote(query).into()
+        } else {
+            query.into()
+        }
+    }
+
+    /// Parallel requests against the SE search/advanced endpoint across all configured sites,
+    /// along with the sites that have more results past the first page
+    async fn parallel_search_advanced(
+        &self,
+        query: &str,
+        deadline: Option<time::Instant>,
+    ) -> Result<(Vec<Question<String>>, Vec<More>)> {
+        let query = self.api_query(query);
+        let tag = self.tag();
+        let tasks: Vec<_> = self
+            .live_sites()?
+            .into_iter()
             .map(|site| {
-                let api = self.api.clone();
+                let api = self
+                    .api
+                    .clone()
+                    .with_unanswered(self.config.include_unanswered);
                 let limit = self.config.limit;
-                let query = self.query.clone();
-                tokio::spawn(async move {
-                    let api = &api;
-                    api.search_advanced(&query, &site, limit).await
-                })
+                let query = Arc::clone(&query);
+                let label = site.clone();
+                let task = async move {
+                    let tagged = api.clone().with_tagged(tag);
+                    let (page, tagged) =
+                        match tagged.search_advanced(&query, &site, limit, 1).await? {
+                            // Sites other than Stack Overflow may not know the tag at all
+                            page if page.questions.is_empty() && tag.is_some() => {
+                                (api.search_advanced(&query, &site, limit, 1).await?, false)
+                            }
+                            page => (page, tag.is_some()),
+                        };
+                    let more = More {
+                        site,
+                        tagged,
+                        fetched: page.questions.len(),
+                    };
+                    let more = Some(more).filter(|_| page.has_more);
+                    Ok((page.questions, more))
+                };
+                (label, task)
             })
-            .buffer_unordered(CONCURRENT_REQUESTS_LIMIT)
-            .collect::<Vec<_>>()
-            .await
+            .collect();
+        // Enough to fill `limit` several times over leaves little for the rest to add
+        let early_stop = self.config.early_stop && self.config.sites.len() > 1;
+        let plenty = usize::from(self.config.limit) * EARLY_STOP_FACTOR;
+        let enough = |results: &[(Vec<Question<String>>, Option<More>)]| {
+            early_stop && results.iter().map(|(qs, _)| qs.len()).sum::<usize>() >= plenty
+        };
+        let (qs, more): (Vec<_>, Vec<_>) = self
+            .collect_until(tasks, deadline, enough)
+            .await?
             .into_iter()
-            .map(|r| r.map_err(Error::from).and_then(|x| x))
-            .collect::<Result<Vec<Vec<_>>>>()
-            .map(|v| {
-                let mut qs: Vec<Question<String>> = v.into_iter().flatten().collect();
-                if self.config.sites.len() > 1 {
-                    qs.sort_unstable_by_key(|q| -q.score);
-                }
-                qs
-            })
+            .unzip();
+        let mut qs: Vec<Question<String>> = qs.into_iter().flatten().collect();
+        if self.config.sites.len() > 1 {
+            qs.sort_unstable_by_key(|q| -q.score);
+        }
+        Ok((qs, more.into_iter().flatten().collect()))
     }
-}
 
-/// Parse all markdown fields
-/// This only happens for content going into the cursive TUI (not lucky prompt)
-fn parse_markdown(qs: Vec<Question<String>>) -> Vec<Question<Markdown>> {
-    qs.into_par_iter()
-        .map(|q| {
-            let body = markdown::parse(q.body);
-            let answers = q
-                .answers
-                .into_par_iter()
-                .map(|a| {
-                    let body = markdown::parse(a.body);
-                    Answer {
-                        body,
-                        id: a.id,
-                        score: a.score,
-                        is_accepted: a.is_accepted,
-                    }
-                })
-                .collect::<Vec<_>>();
-            Question {
-                body,
-                answers,
-                id: q.id,
-                score: q.score,
-                title: q.title,
+    /// Run `tasks` via `collect_until`, flagging the search as partial if the deadline cut it off,
+    /// and recording the sites that failed if others didn't, and those `enough` stopped it from
+    /// waiting for. Fails only if nothing came back, with every site's error.
+    async fn collect_until<T, F, I, E>(
+        &self,
+        tasks: I,
+        deadline: Option<time::Instant>,
+        enough: E,
+    ) -> Result<Vec<T>>
+    where
+        I: IntoIterator<Item = (String, F)>,
+        F: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+        E: Fn(&[T]) -> bool,
+    {
+        let ceiling = self.config.max_concurrent_requests;
+        let Collected {
+            results,
+            failures,
+            cut_off,
+            skipped,
+        } = collect_until(tasks, deadline, ceiling, &self.cancel, enough).await?;
+        *self.early_stopped.lock().unwrap_or_else(|e| e.into_inner()) = skipped;
+        let mut failures = self.exclude_gone(failures).await;
+        if results.is_empty() {
+            match failures.len() {
+                0 if cut_off => return Err(self.timeout()),
+                0 => {}
+                1 => return Err(failures.remove(0)),
+                _ => return Err(Error::AllSites(failures)),
             }
-        })
-        .collect::<Vec<_>>()
-}
+        }
+        if cut_off {
+            self.partial.store(true, Ordering::Relaxed);
+            self.messages
+                .warn("--max-time reached before all sites responded; results are partial");
+        }
+        for failure in failures {
+            // Not a failure to retry, but a config to fix
+            if let Error::SiteGone { .. } = failure {
+                self.messages.warn(failure.to_string());
+                continue;
+            }
+            self.messages.warn(format!(
+                "Couldn't search {}; showing the other sites' results",
+                failure
+            ));
+            self.site_failures
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(failure.to_string());
+        }
+        Ok(results)
+    }
 
-// TODO find a query that returns no results so that I can test it and
+    /// `failures`, with those of sites the API no longer knows as `Error::SiteGone`, which the
+    /// rest of this invocation's requests leave out
+    async fn exclude_gone(&self, failures: Vec<Error>) -> Vec<Error> {
+        if !failures.iter().any(Error::is_unknown_site) {
+            return failures;
+        }
+        // A gone site stays in the listing cached before it went, so only a fresh one can tell
+        // where it went
+        let fresh = LocalStorage::from_sites(self.api.sites().await.unwrap_or_default());
+        let mut gone = self.gone.lock().unwrap_or_else(|e| e.into_inner());
+        failures
+            .into_iter()
+            .map(|failure| {
+                let site = match (failure.is_unknown_site(), failure.site()) {
+                    (true, Some(site)) => site.to_string(),
+                    _ => return failure,
+                };
+                let successor = self
+                    .sites
+                    .get(&site)
+                    .and_then(|url| fresh.successor(&site, url))
+                    .map(|successor| successor.api_site_parameter.clone());
+                gone.insert(site.clone(), successor.clone());
+                Error::SiteGone { site, successor }
+            })
+            .collect()
+    }
+
+    /// The configured sites, less those the API turned out not to know anymore. Fails with why
+    /// if that leaves none.
+    fn live_sites(&self) -> Result<Vec<String>> {
+        let gone = self.gone.lock().unwrap_or_else(|e| e.into_inner());
+        let (dead, live): (Vec<&String>, Vec<&String>) = self
+            .config
+            .sites
+            .iter()
+            .partition(|site| gone.contains_key(*site));
+        if live.is_empty() && !dead.is_empty() {
+            let mut errors: Vec<Error> = dead
+                .into_iter()
+                .map(|site| Error::SiteGone {
+                    site: site.clone(),
+                    successor: gone[site].clone(),
+                })
+                .collect();
+            return Err(match errors.len() {
+                1 => errors.remove(0),
+                _ => Error::AllSites(errors),
+            });
+        }
+        Ok(live.into_iter().cloned().collect())
+    }
+
+    fn timeout(&self) -> Error {
+        Error::Timeout(self.max_time.unwrap_or_default())
+    }
+}
+
+/// A site searched via the API that has more results past its first page
+#[derive(Debug, Clone)]
+struct More {
+    site: String,
+    /// Whether the search was narrowed down to the context's tag, as the next page has to be too
+    tagged: bool,
+    /// Questions the first page had
+    fetched: usize,
+}
+
+/// Another page fetched to make up for the questions filters left out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backfill {
+    /// Sites whose second page was fetched
+    pub sites: Vec<String>,
+    /// Questions it added to those shown
+    pub added: usize,
+}
+
+/// Sites of `more` with the best yield: the largest share of the questions of their first page
+/// among those `shown`, all of them if they're tied
+fn best_yield<'a>(more: &'a [More], shown: &[Question<String>]) -> Vec<&'a More> {
+    let mut best: Vec<(&More, usize)> = Vec::new();
+    for site in more.iter().filter(|m| m.fetched > 0) {
+        let kept = shown.iter().filter(|q| q.site == site.site).count();
+        // Shares compared as kept / fetched, without dividing
+        if let Some(&(top, top_kept)) = best.first() {
+            let share = kept * top.fetched;
+            let top_share = top_kept * site.fetched;
+            if share < top_share {
+                continue;
+            }
+            if share > top_share {
+                best.clear();
+            }
+        }
+        best.push((site, kept));
+    }
+    best.into_iter().map(|(site, _)| site).collect()
+}
+
+/// What `collect_until` got out of its tasks
+#[derive(Debug)]
+struct Collected<T> {
+    /// Results of the tasks that succeeded
+    results: Vec<T>,
+    /// Errors of the tasks that failed or panicked, each attributed to its site
+    failures: Vec<Error>,
+    /// Whether the deadline cut off any tasks
+    cut_off: bool,
+    /// Sites of the tasks called off, or never started, once the results were enough
+    skipped: Vec<String>,
+}
+
+/// Results of more sites than this times `limit` leave little for the rest to add, see
+/// `early_stop`
+const EARLY_STOP_FACTOR: usize = 3;
+
+/// For `collect_until` to wait for every task
+fn never<T>(_: &[T]) -> bool {
+    false
+}
+
+/// Run `tasks`, each labelled with the site it's for, concurrently (at most `ceiling` at a time)
+/// until all of them complete, `enough` says the results so far are enough, `deadline` passes or
+/// `cancel` is cancelled. Whichever tasks are still running then are aborted, along with their
+/// requests.
+///
+/// A task failing or panicking doesn't stop the others; only cancelling fails the lot.
+async fn collect_until<T, F, I, E>(
+    tasks: I,
+    deadline: Option<time::Instant>,
+    ceiling: usize,
+    cancel: &CancellationToken,
+    enough: E,
+) -> Result<Collected<T>>
+where
+    I: IntoIterator<Item = (String, F)>,
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: Send + 'static,
+    E: Fn(&[T]) -> bool,
+{
+    let tasks: Vec<(String, F)> = tasks.into_iter().collect();
+    let limit = concurrency(tasks.len(), ceiling);
+    let mut tasks = tasks.into_iter();
+    // Aborts whatever is still running when dropped
+    let mut running = JoinSet::new();
+    // Sites of the tasks running, in the order they started
+    let mut in_flight = Vec::new();
+    for (site, task) in tasks.by_ref().take(limit) {
+        in_flight.push(site.clone());
+        running.spawn(labelled(site, task));
+    }
+    let cut_off = async {
+        match deadline {
+            Some(deadline) => time::sleep_until(deadline).await,
+            None => futures::future::pending().await,
+        }
+    };
+    tokio::pin!(cut_off);
+    let mut collected = Collected {
+        results: Vec::new(),
+        failures: Vec::new(),
+        cut_off: false,
+        skipped: Vec::new(),
+    };
+    loop {
+        let next = tokio::select! {
+            _ = cancel.cancelled() => return Err(Error::Cancelled),
+            _ = &mut cut_off => {
+                collected.cut_off = true;
+                return Ok(collected);
+            }
+            next = running.join_next() => next,
+        };
+        let (site, result) = match next {
+            Some(next) => next?,
+            None => return Ok(collected),
+        };
+        if let Some(i) = in_flight.iter().position(|running| *running == site) {
+            in_flight.remove(i);
+        }
+        match result {
+            Ok(result) => collected.results.push(result),
+            Err(e) => collected.failures.push(e),
+        }
+        if enough(&collected.results) {
+            collected.skipped = in_flight.into_iter().chain(tasks.map(|t| t.0)).collect();
+            return Ok(collected);
+        }
+        if let Some((site, task)) = tasks.next() {
+            in_flight.push(site.clone());
+            running.spawn(labelled(site, task));
+        }
+    }
+}
+
+/// `attributed`, along with `site` to tell which task completed
+async fn labelled<T, F>(site: String, task: F) -> (String, Result<T>)
+where
+    F: Future<Output = Result<T>>,
+{
+    let result = attributed(site.clone(), task).await;
+    (site, result)
+}
+
+/// `task` with its error, or its panic, attributed to `site`
+async fn attributed<T, F>(site: String, task: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match AssertUnwindSafe(task).catch_unwind().await {
+        Ok(result) => result.map_err(|e| Error::at_site(site, e)),
+        Err(payload) => Err(Error::at_site(
+            site,
+            Error::Panic(isolation::panic_message(&*payload)),
+        )),
+    }
+}
+
+/// Aggregate title relevance below which `auto_requote` retries a search with the query quoted
+const REQUOTE_BELOW: f64 = 0.3;
+
+/// How a search was retried with the query quoted, because its results looked off-topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requote {
+    /// Aggregate title relevance of the original results
+    pub relevance: f64,
+    /// Aggregate title relevance of the quoted results, unless the retry failed
+    pub quoted_relevance: Option<f64>,
+}
+
+impl Requote {
+    /// Whether the quoted results were kept over the original ones
+    pub fn kept_quoted(&self) -> bool {
+        self.quoted_relevance.is_some_and(|r| r > self.relevance)
+    }
+}
+
+/// Run `search` for `query`, and if the results barely match it, once more for the query quoted,
+/// keeping whichever results match better
+async fn requoting<F, Fut>(
+    query: &str,
+    language: Language,
+    search: F,
+) -> Result<(Vec<Question<String>>, Option<Requote>)>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Vec<Question<String>>>>,
+{
+    let qs = search(query.to_string()).await?;
+    let relevance = relevance::aggregate(query, qs.iter().map(|q| q.title.as_str()), language);
+    let quoted = match relevance::quoted(query, language) {
+        Some(quoted) if relevance < REQUOTE_BELOW => quoted,
+        _ => return Ok((qs, None)),
+    };
+    let (quoted_qs, quoted_relevance) = match search(quoted).await {
+        Ok(quoted_qs) => {
+            let titles = quoted_qs.iter().map(|q| q.title.as_str());
+            let r = relevance::aggregate(query, titles, language);
+            (quoted_qs, Some(r))
+        }
+        Err(_) => (Vec::new(), None),
+    };
+    let requote = Requote {
+        relevance,
+        quoted_relevance,
+    };
+    let qs = if requote.kept_quoted() { quoted_qs } else { qs };
+    Ok((qs, Some(requote)))
+}
+
+/// How many of `tasks` to run at once: no more than there are, nor than `ceiling`, but at least
+/// one
+pub fn concurrency(tasks: usize, ceiling: usize) -> usize {
+    tasks.min(ceiling).max(1)
+}
+
+/// Process exit code for the lucky answer from `Search::search_lucky`: 0 when it's accepted,
+/// and otherwise `NOT_ACCEPTED_EXIT_CODE`
+pub fn lucky_exit_code(lucky: &Question<String>) -> i32 {
+    match lucky.answers.first() {
+        Some(answer) if answer.is_accepted => 0,
+        _ => NOT_ACCEPTED_EXIT_CODE,
+    }
+}
+
+/// Credit scraped questions to the search engine that found them, ranked as in its results
+/// (`ordering` counts from zero), rather than to the API requests that fetched them
+fn rank_by_engine<S>(
+    qs: &mut [Question<S>],
+    engine: &SearchEngine,
+    ordering: &HashMap<String, usize>,
+) {
+    for q in qs {
+        let rank = ordering.get(&q.id.to_string()).copied();
+        if let (Some(provenance), Some(rank)) = (q.provenance.as_mut(), rank) {
+            provenance.engine = engine.clone();
+            provenance.rank = rank + 1;
+        }
+    }
+}
+
+/// Parse all markdown fields
+/// This only happens for content going into the cursive TUI (not lucky prompt)
+/// Bodies `parser` panics on are shown as plain text instead, with the failure recorded in
+/// `failures`.
+#[cfg(feature = "tui")]
+fn parse_markdown(
+    qs: Vec<Question<String>>,
+    parser: fn(String, &[CodeMatch]) -> Markdown,
+    failures: &Failures,
+) -> Vec<Question<Markdown>> {
+    qs.into_par_iter()
+        .map(|q| {
+            let question_id = q.id;
+            let body = q
+                .body
+                .map(|body| parse_post(parser, &body, &[], question_id, None, failures));
+            let answers = q
+                .answers
+                .into_par_iter()
+                .map(|a| {
+                    let code_blocks = snippets::code_blocks(&a.body);
+                    let body = parse_post(
+                        parser,
+                        &a.body,
+                        &a.code_matches,
+                        question_id,
+                        Some(a.id),
+                        failures,
+                    );
+                    Answer {
+                        body,
+                        id: a.id,
+                        score: a.score,
+                        is_accepted: a.is_accepted,
+                        link: a.link,
+                        owner: None,
+                        creation_date: a.creation_date,
+                        duplicate_of: a.duplicate_of,
+                        code_matches: a.code_matches,
+                        code_blocks,
+                    }
+                })
+                .collect::<Vec<_>>();
+            Question {
+                body,
+                answers,
+                id: q.id,
+                score: q.score,
+                title: q.title,
+                tags: q.tags,
+                site: q.site,
+                creation_date: q.creation_date,
+                last_activity_date: q.last_activity_date,
+                link: q.link,
+                owner: None,
+                provenance: q.provenance,
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Parse the body of a single question or answer, isolated from all the others
+#[cfg(feature = "tui")]
+fn parse_post(
+    parser: fn(String, &[CodeMatch]) -> Markdown,
+    body: &str,
+    highlights: &[CodeMatch],
+    question_id: u32,
+    answer_id: Option<u32>,
+    failures: &Failures,
+) -> Markdown {
+    isolation::catch(|| parser(body.to_string(), highlights)).unwrap_or_else(|message| {
+        failures.record(Failure {
+            question_id,
+            answer_id,
+            stage: Stage::Parse,
+            message,
+        });
+        let mut fallback = Markdown::styled(
+            isolation::notice(answer_id.is_some()),
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Red)),
+                Style::from(Effect::Italic),
+            ]),
+        );
+        fallback.append_plain("\n\n");
+        fallback.append_plain(body);
+        fallback
+    })
+}
+
+// TODO find a query that returns no results so that I can test it and
 // differentiate it from a blocked request
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::config::Team;
+    use crate::stackexchange::answer_sort::AnswerSort;
+    use crate::stackexchange::api::{Request, Site};
+    use crate::stackexchange::filtered::Reason;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
+
+    /// Task for the site `name`, taking `delay_ms` to come back with its name
+    fn site(
+        name: &'static str,
+        delay_ms: u64,
+    ) -> (String, impl Future<Output = Result<&'static str>>) {
+        let task = async move {
+            time::sleep(Duration::from_millis(delay_ms)).await;
+            Ok(name)
+        };
+        (name.to_string(), task)
+    }
+
+    /// `collect_until` without cancellation
+    async fn collect<T, F, I>(
+        tasks: I,
+        deadline: Option<time::Instant>,
+        ceiling: usize,
+    ) -> Collected<T>
+    where
+        I: IntoIterator<Item = (String, F)>,
+        F: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        collect_until(tasks, deadline, ceiling, &CancellationToken::new(), never)
+            .await
+            .unwrap()
+    }
+
+    /// Local endpoint standing in for the network, counting every connection made to it
+    async fn tripwire() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while listener.accept().await.is_ok() {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        (format!("http://{}/2.3", addr), connections)
+    }
+
+    /// Config searching a team whose API is served at `api_url`
+    fn team_config(api_url: String, offline: bool) -> Config {
+        let mut config = Config {
+            team: Some(String::from("work")),
+            offline,
+            ..Config::default()
+        };
+        config.teams.insert(
+            String::from("work"),
+            Team {
+                api_url,
+                slug: String::from("acme"),
+                token: String::from("token"),
+            },
+        );
+        config
+    }
+
+    fn search(config: &Config, cache: &QueryCache) -> Search {
+        let ls = LocalStorage { sites: vec![] };
+        Search::new(config.clone(), ls).with_cache(cache.clone())
+    }
+
+    /// Parser with a bug triggered by certain bodies
+    #[cfg(feature = "tui")]
+    fn fragile_parse(body: String, highlights: &[CodeMatch]) -> Markdown {
+        if body.contains("BOOM") {
+            panic!("parser bug");
+        }
+        markdown::parse_with_highlights(body, highlights)
+    }
+
+    #[test]
+    fn test_rank_by_engine() {
+        use super::super::api::{Provenance, Request};
+
+        let mut qs = vec![
+            question(7, "Seven"),
+            question(3, "Three"),
+            question(5, "Five"),
+        ];
+        for (i, q) in qs.iter_mut().enumerate() {
+            q.provenance = Some(Provenance {
+                engine: SearchEngine::StackExchange,
+                site: String::from("stackoverflow"),
+                rank: i + 1,
+                request: Request::Batch { chunk: 0 },
+            });
+        }
+        // Questions the scraper didn't rank, e.g. ones an alias was followed to, keep theirs
+        let ordering = vec![("7", 0), ("3", 4)]
+            .into_iter()
+            .map(|(id, rank)| (id.to_string(), rank))
+            .collect();
+        rank_by_engine(&mut qs, &SearchEngine::DuckDuckGo, &ordering);
+        let ranked: Vec<_> = qs
+            .iter()
+            .map(|q| {
+                let p = q.provenance.as_ref().unwrap();
+                (p.engine.clone(), p.rank, p.request)
+            })
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                (SearchEngine::DuckDuckGo, 1, Request::Batch { chunk: 0 }),
+                (SearchEngine::DuckDuckGo, 5, Request::Batch { chunk: 0 }),
+                (SearchEngine::StackExchange, 3, Request::Batch { chunk: 0 }),
+            ]
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_parse_markdown_isolates_failures() {
+        let mut qs = vec![question(1, "Fine"), question(2, "Trouble")];
+        let fragile = Answer {
+            id: 21,
+            body: String::from("**BOOM** `x`"),
+            ..qs[1].answers[0].clone()
+        };
+        qs[1].answers.push(fragile);
+        let failures = Failures::default();
+        let qs = parse_markdown(qs, fragile_parse, &failures);
+
+        assert_eq!(qs.len(), 2);
+        let parsed = markdown::parse("Run `kubectl apply`");
+        assert_eq!(qs[0].answers[0].body, parsed);
+        assert_eq!(qs[1].answers[0].body, parsed);
+        // Shown as plain text, markup and all
+        assert_eq!(
+            qs[1].answers[1].body.source(),
+            "Rendering failed for this answer; showing it as plain text.\n\n**BOOM** `x`"
+        );
+        assert_eq!(
+            failures.list(),
+            vec![Failure {
+                question_id: 2,
+                answer_id: Some(21),
+                stage: Stage::Parse,
+                message: String::from("parser bug"),
+            }]
+        );
+    }
+
+    fn question(id: u32, title: &str) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            answers: vec![Answer {
+                id: id * 10,
+                score: 1,
+                body: String::from("Run `kubectl apply`"),
+                is_accepted: true,
+                ..Answer::default()
+            }],
+            title: title.to_string(),
+            body: Some(String::from("How do we deploy?")),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[tokio::test]
+    async fn test_offline_search_never_touches_network() {
+        let (api_url, connections) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-offline-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+
+        // Pre-populate the cache as an earlier online search would have
+        let online = Config {
+            cache_ttl_minutes: 0,
+            ..team_config(api_url.clone(), false)
+        };
+        let seeded = search(&online, &cache);
+        cache
+            .put(
+                &seeded.cache_key("deploy to staging"),
+                &[question(1, "Deploying to staging")],
+            )
+            .unwrap();
+
+        let offline = team_config(api_url, true);
+        let mut hit = search(&offline, &cache);
+        let qs = hit.search_md("Deploy to   staging").await.unwrap();
+        assert_eq!(qs.shown.len(), 1);
+        assert_eq!(qs.shown[0].title, "Deploying to staging");
+        assert!(hit.offline_since().is_some());
+        let lucky = hit.search_lucky("Deploy to   staging").await.unwrap();
+        assert_eq!(lucky.title, "Deploying to staging");
+
+        let miss = search(&offline, &cache);
+        match miss.search("staging database password").await {
+            Err(Error::NotCached(query, closest)) => {
+                assert_eq!(query, "staging database password");
+                assert_eq!(closest, Some(String::from("deploy to staging")));
+            }
+            _ => panic!("expected a not cached error"),
+        }
+        assert!(miss.offline_since().is_none());
+
+        // Unless it's looked for by what the cached questions say
+        let text = search(&offline, &cache).with_full_text(true);
+        let qs = text.search("staging database password").await.unwrap();
+        assert_eq!(qs[0].title, "Deploying to staging");
+        assert!(text.offline_since().is_some());
+        assert!(matches!(
+            text.search("rollback").await,
+            Err(Error::NoResults)
+        ));
+
+        // Give any stray connection a chance to land
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+
+        // Whereas going online does try to reach the API
+        assert!(seeded.search("deploy to staging").await.is_err());
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(connections.load(Ordering::SeqCst) > 0);
+    }
+
+    #[tokio::test]
+    async fn test_recent_search_served_from_cache() {
+        let (api_url, connections) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-fresh-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir.clone());
+        let config = team_config(api_url, false);
+
+        // A miss goes to the network, which fails here and caches nothing
+        let miss = search(&config, &cache);
+        assert!(miss.search("deploy to staging").await.is_err());
+        time::sleep(Duration::from_millis(50)).await;
+        let misses = connections.load(Ordering::SeqCst);
+        assert!(misses > 0);
+        assert!(cache
+            .get(&miss.cache_key("deploy to staging"))
+            .unwrap()
+            .is_none());
+
+        // A hit doesn't, and isn't passed off as offline
+        cache
+            .put(
+                &miss.cache_key("deploy to staging"),
+                &[question(1, "Deploying to staging")],
+            )
+            .unwrap();
+        let hit = search(&config, &cache);
+        let qs = hit.search("Deploy to staging").await.unwrap();
+        assert_eq!(qs[0].title, "Deploying to staging");
+        assert!(hit.offline_since().is_none());
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(connections.load(Ordering::SeqCst), misses);
+
+        // Unless caching is off, as with `--no-cache`
+        let bypass = Config {
+            cache_ttl_minutes: 0,
+            ..config
+        };
+        assert!(search(&bypass, &cache)
+            .search("deploy to staging")
+            .await
+            .is_err());
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(connections.load(Ordering::SeqCst) > misses);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_context_added() {
+        let dir = std::env::temp_dir().join(format!("so-context-added-{}", std::process::id()));
+        let cache = QueryCache::new(dir);
+        let rust = || {
+            Some(context::Context {
+                tag: "rust",
+                manifest: PathBuf::from("/src/so/Cargo.toml"),
+            })
+        };
+        let ddg = Config::default();
+        assert_eq!(search(&ddg, &cache).context_added("sort a vec"), None);
+        assert_eq!(
+            search(&ddg, &cache)
+                .with_context(rust())
+                .context_added("sort a vec"),
+            Some(String::from(
+                "searched for \"sort a vec rust\", from /src/so/Cargo.toml"
+            ))
+        );
+        assert_eq!(
+            search(&ddg, &cache)
+                .with_context(rust())
+                .context_added("sort a Rust vec"),
+            Some(String::from(
+                "nothing added, the query already mentions rust, from /src/so/Cargo.toml"
+            ))
+        );
+        let api = team_config(String::from("http://localhost"), false);
+        assert_eq!(
+            search(&api, &cache)
+                .with_context(rust())
+                .context_added("sort a vec"),
+            Some(String::from("tagged [rust], from /src/so/Cargo.toml"))
+        );
+
+        // Results narrowed down by a tag are cached apart from the others
+        let tagged = search(&ddg, &cache).with_context(rust());
+        assert_ne!(
+            tagged.cache_key("sort a vec"),
+            search(&ddg, &cache).cache_key("sort a vec")
+        );
+        assert_eq!(
+            tagged.cache_key("sort a vec").tag,
+            Some(String::from("rust"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filtered_out_kept_with_reason() {
+        let (api_url, _) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-filtered-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+        let config = team_config(api_url, true);
+        let mut unanswered = question(2, "Deploying on Fridays");
+        unanswered.answers.clear();
+        let qs = [
+            question(1, "Deploying by hand"),
+            unanswered,
+            question(3, "Deploying with CI"),
+        ];
+        let seed = |config: &Config| {
+            let seeded = search(config, &cache);
+            cache.put(&seeded.cache_key("deploy"), &qs).unwrap();
+        };
+        seed(&config);
+
+        let qs = search(&config, &cache)
+            .search_filtered("deploy")
+            .await
+            .unwrap();
+        assert_eq!(
+            qs.shown.iter().map(|q| q.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(qs.hidden.len(), 1);
+        assert_eq!(qs.hidden[0].question.title, "Deploying on Fridays");
+        assert_eq!(qs.hidden[0].reason, Reason::Unanswered);
+
+        // Nothing is left out when unanswered questions are wanted
+        let everything = Config {
+            include_unanswered: true,
+            ..config
+        };
+        seed(&everything);
+        let qs = search(&everything, &cache)
+            .search_filtered("deploy")
+            .await
+            .unwrap();
+        assert_eq!(qs.shown.len(), 3);
+        assert!(qs.hidden.is_empty());
+    }
+
+    /// Records the questions it's shown, then hides those with `word` in their title
+    struct Hide {
+        word: &'static str,
+        seen: Arc<Mutex<Vec<Vec<u32>>>>,
+    }
+
+    impl ResultTransformer for Hide {
+        fn transform(&self, results: Filtered<String>) -> Filtered<String> {
+            let ids = results.shown.iter().map(|q| q.id).collect();
+            self.seen.lock().unwrap().push(ids);
+            results.filter(Reason::Other("not for us"), |q| {
+                !q.title.contains(self.word)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transformers_run_after_builtins_in_order() {
+        let (api_url, _) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-transformers-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+        let config = team_config(api_url, true);
+        let mut unanswered = question(2, "Deploying on Fridays");
+        unanswered.answers.clear();
+        let qs = [
+            question(1, "Deploying by hand"),
+            unanswered,
+            question(3, "Deploying with CI"),
+            question(4, "Deploying with CI by hand"),
+        ];
+        cache
+            .put(&search(&config, &cache).cache_key("deploy"), &qs)
+            .unwrap();
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let hide = |word| {
+            Box::new(Hide {
+                word,
+                seen: Arc::clone(&seen),
+            })
+        };
+        let qs = search(&config, &cache)
+            .with_transformer(hide("hand"))
+            .with_transformer(hide("CI"))
+            .search_filtered("deploy")
+            .await;
+        // Unanswered questions are hidden before either transformer sees them, and the second only
+        // sees what the first kept
+        assert_eq!(*seen.lock().unwrap(), vec![vec![1, 3, 4], vec![3]]);
+        // Which leaves nothing to show
+        assert!(matches!(qs, Err(Error::NoResults)));
+    }
+
+    #[cfg(feature = "tui")]
+    #[tokio::test]
+    async fn test_link_rewrites_leave_cache_alone() {
+        let (api_url, _) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-link-rewrites-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+        let config = Config {
+            link_rewrites: serde_yaml::from_str("stackoverflowteams.com: teams.corp").unwrap(),
+            ..team_config(api_url, true)
+        };
+        let mut q = question(1, "Deploying by hand");
+        q.link = Some(String::from(
+            "https://stackoverflowteams.com/c/acme/questions/1",
+        ));
+        q.answers[0].body = String::from("See https://stackoverflowteams.com/c/acme/a/2");
+        let search = search(&config, &cache);
+        cache.put(&search.cache_key("deploy"), &[q]).unwrap();
+
+        let qs = search.search("deploy").await.unwrap();
+        assert_eq!(
+            qs[0].link.as_deref(),
+            Some("https://teams.corp/c/acme/questions/1")
+        );
+        assert_eq!(qs[0].answers[0].body, "See https://teams.corp/c/acme/a/2");
+        assert_eq!(search.site_urls()["work"], "https://teams.corp/c/acme");
+        let md = search.search_md("deploy").await.unwrap();
+        assert_eq!(
+            md.shown[0].link.as_deref(),
+            Some("https://teams.corp/c/acme/questions/1")
+        );
+        let cached = cache.get(&search.cache_key("deploy")).unwrap().unwrap();
+        assert_eq!(
+            cached.questions[0].link.as_deref(),
+            Some("https://stackoverflowteams.com/c/acme/questions/1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lucky_accepted() {
+        let (api_url, _) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-lucky-accepted-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+        let config = team_config(api_url, true);
+        let mut best_effort = question(1, "Deploying by hand");
+        best_effort.answers[0].is_accepted = false;
+        let seed = |qs: &[Question<String>]| {
+            let seeded = search(&config, &cache);
+            cache.put(&seeded.cache_key("deploy"), qs).unwrap();
+        };
+
+        // The top answer of the top question, accepted or not
+        seed(&[best_effort.clone(), question(2, "Deploying with CI")]);
+        let lucky = search(&config, &cache)
+            .search_lucky("deploy")
+            .await
+            .unwrap();
+        assert_eq!(lucky.id, 1);
+        assert_eq!(lucky_exit_code(&lucky), NOT_ACCEPTED_EXIT_CODE);
+
+        // Unless an accepted answer is required, which skips ahead
+        let strict = Config {
+            require_accepted: true,
+            ..config.clone()
+        };
+        let lucky = search(&strict, &cache)
+            .search_lucky("deploy")
+            .await
+            .unwrap();
+        assert_eq!(lucky.id, 2);
+        assert_eq!(lucky_exit_code(&lucky), 0);
+
+        // Or finds nothing
+        seed(&[best_effort]);
+        match search(&strict, &cache).search_lucky("deploy").await {
+            Err(e @ Error::NoResults) => assert_eq!(e.exit_code(), 2),
+            _ => panic!("expected no results"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lucky_answer() {
+        let (api_url, _) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-lucky-answer-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+        let config = team_config(api_url, true);
+        let mut deploy = question(1, "Deploying");
+        let second = Answer {
+            id: 11,
+            body: String::from("Run `helm upgrade`"),
+            ..deploy.answers[0].clone()
+        };
+        deploy.answers.push(second);
+        let seeded = search(&config, &cache);
+        cache.put(&seeded.cache_key("deploy"), &[deploy]).unwrap();
+        let (config, cache) = (&config, &cache);
+        let answer = |n| async move {
+            search(config, cache)
+                .with_lucky_answer(n)
+                .search_lucky("deploy")
+                .await
+                .map(|q| q.answers.iter().map(|a| a.id).collect::<Vec<_>>())
+        };
+
+        assert_eq!(answer(1).await.unwrap(), vec![10]);
+        assert_eq!(answer(2).await.unwrap(), vec![11]);
+        assert!(matches!(
+            answer(3).await,
+            Err(Error::NoSuchAnswer {
+                wanted: 3,
+                found: 2
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_lucky_answer_sort() {
+        let (api_url, _) = tripwire().await;
+        let dir = std::env::temp_dir().join(format!("so-answer-sort-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir);
+        let config = team_config(api_url, true);
+        // The accepted answer is outvoted, and the newest has the fewest votes
+        let mut deploy = question(1, "Deploying");
+        deploy.answers[0].creation_date = Some(1_300_000_000);
+        let other = |id, score, creation_date| Answer {
+            id,
+            score,
+            is_accepted: false,
+            creation_date: Some(creation_date),
+            ..deploy.answers[0].clone()
+        };
+        let (newest, top) = (other(11, 0, 1_600_000_000), other(12, 90, 1_400_000_000));
+        deploy.answers.extend(vec![newest, top]);
+        let seeded = search(&config, &cache);
+        cache.put(&seeded.cache_key("deploy"), &[deploy]).unwrap();
+        let (config, cache) = (&config, &cache);
+        let lucky = |answer_sort| async move {
+            let config = Config {
+                answer_sort,
+                ..config.clone()
+            };
+            let lucky = search(&config, cache).search_lucky("deploy").await;
+            lucky.unwrap().answers[0].id
+        };
+
+        assert_eq!(lucky(AnswerSort::Votes).await, 12);
+        assert_eq!(lucky(AnswerSort::Accepted).await, 10);
+        assert_eq!(lucky(AnswerSort::Newest).await, 11);
+    }
+
+    #[tokio::test]
+    async fn test_requoting() {
+        // Mock DuckDuckGo: off-topic hits for the bare query, on-topic ones for the quoted query
+        let calls = AtomicUsize::new(0);
+        let mock = |off_topic: bool, quoted_fails: bool| {
+            let calls = &calls;
+            move |query: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let quoted = query.starts_with('"') && query.ends_with('"');
+                async move {
+                    match (quoted, off_topic, quoted_fails) {
+                        (false, true, _) => Ok(vec![
+                            question(1, "Kubernetes ingress annotations"),
+                            question(2, "Helm chart values"),
+                        ]),
+                        (true, _, true) => Err(Error::Blocked {
+                            engine: "DuckDuckGo",
+                        }),
+                        _ => Ok(vec![
+                            question(3, "Rolling back a deploy to staging"),
+                            question(4, "Deploy to staging from CI"),
+                        ]),
+                    }
+                }
+            }
+        };
+
+        // On-topic results are kept without a retry
+        calls.store(0, Ordering::SeqCst);
+        let (qs, requote) = requoting(
+            "rollback deploy staging",
+            Language::English,
+            mock(false, false),
+        )
+        .await
+        .unwrap();
+        assert_eq!(qs[0].id, 3);
+        assert_eq!(requote, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Off-topic results are retried once, quoted, and the better results kept
+        calls.store(0, Ordering::SeqCst);
+        let (qs, requote) = requoting(
+            "rollback deploy staging",
+            Language::English,
+            mock(true, false),
+        )
+        .await
+        .unwrap();
+        assert_eq!(qs[0].id, 3);
+        let requote = requote.unwrap();
+        assert_eq!(requote.relevance, 0.0);
+        assert!(requote.quoted_relevance.unwrap() > REQUOTE_BELOW);
+        assert!(requote.kept_quoted());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // A failed retry keeps the original results rather than failing the search
+        calls.store(0, Ordering::SeqCst);
+        let (qs, requote) = requoting(
+            "rollback deploy staging",
+            Language::English,
+            mock(true, true),
+        )
+        .await
+        .unwrap();
+        assert_eq!(qs[0].id, 1);
+        assert_eq!(requote.unwrap().quoted_relevance, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Queries that are already quoted, or a single term, aren't retried
+        for query in &["\"rollback deploy\" staging", "kubernetes"] {
+            calls.store(0, Ordering::SeqCst);
+            let (qs, requote) = requoting(query, Language::English, mock(true, false))
+                .await
+                .unwrap();
+            assert_eq!(qs[0].id, 1);
+            assert_eq!(requote, None);
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_api_refuses_requests() {
+        let api = Api::new(None, None, OfflinePolicy::Offline);
+        assert!(matches!(api.sites().await, Err(Error::Offline)));
+        let qs = api
+            .questions("stackoverflow", vec![String::from("1")])
+            .await;
+        assert!(matches!(qs, Err(Error::Offline)));
+    }
 
     #[test]
     fn test_duckduckgo_response() {
         // TODO make sure results are either 1) answers 2) failed connection 3) blocked
     }
+
+    #[tokio::test]
+    async fn test_collect_until_cut_off() {
+        let start = time::Instant::now();
+        let deadline = start + Duration::from_millis(300);
+        let tasks = vec![site("slow", 10_000), site("fast", 10), site("faster", 1)];
+        let Collected {
+            mut results,
+            cut_off,
+            ..
+        } = collect(tasks, Some(deadline), 8).await;
+        results.sort_unstable();
+        assert_eq!(results, vec!["fast", "faster"]);
+        assert!(cut_off);
+        // Didn't wait for the slow site
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_all_in_time() {
+        let deadline = time::Instant::now() + Duration::from_secs(10);
+        let tasks = vec![site("a", 20), site("b", 1)];
+        let collected = collect(tasks, Some(deadline), 8).await;
+        assert_eq!(collected.results.len(), 2);
+        assert!(!collected.cut_off);
+
+        let collected = collect(vec![site("a", 1)], None, 8).await;
+        assert_eq!(collected.results, vec!["a"]);
+        assert!(!collected.cut_off);
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_nothing_in_time() {
+        let deadline = time::Instant::now() + Duration::from_millis(50);
+        let collected = collect(vec![site("slow", 10_000)], Some(deadline), 8).await;
+        assert!(collected.results.is_empty());
+        assert!(collected.cut_off);
+    }
+
+    /// Task recording how many tasks sharing `in_flight` ran at once, in `peak`
+    async fn tracked(in_flight: Arc<AtomicUsize>, peak: Arc<AtomicUsize>) -> Result<()> {
+        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        peak.fetch_max(now, Ordering::SeqCst);
+        time::sleep(Duration::from_millis(20)).await;
+        in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrency() {
+        assert_eq!(concurrency(1, 8), 1);
+        assert_eq!(concurrency(2, 8), 2);
+        assert_eq!(concurrency(30, 8), 8);
+        assert_eq!(concurrency(0, 8), 1);
+        assert_eq!(concurrency(5, 0), 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_bounds_concurrency() {
+        for (tasks, ceiling, expected) in &[(20, 3, 3), (2, 8, 2), (5, 1, 1)] {
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            let peak = Arc::new(AtomicUsize::new(0));
+            let tasks =
+                (0..*tasks).map(|i| (i.to_string(), tracked(in_flight.clone(), peak.clone())));
+            let collected = collect(tasks, None, *ceiling).await;
+            assert_eq!(peak.load(Ordering::SeqCst), *expected);
+            assert!(!collected.results.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_aborts_cut_off_tasks() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        let slow = async move {
+            time::sleep(Duration::from_millis(200)).await;
+            flag.store(true, Ordering::SeqCst);
+            Ok(())
+        };
+        let deadline = time::Instant::now() + Duration::from_millis(20);
+        collect(vec![(String::from("slow"), slow)], Some(deadline), 8).await;
+        time::sleep(Duration::from_millis(400)).await;
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    /// What the API responds with to too many requests
+    fn throttled() -> Error {
+        Error::Api {
+            site: None,
+            endpoint: None,
+            status: 502,
+            name: Some(String::from("throttle_violation")),
+            message: String::from("throttled"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_attributes_failures() {
+        let failing = async { Err::<(), _>(throttled()) };
+        let tasks = vec![(String::from("superuser"), failing)];
+        let mut collected = collect(tasks, None, 8).await;
+        let e = collected.failures.remove(0);
+        assert_eq!(
+            e.to_string(),
+            "superuser: StackExchange API error 502 (throttle_violation): throttled"
+        );
+        assert_eq!(e.site(), Some("superuser"));
+        assert_eq!(e.status(), Some(502));
+        assert!(std::error::Error::source(&e).is_some());
+
+        let panicking = async {
+            if true {
+                panic!("bad item");
+            }
+            Ok(())
+        };
+        let tasks = vec![(String::from("meta"), panicking)];
+        let mut collected = collect(tasks, None, 8).await;
+        let e = collected.failures.remove(0);
+        assert!(matches!(&e, Error::Site { site, source }
+            if site == "meta" && matches!(**source, Error::Panic(ref m) if m == "bad item")));
+        assert_eq!(e.to_string(), "meta: Panicked: bad item");
+        assert_eq!(e.exit_code(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_keeps_going_after_failures() {
+        let failing = async { Err(throttled()) };
+        let tasks = vec![
+            (String::from("superuser"), failing.boxed()),
+            (String::from("stackoverflow"), async { Ok("so") }.boxed()),
+        ];
+        let collected = collect(tasks, None, 1).await;
+        assert_eq!(collected.results, vec!["so"]);
+        assert_eq!(collected.failures.len(), 1);
+        assert!(!collected.cut_off);
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_cancelled() {
+        let cancel = CancellationToken::new();
+        let start = time::Instant::now();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let tasks = vec![site("slow", 10_000), site("fast", 1)];
+        let result = collect_until(tasks, None, 8, &cancel, never).await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_collect_until_enough() {
+        let start = time::Instant::now();
+        let tasks = vec![
+            site("slow", 10_000),
+            site("fast", 10),
+            site("faster", 1),
+            site("queued", 1),
+        ];
+        let enough = |results: &[&str]| results.len() >= 2;
+        let Collected {
+            mut results,
+            skipped,
+            cut_off,
+            ..
+        } = collect_until(tasks, None, 2, &CancellationToken::new(), enough)
+            .await
+            .unwrap();
+        results.sort_unstable();
+        assert_eq!(results, vec!["fast", "faster"]);
+        // Running, then never started
+        assert_eq!(skipped, vec!["slow", "queued"]);
+        assert!(!cut_off);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // Nothing's skipped once everything's in
+        let tasks = vec![site("fast", 10), site("faster", 1)];
+        let collected = collect_until(tasks, None, 8, &CancellationToken::new(), enough)
+            .await
+            .unwrap();
+        assert_eq!(collected.results.len(), 2);
+        assert!(collected.skipped.is_empty());
+    }
+
+    /// Endpoint that never responds, reporting every connection it accepts, and when the client
+    /// hangs up on it
+    async fn unresponsive() -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        use tokio::io::AsyncReadExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let closed = Arc::new(AtomicUsize::new(0));
+        let (on_accept, on_close) = (accepted.clone(), closed.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                on_accept.fetch_add(1, Ordering::SeqCst);
+                let on_close = on_close.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {}
+                    on_close.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+        (format!("http://{}/2.3", addr), accepted, closed)
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_requests() {
+        let (api_url, accepted, closed) = unresponsive().await;
+        let config = Config {
+            search_engine: SearchEngine::StackExchange,
+            ..team_config(api_url, false)
+        };
+        let cancel = CancellationToken::new();
+        let ls = LocalStorage { sites: vec![] };
+        let search = Search::new(config, ls).with_cancellation(cancel.clone());
+        let running = tokio::spawn(async move { search.search("deploy").await });
+        while accepted.load(Ordering::SeqCst) == 0 {
+            time::sleep(Duration::from_millis(5)).await;
+        }
+        let start = time::Instant::now();
+        cancel.cancel();
+        let result = time::timeout(Duration::from_secs(5), running)
+            .await
+            .expect("search kept running after being cancelled")
+            .unwrap();
+        assert!(matches!(result, Err(Error::Cancelled)));
+        // The request in flight was dropped, closing its connection
+        while closed.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[tokio::test]
+    async fn test_interrupt_aborts_requests() {
+        let (api_url, accepted, closed) = unresponsive().await;
+        let config = Config {
+            search_engine: SearchEngine::StackExchange,
+            ..team_config(api_url, false)
+        };
+        let ls = LocalStorage { sites: vec![] };
+        let search = Search::new(config, ls);
+        // Stands in for Ctrl-C, once the request is in flight
+        let interrupt = async {
+            while accepted.load(Ordering::SeqCst) == 0 {
+                time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+        let start = time::Instant::now();
+        let result = time::timeout(
+            Duration::from_secs(5),
+            crate::term::interruptible(search.search("deploy"), interrupt),
+        )
+        .await
+        .expect("search kept running after being interrupted");
+        assert!(matches!(result, Err(Error::Interrupted)));
+        assert_eq!(result.unwrap_err().exit_code(), 130);
+        // Dropping the search aborted its request, closing the connection
+        while closed.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    /// API searching each site of `sites` in its delay, in milliseconds, with a question titled
+    /// after the site, or with nothing for the site `empty`. The sites `broken` and `down` always
+    /// fail, and `flaky` fails searches for "fail". Reports the sites searched, and how many
+    /// searches the client hung up on before they came back.
+    async fn sites_server(
+        sites: &'static [(&'static str, u64)],
+    ) -> (String, Arc<Mutex<Vec<String>>>, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let searched = Arc::new(Mutex::new(Vec::new()));
+        let hung_up = Arc::new(AtomicUsize::new(0));
+        let (on_search, on_hang_up) = (searched.clone(), hung_up.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (on_search, on_hang_up) = (on_search.clone(), on_hang_up.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                            _ => return,
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let line = request.lines().next().unwrap_or_default();
+                    let body = if line.contains("/filters/create") {
+                        String::from(r#"{"items": [{"filter": "derived"}]}"#)
+                    } else if line.contains("/sites?") {
+                        String::from(include_str!("../../test/stackexchange/sites.json"))
+                    } else {
+                        let site = line
+                            .split(['?', '&', ' '])
+                            .find_map(|param| param.strip_prefix("site="))
+                            .unwrap_or_default()
+                            .to_string();
+                        on_search.lock().unwrap().push(site.clone());
+                        let delay = sites
+                            .iter()
+                            .find(|(name, _)| *name == site)
+                            .map_or(0, |(_, delay)| *delay);
+                        tokio::select! {
+                            _ = time::sleep(Duration::from_millis(delay)) => {}
+                            _ = socket.read(&mut buf) => {
+                                on_hang_up.fetch_add(1, Ordering::SeqCst);
+                                return;
+                            }
+                        }
+                        match site.as_str() {
+                            "empty" => String::from(r#"{"items": []}"#),
+                            "broken" | "down" => String::from("Service Unavailable"),
+                            "flaky" if line.contains("q=fail") => {
+                                String::from("Service Unavailable")
+                            }
+                            "programmers" | "windowsphone" => format!(
+                                r#"{{"error_id": 400, "error_name": "bad_parameter",
+                                "error_message": "No site found for name `{}`"}}"#,
+                                site
+                            ),
+                            _ if line.contains("/answers/") => {
+                                String::from(r#"{"items": [{"answer_id": 2, "question_id": 1}]}"#)
+                            }
+                            _ => format!(
+                                r#"{{"items": [{{"question_id": 1, "score": 1, "title": "{0}",
+                                "answers": [{{"answer_id": 2, "score": 1,
+                                "body_markdown": "From {0}", "is_accepted": true}}]}}]}}"#,
+                                site
+                            ),
+                        }
+                    };
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (format!("http://{}", addr), searched, hung_up)
+    }
+
+    /// Config searching `sites` via the API served at `api_url`, picking the lucky site by
+    /// `strategy`
+    fn sites_config(api_url: String, sites: &[&str], strategy: LuckySiteStrategy) -> Config {
+        Config {
+            api_url,
+            search_engine: SearchEngine::StackExchange,
+            sites: sites.iter().map(|site| site.to_string()).collect(),
+            lucky_site_strategy: strategy,
+            ..Config::default()
+        }
+    }
+
+    async fn lucky_title(config: &Config, latency: &Latency) -> Result<String> {
+        let ls = LocalStorage { sites: vec![] };
+        Search::new(config.clone(), ls)
+            .with_latency(latency.clone())
+            .search_lucky("deploy")
+            .await
+            .map(|q| q.title)
+    }
+
+    #[tokio::test]
+    async fn test_lucky_site_strategies() {
+        let (api_url, searched, _) = sites_server(&[("slow", 300), ("quick", 0)]).await;
+        let latency = Latency::default();
+
+        // Always the first site
+        let first = sites_config(
+            api_url.clone(),
+            &["slow", "quick"],
+            LuckySiteStrategy::First,
+        );
+        assert_eq!(lucky_title(&first, &latency).await.unwrap(), "slow");
+        assert_eq!(lucky_title(&first, &latency).await.unwrap(), "slow");
+        assert!(latency.average("slow").unwrap() >= Duration::from_millis(300));
+
+        // The quickest site, once every site was measured
+        let fastest = Config {
+            lucky_site_strategy: LuckySiteStrategy::Fastest,
+            ..first
+        };
+        assert_eq!(lucky_title(&fastest, &latency).await.unwrap(), "quick");
+        assert_eq!(lucky_title(&fastest, &latency).await.unwrap(), "quick");
+        assert!(latency.average("quick").unwrap() < latency.average("slow").unwrap());
+        assert_eq!(
+            *searched.lock().unwrap(),
+            vec!["slow", "slow", "quick", "quick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remembered() {
+        let (api_url, _, _) = sites_server(&[]).await;
+        let config = sites_config(api_url, &["quick"], LuckySiteStrategy::First);
+        let ls = LocalStorage { sites: vec![] };
+        let search = Search::new(config, ls);
+        let remembered = |site: &str, answer_id| Remembered {
+            site: site.to_string(),
+            question_id: 1,
+            answer_id,
+        };
+
+        let question = search.remembered(&remembered("quick", 2)).await;
+        let question = question.unwrap().unwrap();
+        assert_eq!(question.title, "quick");
+        assert_eq!(question.answers.len(), 1);
+        assert_eq!(question.answers[0].id, 2);
+        // Gone, so lucky mode goes back to searching
+        let gone = search.remembered(&remembered("quick", 3)).await;
+        assert!(gone.unwrap().is_none());
+        let gone = search.remembered(&remembered("empty", 2)).await;
+        assert!(gone.unwrap().is_none());
+        // Which isn't the same as failing to tell
+        assert!(search.remembered(&remembered("broken", 2)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gone_sites_left_out() {
+        let (api_url, searched, _) = sites_server(&[]).await;
+        // As cached before the sites went
+        let ls = || LocalStorage {
+            sites: serde_json::from_str(
+                r#"[
+                    {"api_site_parameter": "programmers",
+                     "site_url": "programmers.stackexchange.com"},
+                    {"api_site_parameter": "windowsphone",
+                     "site_url": "windowsphone.stackexchange.com"}
+                ]"#,
+            )
+            .unwrap(),
+        };
+        let config = sites_config(
+            api_url.clone(),
+            &["programmers", "quick", "windowsphone"],
+            LuckySiteStrategy::First,
+        );
+        let search = Search::new(config, ls());
+        let titles = |qs: Vec<Question<String>>| -> Vec<String> {
+            qs.into_iter().map(|q| q.title).collect()
+        };
+        assert_eq!(
+            titles(search.search("deploy").await.unwrap()),
+            vec!["quick"]
+        );
+        let warnings: Vec<String> = search
+            .messages()
+            .read()
+            .into_iter()
+            .map(|m| m.text)
+            .collect();
+        assert!(warnings.contains(&String::from(
+            "site 'programmers' no longer exists \u{2014} it moved to 'softwareengineering'; \
+             use that in your config instead"
+        )));
+        assert!(warnings.contains(&String::from(
+            "site 'windowsphone' no longer exists \u{2014} remove it from your config"
+        )));
+        // Not failures, which would keep the results out of the cache
+        assert!(search.site_failures().is_empty());
+        let gone: Vec<String> = search.gone_sites().iter().map(Error::to_string).collect();
+        assert_eq!(gone.len(), 2);
+        assert!(gone[0].starts_with("site 'programmers' "));
+        // Nor are they searched again
+        searched.lock().unwrap().clear();
+        search.search("deploy").await.unwrap();
+        assert_eq!(*searched.lock().unwrap(), vec!["quick"]);
+
+        // Nothing else to search
+        let config = sites_config(api_url, &["programmers"], LuckySiteStrategy::First);
+        let search = Search::new(config, ls());
+        for _ in 0..2 {
+            match search.search("deploy").await {
+                Err(Error::SiteGone { site, successor }) => {
+                    assert_eq!(site, "programmers");
+                    assert_eq!(successor.as_deref(), Some("softwareengineering"));
+                }
+                r => panic!("expected the site to be gone, got {:?}", r.map(titles)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_early_stop() {
+        let (api_url, _, hung_up) =
+            sites_server(&[("slow", 1500), ("quick", 10), ("fast", 20), ("swift", 30)]).await;
+        let ls = || LocalStorage { sites: vec![] };
+        let config = Config {
+            limit: 1,
+            ..sites_config(
+                api_url,
+                &["slow", "quick", "fast", "swift"],
+                LuckySiteStrategy::First,
+            )
+        };
+        let titles = |qs: Vec<Question<String>>| -> Vec<String> {
+            let mut titles: Vec<String> = qs.into_iter().map(|q| q.title).collect();
+            titles.sort_unstable();
+            titles
+        };
+
+        // Off, every site is waited for
+        let search = Search::new(config.clone(), ls());
+        let start = time::Instant::now();
+        let all = titles(search.search("deploy").await.unwrap());
+        assert_eq!(all, vec!["fast", "quick", "slow", "swift"]);
+        assert!(start.elapsed() >= Duration::from_millis(1500));
+        assert!(search.early_stopped().is_empty());
+
+        // On, the slow site is called off once the others have three times the limit
+        let search = Search::new(
+            Config {
+                early_stop: true,
+                ..config.clone()
+            },
+            ls(),
+        );
+        let start = time::Instant::now();
+        let early = titles(search.search("deploy").await.unwrap());
+        assert_eq!(early, vec!["fast", "quick", "swift"]);
+        assert!(start.elapsed() < Duration::from_millis(1500));
+        assert_eq!(search.early_stopped(), vec!["slow"]);
+        assert!(search.site_failures().is_empty());
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(hung_up.load(Ordering::SeqCst), 1);
+
+        // Not when that's more than the sites have between them
+        let search = Search::new(
+            Config {
+                early_stop: true,
+                limit: 2,
+                ..config
+            },
+            ls(),
+        );
+        assert_eq!(titles(search.search("deploy").await.unwrap()), all);
+        assert!(search.early_stopped().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_direct() {
+        let (api_url, searched, _) = sites_server(&[]).await;
+        let ls = || LocalStorage {
+            sites: vec![Site {
+                api_site_parameter: String::from("superuser"),
+                site_url: String::from("https://superuser.com"),
+                ..Site::default()
+            }],
+        };
+        let config = sites_config(
+            api_url,
+            &["stackoverflow", "askubuntu"],
+            LuckySiteStrategy::First,
+        );
+
+        // A link to a question is looked up on its site, whichever sites are searched
+        let search = Search::new(config.clone(), ls());
+        let qs = search
+            .search("https://superuser.com/questions/1/deploy")
+            .await
+            .unwrap();
+        assert_eq!(qs[0].title, "superuser");
+        assert_eq!(*searched.lock().unwrap(), vec!["superuser"]);
+
+        // So is the question of a link to an answer
+        searched.lock().unwrap().clear();
+        let qs = search.search("https://superuser.com/a/2").await.unwrap();
+        assert_eq!(qs[0].answers[0].id, 2);
+        assert_eq!(*searched.lock().unwrap(), vec!["superuser", "superuser"]);
+
+        // A query that merely mentions a link is searched for
+        searched.lock().unwrap().clear();
+        search
+            .search("deploy like https://superuser.com/q/1")
+            .await
+            .unwrap();
+        assert_eq!(
+            *searched.lock().unwrap(),
+            vec!["stackoverflow", "askubuntu"]
+        );
+
+        // A host that's no known site fails
+        assert!(matches!(
+            search.search("https://example.com/questions/1").await,
+            Err(Error::UnknownHost(host)) if host == "example.com"
+        ));
+
+        // A bare id is looked up only on a site given for it
+        searched.lock().unwrap().clear();
+        let one = sites_config(
+            config.api_url.clone(),
+            &["askubuntu"],
+            LuckySiteStrategy::First,
+        );
+        Search::new(one.clone(), ls()).search("1").await.unwrap();
+        assert_eq!(*searched.lock().unwrap(), vec!["askubuntu"]);
+        let search = Search::new(one, ls()).with_direct_ids(true);
+        assert!(search.is_direct("1"));
+        assert!(!search.is_direct("1 2"));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_sites() {
+        let (api_url, searched, _) = sites_server(&[]).await;
+        let ls = || LocalStorage { sites: vec![] };
+        let config = sites_config(
+            api_url,
+            &["quick", "fast", "quick", "fast", "quick"],
+            LuckySiteStrategy::First,
+        );
+        let qs = Search::new(config, ls()).search("deploy").await.unwrap();
+        let mut titles: Vec<String> = qs.into_iter().map(|q| q.title).collect();
+        titles.sort_unstable();
+        assert_eq!(titles, vec!["fast", "quick"]);
+        let mut searched = searched.lock().unwrap().clone();
+        searched.sort_unstable();
+        assert_eq!(searched, vec!["fast", "quick"]);
+    }
+
+    #[tokio::test]
+    async fn test_normalized() {
+        let (api_url, _, _) = sites_server(&[]).await;
+        let ls = || LocalStorage { sites: vec![] };
+        let config = sites_config(api_url, &["stackoverflow"], LuckySiteStrategy::First);
+
+        let search = Search::new(config.clone(), ls());
+        search
+            .search("pyhton  \u{201c}lenght\u{201d}")
+            .await
+            .unwrap();
+        let normalized = search.normalized().unwrap();
+        assert_eq!(normalized.query, "python \"lenght\"");
+        assert_eq!(
+            normalized.corrections,
+            vec![(String::from("pyhton"), String::from("python"))]
+        );
+
+        // Nothing to tidy up, or told not to
+        search.search("python").await.unwrap();
+        assert_eq!(search.normalized(), None);
+        let search = Search::new(
+            Config {
+                normalize_query: false,
+                ..config
+            },
+            ls(),
+        );
+        search.search("pyhton").await.unwrap();
+        assert_eq!(search.normalized(), None);
+    }
+
+    #[tokio::test]
+    async fn test_partial_results_when_a_site_fails() {
+        let (api_url, _, _) = sites_server(&[]).await;
+        let ls = || LocalStorage { sites: vec![] };
+        let config = sites_config(
+            api_url.clone(),
+            &["broken", "quick"],
+            LuckySiteStrategy::First,
+        );
+        let search = Search::new(config, ls());
+        let qs = search.search("deploy").await.unwrap();
+        assert_eq!(
+            qs.iter().map(|q| q.title.as_str()).collect::<Vec<_>>(),
+            vec!["quick"]
+        );
+        let failures = search.site_failures();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("broken: "), "{}", failures[0]);
+        assert!(search.messages().unread().is_some());
+
+        // Same for questions found by a search engine
+        let data = ScrapedData {
+            question_ids: vec![
+                (String::from("broken"), vec![String::from("1")]),
+                (String::from("quick"), vec![String::from("1")]),
+            ]
+            .into_iter()
+            .collect(),
+            answer_ids: HashMap::new(),
+            ordering: vec![(String::from("1"), 0)].into_iter().collect(),
+            strategy: None,
+        };
+        let qs = search.parallel_questions(data, None).await.unwrap();
+        assert_eq!(qs.len(), 1);
+        assert_eq!(qs[0].title, "quick");
+        assert_eq!(search.site_failures().len(), 2);
+
+        // Answer links are looked up as the questions they're on, skipping the sites that fail
+        let data = ScrapedData {
+            question_ids: HashMap::new(),
+            answer_ids: vec![
+                (String::from("broken"), vec![String::from("2")]),
+                (String::from("quick"), vec![String::from("2")]),
+            ]
+            .into_iter()
+            .collect(),
+            ordering: vec![(String::from("2"), 0)].into_iter().collect(),
+            strategy: None,
+        };
+        let qs = search.parallel_questions(data, None).await.unwrap();
+        assert_eq!(qs.len(), 1);
+        assert_eq!((qs[0].id, qs[0].title.as_str()), (1, "quick"));
+
+        // Only when every site fails does the search, naming each
+        let config = sites_config(api_url, &["broken", "down"], LuckySiteStrategy::First);
+        let search = Search::new(config, ls());
+        match search.search("deploy").await {
+            Err(Error::AllSites(failures)) => {
+                assert_eq!(failures.len(), 2);
+                let e = Error::AllSites(failures).to_string();
+                assert!(e.starts_with("Every site failed: "));
+                assert!(e.contains("broken: ") && e.contains("down: "), "{}", e);
+            }
+            other => panic!(
+                "expected every site to fail, got {:?}",
+                other.map(|qs| qs.len())
+            ),
+        }
+        assert!(search.site_failures().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_per_query() {
+        let (api_url, _, _) = sites_server(&[]).await;
+        let dir = std::env::temp_dir().join(format!("so-per-query-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = QueryCache::new(dir.clone());
+        let config = sites_config(api_url, &["flaky", "quick"], LuckySiteStrategy::First);
+        let search = search(&config, &cache);
+        search.search("fail").await.unwrap();
+        assert_eq!(search.site_failures().len(), 1);
+        assert!(cache.get(&search.cache_key("fail")).unwrap().is_none());
+
+        // As when searching again from the TUI, which reuses the search
+        let qs = search.search("deploy").await.unwrap();
+        assert_eq!(qs.len(), 2);
+        assert!(search.site_failures().is_empty());
+        assert!(!search.partial());
+        assert!(cache.get(&search.cache_key("deploy")).unwrap().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scraped_question_merged() {
+        let (api_url, _, _) = sites_server(&[]).await;
+        let dir = std::env::temp_dir().join(format!("so-search-aliases-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let aliases = Aliases::load(dir.join("aliases.json")).unwrap();
+        let config = Config {
+            search_engine: SearchEngine::DuckDuckGo,
+            ..sites_config(api_url, &["quick"], LuckySiteStrategy::First)
+        };
+        let search =
+            Search::new(config, LocalStorage { sites: vec![] }).with_aliases(aliases.clone());
+        // Question 9 was merged into 1, which it comes back as; the second time around the alias
+        // is already known, and 1 is asked for directly
+        for _ in 0..2 {
+            let data = ScrapedData {
+                question_ids: vec![(String::from("quick"), vec![String::from("9")])]
+                    .into_iter()
+                    .collect(),
+                answer_ids: HashMap::new(),
+                ordering: vec![(String::from("9"), 0)].into_iter().collect(),
+                strategy: None,
+            };
+            let qs = search.parallel_questions(data, None).await.unwrap();
+            assert_eq!(qs.len(), 1);
+            assert_eq!(qs[0].id, 1);
+            let provenance = qs[0].provenance.as_ref().unwrap();
+            assert_eq!(
+                (&provenance.engine, provenance.rank),
+                (&SearchEngine::DuckDuckGo, 1)
+            );
+        }
+        assert_eq!(aliases.resolve("quick", 9), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lucky_race() {
+        let (api_url, searched, hung_up) =
+            sites_server(&[("slow", 5000), ("quick", 50), ("empty", 0), ("late", 200)]).await;
+        let latency = Latency::default();
+
+        // The quicker site wins, and the slower one's search is called off
+        let start = time::Instant::now();
+        let race = sites_config(api_url.clone(), &["slow", "quick"], LuckySiteStrategy::Race);
+        assert_eq!(lucky_title(&race, &latency).await.unwrap(), "quick");
+        while hung_up.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(4));
+            time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // Only the first two sites race, and coming back first without an answer doesn't count
+        let race = sites_config(
+            api_url,
+            &["empty", "late", "quick"],
+            LuckySiteStrategy::Race,
+        );
+        assert_eq!(lucky_title(&race, &latency).await.unwrap(), "late");
+        let mut searched = searched.lock().unwrap().clone();
+        searched.sort();
+        assert_eq!(searched, vec!["empty", "late", "quick", "slow"]);
+    }
+    /// Page of `/search/advanced` results with a question for each of `questions`, its id and
+    /// the body of its one answer, or none for an unanswered one
+    fn page(questions: &[(u32, Option<&str>)], has_more: bool) -> String {
+        let items: Vec<_> = questions
+            .iter()
+            .map(|(id, answer)| {
+                let answers: Vec<_> = answer
+                    .iter()
+                    .map(|body| {
+                        serde_json::json!({"answer_id": id * 10, "score": 1,
+                            "body_markdown": body, "is_accepted": true})
+                    })
+                    .collect();
+                serde_json::json!({"question_id": id, "score": 1,
+                    "title": format!("Question {}", id), "answers": answers})
+            })
+            .collect();
+        serde_json::json!({"items": items, "has_more": has_more}).to_string()
+    }
+
+    /// Local StackExchange API serving `pages` by site and page number, and nothing for any
+    /// other. Reports the pages asked for, in order.
+    async fn pages_server(
+        pages: Vec<(&'static str, u32, String)>,
+    ) -> (String, Arc<Mutex<Vec<(String, u32)>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let on_request = requested.clone();
+        let pages = Arc::new(pages);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (pages, on_request) = (pages.clone(), on_request.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                            _ => return,
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let line = request.lines().next().unwrap_or_default();
+                    let param = |name: &str| {
+                        line.split(['?', '&', ' '])
+                            .find_map(|param| param.strip_prefix(name))
+                            .unwrap_or_default()
+                            .to_string()
+                    };
+                    let body = if line.contains("/filters/create") {
+                        String::from(r#"{"items": [{"filter": "derived"}]}"#)
+                    } else {
+                        let site = param("site=");
+                        let number: u32 = param("page=").parse().unwrap_or_default();
+                        on_request.lock().unwrap().push((site.clone(), number));
+                        pages
+                            .iter()
+                            .find(|(s, n, _)| *s == site && *n == number)
+                            .map_or_else(|| page(&[], false), |(_, _, body)| body.clone())
+                    };
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (format!("http://{}", addr), requested)
+    }
+
+    /// Sites whose first page filters leave little of: `noisy` only has unanswered questions,
+    /// half of `tidy`'s are, and `done` has no more past its first page
+    fn filter_heavy_pages() -> Vec<(&'static str, u32, String)> {
+        let answered = Some("Run `deploy`");
+        vec![
+            ("noisy", 1, page(&[(1, None), (2, None)], true)),
+            ("noisy", 2, page(&[(3, answered)], true)),
+            ("tidy", 1, page(&[(11, answered), (12, None)], true)),
+            // One of the first page's questions moved on to this one
+            (
+                "tidy",
+                2,
+                page(&[(11, answered), (13, answered), (14, None)], true),
+            ),
+            ("tidy", 3, page(&[(15, answered)], false)),
+            ("done", 1, page(&[(21, answered)], false)),
+        ]
+    }
+
+    fn requested_pages(requested: &Mutex<Vec<(String, u32)>>) -> Vec<(String, u32)> {
+        let mut requested = requested.lock().unwrap().clone();
+        requested.sort();
+        requested
+    }
+
+    #[test]
+    fn test_best_yield() {
+        let more = |site: &str, fetched| More {
+            site: site.to_string(),
+            tagged: false,
+            fetched,
+        };
+        let shown = |sites: &[&str]| -> Vec<Question<String>> {
+            sites
+                .iter()
+                .enumerate()
+                .map(|(i, site)| Question {
+                    site: site.to_string(),
+                    ..question(i as u32, "Deploying")
+                })
+                .collect()
+        };
+        let best = |more: &[More], sites: &[&str]| -> Vec<String> {
+            best_yield(more, &shown(sites))
+                .into_iter()
+                .map(|m| m.site.clone())
+                .collect()
+        };
+        let sites = [more("a", 4), more("b", 4), more("c", 2), more("d", 0)];
+        // b kept 2 of 4, c 1 of 2, a none
+        assert_eq!(best(&sites, &["b", "b", "c"]), vec!["b", "c"]);
+        assert_eq!(best(&sites, &["b", "b", "b", "c"]), vec!["b"]);
+        assert_eq!(best(&sites, &["a", "b"]), vec!["a", "b"]);
+        // Filters left nothing of any, so any of them could do better
+        assert_eq!(best(&sites, &[]), vec!["a", "b", "c"]);
+        assert!(best(&[], &["a"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backfill() {
+        let (api_url, requested) = pages_server(filter_heavy_pages()).await;
+        let ls = || LocalStorage { sites: vec![] };
+        let config = Config {
+            limit: 5,
+            ..sites_config(
+                api_url,
+                &["noisy", "tidy", "done"],
+                LuckySiteStrategy::First,
+            )
+        };
+        let search = Search::new(config.clone(), ls());
+        let qs = search.search_filtered("deploy").await.unwrap();
+        // The site that kept the most got another page, its repeat left out, and that's it even
+        // though there are still fewer than the limit and more pages
+        assert_eq!(
+            requested_pages(&requested),
+            vec![
+                (String::from("done"), 1),
+                (String::from("noisy"), 1),
+                (String::from("tidy"), 1),
+                (String::from("tidy"), 2),
+            ]
+        );
+        let mut shown: Vec<u32> = qs.shown.iter().map(|q| q.id).collect();
+        shown.sort_unstable();
+        assert_eq!(shown, vec![11, 13, 21]);
+        assert_eq!(qs.hidden.len(), 4);
+        let page_two = qs.shown.iter().find(|q| q.id == 13).unwrap();
+        assert_eq!(
+            page_two.provenance.as_ref().unwrap().request,
+            Request::Search { page: 2 }
+        );
+        assert_eq!(
+            search.backfilled(),
+            Some(Backfill {
+                sites: vec![String::from("tidy")],
+                added: 1,
+            })
+        );
+
+        // Nothing to make up for once there are enough
+        requested.lock().unwrap().clear();
+        let enough = Config {
+            limit: 2,
+            ..config.clone()
+        };
+        let search = Search::new(enough, ls());
+        assert_eq!(
+            search.search_filtered("deploy").await.unwrap().shown.len(),
+            2
+        );
+        assert!(requested_pages(&requested)
+            .iter()
+            .all(|(_, page)| *page == 1));
+        assert_eq!(search.backfilled(), None);
+    }
+
+    #[tokio::test]
+    async fn test_no_backfill_when_lucky() {
+        let (api_url, requested) = pages_server(filter_heavy_pages()).await;
+        let config = Config {
+            limit: 5,
+            require_accepted: true,
+            ..sites_config(api_url, &["tidy"], LuckySiteStrategy::First)
+        };
+        let mut search = Search::new(config, LocalStorage { sites: vec![] });
+        assert_eq!(search.search_lucky("deploy").await.unwrap().id, 11);
+        assert_eq!(requested_pages(&requested), vec![(String::from("tidy"), 1)]);
+        assert_eq!(search.backfilled(), None);
+
+        // The search behind it is free to
+        search.search_filtered("deploy").await.unwrap();
+        assert!(requested_pages(&requested).contains(&(String::from("tidy"), 2)));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_counts_towards_code_search_stats() {
+        let code = Some("Like so:\n\n    deploy --now");
+        let prose = Some("Deploy it by hand");
+        let pages = vec![
+            ("tidy", 1, page(&[(1, code), (2, prose)], true)),
+            ("tidy", 2, page(&[(3, prose), (4, code), (5, prose)], false)),
+        ];
+        let (api_url, _) = pages_server(pages).await;
+        let config = Config {
+            limit: 5,
+            code_search: true,
+            ..sites_config(api_url, &["tidy"], LuckySiteStrategy::First)
+        };
+        let search = Search::new(config, LocalStorage { sites: vec![] });
+        let qs = search.search_filtered("deploy").await.unwrap();
+        assert_eq!(
+            qs.shown.iter().map(|q| q.id).collect::<Vec<_>>(),
+            vec![1, 4]
+        );
+        assert_eq!(
+            search.code_search_stats(),
+            Some(code_search::Stats {
+                questions: 5,
+                matched: 2,
+            })
+        );
+        assert_eq!(search.backfilled().unwrap().added, 1);
+    }
 }
@@ -0,0 +1,268 @@
+//! Extraction of code snippets and summary sentences from answer markdown.
+
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// Answers with no more prose than this are summarized in full
+const SHORT_ANSWER: usize = 100;
+
+/// Abbreviations whose period doesn't end a sentence, lowercased and without that period
+const ABBREVIATIONS: &[&str] = &[
+    "al", "approx", "cf", "dr", "e.g", "eg", "esp", "fig", "i.e", "ie", "incl", "mr", "mrs", "ms",
+    "prof", "resp", "vs", "viz",
+];
+
+/// Contents of every fenced or indented code block in `md`, in order of appearance
+pub fn code_blocks(md: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<String> = None;
+    for event in Parser::new(md) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => current = Some(String::new()),
+            Event::End(Tag::CodeBlock(_)) => blocks.extend(current.take()),
+            Event::Text(text) => {
+                if let Some(block) = current.as_mut() {
+                    block.push_str(&text);
+                }
+            }
+            _ => (),
+        }
+    }
+    blocks
+}
+
+/// One line summing up `md`: its first sentence, or all of it if it's short. Answers leading with
+/// a code block are summed up by the first line of code instead.
+pub fn first_sentence(md: &str) -> String {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_code = false;
+    for event in Parser::new(md) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+            Event::Text(text) if in_code => {
+                let leading = paragraphs.is_empty() && current.trim().is_empty();
+                match text.lines().map(str::trim).find(|l| !l.is_empty()) {
+                    Some(line) if leading => return line.to_string(),
+                    _ => (),
+                }
+            }
+            Event::Text(text) | Event::Code(text) => current.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => current.push(' '),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Heading(_)) | Event::End(Tag::Item) => {
+                let paragraph = current.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !paragraph.is_empty() {
+                    paragraphs.push(paragraph);
+                }
+                current.clear();
+            }
+            _ => (),
+        }
+    }
+    let all = paragraphs.join(" ");
+    if all.chars().count() <= SHORT_ANSWER {
+        return all;
+    }
+    let first = &paragraphs[0];
+    sentences(first)
+        .first()
+        .map_or_else(|| first.clone(), |sentence| sentence.to_string())
+}
+
+/// Paragraphs of prose in `md`, as markdown on a single line: code blocks, headings, images and
+/// HTML are left out, and so is the markup of links, leaving their text. List items count as
+/// paragraphs of their own.
+pub fn prose(md: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    // Depth of the blocks and inlines that aren't prose
+    let mut skipped = 0;
+    for event in Parser::new(md) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Start(Tag::Heading(_)) => skipped += 1,
+            Event::Start(Tag::Image(..)) => skipped += 1,
+            Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Heading(_)) => skipped -= 1,
+            Event::End(Tag::Image(..)) => skipped -= 1,
+            _ if skipped > 0 => (),
+            Event::Text(text) => current.push_str(&escape(&text)),
+            // Double backticks, padded with spaces, hold code with backticks of its own
+            Event::Code(code) if code.contains('`') => current.push_str(&format!("`` {} ``", code)),
+            Event::Code(code) => current.push_str(&format!("`{}`", code)),
+            Event::SoftBreak | Event::HardBreak => current.push(' '),
+            Event::Start(Tag::Emphasis) | Event::End(Tag::Emphasis) => current.push('*'),
+            Event::Start(Tag::Strong) | Event::End(Tag::Strong) => current.push_str("**"),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Item) => {
+                let paragraph = current.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !paragraph.is_empty() {
+                    paragraphs.push(paragraph);
+                }
+                current.clear();
+            }
+            _ => (),
+        }
+    }
+    paragraphs
+}
+
+/// Escape what would otherwise be markup in plain `text`
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Sentences of a single line of `text`
+///
+/// A sentence ends at a period, exclamation or question mark (and any closing quotes or
+/// brackets right after it) followed by whitespace and a word that doesn't start in lowercase,
+/// or at the end of the text. Periods of abbreviations such as "e.g." and of initials don't end
+/// sentences, and neither do those of decimals and versions, which aren't followed by whitespace.
+pub fn sentences(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (n, &(ix, c)) in chars.iter().enumerate() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        let mut after = n + 1;
+        while chars
+            .get(after)
+            .is_some_and(|&(_, c)| matches!(c, '"' | '\'' | ')' | ']' | '\u{201d}' | '\u{2019}'))
+        {
+            after += 1;
+        }
+        let end = chars.get(after).map_or(text.len(), |&(ix, _)| ix);
+        if !text[end..].starts_with(char::is_whitespace) {
+            continue;
+        }
+        let next = text[end..].trim_start().chars().next();
+        if next.is_some_and(char::is_lowercase) {
+            continue;
+        }
+        if c == '.' && is_abbreviation(&text[start..ix], next) {
+            continue;
+        }
+        let sentence = text[start..end].trim();
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+        start = end;
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+/// Whether the word at the end of `before` is an abbreviation or initial, given the char the
+/// next word starts with
+fn is_abbreviation(before: &str, next: Option<char>) -> bool {
+    let word = before
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut chars = word.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // Initials, and the first period of "e.g."
+        return c.is_alphabetic();
+    }
+    match word.as_str() {
+        // Often ends a sentence as well as the list it's in
+        "etc" => !next.is_some_and(char::is_uppercase),
+        word => ABBREVIATIONS.contains(&word),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_blocks() {
+        let md = "Run this:\n\n```bash\ngit reset --hard\ngit clean -fd\n```\n\n\
+                  or, `inline` code doesn't count:\n\n    cargo build\n\nDone.";
+        assert_eq!(
+            code_blocks(md),
+            vec!["git reset --hard\ngit clean -fd\n", "cargo build\n"]
+        );
+    }
+
+    #[test]
+    fn test_first_sentence() {
+        let md = "Hit `Esc` to get back to normal mode. Then type `:q` and press enter, or \
+                  `:q!` to quit without saving your changes, since Vim won't let you quit \
+                  otherwise.\n\nThere are more ways, too.";
+        assert_eq!(first_sentence(md), "Hit Esc to get back to normal mode.");
+        // Decimal points and such don't end sentences
+        let md = "Upgrade to v1.2.3 or later and the bug goes away, since the release fixed the \
+                  way the parser handles escapes. See the changelog.";
+        assert!(first_sentence(md).starts_with("Upgrade to v1.2.3 or later"));
+        assert!(first_sentence(md).ends_with("escapes."));
+    }
+
+    #[test]
+    fn test_first_sentence_short() {
+        assert_eq!(first_sentence("Use `ZZ`.\n\nOr `:x`."), "Use ZZ. Or :x.");
+    }
+
+    #[test]
+    fn test_first_sentence_leading_code() {
+        let md = "```\n\n  git reset --hard HEAD~1\ngit push -f\n```\n\nThis rewrites history.";
+        assert_eq!(first_sentence(md), "git reset --hard HEAD~1");
+        let md = "Run this:\n\n```\ngit reset --hard HEAD~1\n```";
+        assert_eq!(first_sentence(md), "Run this:");
+    }
+
+    #[test]
+    fn test_no_code_blocks() {
+        assert!(code_blocks("Just `inline` and *prose*.").is_empty());
+    }
+
+    #[test]
+    fn test_sentences() {
+        let text = "Use `git reset`, e.g. with `--soft`. It keeps changes, i.e. they're staged! \
+                    Works since v2.3.4 (see Dr. Smith's post.) Why? Options are -a, -b etc. and \
+                    more. J. R. R. Tolkien agrees.";
+        assert_eq!(
+            sentences(text),
+            vec![
+                "Use `git reset`, e.g. with `--soft`.",
+                "It keeps changes, i.e. they're staged!",
+                "Works since v2.3.4 (see Dr. Smith's post.)",
+                "Why?",
+                "Options are -a, -b etc. and more.",
+                "J. R. R. Tolkien agrees.",
+            ]
+        );
+        // "etc." at the end of a sentence
+        assert_eq!(
+            sentences("Install tools, libs etc. Then build it."),
+            vec!["Install tools, libs etc.", "Then build it."]
+        );
+        assert!(sentences("  ").is_empty());
+        assert_eq!(sentences("No period"), vec!["No period"]);
+    }
+
+    #[test]
+    fn test_prose() {
+        let md = "# Heading\n\nHit <kbd>Esc</kbd>, then *type* `:q`. A 2*3 \
+                  [link](https://example.com) ![img](a.png)\n\n\
+                  ```\n:q!\n```\n\n- `:wq` to **write** and quit\n- ``a`b``";
+        assert_eq!(
+            prose(md),
+            vec![
+                "Hit Esc, then *type* `:q`. A 2\\*3 link",
+                "`:wq` to **write** and quit",
+                "`` a`b ``",
+            ]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Extractive summaries of answers for `--tl-dr`: the few sentences of an answer's prose that
+//! best say what to do.
+//!
+//! Sentences are scored by how many of the query's terms they have, how early in the answer they
+//! come, and whether they tell the reader to do something, and the best ones are shown in the
+//! order they were written in. Code is left out, and so are answers too short to need a summary.
+//! It's all heuristics, run locally.
+
+use std::collections::HashSet;
+
+use super::analysis::{self, Language};
+use super::snippets;
+
+/// What summaries are labeled with, so that nobody takes them for part of the answer
+pub const LABEL: &str = "tl;dr (auto)";
+
+/// Answers with fewer words of prose than this are left alone
+const MIN_WORDS: usize = 60;
+
+/// Sentences with fewer words than this say too little to sum anything up
+const MIN_SENTENCE_WORDS: usize = 4;
+
+const QUERY_WEIGHT: f64 = 2.0;
+const POSITION_WEIGHT: f64 = 1.0;
+const IMPERATIVE_WEIGHT: f64 = 1.5;
+
+/// Verbs that start instructions, as in "Run `cargo update`"
+const IMPERATIVES: &[&str] = &[
+    "add",
+    "avoid",
+    "call",
+    "change",
+    "check",
+    "configure",
+    "copy",
+    "create",
+    "delete",
+    "disable",
+    "do",
+    "download",
+    "edit",
+    "enable",
+    "execute",
+    "hit",
+    "import",
+    "include",
+    "install",
+    "make",
+    "move",
+    "open",
+    "pass",
+    "press",
+    "put",
+    "remove",
+    "rename",
+    "replace",
+    "restart",
+    "run",
+    "select",
+    "set",
+    "specify",
+    "switch",
+    "try",
+    "type",
+    "uninstall",
+    "update",
+    "upgrade",
+    "use",
+    "wrap",
+    "write",
+];
+
+/// Words that may come before the verb of an instruction, as in "Then run ..."
+const LEAD_INS: &[&str] = &[
+    "also", "finally", "first", "instead", "just", "next", "now", "second", "simply", "then",
+];
+
+/// Openings of sentences that are about the answer rather than the problem
+const ASIDES: &[&str] = &["edit", "update", "hope", "thanks", "good luck", "note:"];
+
+/// Up to `max` sentences summing up the answer `md` to a query with `query_terms`, in the order
+/// they come in, or `None` if the answer is too short to need a summary
+pub fn summarize(
+    md: &str,
+    query_terms: &HashSet<String>,
+    language: Language,
+    max: usize,
+) -> Option<Vec<String>> {
+    let paragraphs = snippets::prose(md);
+    let words: usize = paragraphs
+        .iter()
+        .map(|p| p.split_whitespace().count())
+        .sum();
+    let sentences: Vec<&str> = paragraphs
+        .iter()
+        .flat_map(|p| snippets::sentences(p))
+        .collect();
+    if max == 0 || words < MIN_WORDS || sentences.len() <= max {
+        return None;
+    }
+    let count = sentences.len() as f64;
+    let mut scored: Vec<(usize, f64)> = sentences
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| is_candidate(s))
+        .map(|(i, s)| {
+            let position = 1.0 - i as f64 / count;
+            let score = QUERY_WEIGHT * query_overlap(s, query_terms, language)
+                + POSITION_WEIGHT * position
+                + IMPERATIVE_WEIGHT * imperative(s);
+            (i, score)
+        })
+        .collect();
+    // Best first, earlier first among equals
+    scored.sort_by(|(i, a), (j, b)| b.total_cmp(a).then(i.cmp(j)));
+    let mut picked: Vec<usize> = scored.into_iter().take(max).map(|(i, _)| i).collect();
+    if picked.is_empty() {
+        return None;
+    }
+    picked.sort_unstable();
+    Some(
+        picked
+            .into_iter()
+            .map(|i| sentences[i].to_string())
+            .collect(),
+    )
+}
+
+/// `md` with its `summary` on top, as a labeled block quote
+pub fn prepend(md: &str, summary: &[String]) -> String {
+    format!("> **{}:** {}\n\n{}", LABEL, summary.join(" "), md)
+}
+
+/// Whether `sentence` could be part of a summary at all: long enough, ending like a statement
+/// rather than a question or a caption, and not an aside
+fn is_candidate(sentence: &str) -> bool {
+    let lower = sentence.to_lowercase();
+    let end = sentence.trim_end_matches(&['"', '\'', ')', ']', '\u{201d}', '\u{2019}'][..]);
+    sentence.split_whitespace().count() >= MIN_SENTENCE_WORDS
+        && end.ends_with(&['.', '!'][..])
+        && !ASIDES.iter().any(|aside| lower.starts_with(aside))
+}
+
+/// Share of `query_terms` in `sentence`, from 0 to 1
+fn query_overlap(sentence: &str, query_terms: &HashSet<String>, language: Language) -> f64 {
+    if query_terms.is_empty() {
+        return 0.0;
+    }
+    let terms = analysis::terms(sentence, language);
+    query_terms.intersection(&terms).count() as f64 / query_terms.len() as f64
+}
+
+/// 1 for sentences that start with an instruction, a half for "you can ...", "you should ..."
+/// and the like, and 0 for anything else
+fn imperative(sentence: &str) -> f64 {
+    let words: Vec<String> = analysis::tokens(sentence).take(4).collect();
+    let mut words = words.iter().map(String::as_str).peekable();
+    while words.peek().is_some_and(|w| LEAD_INS.contains(w)) {
+        words.next();
+    }
+    match (words.next(), words.next()) {
+        (Some(verb), _) if IMPERATIVES.contains(&verb) => 1.0,
+        (Some("you"), Some("can" | "should" | "need" | "must" | "have")) => 0.5,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// From "How do I undo the most recent local commits in Git?"
+    const UNDO_COMMIT: &str = "\
+Undo a commit & redo
+
+```
+$ git commit -m \"Something terribly misguided\" # (0: Your Accident)
+$ git reset HEAD~                              # (1)
+```
+
+This command is responsible for the *undo*. It will undo your last commit while leaving your \
+working tree (the state of your files on disk) untouched. You'll need to add them again before \
+you can commit them again.
+
+Make corrections to working tree files. Then `git add` anything that you want to include in \
+your new commit. Commit the changes, reusing the old commit message. `reset` copied the old \
+head to `.git/ORIG_HEAD`; `commit` with `-c ORIG_HEAD` will open an editor, which initially \
+contains the log message from the old commit and allows you to edit it.
+
+Alternatively, to edit the previous commit (or just its commit message), `commit --amend` will \
+add changes within the current index to the previous commit.
+
+Hope this helps!";
+
+    /// From "How do I exit Vim?"
+    const EXIT_VIM: &str = "\
+Hit the <kbd>Esc</kbd> key to enter \"Normal mode\". Then you can type `:` to enter \
+\"Command-line mode\". A colon (`:`) will appear at the bottom of the screen and you can type \
+in one of the following commands. To execute a command, press the <kbd>Enter</kbd> key.
+
+- `:q` to quit (short for `:quit`)
+- `:q!` to quit without saving (short for `:quit!`)
+- `:wq` to write and quit
+
+Note: Vim has other modes too, e.g. visual mode. In those, Esc gets you back to normal mode \
+as well, i.e. the same as above.";
+
+    fn terms(query: &str) -> HashSet<String> {
+        analysis::terms(query, Language::English)
+    }
+
+    fn tl_dr(md: &str, query: &str, max: usize) -> Option<Vec<String>> {
+        summarize(md, &terms(query), Language::English, max)
+    }
+
+    #[test]
+    fn test_undo_commit() {
+        let summary = tl_dr(UNDO_COMMIT, "undo last git commit", 3).unwrap();
+        assert_eq!(
+            summary,
+            vec![
+                "It will undo your last commit while leaving your working tree (the state of \
+                 your files on disk) untouched.",
+                "Make corrections to working tree files.",
+                "Then `git add` anything that you want to include in your new commit.",
+            ]
+        );
+        // The caption on top has the query's terms, but doesn't end like a sentence
+        assert!(summary.iter().all(|s| !s.starts_with("Undo a commit")));
+        // Capped, and still in order
+        let summary = tl_dr(UNDO_COMMIT, "undo last git commit", 2).unwrap();
+        assert_eq!(summary.len(), 2);
+        assert!(summary[0].starts_with("It will undo"));
+    }
+
+    #[test]
+    fn test_exit_vim() {
+        let summary = tl_dr(EXIT_VIM, "how to exit vim", 2).unwrap();
+        assert_eq!(
+            summary,
+            vec![
+                "Hit the Esc key to enter \"Normal mode\".",
+                "Then you can type `:` to enter \"Command-line mode\".",
+            ]
+        );
+        // Asides are never picked, whatever the query
+        let summary = tl_dr(EXIT_VIM, "vim modes note", 3).unwrap();
+        assert!(summary.iter().all(|s| !s.starts_with("Note:")));
+    }
+
+    #[test]
+    fn test_short_answers() {
+        assert_eq!(tl_dr("Type `:q!` and hit enter.", "exit vim", 3), None);
+        assert_eq!(tl_dr("", "exit vim", 3), None);
+        assert_eq!(tl_dr("```\n:q!\n```", "exit vim", 3), None);
+        // Long, but with no more sentences than a summary would have
+        let one_sentence = format!("Use {}.", "this and ".repeat(40));
+        assert_eq!(tl_dr(&one_sentence, "exit vim", 3), None);
+        assert_eq!(tl_dr(UNDO_COMMIT, "undo", 0), None);
+    }
+
+    #[test]
+    fn test_imperative() {
+        assert_eq!(imperative("Run `cargo update` first."), 1.0);
+        assert_eq!(imperative("Then simply install the package."), 1.0);
+        assert_eq!(imperative("You can also use rebase."), 0.5);
+        assert_eq!(imperative("This runs the tests."), 0.0);
+    }
+
+    #[test]
+    fn test_prepend() {
+        let summary = vec![String::from("Hit Esc."), String::from("Type `:q`.")];
+        assert_eq!(
+            prepend("Hit Esc. Then...", &summary),
+            "> **tl;dr (auto):** Hit Esc. Type `:q`.\n\nHit Esc. Then..."
+        );
+    }
+}
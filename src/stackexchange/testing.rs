@@ -0,0 +1,33 @@
+//! Questions and answers for tests, with whatever body type the code under test works on
+
+use super::{Answer, Question};
+
+/// Answer with `body`
+pub fn answer<S: Default>(id: u32, score: i32, is_accepted: bool, body: S) -> Answer<S> {
+    Answer {
+        id,
+        score,
+        body,
+        is_accepted,
+        ..Answer::default()
+    }
+}
+
+/// Question on Stack Overflow with `body`
+pub fn question<S: Default>(
+    id: u32,
+    score: i32,
+    title: &str,
+    body: S,
+    answers: Vec<Answer<S>>,
+) -> Question<S> {
+    Question {
+        id,
+        score,
+        answers,
+        title: title.to_string(),
+        body: Some(body),
+        site: String::from("stackoverflow"),
+        ..Question::default()
+    }
+}
@@ -0,0 +1,176 @@
+//! Stopwatch instrumentation of the search pipeline, reported via `--timings`.
+//!
+//! Requests are timed around their send/await points: "ttfb" covers DNS, connect
+//! and waiting for the response headers, while "total" also includes reading and
+//! decoding the body.
+
+use serde::{Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Timing of a single HTTP request
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestTiming {
+    pub label: String,
+    #[serde(rename = "ttfb_ms", serialize_with = "as_millis")]
+    pub ttfb: Duration,
+    #[serde(rename = "total_ms", serialize_with = "as_millis")]
+    pub total: Duration,
+}
+
+/// Breakdown of where the time went during a search. Serialized for `--json`, with each
+/// duration in whole milliseconds as in the table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Timings {
+    /// Every request made, in order of completion
+    pub requests: Vec<RequestTiming>,
+    /// Markdown preprocessing, summed over all sites
+    #[serde(rename = "preprocess_ms", serialize_with = "as_millis")]
+    pub preprocess: Duration,
+    /// Markdown parsing (TUI only)
+    #[serde(rename = "parse_ms", serialize_with = "as_millis_opt")]
+    pub parse: Option<Duration>,
+    /// Wall time of the entire search
+    #[serde(rename = "total_ms", serialize_with = "as_millis_opt")]
+    pub total: Option<Duration>,
+}
+
+/// Shared handle used to collect `Timings` from concurrently running tasks
+#[derive(Debug, Clone, Default)]
+pub struct Recorder(Arc<Mutex<Timings>>);
+
+impl Recorder {
+    pub fn request(&self, label: String, ttfb: Duration, total: Duration) {
+        self.with(|t| t.requests.push(RequestTiming { label, ttfb, total }));
+    }
+
+    pub fn preprocess(&self, d: Duration) {
+        self.with(|t| t.preprocess += d);
+    }
+
+    pub fn parse(&self, d: Duration) {
+        self.with(|t| t.parse = Some(t.parse.unwrap_or_default() + d));
+    }
+
+    /// Snapshot of everything recorded so far
+    pub fn timings(&self) -> Timings {
+        self.with(|t| t.clone())
+    }
+
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Timings) -> R,
+    {
+        // A poisoned lock only means some other task panicked mid-update; the
+        // timings themselves are still fine to report.
+        let mut timings = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut timings)
+    }
+}
+
+impl fmt::Display for Timings {
+    /// Aligned table of all stages
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows: Vec<(&str, Option<Duration>, Duration)> = self
+            .requests
+            .iter()
+            .map(|r| (r.label.as_str(), Some(r.ttfb), r.total))
+            .collect();
+        rows.push(("preprocess", None, self.preprocess));
+        if let Some(parse) = self.parse {
+            rows.push(("parse markdown", None, parse));
+        }
+        if let Some(total) = self.total {
+            rows.push(("total", None, total));
+        }
+        let width = rows
+            .iter()
+            .map(|(label, _, _)| label.chars().count())
+            .chain(std::iter::once("stage".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:<width$}  {:>8}  {:>8}", "stage", "ttfb", "total")?;
+        for (label, ttfb, total) in rows {
+            let ttfb = ttfb.map(millis).unwrap_or_else(|| String::from("-"));
+            writeln!(f, "{:<width$}  {:>8}  {:>8}", label, ttfb, millis(total))?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(d: Duration) -> String {
+    format!("{}ms", d.as_millis())
+}
+
+fn as_millis<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+fn as_millis_opt<S: Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    match d {
+        Some(d) => as_millis(d, s),
+        None => s.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_table() {
+        let recorder = Recorder::default();
+        recorder.request(String::from("duckduckgo"), ms(312), ms(340));
+        recorder.request(String::from("stackoverflow questions"), ms(201), ms(1250));
+        recorder.preprocess(ms(5));
+        recorder.preprocess(ms(7));
+        recorder.parse(ms(30));
+        let timings = Timings {
+            total: Some(ms(1622)),
+            ..recorder.timings()
+        };
+        assert_eq!(
+            timings.to_string(),
+            "\
+stage                        ttfb     total
+duckduckgo                  312ms     340ms
+stackoverflow questions     201ms    1250ms
+preprocess                      -      12ms
+parse markdown                  -      30ms
+total                           -    1622ms
+"
+        );
+    }
+
+    #[test]
+    fn test_table_minimal() {
+        assert_eq!(
+            Timings::default().to_string(),
+            "\
+stage           ttfb     total
+preprocess         -       0ms
+"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let recorder = Recorder::default();
+        recorder.request(String::from("duckduckgo"), ms(312), ms(340));
+        recorder.preprocess(ms(12));
+        let timings = Timings {
+            total: Some(ms(1622)),
+            ..recorder.timings()
+        };
+        assert_eq!(
+            serde_json::to_string(&timings).unwrap(),
+            r#"{"requests":[{"label":"duckduckgo","ttfb_ms":312,"total_ms":340}],"preprocess_ms":12,"parse_ms":null,"total_ms":1622}"#
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Steps results go through between being fetched and being parsed for display: the built-in
+//! filters, and whatever library users register with `Search::with_transformer`.
+//!
+//! Results go through the built-ins first, in the order `Search` lists them (unanswered
+//! questions, `--code-search`, then the answer order), and then through registered transformers,
+//! in the order they were registered. Results served from the cache go through all of them again,
+//! and a page fetched to make up for filtered out questions goes through them on its own before
+//! joining the first. Transformers should hide questions, with a `Reason`, rather than drop them,
+//! so that they're still counted and cached.
+
+use std::sync::{Arc, Mutex};
+
+use super::answer_sort::AnswerSort;
+use super::code_search::{self, Stats};
+use super::filtered::{Filtered, Reason};
+
+/// A step of the pipeline between fetching results and showing them
+pub trait ResultTransformer: Send + Sync {
+    /// `results`, filtered, rewritten or reordered; already hidden questions are passed along too
+    fn transform(&self, results: Filtered<String>) -> Filtered<String>;
+}
+
+/// Run `results` through `steps`, in order
+pub fn run(steps: &[Arc<dyn ResultTransformer>], results: Filtered<String>) -> Filtered<String> {
+    steps
+        .iter()
+        .fold(results, |results, step| step.transform(results))
+}
+
+/// Hides questions nobody answered, unless they're included
+pub struct Unanswered {
+    pub include_unanswered: bool,
+}
+
+impl ResultTransformer for Unanswered {
+    fn transform(&self, results: Filtered<String>) -> Filtered<String> {
+        let include_unanswered = self.include_unanswered;
+        results.filter(Reason::Unanswered, |q| {
+            include_unanswered || !q.answers.is_empty()
+        })
+    }
+}
+
+/// Hides questions without the query in a code block, in `--code-search` mode, counting what it
+/// kept across every batch it's given
+pub struct CodeSearch {
+    needles: Vec<String>,
+    stats: Mutex<Option<Stats>>,
+}
+
+impl CodeSearch {
+    pub fn new(query: &str) -> Self {
+        CodeSearch {
+            needles: code_search::needles(query),
+            stats: Mutex::default(),
+        }
+    }
+
+    /// What the filter kept so far, once it has been given results
+    pub fn stats(&self) -> Option<Stats> {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ResultTransformer for CodeSearch {
+    fn transform(&self, results: Filtered<String>) -> Filtered<String> {
+        let (results, stats) = code_search::filter(results, &self.needles);
+        let mut total = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        match total.as_mut() {
+            Some(total) => *total += stats,
+            None => *total = Some(stats),
+        }
+        results
+    }
+}
+
+/// Puts the answers of every question, shown or hidden, in `answer_sort` order
+pub struct SortAnswers(pub AnswerSort);
+
+impl ResultTransformer for SortAnswers {
+    fn transform(&self, results: Filtered<String>) -> Filtered<String> {
+        let answer_sort = self.0;
+        results.convert(|mut qs| {
+            for q in &mut qs {
+                answer_sort.sort(&mut q.answers);
+            }
+            qs
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::{testing, Answer, Question};
+
+    fn answer(id: u32, score: i32, body: &str) -> Answer<String> {
+        testing::answer(id, score, false, body.to_string())
+    }
+
+    fn question(id: u32, tags: &[&str], answers: Vec<Answer<String>>) -> Question<String> {
+        Question {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..testing::question(id, 1, &format!("Question {}", id), String::new(), answers)
+        }
+    }
+
+    fn fetched() -> Filtered<String> {
+        Filtered::new(vec![
+            question(
+                1,
+                &["internal"],
+                vec![
+                    answer(10, 1, "```\ncurl build.corp\n```"),
+                    answer(11, 5, "curl build.corp"),
+                ],
+            ),
+            question(2, &["public"], vec![]),
+            question(
+                3,
+                &["public"],
+                vec![answer(30, 2, "```\ncurl build.corp\n```")],
+            ),
+            question(4, &["public"], vec![answer(40, 3, "Use wget")]),
+        ])
+    }
+
+    /// Hides the questions without `tag`, as a library user might
+    struct Tagged(&'static str);
+
+    impl ResultTransformer for Tagged {
+        fn transform(&self, results: Filtered<String>) -> Filtered<String> {
+            results.filter(Reason::Other("not tagged for us"), |q| {
+                q.tags.iter().any(|t| t == self.0)
+            })
+        }
+    }
+
+    /// Rewrites a hostname in every answer, shown or hidden
+    struct Rewrite;
+
+    impl ResultTransformer for Rewrite {
+        fn transform(&self, results: Filtered<String>) -> Filtered<String> {
+            results.convert(|mut qs| {
+                for a in qs.iter_mut().flat_map(|q| q.answers.iter_mut()) {
+                    a.body = a.body.replace("build.corp", "build.example.com");
+                }
+                qs
+            })
+        }
+    }
+
+    fn shown(results: &Filtered<String>) -> Vec<u32> {
+        results.shown.iter().map(|q| q.id).collect()
+    }
+
+    fn hidden(results: &Filtered<String>) -> Vec<(u32, Reason)> {
+        results
+            .hidden
+            .iter()
+            .map(|h| (h.question.id, h.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtins() {
+        let code_search = Arc::new(CodeSearch::new("curl build.corp"));
+        let steps: Vec<Arc<dyn ResultTransformer>> = vec![
+            Arc::new(Unanswered {
+                include_unanswered: false,
+            }),
+            code_search.clone(),
+            Arc::new(SortAnswers(AnswerSort::Votes)),
+        ];
+        let results = run(&steps, fetched());
+        assert_eq!(shown(&results), vec![1, 3]);
+        assert_eq!(
+            hidden(&results),
+            vec![(2, Reason::Unanswered), (4, Reason::NoCodeMatch)]
+        );
+        let answers: Vec<u32> = results.shown[0].answers.iter().map(|a| a.id).collect();
+        assert_eq!(answers, vec![11, 10]);
+        assert_eq!(
+            code_search.stats(),
+            Some(Stats {
+                questions: 3,
+                matched: 2
+            })
+        );
+        // Counted across batches
+        run(&steps, fetched());
+        assert_eq!(
+            code_search.stats(),
+            Some(Stats {
+                questions: 6,
+                matched: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_steps_run_in_order() {
+        let unanswered: Arc<dyn ResultTransformer> = Arc::new(Unanswered {
+            include_unanswered: false,
+        });
+        let tagged: Arc<dyn ResultTransformer> = Arc::new(Tagged("public"));
+        // Each step only sees what the ones before it showed, so the order decides why a question
+        // was hidden
+        let results = run(&[unanswered.clone(), tagged.clone()], fetched());
+        assert_eq!(shown(&results), vec![3, 4]);
+        assert_eq!(
+            hidden(&results),
+            vec![
+                (2, Reason::Unanswered),
+                (1, Reason::Other("not tagged for us"))
+            ]
+        );
+        let results = run(&[tagged, unanswered], fetched());
+        assert_eq!(shown(&results), vec![3, 4]);
+        assert_eq!(
+            hidden(&results),
+            vec![
+                (1, Reason::Other("not tagged for us")),
+                (2, Reason::Unanswered)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_before_code_search() {
+        let rewrite: Arc<dyn ResultTransformer> = Arc::new(Rewrite);
+        let code_search: Arc<dyn ResultTransformer> = Arc::new(CodeSearch::new("build.corp"));
+        // Code search runs on what the rewrite left
+        let results = run(&[rewrite.clone(), code_search.clone()], fetched());
+        assert!(results.shown.is_empty());
+        // Rewriting after code search still rewrites the matches it kept
+        let results = run(&[code_search, rewrite], fetched());
+        assert_eq!(shown(&results), vec![1, 3]);
+        assert_eq!(
+            results.shown[1].answers[0].body,
+            "```\ncurl build.example.com\n```"
+        );
+        assert_eq!(results.shown[1].answers[0].code_matches.len(), 1);
+    }
+}
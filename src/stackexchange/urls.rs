@@ -0,0 +1,336 @@
+//! Classification of links to StackExchange posts, for `so url` and anything else that needs to
+//! make sense of a URL someone pasted.
+//!
+//! Recognizes question links (`/questions/<id>`, with or without a slug or answer id after it),
+//! share links (`/q/<id>` and `/a/<id>`, with or without the sharing user's id after them), on
+//! any host, with the mobile `m.` and `www.` prefixes dropped. Whether the host is actually a
+//! StackExchange site is up to the caller, e.g. via `LocalStorage::site_by_host`. `direct` tells
+//! a query that is nothing but such a link, which is looked up rather than searched for.
+//!
+//! Links `so` prints itself are built the other way around, by `format_link`, in the form the
+//! `link_style` config asks for. They come as `PostLink`s, which nothing else can make, so that no
+//! output can slip a link past the preference.
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Post a link points at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Post {
+    Question(u32),
+    Answer(u32),
+}
+
+/// A link to a post on some site
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// Lowercase host, without any `m.` or `www.` prefix
+    pub host: String,
+    pub post: Post,
+}
+
+/// Classify `input` as a link to a question or answer, if it is one. The scheme is optional.
+pub fn classify(input: &str) -> Option<Link> {
+    let input = input.trim();
+    let url = if input.contains("://") {
+        Url::parse(input)
+    } else {
+        Url::parse(&format!("https://{}", input))
+    }
+    .ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = url.host_str()?;
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("m."))
+        .unwrap_or(host);
+    let mut segments = url.path_segments()?;
+    let kind = segments.next()?;
+    let id = segments.next()?;
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let id = id.parse().ok()?;
+    let post = match kind {
+        "questions" | "q" => Post::Question(id),
+        "a" => Post::Answer(id),
+        _ => return None,
+    };
+    Some(Link {
+        host: host.to_string(),
+        post,
+    })
+}
+
+/// A query naming a post outright, rather than describing what to search for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Direct {
+    /// A link to a question or answer, and nothing else
+    Link(Link),
+    /// A bare question id, on the one site given with `--site`
+    Id(u32),
+}
+
+/// What `query` names outright, if it's nothing but a link to a post, or, if a site was
+/// `site_given`, nothing but a question id. A query that merely has a link among other words is
+/// an ordinary query.
+pub fn direct(query: &str, site_given: bool) -> Option<Direct> {
+    let query = query.trim();
+    if query.is_empty() || query.contains(char::is_whitespace) {
+        return None;
+    }
+    if site_given && query.chars().all(|c| c.is_ascii_digit()) {
+        return query.parse().ok().map(Direct::Id);
+    }
+    classify(query).map(Direct::Link)
+}
+
+/// Canonical link to question `id` on the site at `site_url`
+pub fn canonical(site_url: &str, id: u32) -> String {
+    format_link(site_url, LinkKind::Question, id, LinkStyle::Full).into()
+}
+
+/// Which form links to posts take
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// `/questions/<id>`, as the sites link questions themselves
+    #[default]
+    Full,
+    /// `/q/<id>` and `/a/<id>`, as the sites' share buttons do, e.g. for chat messages
+    Short,
+}
+
+/// What a link points at. Answers are on their question's page, which full links go to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    Question,
+    Answer { question_id: u32 },
+}
+
+/// A link to a post, in the form some `LinkStyle` asks for
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostLink(String);
+
+impl PostLink {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for PostLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<PostLink> for String {
+    fn from(link: PostLink) -> Self {
+        link.0
+    }
+}
+
+/// Link to the post `id` of `kind` on the site at `site_url`, in `style`. Sites are given by
+/// their URL, with or without a scheme, and may live under a path, as Teams do, which short links
+/// keep too. Links always go over HTTPS.
+pub fn format_link(site_url: &str, kind: LinkKind, id: u32, style: LinkStyle) -> PostLink {
+    let host = site_url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    PostLink(match (kind, style) {
+        (LinkKind::Question, LinkStyle::Full) => format!("https://{}/questions/{}", host, id),
+        (LinkKind::Question, LinkStyle::Short) => format!("https://{}/q/{}", host, id),
+        (LinkKind::Answer { question_id }, LinkStyle::Full) => {
+            format!("https://{}/questions/{}#{}", host, question_id, id)
+        }
+        (LinkKind::Answer { .. }, LinkStyle::Short) => format!("https://{}/a/{}", host, id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direct() {
+        let link = |host: &str, post| {
+            Some(Direct::Link(Link {
+                host: host.to_string(),
+                post,
+            }))
+        };
+        assert_eq!(
+            direct(
+                "https://stackoverflow.com/questions/11828270/how-do-i-exit-the-vim-editor",
+                false
+            ),
+            link("stackoverflow.com", Post::Question(11828270))
+        );
+        assert_eq!(
+            direct(" askubuntu.com/a/24406 ", false),
+            link("askubuntu.com", Post::Answer(24406))
+        );
+        // Merely mentioning a link, or one to something other than a post, is searched for
+        assert_eq!(
+            direct("why does https://stackoverflow.com/q/1 404", false),
+            None
+        );
+        assert_eq!(direct("https://stackoverflow.com/users/1", false), None);
+        assert_eq!(direct("https://docs.rs/tokio", false), None);
+        assert_eq!(direct("", false), None);
+        // An id only with a site to look it up on
+        assert_eq!(direct("11828270", true), Some(Direct::Id(11828270)));
+        assert_eq!(direct("11828270", false), None);
+        assert_eq!(direct("error 404", true), None);
+        assert_eq!(direct("99999999999", true), None);
+    }
+
+    #[test]
+    fn test_classify() {
+        use Post::*;
+        let cases: &[(&str, Option<(&str, Post)>)] = &[
+            // Question links
+            (
+                "https://stackoverflow.com/questions/11828270/how-do-i-exit-the-vim-editor",
+                Some(("stackoverflow.com", Question(11828270))),
+            ),
+            (
+                "https://stackoverflow.com/questions/11828270",
+                Some(("stackoverflow.com", Question(11828270))),
+            ),
+            (
+                "https://stackoverflow.com/questions/11828270/",
+                Some(("stackoverflow.com", Question(11828270))),
+            ),
+            (
+                "http://unix.stackexchange.com/questions/42/title?noredirect=1#comment1",
+                Some(("unix.stackexchange.com", Question(42))),
+            ),
+            // Answer permalinks still identify the question
+            (
+                "https://stackoverflow.com/questions/11828270/how-do-i-exit/11828573#11828573",
+                Some(("stackoverflow.com", Question(11828270))),
+            ),
+            // Share links, with and without the sharing user's id
+            (
+                "https://stackoverflow.com/q/11828270",
+                Some(("stackoverflow.com", Question(11828270))),
+            ),
+            (
+                "https://stackoverflow.com/q/11828270/1234567",
+                Some(("stackoverflow.com", Question(11828270))),
+            ),
+            (
+                "https://askubuntu.com/a/24406",
+                Some(("askubuntu.com", Answer(24406))),
+            ),
+            (
+                "https://askubuntu.com/a/24406/98765",
+                Some(("askubuntu.com", Answer(24406))),
+            ),
+            // Hosts
+            (
+                "https://m.stackoverflow.com/q/1",
+                Some(("stackoverflow.com", Question(1))),
+            ),
+            (
+                "https://www.stackoverflow.com/q/1",
+                Some(("stackoverflow.com", Question(1))),
+            ),
+            (
+                "https://StackOverflow.com/q/1",
+                Some(("stackoverflow.com", Question(1))),
+            ),
+            (
+                "https://meta.stackexchange.com/q/1",
+                Some(("meta.stackexchange.com", Question(1))),
+            ),
+            // No scheme, or surrounding whitespace
+            (
+                "stackoverflow.com/q/7",
+                Some(("stackoverflow.com", Question(7))),
+            ),
+            (
+                "  https://stackoverflow.com/q/7\n",
+                Some(("stackoverflow.com", Question(7))),
+            ),
+            // Not posts
+            ("https://stackoverflow.com", None),
+            ("https://stackoverflow.com/", None),
+            ("https://stackoverflow.com/questions", None),
+            ("https://stackoverflow.com/questions/", None),
+            ("https://stackoverflow.com/questions/tagged/vim", None),
+            ("https://stackoverflow.com/questions/ask", None),
+            ("https://stackoverflow.com/users/1/jeff-atwood", None),
+            ("https://stackoverflow.com/posts/1/edit", None),
+            ("https://stackoverflow.com/q/-1", None),
+            ("https://stackoverflow.com/q/99999999999", None),
+            ("ftp://stackoverflow.com/q/1", None),
+            ("mailto:someone@stackoverflow.com", None),
+            ("not a url", None),
+            ("", None),
+        ];
+        for (input, expected) in cases {
+            let expected = expected.map(|(host, post)| Link {
+                host: host.to_string(),
+                post,
+            });
+            assert_eq!(classify(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(
+            canonical("stackoverflow.com", 1),
+            "https://stackoverflow.com/questions/1"
+        );
+        assert_eq!(
+            canonical("https://unix.stackexchange.com/", 2),
+            "https://unix.stackexchange.com/questions/2"
+        );
+    }
+
+    #[test]
+    fn test_format_link() {
+        use LinkKind::Question;
+        use LinkStyle::*;
+        let link = |site_url, kind, style| format_link(site_url, kind, 7, style).to_string();
+        let answer = LinkKind::Answer { question_id: 1 };
+        // Site URLs as in the config, as the API lists them, and with a trailing slash
+        for site_url in &[
+            "askubuntu.com",
+            "https://askubuntu.com",
+            "http://askubuntu.com/",
+        ] {
+            assert_eq!(
+                link(site_url, Question, Full),
+                "https://askubuntu.com/questions/7"
+            );
+            assert_eq!(link(site_url, Question, Short), "https://askubuntu.com/q/7");
+            assert_eq!(
+                link(site_url, answer, Full),
+                "https://askubuntu.com/questions/1#7"
+            );
+            assert_eq!(link(site_url, answer, Short), "https://askubuntu.com/a/7");
+        }
+        assert_eq!(
+            link("meta.stackexchange.com", answer, Short),
+            "https://meta.stackexchange.com/a/7"
+        );
+        // Teams live under a path, which their short links keep
+        let team = "https://stackoverflowteams.com/c/acme/";
+        assert_eq!(
+            link(team, Question, Short),
+            "https://stackoverflowteams.com/c/acme/q/7"
+        );
+        assert_eq!(
+            link(team, answer, Full),
+            "https://stackoverflowteams.com/c/acme/questions/1#7"
+        );
+    }
+}
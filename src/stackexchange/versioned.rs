@@ -0,0 +1,210 @@
+//! Envelope for question data persisted across versions of `so`, i.e. the query cache and the
+//! question cache, written as `{"v": 2, "data": ...}`.
+//!
+//! Loading runs one migration per version, from the version the data was written with up to the
+//! current one, so a change to the shape of persisted questions only needs to bump `VERSION` and
+//! add its step to `MIGRATIONS`. Fields that merely got added are left to serde defaults instead,
+//! which is what lets data from one version back load without a migration doing any work. Data
+//! without the envelope is version 1. Anything older, written by a newer `so`, or otherwise
+//! unreadable loads as `None`, which the caches treat as a miss rather than an error.
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::convert::TryFrom;
+
+/// Version of the shape of persisted questions
+pub const VERSION: u32 = 2;
+
+/// Migrations of a single question from each version to the next, starting at version 1
+const MIGRATIONS: [fn(Value) -> Option<Value>; VERSION as usize - 1] = [from_v1];
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct Versioned<T> {
+    pub v: u32,
+    pub data: T,
+}
+
+/// Question data, a single question or a list of them, as written by any version of `so` since 1
+/// and migrated up to the current one
+pub fn load<T: DeserializeOwned>(value: Value) -> Option<T> {
+    let (version, mut data) = match value {
+        Value::Object(mut envelope) if envelope.contains_key("v") => {
+            let version = u32::try_from(envelope.get("v")?.as_u64()?).ok()?;
+            (version, envelope.remove("data")?)
+        }
+        // From before there was an envelope
+        raw => (1, raw),
+    };
+    if version == 0 || version > VERSION {
+        return None;
+    }
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        data = match data {
+            Value::Array(questions) => {
+                Value::Array(questions.into_iter().map(migrate).collect::<Option<_>>()?)
+            }
+            question => migrate(question)?,
+        };
+    }
+    serde_json::from_value(data).ok()
+}
+
+/// Write a field holding question data in the envelope, for `#[serde(with = "versioned")]`
+pub fn serialize<T: Serialize, S: Serializer>(
+    data: &T,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    Versioned { v: VERSION, data }.serialize(serializer)
+}
+
+/// Read a field holding question data with `load`, for `#[serde(with = "versioned")]`
+pub fn deserialize<'de, T: DeserializeOwned, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<T, D::Error> {
+    load(Value::deserialize(deserializer)?)
+        .ok_or_else(|| D::Error::custom("question data from an unreadable version"))
+}
+
+/// Version 1 is what was written before the envelope, and lines up with version 2 field for
+/// field; questions from before they had a site, dates or provenance get those from defaults
+fn from_v1(question: Value) -> Option<Value> {
+    Some(question)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::Question;
+
+    /// Written by the first versions with a query cache: bodies were required, and questions
+    /// had no site or dates
+    const V1_ORIGINAL: &str = r#"[{
+        "question_id": 1,
+        "score": 12,
+        "answers": [{
+            "answer_id": 10,
+            "score": 30,
+            "body_markdown": "Hit `Esc`, then type `:q!`",
+            "is_accepted": true
+        }],
+        "title": "How do I exit Vim?",
+        "body_markdown": "I'm stuck"
+    }]"#;
+
+    /// Written right before the envelope, with everything questions have now
+    const V1_LATEST: &str = r#"[{
+        "question_id": 1,
+        "score": 12,
+        "answers": [{
+            "answer_id": 10,
+            "score": 30,
+            "body_markdown": "Hit `Esc`, then type `:q!`",
+            "is_accepted": true
+        }],
+        "title": "How do I exit Vim?",
+        "body_markdown": null,
+        "site": "stackoverflow",
+        "creation_date": 1600000000,
+        "last_activity_date": 1700000000,
+        "provenance": {
+            "engine": "stackexchange",
+            "site": "stackoverflow",
+            "rank": 1,
+            "request": {"search": {"page": 1}}
+        }
+    }]"#;
+
+    fn load_str<T: DeserializeOwned>(json: &str) -> Option<T> {
+        load(serde_json::from_str(json).unwrap())
+    }
+
+    fn envelope(v: u32, data: &str) -> String {
+        format!(r#"{{"v": {}, "data": {}}}"#, v, data)
+    }
+
+    #[test]
+    fn test_v1_original() {
+        let qs: Vec<Question<String>> = load_str(V1_ORIGINAL).unwrap();
+        assert_eq!(qs.len(), 1);
+        assert_eq!(qs[0].title, "How do I exit Vim?");
+        assert_eq!(qs[0].body.as_deref(), Some("I'm stuck"));
+        assert_eq!(qs[0].answers[0].body, "Hit `Esc`, then type `:q!`");
+        assert_eq!(qs[0].site, "");
+        assert!(qs[0].tags.is_empty());
+        assert_eq!(qs[0].creation_date, None);
+        assert!(qs[0].provenance.is_none());
+    }
+
+    #[test]
+    fn test_v1_latest() {
+        let qs: Vec<Question<String>> = load_str(V1_LATEST).unwrap();
+        assert_eq!(qs[0].body, None);
+        assert_eq!(qs[0].site, "stackoverflow");
+        assert_eq!(qs[0].last_activity_date, Some(1_700_000_000));
+        assert_eq!(qs[0].provenance.as_ref().unwrap().rank, 1);
+        // A single question, as in the question cache
+        let single = V1_LATEST
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let q: Question<String> = load_str(single).unwrap();
+        assert_eq!(q.id, 1);
+    }
+
+    #[test]
+    fn test_current() {
+        let qs: Vec<Question<String>> = load_str(V1_LATEST).unwrap();
+        let written = serde_json::to_string(&Versioned {
+            v: VERSION,
+            data: &qs,
+        })
+        .unwrap();
+        assert!(written.starts_with(r#"{"v":2,"data":[{"question_id":1,"#));
+        let read: Vec<Question<String>> = load_str(&written).unwrap();
+        assert_eq!(
+            serde_json::to_string(&read).unwrap(),
+            serde_json::to_string(&qs).unwrap()
+        );
+        // Enveloped data from one version back loads the same
+        let v1: Vec<Question<String>> = load_str(&envelope(1, V1_ORIGINAL)).unwrap();
+        assert_eq!(v1[0].answers[0].id, 10);
+    }
+
+    #[test]
+    fn test_unreadable() {
+        let load_qs = |json: &str| load_str::<Vec<Question<String>>>(json);
+        // Too old, or from a newer `so`
+        assert!(load_qs(&envelope(0, V1_LATEST)).is_none());
+        assert!(load_qs(&envelope(VERSION + 1, V1_LATEST)).is_none());
+        // Not an envelope after all
+        assert!(load_qs(r#"{"v": "2", "data": []}"#).is_none());
+        assert!(load_qs(r#"{"v": 2}"#).is_none());
+        assert!(load_qs(r#"{"v": -1, "data": []}"#).is_none());
+        // Not questions
+        assert!(load_qs(&envelope(VERSION, r#"[{"id": 1}]"#)).is_none());
+        assert!(load_qs(r#"{"question_id": 1}"#).is_none());
+        assert!(load_qs("42").is_none());
+        assert!(load_qs(&envelope(VERSION, "[]")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_field() {
+        #[derive(Deserialize, Serialize)]
+        struct Entry {
+            fetched_at: i64,
+            #[serde(with = "super")]
+            questions: Vec<Question<String>>,
+        }
+        let raw = format!(r#"{{"fetched_at": 5, "questions": {}}}"#, V1_ORIGINAL);
+        let entry: Entry = serde_json::from_str(&raw).unwrap();
+        let written = serde_json::to_value(&entry).unwrap();
+        assert_eq!(written["questions"]["v"], VERSION);
+        assert_eq!(written["questions"]["data"][0]["question_id"], 1);
+        let raw = format!(
+            r#"{{"fetched_at": 5, "questions": {}}}"#,
+            envelope(0, V1_ORIGINAL)
+        );
+        assert!(serde_json::from_str::<Entry>(&raw).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Templates for the answer printed in lucky mode, set via `lucky_template` in the config, e.g.
+//!
+//! ```text
+//! {title} [{score}]
+//! {body}
+//! -- {link}
+//! ```
+//!
+//! Placeholders are field names in braces; `{body}` is rendered for the terminal, while
+//! `{body:raw}` is the markdown as is. Literal braces are written `{{` and `}}`.
+
+use std::fmt;
+
+/// Template reproducing what lucky mode printed before templates existed
+pub const DEFAULT: &str = "{body}";
+
+/// Marker `{accepted}` expands to for accepted answers, the same one the TUI shows
+const ACCEPTED_MARKER: &str = "\u{2713}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,
+    Site,
+    Score,
+    Accepted,
+    Body,
+    RawBody,
+    Link,
+    Tags,
+}
+
+impl Field {
+    /// Field for the placeholder `name`, with its `modifier` if any
+    fn parse(name: &str, modifier: Option<&str>) -> Result<Self, ParseError> {
+        let field = match name {
+            "title" => Field::Title,
+            "site" => Field::Site,
+            "score" => Field::Score,
+            "accepted" => Field::Accepted,
+            "body" => Field::Body,
+            "link" => Field::Link,
+            "tags" => Field::Tags,
+            _ => return Err(ParseError::UnknownPlaceholder(name.to_string())),
+        };
+        match (field, modifier) {
+            (field, None) => Ok(field),
+            (Field::Body, Some("raw")) => Ok(Field::RawBody),
+            (_, Some(modifier)) => Err(ParseError::UnknownModifier {
+                placeholder: name.to_string(),
+                modifier: modifier.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnknownPlaceholder(String),
+    UnknownModifier {
+        placeholder: String,
+        modifier: String,
+    },
+    /// A `{` without its `}`, holding what followed it
+    Unclosed(String),
+    /// A `}` that doesn't close a placeholder
+    Unmatched,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownPlaceholder(name) => write!(
+                f,
+                "unknown placeholder `{{{}}}`; available are {{title}}, {{site}}, {{score}}, \
+                 {{accepted}}, {{body}}, {{body:raw}}, {{link}} and {{tags}}",
+                name
+            ),
+            ParseError::UnknownModifier {
+                placeholder,
+                modifier,
+            } => write!(
+                f,
+                "unknown modifier `{}` in `{{{}:{}}}`; only `{{body:raw}}` takes one",
+                modifier, placeholder, modifier
+            ),
+            ParseError::Unclosed(rest) => write!(
+                f,
+                "unclosed placeholder `{{{}`; write `{{{{` for a literal brace",
+                rest
+            ),
+            ParseError::Unmatched => write!(f, "unmatched `}}`; write `}}}}` for a literal brace"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// What placeholders expand to; fields that aren't known expand to nothing
+#[derive(Debug, Clone, Default)]
+pub struct Values<'a> {
+    pub title: &'a str,
+    pub site: &'a str,
+    /// Already formatted for the locale
+    pub score: String,
+    pub accepted: bool,
+    /// Body as rendered for the terminal
+    pub body: &'a str,
+    /// Body as markdown
+    pub raw_body: &'a str,
+    pub link: Option<&'a str>,
+    pub tags: &'a [String],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template {
+            parts: vec![Part::Field(Field::Body)],
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, ParseError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+        while let Some((ix, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(ParseError::Unmatched),
+                '{' => {
+                    let rest = &template[ix + 1..];
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| ParseError::Unclosed(rest.to_string()))?;
+                    let placeholder = &rest[..end];
+                    let (name, modifier) = match placeholder.split_once(':') {
+                        Some((name, modifier)) => (name, Some(modifier)),
+                        None => (placeholder, None),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(Field::parse(name.trim(), modifier)?));
+                    // Skip past the closing brace
+                    while chars.next().is_some_and(|(_, c)| c != '}') {}
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    pub fn render(&self, values: &Values<'_>) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field(Field::Title) => out.push_str(values.title),
+                Part::Field(Field::Site) => out.push_str(values.site),
+                Part::Field(Field::Score) => out.push_str(&values.score),
+                Part::Field(Field::Accepted) if values.accepted => out.push_str(ACCEPTED_MARKER),
+                Part::Field(Field::Accepted) => {}
+                Part::Field(Field::Body) => out.push_str(values.body),
+                Part::Field(Field::RawBody) => out.push_str(values.raw_body),
+                Part::Field(Field::Link) => out.push_str(values.link.unwrap_or_default()),
+                Part::Field(Field::Tags) => out.push_str(&values.tags.join(" ")),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Values<'static> {
+        Values {
+            title: "How do I exit Vim?",
+            site: "stackoverflow",
+            score: String::from("2,048"),
+            accepted: true,
+            body: "Hit Esc, then type :q",
+            raw_body: "Hit `Esc`, then type `:q`",
+            link: Some("https://stackoverflow.com/questions/11828270"),
+            tags: &[],
+        }
+    }
+
+    fn render(template: &str, values: &Values<'_>) -> String {
+        Template::parse(template).unwrap().render(values)
+    }
+
+    #[test]
+    fn test_default() {
+        let template = Template::parse(DEFAULT).unwrap();
+        assert_eq!(template, Template::default());
+        assert_eq!(template.render(&values()), "Hit Esc, then type :q");
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("{title} [{score}] {accepted}\n{body}\n-- {link}", &values()),
+            "How do I exit Vim? [2,048] \u{2713}\n\
+             Hit Esc, then type :q\n\
+             -- https://stackoverflow.com/questions/11828270"
+        );
+        assert_eq!(
+            render("{site}: {body:raw}", &values()),
+            "stackoverflow: Hit `Esc`, then type `:q`"
+        );
+        let tags = [String::from("vim"), String::from("editor")];
+        let values = Values {
+            tags: &tags,
+            ..values()
+        };
+        assert_eq!(render("{ tags }", &values), "vim editor");
+        assert_eq!(render("", &values), "");
+        assert_eq!(render("just text", &values), "just text");
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let values = Values {
+            accepted: false,
+            link: None,
+            ..values()
+        };
+        assert_eq!(
+            render("[{accepted}] {title} <{link}> {tags}.", &values),
+            "[] How do I exit Vim? <> ."
+        );
+        assert_eq!(render("{title}", &Values::default()), String::new());
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(render("{{body}}", &values()), "{body}");
+        assert_eq!(render("{{{score}}} }}{{", &values()), "{2,048} }{");
+        assert_eq!(Template::parse("{body} }"), Err(ParseError::Unmatched));
+        assert_eq!(
+            Template::parse("{body} {title"),
+            Err(ParseError::Unclosed(String::from("title")))
+        );
+        assert_eq!(
+            Template::parse("{ {body}"),
+            Err(ParseError::UnknownPlaceholder(String::from("{body")))
+        );
+    }
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(
+            Template::parse("{title:raw}"),
+            Err(ParseError::UnknownModifier {
+                placeholder: String::from("title"),
+                modifier: String::from("raw"),
+            })
+        );
+        assert_eq!(
+            Template::parse("{body:bold}").unwrap_err().to_string(),
+            "unknown modifier `bold` in `{body:bold}`; only `{body:raw}` takes one"
+        );
+        // Only the first colon separates the modifier
+        assert!(matches!(
+            Template::parse("{body:raw:raw}"),
+            Err(ParseError::UnknownModifier { modifier, .. }) if modifier == "raw:raw"
+        ));
+    }
+
+    #[test]
+    fn test_unknown_placeholder() {
+        let err = Template::parse("{title}\n{answer}").unwrap_err();
+        assert_eq!(err, ParseError::UnknownPlaceholder(String::from("answer")));
+        assert!(err
+            .to_string()
+            .starts_with("unknown placeholder `{answer}`"));
+    }
+}
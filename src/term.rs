@@ -1,18 +1,22 @@
-use crossterm::event::{read, Event, KeyCode, KeyEvent};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::style::{Color, Print};
 use crossterm::terminal::ClearType;
 use crossterm::{cursor, execute, terminal};
 use futures::Future;
-use std::io::{stderr, Write};
+use std::io::{self, stderr, IsTerminal, Write};
+use std::sync::Arc;
+use std::time::Duration;
 use termimad::{CompoundStyle, LineStyle, MadSkin};
 use tokio::sync::{
     oneshot,
     oneshot::{error::TryRecvError, Receiver, Sender},
+    Notify,
 };
 use tokio::task::JoinHandle;
-use tokio::time;
+use tokio::{signal, time};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::stackexchange::commands;
 
 const LOADING_SPINNER_DELAY: u64 = 40;
 const LOADING_SPINNER_DOTS: [&str; 56] = [
@@ -35,19 +39,26 @@ impl Default for Term {
 struct Spinner {
     tx: Sender<()>,
     handle: JoinHandle<Result<()>>,
+    /// Notified on Ctrl-C, which the spinner reads as a key press since it puts the terminal in
+    /// raw mode
+    interrupt: Arc<Notify>,
+}
+
+/// Markdown styles for output outside of the TUI
+pub fn skin() -> MadSkin {
+    MadSkin {
+        inline_code: CompoundStyle::with_fg(Color::Cyan),
+        code_block: LineStyle {
+            compound_style: CompoundStyle::with_fg(Color::Cyan),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
 }
 
 impl Term {
     pub fn new() -> Self {
-        let skin = MadSkin {
-            inline_code: CompoundStyle::with_fg(Color::Cyan),
-            code_block: LineStyle {
-                compound_style: CompoundStyle::with_fg(Color::Cyan),
-                ..Default::default()
-            },
-            ..Default::default()
-        };
-        Term { skin }
+        Term { skin: skin() }
     }
 
     /// Print text to stdout
@@ -73,7 +84,8 @@ impl Term {
         // Set fg
         self.skin.paragraph.set_fg(fg);
         self.skin
-            .write_text_on(&mut std::io::stderr(), &styled_text)?;
+            .write_text_on(&mut std::io::stderr(), &styled_text)
+            .map_err(terminal_error)?;
         // Unset fg
         self.skin
             .paragraph
@@ -83,42 +95,93 @@ impl Term {
         Ok(())
     }
 
-    /// Blocks and waits for the user to press any key. Returns whether or not that key is the
-    /// character key `c`.
-    pub fn wait_for_char(c: char) -> Result<bool> {
-        let mut pressed = false;
-        terminal::enable_raw_mode()?;
-        loop {
-            match read()? {
+    /// Blocks and waits for the user to press any key. Returns its character, if it's a character
+    /// key.
+    pub fn read_char() -> Result<Option<char>> {
+        terminal::enable_raw_mode().map_err(terminal_error)?;
+        let pressed = loop {
+            match read().map_err(terminal_error)? {
                 Event::Key(KeyEvent {
                     code: KeyCode::Char(ch),
                     ..
-                }) if ch == c => {
-                    pressed = true;
-                    break;
-                }
-                Event::Key(_) => break,
+                }) => break Some(ch),
+                Event::Key(_) => break None,
                 _ => (),
             }
-        }
-        terminal::disable_raw_mode()?;
+        };
+        terminal::disable_raw_mode().map_err(terminal_error)?;
         Ok(pressed)
     }
 
-    /// As it sounds, takes a future and shows a CLI spinner until it's output is ready
+    /// Lists `items` numbered on stderr and asks which of them to take, e.g. `1,3-4`, until the
+    /// answer makes sense; blank takes all of them. Returns their indexes, in the order given.
+    pub fn pick(items: &[String]) -> Result<Vec<usize>> {
+        let mut stderr = stderr();
+        for (i, item) in items.iter().enumerate() {
+            let item = item.replace('\n', "\n     ");
+            writeln!(stderr, "{:>3}  {}", i + 1, item)?;
+        }
+        loop {
+            write!(stderr, "Pick (e.g. 1,3-4, blank for all): ")?;
+            stderr.flush()?;
+            let mut input = String::new();
+            if std::io::stdin().read_line(&mut input)? == 0 {
+                // Nothing to go by
+                return Ok(Vec::new());
+            }
+            match commands::parse_picks(&input, items.len()) {
+                Some(picks) => return Ok(picks),
+                None => writeln!(stderr, "Pick numbers from 1 to {}", items.len())?,
+            }
+        }
+    }
+
+    /// Asks `question` on stderr until the answer is yes or no; blank, or no input at all, is no
+    pub fn confirm(question: &str) -> Result<bool> {
+        let mut stderr = stderr();
+        loop {
+            write!(stderr, "{} [y/N] ", question)?;
+            stderr.flush()?;
+            let mut input = String::new();
+            if std::io::stdin().read_line(&mut input)? == 0 {
+                return Ok(false);
+            }
+            match input.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "" | "n" | "no" => return Ok(false),
+                _ => writeln!(stderr, "Answer y or n")?,
+            }
+        }
+    }
+
+    /// As it sounds, takes a future and shows a CLI spinner until it's output is ready. Without a
+    /// terminal to show it on, e.g. when run from a script, there's no spinner.
+    ///
+    /// Ctrl-C drops the future, aborting whatever requests it has in flight, clears the spinner
+    /// and fails with `Error::Interrupted`.
     pub async fn wrap_spinner<F>(future: F) -> Result<F::Output>
     where
         F: Future,
     {
+        if !stderr().is_terminal() {
+            return interruptible(future, ctrl_c()).await;
+        }
         // Start spinner
         let spinner = Spinner::new();
+        let interrupt = Arc::clone(&spinner.interrupt);
 
-        let result = future.await;
+        let result = interruptible(future, async {
+            tokio::select! {
+                _ = ctrl_c() => (),
+                _ = interrupt.notified() => (),
+            }
+        })
+        .await;
 
         // Stop spinner
         spinner.stop().await?;
 
-        Ok(result)
+        result
     }
 }
 
@@ -127,8 +190,13 @@ impl Spinner {
     /// `Spinner`.
     pub fn new() -> Self {
         let (tx, rx) = oneshot::channel();
-        let handle = tokio::spawn(Self::spin(rx));
-        Spinner { tx, handle }
+        let interrupt = Arc::new(Notify::new());
+        let handle = tokio::spawn(Self::spin(rx, Arc::clone(&interrupt)));
+        Spinner {
+            tx,
+            handle,
+            interrupt,
+        }
     }
 
     /// Stop the spinner. This requires a bit of cleanup, and so should be `await`ed before doing
@@ -139,16 +207,17 @@ impl Spinner {
         Ok(())
     }
 
-    /// Spin until receiver finds unit
-    async fn spin(mut rx: Receiver<()>) -> Result<()> {
+    /// Spin until receiver finds unit, notifying `interrupt` of Ctrl-C in the meantime
+    async fn spin(mut rx: Receiver<()>, interrupt: Arc<Notify>) -> Result<()> {
         let mut dots = LOADING_SPINNER_DOTS.iter().cycle();
-        terminal::enable_raw_mode()?;
+        terminal::enable_raw_mode().map_err(terminal_error)?;
         execute!(
             stderr(),
             cursor::SavePosition,
             cursor::Hide,
             terminal::Clear(ClearType::CurrentLine),
-        )?;
+        )
+        .map_err(terminal_error)?;
         let mut interval = time::interval(time::Duration::from_millis(LOADING_SPINNER_DELAY));
         while let Err(TryRecvError::Empty) = rx.try_recv() {
             execute!(
@@ -156,7 +225,19 @@ impl Spinner {
                 cursor::MoveToColumn(0),
                 terminal::Clear(ClearType::CurrentLine),
                 Print(dots.next().unwrap())
-            )?;
+            )
+            .map_err(terminal_error)?;
+            while poll(Duration::from_millis(0)).map_err(terminal_error)? {
+                if let Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers,
+                }) = read().map_err(terminal_error)?
+                {
+                    if modifiers.contains(KeyModifiers::CONTROL) {
+                        interrupt.notify_one();
+                    }
+                }
+            }
             interval.tick().await;
         }
         execute!(
@@ -164,12 +245,41 @@ impl Spinner {
             terminal::Clear(ClearType::CurrentLine),
             cursor::RestorePosition,
             cursor::Show,
-        )?;
-        terminal::disable_raw_mode()?;
+        )
+        .map_err(terminal_error)?;
+        terminal::disable_raw_mode().map_err(terminal_error)?;
         Ok(())
     }
 }
 
+/// `future`'s output, unless `interrupt` resolves first, in which case `future` is dropped, and with
+/// it the requests it has in flight, and this fails with `Error::Interrupted`
+pub async fn interruptible<F, I>(future: F, interrupt: I) -> Result<F::Output>
+where
+    F: Future,
+    I: Future<Output = ()>,
+{
+    tokio::select! {
+        output = future => Ok(output),
+        _ = interrupt => Err(Error::Interrupted),
+    }
+}
+
+/// Resolves on SIGINT, or never if it can't be listened for
+async fn ctrl_c() {
+    if signal::ctrl_c().await.is_err() {
+        futures::future::pending::<()>().await;
+    }
+}
+
+/// A failure of termimad or crossterm, which are IO errors underneath
+fn terminal_error<E>(e: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Error::IO(io::Error::other(e))
+}
+
 pub fn print_error(text: &str) -> Result<()> {
     Term::new().print_error(text)
 }
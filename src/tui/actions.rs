@@ -0,0 +1,254 @@
+//! Everything the TUI can do, as one registry for its key bindings and the action palette.
+//!
+//! An action has the key it's bound to, a name and a description, the panes it makes sense from,
+//! and a handler. `bind` binds the keys, and `:` or Ctrl-P opens the palette: the actions that
+//! make sense from the focused pane, narrowed down as you type by fuzzily matching their names and
+//! descriptions. Actions on the selected answer are only offered while the answer side has focus,
+//! though their keys work from anywhere, as they always have.
+
+use cursive::event::{Event, Key};
+use cursive::traits::{Nameable, Resizable, Scrollable};
+use cursive::views::{Dialog, EditView, LinearLayout, OnEventView, SelectView};
+use cursive::Cursive;
+use std::rc::Rc;
+
+use super::fuzzy;
+use super::views::{LayoutView, NAME_ANSWER_LIST, NAME_ANSWER_VIEW, NAME_FULL_LAYOUT};
+
+pub const NAME_PALETTE_QUERY: &str = "palette_query";
+pub const NAME_PALETTE_LIST: &str = "palette_list";
+
+/// Keys that open the palette, or close it
+const PALETTE_KEYS: [Event; 2] = [Event::Char(':'), Event::CtrlChar('p')];
+
+/// Widest the palette gets
+const PALETTE_WIDTH: usize = 96;
+
+/// Side of the layout that has focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    /// The question list or the question
+    Questions,
+    /// The answer list or the answer
+    Answers,
+}
+
+impl Focus {
+    /// Side of the layout on `s` that has focus
+    pub fn of(s: &mut Cursive) -> Self {
+        let focused = s.call_on_name(NAME_FULL_LAYOUT, |v: &mut LayoutView| v.focused());
+        match focused {
+            Some(NAME_ANSWER_LIST) | Some(NAME_ANSWER_VIEW) => Focus::Answers,
+            _ => Focus::Questions,
+        }
+    }
+}
+
+/// Where an action's key is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// A global callback, which `bind` adds
+    Global,
+    /// The layout, which handles it itself while it's on top
+    Layout,
+}
+
+pub type Handler = Rc<dyn Fn(&mut Cursive)>;
+
+pub struct Action {
+    pub key: Event,
+    pub binding: Binding,
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Whether it makes sense with this focus, and so is offered in the palette
+    pub when: fn(Focus) -> bool,
+    pub handler: Handler,
+}
+
+impl Action {
+    /// Action bound globally to `key`, making sense from anywhere
+    pub fn new<K, F>(key: K, name: &'static str, description: &'static str, handler: F) -> Self
+    where
+        K: Into<Event>,
+        F: Fn(&mut Cursive) + 'static,
+    {
+        Action {
+            key: key.into(),
+            binding: Binding::Global,
+            name,
+            description,
+            when: anywhere,
+            handler: Rc::new(handler),
+        }
+    }
+
+    /// This action, only offered while the answer side has focus
+    pub fn on_answers(self) -> Self {
+        Action {
+            when: on_answers,
+            ..self
+        }
+    }
+
+    /// This action, with its key handled by the layout rather than bound globally
+    pub fn bound_by_layout(self) -> Self {
+        Action {
+            binding: Binding::Layout,
+            ..self
+        }
+    }
+}
+
+fn anywhere(_: Focus) -> bool {
+    true
+}
+
+fn on_answers(focus: Focus) -> bool {
+    focus == Focus::Answers
+}
+
+/// `key` as the help writes it, e.g. `Ctrl<r>`
+pub fn key_label(key: &Event) -> String {
+    match key {
+        Event::Char(' ') => String::from("Space"),
+        Event::Char(c) => c.to_string(),
+        Event::CtrlChar(c) => format!("Ctrl<{}>", c),
+        Event::Key(key) => format!("{:?}", key),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Indexes of those of `actions` that make sense with `focus` and match `query`, best match first
+pub fn matching(actions: &[Action], focus: Focus, query: &str) -> Vec<usize> {
+    let offered: Vec<usize> = (0..actions.len())
+        .filter(|&i| (actions[i].when)(focus))
+        .collect();
+    let texts: Vec<String> = offered
+        .iter()
+        .map(|&i| format!("{} {}", actions[i].name, actions[i].description))
+        .collect();
+    fuzzy::visible(query, texts.iter().map(String::as_str))
+        .into_iter()
+        .map(|(i, _)| offered[i])
+        .collect()
+}
+
+/// Bind the keys of `actions` that are bound globally, and the keys of the palette offering them
+pub fn bind(siv: &mut Cursive, actions: Vec<Action>) {
+    let actions = Rc::new(actions);
+    for action in actions.iter().filter(|a| a.binding == Binding::Global) {
+        let handler = Rc::clone(&action.handler);
+        siv.add_global_callback(action.key.clone(), move |s| handler(s));
+    }
+    for key in PALETTE_KEYS.iter() {
+        let actions = Rc::clone(&actions);
+        siv.add_global_callback(key.clone(), move |s| toggle_palette(s, &actions));
+    }
+}
+
+/// Open the palette of `actions`, or close it if it's open
+fn toggle_palette(s: &mut Cursive, actions: &Rc<Vec<Action>>) {
+    if let Some(pos) = s.screen_mut().find_layer_from_name(NAME_PALETTE_QUERY) {
+        s.screen_mut().remove_layer(pos);
+        return;
+    }
+    let focus = Focus::of(s);
+    let mut list = SelectView::new();
+    fill(&mut list, actions, focus, "");
+    let submitted = Rc::clone(actions);
+    list.set_on_submit(move |s, i: &usize| run(s, &submitted, *i));
+    let edited = Rc::clone(actions);
+    let entered = Rc::clone(actions);
+    // Enter runs the top match, or whichever is selected
+    let query = EditView::new()
+        .on_edit(move |s, query, _| {
+            s.call_on_name(NAME_PALETTE_LIST, |v: &mut SelectView<usize>| {
+                fill(v, &edited, focus, query)
+            });
+        })
+        .on_submit(move |s, _| {
+            let selected = s
+                .call_on_name(NAME_PALETTE_LIST, |v: &mut SelectView<usize>| v.selection())
+                .flatten();
+            if let Some(i) = selected {
+                run(s, &entered, *i);
+            }
+        })
+        .with_name(NAME_PALETTE_QUERY)
+        .full_width();
+    let width = s.screen_size().x.saturating_sub(4).min(PALETTE_WIDTH);
+    let palette = LinearLayout::vertical()
+        .child(query)
+        .child(
+            list.with_name(NAME_PALETTE_LIST)
+                .scrollable()
+                .max_height(12),
+        )
+        .fixed_width(width);
+    s.add_layer(
+        OnEventView::new(Dialog::around(palette).title("Actions")).on_event(Key::Esc, |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+/// Fill `list` with those of `actions` that make sense with `focus` and match `query`
+fn fill(list: &mut SelectView<usize>, actions: &[Action], focus: Focus, query: &str) {
+    list.clear();
+    for i in matching(actions, focus, query) {
+        let action = &actions[i];
+        list.add_item(
+            format!(
+                "{:<9}{:<26}{}",
+                key_label(&action.key),
+                action.name,
+                action.description
+            ),
+            i,
+        );
+    }
+}
+
+/// Close the palette and run the action `i` of `actions` on what was selected before it opened
+fn run(s: &mut Cursive, actions: &[Action], i: usize) {
+    s.pop_layer();
+    (actions[i].handler)(s);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions() -> Vec<Action> {
+        vec![
+            Action::new('p', "Pin question", "Pin the selected question", |_| ()),
+            Action::new('O', "Open answer", "Open the answer in a browser", |_| ()).on_answers(),
+            Action::new('q', "Quit", "Exit", |s| s.quit()).bound_by_layout(),
+        ]
+    }
+
+    #[test]
+    fn test_context() {
+        let actions = actions();
+        assert_eq!(matching(&actions, Focus::Answers, ""), vec![0, 1, 2]);
+        // Answer actions are left out while the questions have focus
+        assert_eq!(matching(&actions, Focus::Questions, ""), vec![0, 2]);
+        assert!(matching(&actions, Focus::Questions, "open answer").is_empty());
+    }
+
+    #[test]
+    fn test_matching() {
+        let actions = actions();
+        assert_eq!(matching(&actions, Focus::Answers, "pin")[0], 0);
+        assert_eq!(matching(&actions, Focus::Answers, "browser"), vec![1]);
+        assert_eq!(matching(&actions, Focus::Answers, "QUIT")[0], 2);
+        assert!(matching(&actions, Focus::Answers, "zzz").is_empty());
+    }
+
+    #[test]
+    fn test_key_label() {
+        assert_eq!(key_label(&Event::Char('p')), "p");
+        assert_eq!(key_label(&Event::Char(' ')), "Space");
+        assert_eq!(key_label(&Event::CtrlChar('r')), "Ctrl<r>");
+    }
+}
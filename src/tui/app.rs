@@ -1,119 +1,2123 @@
-use cursive::event::Event;
+use cursive::event::{Event, Key};
 use cursive::theme::{BaseColor, Color, Effect, Style};
-use cursive::traits::{Nameable, Scrollable};
+use cursive::traits::{Nameable, Resizable, Scrollable};
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::SpannedString;
-use cursive::views::{Dialog, TextView};
+use cursive::view::Position;
+use cursive::views::{Dialog, EditView, Layer, LinearLayout, OnEventView, SelectView, TextView};
 use cursive::Cursive;
 use cursive::XY;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
 
+use super::actions::{self, Action};
+use super::code_blocks;
+use super::comments::{Comments, Fetch, Prefetch, Rewritten, Source};
+use super::console;
+use super::diff;
+use super::editor;
+use super::fuzzy;
+use super::graphics::{self, Protocol};
+use super::loading::{self, Ticket};
+use super::local_file;
 use super::markdown;
-use super::markdown::Markdown;
+use super::markdown::{CodeBlock, Image, Markdown};
+use super::pins::Pins;
+use super::prefetch::{Busy, Prefetcher};
+use super::search_again::{self, SearchAgain};
+use super::share;
+use super::shown::Shown;
+use super::spoilers::Spoilers;
+use super::syntax::{Highlighter, Syntax};
+use super::theme::{self, Appearance, Detect, Resolved};
+use super::updater::{Update, Updater};
 use super::views::{
     LayoutView, ListView, MdView, Name, Vimable, NAME_ANSWER_LIST, NAME_ANSWER_VIEW,
-    NAME_QUESTION_LIST, NAME_QUESTION_VIEW,
+    NAME_FULL_LAYOUT, NAME_QUESTION_LIST, NAME_QUESTION_VIEW,
 };
-use crate::config::Config;
+use crate::config::{Config, ImagePreview};
 use crate::error::Result;
-use crate::stackexchange::{Answer, Question};
+use crate::format::{self, Locale, ScoreStyle, ScoreThresholds};
+use crate::messages::Messages;
+use crate::notes::{Note, Notes};
+use crate::stackexchange::aliases::Aliases;
+use crate::stackexchange::answer_sort::AnswerSort;
+use crate::stackexchange::backoff::Backoff;
+use crate::stackexchange::filtered::{Filtered, Hidden, Reason};
+use crate::stackexchange::filters::Filters;
+use crate::stackexchange::images::ImageCache;
+use crate::stackexchange::query_cache;
+use crate::stackexchange::{
+    Answer, Api, CodeMatch, Comment, Destination, OfflinePolicy, Owner, Provenance, Question,
+    Request, Search,
+};
+use crate::utils;
+
+pub const NAME_HELP_VIEW: &str = "help_view";
+pub const NAME_NOTE_EDIT: &str = "note_edit";
+pub const NAME_DIFF_PATH: &str = "diff_path";
+pub const NAME_DIFF_BLOCK: &str = "diff_block";
+pub const NAME_SHOWN_STATUS: &str = "shown_status";
+pub const NAME_FILTER_EDIT: &str = "filter_edit";
+pub const NAME_SHARE_PATH: &str = "share_path";
+pub const NAME_EDIT_BLOCK: &str = "edit_block";
+pub const NAME_SEARCH_EDIT: &str = "search_edit";
+
+/// How long a notice stays in the status bar
+const NOTICE_SECS: u64 = 3;
+
+/// State that lives for the duration of the TUI session
+struct Session {
+    /// What was searched for
+    query: String,
+    /// Questions from the latest search, in their original order
+    results: Vec<Question<Markdown>>,
+    /// Questions from the latest search that filters left out, and why
+    hidden: Vec<Hidden<Markdown>>,
+    /// List the questions filters left out too, after the others
+    reveal: bool,
+    pins: Pins<Markdown>,
+    /// Questions listed so far this session
+    shown: Shown,
+    notes: Notes,
+    scores: Scores,
+    /// Only list questions whose title fuzzily matches this, best match first
+    filter: String,
+    /// Whether the filter is being typed, which keeps the top match selected
+    filtering: bool,
+    /// Question and answer id of the answer marked to diff other answers against
+    diff_base: Option<(u32, u32)>,
+    image_preview: ImagePreview,
+    /// Images to draw once the TUI is suspended, see `graphics`
+    preview: Option<Preview>,
+    /// Code to edit once the TUI is suspended, see `editor`
+    edit: Option<Edit>,
+    /// Code edited this session, to print once the TUI quits
+    edited: Vec<String>,
+    comments: Comments,
+    /// Fetches the comments on the listed answers ahead of time, with `prefetch_comments`
+    prefetcher: Option<Prefetcher>,
+    /// Searches for other queries, started from the TUI
+    search_again: SearchAgain,
+    spoilers: Spoilers,
+    /// Highlights the syntax of code, unless turned off
+    syntax: Option<Highlighter>,
+    /// Posts rendered with their syntax highlighted so far, by id, since that takes a while
+    highlighted: HashMap<u32, Markdown>,
+    /// Opens links in a browser
+    open: Opener,
+    /// Puts shared summaries and code on the clipboard
+    copy: Copier,
+    /// Code block of the selected answer selected with `]` or `[`, to copy with `y`
+    code_block: code_blocks::Cursor,
+    /// What the status bar tells until a few seconds after it's set, and how many were set so
+    /// far, which tells a later one from the one to clear
+    notice: Option<String>,
+    notices: usize,
+    /// Longest a shared summary gets, in characters
+    share_max_chars: usize,
+    /// Order answers are listed in, which `a` cycles through
+    answer_sort: AnswerSort,
+    updater: Updater<Markdown>,
+}
+
+type Opener = Box<dyn Fn(&str) -> Result<()>>;
+type Copier = Box<dyn Fn(&str) -> Result<()>>;
+
+/// A question as listed: whether it's pinned, why filters left it out if it's only listed to
+/// reveal what they did, and which characters of its title matched the filter
+type Listed = (Question<Markdown>, bool, Option<Reason>, Vec<usize>);
+
+struct Preview {
+    title: String,
+    images: Vec<Image>,
+    protocol: Protocol,
+}
+
+#[derive(Clone)]
+struct Edit {
+    code: String,
+    extension: &'static str,
+}
+
+impl Session {
+    /// Current contents of the question list
+    fn questions(&self) -> Vec<Question<Markdown>> {
+        self.pins.merge(self.results.clone())
+    }
+
+    /// Current contents of the question list, with whether each is pinned, and why filters
+    /// left it out if it's only listed to reveal what they did
+    fn listing(&self) -> Vec<(Question<Markdown>, bool, Option<Reason>)> {
+        let mut listing: Vec<_> = self
+            .questions()
+            .into_iter()
+            .map(|q| {
+                let pinned = self.pins.is_pinned(q.id);
+                (q, pinned, None)
+            })
+            .collect();
+        if self.reveal {
+            for h in &self.hidden {
+                if !listing.iter().any(|(q, _, _)| q.id == h.question.id) {
+                    listing.push((h.question.clone(), false, Some(h.reason)));
+                }
+            }
+        }
+        listing
+    }
+
+    /// The listing narrowed down to the questions matching the filter, with the positions of the
+    /// characters of their titles that matched
+    fn visible(&self) -> Vec<Listed> {
+        let listing = self.listing();
+        fuzzy::visible(
+            &self.filter,
+            listing.iter().map(|(q, _, _)| q.title.as_str()),
+        )
+        .into_iter()
+        .map(|(i, m)| {
+            let (q, pinned, hidden) = listing[i].clone();
+            (q, pinned, hidden, m.positions)
+        })
+        .collect()
+    }
+
+    /// Question with id `qid`, with the latest data fetched for it
+    fn question(&self, qid: u32) -> Option<&Question<Markdown>> {
+        self.all().find(|q| q.id == qid)
+    }
+
+    /// Answer with id `aid` to any question
+    fn answer(&self, aid: u32) -> Option<&Answer<Markdown>> {
+        self.all()
+            .flat_map(|q| q.answers.iter())
+            .find(|a| a.id == aid)
+    }
+
+    /// Question `aid` answers
+    fn answer_question(&self, aid: u32) -> Option<&Question<Markdown>> {
+        self.all().find(|q| q.answers.iter().any(|a| a.id == aid))
+    }
+
+    /// Site of the question `aid` answers
+    fn answer_site(&self, aid: u32) -> Option<&str> {
+        self.answer_question(aid).map(|q| q.site.as_str())
+    }
+
+    /// Reveal the next spoiler of the post `id`, see `Spoilers::toggle`
+    fn toggle_spoiler(&mut self, id: u32, body: &Markdown) -> bool {
+        // Rendered with the spoilers as they were
+        self.highlighted.remove(&id);
+        self.spoilers.toggle(id, body)
+    }
+
+    /// `body` of the post `id`, on a question tagged `tags`, as its view shows it: with the
+    /// spoilers revealed so far, the code lines `highlights` highlighted, and the syntax of its
+    /// code highlighted the first time it's shown
+    fn render(
+        &mut self,
+        id: u32,
+        body: &Markdown,
+        highlights: &[CodeMatch],
+        tags: &[String],
+    ) -> Markdown {
+        let highlighter = match &self.syntax {
+            Some(highlighter) => highlighter,
+            None => return self.spoilers.render(id, body, highlights),
+        };
+        if let Some(rendered) = self.highlighted.get(&id) {
+            return rendered.clone();
+        }
+        let syntax = Syntax { highlighter, tags };
+        let rendered = self
+            .spoilers
+            .render_highlighted(id, body, highlights, Some(syntax));
+        self.highlighted.insert(id, rendered.clone());
+        rendered
+    }
+
+    /// Code blocks of the answer `aid` as its view shows them
+    fn code_blocks(&self, aid: u32) -> Vec<CodeBlock> {
+        match self.answer(aid) {
+            Some(answer) => self.spoilers.code_blocks(aid, &answer.body),
+            None => Vec::new(),
+        }
+    }
+
+    /// Open `link` to a `post` in a browser, telling `messages` if there's none or that fails
+    fn open_link(&self, link: Option<&str>, post: &str, messages: &Messages) {
+        match link {
+            Some(link) => {
+                if let Err(e) = (self.open)(link) {
+                    messages.error(e.to_string());
+                }
+            }
+            None => messages.warn(format!(
+                "This {} has no link, likely since it was cached by an older version",
+                post
+            )),
+        }
+    }
+
+    /// Every question of the session, listed or not
+    fn all(&self) -> impl Iterator<Item = &Question<Markdown>> {
+        self.results
+            .iter()
+            .chain(self.pins.iter())
+            .chain(self.hidden.iter().map(|h| &h.question))
+    }
+}
+
+/// Run the TUI on `qs`, found by `search` for `query`, keeping the questions filters left out at
+/// hand; searching again from the TUI goes through `search` too, and its messages are where
+/// warnings and errors of the session are collected
+pub fn run(qs: Filtered<Markdown>, query: &str, config: Config, search: Search) -> Result<()> {
+    let offline_since = search.offline_since();
+    let messages = search.messages();
+    let mut siv = cursive::default();
+    let images = ImageCache::open(OfflinePolicy::from(&config))?
+        .with_extra_headers(config.headers_for(Destination::Images));
+    let detectors = theme::detectors();
+    let detectors: Vec<&dyn Detect> = detectors.iter().map(|d| d.as_ref()).collect();
+    let resolved = theme::resolve(config.theme, theme::file_customized()?, &detectors);
+
+    // Questions with notes showing up again keeps the notes from being pruned
+    let mut notes = Notes::load(&Notes::path()?)?;
+    let migrated = notes.migrate(&Aliases::open()?);
+    if notes.touch(&qs.shown) || migrated {
+        notes.save(&Notes::path()?)?;
+    }
+
+    build(
+        &mut siv,
+        qs,
+        &config,
+        offline_since,
+        messages.clone(),
+        notes,
+        resolved,
+    );
+    siv.with_user_data(|session: &mut Session| session.query = query.to_string());
+    // Comments are fetched on demand, and searches run, on a runtime of their own since the TUI
+    // runs outside one
+    let runtime = Runtime::new()?;
+    let api = Api::new(
+        config.api_key.clone(),
+        config.filter.clone(),
+        OfflinePolicy::from(&config),
+    )
+    .with_api_url(&config.api_url)
+    .with_api_version(&config.api_version)
+    .with_extra_headers(config.headers_for(Destination::StackExchange))
+    .with_team(config.selected_team()?.cloned())
+    .with_preprocess_passes(config.passes())
+    .with_filters(Filters::open()?)
+    .with_backoff(Backoff::open()?);
+    let fetcher: Arc<dyn Fetch> = match config.link_rewrites.is_empty() {
+        true => Arc::new(api),
+        false => Arc::new(Rewritten {
+            fetcher: Arc::new(api),
+            rewrites: config.link_rewrites.clone(),
+            in_code: config.rewrite_links_in_code,
+        }),
+    };
+    set_comments_source(
+        &mut siv,
+        Source {
+            fetcher,
+            runtime: runtime.handle().clone(),
+        },
+    );
+    set_search_source(
+        &mut siv,
+        search_again::Source {
+            searcher: Arc::new(search),
+            runtime: runtime.handle().clone(),
+        },
+    );
+    if config.prefetch_comments {
+        // Callbacks queued before the TUI runs are taken once it's drawn the results
+        let _ = siv.cb_sink().send(Box::new(start_prefetch));
+    }
+    siv.run();
+    // Images are drawn and code is edited with the TUI out of the way, which then picks up where
+    // it left off
+    loop {
+        let (preview, edit) = siv
+            .with_user_data(|session: &mut Session| (session.preview.take(), session.edit.take()))
+            .unwrap_or_default();
+        if preview.is_none() && edit.is_none() {
+            break;
+        }
+        let dump = siv.dump();
+        drop(siv);
+        if let Some(preview) = preview {
+            if let Err(e) =
+                graphics::show(&preview.title, &preview.images, preview.protocol, &images)
+            {
+                messages.error(format!("Couldn't preview images: {}", e));
+            }
+        }
+        let edited = edit.map(|edit| editor::edit(&edit.code, edit.extension));
+        siv = cursive::default();
+        siv.restore(dump);
+        if let Some(edited) = edited {
+            code_edited(&mut siv, edited);
+        }
+        siv.run();
+    }
+    // Quitting cancels prefetching, along with the fetch in progress
+    siv.with_user_data(|session: &mut Session| session.prefetcher = None);
+    // Printed with the TUI gone, to pipe on
+    if config.print_edited_code {
+        let edited = siv
+            .with_user_data(|session: &mut Session| std::mem::take(&mut session.edited))
+            .unwrap_or_default();
+        for code in edited {
+            print!("{}", code);
+        }
+    }
+    Ok(())
+}
+
+/// Fetch comments from `source` when asked for them
+pub(super) fn set_comments_source(siv: &mut Cursive, source: Source) {
+    siv.with_user_data(|session: &mut Session| session.comments = Comments::new(source));
+}
+
+/// Fetch the comments on the listed answers ahead of time, from the selected question on, in
+/// the order they're listed; started over for each new list of results, which cancels prefetching
+/// for the last one. Without a comments source, there's nothing to fetch them from.
+fn start_prefetch(s: &mut Cursive) {
+    let selected = s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten();
+    s.with_user_data(|session: &mut Session| {
+        let source = match session.comments.source() {
+            Some(source) => source.clone(),
+            None => return,
+        };
+        let listed = session.visible();
+        let ids = listed.iter().map(|(q, ..)| q.id).collect();
+        let answers = listed
+            .into_iter()
+            .map(|(mut q, ..)| {
+                session.answer_sort.sort(&mut q.answers);
+                let answer_ids = q.answers.iter().map(|a| a.id).collect();
+                (q.id, (q.site, answer_ids))
+            })
+            .collect();
+        let prefetch = Prefetch {
+            fetcher: source.fetcher,
+            answers,
+            store: session.comments.store(),
+        };
+        let _runtime = source.runtime.enter();
+        let prefetcher = Prefetcher::start(ids, Arc::new(prefetch));
+        if let Some(qid) = selected {
+            prefetcher.select(qid);
+        }
+        session.prefetcher = Some(prefetcher);
+    });
+}
+
+/// Run searches for other queries on `source`
+pub(super) fn set_search_source(siv: &mut Cursive, source: search_again::Source) {
+    siv.with_user_data(|session: &mut Session| session.search_again = SearchAgain::new(source));
+}
+
+/// Lay out the TUI for `qs` on `siv`, with all its key bindings, without touching the terminal
+/// or any files; the caller loads `notes` and works out the theme, and sets where comments come
+/// from and where searches run, if anywhere
+pub(super) fn build(
+    siv: &mut Cursive,
+    qs: Filtered<Markdown>,
+    config: &Config,
+    offline_since: Option<i64>,
+    messages: Messages,
+    notes: Notes,
+    resolved: Resolved,
+) {
+    let locale = config.locale;
+    let clipboard = config.clipboard;
+    let scores = Scores {
+        locale,
+        thresholds: config.score_thresholds,
+    };
+    load_theme(siv, resolved);
+
+    let question_view = MdView::new(Name::QuestionView);
+    let answer_view = MdView::new(Name::AnswerView);
+
+    // Listed once the session is set up, see `apply`
+    let question_list_view = ListView::new_lazy(
+        Name::QuestionList,
+        vec![],
+        |_| StyledString::new(),
+        move |s, qid| question_selected_callback(s, *qid, scores),
+    );
+    siv.set_user_data(Session {
+        query: String::new(),
+        results: vec![],
+        hidden: vec![],
+        reveal: false,
+        pins: Pins::default(),
+        shown: Shown::default(),
+        notes,
+        scores,
+        filter: String::new(),
+        filtering: false,
+        diff_base: None,
+        image_preview: config.image_preview,
+        preview: None,
+        edit: None,
+        edited: vec![],
+        comments: Comments::default(),
+        prefetcher: None,
+        search_again: SearchAgain::default(),
+        spoilers: Spoilers::default(),
+        syntax: match config.syntax_highlighting {
+            true => Some(Highlighter::new(&config.syntax_theme, appearance(resolved))),
+            false => None,
+        },
+        highlighted: HashMap::new(),
+        open: Box::new(utils::open_browser),
+        copy: Box::new(move |text| utils::copy_via(clipboard, text)),
+        code_block: code_blocks::Cursor::default(),
+        notice: None,
+        notices: 0,
+        share_max_chars: config.share_max_chars,
+        answer_sort: config.answer_sort,
+        updater: Updater::new(siv.cb_sink().clone(), apply),
+    });
+
+    let answer_list_view = ListView::new(Name::AnswerList, move |s, aid| show_answer(s, *aid));
+
+    let layout = LayoutView::new(
+        1,
+        question_list_view,
+        question_view,
+        answer_list_view,
+        answer_view,
+    )
+    .add_vim_bindings();
+    let mut screen = LinearLayout::vertical();
+    if let Some(fetched_at) = offline_since {
+        screen.add_child(TextView::new(StyledString::styled(
+            query_cache::offline_banner(fetched_at, locale),
+            Effect::Reverse,
+        )));
+    }
+    siv.add_layer(
+        screen.child(layout).child(
+            LinearLayout::horizontal()
+                .child(TextView::new("").with_name(NAME_SHOWN_STATUS).full_width())
+                .child(console::status_bar(&messages)),
+        ),
+    );
+
+    apply(siv, Update::Replace(qs));
+
+    actions::bind(siv, registry(&messages, resolved));
+    // Messages can come in at any time, so check for them every now and then
+    siv.set_fps(1);
+    siv.add_global_callback(Event::Refresh, move |s| {
+        console::refresh_badge(s, &messages)
+    });
+}
+
+/// Every action of the TUI, with its key binding; the palette offers them too
+fn registry(messages: &Messages, resolved: Resolved) -> Vec<Action> {
+    let comments_messages = messages.clone();
+    let retry_messages = messages.clone();
+    let open_messages = messages.clone();
+    let open_answer_messages = messages.clone();
+    let console_messages = messages.clone();
+    let search_messages = messages.clone();
+    vec![
+        Action::new('?', "Help", "Show/hide the key bindings", |s| {
+            if let Some(pos) = s.screen_mut().find_layer_from_name(NAME_HELP_VIEW) {
+                s.screen_mut().remove_layer(pos);
+            } else {
+                s.add_layer(help());
+            }
+        }),
+        Action::new(
+            'p',
+            "Pin question",
+            "Pin/unpin the selected question to the top of the list",
+            toggle_pin,
+        ),
+        Action::new(
+            'H',
+            "Hide listed questions",
+            "Hide/show questions already listed this session in new results",
+            toggle_shown,
+        ),
+        Action::new(
+            'R',
+            "Reveal filtered questions",
+            "Reveal/hide the questions filters left out, listed after the others",
+            toggle_reveal,
+        ),
+        Action::new(
+            'm',
+            "Edit note",
+            "Add, edit or remove a note on the selected question",
+            edit_note,
+        ),
+        Action::new(
+            'F',
+            "Filter questions",
+            "Filter the questions by title as you type",
+            filter_questions,
+        ),
+        Action::new(
+            search_again::SEARCH_KEY,
+            "Search again",
+            "Search for another query, listing its results in place of these",
+            move |s| prompt_search(s, &search_messages),
+        ),
+        Action::new(
+            'i',
+            "Question details",
+            "Show details of the selected question, such as which engine found it",
+            show_info,
+        ),
+        Action::new(
+            'a',
+            "Sort answers",
+            "Cycle the answers between by votes, accepted first and newest first",
+            cycle_answer_sort,
+        ),
+        Action::new(
+            'J',
+            "Jump to original",
+            "Jump to the original of a duplicate answer",
+            jump_to_original,
+        )
+        .on_answers(),
+        Action::new(
+            'd',
+            "Diff answers",
+            "Mark the selected answer, then diff the code of another answer against it",
+            diff_answers,
+        )
+        .on_answers(),
+        Action::new(
+            'D',
+            "Diff local file",
+            "Diff a local file against the code of the selected answer",
+            diff_local_file,
+        )
+        .on_answers(),
+        Action::new(
+            'I',
+            "Preview images",
+            "Draw the images of the selected answer, or list their links",
+            preview_images,
+        )
+        .on_answers(),
+        Action::new(
+            's',
+            "Reveal spoiler",
+            "Reveal the next spoiler of the selected answer or question, or hide them all",
+            toggle_spoiler,
+        ),
+        Action::new(
+            'S',
+            "Share summary",
+            "Sum up the listed questions in markdown, to copy or save to a file",
+            share_summary,
+        ),
+        Action::new(
+            'E',
+            "Edit code",
+            "Edit a code block of the selected answer in $EDITOR, then copy it",
+            edit_code,
+        )
+        .on_answers(),
+        Action::new(
+            ']',
+            "Next code block",
+            "Select the next code block of the selected answer",
+            |s| step_code_block(s, true),
+        )
+        .on_answers(),
+        Action::new(
+            '[',
+            "Previous code block",
+            "Select the previous code block of the selected answer",
+            |s| step_code_block(s, false),
+        )
+        .on_answers(),
+        Action::new(
+            'y',
+            "Copy code block",
+            "Copy the selected code block of the selected answer to the clipboard",
+            copy_code_block,
+        )
+        .on_answers(),
+        Action::new(
+            'c',
+            "Toggle comments",
+            "Show/hide the comments on the selected answer",
+            move |s| toggle_comments(s, &comments_messages),
+        )
+        .on_answers(),
+        Action::new(
+            loading::RETRY_KEY,
+            "Retry comments",
+            "Fetch the comments on the selected answer again after failing to",
+            move |s| retry_comments(s, &retry_messages),
+        )
+        .on_answers(),
+        Action::new(
+            'o',
+            "Open question",
+            "Open the selected question in a browser",
+            move |s| open_question(s, &open_messages),
+        ),
+        Action::new(
+            'O',
+            "Open answer",
+            "Open the selected answer in a browser",
+            move |s| open_answer(s, &open_answer_messages),
+        )
+        .on_answers(),
+        Action::new(
+            '!',
+            "Messages",
+            "Show/hide the console of warnings and errors",
+            move |s| console::toggle(s, &console_messages),
+        ),
+        Action::new(
+            Event::CtrlChar('r'),
+            "Reload theme",
+            "Load the theme again, e.g. after editing it",
+            move |s| load_theme(s, resolved),
+        ),
+        Action::new(
+            ' ',
+            "Cycle layout",
+            "Switch between both columns, a single one and full screen",
+            |s| {
+                s.call_on_name(NAME_FULL_LAYOUT, |v: &mut LayoutView| v.next_layout());
+            },
+        )
+        .bound_by_layout(),
+        Action::new('q', "Quit", "Exit so", |s| s.quit()).bound_by_layout(),
+    ]
+}
+
+/// Appearance of the TUI's theme, taking custom themes for dark ones
+fn appearance(resolved: Resolved) -> Appearance {
+    match resolved {
+        Resolved::BuiltIn(appearance) => appearance,
+        Resolved::Custom => Appearance::Dark,
+    }
+}
+
+fn load_theme(s: &mut Cursive, resolved: Resolved) {
+    match resolved.built_in() {
+        Some(toml) => s.load_toml(toml).unwrap(),
+        None => s.load_theme_file(theme::file_path().unwrap()).unwrap(), // TODO dont unwrap
+    }
+}
+
+/// Apply `update` to the question list; nothing else changes which questions are listed, see
+/// `Updater`
+fn apply(s: &mut Cursive, update: Update<Markdown>) {
+    let selected = s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten();
+    let new_results = !matches!(update, Update::Relist);
+    let listed = s.with_user_data(|session: &mut Session| {
+        match update {
+            Update::Replace(qs) => {
+                let qs = session.shown.filter(qs, &session.pins);
+                session.results = qs.shown;
+                session.hidden = qs.hidden;
+            }
+            Update::Relist => (),
+        }
+        let listing = session
+            .visible()
+            .into_iter()
+            .map(|(q, pinned, hidden, matched)| (q.id, (q, pinned, hidden, matched)))
+            .collect::<Vec<_>>();
+        (
+            listing,
+            session.scores,
+            filter_status(session),
+            session.filtering,
+        )
+    });
+    let (listing, scores, status, filtering) = match listed {
+        Some(listed) => listed,
+        None => return,
+    };
+    let ids = listing.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let nothing_matches = ids.is_empty();
+    let previews: HashMap<u32, Listed> = listing.into_iter().collect();
+    let cb = s.call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| {
+        let first = v.reset_lazy(ids, move |qid| {
+            let (q, pinned, hidden, matched) = &previews[&qid];
+            preview_question(q, *pinned, *hidden, matched, scores)
+        });
+        // While the filter is typed, the top match is the one to look at; otherwise keep the
+        // selection where it was, if it's still listed
+        match selected {
+            Some(qid) if !filtering => v.select_id(qid).unwrap_or(first),
+            _ => first,
+        }
+    });
+    if let Some(cb) = cb {
+        cb(s)
+    }
+    // Nothing is selected, so nothing would replace what the previous selection showed
+    if nothing_matches && filtering {
+        s.call_on_name(NAME_QUESTION_VIEW, |v: &mut MdView| {
+            v.set_content(&StyledString::plain("No question title matches the filter"))
+        });
+        s.call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| {
+            v.reset_with_all(Vec::<(StyledString, u32)>::new())
+        });
+        s.call_on_name(NAME_ANSWER_VIEW, |v: &mut MdView| {
+            v.set_content(&StyledString::new())
+        });
+    }
+    s.call_on_name(NAME_SHOWN_STATUS, |v: &mut TextView| v.set_content(status));
+    let prefetching = s
+        .user_data::<Session>()
+        .is_some_and(|session| session.prefetcher.is_some());
+    if new_results && prefetching {
+        start_prefetch(s);
+    }
+}
+
+/// The question `qid`, with its spoilers as revealed so far, and what the question view shows of it
+fn question_content(s: &mut Cursive, qid: u32) -> Option<(Question<Markdown>, Markdown)> {
+    let session = s.user_data::<Session>()?;
+    let mut q = session.question(qid)?.clone();
+    session.answer_sort.sort(&mut q.answers);
+    let tags = &q.tags;
+    q.body = q
+        .body
+        .as_ref()
+        .map(|body| session.render(qid, body, &[], tags));
+    let note = session.notes.get(&q.site, q.id);
+    let body = with_note(&with_tags(&question_body(&q), &q.tags), note);
+    Some((q, body))
+}
+
+fn question_selected_callback(s: &mut Cursive, qid: u32, scores: Scores) {
+    if let Some(prefetcher) = s
+        .user_data::<Session>()
+        .and_then(|session| session.prefetcher.as_ref())
+    {
+        prefetcher.select(qid);
+    }
+    let (q, body) = match question_content(s, qid) {
+        Some(content) => content,
+        None => return,
+    };
+    let q = &q;
+    let diff_base = s
+        .user_data::<Session>()
+        .and_then(|session| session.diff_base)
+        .filter(|(base_qid, _)| *base_qid == qid)
+        .map(|(_, aid)| aid);
+    let XY { x, y: _y } = s.screen_size();
+    // Update question view
+    s.call_on_name(NAME_QUESTION_VIEW, |v: &mut MdView| {
+        v.set_content(&body);
+    })
+    .expect("Panic: setting question view content failed");
+    // Update answer list view
+    let cb = s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| {
+            v.reset_with_all(
+                q.answers
+                    .iter()
+                    .map(|a| (preview_answer(x, a, diff_base == Some(a.id), scores), a.id)),
+            )
+        })
+        .expect("Panic: setting answer list content failed");
+    cb(s);
+    // With no answer to select, the answer view would otherwise keep the previous answer
+    if q.answers.is_empty() {
+        s.call_on_name(NAME_ANSWER_VIEW, |v: &mut MdView| {
+            v.set_content(&no_answers(&question_body(q)))
+        });
+    }
+}
+
+/// Show the answer `aid` in the answer view, with its comments if they're open
+fn show_answer(s: &mut Cursive, aid: u32) {
+    let content = s.user_data::<Session>().and_then(|session| {
+        let answer = session.answer(aid)?.clone();
+        let tags = session.answer_question(aid)?.tags.clone();
+        let mut body = session.render(aid, &answer.body, &answer.code_matches, &tags);
+        if let Some(i) = session.code_block.current(aid) {
+            if let Some(block) = session.code_blocks(aid).get(i) {
+                body = code_blocks::mark(&body, &block.text);
+            }
+        }
+        let body = with_byline(body, answer.owner.as_ref(), session.scores.locale);
+        Some(session.comments.below(aid, &body))
+    });
+    if let Some(content) = content {
+        s.call_on_name(NAME_ANSWER_VIEW, |v: &mut MdView| v.set_content(&content));
+    }
+}
+
+/// `body` of an answer followed by a dim line naming its author, if there's a name to give
+fn with_byline(mut body: Markdown, owner: Option<&Owner>, locale: Locale) -> Markdown {
+    if let Some(byline) = owner.and_then(|owner| owner.byline(locale)) {
+        body.append_plain("\n\n");
+        body.append_styled(
+            format!("\u{2014} answered by {}", byline),
+            Color::Light(BaseColor::Black),
+        );
+    }
+    body
+}
+
+/// Show the answer `aid` again if it's still the selected one, e.g. once its comments are in
+fn refresh_answer(s: &mut Cursive, aid: u32) {
+    let selected = s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten();
+    if selected == Some(aid) {
+        show_answer(s, aid);
+    }
+}
+
+fn toggle_comments(s: &mut Cursive, messages: &Messages) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let load = s.with_user_data(|session: &mut Session| {
+        let site = session.answer_site(aid)?.to_string();
+        let source = session.comments.source()?.clone();
+        Some((site, source, session.comments.toggle(aid)))
+    });
+    if let Some(Some((site, source, ticket))) = load {
+        if let Some(ticket) = ticket {
+            fetch_comments(s, source, site, ticket, messages.clone());
+        }
+        show_answer(s, aid);
+    }
+}
+
+/// Reveal the next spoiler of the selected answer, or collapse them all again once they're all
+/// revealed; if the answer has none, do so for the question instead
+fn toggle_spoiler(s: &mut Cursive) {
+    let aid = s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten();
+    let toggled = s.with_user_data(|session: &mut Session| {
+        let body = session.answer(aid?)?.body.clone();
+        Some(session.toggle_spoiler(aid?, &body))
+    });
+    if let (Some(aid), Some(Some(true))) = (aid, toggled) {
+        show_answer(s, aid);
+        return;
+    }
+    let qid = match s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(qid) => qid,
+        None => return,
+    };
+    let toggled = s.with_user_data(|session: &mut Session| {
+        let body = session.question(qid)?.body.clone()?;
+        Some(session.toggle_spoiler(qid, &body))
+    });
+    if toggled != Some(Some(true)) {
+        return;
+    }
+    if let Some((q, body)) = question_content(s, qid) {
+        s.call_on_name(NAME_QUESTION_VIEW, |v: &mut MdView| v.set_content(&body));
+        if q.answers.is_empty() {
+            s.call_on_name(NAME_ANSWER_VIEW, |v: &mut MdView| {
+                v.set_content(&no_answers(&question_body(&q)))
+            });
+        }
+    }
+}
+
+fn open_question(s: &mut Cursive, messages: &Messages) {
+    let qid = match s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(qid) => qid,
+        None => return,
+    };
+    s.with_user_data(|session: &mut Session| {
+        let link = session.question(qid).and_then(|q| q.link.as_deref());
+        session.open_link(link, "question", messages);
+    });
+}
+
+fn open_answer(s: &mut Cursive, messages: &Messages) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    s.with_user_data(|session: &mut Session| {
+        let link = session.answer(aid).and_then(|a| a.link.as_deref());
+        session.open_link(link, "answer", messages);
+    });
+}
+
+fn retry_comments(s: &mut Cursive, messages: &Messages) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let load = s.with_user_data(|session: &mut Session| {
+        let site = session.answer_site(aid)?.to_string();
+        let source = session.comments.source()?.clone();
+        Some((site, source, session.comments.retry(aid)?))
+    });
+    if let Some(Some((site, source, ticket))) = load {
+        fetch_comments(s, source, site, ticket, messages.clone());
+        show_answer(s, aid);
+    }
+}
+
+/// Fetch the comments `ticket` was issued for in the background, animating the loading indicator
+/// meanwhile; failures go to `messages` as well as below the answer
+fn fetch_comments(
+    s: &mut Cursive,
+    source: Source,
+    site: String,
+    ticket: Ticket,
+    messages: Messages,
+) {
+    let aid = ticket.id;
+    let cb_sink = s.cb_sink().clone();
+    loading::animate(cb_sink.clone(), &ticket, move |s| {
+        let ticked = s.with_user_data(|session: &mut Session| session.comments.tick());
+        if ticked == Some(true) {
+            refresh_answer(s, aid);
+        }
+    });
+    let fetcher = Arc::clone(&source.fetcher);
+    let busy = user_request(s);
+    source.runtime.spawn(async move {
+        let result = ticket.run(fetcher.fetch(&site, aid)).await;
+        drop(busy);
+        if let Some(result) = result {
+            let result = result.map_err(|e| e.to_string());
+            // The TUI may be gone by now
+            let _ = cb_sink.send(Box::new(move |s| {
+                comments_fetched(s, &ticket, result, &messages)
+            }));
+        }
+    });
+}
+
+/// Pause prefetching until the returned guard is dropped, for a request the user waits on
+fn user_request(s: &mut Cursive) -> Option<Busy> {
+    s.user_data::<Session>()?
+        .prefetcher
+        .as_ref()
+        .map(Prefetcher::user_request)
+}
+
+fn comments_fetched(
+    s: &mut Cursive,
+    ticket: &Ticket,
+    result: std::result::Result<Vec<Comment>, String>,
+    messages: &Messages,
+) {
+    let error = result.as_ref().err().cloned();
+    let taken = s
+        .with_user_data(|session: &mut Session| session.comments.finish(ticket, result))
+        .unwrap_or(false);
+    if !taken {
+        return;
+    }
+    if let Some(error) = error {
+        messages.error(format!(
+            "Couldn't load the comments on answer {}: {}",
+            ticket.id, error
+        ));
+    }
+    refresh_answer(s, ticket.id);
+}
+
+fn jump_to_original(s: &mut Cursive) {
+    let original = s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+        .and_then(|aid| {
+            s.user_data::<Session>()
+                .and_then(|session| session.answer(aid))
+                .and_then(|a| a.duplicate_of.clone())
+        });
+    if let Some(original) = original {
+        let cb = s
+            .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| {
+                v.select_id(original.question_id)
+            })
+            .flatten();
+        if let Some(cb) = cb {
+            cb(s)
+        }
+        let cb = s
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| {
+                v.select_id(original.answer_id)
+            })
+            .flatten();
+        if let Some(cb) = cb {
+            cb(s)
+        }
+    }
+}
+
+fn diff_answers(s: &mut Cursive) {
+    let qid = s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten();
+    let aid = s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten();
+    let (qid, aid) = match (qid, aid) {
+        (Some(qid), Some(aid)) => (qid, aid),
+        _ => return,
+    };
+    let base = s
+        .with_user_data(|session: &mut Session| session.diff_base.take())
+        .flatten();
+    let diffed = match base {
+        Some((base_qid, base_aid)) if base_qid == qid && base_aid != aid => {
+            let lines = s.with_user_data(|session: &mut Session| {
+                let base = session.answer(base_aid)?;
+                let other = session.answer(aid)?;
+                Some(diff::diff(&base.code_blocks, &other.code_blocks))
+            });
+            lines.flatten().map(|lines| (base_aid, lines))
+        }
+        // Marking the base again unmarks it
+        Some((base_qid, base_aid)) if base_qid == qid && base_aid == aid => None,
+        _ => {
+            s.with_user_data(|session: &mut Session| session.diff_base = Some((qid, aid)));
+            None
+        }
+    };
+    // Re-select the question and answer to show or hide the marker
+    let cb = s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.select_id(qid))
+        .flatten();
+    if let Some(cb) = cb {
+        cb(s)
+    }
+    let cb = s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(aid))
+        .flatten();
+    if let Some(cb) = cb {
+        cb(s)
+    }
+    if let Some((base_aid, lines)) = diffed {
+        let content = if lines.is_empty() {
+            StyledString::plain("Neither answer has any code to compare")
+        } else {
+            diff::render(&lines)
+        };
+        show_scrollable(
+            s,
+            format!("Code of answer {} against {}", aid, base_aid),
+            content,
+        );
+    }
+}
+
+/// Ask for a local file, and which code block of the selected answer to diff it against
+fn diff_local_file(s: &mut Cursive) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let blocks = s
+        .with_user_data(|session: &mut Session| session.answer(aid).map(|a| a.code_blocks.clone()))
+        .flatten()
+        .unwrap_or_default();
+    let largest = match diff::largest_block(&blocks) {
+        Some(largest) => largest,
+        None => {
+            s.add_layer(Dialog::info("This answer has no code to compare"));
+            return;
+        }
+    };
+    let blocks = Arc::new(blocks);
+    let submit = {
+        let blocks = Arc::clone(&blocks);
+        move |s: &mut Cursive| compare_local_file(s, aid, &blocks, largest)
+    };
+    let submit = Arc::new(submit);
+    let on_enter = submit.clone();
+    let mut form = LinearLayout::vertical()
+        .child(TextView::new("File to compare against:"))
+        .child(
+            EditView::new()
+                .on_submit(move |s, _| on_enter(s))
+                .with_name(NAME_DIFF_PATH)
+                .fixed_width(60),
+        );
+    if blocks.len() > 1 {
+        let mut which = SelectView::new();
+        for (i, block) in blocks.iter().enumerate() {
+            which.add_item(block_item(i, block), i);
+        }
+        which.set_selection(largest);
+        form = form
+            .child(TextView::new("\nCode block:"))
+            .child(which.with_name(NAME_DIFF_BLOCK).max_height(8));
+    }
+    s.add_layer(
+        Dialog::around(form)
+            .title(format!("Diff a file against answer {}", aid))
+            .button("Compare", move |s| submit(s))
+            .dismiss_button("Cancel"),
+    );
+}
+
+/// Code block `i` of an answer as listed to pick from: its first line and how long it is
+fn block_item(i: usize, block: &str) -> String {
+    let first = block.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    format!(
+        "{}. {} ({} lines)",
+        i + 1,
+        first.trim(),
+        block.lines().count()
+    )
+}
+
+fn compare_local_file(s: &mut Cursive, aid: u32, blocks: &[String], largest: usize) {
+    let input = s
+        .call_on_name(NAME_DIFF_PATH, |v: &mut EditView| v.get_content())
+        .unwrap_or_default();
+    if input.trim().is_empty() {
+        return;
+    }
+    let block = s
+        .call_on_name(NAME_DIFF_BLOCK, |v: &mut SelectView<usize>| v.selection())
+        .flatten()
+        .map_or(largest, |i| *i);
+    let path = local_file::expand(&input);
+    match local_file::read(&path) {
+        // Keep the prompt open to fix the path
+        Err(e) => s.add_layer(Dialog::info(e.to_string()).title("Couldn't compare")),
+        Ok(local) => {
+            s.pop_layer();
+            let lines = diff::diff_file(&local, &blocks[block]);
+            let content = if lines.is_empty() {
+                StyledString::plain("Both are empty")
+            } else {
+                diff::render(&lines)
+            };
+            let title = if blocks.len() > 1 {
+                format!(
+                    "{} against code block {} of answer {}",
+                    path.display(),
+                    block + 1,
+                    aid
+                )
+            } else {
+                format!("{} against the code of answer {}", path.display(), aid)
+            };
+            show_scrollable(s, title, content);
+        }
+    }
+}
+
+/// Sum up the listed questions in markdown, and offer to copy the summary or save it to a file
+fn share_summary(s: &mut Cursive) {
+    let summary = s
+        .with_user_data(|session: &mut Session| {
+            let questions: Vec<_> = session.visible().into_iter().map(|l| l.0).collect();
+            if questions.is_empty() {
+                return None;
+            }
+            let date = session.scores.locale.date(now());
+            Some(share::summary(
+                &session.query,
+                &date,
+                &questions,
+                session.share_max_chars,
+            ))
+        })
+        .flatten();
+    let summary = match summary {
+        Some(summary) => Arc::new(summary),
+        None => {
+            s.add_layer(Dialog::info("There are no questions to sum up"));
+            return;
+        }
+    };
+    let to_copy = Arc::clone(&summary);
+    let to_save = Arc::clone(&summary);
+    let on_enter = Arc::clone(&summary);
+    let form = LinearLayout::vertical()
+        .child(TextView::new(summary.as_str()).scrollable().max_height(15))
+        .child(TextView::new("\nFile to save it to:"))
+        .child(
+            EditView::new()
+                .on_submit(move |s, _| save_summary(s, &on_enter))
+                .with_name(NAME_SHARE_PATH)
+                .fixed_width(60),
+        );
+    s.add_layer(
+        Dialog::around(form)
+            .title("Summary")
+            .button("Copy", move |s| copy_summary(s, &to_copy))
+            .button("Save", move |s| save_summary(s, &to_save))
+            .dismiss_button("Cancel"),
+    );
+}
+
+fn copy_summary(s: &mut Cursive, summary: &str) {
+    let copied = s.with_user_data(|session: &mut Session| (session.copy)(summary));
+    match copied {
+        // Keep the prompt open to save it to a file instead
+        Some(Err(e)) => s.add_layer(Dialog::info(e.to_string()).title("Couldn't copy")),
+        _ => {
+            s.pop_layer();
+            s.add_layer(Dialog::info("Copied the summary to the clipboard"));
+        }
+    }
+}
+
+fn save_summary(s: &mut Cursive, summary: &str) {
+    let input = s
+        .call_on_name(NAME_SHARE_PATH, |v: &mut EditView| v.get_content())
+        .unwrap_or_default();
+    if input.trim().is_empty() {
+        return;
+    }
+    let path = local_file::expand(&input);
+    match utils::write_atomic(&path, summary.as_bytes()) {
+        // Keep the prompt open to fix the path
+        Err(e) => s.add_layer(Dialog::info(e.to_string()).title("Couldn't save")),
+        Ok(()) => {
+            s.pop_layer();
+            s.add_layer(Dialog::info(format!(
+                "Saved the summary to {}",
+                path.display()
+            )));
+        }
+    }
+}
+
+/// Suspend the TUI to edit a code block of the selected answer in `$EDITOR`: the only one, or the
+/// one picked if there are more
+fn edit_code(s: &mut Cursive) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let mut blocks = s
+        .with_user_data(|session: &mut Session| {
+            let answer = session.answer(aid)?;
+            let languages = editor::languages(answer.body.source());
+            let tags = &session.answer_question(aid)?.tags;
+            let blocks: Vec<Edit> = answer
+                .code_blocks
+                .iter()
+                .enumerate()
+                .map(|(i, code)| Edit {
+                    code: code.clone(),
+                    extension: editor::extension(
+                        languages.get(i).cloned().flatten().as_deref(),
+                        tags,
+                    ),
+                })
+                .collect();
+            Some(blocks)
+        })
+        .flatten()
+        .unwrap_or_default();
+    match blocks.len() {
+        0 => s.add_layer(Dialog::info("This answer has no code to edit")),
+        1 => queue_edit(s, blocks.remove(0)),
+        _ => {
+            let mut which = SelectView::new();
+            for (i, edit) in blocks.iter().enumerate() {
+                which.add_item(block_item(i, &edit.code), i);
+            }
+            which.set_on_submit(move |s, i: &usize| {
+                s.pop_layer();
+                queue_edit(s, blocks[*i].clone());
+            });
+            s.add_layer(
+                Dialog::around(which.with_name(NAME_EDIT_BLOCK).scrollable().max_height(10))
+                    .title(format!("Edit a code block of answer {}", aid))
+                    .dismiss_button("Cancel"),
+            );
+        }
+    }
+}
+
+/// Select the next code block of the selected answer, or the previous one if not `forward`, and
+/// scroll to it
+fn step_code_block(s: &mut Cursive, forward: bool) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let selected = s
+        .with_user_data(|session: &mut Session| {
+            let blocks = session.code_blocks(aid);
+            let i = session.code_block.step(aid, blocks.len(), forward)?;
+            Some((blocks[i].text.start, i, blocks.len()))
+        })
+        .flatten();
+    match selected {
+        Some((offset, i, count)) => {
+            show_answer(s, aid);
+            s.call_on_name(NAME_ANSWER_VIEW, |v: &mut MdView| v.scroll_to(offset));
+            notify(
+                s,
+                format!("code block {} of {} (press y to copy)", i + 1, count),
+            );
+        }
+        None => notify(s, "This answer has no code blocks"),
+    }
+}
+
+/// Copy the code block of the selected answer that `]` or `[` selected, or its only one, and
+/// tell how many lines that was or why it wasn't copied
+fn copy_code_block(s: &mut Cursive) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let copied = s.with_user_data(|session: &mut Session| {
+        let blocks = session.code_blocks(aid);
+        let block = match (session.code_block.current(aid), blocks.len()) {
+            (_, 0) => return Err("This answer has no code to copy"),
+            (Some(i), _) => blocks.get(i),
+            (None, 1) => blocks.first(),
+            (None, _) => None,
+        };
+        match block {
+            Some(block) => Ok((session.copy)(&block.code).map(|()| block.lines())),
+            None => Err("Press ] or [ to select a code block to copy"),
+        }
+    });
+    match copied {
+        Some(Ok(Ok(lines))) => {
+            let noun = if lines == 1 { "line" } else { "lines" };
+            notify(s, format!("copied {} {}", lines, noun));
+        }
+        Some(Ok(Err(e))) => s.add_layer(Dialog::info(e.to_string()).title("Couldn't copy")),
+        Some(Err(reason)) => notify(s, reason),
+        None => (),
+    }
+}
+
+/// Tell `notice` in the status bar for `NOTICE_SECS`
+fn notify<S: Into<String>>(s: &mut Cursive, notice: S) {
+    let shown = s.with_user_data(|session: &mut Session| {
+        session.notice = Some(notice.into());
+        session.notices += 1;
+        session.notices
+    });
+    refresh_status(s);
+    let shown = match shown {
+        Some(shown) => shown,
+        None => return,
+    };
+    let cb_sink = s.cb_sink().clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(NOTICE_SECS));
+        let _ = cb_sink.send(Box::new(move |s| {
+            // Unless another one took its place since
+            let expired = s.with_user_data(|session: &mut Session| {
+                session.notices == shown && session.notice.take().is_some()
+            });
+            if expired == Some(true) {
+                refresh_status(s);
+            }
+        }));
+    });
+}
+
+/// Suspend the TUI to edit `edit`, see `run`
+fn queue_edit(s: &mut Cursive, edit: Edit) {
+    s.with_user_data(|session: &mut Session| session.edit = Some(edit));
+    s.quit();
+}
+
+/// Copy the code as it was left in the editor, and keep it to print once the TUI quits, or tell
+/// why editing failed
+fn code_edited(s: &mut Cursive, edited: Result<String>) {
+    let code = match edited {
+        Ok(code) => code,
+        Err(e) => {
+            s.add_layer(Dialog::info(e.to_string()).title("Couldn't edit the code"));
+            return;
+        }
+    };
+    let copied = s.with_user_data(|session: &mut Session| {
+        session.edited.push(code.clone());
+        (session.copy)(&code)
+    });
+    match copied {
+        Some(Err(e)) => s.add_layer(Dialog::info(e.to_string()).title("Couldn't copy")),
+        _ => s.add_layer(Dialog::info("Copied the edited code to the clipboard")),
+    }
+}
+
+/// Suspend the TUI to draw the images of the selected answer, if the terminal speaks a graphics
+/// protocol, or else list their links
+fn preview_images(s: &mut Cursive) {
+    let aid = match s
+        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(aid) => aid,
+        None => return,
+    };
+    let found = s
+        .with_user_data(|session: &mut Session| {
+            let images = markdown::images(session.answer(aid)?.body.source());
+            Some((images, session.image_preview))
+        })
+        .flatten();
+    let (images, setting) = match found {
+        Some(found) => found,
+        None => return,
+    };
+    if images.is_empty() {
+        s.add_layer(Dialog::info("This answer has no images"));
+        return;
+    }
+    let title = format!("Images of answer {}", aid);
+    match graphics::protocol(setting, |var| std::env::var(var).ok()) {
+        Some(protocol) => {
+            s.with_user_data(|session: &mut Session| {
+                session.preview = Some(Preview {
+                    title,
+                    images,
+                    protocol,
+                })
+            });
+            s.quit();
+        }
+        None => {
+            let mut links: Vec<String> = images.iter().map(Image::to_string).collect();
+            if setting == ImagePreview::Off {
+                links.push(String::from(
+                    "\nSet `image_preview` in your config to draw them in the terminal",
+                ));
+            }
+            show_scrollable(s, title, StyledString::plain(links.join("\n")));
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Show `content`, such as a rendered diff, in a scrollable layer
+fn show_scrollable(s: &mut Cursive, title: String, content: StyledString) {
+    s.add_layer(
+        OnEventView::new(
+            Dialog::around(TextView::new(content).scrollable())
+                .title(title)
+                .dismiss_button("Close"),
+        )
+        .on_event(Key::Esc, |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+fn toggle_pin(s: &mut Cursive) {
+    let qid = match s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(qid) => qid,
+        None => return,
+    };
+    let updater = s.with_user_data(|session: &mut Session| {
+        if let Some(q) = session.question(qid).cloned() {
+            session.pins.toggle(&q);
+        }
+        session.updater.clone()
+    });
+    if let Some(updater) = updater {
+        updater.send(Update::Relist);
+    }
+}
+
+fn toggle_shown(s: &mut Cursive) {
+    let updater = s.with_user_data(|session: &mut Session| {
+        session.shown.toggle();
+        session.updater.clone()
+    });
+    // Only new result lists leave questions out, but the status bar says it's on
+    if let Some(updater) = updater {
+        updater.send(Update::Relist);
+    }
+}
+
+/// List the answers in the next order of `AnswerSort::ALL`, without fetching them again
+fn cycle_answer_sort(s: &mut Cursive) {
+    let updater = s.with_user_data(|session: &mut Session| {
+        session.answer_sort = session.answer_sort.next();
+        session.updater.clone()
+    });
+    if let Some(updater) = updater {
+        updater.send(Update::Relist);
+    }
+}
+
+fn toggle_reveal(s: &mut Cursive) {
+    let updater = s.with_user_data(|session: &mut Session| {
+        session.reveal = !session.reveal;
+        session.updater.clone()
+    });
+    if let Some(updater) = updater {
+        updater.send(Update::Relist);
+    }
+}
+
+/// Prompt for the filter on the question list over the status bar, narrowing down the list with
+/// every key; Enter keeps the filter and Esc clears it
+fn filter_questions(s: &mut Cursive) {
+    let query = s.with_user_data(|session: &mut Session| {
+        session.filtering = true;
+        session.filter.clone()
+    });
+    let query = match query {
+        Some(query) => query,
+        None => return,
+    };
+    let XY { x, y } = s.screen_size();
+    let edit = EditView::new()
+        .content(query)
+        .on_edit(|s, query, _| set_filter(s, query, true))
+        .on_submit(|s, query| {
+            s.pop_layer();
+            set_filter(s, query, false);
+        })
+        .with_name(NAME_FILTER_EDIT)
+        .full_width();
+    let prompt = OnEventView::new(edit).on_event(Key::Esc, |s| {
+        s.pop_layer();
+        set_filter(s, "", false);
+    });
+    s.screen_mut().add_transparent_layer_at(
+        Position::absolute((0, y.saturating_sub(1))),
+        Layer::new(
+            LinearLayout::horizontal()
+                .child(TextView::new("Filter: "))
+                .child(prompt)
+                .fixed_width(x),
+        ),
+    );
+}
+
+/// Prompt for a query to search for, the current one to start with, and search for it in the
+/// background
+fn prompt_search(s: &mut Cursive, messages: &Messages) {
+    let query = match s.user_data::<Session>() {
+        Some(session) if session.search_again.source().is_some() => session.query.clone(),
+        _ => return,
+    };
+    let XY { x, y } = s.screen_size();
+    let messages = messages.clone();
+    let edit = EditView::new()
+        .content(query)
+        .on_submit(move |s, query| {
+            s.pop_layer();
+            search_again(s, query, messages.clone());
+        })
+        .with_name(NAME_SEARCH_EDIT)
+        .full_width();
+    let prompt = OnEventView::new(edit).on_event(Key::Esc, |s| {
+        s.pop_layer();
+    });
+    s.screen_mut().add_transparent_layer_at(
+        Position::absolute((0, y.saturating_sub(1))),
+        Layer::new(
+            LinearLayout::horizontal()
+                .child(TextView::new("Search: "))
+                .child(prompt)
+                .fixed_width(x),
+        ),
+    );
+}
+
+/// Search for `query` in the background, spinning in the status bar meanwhile, and list its
+/// results once they're in; failures go to `messages` as well as the status bar
+fn search_again(s: &mut Cursive, query: &str, messages: Messages) {
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        return;
+    }
+    let start = s.with_user_data(|session: &mut Session| {
+        let source = session.search_again.source()?.clone();
+        Some((source, session.search_again.start(&query)?))
+    });
+    let (source, ticket) = match start.flatten() {
+        Some(start) => start,
+        None => return,
+    };
+    let cb_sink = s.cb_sink().clone();
+    loading::animate(cb_sink.clone(), &ticket, |s| {
+        let ticked = s.with_user_data(|session: &mut Session| session.search_again.tick());
+        if ticked == Some(true) {
+            refresh_status(s);
+        }
+    });
+    refresh_status(s);
+    let searcher = Arc::clone(&source.searcher);
+    let busy = user_request(s);
+    source.runtime.spawn(async move {
+        let result = ticket.run(searcher.search(&query)).await;
+        drop(busy);
+        if let Some(result) = result {
+            let result = result.map_err(|e| search_again::failure(&e));
+            // The TUI may be gone by now
+            let _ = cb_sink.send(Box::new(move |s| {
+                searched(s, &ticket, query, result, &messages)
+            }));
+        }
+    });
+}
+
+fn searched(
+    s: &mut Cursive,
+    ticket: &Ticket,
+    query: String,
+    result: std::result::Result<Filtered<Markdown>, String>,
+    messages: &Messages,
+) {
+    let (qs, outcome) = match result {
+        Ok(qs) => (Some(qs), Ok(())),
+        Err(error) => (None, Err(error)),
+    };
+    let taken = s
+        .with_user_data(|session: &mut Session| {
+            session.search_again.finish(ticket, outcome.clone())
+        })
+        .unwrap_or(false);
+    if !taken {
+        return;
+    }
+    let qs = match (qs, outcome) {
+        (Some(qs), _) => qs,
+        (None, Err(error)) => {
+            messages.error(format!("Couldn't search for \"{}\": {}", query, error));
+            refresh_status(s);
+            return;
+        }
+        (None, Ok(())) => return,
+    };
+    // The title filter and the answer marked for diffing were for the results they replace
+    let updated = s.with_user_data(|session: &mut Session| {
+        session.query = query;
+        session.filter.clear();
+        session.filtering = false;
+        session.diff_base = None;
+        // Questions with notes showing up again keeps the notes from being pruned
+        let saved = match session.notes.touch(&qs.shown) {
+            true => Notes::path().and_then(|path| session.notes.save(&path)),
+            false => Ok(()),
+        };
+        (session.updater.clone(), saved)
+    });
+    if let Some((updater, saved)) = updated {
+        if let Err(e) = saved {
+            messages.error(format!("Couldn't save notes: {}", e));
+        }
+        updater.send(Update::Replace(qs));
+    }
+}
 
-pub const NAME_HELP_VIEW: &str = "help_view";
+/// Update the status bar, e.g. as a search in the background goes
+fn refresh_status(s: &mut Cursive) {
+    if let Some(status) = s
+        .user_data::<Session>()
+        .map(|session| filter_status(session))
+    {
+        s.call_on_name(NAME_SHOWN_STATUS, |v: &mut TextView| v.set_content(status));
+    }
+}
 
-pub fn run(qs: Vec<Question<Markdown>>) -> Result<()> {
-    let mut siv = cursive::default();
-    siv.load_theme_file(Config::theme_file_path()?).unwrap(); // TODO dont unwrap
+fn set_filter(s: &mut Cursive, query: &str, filtering: bool) {
+    let set = s.with_user_data(|session: &mut Session| {
+        session.filter = query.to_string();
+        session.filtering = filtering;
+    });
+    if set.is_some() {
+        apply(s, Update::Relist);
+    }
+}
 
-    let question_map: HashMap<u32, Question<Markdown>> =
-        qs.clone().into_iter().map(|q| (q.id, q)).collect();
-    let question_map = Arc::new(question_map);
-    let answer_map: HashMap<u32, Answer<Markdown>> = qs
-        .clone()
-        .into_iter()
-        .map(|q| q.answers.into_iter().map(|a| (a.id, a)))
+/// Status bar indicator for a search running in the background or failing, the latest notice,
+/// the filter on titles, the order of the answers unless it's by votes, hiding previously listed
+/// questions, and how many questions filters left out
+fn filter_status(session: &Session) -> StyledString {
+    let mut status = session.search_again.status();
+    if let Some(notice) = &session.notice {
+        status.append_styled(notice, Effect::Bold);
+        status.append_plain("  ");
+    }
+    if let Some(reading) = session.search_again.low_quota() {
+        status.append_styled(
+            format!(
+                "API quota: {} of {} left today",
+                reading.remaining, reading.max
+            ),
+            Color::Light(BaseColor::Red),
+        );
+        status.append_plain("  ");
+    }
+    if !session.filter.trim().is_empty() {
+        status.append_styled(
+            format!(
+                "matching \"{}\": {} of {}",
+                session.filter,
+                session.visible().len(),
+                session.listing().len()
+            ),
+            Effect::Reverse,
+        );
+        status.append_plain(" (press F to change)  ");
+    }
+    let order = match session.answer_sort {
+        AnswerSort::Votes => None,
+        AnswerSort::Accepted => Some("accepted answer first"),
+        AnswerSort::Newest => Some("newest answers first"),
+    };
+    if let Some(order) = order {
+        status.append_styled(order, Effect::Reverse);
+        status.append_plain(" (press a to change)  ");
+    }
+    if session.shown.hiding() {
+        status.append_styled("hiding shown questions", Effect::Reverse);
+    }
+    if !session.hidden.is_empty() {
+        if session.shown.hiding() {
+            status.append_plain("  ");
+        }
+        let shown = session.results.len()
+            + session
+                .pins
+                .iter()
+                .filter(|p| !session.results.iter().any(|q| q.id == p.id))
+                .count();
+        let action = if session.reveal { "hide" } else { "reveal" };
+        status.append_plain(format!(
+            "{} shown, {} hidden by filters (press R to {} them)",
+            shown,
+            session.hidden.len(),
+            action
+        ));
+    }
+    status
+}
+
+fn show_info(s: &mut Cursive) {
+    let qid = match s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
         .flatten()
-        .collect();
-    let answer_map = Arc::new(answer_map);
+    {
+        Some(qid) => qid,
+        None => return,
+    };
+    let info = s.with_user_data(|session: &mut Session| {
+        let q = session.question(qid).cloned()?;
+        Some((q.title.clone(), question_info(&q, session.scores.locale)))
+    });
+    if let Some((title, info)) = info.flatten() {
+        s.add_layer(Dialog::info(info).title(title));
+    }
+}
 
-    let question_view = MdView::new(Name::QuestionView);
-    let answer_view = MdView::new(Name::AnswerView);
+/// Site, id and dates of `q`, and which engine found it and how it was fetched
+fn question_info<S>(q: &Question<S>, locale: Locale) -> String {
+    let mut lines = vec![
+        format!("Site:     {}", q.site),
+        format!("Id:       {}", q.id),
+    ];
+    if !q.tags.is_empty() {
+        lines.push(format!("Tags:     {}", q.tags.join(", ")));
+    }
+    if let Some(created) = q.creation_date {
+        lines.push(format!("Asked:    {}", locale.date(created)));
+    }
+    if let Some(active) = q.last_activity_date {
+        lines.push(format!("Active:   {}", locale.date(active)));
+    }
+    match &q.provenance {
+        Some(provenance) => lines.extend(provenance_lines(provenance)),
+        None => lines.push(String::from(
+            "Where this question came from wasn't recorded",
+        )),
+    }
+    lines.join("\n")
+}
 
-    let question_list_view = ListView::new_with_items(
-        Name::QuestionList,
-        qs.into_iter().map(|q| (preview_question(&q), q.id)),
-        move |s, qid| question_selected_callback(question_map.clone(), s, *qid),
-    );
+fn provenance_lines(provenance: &Provenance) -> Vec<String> {
+    let request = match provenance.request {
+        Request::Cached => String::from("none, the question cache had it"),
+        request => request.to_string(),
+    };
+    vec![
+        format!("Engine:   {}", provenance.engine),
+        format!("Rank:     {}", provenance.rank),
+        format!("Request:  {}", request),
+    ]
+}
 
-    let answer_list_view = ListView::new(Name::AnswerList, move |s, aid| {
-        let a = answer_map.get(aid).unwrap();
-        s.call_on_name(NAME_ANSWER_VIEW, |v: &mut MdView| v.set_content(&a.body));
+fn edit_note(s: &mut Cursive) {
+    let qid = match s
+        .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.selection())
+        .flatten()
+    {
+        Some(qid) => qid,
+        None => return,
+    };
+    let question = s.with_user_data(|session: &mut Session| {
+        let q = session.question(qid).cloned()?;
+        let text = session
+            .notes
+            .get(&q.site, q.id)
+            .map(|n| n.text.clone())
+            .unwrap_or_default();
+        Some((q, text))
     });
-
-    siv.add_layer(
-        LayoutView::new(
-            1,
-            question_list_view,
-            question_view,
-            answer_list_view,
-            answer_view,
+    let (q, text) = match question.flatten() {
+        Some(question) => question,
+        None => return,
+    };
+    let title = format!("Note on \"{}\"", q.title);
+    let save = move |s: &mut Cursive| {
+        let text = s
+            .call_on_name(NAME_NOTE_EDIT, |v: &mut EditView| v.get_content())
+            .unwrap_or_default();
+        s.pop_layer();
+        let saved = s.with_user_data(|session: &mut Session| {
+            session.notes.set(&q, &text);
+            Notes::path().and_then(|path| session.notes.save(&path))
+        });
+        if let Some(Err(e)) = saved {
+            s.add_layer(Dialog::info(format!("Couldn't save note: {}", e)));
+        }
+        // Re-select the question to show the note
+        let cb = s
+            .call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.select_id(qid))
+            .flatten();
+        if let Some(cb) = cb {
+            cb(s)
+        }
+    };
+    let save = Arc::new(save);
+    let submit = save.clone();
+    s.add_layer(
+        Dialog::around(
+            EditView::new()
+                .content(text)
+                .on_submit(move |s, _| submit(s))
+                .with_name(NAME_NOTE_EDIT)
+                .fixed_width(60),
         )
-        .add_vim_bindings(),
+        .title(title)
+        .button("Save", move |s| save(s))
+        .dismiss_button("Cancel"),
     );
+}
 
-    let cb = siv.call_on_name(NAME_QUESTION_LIST, |v: &mut ListView| v.select(0));
-    if let Some(cb) = cb {
-        cb(&mut siv)
-    }
+/// The body of `q`, or a notice in its place if it wasn't fetched
+fn question_body(q: &Question<Markdown>) -> Markdown {
+    q.body.clone().unwrap_or_else(|| {
+        StyledString::styled(
+            "The question body wasn't fetched",
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Black)),
+                Style::from(Effect::Italic),
+            ]),
+        )
+    })
+}
 
-    // Help / View keymappings
-    siv.add_global_callback('?', |s| {
-        if let Some(pos) = s.screen_mut().find_layer_from_name(NAME_HELP_VIEW) {
-            s.screen_mut().remove_layer(pos);
-        } else {
-            s.add_layer(help());
+/// The question `body`, with the `note` on it shown as a line of italics on top
+fn with_note(body: &Markdown, note: Option<&Note>) -> Markdown {
+    match note {
+        Some(note) => {
+            let mut annotated = StyledString::styled(
+                format!("Note: {}", note.text),
+                Style::merge(&[
+                    Style::from(Color::Light(BaseColor::Yellow)),
+                    Style::from(Effect::Italic),
+                ]),
+            );
+            annotated.append_plain("\n\n");
+            annotated.append(body.clone());
+            annotated
         }
-    });
-    // Reload theme
-    siv.add_global_callback(Event::CtrlChar('r'), |s| {
-        s.load_theme_file(Config::theme_file_path().unwrap())
-            .unwrap()
-    });
-    siv.run();
-    Ok(())
+        None => body.clone(),
+    }
 }
 
-fn question_selected_callback(
-    question_map: Arc<HashMap<u32, Question<Markdown>>>,
-    mut s: &mut Cursive,
-    qid: u32,
-) {
-    let q = question_map.get(&qid).unwrap();
-    let body = &q.body;
-    let XY { x, y: _y } = s.screen_size();
-    // Update question view
-    s.call_on_name(NAME_QUESTION_VIEW, |v: &mut MdView| {
-        v.set_content(body);
-    })
-    .expect("Panic: setting question view content failed");
-    // Update answer list view
-    let cb = s
-        .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| {
-            v.reset_with_all(q.answers.iter().map(|a| (preview_answer(x, a), a.id)))
-        })
-        .expect("Panic: setting answer list content failed");
-    cb(&mut s)
+/// The question `body`, with its `tags` shown as a dim line on top
+fn with_tags(body: &Markdown, tags: &[String]) -> Markdown {
+    if tags.is_empty() {
+        return body.clone();
+    }
+    let mut tagged = StyledString::styled(tag_list(tags), Color::Light(BaseColor::Black));
+    tagged.append_plain("\n\n");
+    tagged.append(body.clone());
+    tagged
 }
 
-fn preview_question(q: &Question<Markdown>) -> StyledString {
-    let mut preview = pretty_score(q.score);
-    preview.append_plain(&q.title);
+/// `tags` as they're shown in the question list and view
+fn tag_list(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!("[{}]", tag))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What the answer view shows for a question nobody has answered yet: a notice, then the
+/// question `body`
+fn no_answers(body: &Markdown) -> Markdown {
+    let mut content = StyledString::styled(
+        "No answers yet",
+        Style::merge(&[
+            Style::from(Color::Light(BaseColor::Black)),
+            Style::from(Effect::Italic),
+        ]),
+    );
+    content.append_plain("\n\n");
+    content.append(body.clone());
+    content
+}
+
+fn preview_question(
+    q: &Question<Markdown>,
+    pinned: bool,
+    hidden: Option<Reason>,
+    matched: &[usize],
+    scores: Scores,
+) -> StyledString {
+    let mut preview = scores.pretty(q.score);
+    if pinned {
+        preview.append_styled(
+            "[pinned] ",
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Yellow)),
+                Style::from(Effect::Bold),
+            ]),
+        );
+    }
+    if let Some(reason) = hidden {
+        preview.append_styled(
+            format!("[hidden: {}] ", reason),
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Black)),
+                Style::from(Effect::Italic),
+            ]),
+        );
+    }
+    let style = if q.answers.is_empty() || hidden.is_some() {
+        Style::from(Color::Light(BaseColor::Black))
+    } else {
+        Style::none()
+    };
+    preview.append(highlight_matches(&q.title, style, matched));
+    if !q.tags.is_empty() {
+        preview.append_styled(
+            format!(" {}", tag_list(&q.tags)),
+            Color::Light(BaseColor::Black),
+        );
+    }
     preview
 }
 
-fn preview_answer(screen_width: usize, a: &Answer<Markdown>) -> StyledString {
+/// `title` in `style`, with the characters at `matched` underlined and in bold
+fn highlight_matches(title: &str, style: Style, matched: &[usize]) -> StyledString {
+    let highlight = Style::merge(&[
+        style,
+        Style::from(Effect::Bold),
+        Style::from(Effect::Underline),
+    ]);
+    let mut styled = StyledString::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in title.chars().enumerate() {
+        let is_matched = matched.contains(&i);
+        if is_matched != run_matched && !run.is_empty() {
+            let style = if run_matched { highlight } else { style };
+            styled.append_styled(std::mem::take(&mut run), style);
+        }
+        run_matched = is_matched;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        styled.append_styled(run, if run_matched { highlight } else { style });
+    }
+    styled
+}
+
+fn preview_answer(
+    screen_width: usize,
+    a: &Answer<Markdown>,
+    diff_base: bool,
+    scores: Scores,
+) -> StyledString {
     let md = markdown::preview(screen_width, &a.body);
-    let mut preview = pretty_score(a.score);
+    let mut preview = scores.pretty(a.score);
+    if diff_base {
+        preview.append_styled(
+            "[diff base] ",
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Cyan)),
+                Style::from(Effect::Bold),
+            ]),
+        );
+    }
     if a.is_accepted {
         preview.append_styled(
             "\u{2713} ", // "✔ "
@@ -127,16 +2131,28 @@ fn preview_answer(screen_width: usize, a: &Answer<Markdown>) -> StyledString {
     preview
 }
 
-fn pretty_score(score: i32) -> StyledString {
-    let color = if score > 0 {
-        Color::Light(BaseColor::Green)
-    } else {
-        Color::Light(BaseColor::Red)
-    };
-    SpannedString::styled(
-        format!("({}) ", score),
-        Style::merge(&[Style::from(color), Style::from(Effect::Bold)]),
-    )
+/// How scores in the question and answer lists are written and colored
+#[derive(Debug, Clone, Copy)]
+struct Scores {
+    locale: Locale,
+    thresholds: ScoreThresholds,
+}
+
+impl Scores {
+    fn pretty(self, score: i32) -> StyledString {
+        let color = match format::score_style(score, &self.thresholds) {
+            ScoreStyle::Negative => Some(Color::Light(BaseColor::Red)),
+            ScoreStyle::Plain => None,
+            ScoreStyle::Good => Some(Color::Dark(BaseColor::Green)),
+            ScoreStyle::Great => Some(Color::Light(BaseColor::Green)),
+            ScoreStyle::Gold => Some(Color::Dark(BaseColor::Yellow)),
+        };
+        let style = match color {
+            Some(color) => Style::merge(&[Style::from(color), Style::from(Effect::Bold)]),
+            None => Style::from(Effect::Bold),
+        };
+        SpannedString::styled(format!("({}) ", self.locale.number(score.into())), style)
+    }
 }
 
 // This would be a good usecase for brining in termimad tables
@@ -156,9 +2172,30 @@ pub fn help() -> Dialog {
 **G**:       Scroll To Bottom
 
 ## Misc
+**p**:              Pin/unpin the selected question to the top of the list
+**H**:              Hide/show questions already listed this session in new results
+**R**:              Reveal/hide the questions filters left out, listed after the others
+**a**:              Cycle the answers between by votes, accepted first and newest first
+**J**:              Jump to the original of a duplicate answer
+**m**:              Add, edit or remove (by clearing) a note on the selected question
+**F**:              Filter the questions by title as you type; Enter keeps it, Esc clears it
+**/**:              Search for another query, listing its results in place of these
+**i**:              Show details of the selected question, such as which engine found it
+**d**:              Mark the selected answer, then diff the code of another answer against it
+**D**:              Diff a local file against the code of the selected answer
+**I**:              Draw the images of the selected answer, or list their links
+**c**:              Show/hide the comments on the selected answer; **r** retries loading them
+**s**:              Reveal the next spoiler of the selected answer or question, or hide them all
+**S**:              Sum up the listed questions in markdown, to copy or save to a file
+**E**:              Edit a code block of the selected answer in $EDITOR, then copy it
+**], [**:           Select the next or previous code block of the selected answer
+**y**:              Copy the selected code block, or the answer's only one, to the clipboard
+**o, O**:           Open the selected question or answer in a browser
 **q, ZZ, Ctrl<c>**: Exit
+**!**:              Toggle the console of warnings and errors
 **Ctrl<r>**:        Reload theme
 **?**:              Toggle this help menu
+**:, Ctrl<p>**:     Search the actions above by name and run one on the selection
 "###;
     Dialog::around(
         TextView::new(markdown::parse(bindings))
@@ -171,3 +2208,964 @@ pub fn help() -> Dialog {
 
 // TODO see cursive/examples/src/bin/select_test.rs for how to test the interface!
 // maybe see if we can conditionally run when --nocapture is passed?
+
+#[cfg(test)]
+mod tests {
+    use super::super::spoilers;
+    use super::super::testing::{self, Puppet};
+    use super::*;
+
+    #[test]
+    fn test_with_note() {
+        let body = markdown::parse("I am stuck");
+        assert_eq!(with_note(&body, None), body);
+
+        let note = Note {
+            site: String::from("stackoverflow"),
+            question_id: 1,
+            title: String::from("How do I exit Vim?"),
+            text: String::from("try :wq first"),
+            updated_at: 0,
+            last_seen: 0,
+        };
+        let annotated = with_note(&body, Some(&note));
+        assert_eq!(annotated.source(), "Note: try :wq first\n\nI am stuck");
+        let first = annotated.spans().next().unwrap();
+        assert_eq!(first.content, "Note: try :wq first");
+        assert!(first.attr.effects.contains(Effect::Italic));
+    }
+
+    #[test]
+    fn test_question_info() {
+        use crate::config::SearchEngine;
+
+        let mut q: Question<String> = Question {
+            id: 1,
+            score: 1,
+            title: String::from("How do I exit Vim?"),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        };
+        assert_eq!(
+            question_info(&q, Locale::En),
+            "Site:     stackoverflow\n\
+             Id:       1\n\
+             Where this question came from wasn't recorded"
+        );
+        q.tags = vec![String::from("vim"), String::from("exit")];
+        assert!(question_info(&q, Locale::En).contains("Id:       1\nTags:     vim, exit\n"));
+        q.provenance = Some(Provenance {
+            engine: SearchEngine::DuckDuckGo,
+            site: String::from("stackoverflow"),
+            rank: 3,
+            request: Request::Batch { chunk: 0 },
+        });
+        assert!(question_info(&q, Locale::En).ends_with(
+            "Engine:   duckduckgo\n\
+             Rank:     3\n\
+             Request:  batch 1 of ids"
+        ));
+    }
+
+    #[test]
+    fn test_main_layout_snapshot() {
+        let screen = Puppet::tui(testing::questions()).screen();
+        testing::assert_snapshot("main_layout", &screen);
+    }
+
+    #[test]
+    fn test_compact_layout_snapshot() {
+        let mut tui = Puppet::tui(testing::questions());
+        // Single column
+        tui.keys(" ");
+        testing::assert_snapshot("compact_layout", &tui.screen());
+        // Full screen, then back to both columns
+        tui.keys(" ");
+        testing::assert_snapshot("full_screen_layout", &tui.screen());
+        tui.keys(" ");
+        assert_eq!(tui.screen(), Puppet::tui(testing::questions()).screen());
+    }
+
+    #[test]
+    fn test_navigation_snapshot() {
+        let mut tui = Puppet::tui(testing::questions());
+        // Next question, then over to its answers and down to the second one
+        tui.keys("j");
+        testing::assert_snapshot("second_question", &tui.screen());
+        tui.keys("kl");
+        tui.press(vec![Event::Key(Key::Tab)]);
+        tui.keys("j");
+        testing::assert_snapshot("second_answer", &tui.screen());
+    }
+
+    #[test]
+    fn test_answer_headers() {
+        let screen = Puppet::tui(testing::questions()).screen();
+        // Score, then a check mark for the accepted answer, then the start of the answer
+        for header in &[
+            "(5,120) \u{2713} Hit Esc to leave insert mode",
+            "(42) Type ZZ to save and quit.",
+            "(-3) Pull the plug.",
+        ] {
+            assert!(
+                screen.lines().any(|line| line.contains(header)),
+                "no answer header {:?} in\n{}",
+                header,
+                screen
+            );
+        }
+    }
+
+    #[test]
+    fn test_status_bar() {
+        let messages = Messages::default();
+        let mut tui = Puppet::tui_with(Filtered::new(testing::questions()), None, messages.clone());
+        assert_eq!(tui.status_bar(), "");
+
+        messages.warn("results are partial");
+        tui.press(vec![Event::Refresh]);
+        assert!(tui.status_bar().ends_with("\u{26a0} 1 (press ! to view)"));
+
+        tui.keys("H");
+        let status = tui.status_bar();
+        assert!(status.starts_with("hiding shown questions"));
+        assert!(status.ends_with("(press ! to view)"));
+
+        // Reading the messages clears the badge
+        tui.keys("!");
+        assert!(tui.screen().contains("warning: results are partial"));
+        tui.keys("!");
+        assert_eq!(tui.status_bar(), "hiding shown questions");
+    }
+
+    #[test]
+    fn test_offline_banner() {
+        let mut tui = Puppet::tui_with(
+            Filtered::new(testing::questions()),
+            Some(0),
+            Messages::default(),
+        );
+        let banner = query_cache::offline_banner(0, Locale::En);
+        let screen = tui.screen();
+        let first = screen.lines().find(|line| !line.trim().is_empty());
+        assert_eq!(first.map(str::trim), Some(banner.as_str()));
+    }
+
+    #[test]
+    fn test_pin_keeps_selection() {
+        let mut tui = Puppet::tui(testing::questions());
+        tui.keys("jp");
+        let screen = tui.screen();
+        assert!(screen.contains("Quit vi without saving changes"));
+        assert!(screen.contains("How do I throw away my changes?"));
+        let session = tui.siv().user_data::<Session>().unwrap();
+        assert_eq!(session.questions()[0].id, 2);
+    }
+
+    #[test]
+    fn test_cycle_answer_sort() {
+        let mut qs = testing::questions();
+        // A downvoted answer was accepted, and a middling one is the latest
+        for a in qs[0].answers.iter_mut() {
+            a.is_accepted = a.id == 12;
+            a.creation_date = Some(if a.id == 11 {
+                1_600_000_000
+            } else {
+                1_500_000_000
+            });
+        }
+        let mut tui = Puppet::tui(qs);
+        // The top answer is the one selected
+        let first = |tui: &mut Puppet| {
+            tui.siv()
+                .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.selection())
+                .flatten()
+        };
+        assert_eq!(first(&mut tui), Some(10));
+        assert_eq!(tui.status_bar(), "");
+
+        tui.keys("a");
+        assert_eq!(first(&mut tui), Some(12));
+        assert_eq!(
+            tui.status_bar(),
+            "accepted answer first (press a to change)"
+        );
+        tui.keys("a");
+        assert_eq!(first(&mut tui), Some(11));
+        assert_eq!(tui.status_bar(), "newest answers first (press a to change)");
+        tui.keys("a");
+        assert_eq!(first(&mut tui), Some(10));
+        assert_eq!(tui.status_bar(), "");
+    }
+
+    #[test]
+    fn test_reveal_hidden() {
+        let qs = Filtered::new(testing::questions())
+            .filter(Reason::Unanswered, |q| !q.answers.is_empty());
+        let mut tui = Puppet::tui_with(qs, None, Messages::default());
+        let screen = tui.screen();
+        assert!(screen.contains("2 shown, 1 hidden by filters (press R to reveal them)"));
+        assert!(!screen.contains("Exit Vim from a script"));
+
+        tui.keys("R");
+        let screen = tui.screen();
+        assert!(screen.contains("2 shown, 1 hidden by filters (press R to hide them)"));
+        assert!(screen.contains("(0) [hidden: no answers] Exit Vim"));
+        // Revealed questions come last and can be opened like any other
+        tui.keys("jj");
+        assert!(tui
+            .screen()
+            .contains("Is there a way to do this non-interactively?"));
+
+        tui.keys("R");
+        assert!(!tui.screen().contains("Exit Vim from a script"));
+        let session = tui.siv().user_data::<Session>().unwrap();
+        assert_eq!(session.questions().len(), 2);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut qs = testing::questions();
+        qs[1].tags = vec![String::from("vi"), String::from("git")];
+        let mut tui = Puppet::tui(qs);
+        assert!(tui
+            .screen()
+            .contains("(87) Quit vi without saving changes [vi] [git]"));
+        tui.keys("j");
+        assert!(tui.screen().contains("\u{2502}[vi] [git]"));
+
+        let tags = [String::from("vim"), String::from("exit")];
+        let body = with_tags(&markdown::parse("I am stuck"), &tags);
+        assert_eq!(body.source(), "[vim] [exit]\n\nI am stuck");
+        assert_eq!(
+            body.spans().next().unwrap().attr.color,
+            Style::from(Color::Light(BaseColor::Black)).color
+        );
+        let body = markdown::parse("I am stuck");
+        assert_eq!(with_tags(&body, &[]), body);
+    }
+
+    #[test]
+    fn test_filter_questions() {
+        let mut tui = Puppet::tui(testing::questions());
+        tui.keys("j");
+        tui.keys("Fexit vim");
+        let screen = tui.screen();
+        assert!(screen.contains("Filter: exit vim"));
+        assert!(!screen.contains("Quit vi without saving changes"));
+        // The best match comes first, and is the one shown
+        let session = tui.siv().user_data::<Session>().unwrap();
+        let visible: Vec<u32> = session.visible().iter().map(|(q, ..)| q.id).collect();
+        assert_eq!(visible, vec![3, 1]);
+        assert!(screen.contains("Is there a way to do this non-interactively?"));
+        assert!(screen.contains("No answers yet"));
+
+        // Kept, with keys doing what they do again
+        tui.press(vec![Key::Enter]);
+        assert_eq!(
+            tui.status_bar(),
+            "matching \"exit vim\": 2 of 3 (press F to change)"
+        );
+        tui.keys("j");
+        assert!(tui.screen().contains("I'm stuck in Vim and can't get out."));
+
+        // Nothing left to show
+        tui.keys("Fx");
+        let screen = tui.screen();
+        assert!(screen.contains("No question title matches the filter"));
+        assert!(!screen.contains("I'm stuck in Vim"));
+
+        // Cleared, staying on the question looked at last
+        tui.press(vec![Key::Backspace]);
+        tui.press(vec![Key::Esc]);
+        let screen = tui.screen();
+        assert!(screen.contains("Quit vi without saving changes"));
+        assert!(screen.contains("Is there a way to do this non-interactively?"));
+        assert_eq!(tui.status_bar(), "");
+        let session = tui.siv().user_data::<Session>().unwrap();
+        assert!(session.filter.is_empty());
+        assert!(!session.filtering);
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let grey = Style::from(Color::Light(BaseColor::Black));
+        let styled = highlight_matches("Exit Vim", grey, &[0, 5, 6]);
+        let spans: Vec<_> = styled.spans().map(|s| (s.content, s.attr)).collect();
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans[0].0, "E");
+        assert!(spans[0].1.effects.contains(Effect::Underline));
+        assert_eq!(spans[0].1.color, grey.color);
+        assert_eq!(spans[1], ("xit ", &grey));
+        assert_eq!(spans[2].0, "Vi");
+        assert_eq!(spans[3], ("m", &grey));
+        assert_eq!(highlight_matches("Exit", grey, &[]).spans().count(), 1);
+    }
+
+    #[test]
+    fn test_diff_local_file() {
+        let dir = std::env::temp_dir().join(format!("so-diff-local-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("quit.vim");
+        std::fs::write(&script, ":wq\n").unwrap();
+        let binary = dir.join("vim");
+        std::fs::write(&binary, b"\x7fELF\x00").unwrap();
+
+        let mut tui = Puppet::tui(testing::questions());
+        tui.keys("D");
+        assert!(tui.screen().contains("File to compare against:"));
+        tui.keys(&binary.to_string_lossy());
+        tui.press(vec![Key::Enter]);
+        let screen = tui.screen();
+        assert!(screen.contains("Couldn't compare"));
+        assert!(screen.contains("looks like a binary file"));
+
+        // The prompt stays up to try another path
+        tui.press(vec![Key::Enter]);
+        tui.press(vec![Key::Backspace; binary.to_string_lossy().len()]);
+        tui.keys(&script.to_string_lossy());
+        tui.press(vec![Key::Enter]);
+        let screen = tui.screen();
+        assert!(screen.contains("against the code of answer 10"));
+        assert!(screen.contains("- :wq"));
+        assert!(screen.contains("+ :q!"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_local_file_without_code() {
+        let mut tui = Puppet::tui(testing::questions());
+        tui.keys("jD");
+        assert!(tui.screen().contains("This answer has no code to compare"));
+    }
+
+    #[test]
+    fn test_no_answers() {
+        let body = markdown::parse("I am stuck");
+        let content = no_answers(&body);
+        assert_eq!(content.source(), "No answers yet\n\nI am stuck");
+        let first = content.spans().next().unwrap();
+        assert_eq!(first.content, "No answers yet");
+        assert!(first.attr.effects.contains(Effect::Italic));
+    }
+
+    #[test]
+    fn test_byline() {
+        let mut qs = testing::questions();
+        qs[0].answers[0].owner = Some(Owner {
+            display_name: Some(String::from("jane")),
+            reputation: Some(12_345),
+        });
+        // Deleted users can come without a name
+        qs[0].answers[1].owner = Some(Owner::default());
+        let mut tui = Puppet::tui(qs);
+        assert!(tui.screen().contains("\u{2014} answered by jane (12.3k)"));
+        tui.keys("j");
+        assert!(!tui.screen().contains("answered by"));
+
+        let body = with_byline(markdown::parse("Hit `Esc`"), None, Locale::En);
+        assert_eq!(body.source(), "Hit `Esc`");
+        let owner = Owner {
+            display_name: Some(String::from("jane")),
+            reputation: None,
+        };
+        let body = with_byline(markdown::parse("Hit `Esc`"), Some(&owner), Locale::En);
+        assert_eq!(body.source(), "Hit `Esc`\n\n\u{2014} answered by jane");
+    }
+
+    #[test]
+    fn test_preview_images() {
+        let qs = vec![testing::question(
+            1,
+            1,
+            "Where is the settings dialog?",
+            "I can't find it",
+            vec![
+                testing::answer(
+                    10,
+                    1,
+                    true,
+                    "Here: ![settings](https://i.sstatic.net/a.png)",
+                ),
+                testing::answer(11, 1, false, "No idea"),
+            ],
+        )];
+        let mut tui = Puppet::tui(qs);
+        // Links only, by default
+        tui.keys("I");
+        let screen = tui.screen();
+        assert!(screen.contains("Images of answer 10"));
+        assert!(screen.contains("settings: https://i.sstatic.net/a.png"));
+        assert!(screen.contains("Set `image_preview` in your config"));
+        tui.press(vec![Event::Key(Key::Esc)]);
+
+        // Drawn once the TUI is out of the way
+        tui.siv().user_data::<Session>().unwrap().image_preview = ImagePreview::Kitty;
+        tui.keys("I");
+        assert!(!tui.siv().is_running());
+        let session = tui.siv().user_data::<Session>().unwrap();
+        let preview = session.preview.as_ref().unwrap();
+        assert_eq!(preview.protocol, Protocol::Kitty);
+        assert_eq!(preview.images[0].url, "https://i.sstatic.net/a.png");
+    }
+
+    #[test]
+    fn test_edit_code() {
+        let mut qs = vec![testing::question(
+            1,
+            1,
+            "How do I read a file?",
+            "All of it, at once",
+            vec![
+                testing::answer(
+                    10,
+                    2,
+                    true,
+                    "Read all of it into memory at once, which is fine for files that are small:\n\n\
+                     ```rust\nfs::read(path)\n```",
+                ),
+                testing::answer(
+                    11,
+                    1,
+                    false,
+                    "Either open it and read it bit by bit, or read it all in one go:\n\n\
+                     \x20   open(path)\n\nor\n\n    read(path)\n",
+                ),
+                testing::answer(12, 0, false, "No idea"),
+            ],
+        )];
+        qs[0].tags = vec![String::from("python-3.x")];
+        let mut tui = Puppet::tui(qs);
+        let copied = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorder = copied.clone();
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.copy = Box::new(move |text| {
+                recorder.lock().unwrap().push(text.to_string());
+                Ok(())
+            })
+        });
+
+        // The only code block, right away, with the TUI out of the way
+        tui.keys("E");
+        assert!(!tui.siv().is_running());
+        let edit = tui
+            .siv()
+            .with_user_data(|session: &mut Session| session.edit.take())
+            .flatten()
+            .unwrap();
+        assert_eq!(edit.code, "fs::read(path)\n");
+        assert_eq!(edit.extension, "rs");
+
+        // Copied and kept once edited
+        code_edited(tui.siv(), Ok(String::from("fs::read(\"a.txt\")\n")));
+        tui.step();
+        assert!(tui
+            .screen()
+            .contains("Copied the edited code to the clipboard"));
+        assert_eq!(*copied.lock().unwrap(), vec!["fs::read(\"a.txt\")\n"]);
+        tui.press(vec![Key::Enter]);
+        code_edited(tui.siv(), Err(crate::error::Error::NoEditor));
+        tui.step();
+        let screen = tui.screen();
+        assert!(screen.contains("Couldn't edit the code"));
+        assert!(screen.contains("Set $EDITOR to edit code"));
+        assert_eq!(copied.lock().unwrap().len(), 1);
+        tui.press(vec![Key::Enter]);
+        let edited = tui
+            .siv()
+            .with_user_data(|session: &mut Session| session.edited.clone())
+            .unwrap();
+        assert_eq!(edited, vec!["fs::read(\"a.txt\")\n"]);
+
+        // Picked from the blocks of an answer with more, as the language of the question's tags
+        tui.siv()
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(11));
+        tui.step().keys("E");
+        let screen = tui.screen();
+        assert!(screen.contains("1. open(path) (1 lines)"));
+        assert!(screen.contains("2. read(path) (1 lines)"));
+        tui.press(vec![Key::Down, Key::Enter]);
+        let edit = tui
+            .siv()
+            .with_user_data(|session: &mut Session| session.edit.take())
+            .flatten()
+            .unwrap();
+        assert_eq!(edit.code, "read(path)\n");
+        assert_eq!(edit.extension, "py");
+
+        tui.siv()
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(12));
+        tui.step().keys("E");
+        assert!(tui.screen().contains("This answer has no code to edit"));
+    }
+
+    #[test]
+    fn test_copy_code_blocks() {
+        let qs = vec![testing::question(
+            1,
+            1,
+            "How do I read a file?",
+            "All of it, at once",
+            vec![
+                testing::answer(
+                    10,
+                    2,
+                    true,
+                    "Read all of it into memory at once, which is fine for files that are small:\n\n\
+                     \x20   fs::read(path)\n",
+                ),
+                testing::answer(
+                    11,
+                    1,
+                    false,
+                    "Either open it and read it bit by bit, or read it all in one go:\n\n\
+                     \x20   let f = open(path);\n    f.read()\n\nor\n\n    read(path)\n",
+                ),
+                testing::answer(12, 0, false, "No idea"),
+            ],
+        )];
+        let mut tui = Puppet::tui(qs);
+        let copied = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorder = copied.clone();
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.copy = Box::new(move |text| {
+                recorder.lock().unwrap().push(text.to_string());
+                Ok(())
+            })
+        });
+
+        // The only code block, without selecting it first
+        tui.keys("y");
+        assert!(tui.status_bar().contains("copied 1 line"));
+        assert_eq!(*copied.lock().unwrap(), vec!["fs::read(path)\n"]);
+
+        // One of more, once selected, with a gutter along it
+        tui.siv()
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(11));
+        tui.step().keys("y");
+        assert!(tui
+            .status_bar()
+            .contains("Press ] or [ to select a code block"));
+        tui.keys("]");
+        assert!(tui.status_bar().contains("code block 1 of 2"));
+        let screen = tui.screen();
+        assert!(screen.contains("\u{258c} let f = open(path);"));
+        assert!(screen.contains("\u{258c} f.read()"));
+        assert!(!screen.contains("\u{258c} read(path)"));
+        tui.keys("]]");
+        assert!(tui.status_bar().contains("code block 2 of 2"));
+        assert!(tui.screen().contains("\u{258c} read(path)"));
+        tui.keys("[y");
+        assert!(tui.status_bar().contains("copied 2 lines"));
+        assert_eq!(
+            copied.lock().unwrap().last().unwrap(),
+            "let f = open(path);\nf.read()\n"
+        );
+
+        // Nothing to select
+        tui.siv()
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(12));
+        tui.step().keys("]");
+        assert!(tui.status_bar().contains("This answer has no code blocks"));
+        tui.keys("y");
+        assert!(tui.status_bar().contains("This answer has no code to copy"));
+
+        // Told when there's no clipboard to copy to
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.copy = Box::new(|_| {
+                Err(crate::error::Error::Clipboard(vec![
+                    crate::error::CommandError::Io {
+                        command: String::from("xclip"),
+                        source: std::io::Error::new(std::io::ErrorKind::NotFound, "not found"),
+                    },
+                ]))
+            })
+        });
+        tui.siv()
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(10));
+        tui.step().keys("y");
+        let screen = tui.screen();
+        assert!(screen.contains("Couldn't copy"));
+        assert!(screen.contains("`xclip` couldn't run: not found"));
+        assert_eq!(copied.lock().unwrap().len(), 2);
+    }
+
+    /// Comments for answer 10, and a failure for any other
+    struct FakeComments;
+
+    impl super::super::comments::Fetch for FakeComments {
+        fn fetch(
+            &self,
+            _site: &str,
+            answer_id: u32,
+        ) -> futures::future::BoxFuture<'static, Result<Vec<Comment>>> {
+            Box::pin(async move {
+                match answer_id {
+                    10 => Ok(vec![Comment {
+                        score: 7,
+                        body_markdown: String::from("Use `:wq` to save first"),
+                        owner_display_name: String::from("jane"),
+                    }]),
+                    _ => Err(crate::error::Error::Api {
+                        site: Some(String::from("stackoverflow")),
+                        endpoint: Some(format!("answers/{}/comments", answer_id)),
+                        status: 502,
+                        name: Some(String::from("throttle_violation")),
+                        message: String::from("throttled"),
+                    }),
+                }
+            })
+        }
+    }
+
+    /// Step `tui` until its screen has `text`, or fail after a while
+    fn wait_for(tui: &mut Puppet, text: &str) -> String {
+        for _ in 0..100 {
+            let screen = tui.step().screen();
+            if screen.contains(text) {
+                return screen;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        panic!("never showed {:?}:\n{}", text, tui.screen());
+    }
+
+    #[test]
+    fn test_open_links() {
+        let mut qs = testing::questions();
+        qs[0].link = Some(String::from("https://stackoverflow.com/questions/1"));
+        qs[0].answers[0].link = Some(String::from("https://stackoverflow.com/a/10"));
+        let messages = Messages::default();
+        let mut tui = Puppet::tui_with(Filtered::new(qs), None, messages.clone());
+        let opened = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorder = opened.clone();
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.open = Box::new(move |link| {
+                recorder.lock().unwrap().push(link.to_string());
+                Ok(())
+            })
+        });
+        tui.keys("oO");
+        assert_eq!(
+            *opened.lock().unwrap(),
+            vec![
+                "https://stackoverflow.com/questions/1",
+                "https://stackoverflow.com/a/10"
+            ]
+        );
+        assert!(messages.unread().is_none());
+
+        // Without a link or a browser, the status bar says so and the TUI stays up
+        tui.keys("jO");
+        assert!(messages.unread().is_some());
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.open = Box::new(|link| {
+                Err(crate::error::Error::Browser {
+                    url: link.to_string(),
+                    source: crate::error::CommandError::Io {
+                        command: String::from("xdg-open"),
+                        source: std::io::Error::new(std::io::ErrorKind::NotFound, "no opener"),
+                    },
+                })
+            })
+        });
+        tui.keys("ko");
+        let read = messages.read();
+        assert_eq!(read.len(), 2);
+        assert!(read[1].text.contains("no opener"));
+        assert!(tui.screen().contains("How do I exit the Vim editor?"));
+    }
+
+    #[test]
+    fn test_spoilers() {
+        let qs = vec![testing::question(
+            1,
+            1,
+            "Who wrote it?",
+            "Guess first.\n\n>! Someone famous",
+            vec![
+                testing::answer(10, 1, true, "It was\n\n>! Shakespeare"),
+                testing::answer(11, 1, false, "No idea"),
+            ],
+        )];
+        let mut tui = Puppet::tui(qs);
+        let screen = tui.screen();
+        // In the question, and in the answer as well as its preview
+        assert_eq!(screen.matches(spoilers::COLLAPSED).count(), 3);
+        assert!(!screen.contains("Shakespeare"));
+
+        // The selected answer's first
+        tui.keys("s");
+        let screen = tui.screen();
+        assert!(screen.contains("Shakespeare"));
+        assert!(!screen.contains("Someone famous"));
+        // With none in the selected answer, the question's
+        tui.press(vec![Key::Tab]).keys("js");
+        let screen = tui.screen();
+        assert!(screen.contains("Someone famous"));
+        // Previews stay as they are
+        assert_eq!(screen.matches(spoilers::COLLAPSED).count(), 1);
+        // Once all are revealed, they collapse again
+        tui.keys("s");
+        assert!(!tui.screen().contains("Someone famous"));
+    }
+
+    #[test]
+    fn test_share_summary() {
+        let mut tui = Puppet::tui(testing::questions());
+        let copied = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorder = copied.clone();
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.copy = Box::new(move |text| {
+                recorder.lock().unwrap().push(text.to_string());
+                Ok(())
+            })
+        });
+        tui.keys("S");
+        assert!(tui.screen().contains("File to save it to:"));
+        // Over to the buttons, the first of which copies
+        tui.press(vec![Key::Tab, Key::Enter]);
+        assert!(tui.screen().contains("Copied the summary to the clipboard"));
+        let copied = copied.lock().unwrap();
+        assert_eq!(copied.len(), 1);
+        assert!(copied[0].contains("- How do I exit the Vim editor?"));
+
+        // Or saved to a file
+        let path = std::env::temp_dir().join(format!("so-share-{}.md", std::process::id()));
+        tui.press(vec![Key::Enter]).keys("S");
+        tui.keys(&path.to_string_lossy()).press(vec![Key::Enter]);
+        assert!(tui.screen().contains("Saved the summary to"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), copied[0]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_comments() {
+        let runtime = Runtime::new().unwrap();
+        let messages = Messages::default();
+        let mut tui = Puppet::tui_with(Filtered::new(testing::questions()), None, messages.clone());
+        set_comments_source(
+            tui.siv(),
+            Source {
+                fetcher: Arc::new(FakeComments),
+                runtime: runtime.handle().clone(),
+            },
+        );
+        tui.keys("c");
+        let screen = wait_for(&mut tui, "Use :wq to save first");
+        assert!(screen.contains("(7) jane"));
+        // Closed, then open again without another fetch
+        tui.keys("c");
+        assert!(!tui.screen().contains("(7) jane"));
+        tui.keys("c");
+        assert!(tui.screen().contains("(7) jane"));
+
+        // Failures are shown below the answer and in the status bar, and the view stays up
+        tui.siv().with_user_data(|session: &mut Session| {
+            session.comments.toggle(10);
+        });
+        let cb = tui
+            .siv()
+            .call_on_name(NAME_ANSWER_LIST, |v: &mut ListView| v.select_id(11))
+            .flatten();
+        if let Some(cb) = cb {
+            cb(tui.siv());
+        }
+        tui.keys("c");
+        wait_for(&mut tui, "Couldn't load comments:");
+        assert!(messages.unread().is_some());
+        assert!(tui.screen().contains("Type ZZ to save and quit."));
+    }
+
+    /// Comments for answer 10, counting how often they're fetched
+    #[derive(Default)]
+    struct CountingComments(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl super::super::comments::Fetch for CountingComments {
+        fn fetch(
+            &self,
+            site: &str,
+            answer_id: u32,
+        ) -> futures::future::BoxFuture<'static, Result<Vec<Comment>>> {
+            if answer_id == 10 {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+            super::super::comments::Fetch::fetch(&FakeComments, site, answer_id)
+        }
+    }
+
+    #[test]
+    fn test_prefetch_comments() {
+        let runtime = Runtime::new().unwrap();
+        let mut tui = Puppet::tui_with(
+            Filtered::new(testing::questions()),
+            None,
+            Messages::default(),
+        );
+        let fetcher = CountingComments::default();
+        let fetched = fetcher.0.clone();
+        set_comments_source(
+            tui.siv(),
+            Source {
+                fetcher: Arc::new(fetcher),
+                runtime: runtime.handle().clone(),
+            },
+        );
+        start_prefetch(tui.siv());
+        let store = tui
+            .siv()
+            .with_user_data(|session: &mut Session| session.comments.store())
+            .unwrap();
+        for _ in 0..100 {
+            if store.lock().unwrap().contains_key(&10) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(fetched.load(std::sync::atomic::Ordering::SeqCst), 1);
+        // Shown right away, without fetching them again
+        tui.keys("c");
+        assert!(tui.screen().contains("(7) jane"));
+        assert_eq!(fetched.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    /// Finds the question about vi for "quit vi", never finishes searching for "slow", and finds
+    /// nothing for anything else
+    struct FakeSearch;
+
+    impl super::super::search_again::Searcher for FakeSearch {
+        fn search(
+            &self,
+            query: &str,
+        ) -> futures::future::BoxFuture<'static, Result<Filtered<Markdown>>> {
+            let query = query.to_string();
+            Box::pin(async move {
+                match query.as_str() {
+                    "quit vi" => Ok(Filtered::new(
+                        testing::questions()
+                            .into_iter()
+                            .filter(|q| q.id == 2)
+                            .collect(),
+                    )),
+                    "slow" => futures::future::pending().await,
+                    _ => Err(crate::error::Error::NoResults),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_search_again() {
+        let runtime = Runtime::new().unwrap();
+        let messages = Messages::default();
+        let mut tui = Puppet::tui_with(Filtered::new(testing::questions()), None, messages.clone());
+        set_search_source(
+            tui.siv(),
+            search_again::Source {
+                searcher: Arc::new(FakeSearch),
+                runtime: runtime.handle().clone(),
+            },
+        );
+        // The listed results stay up while searching
+        tui.keys("/slow").press(vec![Key::Enter]);
+        let screen = wait_for(&mut tui, "searching for \"slow\"");
+        assert!(screen.contains("How do I exit the Vim editor?"));
+        assert!(screen.contains("I'm stuck in Vim and can't get out."));
+
+        // And when finding nothing, which takes over from the search still running
+        tui.keys("/nothing").press(vec![Key::Enter]);
+        let screen = wait_for(&mut tui, "couldn't search for \"nothing\": no results");
+        assert!(screen.contains("How do I exit the Vim editor?"));
+        assert!(!screen.contains("searching for"));
+        assert!(messages.unread().is_some());
+
+        tui.keys("/quit vi").press(vec![Key::Enter]);
+        let screen = wait_for(&mut tui, "How do I throw away my changes?");
+        assert!(!screen.contains("How do I exit the Vim editor?"));
+        assert!(!screen.contains("couldn't search for"));
+        let session = tui.siv().user_data::<Session>().unwrap();
+        assert_eq!(session.query, "quit vi");
+        // Which the next search starts from
+        tui.keys("/");
+        assert!(tui.screen().contains("Search: quit vi"));
+    }
+
+    /// Never finishes searching, and has hardly any quota left
+    struct LowQuota;
+
+    impl super::super::search_again::Searcher for LowQuota {
+        fn search(
+            &self,
+            _: &str,
+        ) -> futures::future::BoxFuture<'static, Result<Filtered<Markdown>>> {
+            Box::pin(futures::future::pending())
+        }
+
+        fn low_quota(&self) -> Option<crate::stackexchange::quota::Reading> {
+            Some(crate::stackexchange::quota::Reading {
+                remaining: 12,
+                max: 300,
+                at: 0,
+            })
+        }
+    }
+
+    #[test]
+    fn test_low_quota() {
+        let runtime = Runtime::new().unwrap();
+        let mut tui = Puppet::tui(testing::questions());
+        assert!(!tui.screen().contains("API quota"));
+        set_search_source(
+            tui.siv(),
+            search_again::Source {
+                searcher: Arc::new(LowQuota),
+                runtime: runtime.handle().clone(),
+            },
+        );
+        tui.keys("/slow").press(vec![Key::Enter]);
+        wait_for(&mut tui, "API quota: 12 of 300 left today");
+    }
+
+    #[test]
+    fn test_registry() {
+        let actions = registry(&Messages::default(), Resolved::BuiltIn(Appearance::Dark));
+        let mut keys: Vec<String> = actions.iter().map(|a| actions::key_label(&a.key)).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), actions.len(), "two actions share a key");
+        let names = |focus| -> Vec<&str> {
+            actions::matching(&actions, focus, "")
+                .into_iter()
+                .map(|i| actions[i].name)
+                .collect()
+        };
+        assert!(names(actions::Focus::Answers).contains(&"Open answer"));
+        assert!(!names(actions::Focus::Questions).contains(&"Open answer"));
+        assert!(names(actions::Focus::Questions).contains(&"Open question"));
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut tui = Puppet::tui(testing::questions());
+        tui.keys(":");
+        let screen = tui.screen();
+        assert!(screen.contains("Pin question"));
+        // Answer actions only with the answers focused
+        assert!(!screen.contains("Jump to original"));
+        tui.keys("pin q").press(vec![Key::Enter]);
+        assert!(!tui.screen().contains("Pin question"));
+        let session = tui.siv().user_data::<Session>().unwrap();
+        assert!(session.pins.is_pinned(1));
+
+        // Closed again by its key, or Esc
+        tui.press(vec![Event::CtrlChar('p')]);
+        assert!(tui.screen().contains("Pin question"));
+        tui.press(vec![Event::CtrlChar('p')]);
+        assert!(!tui.screen().contains("Pin question"));
+        tui.keys("l").press(vec![Key::Tab]).keys(":");
+        assert!(tui.screen().contains("Jump to original"));
+        tui.press(vec![Key::Esc]);
+        assert!(!tui.screen().contains("Jump to original"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Stepping through the code blocks of the selected answer, to copy one at a time.
+//!
+//! Blocks are those `markdown::parse_with_blocks` shows, so that code in collapsed spoilers is
+//! skipped. The selected block is marked with a gutter down its left side rather than restyled,
+//! which keeps its syntax colors and code match highlights.
+
+use cursive::theme::PaletteColor;
+use cursive::utils::lines::spans::LinesIterator;
+use std::ops::Range;
+
+use super::markdown::Markdown;
+
+/// Marks the lines of the selected block
+const GUTTER: &str = "\u{258c} ";
+
+/// Which code block is selected, if any, and of which answer
+#[derive(Debug, Default)]
+pub struct Cursor {
+    selected: Option<(u32, usize)>,
+}
+
+impl Cursor {
+    /// Select the next block of the answer `aid`, which has `count`, or the previous one if not
+    /// `forward`; the first or the last if none of its blocks was selected. Stays put at either
+    /// end. Returns the one selected, if the answer has any.
+    pub fn step(&mut self, aid: u32, count: usize, forward: bool) -> Option<usize> {
+        if count == 0 {
+            self.selected = None;
+            return None;
+        }
+        let next = match (self.current(aid), forward) {
+            (Some(i), true) => (i + 1).min(count - 1),
+            (Some(i), false) => i.saturating_sub(1),
+            (None, true) => 0,
+            (None, false) => count - 1,
+        };
+        self.selected = Some((aid, next));
+        Some(next)
+    }
+
+    /// The selected block of the answer `aid`, if it has one
+    pub fn current(&self, aid: u32) -> Option<usize> {
+        match self.selected {
+            Some((selected, i)) if selected == aid => Some(i),
+            _ => None,
+        }
+    }
+}
+
+/// `md` with a gutter in front of each line of the block at `block`, a byte range of its text
+/// that ends with the line break after the block
+pub fn mark(md: &Markdown, block: &Range<usize>) -> Markdown {
+    let lines = block.start..block.end.saturating_sub(1);
+    let mut marked = Markdown::new();
+    let mut at = 0;
+    let mut line_start = true;
+    for span in md.spans() {
+        for piece in span.content.split_inclusive('\n') {
+            if (line_start || at == block.start) && lines.contains(&at) {
+                marked.append_styled(GUTTER, PaletteColor::Highlight);
+            }
+            marked.append_styled(piece, *span.attr);
+            at += piece.len();
+            line_start = piece.ends_with('\n');
+        }
+    }
+    marked
+}
+
+/// Row the byte `offset` of `md` is on, once it's wrapped to `width` columns
+pub fn row(md: &Markdown, offset: usize, width: usize) -> usize {
+    let mut starts = Vec::new();
+    let mut at = 0;
+    for span in md.spans() {
+        starts.push(at);
+        at += span.content.len();
+    }
+    LinesIterator::new(md, width)
+        .position(|row| {
+            row.segments
+                .last()
+                .is_some_and(|segment| starts[segment.span_id] + segment.end > offset)
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::markdown;
+
+    /// Rendered text, without styles
+    fn text(md: &Markdown) -> String {
+        md.spans().map(|span| span.content).collect()
+    }
+
+    #[test]
+    fn test_step() {
+        let mut cursor = Cursor::default();
+        assert_eq!(cursor.step(1, 3, true), Some(0));
+        assert_eq!(cursor.step(1, 3, true), Some(1));
+        assert_eq!(cursor.step(1, 3, true), Some(2));
+        assert_eq!(cursor.step(1, 3, true), Some(2));
+        assert_eq!(cursor.step(1, 3, false), Some(1));
+        assert_eq!(cursor.current(1), Some(1));
+
+        // Another answer starts over, from whichever end
+        assert_eq!(cursor.current(2), None);
+        assert_eq!(cursor.step(2, 3, false), Some(2));
+        assert_eq!(cursor.current(1), None);
+        assert_eq!(cursor.step(2, 0, true), None);
+        assert_eq!(cursor.current(2), None);
+    }
+
+    #[test]
+    fn test_mark() {
+        let input = "Try\n\n```\nfn main() {\n\n}\n```\n\nor\n\n    cargo run\n";
+        let (parsed, blocks) = markdown::parse_with_blocks(input, &[], &[], None);
+        assert_eq!(
+            text(&mark(&parsed, &blocks[0].text)),
+            "Try\n\n\u{258c} fn main() {\n\u{258c} \n\u{258c} }\n\nor\n\ncargo run\n\n"
+        );
+        assert_eq!(
+            text(&mark(&parsed, &blocks[1].text)),
+            "Try\n\nfn main() {\n\n}\n\nor\n\n\u{258c} cargo run\n\n"
+        );
+        let gutter = mark(&parsed, &blocks[1].text)
+            .spans()
+            .find(|span| span.content == GUTTER)
+            .map(|span| *span.attr);
+        assert_eq!(
+            gutter,
+            Some(cursive::theme::Style::from(PaletteColor::Highlight))
+        );
+    }
+
+    #[test]
+    fn test_row() {
+        let (parsed, blocks) =
+            markdown::parse_with_blocks("One two three four\n\n    code\n", &[], &[], None);
+        // Wrapped onto two rows, then the blank line between
+        assert_eq!(row(&parsed, blocks[0].text.start, 10), 3);
+        assert_eq!(row(&parsed, blocks[0].text.start, 80), 2);
+        assert_eq!(row(&parsed, 0, 10), 0);
+    }
+}
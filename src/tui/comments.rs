@@ -0,0 +1,418 @@
+//! Comments on answers, fetched on demand and kept for the rest of the session.
+//!
+//! Pressing `c` on an answer opens its comments below its body, fetching them the first time, and
+//! pressing it again closes them. Only one answer's comments load at a time, through a
+//! `loading::Pane`, so opening another answer's comments cancels a fetch nobody waits on anymore.
+//! Fetched comments are kept rendered, so toggling back and forth never fetches them again.
+//! With `prefetch_comments`, the comments on every listed answer are fetched ahead of time, see
+//! `Prefetch`, and opening them takes no request at all.
+
+use cursive::theme::{BaseColor, Color, Effect, Style};
+use cursive::utils::markup::StyledString;
+use futures::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::runtime::Handle;
+
+use super::loading::{self, Pane, Phase, Ticket};
+use super::markdown::{self, Markdown};
+use super::prefetch;
+use crate::error::Result;
+use crate::stackexchange::link_rewrites::LinkRewrites;
+use crate::stackexchange::{Api, Comment};
+
+/// Fetches the comments on an answer
+pub trait Fetch: Send + Sync + 'static {
+    fn fetch(&self, site: &str, answer_id: u32) -> BoxFuture<'static, Result<Vec<Comment>>>;
+}
+
+impl Fetch for Api {
+    fn fetch(&self, site: &str, answer_id: u32) -> BoxFuture<'static, Result<Vec<Comment>>> {
+        let api = self.clone();
+        let site = site.to_string();
+        Box::pin(async move { api.answer_comments(&site, answer_id).await })
+    }
+}
+
+/// Comments from `fetcher`, with their links rewritten as `rewrites` has it
+pub struct Rewritten {
+    pub fetcher: Arc<dyn Fetch>,
+    pub rewrites: LinkRewrites,
+    pub in_code: bool,
+}
+
+impl Fetch for Rewritten {
+    fn fetch(&self, site: &str, answer_id: u32) -> BoxFuture<'static, Result<Vec<Comment>>> {
+        let fetched = self.fetcher.fetch(site, answer_id);
+        let (rewrites, in_code) = (self.rewrites.clone(), self.in_code);
+        Box::pin(async move {
+            let comments = fetched.await?;
+            Ok(comments
+                .into_iter()
+                .map(|c| Comment {
+                    body_markdown: rewrites.body(&c.body_markdown, in_code).into_owned(),
+                    ..c
+                })
+                .collect())
+        })
+    }
+}
+
+/// Where comments come from, and the runtime their fetches run on, since the TUI runs outside of
+/// one
+#[derive(Clone)]
+pub struct Source {
+    pub fetcher: Arc<dyn Fetch>,
+    pub runtime: Handle,
+}
+
+/// Comments fetched so far, as they came, by answer id; shared with `Prefetch`
+pub type Store = Arc<Mutex<HashMap<u32, Vec<Comment>>>>;
+
+/// Comments of the session; without a `Source`, none are fetched
+#[derive(Default)]
+pub struct Comments {
+    source: Option<Source>,
+    /// Answers whose comments are open
+    open: HashSet<u32>,
+    /// Comments fetched so far, rendered, by answer id
+    fetched: HashMap<u32, Markdown>,
+    /// Comments fetched so far, including those fetched ahead of time and not rendered yet
+    store: Store,
+    pane: Pane<Markdown>,
+}
+
+impl Comments {
+    pub fn new(source: Source) -> Self {
+        Comments {
+            source: Some(source),
+            ..Comments::default()
+        }
+    }
+
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Where fetched comments are kept, for `Prefetch` to add to
+    pub fn store(&self) -> Store {
+        Arc::clone(&self.store)
+    }
+
+    /// Open or close the comments on `answer_id`. Returns the load to start if they have to be
+    /// fetched first.
+    pub fn toggle(&mut self, answer_id: u32) -> Option<Ticket> {
+        if self.is_shown(answer_id) {
+            self.open.remove(&answer_id);
+            return None;
+        }
+        self.open.insert(answer_id);
+        if self.fetched.contains_key(&answer_id) {
+            return None;
+        }
+        let prefetched = lock(&self.store).get(&answer_id).map(|c| render(c));
+        if let Some(comments) = prefetched {
+            self.fetched.insert(answer_id, comments);
+            return None;
+        }
+        self.pane.select(answer_id)
+    }
+
+    /// Fetch the comments on `answer_id` again, if that's what failed
+    pub fn retry(&mut self, answer_id: u32) -> Option<Ticket> {
+        match self.pane.phase() {
+            Phase::Failed { id, .. } if *id == answer_id => self.pane.retry(),
+            _ => None,
+        }
+    }
+
+    /// Take the `result` of the load `ticket` was issued for. Returns whether it was taken,
+    /// which it isn't if another load superseded it.
+    pub fn finish(
+        &mut self,
+        ticket: &Ticket,
+        result: std::result::Result<Vec<Comment>, String>,
+    ) -> bool {
+        let comments = result.as_ref().ok().cloned();
+        if !self
+            .pane
+            .finish(ticket, result.map(|comments| render(&comments)))
+        {
+            return false;
+        }
+        // So that they aren't fetched ahead of time again
+        if let Some(comments) = comments {
+            lock(&self.store).insert(ticket.id, comments);
+        }
+        if let Phase::Ready { id, content } = self.pane.phase() {
+            self.fetched.insert(*id, content.clone());
+        }
+        true
+    }
+
+    /// Move the loading indicator on. Returns whether there's still something loading.
+    pub fn tick(&mut self) -> bool {
+        self.pane.tick()
+    }
+
+    /// `body` of the answer `answer_id`, followed by its comments if they're open, or by how
+    /// loading them goes
+    pub fn below(&self, answer_id: u32, body: &Markdown) -> Markdown {
+        if !self.is_shown(answer_id) {
+            return body.clone();
+        }
+        let comments = match self.fetched.get(&answer_id) {
+            Some(comments) => comments.clone(),
+            None => loading::render(&self.pane, "comments").unwrap_or_default(),
+        };
+        let mut content = body.clone();
+        content.append_plain("\n\n");
+        content.append_styled(
+            "Comments",
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Black)),
+                Style::from(Effect::Bold),
+            ]),
+        );
+        content.append_plain("\n\n");
+        content.append(comments);
+        content
+    }
+
+    /// Whether the comments on `answer_id` are open and either fetched or on their way, rather
+    /// than superseded by another answer's while loading
+    fn is_shown(&self, answer_id: u32) -> bool {
+        self.open.contains(&answer_id)
+            && (self.fetched.contains_key(&answer_id)
+                || match self.pane.phase() {
+                    Phase::Loading { id } | Phase::Failed { id, .. } => *id == answer_id,
+                    _ => false,
+                })
+    }
+}
+
+/// Fetches the comments on each answer of a question ahead of time, one answer after another,
+/// for `prefetch::Prefetcher`. Any failure, e.g. a host backed off from, leaves the rest of the
+/// question's answers for when their comments are asked for, which reports it.
+pub struct Prefetch {
+    pub fetcher: Arc<dyn Fetch>,
+    /// Site of each question and its answers' ids, in the order they're listed, by question id
+    pub answers: HashMap<u32, (String, Vec<u32>)>,
+    pub store: Store,
+}
+
+impl prefetch::Fetch for Prefetch {
+    fn fetch(&self, question_id: u32) -> BoxFuture<'static, ()> {
+        let (site, ids) = match self.answers.get(&question_id) {
+            Some(answers) => answers.clone(),
+            None => return Box::pin(async {}),
+        };
+        let fetcher = Arc::clone(&self.fetcher);
+        let store = Arc::clone(&self.store);
+        Box::pin(async move {
+            for id in ids {
+                if lock(&store).contains_key(&id) {
+                    continue;
+                }
+                match fetcher.fetch(&site, id).await {
+                    Ok(comments) => {
+                        lock(&store).insert(id, comments);
+                    }
+                    Err(_) => return,
+                }
+            }
+        })
+    }
+}
+
+fn lock(store: &Store) -> MutexGuard<'_, HashMap<u32, Vec<Comment>>> {
+    store.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `comments` as they're shown below their answer: a dim line with the score and author of each,
+/// followed by its body
+fn render(comments: &[Comment]) -> Markdown {
+    let dim = Style::from(Color::Light(BaseColor::Black));
+    if comments.is_empty() {
+        return StyledString::styled(
+            "No comments",
+            Style::merge(&[dim, Style::from(Effect::Italic)]),
+        );
+    }
+    let mut rendered = StyledString::new();
+    for (i, comment) in comments.iter().enumerate() {
+        if i > 0 {
+            rendered.append_plain("\n\n");
+        }
+        rendered.append_styled(
+            format!("({}) {}", comment.score, comment.owner_display_name),
+            dim,
+        );
+        rendered.append_plain("\n");
+        rendered.append(markdown::parse(comment.body_markdown.as_str()));
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(score: i32, body: &str, owner: &str) -> Comment {
+        Comment {
+            score,
+            body_markdown: body.to_string(),
+            owner_display_name: owner.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_toggle() {
+        let body = markdown::parse("Use `:q!`");
+        let mut comments = Comments::default();
+        let ticket = comments.toggle(1).unwrap();
+        assert_eq!(
+            comments.below(1, &body).source(),
+            "Use `:q!`\n\nComments\n\nLoading   "
+        );
+        assert!(comments.finish(
+            &ticket,
+            Ok(vec![
+                comment(3, "No longer works on v3, use `X`", "jane"),
+                comment(0, "Thanks!", "joe"),
+            ])
+        ));
+        let open =
+            "Use `:q!`\n\nComments\n\n(3) jane\nNo longer works on v3, use `X`\n\n(0) joe\nThanks!";
+        assert_eq!(comments.below(1, &body).source(), open);
+
+        // Closing and opening again doesn't fetch them again
+        assert!(comments.toggle(1).is_none());
+        assert_eq!(comments.below(1, &body), body);
+        assert!(comments.toggle(1).is_none());
+        assert_eq!(comments.below(1, &body).source(), open);
+    }
+
+    /// The same comment on every answer, linking in and out of a code span
+    struct Linking;
+
+    impl Fetch for Linking {
+        fn fetch(&self, _site: &str, _answer_id: u32) -> BoxFuture<'static, Result<Vec<Comment>>> {
+            let body = "See https://stackoverflow.com/a/1 or `curl https://stackoverflow.com`";
+            Box::pin(async move { Ok(vec![comment(1, body, "jane")]) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rewritten() {
+        let rewritten = |in_code| Rewritten {
+            fetcher: Arc::new(Linking),
+            rewrites: serde_yaml::from_str("stackoverflow.com: so.corp").unwrap(),
+            in_code,
+        };
+        let comments = rewritten(false).fetch("stackoverflow", 1).await.unwrap();
+        assert_eq!(
+            comments[0].body_markdown,
+            "See https://so.corp/a/1 or `curl https://stackoverflow.com`"
+        );
+        let comments = rewritten(true).fetch("stackoverflow", 1).await.unwrap();
+        assert_eq!(
+            comments[0].body_markdown,
+            "See https://so.corp/a/1 or `curl https://so.corp`"
+        );
+    }
+
+    /// Comments on answers up to 19, and a failure for any other, counting every fetch
+    #[derive(Default)]
+    struct Counting(std::sync::atomic::AtomicUsize);
+
+    impl Fetch for Counting {
+        fn fetch(&self, _site: &str, answer_id: u32) -> BoxFuture<'static, Result<Vec<Comment>>> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async move {
+                match answer_id {
+                    0..=19 => Ok(vec![comment(1, &format!("On {}", answer_id), "ann")]),
+                    _ => Err(crate::error::Error::NoResults),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        use prefetch::Fetch as _;
+
+        let body = markdown::parse("Use `:q!`");
+        let fetcher = Arc::new(Counting::default());
+        let mut comments = Comments::default();
+        let answers = vec![
+            (1, (String::from("vi"), vec![10, 11, 12])),
+            (2, (String::from("vi"), vec![20, 13])),
+        ];
+        let prefetch = Prefetch {
+            fetcher: fetcher.clone(),
+            answers: answers.into_iter().collect(),
+            store: comments.store(),
+        };
+        // Fetched on demand already, so not again
+        let ticket = comments.toggle(11).unwrap();
+        assert!(comments.finish(&ticket, Ok(vec![comment(2, "Mine", "joe")])));
+        prefetch.fetch(1).await;
+        assert_eq!(fetcher.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Shown without a fetch
+        assert!(comments.toggle(12).is_none());
+        assert_eq!(
+            comments.below(12, &body).source(),
+            "Use `:q!`\n\nComments\n\n(1) ann\nOn 12"
+        );
+
+        // A failure leaves the rest of the question's answers alone
+        prefetch.fetch(2).await;
+        assert_eq!(fetcher.0.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert!(comments.toggle(13).is_some());
+
+        // As does an unknown question
+        prefetch.fetch(3).await;
+        assert_eq!(fetcher.0.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_superseded() {
+        let body = markdown::parse("Use `:q!`");
+        let mut comments = Comments::default();
+        let first = comments.toggle(1).unwrap();
+        let second = comments.toggle(2).unwrap();
+        assert!(first.token().is_cancelled());
+        // The first answer's comments aren't coming anymore, so opening them starts over
+        assert_eq!(comments.below(1, &body), body);
+        assert!(!comments.finish(&first, Ok(vec![])));
+        let again = comments.toggle(1).unwrap();
+        assert!(second.token().is_cancelled());
+        assert!(comments.finish(&again, Ok(vec![])));
+        assert_eq!(
+            comments.below(1, &body).source(),
+            "Use `:q!`\n\nComments\n\nNo comments"
+        );
+    }
+
+    #[test]
+    fn test_failure_and_retry() {
+        let body = markdown::parse("Use `:q!`");
+        let mut comments = Comments::default();
+        assert!(comments.retry(1).is_none());
+        let ticket = comments.toggle(1).unwrap();
+        assert!(comments.finish(&ticket, Err(String::from("offline"))));
+        assert_eq!(
+            comments.below(1, &body).source(),
+            "Use `:q!`\n\nComments\n\nCouldn't load comments: offline\n\nPress r to retry"
+        );
+        assert!(comments.retry(2).is_none());
+        let retry = comments.retry(1).unwrap();
+        assert!(comments.finish(&retry, Ok(vec![comment(1, "Works", "ann")])));
+        assert_eq!(
+            comments.below(1, &body).source(),
+            "Use `:q!`\n\nComments\n\n(1) ann\nWorks"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Message console listing the warnings and errors collected during the session, toggled with
+//! `!`, and the status bar badge counting the ones not seen yet.
+
+use cursive::align::HAlign;
+use cursive::theme::{BaseColor, Color, Effect, Style};
+use cursive::traits::{Nameable, Scrollable};
+use cursive::utils::markup::StyledString;
+use cursive::views::{Dialog, NamedView, TextView};
+use cursive::Cursive;
+
+use crate::format;
+use crate::messages::{Message, Messages, Severity};
+
+pub const NAME_CONSOLE_VIEW: &str = "console_view";
+pub const NAME_STATUS_BAR: &str = "status_bar";
+
+/// Status bar, showing the badge of `messages`
+pub fn status_bar(messages: &Messages) -> NamedView<TextView> {
+    TextView::new(badge(messages))
+        .h_align(HAlign::Right)
+        .with_name(NAME_STATUS_BAR)
+}
+
+/// Update the status bar badge, e.g. after messages came in or were read
+pub fn refresh_badge(s: &mut Cursive, messages: &Messages) {
+    let badge = badge(messages);
+    s.call_on_name(NAME_STATUS_BAR, |v: &mut TextView| {
+        if v.get_content().source() != badge.source() {
+            v.set_content(badge)
+        }
+    });
+}
+
+/// Show the console, marking everything in it as read, or hide it if it's showing
+pub fn toggle(s: &mut Cursive, messages: &Messages) {
+    if let Some(pos) = s.screen_mut().find_layer_from_name(NAME_CONSOLE_VIEW) {
+        s.screen_mut().remove_layer(pos);
+    } else {
+        s.add_layer(
+            Dialog::around(
+                TextView::new(render(&messages.read()))
+                    .scrollable()
+                    .with_name(NAME_CONSOLE_VIEW),
+            )
+            .dismiss_button("Close")
+            .title("Messages"),
+        );
+    }
+    refresh_badge(s, messages);
+}
+
+/// "⚠ 3" in the color of the worst unread message, or nothing if everything was read
+fn badge(messages: &Messages) -> StyledString {
+    match messages.unread() {
+        Some((count, severity)) => StyledString::styled(
+            format!("\u{26a0} {} (press ! to view)", count),
+            Style::merge(&[Style::from(color(severity)), Style::from(Effect::Bold)]),
+        ),
+        None => StyledString::new(),
+    }
+}
+
+/// One line per message, oldest first
+fn render(messages: &[Message]) -> StyledString {
+    if messages.is_empty() {
+        return StyledString::styled(
+            "No warnings or errors so far",
+            Style::merge(&[
+                Style::from(Color::Light(BaseColor::Black)),
+                Style::from(Effect::Italic),
+            ]),
+        );
+    }
+    let mut content = StyledString::new();
+    for (i, message) in messages.iter().enumerate() {
+        if i > 0 {
+            content.append_plain("\n");
+        }
+        content.append_styled(
+            format!("{} ", format::rfc3339(message.at)),
+            Color::Light(BaseColor::Black),
+        );
+        let label = match message.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        content.append_styled(
+            format!("{}: ", label),
+            Style::merge(&[
+                Style::from(color(message.severity)),
+                Style::from(Effect::Bold),
+            ]),
+        );
+        content.append_plain(&message.text);
+    }
+    content
+}
+
+fn color(severity: Severity) -> Color {
+    match severity {
+        Severity::Warning => Color::Light(BaseColor::Yellow),
+        Severity::Error => Color::Light(BaseColor::Red),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_badge() {
+        let messages = Messages::default();
+        assert_eq!(badge(&messages).source(), "");
+        messages.warn("results are partial");
+        messages.error("parsing failed for question 1");
+        let badge = badge(&messages);
+        assert_eq!(badge.source(), "\u{26a0} 2 (press ! to view)");
+        let span = badge.spans().next().unwrap();
+        assert_eq!(
+            span.attr.color.map(|c| c.front),
+            Some(Color::Light(BaseColor::Red).into())
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let messages = Messages::default();
+        messages.warn("results are partial");
+        messages.error("parsing failed for question 1");
+        let content = render(&messages.read());
+        let lines = content.source().lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("Z warning: results are partial"));
+        assert!(lines[1].ends_with("Z error: parsing failed for question 1"));
+        assert!(render(&[]).source().starts_with("No warnings"));
+    }
+}
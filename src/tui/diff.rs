@@ -0,0 +1,317 @@
+//! Diffs between the code of two answers, for comparing answers that propose slightly different
+//! versions of the same snippet, or between a local file and the code of an answer. Prose is left
+//! out; only code blocks are compared.
+
+use cursive::theme::{BaseColor, Color, Effect, Style};
+use cursive::utils::markup::StyledString;
+use similar::{ChangeTag, TextDiff};
+
+/// Blocks less similar than this (0 to 1) aren't paired up with each other
+const MIN_SIMILARITY: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Same,
+    Added,
+    Removed,
+    /// Start of the diff of another pair of blocks
+    Header,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub kind: Kind,
+    pub text: String,
+}
+
+/// Pair up the code blocks of two answers to diff against each other.
+///
+/// Blocks are matched greedily, most similar first, and listed in the order of `base`, followed
+/// by the blocks of `other` left without a match; an empty string stands in for a missing block.
+/// If no two blocks are similar enough to pair, all the code of each answer is diffed as one.
+pub fn pair_blocks(base: &[String], other: &[String]) -> Vec<(String, String)> {
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for (i, b) in base.iter().enumerate() {
+        for (j, o) in other.iter().enumerate() {
+            let ratio = TextDiff::from_chars(b.as_str(), o.as_str()).ratio();
+            if ratio >= MIN_SIMILARITY {
+                candidates.push((ratio, i, j));
+            }
+        }
+    }
+    if candidates.is_empty() {
+        return if base.is_empty() && other.is_empty() {
+            Vec::new()
+        } else {
+            vec![(base.concat(), other.concat())]
+        };
+    }
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    let mut matches: Vec<Option<usize>> = vec![None; base.len()];
+    let mut matched = vec![false; other.len()];
+    for (_, i, j) in candidates {
+        if matches[i].is_none() && !matched[j] {
+            matches[i] = Some(j);
+            matched[j] = true;
+        }
+    }
+    let mut pairs: Vec<(String, String)> = base
+        .iter()
+        .zip(matches)
+        .map(|(b, j)| (b.clone(), j.map(|j| other[j].clone()).unwrap_or_default()))
+        .collect();
+    pairs.extend(
+        other
+            .iter()
+            .zip(matched)
+            .filter(|(_, matched)| !matched)
+            .map(|(o, _)| (String::new(), o.clone())),
+    );
+    pairs
+}
+
+/// Line diff of the code blocks of two answers, pairing blocks with `pair_blocks`
+pub fn diff(base: &[String], other: &[String]) -> Vec<Line> {
+    let pairs = pair_blocks(base, other);
+    let mut lines = Vec::new();
+    let count = pairs.len();
+    for (n, (old, new)) in pairs.iter().enumerate() {
+        if count > 1 {
+            lines.push(Line {
+                kind: Kind::Header,
+                text: format!("code block {} of {}", n + 1, count),
+            });
+        }
+        diff_lines(old, new, &mut lines);
+    }
+    lines
+}
+
+/// Index of the code block to compare a local file against by default: the one with the most
+/// lines, or the first of those
+pub fn largest_block(blocks: &[String]) -> Option<usize> {
+    blocks
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, block)| (block.lines().count(), block.len()))
+        .map(|(i, _)| i)
+}
+
+/// Line diff of the contents of a local file against a snippet. Line endings and a missing
+/// newline at the end don't count as differences.
+pub fn diff_file(local: &str, snippet: &str) -> Vec<Line> {
+    let normalize = |text: &str| {
+        let mut text = text.replace("\r\n", "\n");
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    };
+    let mut lines = Vec::new();
+    diff_lines(&normalize(local), &normalize(snippet), &mut lines);
+    lines
+}
+
+fn diff_lines(old: &str, new: &str, lines: &mut Vec<Line>) {
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        let kind = match change.tag() {
+            ChangeTag::Equal => Kind::Same,
+            ChangeTag::Insert => Kind::Added,
+            ChangeTag::Delete => Kind::Removed,
+        };
+        lines.push(Line {
+            kind,
+            text: change.value().trim_end_matches('\n').to_string(),
+        });
+    }
+}
+
+/// Unified diff, with additions in green and removals in red
+pub fn render(lines: &[Line]) -> StyledString {
+    let mut out = StyledString::new();
+    for line in lines {
+        match line.kind {
+            Kind::Same => out.append_plain(format!("  {}\n", line.text)),
+            Kind::Added => {
+                out.append_styled(format!("+ {}\n", line.text), Color::Light(BaseColor::Green))
+            }
+            Kind::Removed => {
+                out.append_styled(format!("- {}\n", line.text), Color::Light(BaseColor::Red))
+            }
+            Kind::Header => out.append_styled(
+                format!("@@ {} @@\n", line.text),
+                Style::merge(&[
+                    Style::from(Color::Light(BaseColor::Cyan)),
+                    Style::from(Effect::Bold),
+                ]),
+            ),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursive::theme::ColorType;
+
+    fn blocks(blocks: &[&str]) -> Vec<String> {
+        blocks.iter().map(|b| b.to_string()).collect()
+    }
+
+    #[test]
+    fn test_pair_blocks_by_similarity() {
+        let base = blocks(&[
+            "git reset --hard HEAD~1\ngit push -f\n",
+            "cargo build --release\n",
+        ]);
+        let other = blocks(&[
+            "cargo build --release --locked\n",
+            "brew install vim\n",
+            "git reset --soft HEAD~1\ngit push -f\n",
+        ]);
+        assert_eq!(
+            pair_blocks(&base, &other),
+            vec![
+                (base[0].clone(), other[2].clone()),
+                (base[1].clone(), other[0].clone()),
+                (String::new(), other[1].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pair_blocks_falls_back_to_all_code() {
+        let base = blocks(&["ls -la", "pwd"]);
+        let other = blocks(&["cargo", "bun"]);
+        assert_eq!(
+            pair_blocks(&base, &other),
+            vec![(String::from("ls -lapwd"), String::from("cargobun"))]
+        );
+        assert_eq!(
+            pair_blocks(&base, &[]),
+            vec![(String::from("ls -lapwd"), String::new())]
+        );
+        assert!(pair_blocks(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let base = blocks(&["git reset --hard HEAD~1\ngit push -f\n"]);
+        let other = blocks(&["git reset --soft HEAD~1\ngit push -f\n"]);
+        let lines = diff(&base, &other);
+        let lines: Vec<(Kind, &str)> = lines.iter().map(|l| (l.kind, l.text.as_str())).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Kind::Removed, "git reset --hard HEAD~1"),
+                (Kind::Added, "git reset --soft HEAD~1"),
+                (Kind::Same, "git push -f"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_headers() {
+        let base = blocks(&["cargo build\n", "cargo test\n"]);
+        let other = blocks(&["cargo build\n", "cargo test --all\n"]);
+        let headers: Vec<String> = diff(&base, &other)
+            .into_iter()
+            .filter(|l| l.kind == Kind::Header)
+            .map(|l| l.text)
+            .collect();
+        assert_eq!(headers, vec!["code block 1 of 2", "code block 2 of 2"]);
+    }
+
+    #[test]
+    fn test_largest_block() {
+        assert_eq!(largest_block(&[]), None);
+        let code = blocks(&[
+            "ls
+",
+            "set number
+syntax on
+",
+            "set ruler
+set hls
+",
+            "x
+",
+        ]);
+        // Ties go to the longer block, then the first one
+        assert_eq!(largest_block(&code), Some(1));
+        let code = blocks(&[
+            "set ruler
+set hls
+",
+            "set ruler
+set is
+",
+        ]);
+        assert_eq!(largest_block(&code), Some(0));
+    }
+
+    #[test]
+    fn test_diff_file() {
+        let local = "set number\r\nset hls\r\nsyntax on";
+        let snippet = "set number\nset nohls\nsyntax on\n";
+        let lines = diff_file(local, snippet);
+        let lines: Vec<(Kind, &str)> = lines.iter().map(|l| (l.kind, l.text.as_str())).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Kind::Same, "set number"),
+                (Kind::Removed, "set hls"),
+                (Kind::Added, "set nohls"),
+                (Kind::Same, "syntax on"),
+            ]
+        );
+        assert!(diff_file("", "").is_empty());
+        assert!(diff_file("ls", "ls\n").iter().all(|l| l.kind == Kind::Same));
+        assert_eq!(diff_file("", "ls\n")[0].kind, Kind::Added);
+    }
+
+    #[test]
+    fn test_render() {
+        let lines = vec![
+            Line {
+                kind: Kind::Header,
+                text: String::from("code block 1 of 2"),
+            },
+            Line {
+                kind: Kind::Removed,
+                text: String::from("old"),
+            },
+            Line {
+                kind: Kind::Added,
+                text: String::from("new"),
+            },
+            Line {
+                kind: Kind::Same,
+                text: String::from("same"),
+            },
+        ];
+        let rendered = render(&lines);
+        assert_eq!(
+            rendered.source(),
+            "@@ code block 1 of 2 @@\n- old\n+ new\n  same\n"
+        );
+        let colors: Vec<Option<Color>> = rendered
+            .spans()
+            .map(|s| match s.attr.color.map(|c| c.front) {
+                Some(ColorType::Color(c)) => Some(c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            colors,
+            vec![
+                Some(Color::Light(BaseColor::Cyan)),
+                Some(Color::Light(BaseColor::Red)),
+                Some(Color::Light(BaseColor::Green)),
+                None,
+            ]
+        );
+    }
+}
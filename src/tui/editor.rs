@@ -0,0 +1,258 @@
+//! Editing the code of an answer in `$EDITOR`, to adapt a snippet before using it.
+//!
+//! `E` writes a code block of the selected answer to a file of its own in the temp directory,
+//! named after the process and a counter and with the extension of the block's language, so that
+//! the editor highlights it. The TUI steps aside while the editor runs, the way it does to draw
+//! images, and the file is removed as soon as its contents are read back, whichever way editing
+//! ends.
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Error, Result};
+use crate::stackexchange::preprocess;
+use crate::utils;
+
+/// Extension of the files of code whose language isn't known
+const PLAIN: &str = "txt";
+
+/// Extensions of languages, by the names fences, `lang-` hints and tags go by
+const EXTENSIONS: &[(&[&str], &str)] = &[
+    (&["rust", "rs"], "rs"),
+    (&["python", "py", "python3", "python2"], "py"),
+    (&["javascript", "js", "node.js", "nodejs", "node"], "js"),
+    (&["typescript", "ts"], "ts"),
+    (&["jsx"], "jsx"),
+    (&["tsx"], "tsx"),
+    (&["bash", "sh", "shell", "zsh", "console"], "sh"),
+    (&["fish"], "fish"),
+    (&["powershell", "ps1", "pwsh"], "ps1"),
+    (&["batch-file", "bat", "cmd"], "bat"),
+    (&["c"], "c"),
+    (&["c++", "cpp", "cxx"], "cpp"),
+    (&["c#", "csharp", "cs"], "cs"),
+    (&["java"], "java"),
+    (&["kotlin", "kt"], "kt"),
+    (&["scala"], "scala"),
+    (&["go", "golang"], "go"),
+    (&["ruby", "rb"], "rb"),
+    (&["php"], "php"),
+    (&["perl", "pl"], "pl"),
+    (&["lua"], "lua"),
+    (&["r"], "r"),
+    (&["swift"], "swift"),
+    (&["objective-c", "objc"], "m"),
+    (&["haskell", "hs"], "hs"),
+    (&["elixir", "ex"], "ex"),
+    (&["erlang", "erl"], "erl"),
+    (&["clojure", "clj"], "clj"),
+    (&["vim", "vimscript", "viml"], "vim"),
+    (&["sql", "mysql", "postgresql", "sqlite"], "sql"),
+    (&["html", "html5", "xhtml"], "html"),
+    (&["css", "css3"], "css"),
+    (&["scss", "sass"], "scss"),
+    (&["xml", "xaml"], "xml"),
+    (&["json"], "json"),
+    (&["yaml", "yml"], "yaml"),
+    (&["toml"], "toml"),
+    (&["ini"], "ini"),
+    (&["makefile", "make"], "mk"),
+    (&["dockerfile", "docker"], "dockerfile"),
+    (&["diff", "patch"], "diff"),
+    (&["markdown", "md"], "md"),
+    (&["latex", "tex"], "tex"),
+];
+
+/// Files written this session so far, so that no two get the same name
+static WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Extension of the language `name`, as in fences, `lang-` hints and tags, if it's in `EXTENSIONS`
+fn find(name: &str) -> Option<&'static str> {
+    let name = name.trim().to_lowercase();
+    let name = name.strip_prefix("lang-").unwrap_or(&name);
+    EXTENSIONS
+        .iter()
+        .find(|(names, _)| names.contains(&name))
+        .map(|(_, extension)| *extension)
+}
+
+/// Extension of a code block in `language`, else of the first of `tags` that is a language, or
+/// else `txt`. Versioned tags such as `python-3.x` count as their language.
+pub fn extension(language: Option<&str>, tags: &[String]) -> &'static str {
+    language
+        .and_then(find)
+        .or_else(|| {
+            tags.iter()
+                .find_map(|tag| find(tag).or_else(|| tag.split('-').next().and_then(find)))
+        })
+        .unwrap_or(PLAIN)
+}
+
+/// Language of each code block of `md`, in the order of `snippets::code_blocks`: the one its
+/// fence names, else the one a `<!-- language: -->` hint before it names
+pub fn languages(md: &str) -> Vec<Option<String>> {
+    let mut languages = Vec::new();
+    let mut next = None;
+    let mut all = None;
+    for event in Parser::new(md) {
+        match event {
+            Event::Html(html) => match preprocess::language_hint(&html) {
+                Some((language, true)) => all = Some(language.to_string()),
+                Some((language, false)) => next = Some(language.to_string()),
+                None => (),
+            },
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let hint = next.take().or_else(|| all.clone());
+                let fence = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().map(str::to_string)
+                    }
+                    CodeBlockKind::Indented => None,
+                };
+                languages.push(fence.or(hint));
+            }
+            _ => (),
+        }
+    }
+    languages
+}
+
+/// A file of code to edit, removed once it's dropped
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Write `code` to a new file in `dir`, with `extension`
+    pub fn create(dir: &Path, code: &str, extension: &str) -> Result<Self> {
+        loop {
+            let n = WRITTEN.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("so-{}-{}.{}", std::process::id(), n, extension));
+            // Never one left over from an earlier run with the same process id
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let temp = TempFile { path };
+                    file.write_all(code.as_bytes())?;
+                    return Ok(temp);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The code as it is now
+    pub fn read(&self) -> Result<String> {
+        Ok(fs::read_to_string(&self.path)?)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Program and arguments `$EDITOR`, as `var` has it, stands for, e.g. `code --wait`
+fn command<F>(var: F) -> Result<Vec<String>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let editor: Vec<String> = var("EDITOR")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    match editor.is_empty() {
+        true => Err(Error::NoEditor),
+        false => Ok(editor),
+    }
+}
+
+/// Edit `code` in `$EDITOR` as a file with `extension`, returning the code as the editor left it.
+/// Expects to have the terminal to itself.
+pub fn edit(code: &str, extension: &str) -> Result<String> {
+    let editor = command(|var| std::env::var(var).ok())?;
+    let file = TempFile::create(&std::env::temp_dir(), code, extension)?;
+    utils::run(Command::new(&editor[0]).args(&editor[1..]).arg(file.path()))
+        .map_err(Error::Editor)?;
+    file.read()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension(Some("rust"), &[]), "rs");
+        assert_eq!(extension(Some("lang-py"), &tags(&["rust"])), "py");
+        assert_eq!(extension(Some("C++"), &[]), "cpp");
+        assert_eq!(extension(None, &tags(&["linux", "python-3.x"])), "py");
+        assert_eq!(extension(Some("brainfuck"), &tags(&["bash"])), "sh");
+        assert_eq!(extension(None, &tags(&["linux"])), "txt");
+        assert_eq!(extension(None, &[]), "txt");
+    }
+
+    #[test]
+    fn test_languages() {
+        let md = "```rust\nfn main() {}\n```\n\n\
+                  <!-- language: lang-js -->\n\n    let a = 1;\n\n\
+                  <!-- language-all: lang-sh -->\n\n    ls\n\n\
+                  ```py\nx = 1\n```\n\n    echo";
+        assert_eq!(
+            languages(md),
+            vec![
+                Some(String::from("rust")),
+                Some(String::from("lang-js")),
+                Some(String::from("lang-sh")),
+                Some(String::from("py")),
+                Some(String::from("lang-sh")),
+            ]
+        );
+        assert_eq!(languages("    plain"), vec![None]);
+        assert!(languages("no code").is_empty());
+    }
+
+    #[test]
+    fn test_temp_file() {
+        let dir = std::env::temp_dir();
+        let file = TempFile::create(&dir, "fn main() {}\n", "rs").unwrap();
+        let other = TempFile::create(&dir, "", "rs").unwrap();
+        assert_ne!(file.path(), other.path());
+        assert_eq!(file.path().extension().unwrap(), "rs");
+        assert_eq!(file.read().unwrap(), "fn main() {}\n");
+        fs::write(file.path(), "fn main() { edited() }\n").unwrap();
+        assert_eq!(file.read().unwrap(), "fn main() { edited() }\n");
+        // Gone once dropped
+        let path = file.path().to_path_buf();
+        drop(file);
+        assert!(!path.exists());
+        assert!(other.path().exists());
+    }
+
+    #[test]
+    fn test_command() {
+        let editor = |value: Option<&str>| command(|_| value.map(str::to_string));
+        assert_eq!(editor(Some("vim")).unwrap(), vec!["vim"]);
+        assert_eq!(
+            editor(Some(" code  --wait ")).unwrap(),
+            vec!["code", "--wait"]
+        );
+        assert!(matches!(editor(None), Err(Error::NoEditor)));
+        assert!(matches!(editor(Some("  ")), Err(Error::NoEditor)));
+    }
+}
@@ -0,0 +1,210 @@
+//! Fuzzy matching of the question list filter against titles.
+//!
+//! A title matches if it has every character of the query in order, ignoring case and whitespace
+//! in the query. Matches score higher the more of them start words or follow one another, and the
+//! less of the title they skip, so that typing the first letters of a few words finds the title
+//! made of them.
+
+use std::cmp::Reverse;
+
+/// Score of each character matched
+const MATCH: i64 = 16;
+/// Bonus for matching the first character of a word
+const WORD_START: i64 = 8;
+/// Bonus for matching right after the previous match
+const CONSECUTIVE: i64 = 8;
+/// Penalty per character skipped between two matches, up to `MAX_GAP` of them
+const GAP: i64 = 1;
+const MAX_GAP: i64 = 8;
+
+/// How well a title matched, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    /// Positions of the matched characters in the title, in chars
+    pub positions: Vec<usize>,
+}
+
+/// How well `text` matches `query`, if at all; every text matches an empty query
+///
+/// Of all the ways to match, this finds the one that scores best, so that "vim" matches the word
+/// "Vim" in "Move to Vim" rather than the "v" of "Move". Where two ways score the same, the one
+/// matching earlier wins.
+pub fn score(query: &str, text: &str) -> Option<Match> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    let starts: Vec<bool> = text
+        .chars()
+        .scan(None, |previous: &mut Option<char>, c| {
+            let start = !previous.is_some_and(char::is_alphanumeric);
+            *previous = Some(c);
+            Some(start)
+        })
+        .collect();
+    let text: Vec<char> = text.chars().map(fold).collect();
+    if query.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: vec![],
+        });
+    }
+    let max_gap = MAX_GAP as usize;
+    // Best score of matching the query up to its `k`th char with that char at `i`, and where the
+    // char before it matched, by `[k][i]`
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; text.len()]; query.len()];
+    for (k, q) in query.iter().enumerate() {
+        // Best match of the previous char far enough back for the gap penalty to be maxed out
+        let mut far: Option<(i64, usize)> = None;
+        for i in 0..text.len() {
+            if k > 0 && i > max_gap {
+                let j = i - 1 - max_gap;
+                if let Some((score, _)) = best[k - 1][j] {
+                    if far.is_none_or(|(top, _)| score > top) {
+                        far = Some((score, j));
+                    }
+                }
+            }
+            if text[i] != *q {
+                continue;
+            }
+            let own = MATCH + if starts[i] { WORD_START } else { 0 };
+            if k == 0 {
+                best[k][i] = Some((own, 0));
+                continue;
+            }
+            let near = (i.saturating_sub(max_gap)..i).filter_map(|j| {
+                let (score, _) = best[k - 1][j]?;
+                let gap = (i - j - 1) as i64;
+                let transition = if gap == 0 { CONSECUTIVE } else { -GAP * gap };
+                Some((score + transition, j))
+            });
+            let far = far.map(|(score, j)| (score - GAP * MAX_GAP, j));
+            // In order of position, so only a better score replaces an earlier match
+            let mut top: Option<(i64, usize)> = None;
+            for (score, j) in far.into_iter().chain(near) {
+                if top.is_none_or(|(best, _)| score > best) {
+                    top = Some((score, j));
+                }
+            }
+            best[k][i] = top.map(|(score, j)| (score + own, j));
+        }
+    }
+    let mut end: Option<(i64, usize)> = None;
+    for (i, b) in best[query.len() - 1].iter().enumerate() {
+        if let Some((score, _)) = *b {
+            if end.is_none_or(|(top, _)| score > top) {
+                end = Some((score, i));
+            }
+        }
+    }
+    let (score, mut i) = end?;
+    let mut positions = vec![0; query.len()];
+    for k in (0..query.len()).rev() {
+        positions[k] = i;
+        i = best[k][i].map(|(_, j)| j).unwrap_or_default();
+    }
+    Some(Match { score, positions })
+}
+
+/// Which of `titles` match `query`, by index, best first; titles that match equally well keep
+/// their order, and an empty query matches all of them as they are
+pub fn visible<'a, I>(query: &str, titles: I) -> Vec<(usize, Match)>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut matches: Vec<(usize, Match)> = titles
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, title)| score(query, title).map(|m| (i, m)))
+        .collect();
+    matches.sort_by_key(|(_, m)| Reverse(m.score));
+    matches
+}
+
+/// Case folding good enough for matching by hand: the first char of the lowercase form
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TITLES: &[&str] = &[
+        "How do I exit the Vim editor?",
+        "Quit vi without saving changes",
+        "Exit Vim from a script",
+    ];
+
+    fn ids(query: &str) -> Vec<usize> {
+        visible(query, TITLES.iter().copied())
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn test_score() {
+        let m = score("vim", "Exit Vim from a script").unwrap();
+        assert_eq!(m.positions, vec![5, 6, 7]);
+        assert_eq!(m.score, 3 * MATCH + WORD_START + 2 * CONSECUTIVE);
+        // Not in order
+        assert_eq!(score("miv", "Exit Vim from a script"), None);
+        assert_eq!(score("vimx", "Vim"), None);
+        // Whitespace in the query is only there to read it
+        assert_eq!(
+            score("e v", "Exit Vim").unwrap().positions,
+            score("ev", "Exit Vim").unwrap().positions
+        );
+    }
+
+    #[test]
+    fn test_best_match() {
+        // Leftmost, the "v" of "move" would be taken
+        let m = score("vim", "Move to Vim").unwrap();
+        assert_eq!(m.positions, vec![8, 9, 10]);
+        // Word starts over scattered letters
+        let m = score("evs", "Exit Vim from a script").unwrap();
+        assert_eq!(m.positions, vec![0, 5, 16]);
+        // The "t" of "exit" ties with the "t" of "the", and comes first
+        let m = score("exitvim", "How do I exit the Vim editor?").unwrap();
+        assert_eq!(m.positions, vec![9, 10, 11, 12, 18, 19, 20]);
+    }
+
+    #[test]
+    fn test_visible() {
+        // Everything, as listed
+        assert_eq!(ids(""), vec![0, 1, 2]);
+        assert_eq!(ids("   "), vec![0, 1, 2]);
+        assert!(visible("", TITLES.iter().copied())
+            .iter()
+            .all(|(_, m)| m.positions.is_empty()));
+        // Best first
+        assert_eq!(ids("exit vim"), vec![2, 0]);
+        assert_eq!(ids("quit"), vec![1]);
+        // Ties keep their order
+        assert_eq!(ids("i"), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_no_match() {
+        assert!(ids("emacs").is_empty());
+        assert!(visible("vim", Vec::<&str>::new()).is_empty());
+    }
+
+    #[test]
+    fn test_unicode() {
+        // Positions are in chars, not bytes
+        let m = score("über", "Nachrichten ÜBER Vim").unwrap();
+        assert_eq!(m.positions, vec![12, 13, 14, 15]);
+        let m = score("ß", "Straße").unwrap();
+        assert_eq!(m.positions, vec![4]);
+        let m = score("vim 終了", "Vimを終了する方法").unwrap();
+        assert_eq!(m.positions, vec![0, 1, 2, 4, 5]);
+        assert_eq!(score("ñ", "Espana"), None);
+        assert!(score("🦀", "Rust 🦀 lifetimes").is_some());
+    }
+}
@@ -0,0 +1,203 @@
+//! Previewing the images of an answer inline, in terminals that speak a graphics protocol.
+//!
+//! Cursive has no notion of images, so previews are shown with the TUI suspended: the images are
+//! downloaded and written straight to the terminal, one after the other, until the user goes
+//! back. Terminals without a protocol `so` speaks get the image links listed instead.
+
+use std::io::{self, Write};
+use tokio::runtime::Runtime;
+
+use super::markdown::Image;
+use crate::config::ImagePreview;
+use crate::error::Result;
+use crate::stackexchange::images::ImageCache;
+
+/// Kitty takes base64 data in chunks of at most this many bytes
+const KITTY_CHUNK: usize = 4096;
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Graphics protocol to write images to the terminal with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// The kitty graphics protocol, which takes PNGs
+    Kitty,
+    /// iTerm2 inline images, which takes any format macOS can read; WezTerm speaks it too
+    Iterm,
+}
+
+/// Resolve the `image_preview` setting, looking up environment variables with `var` for `auto`;
+/// `None` means the links are listed instead
+pub fn protocol<F>(setting: ImagePreview, var: F) -> Option<Protocol>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match setting {
+        ImagePreview::Kitty => Some(Protocol::Kitty),
+        ImagePreview::Iterm => Some(Protocol::Iterm),
+        ImagePreview::Sixel | ImagePreview::Off => None,
+        ImagePreview::Auto => {
+            if var("KITTY_WINDOW_ID").is_some() || var("TERM").as_deref() == Some("xterm-kitty") {
+                return Some(Protocol::Kitty);
+            }
+            // Inside tmux or screen, this is tmux or screen, which pass neither protocol through
+            match var("TERM_PROGRAM").as_deref() {
+                Some("iTerm.app") | Some("WezTerm") => Some(Protocol::Iterm),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Escape sequences drawing `image` with `protocol`, or `None` if the protocol can't draw its
+/// format
+pub fn encode(protocol: Protocol, image: &[u8]) -> Option<Vec<u8>> {
+    if image.is_empty() {
+        return None;
+    }
+    match protocol {
+        Protocol::Kitty if image.starts_with(PNG_MAGIC) => Some(kitty(image)),
+        Protocol::Kitty => None,
+        Protocol::Iterm => Some(iterm(image)),
+    }
+}
+
+/// Transmit and display a PNG, in as many chunks as it takes
+fn kitty(png: &[u8]) -> Vec<u8> {
+    let data = base64::encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = Vec::with_capacity(data.len() + chunks.len() * 32);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            out.extend_from_slice(format!("\x1b_Gf=100,a=T,m={};", more).as_bytes());
+        } else {
+            out.extend_from_slice(format!("\x1b_Gm={};", more).as_bytes());
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out
+}
+
+fn iterm(image: &[u8]) -> Vec<u8> {
+    format!(
+        "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+        image.len(),
+        base64::encode(image)
+    )
+    .into_bytes()
+}
+
+/// Download `images` with `cache` and draw them with `protocol` under `title`, then wait for the
+/// user to go back; the terminal must not be in use by the TUI
+pub fn show(title: &str, images: &[Image], protocol: Protocol, cache: &ImageCache) -> Result<()> {
+    let rt = Runtime::new()?;
+    let mut out = io::stdout();
+    writeln!(out, "{}\n", title)?;
+    for image in images {
+        writeln!(out, "{}", image)?;
+        match rt.block_on(cache.get(&image.url)) {
+            Ok(bytes) => match encode(protocol, &bytes) {
+                Some(drawn) => {
+                    out.write_all(&drawn)?;
+                    writeln!(out)?;
+                }
+                None => writeln!(out, "Can't draw this kind of image here; open the link")?,
+            },
+            Err(e) => writeln!(out, "Couldn't download it: {}", e)?,
+        }
+        writeln!(out)?;
+    }
+    write!(out, "Press Enter to go back")?;
+    out.flush()?;
+    io::stdin().read_line(&mut String::new())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1x1 PNG
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x00\x01\x00\x00\x00\x01\
+        \x08\x06\x00\x00\x00\x1f\x15\xc4\x89\x00\x00\x00\rIDATx\x9cc`\x00\x00\x02\x00\x00\
+        \x01\xe2!\xbc3\x00\x00\x00\x00IEND\xaeB`\x82";
+
+    const PNG_BASE64: &str =
+        "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGNgAAACAAAB4iG8MwAAAABJRU5ErkJggg==";
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_protocol() {
+        let auto = |vars| protocol(ImagePreview::Auto, env(vars));
+        assert_eq!(auto(&[("TERM", "xterm-kitty")]), Some(Protocol::Kitty));
+        assert_eq!(auto(&[("KITTY_WINDOW_ID", "1")]), Some(Protocol::Kitty));
+        assert_eq!(
+            auto(&[("TERM_PROGRAM", "iTerm.app")]),
+            Some(Protocol::Iterm)
+        );
+        assert_eq!(auto(&[("TERM_PROGRAM", "WezTerm")]), Some(Protocol::Iterm));
+        assert_eq!(auto(&[("TERM_PROGRAM", "tmux")]), None);
+        assert_eq!(auto(&[("TERM", "xterm-256color")]), None);
+        assert_eq!(auto(&[]), None);
+        // Anything but auto doesn't look
+        let kitty = &[("TERM", "xterm-kitty")];
+        assert_eq!(protocol(ImagePreview::Off, env(kitty)), None);
+        assert_eq!(protocol(ImagePreview::Sixel, env(kitty)), None);
+        assert_eq!(
+            protocol(ImagePreview::Iterm, env(kitty)),
+            Some(Protocol::Iterm)
+        );
+        assert_eq!(
+            protocol(ImagePreview::Kitty, env(&[])),
+            Some(Protocol::Kitty)
+        );
+    }
+
+    #[test]
+    fn test_kitty() {
+        let expected = format!("\x1b_Gf=100,a=T,m=0;{}\x1b\\", PNG_BASE64);
+        assert_eq!(encode(Protocol::Kitty, PNG).unwrap(), expected.into_bytes());
+        // Only PNGs
+        assert_eq!(encode(Protocol::Kitty, b"\xff\xd8\xff\xe0JFIF"), None);
+        assert_eq!(encode(Protocol::Kitty, b""), None);
+    }
+
+    #[test]
+    fn test_kitty_chunks() {
+        // 3 bytes to 4 base64 characters: 4096 + 4096 + 8 characters
+        let mut png = PNG_MAGIC.to_vec();
+        png.resize(6150, 0);
+        let encoded = String::from_utf8(encode(Protocol::Kitty, &png).unwrap()).unwrap();
+        let chunks: Vec<&str> = encoded.split_terminator("\x1b\\").collect();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].starts_with("\x1b_Gf=100,a=T,m=1;iVBORw0KGgo"));
+        assert_eq!(chunks[0].len(), "\x1b_Gf=100,a=T,m=1;".len() + 4096);
+        assert!(chunks[1].starts_with("\x1b_Gm=1;"));
+        assert_eq!(chunks[1].len(), "\x1b_Gm=1;".len() + 4096);
+        assert_eq!(chunks[2], "\x1b_Gm=0;AAAAAAAA");
+    }
+
+    #[test]
+    fn test_iterm() {
+        let expected = format!(
+            "\x1b]1337;File=inline=1;size=67;preserveAspectRatio=1:{}\x07",
+            PNG_BASE64
+        );
+        assert_eq!(encode(Protocol::Iterm, PNG).unwrap(), expected.into_bytes());
+        // Any format
+        assert_eq!(
+            encode(Protocol::Iterm, b"GIF89a").unwrap(),
+            b"\x1b]1337;File=inline=1;size=6;preserveAspectRatio=1:R0lGODlh\x07".to_vec()
+        );
+        assert_eq!(encode(Protocol::Iterm, b""), None);
+    }
+}
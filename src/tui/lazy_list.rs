@@ -0,0 +1,142 @@
+//! Lazily rendered list labels, so that long lists only pay for the items actually shown.
+//!
+//! Every item is added to the `SelectView` up front with an empty label, which keeps scrolling
+//! and selection behaving exactly as before; labels within the visible window are then rendered
+//! on demand (and cached by id) each time the list is laid out.
+
+use cursive::utils::markup::StyledString;
+use cursive::views::SelectView;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Rows rendered beyond either edge of the visible window, so that scrolling a little doesn't
+/// show blank items for a frame
+pub const BUFFER: usize = 10;
+
+/// Renders list labels on first use and caches them by item id
+pub struct LazyLabels {
+    labels: HashMap<u32, StyledString>,
+    render: Box<dyn Fn(u32) -> StyledString>,
+}
+
+impl LazyLabels {
+    pub fn new<F>(render: F) -> Self
+    where
+        F: Fn(u32) -> StyledString + 'static,
+    {
+        LazyLabels {
+            labels: HashMap::new(),
+            render: Box::new(render),
+        }
+    }
+
+    /// Label for `id`, rendering it if this is the first time it's needed
+    pub fn get(&mut self, id: u32) -> &StyledString {
+        let render = &self.render;
+        self.labels.entry(id).or_insert_with(|| render(id))
+    }
+
+    pub fn is_rendered(&self, id: u32) -> bool {
+        self.labels.contains_key(&id)
+    }
+}
+
+/// Indices of the items to render when `height` rows starting at `top` are visible, padded by
+/// `buffer` rows on both sides and clamped to the `len` items available
+pub fn window(top: usize, height: usize, len: usize, buffer: usize) -> Range<usize> {
+    let end = top.saturating_add(height).saturating_add(buffer).min(len);
+    let start = top.saturating_sub(buffer).min(end);
+    start..end
+}
+
+/// Fill in the labels of items in `range` that haven't been rendered yet
+pub fn fill_window(view: &mut SelectView<u32>, labels: &mut LazyLabels, range: Range<usize>) {
+    for i in range {
+        if let Some((label, id)) = view.get_item_mut(i) {
+            if !labels.is_rendered(*id) {
+                *label = labels.get(*id).clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const HEIGHT: usize = 20;
+
+    /// A list of `n` synthetic questions, counting how many labels get rendered
+    fn list(n: u32) -> (SelectView<u32>, LazyLabels, Rc<Cell<usize>>) {
+        let mut view = SelectView::new();
+        view.add_all((0..n).map(|id| (StyledString::new(), id)));
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let labels = LazyLabels::new(move |id| {
+            counter.set(counter.get() + 1);
+            StyledString::plain(format!("(1) Question {}", id))
+        });
+        (view, labels, calls)
+    }
+
+    #[test]
+    fn test_window() {
+        assert_eq!(window(0, 20, 1000, 10), 0..30);
+        assert_eq!(window(500, 20, 1000, 10), 490..530);
+        assert_eq!(window(990, 20, 1000, 10), 980..1000);
+        assert_eq!(window(0, 20, 5, 10), 0..5);
+        assert_eq!(window(0, 20, 0, 10), 0..0);
+        assert_eq!(window(50, 20, 10, 10), 10..10);
+    }
+
+    #[test]
+    fn test_cache() {
+        let (_, mut labels, calls) = list(0);
+        assert!(!labels.is_rendered(7));
+        assert_eq!(labels.get(7).source(), "(1) Question 7");
+        labels.get(7);
+        assert!(labels.is_rendered(7));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_stress_renders_bounded_by_window() {
+        let (mut view, mut labels, calls) = list(1000);
+        let len = view.len();
+
+        // Initial display only renders the first window
+        let first = window(0, HEIGHT, len, BUFFER);
+        fill_window(&mut view, &mut labels, first.clone());
+        assert_eq!(calls.get(), first.len());
+        assert_eq!(view.get_item(0).unwrap().0, "(1) Question 0");
+        assert_eq!(view.get_item(first.end).unwrap().0, "");
+
+        // Laying out again without scrolling renders nothing new
+        fill_window(&mut view, &mut labels, first.clone());
+        assert_eq!(calls.get(), first.len());
+
+        // Scrolling one row at a time renders at most one new label per step
+        for top in 1..=100 {
+            let before = calls.get();
+            fill_window(&mut view, &mut labels, window(top, HEIGHT, len, BUFFER));
+            assert!(calls.get() - before <= 1);
+        }
+
+        // Jumping to the end (G) renders one window's worth
+        let before = calls.get();
+        let last = window(len - HEIGHT, HEIGHT, len, BUFFER);
+        fill_window(&mut view, &mut labels, last.clone());
+        assert!(calls.get() - before <= last.len());
+        assert_eq!(view.get_item(999).unwrap().0, "(1) Question 999");
+
+        // Nowhere near all 1000 questions were rendered
+        assert!(calls.get() < 200);
+
+        // Selection semantics are untouched
+        view.set_selection(999);
+        assert_eq!(view.selected_id(), Some(999));
+        assert_eq!(*view.selection().unwrap(), 999);
+    }
+}
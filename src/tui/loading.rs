@@ -0,0 +1,390 @@
+//! Loading state of a lazily loaded pane, e.g. the answers of the selected question, and the
+//! indicator shown while it loads.
+//!
+//! A `Pane` goes from idle to loading when something is selected, and from there to ready or
+//! failed when the load finishes. Every load gets a `Ticket`, whose token is cancelled as soon as
+//! another selection supersedes it, so background tasks can stop early; whatever a superseded
+//! load still delivers is dropped. Failed loads stay failed until retried.
+
+use cursive::theme::{BaseColor, Color, Effect, Style};
+use cursive::utils::markup::StyledString;
+use cursive::{CbSink, Cursive};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Key to retry a failed load
+pub const RETRY_KEY: char = 'r';
+
+/// How often the loading indicator moves
+const FRAME: Duration = Duration::from_millis(300);
+
+/// Frames of the loading indicator where there's only room for a character, e.g. the status bar
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Tells a background load that its result is no longer wanted
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<(AtomicBool, Notify)>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0 .0.store(true, Ordering::SeqCst);
+        // Stores a permit if nobody is waiting yet, so a later `cancelled` still returns
+        self.0 .1.notify_one();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0 .0.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once cancelled
+    pub async fn cancelled(&self) {
+        if !self.is_cancelled() {
+            self.0 .1.notified().await;
+        }
+    }
+}
+
+/// A load started by a `Pane`, to be handed back with its result
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub id: u32,
+    generation: u64,
+    token: CancelToken,
+}
+
+impl Ticket {
+    /// Loads go through `run` rather than watching the token themselves
+    #[cfg(test)]
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+
+    /// Run `load` until it's done or the ticket is cancelled, whichever comes first
+    pub async fn run<F: Future>(&self, load: F) -> Option<F::Output> {
+        if self.token.is_cancelled() {
+            return None;
+        }
+        tokio::select! {
+            output = load => Some(output),
+            _ = self.token.cancelled() => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Phase<T> {
+    Idle,
+    Loading { id: u32 },
+    Ready { id: u32, content: T },
+    Failed { id: u32, error: String },
+}
+
+#[derive(Debug)]
+pub struct Pane<T> {
+    phase: Phase<T>,
+    /// The load in progress, if any
+    current: Option<Ticket>,
+    /// Counts loads, so that results of superseded ones are told apart
+    generation: u64,
+    /// Animation frame of the loading indicator
+    frame: usize,
+}
+
+impl<T> Default for Pane<T> {
+    fn default() -> Self {
+        Pane {
+            phase: Phase::Idle,
+            current: None,
+            generation: 0,
+            frame: 0,
+        }
+    }
+}
+
+impl<T> Pane<T> {
+    pub fn phase(&self) -> &Phase<T> {
+        &self.phase
+    }
+
+    /// Start loading `id`, cancelling the load in progress. Returns the ticket of the new load,
+    /// or nothing if `id` is already loading or loaded, or failed to (see `retry`).
+    pub fn select(&mut self, id: u32) -> Option<Ticket> {
+        match self.phase {
+            Phase::Loading { id: current }
+            | Phase::Ready { id: current, .. }
+            | Phase::Failed { id: current, .. }
+                if current == id =>
+            {
+                None
+            }
+            _ => Some(self.start(id)),
+        }
+    }
+
+    /// Load a failed selection again
+    pub fn retry(&mut self) -> Option<Ticket> {
+        match self.phase {
+            Phase::Failed { id, .. } => Some(self.start(id)),
+            _ => None,
+        }
+    }
+
+    /// Take the `result` of the load `ticket` was issued for. Returns whether it was taken,
+    /// which it isn't if another load superseded it.
+    pub fn finish(&mut self, ticket: &Ticket, result: Result<T, String>) -> bool {
+        match &self.current {
+            Some(current) if current.generation == ticket.generation => (),
+            _ => return false,
+        }
+        // Finished loads have nothing to cancel, but this stops their indicator
+        if let Some(current) = self.current.take() {
+            current.token.cancel();
+        }
+        self.phase = match result {
+            Ok(content) => Phase::Ready {
+                id: ticket.id,
+                content,
+            },
+            Err(error) => Phase::Failed {
+                id: ticket.id,
+                error,
+            },
+        };
+        true
+    }
+
+    /// Move the loading indicator on. Returns whether there's still something loading.
+    pub fn tick(&mut self) -> bool {
+        if let Phase::Loading { .. } = self.phase {
+            self.frame += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn start(&mut self, id: u32) -> Ticket {
+        self.cancel();
+        self.generation += 1;
+        self.frame = 0;
+        let ticket = Ticket {
+            id,
+            generation: self.generation,
+            token: CancelToken::default(),
+        };
+        self.current = Some(ticket.clone());
+        self.phase = Phase::Loading { id };
+        ticket
+    }
+
+    fn cancel(&mut self) {
+        if let Some(current) = self.current.take() {
+            current.token.cancel();
+        }
+    }
+}
+
+impl<T> Drop for Pane<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// What a pane shows instead of its content while loading or after failing to load `what`
+pub fn render<T>(pane: &Pane<T>, what: &str) -> Option<StyledString> {
+    match &pane.phase {
+        Phase::Loading { .. } => Some(indicator(pane.frame)),
+        Phase::Failed { error, .. } => {
+            let mut content = StyledString::styled(
+                format!("Couldn't load {}: {}", what, error),
+                Color::Light(BaseColor::Red),
+            );
+            content.append_styled(
+                format!("\n\nPress {} to retry", RETRY_KEY),
+                Style::merge(&[
+                    Style::from(Color::Light(BaseColor::Black)),
+                    Style::from(Effect::Italic),
+                ]),
+            );
+            Some(content)
+        }
+        Phase::Idle | Phase::Ready { .. } => None,
+    }
+}
+
+/// The loading indicator of `pane` as a single character, while it's loading
+pub fn spinner<T>(pane: &Pane<T>) -> Option<char> {
+    match pane.phase {
+        Phase::Loading { .. } => Some(SPINNER[pane.frame % SPINNER.len()]),
+        _ => None,
+    }
+}
+
+/// "Loading" followed by up to three dots, depending on the `frame`
+fn indicator(frame: usize) -> StyledString {
+    StyledString::styled(
+        format!("Loading{:<3}", ".".repeat(frame % 4)),
+        Style::merge(&[
+            Style::from(Color::Light(BaseColor::Black)),
+            Style::from(Effect::Italic),
+        ]),
+    )
+}
+
+/// Call `tick` on the TUI thread every frame until `ticket` is done or cancelled, e.g. to
+/// `Pane::tick` and redraw the pane
+pub fn animate<F>(cb_sink: CbSink, ticket: &Ticket, tick: F)
+where
+    F: Fn(&mut Cursive) + Send + Sync + 'static,
+{
+    let token = ticket.token.clone();
+    let tick = Arc::new(tick);
+    std::thread::spawn(move || {
+        while !token.is_cancelled() {
+            std::thread::sleep(FRAME);
+            let tick = Arc::clone(&tick);
+            if cb_sink.send(Box::new(move |s| tick(s))).is_err() {
+                // The TUI is gone
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let mut pane: Pane<&str> = Pane::default();
+        assert_eq!(pane.phase(), &Phase::Idle);
+        assert!(!pane.tick());
+
+        let ticket = pane.select(1).unwrap();
+        assert_eq!(pane.phase(), &Phase::Loading { id: 1 });
+        assert!(pane.tick());
+        // Already loading
+        assert!(pane.select(1).is_none());
+
+        assert!(pane.finish(&ticket, Ok("answers")));
+        assert_eq!(
+            pane.phase(),
+            &Phase::Ready {
+                id: 1,
+                content: "answers"
+            }
+        );
+        assert!(ticket.token().is_cancelled(), "indicator should stop");
+        assert!(!pane.tick());
+        // Already loaded, and finishing twice does nothing
+        assert!(pane.select(1).is_none());
+        assert!(!pane.finish(&ticket, Ok("again")));
+    }
+
+    #[test]
+    fn test_rapid_selection() {
+        let mut pane: Pane<u32> = Pane::default();
+        let first = pane.select(1).unwrap();
+        let second = pane.select(2).unwrap();
+        let third = pane.select(3).unwrap();
+        assert!(first.token().is_cancelled());
+        assert!(second.token().is_cancelled());
+        assert!(!third.token().is_cancelled());
+
+        // Superseded loads finishing late are dropped, whatever order they come in
+        assert!(!pane.finish(&second, Ok(2)));
+        assert!(!pane.finish(&first, Err(String::from("timed out"))));
+        assert_eq!(pane.phase(), &Phase::Loading { id: 3 });
+        assert!(pane.finish(&third, Ok(3)));
+        assert_eq!(pane.phase(), &Phase::Ready { id: 3, content: 3 });
+
+        // Going back to an earlier selection loads it again
+        let again = pane.select(1).unwrap();
+        assert_eq!(again.id, 1);
+        assert!(!pane.finish(&first, Ok(1)));
+        assert!(pane.finish(&again, Ok(1)));
+    }
+
+    #[test]
+    fn test_failure_and_retry() {
+        let mut pane: Pane<u32> = Pane::default();
+        assert!(pane.retry().is_none());
+        let ticket = pane.select(1).unwrap();
+        assert!(pane.finish(&ticket, Err(String::from("offline"))));
+        assert_eq!(
+            pane.phase(),
+            &Phase::Failed {
+                id: 1,
+                error: String::from("offline")
+            }
+        );
+        // Selecting it again doesn't hammer a failing source; retrying does
+        assert!(pane.select(1).is_none());
+        let retry = pane.retry().unwrap();
+        assert_eq!(retry.id, 1);
+        assert_eq!(pane.phase(), &Phase::Loading { id: 1 });
+        assert!(!pane.finish(&ticket, Ok(1)));
+        assert!(pane.finish(&retry, Ok(1)));
+        assert!(pane.retry().is_none());
+    }
+
+    #[test]
+    fn test_drop_cancels() {
+        let mut pane: Pane<u32> = Pane::default();
+        let ticket = pane.select(1).unwrap();
+        drop(pane);
+        assert!(ticket.token().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_run_cancelled() {
+        let mut pane: Pane<u32> = Pane::default();
+        let ticket = pane.select(1).unwrap();
+        assert_eq!(ticket.run(async { 1 }).await, Some(1));
+
+        let superseded = pane.select(2).unwrap();
+        let load = tokio::spawn({
+            let superseded = superseded.clone();
+            async move { superseded.run(futures::future::pending::<u32>()).await }
+        });
+        pane.select(3);
+        assert_eq!(load.await.unwrap(), None);
+        // Cancelled before it even started
+        assert_eq!(superseded.run(async { 2 }).await, None);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut pane: Pane<u32> = Pane::default();
+        assert!(render(&pane, "answers").is_none());
+        let ticket = pane.select(1).unwrap();
+        let frames: Vec<String> = (0..5)
+            .map(|_| {
+                let frame = render(&pane, "answers").unwrap().source().to_string();
+                pane.tick();
+                frame
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                "Loading   ",
+                "Loading.  ",
+                "Loading.. ",
+                "Loading...",
+                "Loading   "
+            ]
+        );
+        assert_eq!(spinner(&pane), Some('/'));
+        pane.finish(&ticket, Err(String::from("offline")));
+        assert_eq!(spinner(&pane), None);
+        assert_eq!(
+            render(&pane, "answers").unwrap().source(),
+            "Couldn't load answers: offline\n\nPress r to retry"
+        );
+    }
+}
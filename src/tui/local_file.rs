@@ -0,0 +1,170 @@
+//! Local files to compare the code of an answer against, e.g. a config file an answer shows a
+//! corrected version of.
+
+use directories::BaseDirs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::utils;
+
+/// How much of a file to look at when telling text from binary, as git does
+const SNIFF_LEN: usize = 8000;
+
+/// `input` as typed into a prompt, with a leading `~` and `$VAR` or `${VAR}` expanded
+pub fn expand(input: &str) -> PathBuf {
+    let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    expand_with(input, home.as_deref(), |var| std::env::var(var).ok())
+}
+
+/// Expand `input` with `home` for `~` and `var` for environment variables; anything that doesn't
+/// expand is left as it is
+fn expand_with<F>(input: &str, home: Option<&Path>, var: F) -> PathBuf
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let input = input.trim();
+    let mut expanded = String::with_capacity(input.len());
+    let mut rest = input;
+    if let Some(home) = home {
+        if rest == "~" || rest.starts_with("~/") {
+            expanded.push_str(&home.to_string_lossy());
+            rest = &rest[1..];
+        }
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match var(name).filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[start..start + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+/// Whether the contents of a file look like anything but text: a NUL byte near the start, as git
+/// decides, or anything that isn't UTF-8
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let sniffed = &bytes[..bytes.len().min(SNIFF_LEN)];
+    sniffed.contains(&0) || std::str::from_utf8(bytes).is_err()
+}
+
+/// Contents of the text file at `path`, refusing directories and binary files
+pub fn read(path: &Path) -> Result<String> {
+    let path = path.to_path_buf();
+    let mut file = utils::open_file(&path)?.ok_or_else(|| Error::FileNotFound(path.clone()))?;
+    if !file.metadata()?.is_file() {
+        return Err(Error::NotAFile(path));
+    }
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    if is_binary(&bytes) {
+        return Err(Error::BinaryFile(path));
+    }
+    // Checked above
+    Ok(String::from_utf8(bytes).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn vars(var: &str) -> Option<String> {
+        match var {
+            "PROJECT" => Some(String::from("so")),
+            "XDG_CONFIG_HOME" => Some(String::from("/home/me/.config")),
+            _ => None,
+        }
+    }
+
+    fn expand(input: &str) -> PathBuf {
+        expand_with(input, Some(Path::new("/home/me")), vars)
+    }
+
+    /// Fresh directory under the temp dir, named after the test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("so-local-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_expand_home() {
+        assert_eq!(expand("~"), PathBuf::from("/home/me"));
+        assert_eq!(expand("~/.vimrc"), PathBuf::from("/home/me/.vimrc"));
+        assert_eq!(expand("  ~/.vimrc\n"), PathBuf::from("/home/me/.vimrc"));
+        // Other users' homes aren't looked up
+        assert_eq!(expand("~root/.vimrc"), PathBuf::from("~root/.vimrc"));
+        assert_eq!(expand("notes/~/x"), PathBuf::from("notes/~/x"));
+        assert_eq!(
+            expand_with("~/.vimrc", None, vars),
+            PathBuf::from("~/.vimrc")
+        );
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        assert_eq!(
+            expand("$XDG_CONFIG_HOME/$PROJECT/config.yml"),
+            PathBuf::from("/home/me/.config/so/config.yml")
+        );
+        assert_eq!(expand("${PROJECT}rc"), PathBuf::from("sorc"));
+        assert_eq!(expand("~/src/$PROJECT"), PathBuf::from("/home/me/src/so"));
+        // Unknown, empty or unterminated variables stay as typed
+        assert_eq!(expand("$NOPE/x"), PathBuf::from("$NOPE/x"));
+        assert_eq!(expand("${NOPE}/x"), PathBuf::from("${NOPE}/x"));
+        assert_eq!(expand("cost$"), PathBuf::from("cost$"));
+        assert_eq!(expand("${PROJECT"), PathBuf::from("${PROJECT"));
+        assert_eq!(expand("a$/b"), PathBuf::from("a$/b"));
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b"set number\nsyntax on\n"));
+        assert!(!is_binary("caf\u{e9}\n".as_bytes()));
+        assert!(!is_binary(b""));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00\x00"));
+        assert!(is_binary(&[0xff, 0xfe, b'h', b'i']));
+        // Past the sniffed part, only invalid UTF-8 gives it away
+        let mut late_nul = vec![b'a'; SNIFF_LEN];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = scratch("read");
+        let text = dir.join("vimrc");
+        fs::write(&text, "set number\n").unwrap();
+        assert_eq!(read(&text).unwrap(), "set number\n");
+
+        let binary = dir.join("vim");
+        fs::write(&binary, b"\x7fELF\x00\x00").unwrap();
+        assert!(matches!(read(&binary), Err(Error::BinaryFile(p)) if p == binary));
+        assert!(matches!(read(&dir), Err(Error::NotAFile(p)) if p == dir));
+        let missing = dir.join("missing");
+        assert!(matches!(read(&missing), Err(Error::FileNotFound(p)) if p == missing));
+        assert_eq!(
+            read(&missing).unwrap_err().to_string(),
+            format!("No such file: `{}`", missing.display())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,31 +7,142 @@
 
 // TODO use ColorStyle::secondary() etc. over specific enums
 
-use cursive::theme::{Effect, PaletteColor, Style};
+use cursive::theme::{BaseColor, Color, Effect, PaletteColor, Style};
 use cursive::utils::markup::{StyledIndexedSpan, StyledString};
 use cursive::utils::span::{IndexedCow, IndexedSpan};
-use pulldown_cmark::{self, CowStr, Event, Options, Tag};
+use pulldown_cmark::{self, CodeBlockKind, CowStr, Event, Options, Tag};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+
+use super::spoilers;
+use super::syntax::{self, Syntax};
+use crate::stackexchange::preprocess::language_hint;
+use crate::stackexchange::CodeMatch;
+use syntect::parsing::SyntaxReference;
 
 pub type Markdown = StyledString;
 
 /// Parses the given string as markdown text.
 /// **Note**: Assumes preprocessing has taken place
 pub fn parse<S>(input: S) -> StyledString
+where
+    S: Into<String>,
+{
+    parse_revealing(input, &[], &[])
+}
+
+/// Parses the given string as markdown text, highlighting the given code block lines.
+/// **Note**: Assumes preprocessing has taken place
+pub fn parse_with_highlights<S>(input: S, highlights: &[CodeMatch]) -> StyledString
+where
+    S: Into<String>,
+{
+    parse_revealing(input, highlights, &[])
+}
+
+/// Parses the given string as markdown text, highlighting the given code block lines and showing
+/// the spoilers `revealed`, counted from zero; the others are collapsed.
+/// **Note**: Assumes preprocessing has taken place
+pub fn parse_revealing<S>(input: S, highlights: &[CodeMatch], revealed: &[usize]) -> StyledString
+where
+    S: Into<String>,
+{
+    parse_highlighted(input, highlights, revealed, None)
+}
+
+/// Parses the given string as markdown text like `parse_revealing`, and if there's `syntax` to
+/// go by, highlights the syntax of its code blocks too.
+/// **Note**: Assumes preprocessing has taken place
+pub fn parse_highlighted<S>(
+    input: S,
+    highlights: &[CodeMatch],
+    revealed: &[usize],
+    syntax: Option<Syntax<'_>>,
+) -> StyledString
+where
+    S: Into<String>,
+{
+    parse_with_blocks(input, highlights, revealed, syntax).0
+}
+
+/// A code block as it's shown, one to a block the parse didn't collapse into a spoiler
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// Byte range of the block in the parsed text, its trailing line break included
+    pub text: Range<usize>,
+    /// The code, as it's written
+    pub code: String,
+}
+
+impl CodeBlock {
+    /// Lines of code in the block
+    pub fn lines(&self) -> usize {
+        self.code.lines().count()
+    }
+}
+
+/// `parse_highlighted`, along with the code blocks it shows, in order
+/// **Note**: Assumes preprocessing has taken place
+pub fn parse_with_blocks<S>(
+    input: S,
+    highlights: &[CodeMatch],
+    revealed: &[usize],
+    syntax: Option<Syntax<'_>>,
+) -> (StyledString, Vec<CodeBlock>)
 where
     S: Into<String>,
 {
     let input = input.into();
-    let spans = parse_spans(&input);
-    //let output = build_output(&spans);
-    StyledString::with_spans(input, spans)
+    let marked = spoilers::mark(&input);
+    let mut parser = Parser::new(&marked)
+        .with_highlights(highlights)
+        .with_revealed(revealed);
+    if let Some(syntax) = syntax {
+        parser = parser.with_syntax(syntax);
+    }
+    let spans = parser.by_ref().collect();
+    let blocks = parser.blocks;
+    (StyledString::with_spans(input, spans), blocks)
+}
+
+/// An image in markdown text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub alt: String,
+    pub url: String,
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.alt.is_empty() {
+            write!(f, "{}", self.url)
+        } else {
+            write!(f, "{}: {}", self.alt, self.url)
+        }
+    }
 }
 
-pub fn preprocess(input: String) -> String {
-    input
-        .as_str()
-        .trim()
-        .replace("<kbd>", "**[")
-        .replace("</kbd>", "]**")
+/// Images in the given markdown text, in order
+pub fn images(input: &str) -> Vec<Image> {
+    let mut images = Vec::new();
+    let mut alt: Option<String> = None;
+    for event in pulldown_cmark::Parser::new(input) {
+        match event {
+            Event::Start(Tag::Image(..)) => alt = Some(String::new()),
+            Event::End(Tag::Image(_, url, _)) => images.push(Image {
+                alt: alt.take().unwrap_or_default().trim().to_string(),
+                url: url.into_string(),
+            }),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(alt) = alt.as_mut() {
+                    alt.push_str(&text);
+                }
+            }
+            _ => (),
+        }
+    }
+    images
 }
 
 /// Preview markdown of the given length
@@ -72,12 +183,6 @@ fn drop_color(span: StyledIndexedSpan) -> StyledIndexedSpan {
     }
 }
 
-/// Parse the given markdown text into a list of spans.
-/// This is a shortcut for `Parser::new(preprocessed_input).collect()`.
-fn parse_spans(input: &str) -> Vec<StyledIndexedSpan> {
-    Parser::new(input).collect()
-}
-
 /// Iterator that parse a markdown text and outputs styled spans.
 pub struct Parser<'a> {
     first: bool,
@@ -86,6 +191,30 @@ pub struct Parser<'a> {
     after_code_block: bool,
     stack: Vec<Style>,
     parser: pulldown_cmark::Parser<'a>,
+    /// Code block lines to highlight
+    highlights: &'a [CodeMatch],
+    /// Index of the code block currently being parsed
+    code_block: Option<usize>,
+    code_blocks_seen: usize,
+    /// Line within the current code block
+    code_line: usize,
+    /// Spans produced by a single event that haven't been returned yet
+    pending: VecDeque<StyledIndexedSpan>,
+    /// Spoilers to show rather than collapse, counted from zero, see `spoilers::mark`
+    revealed: &'a [usize],
+    spoilers_seen: usize,
+    /// Whether the spoiler currently being parsed, if any, is revealed
+    spoiler: Option<bool>,
+    syntax: Option<Syntax<'a>>,
+    /// Language hinted at for the next code block, and for all that follow
+    next_language: Option<String>,
+    all_language: Option<String>,
+    /// Code of the current block, if its syntax is highlighted once it's all in, and its language
+    code: Option<(String, &'static SyntaxReference)>,
+    /// Bytes of text returned so far
+    rendered: usize,
+    /// Code blocks shown so far, the last one still being parsed while in `code_block`
+    blocks: Vec<CodeBlock>,
 }
 
 impl<'a> Parser<'a> {
@@ -101,6 +230,149 @@ impl<'a> Parser<'a> {
             first: true,
             parser: pulldown_cmark::Parser::new_ext(input, opts),
             stack: Vec::new(),
+            highlights: &[],
+            code_block: None,
+            code_blocks_seen: 0,
+            code_line: 0,
+            pending: VecDeque::new(),
+            revealed: &[],
+            spoilers_seen: 0,
+            spoiler: None,
+            syntax: None,
+            next_language: None,
+            all_language: None,
+            code: None,
+            rendered: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Highlight the given code block lines
+    pub fn with_highlights(self, highlights: &'a [CodeMatch]) -> Self {
+        Parser { highlights, ..self }
+    }
+
+    /// Show the given spoilers, counted from zero, and collapse the others
+    pub fn with_revealed(self, revealed: &'a [usize]) -> Self {
+        Parser { revealed, ..self }
+    }
+
+    /// Highlight the syntax of code blocks
+    pub fn with_syntax(self, syntax: Syntax<'a>) -> Self {
+        Parser {
+            syntax: Some(syntax),
+            ..self
+        }
+    }
+
+    /// Remember the language `html` hints at, if it's a language hint. Returns whether it was one.
+    fn language_hint(&mut self, html: &str) -> bool {
+        match language_hint(html) {
+            Some((language, true)) => self.all_language = Some(language.to_string()),
+            Some((language, false)) => self.next_language = Some(language.to_string()),
+            None => return false,
+        }
+        true
+    }
+
+    /// Language to highlight the code block starting with `kind` as, if any
+    fn code_language(&mut self, kind: &CodeBlockKind) -> Option<&'static SyntaxReference> {
+        let hint = self
+            .next_language
+            .take()
+            .or_else(|| self.all_language.clone());
+        let syntax = self.syntax?;
+        let fence = match kind {
+            CodeBlockKind::Fenced(info) => info.split_whitespace().next(),
+            CodeBlockKind::Indented => None,
+        };
+        syntax::language(fence, hint.as_deref(), syntax.tags)
+    }
+
+    /// Queue the spans of `code`, highlighted as `language`, reverse styling highlighted lines
+    fn highlight_syntax(&mut self, code: String, language: &SyntaxReference) {
+        let lines = match self
+            .syntax
+            .and_then(|syntax| syntax.highlighter.highlight(language, &code))
+        {
+            Some(lines) => lines,
+            None => return self.highlight_code(CowStr::from(code)),
+        };
+        let block = self.code_block.unwrap_or_default();
+        let base = Style::merge(&self.stack);
+        for line in lines {
+            let matched = self.highlights.contains(&CodeMatch {
+                block,
+                line: self.code_line,
+            });
+            for (style, text) in line {
+                let mut style = Style::merge(&[base, style]);
+                if matched {
+                    style = style.combine(Effect::Reverse);
+                }
+                if text.ends_with('\n') {
+                    self.code_line += 1;
+                }
+                self.pending
+                    .push_back(StyledIndexedSpan::simple_owned(text, style));
+            }
+        }
+    }
+
+    /// Start or end a spoiler if `html` is one of the `spoilers` markers, labeling its start.
+    /// Returns whether it was one.
+    fn spoiler_marker(&mut self, html: &str) -> bool {
+        match html.trim() {
+            spoilers::START => {
+                let revealed = self.revealed.contains(&self.spoilers_seen);
+                self.spoilers_seen += 1;
+                self.spoiler = Some(revealed);
+                let (label, breaks) = if revealed {
+                    (spoilers::REVEALED, "\n")
+                } else {
+                    (spoilers::COLLAPSED, "\n\n")
+                };
+                self.pending.push_back(StyledIndexedSpan::simple_owned(
+                    label.to_string(),
+                    Style::merge(&[
+                        Style::from(Color::Light(BaseColor::Black)),
+                        Style::from(Effect::Italic),
+                    ]),
+                ));
+                // On their own, which is how previews tell line breaks apart
+                self.pending.push_back(self.literal(breaks));
+                true
+            }
+            spoilers::END => {
+                self.spoiler = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the current code block has any lines to highlight
+    fn in_highlighted_block(&self) -> bool {
+        self.code_block
+            .is_some_and(|block| self.highlights.iter().any(|h| h.block == block))
+    }
+
+    /// Split code block text into one span per line, reverse styling highlighted lines
+    fn highlight_code(&mut self, text: CowStr) {
+        let block = self.code_block.unwrap_or_default();
+        for line in text.split_inclusive('\n') {
+            let mut style = Style::merge(&self.stack);
+            if self.highlights.contains(&CodeMatch {
+                block,
+                line: self.code_line,
+            }) {
+                style = style.combine(Effect::Reverse);
+            }
+            self.pending
+                .push_back(StyledIndexedSpan::simple_owned(line.to_string(), style));
+            if line.ends_with('\n') {
+                self.code_line += 1;
+            }
         }
     }
 
@@ -131,25 +403,74 @@ impl<'a> Iterator for Parser<'a> {
     type Item = StyledIndexedSpan;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let span = self.next_span()?;
+        self.rendered += span_len(&span);
+        Some(span)
+    }
+}
+
+/// Bytes of text in `span`
+fn span_len(span: &StyledIndexedSpan) -> usize {
+    match &span.content {
+        IndexedCow::Borrowed { start, end } => end - start,
+        IndexedCow::Owned(text) => text.len(),
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// The next span, before it counts towards `rendered`
+    fn next_span(&mut self) -> Option<StyledIndexedSpan> {
         loop {
-            let next = match self.parser.next() {
-                None => return None,
-                Some(event) => event,
-            };
+            if let Some(span) = self.pending.pop_front() {
+                return Some(span);
+            }
+            let next = self.parser.next()?;
+
+            if let Event::Html(html) = &next {
+                if self.spoiler_marker(html) {
+                    continue;
+                }
+                // Only there for the code that follows
+                if self.language_hint(html.trim()) {
+                    continue;
+                }
+            }
+            if self.spoiler == Some(false) {
+                // Code blocks are counted all the same, which is how highlights refer to them
+                if let Event::Start(Tag::CodeBlock(_)) = next {
+                    self.code_blocks_seen += 1;
+                    self.next_language = None;
+                }
+                continue;
+            }
+            if let (Some(_), Event::Text(text)) = (self.code_block, &next) {
+                if let Some(block) = self.blocks.last_mut() {
+                    block.code.push_str(text);
+                }
+            }
 
             match next {
                 // Add styles to the stack
                 Event::Start(tag) => match tag {
                     Tag::Emphasis => self.stack.push(Style::from(Effect::Italic)),
-                    Tag::Heading(level) if level == 1 => {
-                        self.stack.push(Style::from(PaletteColor::TitlePrimary))
-                    }
+                    Tag::Heading(1) => self.stack.push(Style::from(PaletteColor::TitlePrimary)),
                     Tag::Heading(_) => self.stack.push(Style::from(PaletteColor::TitleSecondary)),
                     // TODO style quote?
                     Tag::BlockQuote => return Some(self.literal("> ")),
                     Tag::Link(_, _, _) => return Some(self.literal("[")),
-                    Tag::CodeBlock(_) => {
+                    Tag::Image(_, _, _) => return Some(self.literal("[image: ")),
+                    Tag::CodeBlock(kind) => {
                         self.stack.push(Style::from(PaletteColor::Secondary));
+                        self.code_block = Some(self.code_blocks_seen);
+                        self.code_blocks_seen += 1;
+                        self.code_line = 0;
+                        self.code = self
+                            .code_language(&kind)
+                            .map(|language| (String::new(), language));
+                        self.blocks.push(CodeBlock {
+                            text: self.rendered..self.rendered,
+                            code: String::new(),
+                        });
                     }
                     Tag::Strong => self.stack.push(Style::from(Effect::Bold)),
                     Tag::List(ix) => {
@@ -179,10 +500,20 @@ impl<'a> Iterator for Parser<'a> {
                     }
                     // TODO underline the link?
                     Tag::Link(_, link, _) => return Some(self.literal(format!("]({})", link))),
+                    Tag::Image(_, url, _) => return Some(self.literal(format!("]({})", url))),
                     Tag::CodeBlock(_) => {
+                        if let Some((code, language)) = self.code.take() {
+                            self.highlight_syntax(code, language);
+                        }
                         self.after_code_block = true;
+                        self.code_block = None;
                         self.stack.pop().unwrap();
-                        return Some(self.literal("\n"));
+                        let end = self.literal("\n");
+                        self.pending.push_back(end);
+                        let queued: usize = self.pending.iter().map(span_len).sum();
+                        if let Some(block) = self.blocks.last_mut() {
+                            block.text.end = self.rendered + queued;
+                        }
                     }
                     Tag::Emphasis | Tag::Strong => {
                         self.stack.pop().unwrap();
@@ -208,6 +539,13 @@ impl<'a> Iterator for Parser<'a> {
                         self.cowstr_to_span(text, Some(Style::from(PaletteColor::Secondary))),
                     );
                 }
+                // Highlighted once the whole block is in
+                Event::Text(text) if self.code.is_some() => {
+                    if let Some((code, _)) = self.code.as_mut() {
+                        code.push_str(&text);
+                    }
+                }
+                Event::Text(text) if self.in_highlighted_block() => self.highlight_code(text),
                 // Treat all other texts the same
                 Event::FootnoteReference(text) | Event::Html(text) | Event::Text(text) => {
                     return Some(self.cowstr_to_span(text, None));
@@ -226,8 +564,26 @@ impl<'a> Iterator for Parser<'a> {
 
 #[cfg(test)]
 mod tests {
+    use super::super::theme::Appearance;
     use super::*;
+    use crate::stackexchange::preprocess::{preprocess_with, Pass, DEFAULT_PASSES};
+    use cursive::theme::ColorType;
     use cursive::utils::span::Span;
+    use std::borrow::Cow;
+
+    fn preprocess(input: &str) -> Cow<'_, str> {
+        preprocess_with(input, DEFAULT_PASSES)
+    }
+
+    #[test]
+    fn test_entities_decoded_once() {
+        let decoded = Pass::Entities.apply(include_str!("../../test/markdown/entities.md"));
+        // Exactly once, also once the renderer decoded text itself
+        let parsed = parse(decoded.as_ref());
+        let rendered: String = parsed.spans().map(|span| span.content).collect();
+        assert!(rendered.contains("type &lt; and it shows as <."));
+        assert!(rendered.contains("To write a literal &lt; in HTML"));
+    }
 
     #[test]
     fn test_basic_styles() {
@@ -236,7 +592,7 @@ Attention
 ====
 I *really* love __Cursive__!";
         let parsed = parse(input);
-        let spans: Vec<_> = parsed.spans().into_iter().collect();
+        let spans: Vec<_> = parsed.spans().collect();
         let expected_spans = &[
             Span {
                 content: "Attention",
@@ -296,7 +652,7 @@ code fences
 ```
 Obviously.";
         let parsed = parse(input);
-        let spans: Vec<_> = parsed.spans().into_iter().collect();
+        let spans: Vec<_> = parsed.spans().collect();
         let expected_spans = &[
             Span {
                 content: "project",
@@ -403,7 +759,7 @@ and tasks
 - [x] done!
 ";
         let parsed = parse(input);
-        let spans: Vec<_> = parsed.spans().into_iter().collect();
+        let spans: Vec<_> = parsed.spans().collect();
         let expected_spans = &[
             Span {
                 content: "1. ",
@@ -485,43 +841,15 @@ and tasks
     fn test_entities() {
         let input = "
 I'm on a Mac running OS&nbsp;X&nbsp;v10.6 (Snow&nbsp;Leopard). I have Mercurial 1.1 installed.\r\n\r\nAfter I hit <kbd>Esc</kbd> to exit insert mode I can't figure out how to save and quit. Hitting <kbd>Ctrl</kbd> + <kbd>C</kbd> shows me instructions that say typing \"quit<enter>\" will write and quit, but it doesn't seem to work.\r\n\r\n\r\n\r\n".to_string();
-        let parsed = parse(preprocess(input));
-        let spans: Vec<_> = parsed.spans().into_iter().collect();
+        let parsed = parse(preprocess(&input));
+        let spans: Vec<_> = parsed.spans().collect();
         let expected_spans = &[
+            // Decoded by the entities pass, so not split up where each entity was
             Span {
-                content: "I\'m on a Mac running OS",
+                content: "I\'m on a Mac running OS\u{a0}X\u{a0}v10.6 (Snow\u{a0}Leopard). I have \
+                          Mercurial 1.1 installed.",
                 attr: &Style::none(),
-                width: 23,
-            },
-            Span {
-                content: "\u{a0}",
-                attr: &Style::none(),
-                width: 1,
-            },
-            Span {
-                content: "X",
-                attr: &Style::none(),
-                width: 1,
-            },
-            Span {
-                content: "\u{a0}",
-                attr: &Style::none(),
-                width: 1,
-            },
-            Span {
-                content: "v10.6 (Snow",
-                attr: &Style::none(),
-                width: 11,
-            },
-            Span {
-                content: "\u{a0}",
-                attr: &Style::none(),
-                width: 1,
-            },
-            Span {
-                content: "Leopard). I have Mercurial 1.1 installed.",
-                attr: &Style::none(),
-                width: 41,
+                width: 79,
             },
             Span {
                 content: "\n\n",
@@ -610,6 +938,154 @@ I'm on a Mac running OS&nbsp;X&nbsp;v10.6 (Snow&nbsp;Leopard). I have Mercurial
         }
     }
 
+    #[test]
+    fn test_code_highlights() {
+        let input = "```\nfn main() {}\n```\n\ntext\n\n```\nlet a = 1;\nlet b = 2;\n```";
+        let highlights = [CodeMatch { block: 1, line: 1 }];
+        let parsed = parse_with_highlights(input, &highlights);
+        let code = Style::from(PaletteColor::Secondary);
+        let highlighted = code.combine(Effect::Reverse);
+        let spans: Vec<_> = parsed
+            .spans()
+            .filter(|s| s.attr == &code || s.attr == &highlighted)
+            .map(|s| (s.content, s.attr == &highlighted))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("fn main() {}\n", false),
+                ("let a = 1;\n", false),
+                ("let b = 2;\n", true),
+            ]
+        );
+        // Without highlights, the output is unchanged
+        assert_eq!(
+            parse_with_highlights(input, &[])
+                .spans()
+                .collect::<Vec<_>>(),
+            parse(input).spans().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_syntax_highlighting() {
+        let highlighter = syntax::Highlighter::new(syntax::AUTO, Appearance::Dark);
+        let tags = vec![String::from("python")];
+        let with = |input: &str, highlights: &[CodeMatch], tags: &[String]| {
+            let syntax = Syntax {
+                highlighter: &highlighter,
+                tags,
+            };
+            parse_highlighted(input, highlights, &[], Some(syntax))
+        };
+        let rgb = |parsed: &StyledString| {
+            parsed
+                .spans()
+                .filter(|s| matches!(s.attr.color, Some(c) if matches!(c.front, ColorType::Color(Color::Rgb(..)))))
+                .count()
+        };
+
+        // Fenced, the text stays the same but comes in many colors
+        let input = "```rust\nfn main() {}\n```\n\ntext";
+        let parsed = with(input, &[], &[]);
+        assert_eq!(parsed.source(), parse(input).source());
+        assert!(rgb(&parsed) > 2);
+        let text: String = parsed.spans().map(|s| s.content).collect();
+        let plain: String = parse(input).spans().map(|s| s.content).collect();
+        assert_eq!(text, plain);
+
+        // Hinted, or by the question's tags, or not at all
+        let bare = "```\nfn main() {}\n```";
+        assert_eq!(rgb(&with(bare, &[], &[])), 0);
+        assert!(
+            rgb(&with(
+                &format!("<!-- language: lang-rust -->\n\n{}", bare),
+                &[],
+                &[]
+            )) > 2
+        );
+        assert!(rgb(&with("```\ndef f(): pass\n```", &[], &tags)) > 2);
+        assert_eq!(rgb(&with("```text\ndef f(): pass\n```", &[], &tags)), 0);
+
+        // Code matches are still reversed
+        let parsed = with(
+            "```rust\nlet a = 1;\nlet b = 2;\n```",
+            &[CodeMatch { block: 0, line: 1 }],
+            &[],
+        );
+        let reversed: String = parsed
+            .spans()
+            .filter(|s| s.attr.effects.contains(Effect::Reverse))
+            .map(|s| s.content)
+            .collect();
+        assert_eq!(reversed, "let b = 2;\n");
+    }
+
+    #[test]
+    fn test_parse_with_blocks() {
+        let input = "Try\n\n```\nfn main() {}\n```\n\n>! ```\n>! hidden\n>! ```\n\n    let a = 1;\n    let b = 2;\n\ndone";
+        let (parsed, blocks) = parse_with_blocks(input, &[], &[], None);
+        let text: String = parsed.spans().map(|s| s.content).collect();
+        assert_eq!(parsed.source(), parse(input).source());
+        // The one in the collapsed spoiler isn't shown
+        assert_eq!(
+            blocks.iter().map(|b| b.code.as_str()).collect::<Vec<_>>(),
+            vec!["fn main() {}\n", "let a = 1;\nlet b = 2;\n"]
+        );
+        assert_eq!(
+            blocks.iter().map(CodeBlock::lines).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(&text[blocks[0].text.clone()], "fn main() {}\n\n");
+        assert_eq!(&text[blocks[1].text.clone()], "let a = 1;\nlet b = 2;\n\n");
+
+        // Revealed, it is, and the ranges are the same with highlighted syntax
+        let highlighter = syntax::Highlighter::new(syntax::AUTO, Appearance::Dark);
+        let syntax = Syntax {
+            highlighter: &highlighter,
+            tags: &[String::from("rust")],
+        };
+        let (highlighted, revealed) = parse_with_blocks(input, &[], &[0], Some(syntax));
+        let text: String = highlighted.spans().map(|s| s.content).collect();
+        assert_eq!(revealed.len(), 3);
+        assert_eq!(revealed[1].code, "hidden\n");
+        for block in &revealed {
+            assert!(text[block.text.clone()].starts_with(&block.code));
+        }
+        assert_eq!(parse_with_blocks(input, &[], &[0], None).1, revealed);
+    }
+
+    #[test]
+    fn test_images() {
+        let input = "See ![the *settings* dialog](https://i.sstatic.net/a.png) and \
+            [![](https://i.sstatic.net/b.gif)](https://example.com)";
+        assert_eq!(
+            parse(input).spans().map(|s| s.content).collect::<String>(),
+            "See [image: the settings dialog](https://i.sstatic.net/a.png) and \
+            [[image: ](https://i.sstatic.net/b.gif)](https://example.com)\n\n"
+        );
+        let found = images(input);
+        assert_eq!(
+            found,
+            vec![
+                Image {
+                    alt: String::from("the settings dialog"),
+                    url: String::from("https://i.sstatic.net/a.png"),
+                },
+                Image {
+                    alt: String::new(),
+                    url: String::from("https://i.sstatic.net/b.gif"),
+                },
+            ]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            "the settings dialog: https://i.sstatic.net/a.png"
+        );
+        assert_eq!(found[1].to_string(), "https://i.sstatic.net/b.gif");
+        assert!(images("no [images](https://example.com) here").is_empty());
+    }
+
     #[test]
     // It appears pulldown_cmark sometimes replaces \t with a space and still
     // calls it "borrowed", but the pointer values show otherwise.
@@ -617,7 +1093,7 @@ I'm on a Mac running OS&nbsp;X&nbsp;v10.6 (Snow&nbsp;Leopard). I have Mercurial
         let input =
             "1. Run the commands below, and compare the outputs\r\n\r\n\t\tsudo cat /etc/shadow";
         let parsed = parse(input);
-        let spans: Vec<_> = parsed.spans().into_iter().collect();
+        let spans: Vec<_> = parsed.spans().collect();
         let expected_spans = &[
             Span {
                 content: "1. ",
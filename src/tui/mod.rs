@@ -1,5 +1,27 @@
+mod actions;
 mod app;
+mod code_blocks;
+mod comments;
+mod console;
+mod diff;
+mod editor;
+mod fuzzy;
+pub mod graphics;
+mod lazy_list;
+mod loading;
+mod local_file;
 pub mod markdown;
+mod pins;
+mod prefetch;
+mod search_again;
+mod share;
+mod shown;
+pub mod spoilers;
+pub mod syntax;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod theme;
+mod updater;
 mod views;
 
 pub use app::run;
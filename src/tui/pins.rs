@@ -0,0 +1,118 @@
+//! Questions pinned to the top of the question list for the rest of the TUI session.
+
+use crate::stackexchange::Question;
+
+/// Pinned questions, in the order they were pinned
+#[derive(Debug, Clone)]
+pub struct Pins<T> {
+    questions: Vec<Question<T>>,
+}
+
+impl<T> Default for Pins<T> {
+    fn default() -> Self {
+        Pins {
+            questions: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> Pins<T> {
+    /// Pin `q`, or unpin it if it is already pinned. Returns whether `q` is now pinned.
+    pub fn toggle(&mut self, q: &Question<T>) -> bool {
+        match self.questions.iter().position(|p| p.id == q.id) {
+            Some(ix) => {
+                self.questions.remove(ix);
+                false
+            }
+            None => {
+                self.questions.push(q.clone());
+                true
+            }
+        }
+    }
+
+    pub fn is_pinned(&self, id: u32) -> bool {
+        self.questions.iter().any(|p| p.id == id)
+    }
+
+    /// Pinned questions, as they were when pinned
+    pub fn iter(&self) -> impl Iterator<Item = &Question<T>> {
+        self.questions.iter()
+    }
+
+    /// Put pinned questions ahead of `fresh` results; see [`merge`]
+    pub fn merge(&self, fresh: Vec<Question<T>>) -> Vec<Question<T>> {
+        merge(&self.questions, fresh)
+    }
+}
+
+/// Merge `pinned` questions ahead of `fresh` results.
+///
+/// A pinned question that also shows up in `fresh` is listed once, at its pinned position, but
+/// with the fresh copy's data since that is more up to date. Pinned questions missing from `fresh`
+/// are kept as they were last fetched.
+pub fn merge<T: Clone>(pinned: &[Question<T>], mut fresh: Vec<Question<T>>) -> Vec<Question<T>> {
+    let mut merged: Vec<Question<T>> = pinned
+        .iter()
+        .map(|p| match fresh.iter().position(|q| q.id == p.id) {
+            Some(ix) => fresh.remove(ix),
+            None => p.clone(),
+        })
+        .collect();
+    merged.append(&mut fresh);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(id: u32, title: &str) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            title: title.to_string(),
+            body: Some(String::new()),
+            site: String::from("stackoverflow"),
+            ..Question::default()
+        }
+    }
+
+    fn ids(qs: &[Question<String>]) -> Vec<u32> {
+        qs.iter().map(|q| q.id).collect()
+    }
+
+    #[test]
+    fn test_merge_pinned_first() {
+        let pinned = vec![question(7, "old"), question(3, "old")];
+        let fresh = vec![question(1, "new"), question(2, "new")];
+        assert_eq!(ids(&merge(&pinned, fresh)), vec![7, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_merge_dedups_fresh() {
+        let pinned = vec![question(2, "old"), question(9, "old")];
+        let fresh = vec![question(1, "new"), question(2, "new"), question(3, "new")];
+        let merged = merge(&pinned, fresh);
+        assert_eq!(ids(&merged), vec![2, 9, 1, 3]);
+        assert_eq!(merged[0].title, "new");
+        assert_eq!(merged[1].title, "old");
+    }
+
+    #[test]
+    fn test_merge_nothing_pinned() {
+        let fresh = vec![question(1, "new"), question(2, "new")];
+        assert_eq!(ids(&merge(&[], fresh)), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut pins = Pins::default();
+        assert!(pins.toggle(&question(1, "a")));
+        assert!(pins.toggle(&question(2, "b")));
+        assert!(pins.is_pinned(1));
+        assert!(!pins.toggle(&question(1, "a")));
+        assert!(!pins.is_pinned(1));
+        assert_eq!(ids(&pins.merge(vec![question(3, "c")])), vec![2, 3]);
+    }
+}
@@ -0,0 +1,357 @@
+//! Idle time prefetching of whatever the TUI would otherwise load when a question is selected.
+//!
+//! A single background task works through the question list in display order, starting from the
+//! selected question and wrapping around to the ones above it. Only one fetch runs at a time, so
+//! prefetching never competes with itself for connections. Selecting another question preempts
+//! the fetch in progress unless it's still the most pressing one, and a user-initiated request
+//! pauses prefetching altogether until it's done. Preempted fetches go back in the queue.
+//! Dropping the `Prefetcher` cancels everything, e.g. when the TUI quits.
+
+use futures::future::BoxFuture;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Loads the lazy data of a question
+pub trait Fetch: Send + Sync + 'static {
+    fn fetch(&self, question_id: u32) -> BoxFuture<'static, ()>;
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Position of the selected question
+    selected: usize,
+    /// Number of user-initiated requests in flight
+    busy: usize,
+    /// Positions already fetched
+    done: HashSet<usize>,
+}
+
+impl State {
+    /// Position to fetch next, if any: the first one not yet fetched at or below the selection,
+    /// or else above it
+    fn next(&self, len: usize) -> Option<usize> {
+        if self.busy > 0 {
+            return None;
+        }
+        (self.selected..len)
+            .chain(0..self.selected.min(len))
+            .find(|pos| !self.done.contains(pos))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Wakes the scheduler when the selection or busy count changes
+    changed: Notify,
+}
+
+impl Shared {
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+        self.changed.notify_one();
+    }
+
+    fn next(&self, len: usize) -> Option<usize> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .next(len)
+    }
+
+    /// Whether all `len` questions have been fetched
+    fn finished(&self, len: usize) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .done
+            .len()
+            == len
+    }
+}
+
+/// Handle on the background prefetching task; dropping it cancels the task
+pub struct Prefetcher {
+    ids: Vec<u32>,
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl Prefetcher {
+    /// Start prefetching the questions `ids`, in display order, with the first one selected.
+    /// Must be called within a tokio runtime.
+    pub fn start(ids: Vec<u32>, fetcher: Arc<dyn Fetch>) -> Self {
+        let shared = Arc::new(Shared::default());
+        let task = tokio::spawn(run(ids.clone(), fetcher, Arc::clone(&shared)));
+        Prefetcher { ids, shared, task }
+    }
+
+    /// Prioritize the questions from `question_id` on
+    pub fn select(&self, question_id: u32) {
+        if let Some(pos) = self.ids.iter().position(|&id| id == question_id) {
+            self.shared.update(|state| state.selected = pos);
+        }
+    }
+
+    /// Pause prefetching for as long as the returned guard is alive, e.g. while the user waits
+    /// on a request of their own
+    pub fn user_request(&self) -> Busy {
+        self.shared.update(|state| state.busy += 1);
+        Busy(Arc::clone(&self.shared))
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Keeps prefetching paused until dropped
+pub struct Busy(Arc<Shared>);
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.update(|state| state.busy -= 1);
+    }
+}
+
+async fn run(ids: Vec<u32>, fetcher: Arc<dyn Fetch>, shared: Arc<Shared>) {
+    loop {
+        let pos = match shared.next(ids.len()) {
+            Some(pos) => pos,
+            None if shared.finished(ids.len()) => return,
+            None => {
+                shared.changed.notified().await;
+                continue;
+            }
+        };
+        let mut fetch = fetcher.fetch(ids[pos]);
+        let fetched = loop {
+            tokio::select! {
+                _ = &mut fetch => break true,
+                _ = shared.changed.notified() => {
+                    // Preempted; dropping the fetch cancels it, and it stays in the queue
+                    if shared.next(ids.len()) != Some(pos) {
+                        break false;
+                    }
+                }
+            }
+        };
+        if fetched {
+            shared.update(|state| {
+                state.done.insert(pos);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+    use Event::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Event {
+        Start(u32),
+        Done(u32),
+        Cancel(u32),
+    }
+
+    /// Fetcher recording what happens to each fetch, which completes once `release`d
+    struct MockFetcher {
+        log: Arc<Mutex<Vec<Event>>>,
+        gate: Arc<Semaphore>,
+    }
+
+    /// Logs a cancellation when a fetch is dropped before completing
+    struct Pending {
+        id: u32,
+        log: Arc<Mutex<Vec<Event>>>,
+        done: bool,
+    }
+
+    impl Drop for Pending {
+        fn drop(&mut self) {
+            if !self.done {
+                self.log.lock().unwrap().push(Event::Cancel(self.id));
+            }
+        }
+    }
+
+    impl Fetch for MockFetcher {
+        fn fetch(&self, id: u32) -> BoxFuture<'static, ()> {
+            let log = Arc::clone(&self.log);
+            let gate = Arc::clone(&self.gate);
+            async move {
+                log.lock().unwrap().push(Event::Start(id));
+                let mut pending = Pending {
+                    id,
+                    log: Arc::clone(&log),
+                    done: false,
+                };
+                gate.acquire().await.unwrap().forget();
+                pending.done = true;
+                log.lock().unwrap().push(Event::Done(id));
+            }
+            .boxed()
+        }
+    }
+
+    impl MockFetcher {
+        fn new() -> Arc<Self> {
+            Arc::new(MockFetcher {
+                log: Arc::default(),
+                gate: Arc::new(Semaphore::new(0)),
+            })
+        }
+
+        /// Let `n` more fetches complete
+        fn release(&self, n: usize) {
+            self.gate.add_permits(n);
+        }
+
+        fn log(&self) -> Vec<Event> {
+            self.log.lock().unwrap().clone()
+        }
+
+        /// Wait until the log has `len` events, and return it
+        async fn wait_for(&self, len: usize) -> Vec<Event> {
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while self.log().len() < len {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            })
+            .await
+            .unwrap_or_else(|_| panic!("timed out waiting for {} events: {:?}", len, self.log()));
+            self.log()
+        }
+    }
+
+    /// Give the scheduler a chance to do anything it shouldn't
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn test_display_order() {
+        let fetcher = MockFetcher::new();
+        fetcher.release(3);
+        let prefetcher = Prefetcher::start(vec![10, 20, 30], fetcher.clone());
+        let log = fetcher.wait_for(6).await;
+        assert_eq!(
+            log,
+            vec![
+                Start(10),
+                Done(10),
+                Start(20),
+                Done(20),
+                Start(30),
+                Done(30)
+            ]
+        );
+        // Nothing is fetched twice
+        prefetcher.select(10);
+        settle().await;
+        assert_eq!(fetcher.log().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_preemption() {
+        let fetcher = MockFetcher::new();
+        let prefetcher = Prefetcher::start(vec![10, 20, 30, 40], fetcher.clone());
+        assert_eq!(fetcher.wait_for(1).await, vec![Start(10)]);
+
+        // Jumping ahead cancels the fetch in progress, which is picked up again after wrapping
+        // around the end of the list
+        prefetcher.select(30);
+        assert_eq!(fetcher.wait_for(3).await[1..], [Cancel(10), Start(30)]);
+        fetcher.release(4);
+        let log = fetcher.wait_for(10).await;
+        assert_eq!(
+            log[3..],
+            [
+                Done(30),
+                Start(40),
+                Done(40),
+                Start(10),
+                Done(10),
+                Start(20),
+                Done(20)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_selection_of_current_fetch() {
+        let fetcher = MockFetcher::new();
+        let prefetcher = Prefetcher::start(vec![10, 20], fetcher.clone());
+        fetcher.wait_for(1).await;
+        // Still the most pressing fetch, so it carries on
+        prefetcher.select(10);
+        settle().await;
+        fetcher.release(2);
+        assert_eq!(
+            fetcher.wait_for(4).await,
+            vec![Start(10), Done(10), Start(20), Done(20)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_request_pauses() {
+        let fetcher = MockFetcher::new();
+        let prefetcher = Prefetcher::start(vec![10, 20], fetcher.clone());
+        fetcher.wait_for(1).await;
+
+        let busy = prefetcher.user_request();
+        assert_eq!(fetcher.wait_for(2).await, vec![Start(10), Cancel(10)]);
+        fetcher.release(2);
+        settle().await;
+        assert_eq!(fetcher.log().len(), 2);
+
+        drop(busy);
+        assert_eq!(
+            fetcher.wait_for(6).await[2..],
+            [Start(10), Done(10), Start(20), Done(20)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_on_drop() {
+        let fetcher = MockFetcher::new();
+        let prefetcher = Prefetcher::start(vec![10, 20], fetcher.clone());
+        fetcher.wait_for(1).await;
+        drop(prefetcher);
+        assert_eq!(fetcher.wait_for(2).await, vec![Start(10), Cancel(10)]);
+        fetcher.release(2);
+        settle().await;
+        assert_eq!(fetcher.log().len(), 2);
+    }
+
+    #[test]
+    fn test_next() {
+        let mut state = State {
+            selected: 2,
+            ..State::default()
+        };
+        assert_eq!(state.next(4), Some(2));
+        state.done.extend(vec![2, 3]);
+        assert_eq!(state.next(4), Some(0));
+        state.busy = 1;
+        assert_eq!(state.next(4), None);
+        state.busy = 0;
+        state.done.extend(vec![0, 1]);
+        assert_eq!(state.next(4), None);
+        // A selection past the end of a shrunk list doesn't skip anything
+        let state = State {
+            selected: 5,
+            ..State::default()
+        };
+        assert_eq!(state.next(2), Some(0));
+    }
+}
@@ -0,0 +1,164 @@
+//! Searching again from within the TUI, for another query, without leaving it.
+//!
+//! Pressing `/` prompts for a query, the current one to start with, and searches for it in the
+//! background with the search that found the listed results, so its site list, clients and caches
+//! carry over. The listed results stay up, and can be browsed, until the new ones are in and take
+//! their place; meanwhile the status bar says what's being searched for. Only one search runs at
+//! a time, through a `loading::Pane`, so searching again cancels a search nobody waits on anymore.
+//! A search that fails, or finds nothing, leaves the listed results as they are and says so in the
+//! status bar.
+
+use cursive::theme::{BaseColor, Color, Effect};
+use cursive::utils::markup::StyledString;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+use super::loading::{self, Pane, Phase, Ticket};
+use super::markdown::Markdown;
+use crate::error::{Error, Result};
+use crate::stackexchange::filtered::Filtered;
+use crate::stackexchange::quota::Reading;
+use crate::stackexchange::Search;
+
+/// Key to search again
+pub const SEARCH_KEY: char = '/';
+
+/// Searches for a query, as the TUI lists the results
+pub trait Searcher: Send + Sync + 'static {
+    fn search(&self, query: &str) -> BoxFuture<'static, Result<Filtered<Markdown>>>;
+
+    /// How much API quota is left, if it's running low
+    fn low_quota(&self) -> Option<Reading> {
+        None
+    }
+}
+
+impl Searcher for Search {
+    fn search(&self, query: &str) -> BoxFuture<'static, Result<Filtered<Markdown>>> {
+        let search = self.clone();
+        let query = query.to_string();
+        Box::pin(async move { search.search_md(&query).await })
+    }
+
+    fn low_quota(&self) -> Option<Reading> {
+        Search::low_quota(self)
+    }
+}
+
+/// Where searches run, and the runtime they run on, since the TUI runs outside of one
+#[derive(Clone)]
+pub struct Source {
+    pub searcher: Arc<dyn Searcher>,
+    pub runtime: Handle,
+}
+
+/// Searches of the session; without a `Source`, there's no searching again
+#[derive(Default)]
+pub struct SearchAgain {
+    source: Option<Source>,
+    /// Query of the latest search started
+    query: String,
+    /// Searches started so far, which tells their loads apart
+    started: u32,
+    pane: Pane<()>,
+}
+
+impl SearchAgain {
+    pub fn new(source: Source) -> Self {
+        SearchAgain {
+            source: Some(source),
+            ..SearchAgain::default()
+        }
+    }
+
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// How much API quota searches have left, if it's running low
+    pub fn low_quota(&self) -> Option<Reading> {
+        self.source.as_ref()?.searcher.low_quota()
+    }
+
+    /// Start searching for `query`, cancelling the search in progress. Returns the load to run.
+    pub fn start(&mut self, query: &str) -> Option<Ticket> {
+        self.started += 1;
+        self.query = query.to_string();
+        self.pane.select(self.started)
+    }
+
+    /// Take the `result` of the search `ticket` was issued for, the results themselves going to
+    /// the question list. Returns whether it was taken, which it isn't if another search
+    /// superseded it.
+    pub fn finish(&mut self, ticket: &Ticket, result: std::result::Result<(), String>) -> bool {
+        self.pane.finish(ticket, result)
+    }
+
+    /// Move the spinner on. Returns whether there's still a search running.
+    pub fn tick(&mut self) -> bool {
+        self.pane.tick()
+    }
+
+    /// What the status bar says about the latest search: that it's running, or why it failed;
+    /// nothing once its results are listed
+    pub fn status(&self) -> StyledString {
+        match self.pane.phase() {
+            Phase::Loading { .. } => {
+                let spinner = loading::spinner(&self.pane).unwrap_or(' ');
+                let mut status = StyledString::styled(
+                    format!("{} searching for \"{}\"", spinner, self.query),
+                    Effect::Reverse,
+                );
+                status.append_plain("  ");
+                status
+            }
+            Phase::Failed { error, .. } => {
+                let mut status = StyledString::styled(
+                    format!("couldn't search for \"{}\": {}", self.query, error),
+                    Color::Light(BaseColor::Red),
+                );
+                status.append_plain(format!(" (press {} to try again)  ", SEARCH_KEY));
+                status
+            }
+            Phase::Idle | Phase::Ready { .. } => StyledString::new(),
+        }
+    }
+}
+
+/// Why a search failed, as the status bar puts it
+pub fn failure(error: &Error) -> String {
+    match error {
+        Error::NoResults => String::from("no results"),
+        error => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let mut searches = SearchAgain::default();
+        assert_eq!(searches.status().source(), "");
+        let first = searches.start("exit vim").unwrap();
+        assert_eq!(searches.status().source(), "| searching for \"exit vim\"  ");
+        searches.tick();
+        assert_eq!(searches.status().source(), "/ searching for \"exit vim\"  ");
+
+        // Searching again supersedes the running search, whose results are dropped
+        let second = searches.start("quit emacs").unwrap();
+        assert!(!searches.finish(&first, Ok(())));
+        assert!(searches.finish(&second, Err(failure(&Error::NoResults))));
+        assert_eq!(
+            searches.status().source(),
+            "couldn't search for \"quit emacs\": no results (press / to try again)  "
+        );
+
+        // The same query again is searched again
+        let third = searches.start("quit emacs").unwrap();
+        assert!(searches.finish(&third, Ok(())));
+        assert_eq!(searches.status().source(), "");
+    }
+}
@@ -0,0 +1,171 @@
+//! Markdown summaries of a search to share, e.g. in a team chat or a gist.
+//!
+//! `S` sums up the listed questions: the query and the date on top, then for each question its
+//! title, linked, and the gist of its best answer — the first code block, or else the first
+//! sentence — with a link back to that answer. Spoilers stay hidden. Questions that would make the
+//! summary longer than `share_max_chars` are left out, and counted in a last line instead.
+
+use super::markdown::Markdown;
+use super::spoilers;
+use crate::output;
+use crate::stackexchange::{snippets, Question};
+
+/// Lines of code a question gets at most
+const MAX_CODE_LINES: usize = 10;
+
+/// Summary of the search for `query` on `date`, with as many of `questions` as fit in `max_chars`
+pub fn summary(
+    query: &str,
+    date: &str,
+    questions: &[Question<Markdown>],
+    max_chars: usize,
+) -> String {
+    let mut summary = format!("## so: {}\n{}\n\n", query, date);
+    let mut length = summary.chars().count();
+    for (i, q) in questions.iter().enumerate() {
+        let entry = entry(q);
+        let left = questions.len() - i - 1;
+        let room = match left {
+            0 => 0,
+            _ => more(left).chars().count(),
+        };
+        let entry_length = entry.chars().count();
+        if length + entry_length + room > max_chars {
+            summary.push_str(&more(questions.len() - i));
+            return summary;
+        }
+        summary.push_str(&entry);
+        length += entry_length;
+    }
+    summary
+}
+
+/// Last line of a summary that left out `n` questions
+fn more(n: usize) -> String {
+    format!("…and {} more\n", n)
+}
+
+/// `q` as a list item
+fn entry(q: &Question<Markdown>) -> String {
+    let mut entry = match &q.link {
+        Some(link) => format!("- [{}]({})\n", q.title, link),
+        None => format!("- {}\n", q.title),
+    };
+    let answer = match output::best_answer(q) {
+        Some(answer) => answer,
+        None => {
+            entry.push_str("  No answers yet\n");
+            return entry;
+        }
+    };
+    let md = spoilers::omit(answer.body.source());
+    match snippets::code_blocks(&md).first() {
+        Some(code) => entry.push_str(&code_block(code)),
+        None => entry.push_str(&format!("  > {}\n", snippets::first_sentence(&md))),
+    }
+    if let Some(link) = &answer.link {
+        let which = if answer.is_accepted {
+            "accepted"
+        } else {
+            "top"
+        };
+        entry.push_str(&format!("  — [{} answer]({})\n", which, link));
+    }
+    entry
+}
+
+/// `code`, fenced and indented into a list item, cut short after `MAX_CODE_LINES`
+fn code_block(code: &str) -> String {
+    let lines: Vec<&str> = code.trim_end_matches('\n').lines().collect();
+    // Longer than any run of backticks in the code, so that none of them ends the block
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest + 1).max(3));
+    let mut block = format!("  {}\n", fence);
+    for line in lines.iter().take(MAX_CODE_LINES) {
+        match line.is_empty() {
+            true => block.push('\n'),
+            false => block.push_str(&format!("  {}\n", line)),
+        }
+    }
+    block.push_str(&format!("  {}\n", fence));
+    if lines.len() > MAX_CODE_LINES {
+        block.push_str(&format!(
+            "  ({} more lines)\n",
+            lines.len() - MAX_CODE_LINES
+        ));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{self, assert_snapshot};
+    use super::*;
+
+    const DATE: &str = "2026-10-17";
+
+    fn linked() -> Vec<Question<Markdown>> {
+        let mut qs = testing::questions();
+        for q in &mut qs {
+            q.link = Some(format!("https://stackoverflow.com/questions/{}", q.id));
+            for a in &mut q.answers {
+                a.link = Some(format!("https://stackoverflow.com/a/{}", a.id));
+            }
+        }
+        qs
+    }
+
+    #[test]
+    fn test_summary_snapshot() {
+        let qs = linked();
+        let summary = summary("how do i exit vim", DATE, &qs, usize::MAX);
+        assert_snapshot("share_summary", &summary);
+    }
+
+    #[test]
+    fn test_summary_truncated_snapshot() {
+        let qs = linked();
+        let full = summary("how do i exit vim", DATE, &qs, usize::MAX);
+        let summary = summary("how do i exit vim", DATE, &qs, 300);
+        assert!(summary.chars().count() <= 300);
+        assert!(summary.len() < full.len());
+        assert_snapshot("share_summary_truncated", &summary);
+    }
+
+    #[test]
+    fn test_nothing_fits() {
+        let qs = linked();
+        assert_eq!(
+            summary("vim", DATE, &qs, 0),
+            format!("## so: vim\n{}\n\n…and {} more\n", DATE, qs.len())
+        );
+    }
+
+    #[test]
+    fn test_code_block() {
+        assert_eq!(code_block(":q!\n"), "  ```\n  :q!\n  ```\n");
+        // Fenced so that the backticks inside don't end it
+        assert_eq!(code_block("```\n\nx\n"), "  ````\n  ```\n\n  x\n  ````\n");
+        let long: String = (0..12).map(|i| format!("{}\n", i)).collect();
+        assert!(code_block(&long).ends_with("  9\n  ```\n  (2 more lines)\n"));
+    }
+
+    #[test]
+    fn test_spoilers_left_out() {
+        let q = testing::question(
+            1,
+            1,
+            "Who wrote it?",
+            "",
+            vec![testing::answer(
+                2,
+                1,
+                false,
+                "It's a classic.\n\n>! The butler did it.\n",
+            )],
+        );
+        let summary = summary("who", DATE, &[q], usize::MAX);
+        assert!(summary.contains("It's a classic."));
+        assert!(!summary.contains("butler"));
+    }
+}
@@ -0,0 +1,165 @@
+//! Questions already listed during this TUI session, so that later result lists in the same
+//! session can leave them out.
+//!
+//! This only lasts as long as the TUI does, unlike the `last_seen` dates of notes, which persist
+//! across sessions. Pinned questions are never left out, so anything worth coming back to stays
+//! reachable.
+
+use std::collections::HashSet;
+
+use super::pins::Pins;
+use crate::stackexchange::filtered::{Filtered, Reason};
+
+#[derive(Debug, Default)]
+pub struct Shown {
+    /// Site and id of every question listed so far
+    seen: HashSet<(String, u32)>,
+    /// Leave previously listed questions out of new result lists
+    hide: bool,
+}
+
+impl Shown {
+    /// Start or stop hiding previously listed questions. Returns whether they're now hidden.
+    pub fn toggle(&mut self) -> bool {
+        self.hide = !self.hide;
+        self.hide
+    }
+
+    pub fn hiding(&self) -> bool {
+        self.hide
+    }
+
+    /// New result list to show for `fresh` results: without the questions listed before if
+    /// hiding them, except for pinned ones. Whatever is still shown counts as listed from now on.
+    pub fn filter<T: Clone>(&mut self, fresh: Filtered<T>, pins: &Pins<T>) -> Filtered<T> {
+        let hide = self.hide;
+        let seen = &self.seen;
+        let kept = fresh.filter(Reason::ListedBefore, |q| {
+            !hide || pins.is_pinned(q.id) || !seen.contains(&(q.site.clone(), q.id))
+        });
+        self.seen
+            .extend(kept.shown.iter().map(|q| (q.site.clone(), q.id)));
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::Question;
+
+    fn question(site: &str, id: u32) -> Question<String> {
+        Question {
+            id,
+            score: 1,
+            title: format!("Question {}", id),
+            body: Some(String::new()),
+            site: site.to_string(),
+            ..Question::default()
+        }
+    }
+
+    /// Ids of the questions `shown` keeps out of `qs`, and how many it leaves out
+    fn filter(
+        shown: &mut Shown,
+        qs: Vec<Question<String>>,
+        pins: &Pins<String>,
+    ) -> (Vec<u32>, usize) {
+        let filtered = shown.filter(Filtered::new(qs), pins);
+        (
+            filtered.shown.iter().map(|q| q.id).collect(),
+            filtered.hidden.len(),
+        )
+    }
+
+    #[test]
+    fn test_hide_previously_shown() {
+        let mut shown = Shown::default();
+        let pins = Pins::default();
+        let first = vec![question("stackoverflow", 1), question("stackoverflow", 2)];
+        assert_eq!(filter(&mut shown, first, &pins), (vec![1, 2], 0));
+
+        // Not hiding yet, so everything is listed again
+        let second = vec![question("stackoverflow", 2), question("stackoverflow", 3)];
+        assert_eq!(filter(&mut shown, second.clone(), &pins), (vec![2, 3], 0));
+
+        assert!(shown.toggle());
+        let third = vec![
+            question("stackoverflow", 1),
+            question("stackoverflow", 3),
+            question("stackoverflow", 4),
+        ];
+        assert_eq!(filter(&mut shown, third.clone(), &pins), (vec![4], 2));
+
+        // Everything listed so far, hidden or not, is still tracked when hiding stops
+        assert!(!shown.toggle());
+        assert_eq!(filter(&mut shown, third, &pins), (vec![1, 3, 4], 0));
+        assert!(shown.toggle());
+        assert_eq!(filter(&mut shown, second, &pins), (vec![], 2));
+    }
+
+    #[test]
+    fn test_left_out_with_reason() {
+        let mut shown = Shown::default();
+        let pins = Pins::default();
+        shown.toggle();
+        let first = Filtered::new(vec![question("stackoverflow", 1)]);
+        shown.filter(first, &pins);
+        let again = Filtered::new(vec![
+            question("stackoverflow", 1),
+            question("stackoverflow", 2),
+        ])
+        .filter(Reason::Unanswered, |q| q.id != 2);
+        let filtered = shown.filter(again, &pins);
+        assert!(filtered.shown.is_empty());
+        let hidden: Vec<_> = filtered
+            .hidden
+            .iter()
+            .map(|h| (h.question.id, h.reason))
+            .collect();
+        assert_eq!(
+            hidden,
+            vec![(2, Reason::Unanswered), (1, Reason::ListedBefore)]
+        );
+    }
+
+    #[test]
+    fn test_pinned_stay_reachable() {
+        let mut shown = Shown::default();
+        let mut pins = Pins::default();
+        shown.toggle();
+        filter(
+            &mut shown,
+            vec![question("stackoverflow", 1), question("stackoverflow", 2)],
+            &pins,
+        );
+        pins.toggle(&question("stackoverflow", 1));
+        let again = vec![question("stackoverflow", 1), question("stackoverflow", 2)];
+        assert_eq!(filter(&mut shown, again, &pins), (vec![1], 1));
+    }
+
+    #[test]
+    fn test_same_id_other_site() {
+        let mut shown = Shown::default();
+        shown.toggle();
+        let pins = Pins::default();
+        filter(&mut shown, vec![question("stackoverflow", 1)], &pins);
+        let other_site = vec![question("superuser", 1)];
+        assert_eq!(filter(&mut shown, other_site, &pins), (vec![1], 0));
+    }
+
+    #[test]
+    fn test_session_scope() {
+        // A fresh session has seen nothing, whatever earlier sessions (or notes) saw
+        let pins = Pins::default();
+        let mut earlier = Shown::default();
+        earlier.toggle();
+        filter(&mut earlier, vec![question("stackoverflow", 1)], &pins);
+        let mut session = Shown::default();
+        session.toggle();
+        assert_eq!(
+            filter(&mut session, vec![question("stackoverflow", 1)], &pins),
+            (vec![1], 0)
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Spoilers, which StackExchange markdown writes as lines starting with `>!`.
+//!
+//! Outside of code blocks, a run of such lines is one spoiler. The TUI collapses each one into a line
+//! saying it's there, and `s` reveals a post's spoilers one at a time, the first hidden one
+//! first; once they're all revealed, it collapses them again. Lucky mode leaves spoilers out
+//! unless run with `--show-spoilers`, which prints them as block quotes.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use super::markdown::{self, CodeBlock, Markdown};
+use super::syntax::Syntax;
+use crate::stackexchange::CodeMatch;
+use crate::stackexchange::{isolation, preprocess};
+
+/// Where `mark` starts a spoiler, for `markdown::Parser` to pick up
+pub const START: &str = "<!-- so:spoiler -->";
+/// Where `mark` ends a spoiler
+pub const END: &str = "<!-- so:/spoiler -->";
+
+/// Shown in place of a collapsed spoiler
+pub const COLLAPSED: &str = "▶ spoiler — press s to reveal";
+/// Shown on top of a revealed spoiler
+pub const REVEALED: &str = "▼ spoiler";
+
+/// A spoiler in markdown
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spoiler {
+    /// Bytes of its lines, line breaks included
+    pub range: Range<usize>,
+    /// Its markdown, without the `>!` of each line
+    pub content: String,
+}
+
+/// Spoilers in `md`, in order
+pub fn find(md: &str) -> Vec<Spoiler> {
+    if !md.contains(">!") {
+        return Vec::new();
+    }
+    // Not code spans, which may well run across the lines of a spoiler
+    let code = preprocess::code_block_ranges(md);
+    let mut spoilers: Vec<Spoiler> = Vec::new();
+    let mut end = 0;
+    for line in md.split_inclusive('\n') {
+        let start = end;
+        end += line.len();
+        let content = match spoiler_line(line) {
+            Some(content) if !code.iter().any(|range| range.contains(&start)) => content,
+            _ => continue,
+        };
+        match spoilers.last_mut() {
+            Some(spoiler) if spoiler.range.end == start => {
+                spoiler.range.end = end;
+                spoiler.content.push_str(content);
+            }
+            _ => spoilers.push(Spoiler {
+                range: start..end,
+                content: content.to_string(),
+            }),
+        }
+    }
+    spoilers
+}
+
+/// What `line` holds if it's part of a spoiler: whatever follows `>!` and a space, after at most
+/// three spaces of indentation
+fn spoiler_line(line: &str) -> Option<&str> {
+    let unindented = line.trim_start_matches(' ');
+    if line.len() - unindented.len() > 3 {
+        return None;
+    }
+    let content = unindented.strip_prefix(">!")?;
+    Some(content.strip_prefix(' ').unwrap_or(content))
+}
+
+/// `md` with each of its spoilers replaced by `with` it and its index, borrowed if it has none
+fn replace(md: &str, with: impl Fn(usize, &Spoiler) -> String) -> Cow<'_, str> {
+    let spoilers = find(md);
+    if spoilers.is_empty() {
+        return Cow::Borrowed(md);
+    }
+    let mut replaced = String::with_capacity(md.len());
+    let mut copied = 0;
+    for (i, spoiler) in spoilers.iter().enumerate() {
+        replaced.push_str(&md[copied..spoiler.range.start]);
+        replaced.push_str(&with(i, spoiler));
+        copied = spoiler.range.end;
+    }
+    replaced.push_str(&md[copied..]);
+    Cow::Owned(replaced)
+}
+
+/// `md` with its spoilers as plain markdown between `START` and `END`, which
+/// `markdown::Parser` collapses or reveals
+pub fn mark(md: &str) -> Cow<'_, str> {
+    replace(md, |_, spoiler| {
+        format!(
+            "\n{}\n\n{}\n\n{}\n\n",
+            START,
+            spoiler.content.trim_end(),
+            END
+        )
+    })
+}
+
+/// `md` without its spoilers
+pub fn omit(md: &str) -> Cow<'_, str> {
+    replace(md, |_, spoiler| {
+        let before = &md[..spoiler.range.start];
+        if before.is_empty() || before.ends_with("\n\n") {
+            String::new()
+        } else {
+            // Keeps what came before and after from running together
+            String::from("\n")
+        }
+    })
+}
+
+/// `md` with its spoilers as ordinary block quotes
+pub fn reveal(md: &str) -> Cow<'_, str> {
+    replace(md, |_, spoiler| {
+        spoiler
+            .content
+            .lines()
+            .map(|line| format!("> {}\n", line))
+            .collect()
+    })
+}
+
+/// Spoilers revealed this session, by post id, which questions and answers of a site share
+#[derive(Default)]
+pub struct Spoilers {
+    revealed: HashMap<u32, HashSet<usize>>,
+}
+
+impl Spoilers {
+    /// Reveal the first hidden spoiler of the post `id`, whose rendered body is `body`, or
+    /// collapse them all again if none is hidden. Returns whether the post has any spoilers.
+    pub fn toggle(&mut self, id: u32, body: &Markdown) -> bool {
+        let count = find(body.source()).len();
+        if count == 0 {
+            return false;
+        }
+        let revealed = self.revealed.entry(id).or_default();
+        match (0..count).find(|i| !revealed.contains(i)) {
+            Some(i) => {
+                revealed.insert(i);
+            }
+            None => revealed.clear(),
+        }
+        true
+    }
+
+    /// `body` of the post `id`, with the spoilers revealed so far showing and the code lines
+    /// `highlights` highlighted
+    pub fn render(&self, id: u32, body: &Markdown, highlights: &[CodeMatch]) -> Markdown {
+        self.render_highlighted(id, body, highlights, None)
+    }
+
+    /// `render`, with the syntax of code blocks highlighted if there's `syntax` to go by
+    pub fn render_highlighted(
+        &self,
+        id: u32,
+        body: &Markdown,
+        highlights: &[CodeMatch],
+        syntax: Option<Syntax<'_>>,
+    ) -> Markdown {
+        let revealed = self.revealed(id);
+        if revealed.is_empty() && syntax.is_none() {
+            return body.clone();
+        }
+        // Bodies that failed to render the first time are already shown as plain text
+        isolation::catch(|| {
+            markdown::parse_highlighted(body.source(), highlights, &revealed, syntax)
+        })
+        .unwrap_or_else(|_| body.clone())
+    }
+
+    /// Code blocks of the post `id` as `render` shows them; none if it can only show `body` as
+    /// plain text
+    pub fn code_blocks(&self, id: u32, body: &Markdown) -> Vec<CodeBlock> {
+        let revealed = self.revealed(id);
+        isolation::catch(|| markdown::parse_with_blocks(body.source(), &[], &revealed, None).1)
+            .unwrap_or_default()
+    }
+
+    /// Spoilers of the post `id` revealed so far
+    fn revealed(&self, id: u32) -> Vec<usize> {
+        match self.revealed.get(&id) {
+            Some(revealed) => revealed.iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEPARATOR: &str = "\n---8<---\n";
+
+    /// Rendered text, without styles
+    fn text(md: &Markdown) -> String {
+        md.spans().map(|span| span.content).collect()
+    }
+
+    #[test]
+    fn test_find() {
+        let fixture = include_str!("../../test/markdown/spoilers.md");
+        let spoilers = find(fixture);
+        assert_eq!(
+            spoilers
+                .iter()
+                .map(|s| s.content.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "The butler did it.\n",
+                "With the candlestick,\n\nin the library.\n",
+                "Indented, but not enough to be code\n",
+            ]
+        );
+        for spoiler in &spoilers {
+            assert!(fixture[spoiler.range.clone()]
+                .trim_start()
+                .starts_with(">!"));
+        }
+        assert!(find("> Just a quote\n\n    >! indented code\n").is_empty());
+    }
+
+    #[test]
+    fn test_omit_and_reveal() {
+        let fixture = include_str!("../../test/markdown/spoilers.md");
+        let expected: Vec<_> = include_str!("../../test/markdown/spoilers.lucky.md")
+            .split(SEPARATOR)
+            .collect();
+        assert_eq!(omit(fixture), expected[0]);
+        assert_eq!(reveal(fixture), expected[1]);
+        assert!(matches!(omit("no spoilers"), Cow::Borrowed("no spoilers")));
+    }
+
+    #[test]
+    fn test_collapsed_and_revealed() {
+        let fixture = include_str!("../../test/markdown/spoilers.md");
+        let expected: Vec<_> = include_str!("../../test/markdown/spoilers.rendered.txt")
+            .split(SEPARATOR)
+            .collect();
+        let body = markdown::parse(fixture);
+        assert_eq!(text(&body), expected[0]);
+
+        // One at a time, then all collapsed again
+        let mut spoilers = Spoilers::default();
+        assert!(spoilers.toggle(1, &body));
+        assert_eq!(text(&spoilers.render(1, &body, &[])), expected[1]);
+        // Only for that post
+        assert_eq!(spoilers.render(2, &body, &[]), body);
+        assert!(spoilers.toggle(1, &body));
+        assert!(spoilers.toggle(1, &body));
+        assert_eq!(text(&spoilers.render(1, &body, &[])), expected[2]);
+        assert!(spoilers.toggle(1, &body));
+        assert_eq!(spoilers.render(1, &body, &[]), body);
+
+        assert!(!spoilers.toggle(3, &markdown::parse("> Just a quote")));
+    }
+
+    #[test]
+    fn test_code_highlights_counted_when_collapsed() {
+        let md = ">! ```\n>! hidden\n>! ```\n\n```\nshown\n```\n";
+        let highlights = [CodeMatch { block: 1, line: 0 }];
+        let body = markdown::parse_with_highlights(md, &highlights);
+        let reversed: Vec<_> = body
+            .spans()
+            .filter(|span| span.attr.effects.contains(cursive::theme::Effect::Reverse))
+            .map(|span| span.content)
+            .collect();
+        assert_eq!(reversed, vec!["shown\n"]);
+    }
+}
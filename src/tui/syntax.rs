@@ -0,0 +1,200 @@
+//! Syntax highlighting of code blocks, with syntect.
+//!
+//! A code block is highlighted as the language its fence names (```` ```rust ````), or else the
+//! one a `<!-- language: lang-rust -->` hint before it names, or else the first of its question's
+//! tags that is a language. Highlighting takes far longer than parsing, so posts are parsed
+//! without it when they're fetched, and highlighted once they're first shown in the TUI. Themes
+//! only lend their foreground colors and font styles, leaving the background to the TUI's theme.
+
+use cursive::theme::{Color, Effect, Style};
+use lazy_static::lazy_static;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{self, FontStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+use super::theme::Appearance;
+
+/// Theme that goes with the TUI's appearance
+pub const AUTO: &str = "auto";
+const DARK: &str = "base16-ocean.dark";
+const LIGHT: &str = "base16-ocean.light";
+
+lazy_static! {
+    static ref SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEMES: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// A line of highlighted code, as text and its style
+pub type Line = Vec<(Style, String)>;
+
+/// How to highlight the code blocks of a post: with `highlighter`, falling back to the `tags` of
+/// its question for the language
+#[derive(Clone, Copy)]
+pub struct Syntax<'a> {
+    pub highlighter: &'a Highlighter,
+    pub tags: &'a [String],
+}
+
+/// Names of the themes to pick from, besides `AUTO`
+pub fn themes() -> Vec<&'static str> {
+    THEMES.themes.keys().map(String::as_str).collect()
+}
+
+/// Whether `name` is `AUTO` or one of `themes`
+pub fn is_theme(name: &str) -> bool {
+    name == AUTO || THEMES.themes.contains_key(name)
+}
+
+/// Highlights code in one of the built-in themes
+#[derive(Clone)]
+pub struct Highlighter {
+    theme: Theme,
+}
+
+impl Highlighter {
+    /// Highlighter with the theme `name`, or if that's `AUTO` or unknown, the one going with
+    /// `appearance`
+    pub fn new(name: &str, appearance: Appearance) -> Self {
+        let auto = match appearance {
+            Appearance::Dark => DARK,
+            Appearance::Light => LIGHT,
+        };
+        let theme = THEMES
+            .themes
+            .get(name)
+            .or_else(|| THEMES.themes.get(auto))
+            .cloned()
+            .unwrap_or_default();
+        Highlighter { theme }
+    }
+
+    /// `code` highlighted as `language`, one line at a time, each with its line break as a span
+    /// of its own; `None` if syntect gives up on it
+    pub fn highlight(&self, language: &SyntaxReference, code: &str) -> Option<Vec<Line>> {
+        let mut lines = HighlightLines::new(language, &self.theme);
+        code.split_inclusive('\n')
+            .map(|line| {
+                let regions = lines.highlight_line(line, &SYNTAXES).ok()?;
+                let mut highlighted: Line = Vec::with_capacity(regions.len() + 1);
+                for (style, text) in regions {
+                    let (text, newline) = match text.strip_suffix('\n') {
+                        Some(text) => (text, true),
+                        None => (text, false),
+                    };
+                    if !text.is_empty() {
+                        highlighted.push((convert(style), text.to_string()));
+                    }
+                    if newline {
+                        highlighted.push((Style::none(), String::from("\n")));
+                    }
+                }
+                Some(highlighted)
+            })
+            .collect()
+    }
+}
+
+/// Language to highlight a code block as: the one its `fence` names, else the one `hint` names,
+/// else the first of `tags` that is one. A block asked to be plain text, say with ```` ```text ````,
+/// isn't highlighted at all.
+pub fn language(
+    fence: Option<&str>,
+    hint: Option<&str>,
+    tags: &[String],
+) -> Option<&'static SyntaxReference> {
+    if let Some(syntax) = fence.into_iter().chain(hint).find_map(find) {
+        return Some(syntax).filter(|syntax| !is_plain(syntax));
+    }
+    // Versioned tags such as `python-3.x` are still the language
+    tags.iter()
+        .find_map(|tag| find(tag).or_else(|| tag.split('-').next().and_then(find)))
+        .filter(|syntax| !is_plain(syntax))
+}
+
+/// Syntax going by `token`, a name or file extension, as in fences and `lang-` hints
+fn find(token: &str) -> Option<&'static SyntaxReference> {
+    let token = token.trim();
+    let token = token.strip_prefix("lang-").unwrap_or(token);
+    match token {
+        "" => None,
+        // Stack Exchange's ways of asking for none
+        "text" | "plaintext" | "none" => Some(SYNTAXES.find_syntax_plain_text()),
+        _ => SYNTAXES.find_syntax_by_token(token),
+    }
+}
+
+fn is_plain(syntax: &SyntaxReference) -> bool {
+    syntax.name == "Plain Text"
+}
+
+/// Foreground and font style of `style`
+fn convert(style: highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut converted = Style::from(Color::Rgb(fg.r, fg.g, fg.b));
+    for (font, effect) in &[
+        (FontStyle::BOLD, Effect::Bold),
+        (FontStyle::ITALIC, Effect::Italic),
+        (FontStyle::UNDERLINE, Effect::Underline),
+    ] {
+        if style.font_style.contains(*font) {
+            converted = converted.combine(*effect);
+        }
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_language() {
+        let name = |fence, hint, t: &[&str]| language(fence, hint, &tags(t)).map(|s| &s.name[..]);
+        assert_eq!(name(Some("rust"), None, &[]), Some("Rust"));
+        assert_eq!(name(Some("py"), Some("lang-rust"), &[]), Some("Python"));
+        assert_eq!(name(None, Some("lang-rust"), &["python"]), Some("Rust"));
+        assert_eq!(name(None, None, &["linux", "python-3.x"]), Some("Python"));
+        assert_eq!(
+            name(Some("no-such-language"), None, &["bash"]),
+            Some("Bourne Again Shell (bash)")
+        );
+        assert_eq!(name(Some("text"), None, &[]), None);
+        assert_eq!(name(Some("text"), None, &["python"]), None);
+        assert_eq!(name(None, Some("lang-none"), &["python"]), None);
+        assert_eq!(name(None, None, &["vim"]), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let rust = language(Some("rust"), None, &[]).unwrap();
+        let lines = Highlighter::new(AUTO, Appearance::Dark)
+            .highlight(rust, "fn main() {}\nlet a = 1;")
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        let text: Vec<String> = lines
+            .iter()
+            .map(|line| line.iter().map(|(_, text)| text.as_str()).collect())
+            .collect();
+        assert_eq!(text, vec!["fn main() {}\n", "let a = 1;"]);
+        // Line breaks on their own, and more than one color
+        assert_eq!(lines[0].last().unwrap().1, "\n");
+        assert!(lines[0]
+            .iter()
+            .all(|(_, text)| text == "\n" || !text.contains('\n')));
+        let mut colors: Vec<_> = lines[0].iter().map(|(style, _)| style.color).collect();
+        colors.dedup();
+        assert!(colors.len() > 2);
+    }
+
+    #[test]
+    fn test_themes() {
+        assert!(is_theme(AUTO));
+        assert!(is_theme("InspiredGitHub"));
+        assert!(!is_theme("no-such-theme"));
+        assert!(themes().contains(&"Solarized (light)"));
+    }
+}
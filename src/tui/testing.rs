@@ -0,0 +1,233 @@
+//! Harness for testing the TUI: lays it out on cursive's puppet backend at a fixed size, drives it
+//! with scripted key presses, and compares what ends up on screen against snapshots.
+//!
+//! Snapshots are text files in `src/tui/snapshots`, one per screen. After a deliberate change to
+//! what the TUI shows, run the tests with `UPDATE_SNAPSHOTS=1` to rewrite them, and review the
+//! diff.
+
+use cursive::backends::puppet::observed::{GraphemePart, ObservedScreen};
+use cursive::backends::puppet::Backend;
+use cursive::event::Event;
+use cursive::{Cursive, Vec2, XY};
+use std::path::PathBuf;
+
+use super::app;
+use super::markdown::{self, Markdown};
+use super::theme::{Appearance, Resolved};
+use crate::config::Config;
+use crate::messages::Messages;
+use crate::notes::Notes;
+use crate::stackexchange::filtered::Filtered;
+use crate::stackexchange::{snippets, testing, Answer, Question};
+
+/// Screen size of every test, so that nothing depends on the terminal running the tests
+pub const SIZE: Vec2 = XY { x: 100, y: 30 };
+
+/// A Cursive root on the puppet backend, and the frames it rendered
+pub struct Puppet {
+    siv: Cursive,
+    /// Latest frame rendered since the last call, if any
+    next_frame: Box<dyn Fn() -> Option<ObservedScreen>>,
+    frame: Option<ObservedScreen>,
+}
+
+impl Puppet {
+    /// Whatever `setup` lays out on a fresh root
+    pub fn new(setup: impl FnOnce(&mut Cursive)) -> Self {
+        let backend = Backend::init(Some(SIZE));
+        let frames = backend.stream();
+        let mut siv = Cursive::new(move || backend);
+        setup(&mut siv);
+        siv.refresh();
+        Puppet {
+            siv,
+            next_frame: Box::new(move || frames.try_iter().last()),
+            frame: None,
+        }
+    }
+
+    /// The TUI showing `qs`, with the default config and dark theme, no notes and no messages
+    pub fn tui(qs: Vec<Question<Markdown>>) -> Self {
+        Self::tui_with(Filtered::new(qs), None, Messages::default())
+    }
+
+    /// The TUI showing `qs`, along with whatever filters left out of them, with an offline banner
+    /// for `offline_since` and the given `messages`
+    pub fn tui_with(
+        qs: Filtered<Markdown>,
+        offline_since: Option<i64>,
+        messages: Messages,
+    ) -> Self {
+        Self::new(|siv| {
+            app::build(
+                siv,
+                qs,
+                &Config::default(),
+                offline_since,
+                messages,
+                Notes::default(),
+                Resolved::BuiltIn(Appearance::Dark),
+            )
+        })
+    }
+
+    /// Feed `events` to the TUI one at a time, redrawing after each
+    pub fn press<E, I>(&mut self, events: I) -> &mut Self
+    where
+        E: Into<Event>,
+        I: IntoIterator<Item = E>,
+    {
+        for event in events {
+            self.siv.on_event(event.into());
+            // Run callbacks the event queued up, e.g. selecting a question
+            self.siv.process_events();
+            self.siv.refresh();
+        }
+        self
+    }
+
+    /// Run whatever was sent through the callback sink, e.g. from another thread, and redraw
+    pub fn step(&mut self) -> &mut Self {
+        self.siv.process_events();
+        self.siv.refresh();
+        self
+    }
+
+    pub fn siv(&mut self) -> &mut Cursive {
+        &mut self.siv
+    }
+
+    /// Type every character of `keys`
+    pub fn keys(&mut self, keys: &str) -> &mut Self {
+        self.press(keys.chars().map(Event::Char))
+    }
+
+    /// What's on screen, one line per row, without trailing spaces
+    pub fn screen(&mut self) -> String {
+        if let Some(frame) = (self.next_frame)() {
+            self.frame = Some(frame);
+        }
+        let frame = self.frame.as_ref().expect("nothing was rendered");
+        let mut lines = Vec::with_capacity(SIZE.y);
+        for y in 0..SIZE.y {
+            let mut line = String::with_capacity(SIZE.x);
+            for x in 0..SIZE.x {
+                match &frame[XY { x, y }] {
+                    Some(cell) => {
+                        if let GraphemePart::Begin(grapheme) = &cell.letter {
+                            line.push_str(grapheme);
+                        }
+                    }
+                    None => line.push(' '),
+                }
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// The status bar below the panes, which takes up no room while there's nothing in it
+    pub fn status_bar(&mut self) -> String {
+        let screen = self.screen();
+        let last = screen
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        // The bottom border of the panes
+        if last.contains('\u{2518}') {
+            String::new()
+        } else {
+            last.trim().to_string()
+        }
+    }
+}
+
+/// Compare `screen` against the snapshot called `name`, or write the snapshot when running with
+/// `UPDATE_SNAPSHOTS` set
+pub fn assert_snapshot(name: &str, screen: &str) {
+    assert_snapshot_in("src/tui/snapshots", name, screen)
+}
+
+/// Compare `text` against the snapshot called `name` in `dir`, relative to the crate root, or
+/// write the snapshot when running with `UPDATE_SNAPSHOTS` set
+pub fn assert_snapshot_in(dir: &str, name: &str, text: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join(dir)
+        .join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, text).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "no snapshot at {}; run with UPDATE_SNAPSHOTS=1 to write it",
+            path.display()
+        )
+    });
+    if expected != text {
+        let diff = similar::TextDiff::from_lines(expected.as_str(), text)
+            .unified_diff()
+            .header("snapshot", "actual")
+            .to_string();
+        panic!("output differs from snapshot {}:\n{}", name, diff);
+    }
+}
+
+/// Answer with `body` in markdown
+pub fn answer(id: u32, score: i32, is_accepted: bool, body: &str) -> Answer<Markdown> {
+    Answer {
+        code_blocks: snippets::code_blocks(body),
+        ..testing::answer(id, score, is_accepted, markdown::parse(body))
+    }
+}
+
+/// Question on Stack Overflow with `body` in markdown
+pub fn question(
+    id: u32,
+    score: i32,
+    title: &str,
+    body: &str,
+    answers: Vec<Answer<Markdown>>,
+) -> Question<Markdown> {
+    testing::question(id, score, title, markdown::parse(body), answers)
+}
+
+/// Results covering what the lists and views have to show: accepted and other answers, code,
+/// scores of every style, and a question nobody has answered
+pub fn questions() -> Vec<Question<Markdown>> {
+    vec![
+        question(
+            1,
+            3456,
+            "How do I exit the Vim editor?",
+            "I'm stuck in Vim and can't get out. **Help!**",
+            vec![
+                answer(
+                    10,
+                    5120,
+                    true,
+                    "Hit `Esc` to leave insert mode, then type\n\n```\n:q!\n```\n\n\
+                     to quit without saving.",
+                ),
+                answer(11, 42, false, "Type `ZZ` to save and quit."),
+                answer(12, -3, false, "Pull the plug."),
+            ],
+        ),
+        question(
+            2,
+            87,
+            "Quit vi without saving changes",
+            "How do I throw away my changes?",
+            vec![answer(20, 12, false, "`:q!` discards them.")],
+        ),
+        question(
+            3,
+            0,
+            "Exit Vim from a script",
+            "Is there a way to do this non-interactively?",
+            vec![],
+        ),
+    ]
+}
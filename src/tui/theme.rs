@@ -0,0 +1,340 @@
+//! Which colors the TUI uses: the built-in dark or light theme, or the user's `colors.toml`.
+//!
+//! With `theme: auto` (the default), an edited `colors.toml` wins. Otherwise the built-in theme
+//! matching the appearance of the terminal is picked, going by the first `Detect` strategy that
+//! has an answer: the `COLORFGBG` variable some terminals set, the background color the terminal
+//! reports for an OSC 11 query, and on macOS, the system appearance. Anything inconclusive falls
+//! back to dark.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{Config, Theme};
+use crate::error::Result;
+use crate::utils;
+
+/// Built-in theme for dark backgrounds, which is also what `colors.toml` starts out as
+pub const DARK: &str = include_str!("../../themes/default.toml");
+
+/// Built-in theme for light backgrounds
+pub const LIGHT: &str = include_str!("../../themes/light.toml");
+
+/// How long to wait for the terminal to answer an OSC 11 query; terminals that support it answer
+/// right away, and the others never do
+const OSC_11_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Whether the terminal has a dark or a light background
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Appearance {
+    Dark,
+    Light,
+}
+
+/// Theme to load
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolved {
+    BuiltIn(Appearance),
+    Custom,
+}
+
+impl Resolved {
+    /// Contents of the built-in theme, if it is one
+    pub fn built_in(self) -> Option<&'static str> {
+        match self {
+            Resolved::BuiltIn(Appearance::Dark) => Some(DARK),
+            Resolved::BuiltIn(Appearance::Light) => Some(LIGHT),
+            Resolved::Custom => None,
+        }
+    }
+}
+
+/// Path of `colors.toml`; if it doesn't exist yet, create it with defaults.
+pub fn file_path() -> Result<PathBuf> {
+    let name = Config::project_dir()?.config_dir().join("colors.toml");
+    if !name.as_path().exists() {
+        let mut file = utils::create_file(&name)?;
+        file.write_all(DARK.as_bytes())?;
+    }
+    Ok(name)
+}
+
+/// Whether `colors.toml` was edited, i.e. differs from the defaults it was created with
+pub fn file_customized() -> Result<bool> {
+    let contents = fs::read_to_string(file_path()?)?;
+    Ok(contents != DARK)
+}
+
+/// A way of telling the appearance of the terminal, which may well not know
+pub trait Detect {
+    fn detect(&self) -> Option<Appearance>;
+}
+
+/// Resolve the `theme` setting; `customized` is whether `colors.toml` was edited. Detectors are
+/// only run for `auto`, and in order until one of them knows.
+pub fn resolve(theme: Theme, customized: bool, detectors: &[&dyn Detect]) -> Resolved {
+    match theme {
+        Theme::Dark => Resolved::BuiltIn(Appearance::Dark),
+        Theme::Light => Resolved::BuiltIn(Appearance::Light),
+        Theme::Custom => Resolved::Custom,
+        Theme::Auto if customized => Resolved::Custom,
+        Theme::Auto => Resolved::BuiltIn(
+            detectors
+                .iter()
+                .find_map(|d| d.detect())
+                .unwrap_or(Appearance::Dark),
+        ),
+    }
+}
+
+/// Detectors for the current platform, in the order they're tried
+pub fn detectors() -> Vec<Box<dyn Detect>> {
+    let mut detectors: Vec<Box<dyn Detect>> = vec![
+        Box::new(ColorFgBg(std::env::var("COLORFGBG").ok())),
+        Box::new(Osc11),
+    ];
+    if cfg!(target_os = "macos") {
+        detectors.push(Box::new(MacOs));
+    }
+    detectors
+}
+
+/// `COLORFGBG`, set by rxvt, Konsole and others to the foreground and background color as
+/// palette indexes, e.g. `15;0`
+pub struct ColorFgBg(pub Option<String>);
+
+impl Detect for ColorFgBg {
+    fn detect(&self) -> Option<Appearance> {
+        // Some terminals put `default` in between, e.g. `15;default;0`
+        let background = self.0.as_deref()?.rsplit(';').next()?.parse::<u8>().ok()?;
+        match background {
+            0..=6 | 8 => Some(Appearance::Dark),
+            7 | 9..=15 => Some(Appearance::Light),
+            _ => None,
+        }
+    }
+}
+
+/// Ask the terminal for its background color with an OSC 11 query
+pub struct Osc11;
+
+impl Detect for Osc11 {
+    fn detect(&self) -> Option<Appearance> {
+        let response = query_background()?;
+        let (r, g, b) = parse_osc_11(&response)?;
+        // Relative luminance, roughly
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        Some(if luminance > 0.5 {
+            Appearance::Light
+        } else {
+            Appearance::Dark
+        })
+    }
+}
+
+/// The system appearance on macOS: `AppleInterfaceStyle` is `Dark` in dark mode, and unset
+/// otherwise
+pub struct MacOs;
+
+impl Detect for MacOs {
+    fn detect(&self) -> Option<Appearance> {
+        let output = std::process::Command::new("defaults")
+            .args(["read", "-g", "AppleInterfaceStyle"])
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()?;
+        if output.status.success() {
+            let style = String::from_utf8_lossy(&output.stdout);
+            Some(if style.trim() == "Dark" {
+                Appearance::Dark
+            } else {
+                Appearance::Light
+            })
+        } else {
+            // The key is missing in light mode
+            Some(Appearance::Light)
+        }
+    }
+}
+
+/// Red, green and blue from `rgb:RRRR/GGGG/BBBB` in the terminal's answer to an OSC 11 query,
+/// scaled to `0.0..=1.0`. Components have one to four hex digits.
+fn parse_osc_11(response: &[u8]) -> Option<(f64, f64, f64)> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.find("rgb:")? + "rgb:".len();
+    let rgb = response[start..].trim_end_matches(['\x07', '\x1b', '\\']);
+    let mut components = rgb.split('/').map(|c| {
+        let c = &c[..c
+            .find(|ch: char| !ch.is_ascii_hexdigit())
+            .unwrap_or(c.len())];
+        if c.is_empty() || c.len() > 4 {
+            return None;
+        }
+        let max = (1u32 << (4 * c.len())) - 1;
+        Some(u32::from_str_radix(c, 16).ok()? as f64 / max as f64)
+    });
+    let (r, g, b) = (
+        components.next()??,
+        components.next()??,
+        components.next()??,
+    );
+    Some((r, g, b))
+}
+
+/// Send an OSC 11 query to the controlling terminal and collect its answer, if it answers within
+/// `OSC_11_TIMEOUT`
+#[cfg(unix)]
+fn query_background() -> Option<Vec<u8>> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let fd = tty.as_raw_fd();
+    // Raw mode, so that the answer isn't echoed and arrives without waiting for a newline
+    let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return None;
+    }
+    let mut raw = original;
+    unsafe { libc::cfmakeraw(&mut raw) };
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+    let mut response = Vec::new();
+    if tty.write_all(b"\x1b]11;?\x07").is_ok() && tty.flush().is_ok() {
+        let deadline = Instant::now() + OSC_11_TIMEOUT;
+        let mut buf = [0; 64];
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut pollfd, 1, left.as_millis() as libc::c_int) };
+            if ready <= 0 {
+                break;
+            }
+            match tty.read(&mut buf) {
+                Ok(n) if n > 0 => response.extend_from_slice(&buf[..n]),
+                _ => break,
+            }
+            // Answers end in BEL or ST
+            if response.ends_with(b"\x07") || response.ends_with(b"\x1b\\") {
+                break;
+            }
+        }
+    }
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    Some(response).filter(|r| !r.is_empty())
+}
+
+#[cfg(not(unix))]
+fn query_background() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Detector with a canned answer, counting how often it was asked
+    struct Fake(Option<Appearance>, Cell<usize>);
+
+    impl Fake {
+        fn new(appearance: Option<Appearance>) -> Self {
+            Fake(appearance, Cell::new(0))
+        }
+    }
+
+    impl Detect for Fake {
+        fn detect(&self) -> Option<Appearance> {
+            self.1.set(self.1.get() + 1);
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        use Appearance::*;
+        let (unknown, light, dark) = (
+            Fake::new(None),
+            Fake::new(Some(Light)),
+            Fake::new(Some(Dark)),
+        );
+
+        // The first detector that knows wins
+        let resolved = resolve(Theme::Auto, false, &[&unknown, &light, &dark]);
+        assert_eq!(resolved, Resolved::BuiltIn(Light));
+        assert_eq!((unknown.1.get(), light.1.get(), dark.1.get()), (1, 1, 0));
+
+        // Dark when nobody knows
+        assert_eq!(
+            resolve(Theme::Auto, false, &[&unknown]),
+            Resolved::BuiltIn(Dark)
+        );
+        assert_eq!(resolve(Theme::Auto, false, &[]), Resolved::BuiltIn(Dark));
+
+        // An edited colors.toml, or an explicit theme, skips detection altogether
+        let asked = light.1.get();
+        assert_eq!(resolve(Theme::Auto, true, &[&light]), Resolved::Custom);
+        assert_eq!(
+            resolve(Theme::Dark, false, &[&light]),
+            Resolved::BuiltIn(Dark)
+        );
+        assert_eq!(
+            resolve(Theme::Light, true, &[&dark]),
+            Resolved::BuiltIn(Light)
+        );
+        assert_eq!(resolve(Theme::Custom, false, &[&light]), Resolved::Custom);
+        assert_eq!(light.1.get(), asked);
+    }
+
+    #[test]
+    fn test_colorfgbg() {
+        let detect = |value: Option<&str>| ColorFgBg(value.map(String::from)).detect();
+        assert_eq!(detect(Some("15;0")), Some(Appearance::Dark));
+        assert_eq!(detect(Some("0;15")), Some(Appearance::Light));
+        assert_eq!(detect(Some("0;7")), Some(Appearance::Light));
+        assert_eq!(detect(Some("15;default;8")), Some(Appearance::Dark));
+        assert_eq!(detect(Some("15;default")), None);
+        assert_eq!(detect(Some("garbage")), None);
+        assert_eq!(detect(None), None);
+    }
+
+    #[test]
+    fn test_parse_osc_11() {
+        assert_eq!(
+            parse_osc_11(b"\x1b]11;rgb:ffff/ffff/ffff\x07"),
+            Some((1.0, 1.0, 1.0))
+        );
+        assert_eq!(
+            parse_osc_11(b"\x1b]11;rgb:0000/0000/0000\x1b\\"),
+            Some((0.0, 0.0, 0.0))
+        );
+        // Two digit components, as some terminals send
+        let (r, g, b) = parse_osc_11(b"\x1b]11;rgb:2d/2d/2d\x07").unwrap();
+        assert!(r < 0.2 && g < 0.2 && b < 0.2);
+        assert_eq!(parse_osc_11(b"\x1b]11;rgb:ffff/ffff\x07"), None);
+        assert_eq!(parse_osc_11(b""), None);
+        assert_eq!(parse_osc_11(b"\x1b[?1;2c"), None);
+    }
+
+    #[test]
+    fn test_built_in_themes_parse() {
+        for appearance in &[Appearance::Dark, Appearance::Light] {
+            let theme = Resolved::BuiltIn(*appearance).built_in().unwrap();
+            assert!(cursive::theme::load_toml(theme).is_ok());
+        }
+        assert_eq!(Resolved::Custom.built_in(), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Changes to which questions the TUI lists, funnelled through one place.
+//!
+//! Anything that changes the question list once the TUI is up, from pinning a question to results
+//! of another search coming in from another thread, sends an `Update` through the `Updater`
+//! instead of reaching into the views itself. Updates that pile up before the event loop gets to
+//! them are collapsed into one, so a burst of them is laid out and drawn once rather than once
+//! per update, and two changes never interleave halfway through. Cursive lays out and redraws after every
+//! callback, including a terminal resize that came in meanwhile, so the screen always matches
+//! the latest list.
+
+use cursive::{CbSink, Cursive};
+use std::sync::{Arc, Mutex};
+
+use crate::stackexchange::filtered::Filtered;
+
+#[derive(Debug, Clone)]
+pub enum Update<T> {
+    /// List these questions in place of the current results
+    Replace(Filtered<T>),
+    /// List the current results again, e.g. after pinning a question
+    Relist,
+}
+
+impl<T> Update<T> {
+    /// The single update with the effect of `self` followed by `next`
+    pub fn then(self, next: Update<T>) -> Update<T> {
+        match (self, next) {
+            (_, Update::Replace(qs)) => Update::Replace(qs),
+            // Every update lists the results again anyway
+            (Update::Relist, update) | (update, Update::Relist) => update,
+        }
+    }
+}
+
+/// Queues updates from any thread and applies them on the TUI thread
+pub struct Updater<T> {
+    sink: CbSink,
+    /// Updates not applied yet, collapsed into one; set while a flush is on its way
+    pending: Arc<Mutex<Option<Update<T>>>>,
+    apply: fn(&mut Cursive, Update<T>),
+}
+
+impl<T> Clone for Updater<T> {
+    fn clone(&self) -> Self {
+        Updater {
+            sink: self.sink.clone(),
+            pending: Arc::clone(&self.pending),
+            apply: self.apply,
+        }
+    }
+}
+
+impl<T: Send + 'static> Updater<T> {
+    /// Updater sending updates through `sink` to `apply`, the only function that should change
+    /// the question list
+    pub fn new(sink: CbSink, apply: fn(&mut Cursive, Update<T>)) -> Self {
+        Updater {
+            sink,
+            pending: Arc::new(Mutex::new(None)),
+            apply,
+        }
+    }
+
+    /// Queue `update`, to be applied on the next step of the event loop
+    pub fn send(&self, update: Update<T>) {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.take() {
+            Some(queued) => *pending = Some(queued.then(update)),
+            None => {
+                *pending = Some(update);
+                let queue = Arc::clone(&self.pending);
+                let apply = self.apply;
+                let flush = move |s: &mut Cursive| {
+                    let update = queue.lock().unwrap().take();
+                    if let Some(update) = update {
+                        apply(s, update);
+                    }
+                };
+                // Nothing to update once the TUI is gone
+                self.sink.send(Box::new(flush)).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stackexchange::testing;
+    use crate::tui::testing::Puppet;
+    use cursive::event::Event;
+
+    fn results(id: u32) -> Filtered<String> {
+        let title = format!("Question {}", id);
+        Filtered::new(vec![testing::question(
+            id,
+            1,
+            &title,
+            String::new(),
+            vec![],
+        )])
+    }
+
+    fn ids(update: &Update<String>) -> Option<Vec<u32>> {
+        match update {
+            Update::Replace(qs) => Some(qs.shown.iter().map(|q| q.id).collect()),
+            Update::Relist => None,
+        }
+    }
+
+    /// Keep every update applied in the user data, in order
+    fn record(s: &mut Cursive, update: Update<String>) {
+        s.with_user_data(|applied: &mut Vec<Update<String>>| applied.push(update));
+    }
+
+    fn take_applied(puppet: &mut Puppet) -> Vec<Update<String>> {
+        puppet
+            .siv()
+            .with_user_data(|applied: &mut Vec<Update<String>>| std::mem::take(applied))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_then() {
+        let replaced = Update::Replace(results(1)).then(Update::Replace(results(2)));
+        assert_eq!(ids(&replaced), Some(vec![2]));
+        let replaced = replaced.then(Update::Relist);
+        assert_eq!(ids(&replaced), Some(vec![2]));
+        let replaced = Update::Relist.then(Update::Replace(results(3)));
+        assert_eq!(ids(&replaced), Some(vec![3]));
+
+        assert!(matches!(
+            Update::<String>::Relist.then(Update::Relist),
+            Update::Relist
+        ));
+    }
+
+    #[test]
+    fn test_burst_applied_once() {
+        let mut puppet = Puppet::new(|siv| siv.set_user_data(Vec::<Update<String>>::new()));
+        let updater = Updater::new(puppet.siv().cb_sink().clone(), record);
+        let sender = updater.clone();
+        std::thread::spawn(move || {
+            for id in 1..=50 {
+                sender.send(Update::Replace(results(id)));
+            }
+        })
+        .join()
+        .unwrap();
+        updater.send(Update::Relist);
+        puppet.step();
+        let applied = take_applied(&mut puppet);
+        assert_eq!(applied.len(), 1);
+        assert_eq!(ids(&applied[0]), Some(vec![50]));
+
+        // Updates after a flush go out in the next one
+        updater.send(Update::Relist);
+        puppet.step();
+        let relisted = take_applied(&mut puppet);
+        assert_eq!(relisted.len(), 1);
+        assert!(matches!(relisted[0], Update::Relist));
+        puppet.step();
+        assert!(take_applied(&mut puppet).is_empty());
+    }
+
+    #[test]
+    fn test_resize_between_updates() {
+        let mut puppet = Puppet::new(|siv| siv.set_user_data(Vec::<Update<String>>::new()));
+        let updater = Updater::new(puppet.siv().cb_sink().clone(), record);
+        updater.send(Update::Replace(results(1)));
+        puppet.press(vec![Event::WindowResize]);
+        updater.send(Update::Replace(results(2)));
+        updater.send(Update::Replace(results(3)));
+        puppet.press(vec![Event::WindowResize]);
+        let applied = take_applied(&mut puppet);
+        assert_eq!(
+            applied.iter().map(ids).collect::<Vec<_>>(),
+            vec![Some(vec![1]), Some(vec![3])]
+        );
+    }
+}
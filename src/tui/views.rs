@@ -11,6 +11,8 @@ use std::fmt;
 use std::fmt::Display;
 use std::rc::Rc;
 
+use super::code_blocks;
+use super::lazy_list::{self, LazyLabels};
 use super::markdown::Markdown;
 
 pub const NAME_QUESTION_LIST: &str = "question_list";
@@ -69,17 +71,25 @@ trait Hide {
 }
 
 // TODO Copy select_view to to allow overriding selected style => reverse video
-pub type ListView =
-    ListViewT<HideableView<ResizedView<Panel<ScrollView<NamedView<SelectView<u32>>>>>>>;
+pub type ListView = ListViewT<ListViewInner>;
+
+type ListViewInner = HideableView<ResizedView<Panel<ScrollView<NamedView<SelectView<u32>>>>>>;
 
 pub struct ListViewT<T: View> {
     inner_name: String,
     view: T,
     force_take_focus: bool,
+    /// Labels rendered on demand, for lists created with `new_lazy`
+    lazy: Option<LazyLabels>,
 }
 
-impl<T: View> ViewWrapper for ListViewT<T> {
-    cursive::wrap_impl!(self.view: T);
+impl ViewWrapper for ListView {
+    cursive::wrap_impl!(self.view: ListViewInner);
+
+    fn wrap_layout(&mut self, size: Vec2) {
+        self.render_visible(size.y);
+        self.view.layout(size);
+    }
 
     // In full screen mode we always take focus, even though currently hidden
     fn wrap_take_focus(&mut self, source: cursive::direction::Direction) -> bool {
@@ -111,13 +121,21 @@ impl ListView {
         ListView::make_new::<StyledString, Vec<_>, _>(name, None, on_select)
     }
 
-    pub fn new_with_items<S, I, F>(name: Name, items: I, on_select: F) -> NamedView<Self>
+    /// Create a list whose labels are only rendered (by `render`, given the item id) once they
+    /// scroll into view
+    pub fn new_lazy<I, R, F>(name: Name, ids: I, render: R, on_select: F) -> NamedView<Self>
     where
-        S: Into<StyledString>,
-        I: IntoIterator<Item = (S, u32)>,
+        I: IntoIterator<Item = u32>,
+        R: Fn(u32) -> StyledString + 'static,
         F: Fn(&mut Cursive, &u32) + 'static,
     {
-        ListView::make_new(name, Some(items), on_select)
+        let mut view = ListView::make_new(
+            name,
+            Some(ids.into_iter().map(|id| (StyledString::new(), id))),
+            on_select,
+        );
+        view.get_mut().lazy = Some(LazyLabels::new(render));
+        view
     }
 
     fn make_new<S, I, F>(name: Name, items: Option<I>, on_select: F) -> NamedView<Self>
@@ -140,6 +158,7 @@ impl ListView {
             view,
             inner_name,
             force_take_focus: false,
+            lazy: None,
         };
 
         view.with_name(name)
@@ -157,8 +176,57 @@ impl ListView {
         })
     }
 
-    pub fn select(&mut self, i: usize) -> Callback {
-        self.call_on_inner(|sv| sv.set_selection(i))
+    /// Replace all items of a lazy list, rendering labels with `render` from now on
+    pub fn reset_lazy<I, R>(&mut self, ids: I, render: R) -> Callback
+    where
+        I: IntoIterator<Item = u32>,
+        R: Fn(u32) -> StyledString + 'static,
+    {
+        self.lazy = Some(LazyLabels::new(render));
+        self.reset_with_all(ids.into_iter().map(|id| (StyledString::new(), id)))
+    }
+
+    /// Render the labels of a lazy list that are within (or close to) `height` rows of both the
+    /// current scroll position and the selection
+    fn render_visible(&mut self, height: usize) {
+        let lazy = match self.lazy.as_mut() {
+            Some(lazy) => lazy,
+            None => return,
+        };
+        let top = self
+            .view
+            .get_inner()
+            .get_inner()
+            .get_inner()
+            .content_viewport()
+            .top();
+        self.view
+            .call_on_name(&self.inner_name, |sv: &mut SelectView<u32>| {
+                let len = sv.len();
+                let selected = sv.selected_id().unwrap_or_default();
+                let around_top = lazy_list::window(top, height, len, lazy_list::BUFFER);
+                let around_selection = lazy_list::window(
+                    selected.saturating_sub(height),
+                    2 * height,
+                    len,
+                    lazy_list::BUFFER,
+                );
+                lazy_list::fill_window(sv, lazy, around_top);
+                lazy_list::fill_window(sv, lazy, around_selection);
+            });
+    }
+
+    /// Select the item with the given id, if present
+    pub fn select_id(&mut self, id: u32) -> Option<Callback> {
+        self.call_on_inner(|sv| {
+            let ix = sv.iter().position(|(_, item)| *item == id)?;
+            Some(sv.set_selection(ix))
+        })
+    }
+
+    /// Id of the currently selected item
+    pub fn selection(&mut self) -> Option<u32> {
+        self.call_on_inner(|sv| sv.selection().map(|id| *id))
     }
 
     fn call_on_inner<F, R>(&mut self, cb: F) -> R
@@ -251,6 +319,19 @@ impl MdView {
             .expect("unwrap failed in MdView.set_content")
     }
 
+    /// Scroll so that the row with the byte `offset` of the content is at the top, wrapped as
+    /// the content was on the last layout
+    pub fn scroll_to(&mut self, offset: usize) {
+        let scroll = self.view.get_inner_mut().get_inner_mut().get_inner_mut();
+        let width = scroll.inner_size().x;
+        if width == 0 {
+            return;
+        }
+        let content = scroll.get_inner_mut().get_mut().get_content();
+        let row = code_blocks::row(&content, offset, width);
+        scroll.set_offset((0, row));
+    }
+
     pub fn show_title(&mut self) {
         self.view
             .get_inner_mut()
@@ -307,8 +388,7 @@ impl ViewWrapper for LayoutView {
 
     fn wrap_on_event(&mut self, event: Event) -> EventResult {
         if let Event::Char(' ') = event {
-            self.cycle_layout();
-            self.layout_invalidated = true;
+            self.next_layout();
             return EventResult::Consumed(None);
         }
 
@@ -459,6 +539,12 @@ impl LayoutView {
         }
     }
 
+    /// Switch to the next layout, from both columns to a single one to full screen and back
+    pub fn next_layout(&mut self) {
+        self.cycle_layout();
+        self.layout_invalidated = true;
+    }
+
     fn cycle_layout(&mut self) {
         self.layout = match self.layout {
             Layout::BothColumns => Layout::SingleColumn,
@@ -493,6 +579,11 @@ impl LayoutView {
             .expect("Panic: call on answer view failed");
     }
 
+    /// Name of the pane that has focus
+    pub fn focused(&self) -> &'static str {
+        Self::xy_to_name(self.get_focused_index())
+    }
+
     fn get_focused_index(&self) -> Vec2 {
         let top = self.view.get_inner();
         let x = top.get_focus_index();
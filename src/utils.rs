@@ -1,7 +1,10 @@
-use crate::error::{Error, PermissionType, Result};
+use crate::config::Clipboard;
+use crate::error::{CommandError, Error, PermissionType, Result};
 use std::fs::File;
-use std::io::ErrorKind;
+use std::io::{self, ErrorKind, Write};
 use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Duration;
 
 pub fn open_file(filename: &PathBuf) -> Result<Option<File>> {
     File::open(filename).map(Some).or_else(|e| match e {
@@ -22,3 +25,223 @@ pub fn create_file(filename: &PathBuf) -> Result<File> {
         }
     })
 }
+
+/// Write `contents` to `filename` by way of a temporary file, so that the file is never left
+/// half written
+pub fn write_atomic(filename: &PathBuf, contents: &[u8]) -> Result<()> {
+    let mut tmp = filename.clone().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    create_file(&tmp)?.write_all(contents)?;
+    std::fs::rename(&tmp, filename)?;
+    Ok(())
+}
+
+/// Open `url` with the system's opener, `xdg-open`, `open` or `start`. Whatever the opener prints
+/// is thrown away, so that it can't garble the TUI.
+pub fn open_browser(url: &str) -> Result<()> {
+    let mut command = opener(url);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    run(&mut command).map_err(|source| Error::Browser {
+        url: url.to_string(),
+        source,
+    })
+}
+
+/// Run `command` to completion, failing unless it exits successfully
+pub fn run(command: &mut Command) -> std::result::Result<(), CommandError> {
+    let status = command.status().map_err(|source| CommandError::Io {
+        command: program(command),
+        source,
+    })?;
+    exited(command, status)
+}
+
+/// Failure of `command` unless it exited with `status` successfully
+fn exited(command: &Command, status: ExitStatus) -> std::result::Result<(), CommandError> {
+    match status.success() {
+        true => Ok(()),
+        false => Err(CommandError::Exited {
+            command: program(command),
+            status,
+        }),
+    }
+}
+
+/// The program `command` runs, as errors name it
+fn program(command: &Command) -> String {
+    command.get_program().to_string_lossy().into_owned()
+}
+
+#[cfg(target_os = "macos")]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("open");
+    command.arg(url);
+    command
+}
+
+#[cfg(windows)]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("cmd");
+    // The empty title keeps `start` from taking a quoted URL for one
+    command.args(&["/C", "start", ""]).arg(url);
+    command
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(url);
+    command
+}
+
+/// Put `text` on the system clipboard with `pbcopy`, `clip`, or `wl-copy` or `xclip` on other
+/// systems, whichever of those is installed
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    let mut failures = Vec::new();
+    for mut command in copiers() {
+        match pipe_to(&mut command, text) {
+            Ok(()) => return Ok(()),
+            Err(e) => failures.push(e),
+        }
+    }
+    Err(Error::Clipboard(failures))
+}
+
+/// Put `text` on `clipboard`; OSC 52 can't tell whether the terminal took it
+pub fn copy_via(clipboard: Clipboard, text: &str) -> Result<()> {
+    let set = |var: &str| std::env::var_os(var).is_some();
+    let osc52 = match clipboard {
+        Clipboard::System => false,
+        Clipboard::Osc52 => true,
+        Clipboard::Auto => set("SSH_TTY") || set("SSH_CONNECTION"),
+    };
+    if !osc52 {
+        return copy_to_clipboard(text);
+    }
+    let mut out = io::stdout();
+    out.write_all(osc52_sequence(text, set("TMUX")).as_bytes())
+        .and_then(|()| out.flush())
+        .map_err(Error::Osc52)
+}
+
+/// OSC 52 sequence putting `text` on the terminal's clipboard, wrapped for tmux to pass on to
+/// the terminal it runs in if `tmux`
+fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", base64::encode(text));
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+/// Run `command` with `text` on its standard input, throwing away whatever it prints
+fn pipe_to(command: &mut Command, text: &str) -> std::result::Result<(), CommandError> {
+    let name = program(command);
+    let failed = |source| CommandError::Io {
+        command: name.clone(),
+        source,
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(failed)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(failed)?;
+    }
+    let status = child.wait().map_err(failed)?;
+    exited(command, status)
+}
+
+#[cfg(target_os = "macos")]
+fn copiers() -> Vec<Command> {
+    vec![Command::new("pbcopy")]
+}
+
+#[cfg(windows)]
+fn copiers() -> Vec<Command> {
+    vec![Command::new("clip")]
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn copiers() -> Vec<Command> {
+    let mut xclip = Command::new("xclip");
+    xclip.args(["-selection", "clipboard"]);
+    vec![Command::new("wl-copy"), xclip]
+}
+
+/// Longest duration `parse_duration` returns; anything longer is as good as waiting forever
+const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Parse a human friendly duration such as `3s`, `1.5s`, `500ms` or `2m`. A bare number is
+/// taken as seconds. Durations longer than a day are cut down to one.
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount = amount
+        .parse::<f64>()
+        .map_err(|_| format!("invalid duration `{}`", s))?;
+    let secs = match unit.trim() {
+        "ms" => amount / 1000.0,
+        "" | "s" => amount,
+        "m" => amount * 60.0,
+        unit => return Err(format!("unknown duration unit `{}`; use ms, s or m", unit)),
+    };
+    // Too long to represent at all, if it fails, since it can't be negative
+    Ok(Duration::try_from_secs_f64(secs).map_or(MAX_DURATION, |d| d.min(MAX_DURATION)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("so\n", false), "\x1b]52;c;c28K\x07");
+        assert_eq!(
+            osc52_sequence("so\n", true),
+            "\x1bPtmux;\x1b\x1b]52;c;c28K\x07\x1b\\"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        assert!(run(&mut Command::new("true")).is_ok());
+        match run(&mut Command::new("false")) {
+            Err(CommandError::Exited { command, status }) => {
+                assert_eq!(command, "false");
+                assert_eq!(status.code(), Some(1));
+            }
+            r => panic!("expected a failed exit, got {:?}", r),
+        }
+        assert!(matches!(
+            run(&mut Command::new("so-no-such-command")),
+            Err(CommandError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("3s"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration(" 2m "), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("3h").is_err());
+        assert!(parse_duration("three seconds").is_err());
+        assert_eq!(parse_duration("90000s"), Ok(MAX_DURATION));
+        assert_eq!(parse_duration("99999999999999999999999s"), Ok(MAX_DURATION));
+        assert_eq!(parse_duration(&"9".repeat(400)), Ok(MAX_DURATION));
+    }
+}